@@ -0,0 +1,68 @@
+//! `cargo binstall apply`: install the crates of a plan written by
+//! `--plan`, exactly as planned.
+//!
+//! The crates are resolved again, pinned to the versions and the targets
+//! planned, and checked against the plan before installing any of them, see
+//! [`PlannedInstall::check_resolution`].
+
+use std::{fs, future::Future, path::Path};
+
+use binstalk::{
+    errors::BinstallError,
+    helpers::jobserver_client::LazyJobserverClient,
+    ops::plan::{InstallPlan, PlannedInstall},
+};
+use miette::{miette, Result};
+use tracing::warn;
+
+use crate::{args::Args, entry, lockfile};
+
+/// Write `plan` to `path` as JSON.
+pub(crate) fn write(path: &Path, plan: &InstallPlan) -> Result<()> {
+    let json = serde_json::to_vec_pretty(plan)
+        .map_err(|err| miette!("Failed to serialize the install plan: {err}"))?;
+    fs::write(path, json).map_err(|err| miette!("Failed to write {}: {err}", path.display()))
+}
+
+pub fn apply(
+    mut args: Args,
+    path: &Path,
+    jobserver_client: LazyJobserverClient,
+) -> Result<Option<impl Future<Output = Result<()>>>> {
+    let content =
+        fs::read(path).map_err(|err| miette!("Failed to read {}: {err}", path.display()))?;
+    if let Some(verify_key) = &args.verify_lockfile {
+        lockfile::verify(path, &content, verify_key)?;
+    }
+    let plan: InstallPlan = serde_json::from_slice(&content)
+        .map_err(|err| miette!("Failed to parse {}: {err}", path.display()))?;
+
+    if plan.binstall_version != env!("CARGO_PKG_VERSION") {
+        warn!(
+            "{} was planned by binstall {}, it may resolve differently with this version",
+            path.display(),
+            plan.binstall_version
+        );
+    }
+
+    args.crate_names = plan
+        .crates
+        .iter()
+        .map(PlannedInstall::crate_name)
+        .collect::<Result<_, BinstallError>>()?;
+
+    let mut targets = Vec::new();
+    for target in plan.crates.iter().filter_map(PlannedInstall::target) {
+        if !targets.iter().any(|desired| desired == target) {
+            targets.push(target.to_owned());
+        }
+    }
+    if !targets.is_empty() {
+        args.targets = Some(targets);
+    }
+
+    args.require_all = true;
+    args.plan = None;
+
+    entry::install_planned_crates(args, Some(plan), jobserver_client)
+}
@@ -0,0 +1,225 @@
+//! `cargo binstall archive-artifacts`: install crates and keep a copy of
+//! every artifact installed from, with its provenance, in a retention
+//! directory, e.g. for compliance regimes requiring to store the exact
+//! third-party binaries used.
+//!
+//! The artifacts are stored as `{name}/{version}/{target}/{file}` and
+//! listed in `manifest.json`, which is updated by each run. Binstall does
+//! not verify signatures of artifacts yet, so none are archived.
+
+use std::{
+    fs,
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+};
+
+use binstalk::{
+    helpers::{hex, jobserver_client::LazyJobserverClient},
+    ops::{
+        plan::{PlannedFetch, PlannedInstall},
+        resolve::{CrateName, ResolutionFetch},
+    },
+};
+use compact_str::CompactString;
+use miette::{miette, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::{args::Args, entry};
+
+const MANIFEST: &str = "manifest.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Manifest {
+    artifacts: Vec<ArchivedArtifact>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct ArchivedArtifact {
+    /// Path of the artifact, relative to the retention directory.
+    path: PathBuf,
+    /// Hex encoded sha256 digest of the artifact.
+    sha256: CompactString,
+    size: u64,
+    /// Source of the artifact, e.g. `QuickInstall`.
+    source: CompactString,
+    third_party: bool,
+    #[serde(flatten)]
+    fetch: PlannedFetch,
+}
+
+pub fn archive_artifacts(
+    mut args: Args,
+    crate_names: Vec<CrateName>,
+    dest: PathBuf,
+    jobserver_client: LazyJobserverClient,
+) -> Result<Option<impl Future<Output = Result<()>>>> {
+    fs::create_dir_all(&dest)
+        .map_err(|err| miette!("Failed to create {}: {err}", dest.display()))?;
+
+    args.crate_names = crate_names;
+    args.archive_artifacts = Some(dest);
+
+    entry::install_crates(args, jobserver_client)
+}
+
+/// Return the file name of the artifact of `fetch`, from its url if any.
+fn file_name(fetch: &PlannedFetch) -> &str {
+    fetch
+        .artifact_url
+        .as_ref()
+        .and_then(|url| url.path_segments()?.next_back())
+        .filter(|name| !name.is_empty() && *name != "." && *name != "..")
+        .unwrap_or("artifact")
+}
+
+/// Copy `src` to `dst` through a temporary file, so that `dst` is never
+/// partially written, and return the sha256 digest and the size of the
+/// copy.
+fn copy_and_hash(src: &Path, dst: &Path) -> io::Result<(CompactString, u64)> {
+    let dir = dst.parent().unwrap();
+    fs::create_dir_all(dir)?;
+
+    let mut tmp = tempfile::NamedTempFile::new_in(dir)?;
+    let size = io::copy(&mut fs::File::open(src)?, &mut tmp)?;
+    tmp.as_file().sync_all()?;
+
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(tmp.path())?, &mut hasher)?;
+    let sha256 = hex(hasher.finalize()).into();
+
+    tmp.persist(dst).map_err(io::Error::from)?;
+
+    Ok((sha256, size))
+}
+
+fn archive_one(dest: &Path, fetch: &ResolutionFetch) -> Result<Option<ArchivedArtifact>> {
+    let Some(artifact) = &fetch.artifact else {
+        return Ok(None);
+    };
+    let PlannedInstall::Fetch(planned) = PlannedInstall::from_fetch(fetch)? else {
+        unreachable!("PlannedInstall::from_fetch always returns PlannedInstall::Fetch")
+    };
+
+    let path: PathBuf = [
+        &*planned.name,
+        &planned.version.to_string(),
+        &planned.target,
+        file_name(&planned),
+    ]
+    .iter()
+    .collect();
+
+    let (sha256, size) = copy_and_hash(artifact, &dest.join(&path))
+        .map_err(|err| miette!("Failed to archive the artifact of {}: {err}", fetch.name))?;
+
+    info!(
+        "Archived the artifact of {} to {}",
+        fetch.name,
+        path.display()
+    );
+
+    Ok(Some(ArchivedArtifact {
+        path,
+        sha256,
+        size,
+        source: fetch.fetcher.source_name(),
+        third_party: fetch.fetcher.is_third_party(),
+        fetch: planned,
+    }))
+}
+
+/// Copy the artifacts of `fetches` into `dest` and add them to its
+/// manifest, replacing the entries of the same paths.
+///
+/// This is a blocking function.
+pub(crate) fn archive(dest: &Path, fetches: &[Box<ResolutionFetch>]) -> Result<()> {
+    let manifest_path = dest.join(MANIFEST);
+    let mut manifest: Manifest = match fs::read(&manifest_path) {
+        Ok(content) => serde_json::from_slice(&content)
+            .map_err(|err| miette!("Failed to parse {}: {err}", manifest_path.display()))?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Manifest::default(),
+        Err(err) => return Err(miette!("Failed to read {}: {err}", manifest_path.display())),
+    };
+
+    let mut archived = false;
+    for fetch in fetches {
+        if let Some(artifact) = archive_one(dest, fetch)? {
+            manifest
+                .artifacts
+                .retain(|archived| archived.path != artifact.path);
+            manifest.artifacts.push(artifact);
+            archived = true;
+        }
+    }
+    if !archived {
+        return Ok(());
+    }
+    manifest
+        .artifacts
+        .sort_by(|lhs, rhs| lhs.path.cmp(&rhs.path));
+
+    let json = serde_json::to_vec_pretty(&manifest)
+        .map_err(|err| miette!("Failed to serialize the manifest: {err}"))?;
+    let write = || -> io::Result<()> {
+        let tmp = tempfile::NamedTempFile::new_in(dest)?;
+        fs::write(tmp.path(), json)?;
+        tmp.persist(&manifest_path)?;
+        Ok(())
+    };
+    write().map_err(|err| miette!("Failed to write {}: {err}", manifest_path.display()))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_file_name() {
+        let mut fetch = PlannedFetch {
+            name: "cargo-binstall".into(),
+            version: "1.0.0".parse().unwrap(),
+            version_req: "=1.0.0".into(),
+            target: "x86_64-unknown-linux-musl".into(),
+            fetcher: "GhCrateMeta".into(),
+            artifact_url: None,
+            bins: Vec::new(),
+        };
+        assert_eq!(file_name(&fetch), "artifact");
+
+        fetch.artifact_url = Some(
+            "https://github.com/cargo-bins/cargo-binstall/releases/download/v1.0.0/cargo-binstall-x86_64-unknown-linux-musl.tgz"
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(
+            file_name(&fetch),
+            "cargo-binstall-x86_64-unknown-linux-musl.tgz"
+        );
+
+        fetch.artifact_url = Some("https://example.com/".parse().unwrap());
+        assert_eq!(file_name(&fetch), "artifact");
+    }
+
+    #[test]
+    fn test_copy_and_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        fs::write(&src, b"abc").unwrap();
+
+        let dst = dir.path().join("a/b/dst");
+        let (sha256, size) = copy_and_hash(&src, &dst).unwrap();
+        assert_eq!(
+            sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(size, 3);
+        assert_eq!(fs::read(&dst).unwrap(), b"abc");
+    }
+}
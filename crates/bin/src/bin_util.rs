@@ -5,10 +5,9 @@ use std::{
 };
 
 use binstalk::errors::BinstallError;
-use binstalk::helpers::tasks::AutoAbortJoinHandle;
+use binstalk::helpers::{message::Message, tasks::AutoAbortJoinHandle};
 use miette::Result;
 use tokio::runtime::Runtime;
-use tracing::{error, info};
 
 use crate::signal::cancel_on_user_sig_term;
 
@@ -23,13 +22,17 @@ impl Termination for MainExit {
         match self {
             Self::Success(spent) => {
                 if let Some(spent) = spent {
-                    info!("Done in {spent:?}");
+                    Message::new("binstall.done", "Done in { duration }")
+                        .arg("duration", format!("{spent:?}"))
+                        .info();
                 }
                 ExitCode::SUCCESS
             }
             Self::Error(err) => err.report(),
             Self::Report(err) => {
-                error!("Fatal error:\n{err:?}");
+                Message::new("binstall.fatal-error", "Fatal error:\n{ error }")
+                    .arg("error", format!("{err:?}"))
+                    .error();
                 ExitCode::from(16)
             }
         }
@@ -37,13 +40,13 @@ impl Termination for MainExit {
 }
 
 impl MainExit {
-    pub fn new(res: Result<()>, done: Duration) -> Self {
-        res.map(|()| MainExit::Success(Some(done)))
-            .unwrap_or_else(|err| {
-                err.downcast::<BinstallError>()
-                    .map(MainExit::Error)
-                    .unwrap_or_else(MainExit::Report)
-            })
+    /// * `done` - time spent, reported on success if `Some`.
+    pub fn new(res: Result<()>, done: Option<Duration>) -> Self {
+        res.map(|()| MainExit::Success(done)).unwrap_or_else(|err| {
+            err.downcast::<BinstallError>()
+                .map(MainExit::Error)
+                .unwrap_or_else(MainExit::Report)
+        })
     }
 }
 
@@ -0,0 +1,68 @@
+//! The artifact cache, enabled by default, and `cargo binstall cache`.
+
+use std::{fs, path::PathBuf};
+
+use binstalk::helpers::clean_artifact_cache;
+use miette::{miette, Result};
+use tracing::warn;
+
+use crate::args::Args;
+
+/// Return `binstall` in the cache directory of the platform, e.g.
+/// `$XDG_CACHE_HOME/binstall` on linux.
+fn default_cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("binstall"))
+}
+
+/// Return the directory of the artifact cache, or `None` if it is disabled
+/// with `--no-cache` or there is no cache directory on this platform.
+pub(crate) fn enabled_cache_dir(args: &Args) -> Option<PathBuf> {
+    if args.no_cache {
+        None
+    } else {
+        args.cache_dir.clone().or_else(default_cache_dir)
+    }
+}
+
+/// Return the directory of the artifact cache to use for this run, given
+/// `--cache-dir` and `--no-cache`.
+///
+/// Unlike `--cache-dir`, failing to create the default cache directory only
+/// disables the cache, e.g. on a read-only home directory.
+pub(crate) fn artifact_cache_dir(cache_dir: Option<PathBuf>, no_cache: bool) -> Option<PathBuf> {
+    if no_cache {
+        return None;
+    }
+    if cache_dir.is_some() {
+        return cache_dir;
+    }
+
+    let dir = default_cache_dir()?;
+    if let Err(err) = fs::create_dir_all(&dir) {
+        warn!(
+            "Failed to create the artifact cache {}, not caching the artifacts: {err}",
+            dir.display()
+        );
+        return None;
+    }
+
+    Some(dir)
+}
+
+pub fn clean(args: Args) -> Result<()> {
+    let dir = args
+        .cache_dir
+        .or_else(default_cache_dir)
+        .ok_or_else(|| miette!("No cache directory found or specified"))?;
+
+    if !dir.is_dir() {
+        println!("Nothing to clean in {}", dir.display());
+        return Ok(());
+    }
+
+    let freed = clean_artifact_cache(&dir)
+        .map_err(|err| miette!("Failed to clean {}: {err}", dir.display()))?;
+    println!("Removed {freed} bytes from {}", dir.display());
+
+    Ok(())
+}
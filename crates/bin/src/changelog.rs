@@ -0,0 +1,145 @@
+//! Release notes of the installed crates being upgraded, shown with
+//! `--show-changelog`.
+
+use binstalk::{
+    errors::BinstallError,
+    helpers::tasks::AutoAbortJoinHandle,
+    ops::{
+        changelog::{fetch_changelog, ChangelogEntry},
+        event::InstallEvent,
+        Options,
+    },
+};
+use compact_str::CompactString;
+use semver::Version;
+use tracing::{info, warn};
+
+pub(crate) struct Changelog {
+    name: CompactString,
+    current_version: Version,
+    new_version: Version,
+    entries: Vec<ChangelogEntry>,
+}
+
+impl Changelog {
+    fn describe(&self) -> String {
+        let Self {
+            name,
+            current_version,
+            new_version,
+            entries,
+        } = self;
+
+        if entries.is_empty() {
+            return format!(
+                "No release notes found for {name} v{current_version} -> v{new_version}"
+            );
+        }
+
+        let mut description = format!("Changes of {name} v{current_version} -> v{new_version}:");
+        for ChangelogEntry { version, notes } in entries {
+            description.push_str(&format!("\n\n## v{version}\n"));
+            if notes.is_empty() {
+                description.push_str("(no release notes)");
+            } else {
+                description.push_str(notes);
+            }
+        }
+        description
+    }
+}
+
+/// Fetch the release notes of `crates`, which are tuples of name,
+/// repository, installed version and version to install.
+///
+/// Crates failing to fetch are logged and skipped.
+pub(crate) async fn fetch_changelogs(
+    opts: &Options,
+    crates: Vec<(CompactString, String, Version, Version)>,
+) -> Vec<Changelog> {
+    let tasks: Vec<_> = crates
+        .into_iter()
+        .map(|(name, repo, current_version, new_version)| {
+            let client = opts.client.clone();
+            let gh_api_client = opts.gh_api_client.clone();
+            AutoAbortJoinHandle::spawn(async move {
+                let entries = fetch_changelog(
+                    &client,
+                    &gh_api_client,
+                    &name,
+                    &repo,
+                    &current_version,
+                    &new_version,
+                )
+                .await
+                .map_err(|err| err.crate_context(name.clone()))?;
+
+                Ok::<_, BinstallError>(Changelog {
+                    name,
+                    current_version,
+                    new_version,
+                    entries,
+                })
+            })
+        })
+        .collect();
+
+    let mut changelogs = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        match task.flattened_join().await {
+            Ok(changelog) => changelogs.push(changelog),
+            Err(err) => warn!("Failed to fetch the release notes: {err}"),
+        }
+    }
+    changelogs
+}
+
+pub(crate) fn print_changelogs(opts: &Options, changelogs: &[Changelog]) {
+    for changelog in changelogs {
+        info!("{}", changelog.describe());
+
+        for entry in &changelog.entries {
+            opts.emit(InstallEvent::Changelog {
+                crate_name: &changelog.name,
+                version: &entry.version,
+                notes: &entry.notes,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        let mut changelog = Changelog {
+            name: "foo".into(),
+            current_version: Version::new(1, 0, 0),
+            new_version: Version::new(1, 2, 0),
+            entries: Vec::new(),
+        };
+
+        assert_eq!(
+            changelog.describe(),
+            "No release notes found for foo v1.0.0 -> v1.2.0"
+        );
+
+        changelog.entries = vec![
+            ChangelogEntry {
+                version: Version::new(1, 2, 0),
+                notes: "- Add bar".into(),
+            },
+            ChangelogEntry {
+                version: Version::new(1, 1, 0),
+                notes: String::new(),
+            },
+        ];
+
+        assert_eq!(
+            changelog.describe(),
+            "Changes of foo v1.0.0 -> v1.2.0:\n\n## v1.2.0\n- Add bar\n\n## v1.1.0\n(no release notes)"
+        );
+    }
+}
@@ -0,0 +1,343 @@
+//! `cargo binstall complete`: shell completion.
+//!
+//! The script printed by `--register` is generated by `clap_complete`, with
+//! a hook completing the crate names, which calls `complete --index N --
+//! WORDS` on each completion. It prints the crates to complete the word at
+//! `N` with, one per line: the installed crates for the subcommands
+//! operating on them, and the crates installed recently, as recorded in the
+//! audit log, for the others.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use binstalk::errors::BinstallError;
+use binstalk_manifests::{
+    audit_log, cargo_config::Config, crates_manifests::load_installed_crates_read_only,
+};
+use clap::{Arg, Command, CommandFactory, ValueEnum};
+use clap_complete::{generate, shells};
+use compact_str::CompactString;
+use home::cargo_home;
+use miette::{miette, Result};
+
+use crate::{args::Args, install_path};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, ValueEnum)]
+pub(crate) enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Name of the binary the scripts complete.
+const BIN_NAME: &str = "cargo-binstall";
+
+const BASH_HOOK: &str = r#"
+# Complete the crates too.
+_cargo_binstall() {
+    _cargo-binstall "$@"
+    [[ ${COMP_WORDS[COMP_CWORD]} == -* ]] && return
+    local IFS=$'
+'
+    COMPREPLY+=($(cargo-binstall binstall complete --index "$COMP_CWORD" -- "${COMP_WORDS[@]}" 2>/dev/null))
+}
+complete -F _cargo_binstall -o bashdefault -o default cargo-binstall
+
+# Complete `cargo binstall`, leaving the other cargo subcommands to the
+# completion of cargo, if any.
+complete -p cargo &>/dev/null || { declare -F _completion_loader &>/dev/null && _completion_loader cargo; }
+__cargo_binstall_cargo_complete=$(complete -p cargo 2>/dev/null | sed -n 's/.*-F \([^ ]*\).*//p')
+_cargo_binstall_cargo() {
+    if [[ ${COMP_WORDS[1]} == binstall ]]; then
+        local COMP_WORDS=(cargo-binstall "${COMP_WORDS[@]:2}") COMP_CWORD=$((COMP_CWORD - 1))
+        _cargo_binstall cargo-binstall "${COMP_WORDS[COMP_CWORD]}" "${COMP_WORDS[COMP_CWORD - 1]}"
+    elif [[ -n $__cargo_binstall_cargo_complete ]]; then
+        "$__cargo_binstall_cargo_complete" "$@"
+    fi
+}
+complete -o default -F _cargo_binstall_cargo cargo
+"#;
+
+const ZSH_HOOK: &str = r#"
+# Complete the crates too.
+_cargo-binstall-crates() {
+    _cargo-binstall "$@"
+    [[ $PREFIX == -* ]] && return
+    local -a crates
+    crates=("${(@f)$(cargo-binstall binstall complete --index $((CURRENT - 1)) -- "${words[@]}" 2>/dev/null)}")
+    if (( ${#crates[@]} )) && [[ -n ${crates[1]} ]]; then
+        compadd -a crates
+    fi
+}
+compdef _cargo-binstall-crates cargo-binstall
+"#;
+
+const FISH_HOOK: &str = r#"
+# Complete the crates too.
+function __cargo_binstall_crates
+    set -l words (commandline -opc) (commandline -ct)
+    cargo-binstall binstall complete --index (math (count $words) - 1) -- $words 2>/dev/null
+end
+complete -c cargo-binstall -f -a '(__cargo_binstall_crates)'
+complete -c cargo -n '__fish_seen_subcommand_from binstall' -f -a '(__cargo_binstall_crates)'
+"#;
+
+/// Write the completion script of `shell` to `out`.
+fn write_script(shell: Shell, out: &mut dyn io::Write) -> io::Result<()> {
+    let mut command = Args::command();
+    let hook = match shell {
+        Shell::Bash => {
+            generate(shells::Bash, &mut command, BIN_NAME, out);
+            BASH_HOOK
+        }
+        Shell::Zsh => {
+            generate(shells::Zsh, &mut command, BIN_NAME, out);
+            ZSH_HOOK
+        }
+        Shell::Fish => {
+            generate(shells::Fish, &mut command, BIN_NAME, out);
+            FISH_HOOK
+        }
+    };
+    out.write_all(hook.as_bytes())
+}
+
+pub fn complete(
+    args: Args,
+    register: Option<Shell>,
+    index: Option<usize>,
+    words: Vec<String>,
+) -> Result<()> {
+    if let Some(shell) = register {
+        write_script(shell, &mut io::stdout().lock()).map_err(BinstallError::from)?;
+        return Ok(());
+    }
+
+    let index = index.ok_or_else(|| miette!("--index is required to complete"))?;
+
+    // Completion must not fail, crates are simply not completed if the
+    // cargo roots cannot be found.
+    let cargo_roots = cargo_roots(args.root);
+
+    for name in crates(&Args::command(), &words, index, cargo_roots.as_deref()) {
+        println!("{name}");
+    }
+
+    Ok(())
+}
+
+fn cargo_roots(root: Option<PathBuf>) -> Option<PathBuf> {
+    let cargo_home = cargo_home().map_err(BinstallError::from).ok()?;
+    let mut config = Config::load_from_path(cargo_home.join("config.toml")).ok()?;
+    install_path::get_cargo_roots_path(root, cargo_home, &mut config)
+}
+
+/// Crates installed in `cargo_roots`.
+fn installed_crates(cargo_roots: Option<&Path>) -> Vec<CompactString> {
+    cargo_roots
+        .and_then(|cargo_roots| load_installed_crates_read_only(cargo_roots).ok())
+        .map(|crates| crates.into_keys().collect())
+        .unwrap_or_default()
+}
+
+/// Crates installed or upgraded by binstall, most recent first.
+fn recent_crates(cargo_roots: Option<&Path>) -> Vec<CompactString> {
+    let entries = cargo_roots
+        .and_then(|cargo_roots| audit_log::load_from_path(audit_log::path(cargo_roots)).ok())
+        .unwrap_or_default();
+
+    let mut crates: Vec<CompactString> = Vec::new();
+    for entry in entries.into_iter().rev() {
+        if !crates.contains(&entry.name) {
+            crates.push(entry.name);
+        }
+    }
+    crates
+}
+
+/// Whether `arg` takes a value, which is then the next word unless it is
+/// passed inline.
+fn takes_value(arg: &Arg) -> bool {
+    arg.get_num_args()
+        .map_or(arg.get_action().takes_values(), |num_args| {
+            num_args.takes_values()
+        })
+}
+
+/// Return the crates to complete `words[index]` with, `words` starting
+/// with `cargo binstall`, `cargo-binstall binstall` or `cargo-binstall`.
+///
+/// The options, their values and the subcommands are completed by the
+/// script generated by `clap_complete`.
+fn crates(
+    root: &Command,
+    words: &[String],
+    index: usize,
+    cargo_roots: Option<&Path>,
+) -> Vec<String> {
+    // Skip the program and `binstall` if invoked by cargo.
+    let skip = match words.get(1) {
+        Some(word) if word == "binstall" => 2,
+        _ => 1,
+    };
+    if index < skip {
+        return Vec::new();
+    }
+    let current = words.get(index).map(String::as_str).unwrap_or_default();
+
+    let mut command = root;
+    let mut subcommands: Vec<&str> = Vec::new();
+    let mut positionals = 0;
+    // The option the current word is the value of, if any.
+    let mut value_of = None;
+
+    let mut i = skip;
+    while i < index {
+        let word = &words[i];
+        i += 1;
+
+        let arg = if let Some(long) = word.strip_prefix("--") {
+            if long.is_empty() || long.contains('=') {
+                continue;
+            }
+            command
+                .get_arguments()
+                .find(|arg| arg.get_long() == Some(long))
+        } else if let Some(short) = word.strip_prefix('-') {
+            let mut shorts = short.chars();
+            match (shorts.next(), shorts.next()) {
+                (Some(short), None) => command
+                    .get_arguments()
+                    .find(|arg| arg.get_short() == Some(short)),
+                _ => continue,
+            }
+        } else {
+            match command.find_subcommand(word) {
+                Some(subcommand) if positionals == 0 => {
+                    command = subcommand;
+                    subcommands.push(subcommand.get_name());
+                }
+                _ => positionals += 1,
+            }
+            continue;
+        };
+
+        if let Some(arg) = arg.filter(|arg| takes_value(arg)) {
+            if i == index {
+                value_of = Some(arg);
+            }
+            i += 1;
+        }
+    }
+
+    let crates = if let Some(arg) = value_of {
+        if arg.get_value_names() == Some(&["crate[@version]".into()]) {
+            recent_crates(cargo_roots)
+        } else {
+            Vec::new()
+        }
+    } else if current.starts_with('-') {
+        Vec::new()
+    } else {
+        match subcommands.first().copied() {
+            // Installs
+            None => recent_crates(cargo_roots),
+            Some("verify" | "export-nix") => installed_crates(cargo_roots),
+            Some("history") if positionals == 0 => installed_crates(cargo_roots),
+            Some("diff" | "probe" | "check-release") if positionals == 0 => {
+                recent_crates(cargo_roots)
+            }
+            Some(_) => Vec::new(),
+        }
+    };
+
+    let mut completed: Vec<String> = Vec::new();
+    for name in crates {
+        if name.starts_with(current) && !completed.iter().any(|c| *c == name) {
+            completed.push(name.into());
+        }
+    }
+    completed
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn complete(words: &[&str], cargo_roots: Option<&Path>) -> Vec<String> {
+        let words: Vec<String> = words.iter().map(ToString::to_string).collect();
+        crates(&Args::command(), &words, words.len() - 1, cargo_roots)
+    }
+
+    #[test]
+    fn test_register() {
+        for (shell, function, hook) in [
+            (Shell::Bash, "_cargo-binstall()", "_cargo_binstall()"),
+            (Shell::Zsh, "_cargo-binstall()", "_cargo-binstall-crates()"),
+            (
+                Shell::Fish,
+                "complete -c cargo-binstall",
+                "__cargo_binstall_crates",
+            ),
+        ] {
+            let mut script = Vec::new();
+            write_script(shell, &mut script).unwrap();
+            let script = String::from_utf8(script).unwrap();
+
+            assert!(script.contains(function), "{shell:?}");
+            assert!(script.contains(hook), "{shell:?}");
+            assert!(script.contains("strategies"), "{shell:?}");
+            assert!(script.contains("check-release"), "{shell:?}");
+        }
+    }
+
+    #[test]
+    fn test_complete_crates() {
+        let cargo_roots = tempfile::tempdir().unwrap();
+        let cargo_roots = cargo_roots.path();
+        std::fs::create_dir_all(cargo_roots.join("binstall")).unwrap();
+        std::fs::write(
+            audit_log::path(cargo_roots),
+            ["ripgrep", "bat", "ripgrep"]
+                .map(|name| {
+                    format!(
+                        r#"{{"time":0,"action":"install","name":"{name}","version":"1.0.0","target":"x86_64-unknown-linux-gnu"}}"#
+                    )
+                })
+                .join("\n"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            complete(&["cargo", "binstall", "--locked", "rip"], Some(cargo_roots)),
+            ["ripgrep"]
+        );
+        assert_eq!(
+            complete(&["cargo", "binstall", "bat", ""], Some(cargo_roots)),
+            ["ripgrep", "bat"]
+        );
+        assert_eq!(
+            complete(
+                &["cargo", "binstall", "export-oci", "--crates", ""],
+                Some(cargo_roots)
+            ),
+            ["ripgrep", "bat"]
+        );
+        assert_eq!(
+            complete(&["cargo-binstall", "diff", "r"], Some(cargo_roots)),
+            ["ripgrep"]
+        );
+
+        // Options, their values and the subcommands are left to the
+        // generated script.
+        assert!(complete(&["cargo", "binstall", "--lo"], Some(cargo_roots)).is_empty());
+        assert!(complete(
+            &["cargo", "binstall", "--strategies", ""],
+            Some(cargo_roots)
+        )
+        .is_empty());
+        assert!(complete(&["cargo-binstall", "lockfile", ""], Some(cargo_roots)).is_empty());
+    }
+}
@@ -0,0 +1,133 @@
+//! `cargo binstall diff`: compare the files of the prebuilt artifacts of
+//! two versions of a crate.
+
+use std::future::Future;
+
+use binstalk::{
+    helpers::jobserver_client::LazyJobserverClient,
+    ops::resolve::{self, ArtifactFiles, CrateName, FileChange},
+};
+use compact_str::CompactString;
+use miette::{miette, Result};
+use semver::{Version, VersionReq};
+use tracing::warn;
+
+use crate::{args::Args, probe::compute_probe_options};
+
+fn exact_version(name: &CompactString, version: &Version) -> CrateName {
+    CrateName {
+        name: name.clone(),
+        version_req: Some(VersionReq::parse(&format!("={version}")).unwrap()),
+    }
+}
+
+pub fn diff(
+    args: Args,
+    crate_name: CompactString,
+    old: Version,
+    new: Version,
+    jobserver_client: LazyJobserverClient,
+) -> Result<Option<impl Future<Output = Result<()>>>> {
+    let (opts, temp_dir) = compute_probe_options(args, Vec::new(), jobserver_client)?;
+
+    Ok(Some(async move {
+        let (old_files, new_files) = tokio::try_join!(
+            resolve::fetch_artifact_files(opts.clone(), exact_version(&crate_name, &old)),
+            resolve::fetch_artifact_files(opts.clone(), exact_version(&crate_name, &new)),
+        )?;
+
+        let old_files = old_files
+            .ok_or_else(|| miette!("No prebuilt artifact of {crate_name} v{old} is available"))?;
+        let new_files = new_files
+            .ok_or_else(|| miette!("No prebuilt artifact of {crate_name} v{new} is available"))?;
+
+        if old_files.fetcher.target() != new_files.fetcher.target() {
+            warn!(
+                "The artifacts compared are for different targets: {} and {}",
+                old_files.fetcher.target(),
+                new_files.fetcher.target()
+            );
+        }
+
+        print!("{}", format_diff(&crate_name, &old_files, &new_files));
+
+        drop(temp_dir);
+        Ok(())
+    }))
+}
+
+fn describe(files: &ArtifactFiles) -> String {
+    format!(
+        "v{} ({}, {})",
+        files.version,
+        files.fetcher.target(),
+        files.fetcher.source_name()
+    )
+}
+
+fn format_diff(crate_name: &str, old: &ArtifactFiles, new: &ArtifactFiles) -> String {
+    let changes = resolve::diff_files(&old.files, &new.files);
+
+    let mut output = format!("{crate_name} {} -> {}\n", describe(old), describe(new));
+    output.push_str(&format_changes(&changes));
+    output
+}
+
+fn format_changes(changes: &[FileChange]) -> String {
+    let mut output = String::new();
+    let (mut added, mut removed, mut changed) = (0, 0, 0);
+
+    for change in changes {
+        let line = match change {
+            FileChange::Added { path, size } => {
+                added += 1;
+                format!("+ {} ({size} bytes)", path.display())
+            }
+            FileChange::Removed { path, size } => {
+                removed += 1;
+                format!("- {} ({size} bytes)", path.display())
+            }
+            FileChange::SizeChanged {
+                path,
+                old_size,
+                new_size,
+            } => {
+                changed += 1;
+                format!("~ {} ({old_size} -> {new_size} bytes)", path.display())
+            }
+        };
+        output.push_str(&line);
+        output.push('\n');
+    }
+
+    output.push_str(&format!(
+        "{added} files added, {removed} removed, {changed} changed in size\n"
+    ));
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_changes() {
+        let changes = [
+            FileChange::SizeChanged {
+                path: "bin/foo".into(),
+                old_size: 10,
+                new_size: 12,
+            },
+            FileChange::Added {
+                path: "bin/bar".into(),
+                size: 5,
+            },
+        ];
+
+        assert_eq!(
+            format_changes(&changes),
+            "~ bin/foo (10 -> 12 bytes)\n+ bin/bar (5 bytes)\n\
+            1 files added, 0 removed, 1 changed in size\n"
+        );
+    }
+}
@@ -1,8 +1,11 @@
 use std::{
+    collections::{BTreeMap, BTreeSet},
     env, fs,
     future::Future,
+    io, mem,
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 
 use binstalk::{
@@ -12,28 +15,51 @@ use binstalk::{
     helpers::{
         gh_api_client::GhApiClient,
         jobserver_client::LazyJobserverClient,
-        remote::{Certificate, Client},
+        message::Message,
+        remote::{
+            header::{HeaderMap, HeaderName, HeaderValue},
+            Certificate, Client, ConnectionOptions, Identity, RetryPolicy, Url,
+        },
         tasks::AutoAbortJoinHandle,
+        DownloadJournal,
     },
     ops::{
         self,
+        build_cache::BuildCache,
+        event::{InstallEvent, ProgressSink},
+        patch_elf::PatchElf,
+        plan::{InstallPlan, PlannedInstall},
         resolve::{CrateName, Resolution, ResolutionFetch, VersionReqExt},
         CargoTomlFetchOverride, Options, Resolver,
     },
 };
 use binstalk_manifests::{
-    cargo_config::Config, cargo_toml_binstall::PkgOverride, crates_manifests::Manifests,
+    cargo_config::Config,
+    cargo_toml_binstall::PkgOverride,
+    crate_info::Environment,
+    crates_manifests::{load_installed_crates_read_only, Manifests},
 };
+use compact_str::{CompactString, ToCompactString};
 use file_format::FileFormat;
 use home::cargo_home;
+use is_terminal::IsTerminal;
 use log::LevelFilter;
 use miette::{miette, Result, WrapErr};
-use tokio::task::block_in_place;
-use tracing::{debug, error, info, warn};
+use tokio::{task::block_in_place, time::timeout};
+use tracing::{debug, warn};
 
 use crate::{
-    args::{Args, Strategy},
-    gh_token, git_credentials, install_path,
+    apply, archive_artifacts,
+    args::{Args, ConfirmPolicy, Strategy},
+    cache,
+    changelog::{fetch_changelogs, print_changelogs},
+    environment, gh_token, git_credentials, install_path,
+    journal::{self, ResumeGuard},
+    json_lines::JsonLinesSink,
+    metrics, policy,
+    progress_bars::ProgressBars,
+    publishers::{fetch_publishers, print_publishers},
+    status_file::update_status_file,
     ui::confirm,
 };
 
@@ -41,45 +67,597 @@ pub fn install_crates(
     args: Args,
     jobserver_client: LazyJobserverClient,
 ) -> Result<Option<impl Future<Output = Result<()>>>> {
-    // Compute Resolvers
-    let mut cargo_install_fallback = false;
-
-    let resolvers: Vec<_> = args
-        .strategies
-        .into_iter()
-        .filter_map(|strategy| match strategy {
-            Strategy::CrateMetaData => Some(GhCrateMeta::new as Resolver),
-            Strategy::QuickInstall => Some(QuickInstall::new as Resolver),
-            Strategy::Compile => {
-                cargo_install_fallback = true;
-                None
-            }
-        })
-        .collect();
+    install_planned_crates(args, None, jobserver_client)
+}
 
+/// Install the crates of `args`, checking that they resolve to the
+/// installs of `plan`, if any.
+pub(crate) fn install_planned_crates(
+    mut args: Args,
+    plan: Option<InstallPlan>,
+    jobserver_client: LazyJobserverClient,
+) -> Result<Option<impl Future<Output = Result<()>>>> {
     // Load .cargo/config.toml
     let cargo_home = cargo_home().map_err(BinstallError::from)?;
     let mut config = Config::load_from_path(cargo_home.join("config.toml"))?;
 
     // Compute paths
-    let cargo_root = args.root;
-    let (install_path, mut manifests, temp_dir) = compute_paths_and_load_manifests(
-        cargo_root.clone(),
-        args.install_path,
+    let (install_path, cargo_roots, temp_dir) = compute_paths(
+        args.root.clone(),
+        args.install_path.take(),
         args.no_track,
         cargo_home,
         &mut config,
     )?;
 
+    if args.resume {
+        match &cargo_roots {
+            Some(cargo_roots) => {
+                let mut crate_names = journal::load(cargo_roots)?;
+                if crate_names.is_empty() {
+                    Message::new(
+                        "binstall.nothing-to-resume",
+                        "No interrupted install to resume",
+                    )
+                    .info();
+                }
+                // The crates specified take precedence over the ones resumed.
+                crate_names.append(&mut args.crate_names);
+                args.crate_names = crate_names;
+            }
+            None => Message::new(
+                "binstall.resume-untracked",
+                "Cannot resume since installed crates are not tracked",
+            )
+            .warn(),
+        }
+    }
+
+    // Load manifests
+    let mut manifests = cargo_roots
+        .as_deref()
+        .map(Manifests::open_exclusive)
+        .transpose()?;
+
     // Remove installed crates
-    let mut crate_names =
-        filter_out_installed_crates(args.crate_names, args.force, manifests.as_mut())?.peekable();
+    let system_crates = load_system_crates(args.system_root.as_deref(), cargo_roots.as_deref())?;
+    let mut crate_names = filter_out_installed_crates(
+        mem::take(&mut args.crate_names),
+        args.force,
+        manifests.as_mut(),
+        system_crates,
+    )?
+    .peekable();
 
     if crate_names.peek().is_none() {
         debug!("Nothing to do");
         return Ok(None);
     }
 
+    // Destruct args before any async function to reduce size of the future
+    let dry_run = args.dry_run;
+    let plan_path = args.plan.take();
+    let archive_dest = args.archive_artifacts.clone();
+    let mut planned: Option<BTreeMap<CompactString, PlannedInstall>> = plan.map(|plan| {
+        plan.crates
+            .into_iter()
+            .map(|planned| (planned.name().into(), planned))
+            .collect()
+    });
+    let confirm_policy = args.confirm;
+    let no_cleanup = args.no_cleanup;
+    let (metrics, metrics_crate_names) = (args.metrics, args.metrics_crate_names);
+    let show_changelog = args.show_changelog;
+    let show_publishers = args.show_publishers || confirm_policy == ConfirmPolicy::OnNewPublisher;
+    let crate_timeout = args.crate_timeout.map(Duration::from_secs);
+    let overwrite_modified = args.overwrite_modified;
+    let success_criteria = SuccessCriteria {
+        require_all: args.require_all,
+        max_failures: match (args.continue_on_error, args.max_failures) {
+            (_, Some(max_failures)) => Some(max_failures),
+            (true, None) => None,
+            (false, None) => Some(0),
+        },
+        partial_success_exit_code: args.partial_success_exit_code,
+    };
+
+    // Clean up after the runs which crashed and journal the downloads of
+    // this one.
+    let (download_journal, run_journal_entry) = match cargo_roots
+        .as_deref()
+        .and_then(|cargo_roots| journal::open_download_journal(cargo_roots, temp_dir.path()))
+    {
+        Some((download_journal, run_journal_entry)) => {
+            (Some(download_journal), Some(run_journal_entry))
+        }
+        None => (None, None),
+    };
+
+    // Create binstall_opts
+    let binstall_opts = Arc::new(compute_options(
+        args,
+        config,
+        install_path,
+        temp_dir.path().to_owned(),
+        jobserver_client,
+        download_journal,
+    )?);
+
+    // Crates to resume if interrupted
+    let resume_guard = ResumeGuard::new(cargo_roots.as_deref());
+    let pending = resume_guard.pending();
+
+    // Resolve crates
+    let tasks: Vec<_> = crate_names
+        .map(|(crate_name, current_version)| {
+            pending.add(&crate_name);
+            let name = crate_name.name.clone();
+            let resolve =
+                ops::resolve::resolve(binstall_opts.clone(), crate_name, current_version.clone());
+
+            let task = AutoAbortJoinHandle::spawn({
+                let name = name.clone();
+                async move { with_crate_timeout(crate_timeout, name, resolve).await }
+            });
+
+            (name, current_version, task)
+        })
+        .collect();
+
+    let install = async move {
+        let environment = environment::capture(&binstall_opts.desired_targets).await;
+        for desired_target in &environment.targets {
+            debug!(
+                "Looking for {} binaries: {}",
+                desired_target.target, desired_target.reason
+            );
+        }
+        binstall_opts.emit(InstallEvent::Environment {
+            environment: &environment,
+        });
+
+        let total = tasks.len();
+        let mut failures = Vec::new();
+
+        // Collect results
+        let mut resolution_fetchs = Vec::new();
+        let mut resolution_sources = Vec::new();
+
+        // Installed crates checked for updates
+        let mut checked = Vec::new();
+        let mut outdated = Vec::new();
+
+        let mut metrics_run = match (&cargo_roots, metrics) {
+            (Some(cargo_roots), true) => Some((cargo_roots.clone(), metrics::Run::default())),
+            (None, true) => {
+                Message::new(
+                    "binstall.metrics-untracked",
+                    "Not recording metrics since installed crates are not tracked",
+                )
+                .warn();
+                None
+            }
+            (_, false) => None,
+        };
+
+        // A crate failing to resolve does not stop the others.
+        // Versions of the installed crates
+        let mut current_versions = BTreeMap::new();
+
+        for (name, current_version, task) in tasks {
+            let res = match (
+                task.flattened_join().await,
+                planned.as_mut().and_then(|planned| planned.remove(&name)),
+            ) {
+                (Ok(mut resolution), Some(planned)) => planned
+                    .check_resolution(&mut resolution)
+                    .map(|()| resolution)
+                    .map_err(|err| err.crate_context(name.clone())),
+                (res, _) => res,
+            };
+            let is_installed = current_version.is_some();
+            if let Some(current_version) = current_version {
+                current_versions.insert(name.clone(), current_version);
+            }
+
+            if let (Some((_, run)), true) = (&mut metrics_run, metrics_crate_names) {
+                run.crates.push(name.clone());
+            }
+
+            if let (true, Ok(resolution)) = (is_installed, &res) {
+                if !matches!(resolution, Resolution::AlreadyUpToDate) {
+                    outdated.push(name.clone());
+                }
+                checked.push(name.clone());
+            }
+
+            match res {
+                Ok(Resolution::AlreadyUpToDate) => pending.done(&name),
+                Ok(Resolution::Fetch(fetch)) => {
+                    fetch.print(&binstall_opts);
+                    resolution_fetchs.push(fetch)
+                }
+                Ok(Resolution::InstallFromSource(source)) => {
+                    source.print();
+                    resolution_sources.push(source)
+                }
+                Err(err) => {
+                    pending.done(&name);
+                    failures.push(err)
+                }
+            }
+        }
+
+        if let Some(cargo_roots) = &cargo_roots {
+            update_status_file(
+                cargo_roots,
+                checked.iter().map(CompactString::as_str),
+                outdated.iter().map(CompactString::as_str),
+            );
+        }
+
+        // Do not overwrite binaries modified since they were installed
+        if let Some(manifests) = manifests.as_ref() {
+            let mut check_unmodified = |name: &str| {
+                let res = manifests
+                    .installed_crate_info(name)
+                    .map_or(Ok(()), |crate_info| {
+                        ops::verify::check_unmodified(crate_info, overwrite_modified)
+                    });
+                match res {
+                    Ok(()) => true,
+                    Err(err) => {
+                        pending.done(name);
+                        failures.push(err.crate_context(name));
+                        false
+                    }
+                }
+            };
+            resolution_fetchs.retain(|fetch| check_unmodified(&fetch.name));
+            resolution_sources.retain(|source| check_unmodified(&source.name));
+        }
+
+        let degraded: Vec<_> = resolution_fetchs
+            .iter()
+            .filter(|fetch| fetch.fetcher.is_degraded())
+            .map(|fetch| fetch.name.as_str())
+            .collect();
+        if !degraded.is_empty() {
+            Message::new(
+                "binstall.github-api-unavailable",
+                "GitHub API was unavailable, the artifacts of these crates were found by \
+                probing urls directly and may be less reliable: { crates }",
+            )
+            .arg("crates", degraded.join(", "))
+            .warn();
+        }
+
+        if let Some((_, run)) = &mut metrics_run {
+            run.strategies.extend(
+                resolution_fetchs
+                    .iter()
+                    .map(|fetch| fetch.fetcher.fetcher_name()),
+            );
+            run.strategies
+                .extend(resolution_sources.iter().map(|source| {
+                    if source.source_archive.is_some() {
+                        "SourceArchive"
+                    } else {
+                        "Compile"
+                    }
+                }));
+            run.probe_cache = binstall_opts.client.probe_cache_stats();
+        }
+
+        let finish = |failures: Vec<BinstallError>| {
+            if let Some((cargo_roots, mut run)) = metrics_run {
+                for err in &failures {
+                    run.record_error(err);
+                }
+                block_in_place(|| metrics::record_run(&cargo_roots, run));
+            }
+
+            report_failures(failures, total, &success_criteria)
+        };
+
+        if success_criteria.require_all && !failures.is_empty() {
+            Message::new(
+                "binstall.require-all-failed",
+                "Not installing any crate since --require-all is specified",
+            )
+            .error();
+            return finish(failures);
+        }
+
+        if let Some(plan_path) = plan_path {
+            let crates = block_in_place(|| {
+                resolution_fetchs
+                    .iter()
+                    .map(|fetch| PlannedInstall::from_fetch(fetch))
+                    .chain(
+                        resolution_sources
+                            .iter()
+                            .map(|source| Ok(PlannedInstall::from_source(source))),
+                    )
+                    .collect::<Result<_, BinstallError>>()
+            })?;
+            let plan = InstallPlan {
+                binstall_version: env!("CARGO_PKG_VERSION").into(),
+                crates,
+            };
+            apply::write(&plan_path, &plan)?;
+            Message::new(
+                "binstall.plan-written",
+                "Wrote the install plan to { path }",
+            )
+            .arg("path", plan_path.display().to_string())
+            .info();
+
+            return finish(failures);
+        }
+
+        if resolution_fetchs.is_empty() && resolution_sources.is_empty() {
+            debug!("Nothing to do");
+            return finish(failures);
+        }
+
+        if show_changelog {
+            let crates = resolution_fetchs
+                .iter()
+                .map(|fetch| (&fetch.name, &fetch.repo, Some(fetch.new_version.clone())))
+                .chain(
+                    resolution_sources
+                        .iter()
+                        .map(|source| (&source.name, &source.repo, source.version.parse().ok())),
+                )
+                .filter_map(|(name, repo, new_version)| {
+                    Some((
+                        name.clone(),
+                        repo.clone()?,
+                        current_versions.get(name)?.clone(),
+                        new_version?,
+                    ))
+                })
+                .collect();
+
+            let changelogs = fetch_changelogs(&binstall_opts, crates).await;
+            print_changelogs(&binstall_opts, &changelogs);
+        }
+
+        let mut has_new_publishers = false;
+        if show_publishers {
+            if binstall_opts.registry.is_crates_io() {
+                let crates = resolution_fetchs
+                    .iter()
+                    .map(|fetch| (fetch.name.clone(), fetch.new_version.to_compact_string()))
+                    .chain(
+                        resolution_sources
+                            .iter()
+                            .map(|source| (source.name.clone(), source.version.clone())),
+                    )
+                    .map(|(name, version)| {
+                        let current_version = current_versions.remove(&name);
+                        (name, version, current_version)
+                    })
+                    .collect();
+
+                let publishers = fetch_publishers(&binstall_opts.client, crates).await;
+                print_publishers(&publishers);
+
+                has_new_publishers = publishers
+                    .iter()
+                    .any(|publisher| publisher.previous_publisher().is_some());
+            } else {
+                Message::new(
+                    "binstall.publishers-unavailable",
+                    "Publishers are only available for crates from crates.io",
+                )
+                .warn();
+            }
+        }
+
+        // Confirm
+        if !dry_run && confirm_policy.should_confirm(has_new_publishers) {
+            confirm().await?;
+        }
+
+        if let Some(archive_dest) = &archive_dest {
+            block_in_place(|| archive_artifacts::archive(archive_dest, &resolution_fetchs))?;
+        }
+
+        let fetched: Vec<_> = resolution_fetchs
+            .iter()
+            .map(|fetch| fetch.name.clone())
+            .collect();
+
+        let (mut installed, install_failures) = do_install_fetches(
+            resolution_fetchs,
+            manifests,
+            &binstall_opts,
+            &environment,
+            dry_run,
+            temp_dir,
+            no_cleanup,
+        )?;
+        failures.extend(install_failures);
+        for name in &fetched {
+            pending.done(name);
+        }
+
+        let tasks: Vec<_> = resolution_sources
+            .into_iter()
+            .map(|source| {
+                let name = source.name.clone();
+                let install = source.install(binstall_opts.clone());
+
+                let task = AutoAbortJoinHandle::spawn({
+                    let name = name.clone();
+                    async move {
+                        let install =
+                            async { install.await.map_err(|err| err.crate_context(name.clone())) };
+                        with_crate_timeout(crate_timeout, name.clone(), install).await
+                    }
+                });
+
+                (name, task)
+            })
+            .collect();
+
+        for (name, task) in tasks {
+            let res = task.flattened_join().await;
+            pending.done(&name);
+
+            match res {
+                Ok(crate_info) => {
+                    // Crates installed from the build cache are recorded by
+                    // binstall instead of cargo, as soon as they are
+                    // installed in case the others are interrupted.
+                    if let (Some(cargo_roots), Some(mut crate_info)) = (&cargo_roots, crate_info) {
+                        crate_info.environment = Some(environment.clone());
+                        block_in_place(|| {
+                            Manifests::open_exclusive(cargo_roots)?.update(vec![crate_info])
+                        })?;
+                    }
+                    installed.push(name);
+                }
+                Err(err) => failures.push(err),
+            }
+        }
+
+        if let (Some(cargo_roots), false) = (&cargo_roots, dry_run) {
+            update_status_file(cargo_roots, installed.iter().map(CompactString::as_str), []);
+        }
+
+        finish(failures)
+    };
+
+    Ok(Some(async move {
+        let res = install.await;
+        resume_guard.finish();
+        drop(run_journal_entry);
+        res
+    }))
+}
+
+/// Run `fut`, resolving or installing the crate `name`, giving up on it
+/// if it takes longer than `crate_timeout`.
+async fn with_crate_timeout<T>(
+    crate_timeout: Option<Duration>,
+    name: CompactString,
+    fut: impl Future<Output = Result<T, BinstallError>>,
+) -> Result<T, BinstallError> {
+    match crate_timeout {
+        Some(crate_timeout) => timeout(crate_timeout, fut)
+            .await
+            .unwrap_or_else(|_| Err(BinstallError::Timeout(crate_timeout).crate_context(name))),
+        None => fut.await,
+    }
+}
+
+/// Decide whether installing multiple crates is successful.
+struct SuccessCriteria {
+    /// Install nothing if any crate fails to resolve.
+    require_all: bool,
+    /// Number of crates allowed to fail, or `None` if any number is.
+    max_failures: Option<usize>,
+    partial_success_exit_code: bool,
+}
+
+/// Report the crates failed to install, out of `total` crates.
+fn failure_message(err: &BinstallError) -> Message<'static> {
+    Message::new("binstall.failure", "  - { error }").arg("error", err.to_string())
+}
+
+fn report_failures(
+    mut failures: Vec<BinstallError>,
+    total: usize,
+    success_criteria: &SuccessCriteria,
+) -> Result<()> {
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    let failed = failures.len();
+
+    if success_criteria
+        .max_failures
+        .map_or(true, |max_failures| failed <= max_failures)
+    {
+        if total > 1 {
+            match success_criteria.max_failures {
+                Some(max_failures) => Message::new(
+                    "binstall.failures-tolerated",
+                    "{ failed } out of { total } crates failed to install, \
+                    which is tolerated (at most { max-failures } allowed):",
+                )
+                .arg("max-failures", max_failures.to_string()),
+                None => Message::new(
+                    "binstall.failures-tolerated-unlimited",
+                    "{ failed } out of { total } crates failed to install, \
+                    which is tolerated:",
+                ),
+            }
+            .arg("failed", failed.to_string())
+            .arg("total", total.to_string())
+            .warn();
+        }
+        for err in &failures {
+            failure_message(err).warn();
+        }
+
+        return Ok(());
+    }
+
+    if total > 1 {
+        Message::new(
+            "binstall.failures",
+            "{ failed } out of { total } crates failed to install:",
+        )
+        .arg("failed", failed.to_string())
+        .arg("total", total.to_string())
+        .error();
+        for err in &failures {
+            failure_message(err).error();
+        }
+    }
+
+    if success_criteria.partial_success_exit_code && failed < total {
+        Err(BinstallError::PartialSuccess { failed, total }.into())
+    } else {
+        Err(failures.swap_remove(0).into())
+    }
+}
+
+/// Compute the [`Options`] shared by all crates from `args`.
+pub(crate) fn compute_options(
+    args: Args,
+    mut config: Config,
+    install_path: PathBuf,
+    temp_dir: PathBuf,
+    jobserver_client: LazyJobserverClient,
+    download_journal: Option<DownloadJournal>,
+) -> Result<Options> {
+    // Compute Resolvers
+    let mut source_archive_fallback = false;
+    let mut cargo_install_fallback = false;
+
+    let resolvers: Vec<_> = args
+        .strategies
+        .into_iter()
+        .filter_map(|strategy| match strategy {
+            Strategy::CrateMetaData => Some(GhCrateMeta::new as Resolver),
+            Strategy::QuickInstall => Some(QuickInstall::new as Resolver),
+            Strategy::SourceArchive => {
+                source_archive_fallback = true;
+                None
+            }
+            Strategy::Compile => {
+                cargo_install_fallback = true;
+                None
+            }
+        })
+        .collect();
+
     // Launch target detection
     let desired_targets = get_desired_targets(args.targets);
 
@@ -88,6 +666,12 @@ pub fn install_crates(
         pkg_url: args.pkg_url,
         pkg_fmt: args.pkg_fmt,
         bin_dir: args.bin_dir,
+        bin_dest: args
+            .bin_dests
+            .into_iter()
+            .map(|bin_dest| (bin_dest.bin, bin_dest.template))
+            .collect(),
+        flavor: args.flavor,
     };
 
     // Initialize reqwest client
@@ -95,8 +679,140 @@ pub fn install_crates(
 
     let mut http = config.http.take();
 
-    let client = Client::new(
-        concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")),
+    let cache_dir = cache::artifact_cache_dir(args.cache_dir, args.no_cache);
+
+    let mut binstall_config = config.binstall.take().unwrap_or_default();
+
+    let allow_hosts = args.allow_hosts.map(|hosts| {
+        hosts
+            .into_iter()
+            .map(CompactString::from)
+            .collect::<Vec<_>>()
+    });
+    let combine_allowed_hosts = |config_allow_hosts| {
+        policy::combine_allowed_hosts(allow_hosts.clone(), config_allow_hosts)
+            .map(|hosts| hosts.into_iter().map(String::from).collect())
+    };
+
+    let mut connection_options = ConnectionOptions {
+        pool_idle_timeout: args.pool_idle_timeout.map(Duration::from_secs),
+        pool_max_idle_per_host: args.pool_max_idle_per_host,
+        dns_cache_ttl: args.dns_cache_ttl.map(Duration::from_secs),
+        pins: args.pins,
+        allowed_hosts: combine_allowed_hosts(binstall_config.allow_hosts.clone()),
+        identity: read_client_identity(
+            args.client_cert.as_deref(),
+            args.client_key.as_deref(),
+            args.client_cert_password.as_deref(),
+        )?,
+        proxy: match args.proxy {
+            Some(proxy) => Some(proxy),
+            None => http
+                .as_mut()
+                .and_then(|http| http.proxy.take())
+                .map(|proxy| parse_cargo_proxy(&proxy))
+                .transpose()?,
+        },
+    };
+
+    if let (Some(policy_url), Some(policy_key)) = (&args.policy_url, &args.policy_key) {
+        // The policy is fetched with the same connection options as the
+        // artifacts, except for the hosts allowed by the policy itself.
+        let mut client = Client::new_with_connection_options(
+            concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")),
+            args.min_tls_version.map(|v| v.into()),
+            rate_limit.duration,
+            rate_limit.request_count,
+            read_root_certs(
+                args.root_certificates.clone(),
+                http.as_ref().and_then(|http| http.cainfo.clone()),
+            ),
+            connection_options.clone(),
+        )
+        .map_err(BinstallError::from)?;
+        if let Some(cache_dir) = &cache_dir {
+            client = client
+                .with_http_cache(cache_dir.join("http"))
+                .map_err(BinstallError::from)?;
+        }
+
+        let policy = policy::load(&client, policy_url, policy_key, cache_dir.as_deref())?;
+        binstall_config = policy::merge(binstall_config, policy);
+        connection_options.allowed_hosts = combine_allowed_hosts(binstall_config.allow_hosts);
+    }
+
+    let patch_interpreter = args.patch_interpreter.or(binstall_config.patch_interpreter);
+    let patch_rpath = args
+        .patch_rpath
+        .or_else(|| binstall_config.patch_rpath.map(String::from));
+    let patch_elf = (patch_interpreter.is_some() || patch_rpath.is_some()).then_some(PatchElf {
+        interpreter: patch_interpreter,
+        rpath: patch_rpath,
+    });
+
+    let mut user_agent =
+        concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")).to_string();
+    if let Some(suffix) = args.user_agent_suffix.or(binstall_config.user_agent_suffix) {
+        user_agent.push(' ');
+        user_agent.push_str(&suffix);
+    }
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in binstall_config.headers.unwrap_or_default() {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|err| miette!("Invalid header name {name} in cargo config: {err}"))?;
+        let value = HeaderValue::from_str(&value)
+            .map_err(|err| miette!("Invalid value of header {name} in cargo config: {err}"))?;
+        headers.insert(name, value);
+    }
+    for header in args.headers {
+        headers.insert(header.name, header.value);
+    }
+
+    let mut host_headers: BTreeMap<String, HeaderMap> = BTreeMap::new();
+    for (host, config_headers) in binstall_config.host_headers.unwrap_or_default() {
+        let headers = host_headers.entry(host.to_string()).or_default();
+        for (name, value) in config_headers {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(|err| {
+                miette!("Invalid header name {name} for host {host} in cargo config: {err}")
+            })?;
+            let value = HeaderValue::from_str(&value).map_err(|err| {
+                miette!("Invalid value of header {name} for host {host} in cargo config: {err}")
+            })?;
+            headers.insert(name, value);
+        }
+    }
+    for host_header in args.host_headers {
+        let header = host_header.header;
+        host_headers
+            .entry(host_header.host)
+            .or_default()
+            .insert(header.name, header.value);
+    }
+    // They usually carry credentials, keep them out of the logs.
+    for value in host_headers.values_mut().flat_map(HeaderMap::values_mut) {
+        value.set_sensitive(true);
+    }
+
+    let mut mirrors: BTreeMap<String, Vec<String>> = binstall_config
+        .mirrors
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(from, to)| (from.into(), vec![to.into()]))
+        .collect();
+    // Rules of the same prefix on the command line replace the one of cargo
+    // config, and are mirrors of each other.
+    let mut overridden = BTreeSet::new();
+    for mirror in args.mirrors {
+        let tos = mirrors.entry(mirror.from.clone()).or_default();
+        if overridden.insert(mirror.from) {
+            tos.clear();
+        }
+        tos.push(mirror.to);
+    }
+
+    let mut client = Client::new_with_connection_options(
+        user_agent,
         args.min_tls_version.map(|v| v.into()),
         rate_limit.duration,
         rate_limit.request_count,
@@ -104,8 +820,86 @@ pub fn install_crates(
             args.root_certificates,
             http.as_mut().and_then(|http| http.cainfo.take()),
         ),
+        connection_options,
     )
-    .map_err(BinstallError::from)?;
+    .map_err(BinstallError::from)?
+    .with_headers(headers)
+    .with_host_headers(host_headers)
+    .with_mirrors(
+        mirrors
+            .into_iter()
+            .flat_map(|(from, tos)| tos.into_iter().map(move |to| (from.clone(), to))),
+    );
+    if args.allow_unverified_mirrors {
+        client = client.with_unverified_mirrors();
+    }
+
+    let mut retry_policy = RetryPolicy::default();
+    if let Some(retries) = args.retries {
+        retry_policy.retries = retries;
+    }
+    if let Some(retry_backoff) = args.retry_backoff {
+        retry_policy.backoff_base = Duration::from_millis(retry_backoff);
+    }
+    if let Some(retry_jitter) = args.retry_jitter {
+        retry_policy.jitter = retry_jitter;
+    }
+    if !args.retry_on.is_empty() {
+        retry_policy.retryable = args.retry_on;
+    }
+    // Git operations share the retry policy of the requests.
+    #[cfg(feature = "git")]
+    let git_options = binstalk::registry::GitOptions {
+        depth: std::num::NonZeroU32::new(args.git_depth.unwrap_or(1)),
+        timeout: args.git_timeout.map(Duration::from_secs),
+        retries: retry_policy.retries,
+        backoff_base: retry_policy.backoff_base,
+    };
+    client = client.with_retry_policy(retry_policy);
+
+    if args.max_requests.is_some() || args.max_download_bytes.is_some() {
+        client = client.with_budget(args.max_requests, args.max_download_bytes);
+    }
+
+    if let Some(download_chunks) = args.download_chunks {
+        client = client.with_download_chunks(download_chunks);
+    }
+
+    if let Some(visit_memory_limit) = args.visit_memory_limit {
+        client = client.with_visit_memory_limit(visit_memory_limit);
+    }
+
+    if let Some(nested_depth) = args.nested_depth {
+        client = client.with_nested_depth(nested_depth);
+    }
+
+    let build_cache = cache_dir.as_ref().map(|cache_dir| {
+        let build_cache = BuildCache::new(cache_dir.join("builds"));
+        match args.build_cache_url {
+            Some(url) => build_cache.with_remote(url),
+            None => build_cache,
+        }
+    });
+
+    #[cfg(feature = "git")]
+    let git_cache_dir = cache_dir.as_ref().map(|cache_dir| cache_dir.join("git"));
+
+    if let Some(cache_dir) = cache_dir {
+        client = client
+            .with_http_cache(cache_dir.join("http"))
+            .and_then(|client| client.with_artifact_cache(cache_dir))
+            .map_err(BinstallError::from)?;
+    }
+
+    if let Some(download_journal) = download_journal {
+        client = client.with_download_journal(download_journal);
+    }
+
+    if let Some(debug_http) = args.debug_http {
+        client = client
+            .with_debug_http(debug_http, args.debug_http_bodies)
+            .map_err(BinstallError::from)?;
+    }
 
     let gh_api_client = GhApiClient::new(
         client.clone(),
@@ -118,14 +912,49 @@ pub fn install_crates(
         }),
     );
 
-    // Create binstall_opts
-    let binstall_opts = Arc::new(Options {
+    let registry = if let Some(index) = args.index {
+        index
+    } else if let Some(registry_name) = args
+        .registry
+        .or_else(|| config.registry.and_then(|registry| registry.default))
+    {
+        let registry_name_lowercase = registry_name.to_lowercase();
+
+        let v = env::vars().find_map(|(k, v)| {
+            let name_lowercase = k
+                .strip_prefix("CARGO_REGISTRIES_")?
+                .strip_suffix("_INDEX")?
+                .to_lowercase();
+
+            (name_lowercase == registry_name_lowercase).then_some(v)
+        });
+
+        if let Some(v) = &v {
+            v
+        } else {
+            config
+                .registries
+                .as_ref()
+                .and_then(|registries| registries.get(&registry_name))
+                .and_then(|registry| registry.index.as_deref())
+                .ok_or_else(|| BinstallError::UnknownRegistryName(registry_name))?
+        }
+        .parse()
+        .map_err(BinstallError::from)?
+    } else {
+        Default::default()
+    };
+    #[cfg(feature = "git")]
+    let registry = registry.with_git_options(git_options.clone(), git_cache_dir);
+
+    Ok(Options {
         no_symlinks: args.no_symlinks,
         dry_run: args.dry_run,
         force: args.force,
         quiet: args.log_level == Some(LevelFilter::Off),
         locked: args.locked,
         no_track: args.no_track,
+        deterministic: args.deterministic,
 
         version_req: args.version_req,
         #[cfg(feature = "git")]
@@ -138,146 +967,106 @@ pub fn install_crates(
 
         #[cfg(not(feature = "git"))]
         cargo_toml_fetch_override: args.manifest_path.map(CargoTomlFetchOverride::Path),
+        #[cfg(feature = "git")]
+        git_options,
+        #[cfg(feature = "git")]
+        git_full_checkout: args.git_full_checkout,
         cli_overrides,
 
         desired_targets,
         resolvers,
+        source_archive_fallback,
         cargo_install_fallback,
+        build_env: binstall_config.build_env.unwrap_or_default(),
+        build_cache,
 
-        temp_dir: temp_dir.path().to_owned(),
+        temp_dir,
         install_path,
-        cargo_root,
+        cargo_root: args.root,
 
         client,
         gh_api_client,
         jobserver_client,
-        registry: if let Some(index) = args.index {
-            index
-        } else if let Some(registry_name) = args
-            .registry
-            .or_else(|| config.registry.and_then(|registry| registry.default))
+        registry,
+        version_resolution_hook: None,
+        progress_sink: if args.json_lines {
+            Some(Arc::new(JsonLinesSink) as Arc<dyn ProgressSink>)
+        } else if !args.no_progress
+            && args.log_level != Some(LevelFilter::Off)
+            && io::stderr().is_terminal()
         {
-            let registry_name_lowercase = registry_name.to_lowercase();
-
-            let v = env::vars().find_map(|(k, v)| {
-                let name_lowercase = k
-                    .strip_prefix("CARGO_REGISTRIES_")?
-                    .strip_suffix("_INDEX")?
-                    .to_lowercase();
-
-                (name_lowercase == registry_name_lowercase).then_some(v)
-            });
-
-            if let Some(v) = &v {
-                v
-            } else {
-                config
-                    .registries
-                    .as_ref()
-                    .and_then(|registries| registries.get(&registry_name))
-                    .and_then(|registry| registry.index.as_deref())
-                    .ok_or_else(|| BinstallError::UnknownRegistryName(registry_name))?
-            }
-            .parse()
-            .map_err(BinstallError::from)?
+            Some(Arc::new(ProgressBars::default()) as Arc<dyn ProgressSink>)
         } else {
-            Default::default()
+            None
         },
-    });
-
-    // Destruct args before any async function to reduce size of the future
-    let dry_run = args.dry_run;
-    let no_confirm = args.no_confirm;
-    let no_cleanup = args.no_cleanup;
-
-    // Resolve crates
-    let tasks: Vec<_> = crate_names
-        .map(|(crate_name, current_version)| {
-            AutoAbortJoinHandle::spawn(ops::resolve::resolve(
-                binstall_opts.clone(),
-                crate_name,
-                current_version,
-            ))
-        })
-        .collect();
-
-    Ok(Some(async move {
-        // Collect results
-        let mut resolution_fetchs = Vec::new();
-        let mut resolution_sources = Vec::new();
-
-        for task in tasks {
-            match task.await?? {
-                Resolution::AlreadyUpToDate => {}
-                Resolution::Fetch(fetch) => {
-                    fetch.print(&binstall_opts);
-                    resolution_fetchs.push(fetch)
-                }
-                Resolution::InstallFromSource(source) => {
-                    source.print();
-                    resolution_sources.push(source)
-                }
-            }
-        }
-
-        if resolution_fetchs.is_empty() && resolution_sources.is_empty() {
-            debug!("Nothing to do");
-            return Ok(());
-        }
-
-        // Confirm
-        if !dry_run && !no_confirm {
-            confirm().await?;
-        }
-
-        do_install_fetches(
-            resolution_fetchs,
-            manifests,
-            &binstall_opts,
-            dry_run,
-            temp_dir,
-            no_cleanup,
-        )?;
-
-        let tasks: Vec<_> = resolution_sources
-            .into_iter()
-            .map(|source| AutoAbortJoinHandle::spawn(source.install(binstall_opts.clone())))
-            .collect();
-
-        for task in tasks {
-            task.await??;
-        }
-
-        Ok(())
-    }))
+        quarantine_dir: args.quarantine_dir,
+        patch_elf,
+        keep_artifacts: args.archive_artifacts.is_some(),
+    })
 }
 
-fn do_read_root_cert(path: &Path) -> Result<Option<Certificate>, BinstallError> {
-    use std::io::{Read, Seek};
+fn do_read_root_cert(path: &Path) -> Result<Vec<Certificate>, BinstallError> {
+    let buffer = fs::read(path)?;
 
-    let mut file = fs::File::open(path)?;
-    let file_format = FileFormat::from_reader(&mut file)?;
+    // Bundles of PEM certificates, e.g. `ca-certificates.crt`, usually
+    // start with comments and are not detected as certificates.
+    const BEGIN: &[u8] = b"-----BEGIN CERTIFICATE-----";
+    if buffer.windows(BEGIN.len()).any(|window| window == BEGIN) {
+        return Certificate::from_pem_bundle(&buffer).map_err(From::from);
+    }
 
-    let open_cert = match file_format {
-        FileFormat::PemCertificate => Certificate::from_pem,
-        FileFormat::DerCertificate => Certificate::from_der,
-        _ => {
+    match FileFormat::from_bytes(&buffer) {
+        FileFormat::DerCertificate => Certificate::from_der(&buffer)
+            .map(|certificate| vec![certificate])
+            .map_err(From::from),
+        file_format => {
             warn!(
                 "Unable to load {}: Expected pem or der ceritificate but found {file_format}",
                 path.display()
             );
 
-            return Ok(None);
+            Ok(Vec::new())
         }
+    }
+}
+
+/// Parse `http.proxy` of the cargo config, in libcurl format, i.e. the
+/// scheme defaults to `http://`.
+fn parse_cargo_proxy(proxy: &str) -> Result<Url> {
+    let url = if proxy.contains("://") {
+        Url::parse(proxy)
+    } else {
+        Url::parse(&format!("http://{proxy}"))
     };
+    url.map_err(|err| miette!("Invalid `http.proxy` in cargo config: {err}"))
+}
 
-    // Move file back to its head
-    file.rewind()?;
+fn read_client_identity(
+    cert: Option<&Path>,
+    key: Option<&Path>,
+    password: Option<&str>,
+) -> Result<Option<Identity>> {
+    let Some(cert_path) = cert else {
+        return Ok(None);
+    };
+    let read = |path: &Path| {
+        fs::read(path).map_err(|err| miette!("Failed to read {}: {err}", path.display()))
+    };
 
-    let mut buffer = Vec::with_capacity(200);
-    file.read_to_end(&mut buffer)?;
+    let cert = read(cert_path)?;
+    let identity = if cert.starts_with(b"-----BEGIN") {
+        let key = match key {
+            Some(key) => read(key)?,
+            None => Vec::new(),
+        };
+        Identity::from_pem(&cert, &key)
+    } else {
+        Identity::from_pkcs12_der(&cert, password.unwrap_or(""))
+    };
 
-    open_cert(&buffer).map_err(From::from).map(Some)
+    identity
+        .map(Some)
+        .map_err(|err| miette!("Invalid client certificate {}: {err}", cert_path.display()))
 }
 
 fn read_root_certs(
@@ -287,30 +1076,34 @@ fn read_root_certs(
     root_certificate_paths
         .into_iter()
         .chain(config_cainfo)
-        .filter_map(|path| match do_read_root_cert(&path) {
-            Ok(optional_cert) => optional_cert,
+        .flat_map(|path| match do_read_root_cert(&path) {
+            Ok(certs) => certs,
             Err(err) => {
                 warn!(
                     "Failed to load root certificate at {}: {err}",
                     path.display()
                 );
-                None
+                Vec::new()
             }
         })
 }
 
-/// Return (install_path, manifests, temp_dir)
-fn compute_paths_and_load_manifests(
+/// Return (install_path, cargo_roots if manifests are used, temp_dir)
+pub(crate) fn compute_paths(
     roots: Option<PathBuf>,
     install_path: Option<PathBuf>,
     no_track: bool,
     cargo_home: PathBuf,
     config: &mut Config,
-) -> Result<(PathBuf, Option<Manifests>, tempfile::TempDir)> {
+) -> Result<(PathBuf, Option<PathBuf>, tempfile::TempDir)> {
     // Compute cargo_roots
     let cargo_roots =
         install_path::get_cargo_roots_path(roots, cargo_home, config).ok_or_else(|| {
-            error!("No viable cargo roots path found of specified, try `--roots`");
+            Message::new(
+                "binstall.no-cargo-roots",
+                "No viable cargo roots path found of specified, try `--roots`",
+            )
+            .error();
             miette!("No cargo roots path found or specified")
         })?;
 
@@ -318,7 +1111,11 @@ fn compute_paths_and_load_manifests(
     let (install_path, custom_install_path) =
         install_path::get_install_path(install_path, Some(&cargo_roots));
     let install_path = install_path.ok_or_else(|| {
-        error!("No viable install path found of specified, try `--install-path`");
+        Message::new(
+            "binstall.no-install-path",
+            "No viable install path found of specified, try `--install-path`",
+        )
+        .error();
         miette!("No install path found or specified")
     })?;
     fs::create_dir_all(&install_path).map_err(BinstallError::Io)?;
@@ -326,13 +1123,6 @@ fn compute_paths_and_load_manifests(
 
     let no_manifests = no_track || custom_install_path;
 
-    // Load manifests
-    let manifests = if !no_manifests {
-        Some(Manifests::open_exclusive(&cargo_roots)?)
-    } else {
-        None
-    };
-
     // Create a temporary directory for downloads etc.
     //
     // Put all binaries to a temporary directory under `dst` first, catching
@@ -344,19 +1134,60 @@ fn compute_paths_and_load_manifests(
         .map_err(BinstallError::from)
         .wrap_err("Creating a temporary directory failed.")?;
 
-    Ok((install_path, manifests, temp_dir))
+    Ok((
+        install_path,
+        (!no_manifests).then_some(cargo_roots),
+        temp_dir,
+    ))
+}
+
+/// Load crates installed in the read-only `system_root`, unless it is
+/// the same as `cargo_roots` that binstall installs to.
+pub(crate) fn load_system_crates(
+    system_root: Option<&Path>,
+    cargo_roots: Option<&Path>,
+) -> Result<Option<BTreeMap<CompactString, semver::Version>>> {
+    let Some(system_root) = system_root else {
+        return Ok(None);
+    };
+
+    if let Some(cargo_roots) = cargo_roots {
+        if fs::canonicalize(system_root).ok() == fs::canonicalize(cargo_roots).ok() {
+            debug!("System root is the cargo root, ignoring it");
+            return Ok(None);
+        }
+    }
+
+    let system_crates = load_installed_crates_read_only(system_root)?;
+    debug!(
+        "Found {} crates installed in system root {}",
+        system_crates.len(),
+        system_root.display()
+    );
+
+    Ok(Some(system_crates))
 }
 
 /// Return vec of (crate_name, current_version)
-fn filter_out_installed_crates(
+pub(crate) fn filter_out_installed_crates(
     crate_names: Vec<CrateName>,
     force: bool,
     manifests: Option<&mut Manifests>,
+    system_crates: Option<BTreeMap<CompactString, semver::Version>>,
 ) -> Result<impl Iterator<Item = (CrateName, Option<semver::Version>)> + '_> {
     let mut installed_crates = manifests
         .map(Manifests::load_installed_crates)
         .transpose()?;
 
+    // Crates installed in the user's root take precedence over the ones
+    // installed in the system root.
+    if let Some(system_crates) = system_crates {
+        let installed_crates = installed_crates.get_or_insert_with(BTreeMap::new);
+        for (name, version) in system_crates {
+            installed_crates.entry(name).or_insert(version);
+        }
+    }
+
     Ok(CrateName::dedup(crate_names)
     .filter_map(move |crate_name| {
         let name = &crate_name.name;
@@ -378,7 +1209,13 @@ fn filter_out_installed_crates(
                 if version_req.is_latest_compatible(&curr_version) =>
             {
                 debug!("Bailing out early because we can assume wanted is already installed from metafile");
-                info!("{name} v{curr_version} is already installed, use --force to override");
+                Message::new(
+                    "binstall.already-installed",
+                    "{ name } v{ version } is already installed, use --force to override",
+                )
+                .arg("name", name)
+                .arg("version", curr_version.to_string())
+                .info();
                 None
             }
 
@@ -393,29 +1230,59 @@ fn filter_out_installed_crates(
 }
 
 #[allow(clippy::vec_box)]
-fn do_install_fetches(
+/// Install the binaries fetched, recording the crates installed in
+/// `manifests`, and return the names of the crates installed along with
+/// the errors of the ones which failed.
+///
+/// A crate failing to install, e.g. because its binaries cannot be patched
+/// or conflict with existing files, does not stop the others.
+pub(crate) fn do_install_fetches(
     resolution_fetchs: Vec<Box<ResolutionFetch>>,
     // Take manifests by value to drop the `FileLock`.
     manifests: Option<Manifests>,
     binstall_opts: &Options,
+    environment: &Environment,
     dry_run: bool,
     temp_dir: tempfile::TempDir,
     no_cleanup: bool,
-) -> Result<()> {
+) -> Result<(Vec<CompactString>, Vec<BinstallError>)> {
     if resolution_fetchs.is_empty() {
-        return Ok(());
+        return Ok(Default::default());
     }
 
     if dry_run {
-        info!("Dry-run: Not proceeding to install fetched binaries");
-        return Ok(());
+        Message::new(
+            "binstall.dry-run",
+            "Dry-run: Not proceeding to install fetched binaries",
+        )
+        .info();
+        let installed = resolution_fetchs
+            .into_iter()
+            .map(|fetch| fetch.name.clone())
+            .collect();
+        return Ok((installed, Vec::new()));
     }
 
     block_in_place(|| {
-        let metadata_vec = resolution_fetchs
+        let mut installed = Vec::with_capacity(resolution_fetchs.len());
+        let mut failures = Vec::new();
+        let metadata_vec: Vec<_> = resolution_fetchs
             .into_iter()
-            .map(|fetch| fetch.install(binstall_opts))
-            .collect::<Result<Vec<_>, BinstallError>>()?;
+            .filter_map(|fetch| {
+                let name = fetch.name.clone();
+                match fetch.install(binstall_opts) {
+                    Ok(mut crate_info) => {
+                        crate_info.environment = Some(environment.clone());
+                        installed.push(name);
+                        Some(crate_info)
+                    }
+                    Err(err) => {
+                        failures.push(err.crate_context(name));
+                        None
+                    }
+                }
+            })
+            .collect();
 
         if let Some(manifests) = manifests {
             manifests.update(metadata_vec)?;
@@ -426,10 +1293,96 @@ fn do_install_fetches(
             let _ = temp_dir.into_path();
         } else {
             temp_dir.close().unwrap_or_else(|err| {
-                warn!("Failed to clean up some resources: {err}");
+                Message::new(
+                    "binstall.cleanup-failed",
+                    "Failed to clean up some resources: { error }",
+                )
+                .arg("error", err.to_string())
+                .warn();
             });
         }
 
-        Ok(())
+        Ok((installed, failures))
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn criteria(max_failures: Option<usize>, partial_success_exit_code: bool) -> SuccessCriteria {
+        SuccessCriteria {
+            require_all: false,
+            max_failures,
+            partial_success_exit_code,
+        }
+    }
+
+    fn failures(n: usize) -> Vec<BinstallError> {
+        (0..n).map(|_| BinstallError::NoViableTargets).collect()
+    }
+
+    fn exit_number(res: Result<()>) -> u8 {
+        res.unwrap_err()
+            .downcast::<BinstallError>()
+            .unwrap()
+            .exit_number()
+    }
+
+    #[tokio::test]
+    async fn test_with_crate_timeout() {
+        let crate_timeout = Some(Duration::from_millis(10));
+
+        assert_eq!(
+            with_crate_timeout(crate_timeout, "foo".into(), async { Ok(1) })
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            with_crate_timeout(None, "foo".into(), async { Ok(1) })
+                .await
+                .unwrap(),
+            1
+        );
+
+        // E.g. `cargo install` of the source fallback still building.
+        let err = with_crate_timeout(
+            crate_timeout,
+            "foo".into(),
+            std::future::pending::<Result<(), BinstallError>>(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.exit_number(), 101);
+        assert!(err.to_string().contains("foo"), "{err}");
+    }
+
+    #[test]
+    fn test_report_failures() {
+        report_failures(Vec::new(), 3, &criteria(Some(0), false)).unwrap();
+
+        // --max-failures
+        report_failures(failures(2), 3, &criteria(Some(2), false)).unwrap();
+        assert_eq!(
+            exit_number(report_failures(failures(2), 3, &criteria(Some(1), false))),
+            87
+        );
+
+        // --continue-on-error
+        report_failures(failures(3), 3, &criteria(None, false)).unwrap();
+    }
+
+    #[test]
+    fn test_partial_success_exit_code() {
+        assert_eq!(
+            exit_number(report_failures(failures(2), 3, &criteria(Some(0), true))),
+            102
+        );
+        // Nothing is installed.
+        assert_eq!(
+            exit_number(report_failures(failures(3), 3, &criteria(Some(0), true))),
+            87
+        );
+    }
+}
@@ -0,0 +1,272 @@
+//! `cargo binstall export-nix`: convert the installed crates into a Nix
+//! expression fetching the same prebuilt artifacts.
+
+use std::{fmt::Write as _, fs, future::Future, path::PathBuf};
+
+use binstalk::{
+    errors::BinstallError,
+    helpers::{
+        hex,
+        jobserver_client::LazyJobserverClient,
+        remote::{Client, Url},
+    },
+    manifests::crate_info::CrateInfo,
+};
+use binstalk_manifests::{binstall_crates_v1::Records, cargo_config::Config};
+use compact_str::CompactString;
+use futures_util::StreamExt;
+use home::cargo_home;
+use miette::{miette, Result};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use crate::{args::Args, install_path, probe::compute_probe_options};
+
+/// Download `url` and return the hex encoded sha256 digest of its content.
+async fn sha256_url(client: &Client, url: &str) -> Result<String> {
+    let url = Url::parse(url).map_err(|err| miette!("Invalid artifact url {url}: {err}"))?;
+
+    let mut hasher = Sha256::new();
+    let mut stream = client.get_stream(url).await.map_err(BinstallError::from)?;
+    while let Some(bytes) = stream.next().await {
+        hasher.update(&bytes.map_err(BinstallError::from)?);
+    }
+
+    Ok(hex(hasher.finalize()))
+}
+
+/// Escape `s` to be used in a Nix string.
+fn nix_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '$' => escaped.push_str("\\$"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Return the derivation installing the binaries of `crate_info` from its
+/// artifact, whose sha256 digest is `sha256`.
+fn derivation(crate_info: &CrateInfo, url: &str, sha256: &str) -> String {
+    let CrateInfo {
+        name,
+        current_version,
+        target,
+        bins,
+        bin_digests,
+        ..
+    } = crate_info;
+
+    let mut native_build_inputs = vec!["pkgs.unzip"];
+    if target.contains("-linux-gnu") {
+        // Binaries dynamically linked against glibc need to be patched to
+        // find it in the nix store.
+        native_build_inputs.push("pkgs.autoPatchelfHook");
+    }
+
+    let mut install_phase = String::from("runHook preInstall\n");
+    // Names of binaries only contain characters which need no quoting.
+    for bin in bins {
+        let file_name = format!("{bin}{}", exe_suffix(target));
+        writeln!(
+            install_phase,
+            "      install -Dm755 \"$(find . -type f -name {file_name} -print -quit)\" \"$out/bin/{file_name}\""
+        )
+        .unwrap();
+    }
+    // Check that the binaries are the ones binstall installed, before they
+    // are patched in the fixup phase.
+    for digest in bin_digests {
+        if let Some(file_name) = digest.path.file_name() {
+            writeln!(
+                install_phase,
+                "      echo \"{}  $out/bin/{}\" | sha256sum -c -",
+                digest.sha256,
+                file_name.to_string_lossy()
+            )
+            .unwrap();
+        }
+    }
+    install_phase.push_str("      runHook postInstall");
+
+    format!(
+        "  {attr} = pkgs.stdenv.mkDerivation {{
+    pname = {name};
+    version = {version};
+    src = pkgs.fetchurl {{
+      url = {url};
+      sha256 = {sha256};
+    }};
+    sourceRoot = \".\";
+    nativeBuildInputs = [ {native_build_inputs} ];
+    installPhase = ''
+      {install_phase}
+    '';
+  }};
+",
+        attr = nix_string(name),
+        name = nix_string(name),
+        version = nix_string(&current_version.to_string()),
+        url = nix_string(url),
+        sha256 = nix_string(sha256),
+        native_build_inputs = native_build_inputs.join(" "),
+    )
+}
+
+fn exe_suffix(target: &str) -> &'static str {
+    if target.contains("windows") {
+        ".exe"
+    } else {
+        ""
+    }
+}
+
+/// Return the Nix expression of `derivations`, with a comment for each
+/// crate skipped and the reason.
+fn expression(derivations: &[String], skipped: &[(CompactString, &str)]) -> String {
+    let mut expression = String::from(
+        "# Generated by `cargo binstall export-nix`.
+#
+# Use it with Home Manager as
+#
+#   home.packages = builtins.attrValues (import ./binstall.nix { inherit pkgs; });
+{ pkgs ? import <nixpkgs> { } }:
+
+{
+",
+    );
+    for derivation in derivations {
+        expression.push_str(derivation);
+    }
+    for (name, reason) in skipped {
+        writeln!(expression, "  # {name} is skipped: {reason}.").unwrap();
+    }
+    expression.push_str("}\n");
+    expression
+}
+
+pub fn export_nix(
+    args: Args,
+    crate_names: Vec<CompactString>,
+    output: Option<PathBuf>,
+    jobserver_client: LazyJobserverClient,
+) -> Result<Option<impl Future<Output = Result<()>>>> {
+    let cargo_home = cargo_home().map_err(BinstallError::from)?;
+    let mut config = Config::load_from_path(cargo_home.join("config.toml"))?;
+
+    let cargo_roots =
+        install_path::get_cargo_roots_path(args.root.clone(), cargo_home, &mut config)
+            .ok_or_else(|| miette!("No cargo roots path found or specified"))?;
+
+    let records = Records::load_from_path(cargo_roots.join("binstall/crates-v1.json"))?;
+
+    if let Some(name) = crate_names.iter().find(|name| !records.contains(name)) {
+        return Err(miette!("{name} is not installed by binstall"));
+    }
+
+    let crate_infos: Vec<CrateInfo> = (&records)
+        .into_iter()
+        .map(|data| data.crate_info.clone())
+        .filter(|crate_info| crate_names.is_empty() || crate_names.contains(&crate_info.name))
+        .collect();
+
+    let (opts, temp_dir) = compute_probe_options(args, Vec::new(), jobserver_client)?;
+
+    Ok(Some(async move {
+        let mut derivations = Vec::new();
+        let mut skipped = Vec::new();
+
+        for crate_info in &crate_infos {
+            let Some(url) = &crate_info.artifact_url else {
+                skipped.push((
+                    crate_info.name.clone(),
+                    "not installed from a prebuilt artifact",
+                ));
+                continue;
+            };
+
+            info!("Hashing the artifact of {} from {url}", crate_info.name);
+
+            match sha256_url(&opts.client, url).await {
+                Ok(sha256) => derivations.push(derivation(crate_info, url, &sha256)),
+                Err(err) => {
+                    warn!(
+                        "Failed to download the artifact of {}: {err}",
+                        crate_info.name
+                    );
+                    skipped.push((crate_info.name.clone(), "failed to download its artifact"));
+                }
+            }
+        }
+
+        let expression = expression(&derivations, &skipped);
+
+        match output {
+            Some(output) => fs::write(&output, expression)
+                .map_err(|err| miette!("Failed to write {}: {err}", output.display()))?,
+            None => print!("{expression}"),
+        }
+
+        drop(temp_dir);
+        Ok(())
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use binstalk::manifests::crate_info::{BinDigest, CrateSource};
+    use semver::Version;
+
+    use super::*;
+
+    #[test]
+    fn test_nix_string() {
+        assert_eq!(nix_string("a\"b${c}\\"), r#""a\"b\${c}\\""#);
+    }
+
+    #[test]
+    fn test_derivation() {
+        let crate_info = CrateInfo {
+            name: "foo".into(),
+            version_req: "*".into(),
+            current_version: Version::new(1, 2, 3),
+            source: CrateSource::cratesio_registry(),
+            target: "x86_64-unknown-linux-musl".into(),
+            bins: vec!["foo".into()],
+            bin_digests: vec![BinDigest {
+                path: "/home/user/.cargo/bin/foo".into(),
+                sha256: "ab".into(),
+                size: 1,
+                mtime_ns: 0,
+            }],
+            artifact_url: Some("https://example.com/foo.tgz".into()),
+            environment: None,
+        };
+
+        let derivation = derivation(&crate_info, "https://example.com/foo.tgz", "cd");
+        assert!(derivation.starts_with("  \"foo\" = pkgs.stdenv.mkDerivation {\n"));
+        assert!(derivation.contains("nativeBuildInputs = [ pkgs.unzip ];"));
+        assert!(derivation.contains(
+            "install -Dm755 \"$(find . -type f -name foo -print -quit)\" \"$out/bin/foo\""
+        ));
+        assert!(derivation.contains("echo \"ab  $out/bin/foo\" | sha256sum -c -"));
+
+        let expression = expression(
+            &[derivation],
+            &[("bar".into(), "not installed from a prebuilt artifact")],
+        );
+        assert!(
+            expression.contains("  # bar is skipped: not installed from a prebuilt artifact.\n")
+        );
+        assert!(expression.ends_with("}\n"));
+    }
+}
@@ -0,0 +1,303 @@
+//! `cargo binstall export-oci`: install crates into a staged root and export
+//! their binaries as a single-layer OCI image tarball.
+
+use std::{
+    fs,
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+};
+
+use binstalk::{
+    helpers::{hex, jobserver_client::LazyJobserverClient},
+    ops::resolve::CrateName,
+    TARGET,
+};
+use miette::{miette, Result};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::task::block_in_place;
+use tracing::info;
+
+use crate::{
+    args::{Args, ConfirmPolicy},
+    entry,
+};
+
+const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+const CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.image.config.v1+json";
+const LAYER_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar";
+const INDEX_MEDIA_TYPE: &str = "application/vnd.oci.image.index.v1+json";
+
+/// Directory of the image the binaries are installed to.
+const IMAGE_BIN_DIR: &str = "usr/local/bin";
+
+#[derive(Debug, Eq, PartialEq)]
+struct Platform {
+    architecture: &'static str,
+    variant: Option<&'static str>,
+}
+
+/// Return the OCI platform of the linux `target`.
+fn platform(target: &str) -> Option<Platform> {
+    if !target.contains("-linux-") {
+        return None;
+    }
+
+    let (architecture, variant) = match target.split('-').next()? {
+        "x86_64" => ("amd64", None),
+        "aarch64" => ("arm64", Some("v8")),
+        "armv7" => ("arm", Some("v7")),
+        "arm" => ("arm", Some("v6")),
+        "i686" | "i586" => ("386", None),
+        "powerpc64le" => ("ppc64le", None),
+        "riscv64gc" => ("riscv64", None),
+        "loongarch64" => ("loong64", None),
+        "s390x" => ("s390x", None),
+        _ => return None,
+    };
+
+    Some(Platform {
+        architecture,
+        variant,
+    })
+}
+
+pub fn export_oci(
+    mut args: Args,
+    crate_names: Vec<CrateName>,
+    output: PathBuf,
+    jobserver_client: LazyJobserverClient,
+) -> Result<Option<impl Future<Output = Result<()>>>> {
+    let target = match args.targets.as_deref() {
+        None => TARGET.to_string(),
+        Some([target]) => target.clone(),
+        Some(_) => return Err(miette!("export-oci supports exactly one --targets")),
+    };
+    let platform = platform(&target)
+        .ok_or_else(|| miette!("Cannot export an OCI image for target {target}"))?;
+
+    let staging =
+        tempfile::tempdir().map_err(|err| miette!("Failed to create the staging root: {err}"))?;
+
+    args.crate_names = crate_names;
+    args.targets = Some(vec![target]);
+    args.root = Some(staging.path().to_owned());
+    args.install_path = None;
+    args.force = true;
+    args.confirm = ConfirmPolicy::Never;
+
+    let install = entry::install_crates(args, jobserver_client)?;
+
+    Ok(Some(async move {
+        if let Some(install) = install {
+            install.await?;
+        }
+
+        block_in_place(|| write_image(&staging.path().join("bin"), &platform, &output))
+            .map_err(|err| miette!("Failed to write {}: {err}", output.display()))?;
+
+        info!("Exported OCI image to {}", output.display());
+
+        Ok(())
+    }))
+}
+
+fn sha256_digest(data: &[u8]) -> String {
+    format!("sha256:{}", hex(Sha256::digest(data)))
+}
+
+fn descriptor(media_type: &str, blob: &[u8]) -> Value {
+    json!({
+        "mediaType": media_type,
+        "digest": sha256_digest(blob),
+        "size": blob.len(),
+    })
+}
+
+fn new_header(entry_type: tar::EntryType, mode: u32, size: u64) -> tar::Header {
+    // Use fixed metadata so that the same binaries produce the same image.
+    let mut header = tar::Header::new_ustar();
+    header.set_entry_type(entry_type);
+    header.set_mode(mode);
+    header.set_size(size);
+    header.set_mtime(0);
+    header.set_uid(0);
+    header.set_gid(0);
+    header
+}
+
+fn append_file(
+    builder: &mut tar::Builder<impl io::Write>,
+    path: &str,
+    data: &[u8],
+) -> io::Result<()> {
+    let mut header = new_header(tar::EntryType::Regular, 0o644, data.len() as u64);
+    builder.append_data(&mut header, path, data)
+}
+
+/// Build the layer, with the binaries in `bin_dir` under [`IMAGE_BIN_DIR`].
+fn build_layer(bin_dir: &Path) -> io::Result<Vec<u8>> {
+    let mut builder = tar::Builder::new(Vec::new());
+
+    let mut path = String::new();
+    for component in IMAGE_BIN_DIR.split('/') {
+        path.push_str(component);
+        path.push('/');
+        let mut header = new_header(tar::EntryType::Directory, 0o755, 0);
+        builder.append_data(&mut header, &path, io::empty())?;
+    }
+
+    let mut entries = fs::read_dir(bin_dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+
+        let data = fs::read(entry.path())?;
+        let mut header = new_header(tar::EntryType::Regular, 0o755, data.len() as u64);
+        builder.append_data(
+            &mut header,
+            Path::new(IMAGE_BIN_DIR).join(entry.file_name()),
+            data.as_slice(),
+        )?;
+    }
+
+    builder.into_inner()
+}
+
+/// Write an OCI image layout tarball of a single layer containing the
+/// binaries in `bin_dir` to `output`.
+fn write_image(bin_dir: &Path, platform: &Platform, output: &Path) -> io::Result<()> {
+    let layer = build_layer(bin_dir)?;
+
+    let mut platform_json = json!({
+        "architecture": platform.architecture,
+        "os": "linux",
+    });
+    if let Some(variant) = platform.variant {
+        platform_json["variant"] = variant.into();
+    }
+
+    let mut config = platform_json.clone();
+    config["config"] = json!({
+        "Env": ["PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin"],
+    });
+    config["rootfs"] = json!({
+        "type": "layers",
+        "diff_ids": [sha256_digest(&layer)],
+    });
+    let config = serde_json::to_vec(&config)?;
+
+    let manifest = serde_json::to_vec(&json!({
+        "schemaVersion": 2,
+        "mediaType": MANIFEST_MEDIA_TYPE,
+        "config": descriptor(CONFIG_MEDIA_TYPE, &config),
+        "layers": [descriptor(LAYER_MEDIA_TYPE, &layer)],
+    }))?;
+
+    let mut manifest_descriptor = descriptor(MANIFEST_MEDIA_TYPE, &manifest);
+    manifest_descriptor["platform"] = platform_json;
+
+    let index = serde_json::to_vec(&json!({
+        "schemaVersion": 2,
+        "mediaType": INDEX_MEDIA_TYPE,
+        "manifests": [manifest_descriptor],
+    }))?;
+
+    let mut builder = tar::Builder::new(fs::File::create(output)?);
+
+    append_file(
+        &mut builder,
+        "oci-layout",
+        br#"{"imageLayoutVersion":"1.0.0"}"#,
+    )?;
+    append_file(&mut builder, "index.json", &index)?;
+    for blob in [&layer, &config, &manifest] {
+        let digest = sha256_digest(blob);
+        let hex = digest.trim_start_matches("sha256:");
+        append_file(&mut builder, &format!("blobs/sha256/{hex}"), blob)?;
+    }
+
+    builder.into_inner()?.sync_all()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeMap, io::Read};
+
+    use super::*;
+
+    #[test]
+    fn test_platform() {
+        assert_eq!(
+            platform("x86_64-unknown-linux-musl"),
+            Some(Platform {
+                architecture: "amd64",
+                variant: None
+            })
+        );
+        assert_eq!(
+            platform("armv7-unknown-linux-gnueabihf"),
+            Some(Platform {
+                architecture: "arm",
+                variant: Some("v7")
+            })
+        );
+        assert_eq!(platform("x86_64-pc-windows-msvc"), None);
+    }
+
+    #[test]
+    fn test_write_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let bin_dir = dir.path().join("bin");
+        fs::create_dir(&bin_dir).unwrap();
+        fs::write(bin_dir.join("foo"), b"foo binary").unwrap();
+
+        let output = dir.path().join("image.tar");
+        write_image(
+            &bin_dir,
+            &platform("aarch64-unknown-linux-gnu").unwrap(),
+            &output,
+        )
+        .unwrap();
+
+        let mut files = BTreeMap::new();
+        for entry in tar::Archive::new(fs::File::open(&output).unwrap())
+            .entries()
+            .unwrap()
+        {
+            let mut entry = entry.unwrap();
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data).unwrap();
+            files.insert(entry.path().unwrap().to_str().unwrap().to_owned(), data);
+        }
+
+        let blob = |digest: &Value| {
+            let path = format!(
+                "blobs/sha256/{}",
+                digest.as_str().unwrap().trim_start_matches("sha256:")
+            );
+            files[&path].clone()
+        };
+
+        let index: Value = serde_json::from_slice(&files["index.json"]).unwrap();
+        let manifest_descriptor = &index["manifests"][0];
+        assert_eq!(manifest_descriptor["platform"]["architecture"], "arm64");
+
+        let manifest: Value =
+            serde_json::from_slice(&blob(&manifest_descriptor["digest"])).unwrap();
+        let layer = blob(&manifest["layers"][0]["digest"]);
+
+        let mut layer_files = Vec::new();
+        for entry in tar::Archive::new(layer.as_slice()).entries().unwrap() {
+            layer_files.push(entry.unwrap().path().unwrap().to_str().unwrap().to_owned());
+        }
+        assert_eq!(
+            layer_files,
+            ["usr/", "usr/local/", "usr/local/bin/", "usr/local/bin/foo"]
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! `cargo binstall generate`: generate files letting other tools install
+//! a pinned set of crates with binstall.
+
+use std::{
+    fmt::Write as _,
+    fs,
+    future::Future,
+    path::{Path, PathBuf},
+};
+
+use binstalk::{
+    errors::BinstallError, helpers::jobserver_client::LazyJobserverClient, ops::resolve::CrateName,
+};
+use compact_str::CompactString;
+use miette::{miette, Result};
+use semver::Version;
+use serde_json::json;
+use tokio::task::block_in_place;
+use tracing::info;
+
+use crate::{args::Args, prefetch::load_tools, probe::compute_probe_options};
+
+/// Install script of binstall, used if binstall is not installed yet.
+const INSTALL_BINSTALL_SCRIPT: &str =
+    "https://raw.githubusercontent.com/cargo-bins/cargo-binstall/main/install-from-binstall-release.sh";
+
+/// Pin `tools` to the latest versions matching their version requirements.
+async fn pin_tools(
+    args: Args,
+    tools: Vec<CrateName>,
+    jobserver_client: LazyJobserverClient,
+) -> Result<Vec<(CompactString, Version)>> {
+    let (opts, _temp_dir) = compute_probe_options(args, Vec::new(), jobserver_client)?;
+
+    let mut pinned = Vec::with_capacity(tools.len());
+    for CrateName { name, version_req } in tools {
+        let version_req = version_req.unwrap_or(semver::VersionReq::STAR);
+        let manifest = opts
+            .registry
+            .fetch_crate_matched(opts.client.clone(), &name, &version_req)
+            .await
+            .map_err(|err| BinstallError::from(err).crate_context(name.clone()))?;
+
+        let package = manifest
+            .package
+            .ok_or_else(|| BinstallError::CargoTomlMissingPackage(name.clone()))?;
+        let version = Version::parse(package.version())
+            .map_err(|err| miette!("Invalid version of {name}: {err}"))?;
+
+        pinned.push((name, version));
+    }
+    Ok(pinned)
+}
+
+fn tools_lock(pinned: &[(CompactString, Version)]) -> String {
+    let mut lock =
+        String::from("# Generated by `cargo binstall generate`, do not edit.\n[tools]\n");
+    for (name, version) in pinned {
+        writeln!(lock, "{name} = \"={version}\"").unwrap();
+    }
+    lock
+}
+
+fn install_script(pinned: &[(CompactString, Version)]) -> String {
+    let mut script = format!(
+        "#!/bin/sh
+# Generated by `cargo binstall generate devcontainer-feature`, do not edit.
+set -eu
+
+if ! command -v cargo-binstall >/dev/null 2>&1; then
+    curl -L --proto '=https' --tlsv1.2 -sSf {INSTALL_BINSTALL_SCRIPT} | bash
+fi
+
+cargo binstall --no-confirm --force"
+    );
+    for (name, version) in pinned {
+        write!(script, " \\\n    '{name}@={version}'").unwrap();
+    }
+    script.push('\n');
+    script
+}
+
+fn feature_json(id: &str, pinned: &[(CompactString, Version)]) -> String {
+    let tools = pinned
+        .iter()
+        .map(|(name, version)| format!("{name} v{version}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let feature = json!({
+        "id": id,
+        "version": "1.0.0",
+        "name": format!("{id} (cargo-binstall)"),
+        "description": format!("Installs {tools} with cargo-binstall"),
+        "options": {},
+        "installsAfter": ["ghcr.io/devcontainers/features/rust"],
+    });
+
+    let mut json = serde_json::to_string_pretty(&feature).unwrap();
+    json.push('\n');
+    json
+}
+
+fn write_feature(output: &Path, id: &str, pinned: &[(CompactString, Version)]) -> Result<()> {
+    let write = |name: &str, content: String| {
+        let path = output.join(name);
+        fs::write(&path, content)
+            .map_err(|err| miette!("Failed to write {}: {err}", path.display()))
+    };
+
+    fs::create_dir_all(output)
+        .map_err(|err| miette!("Failed to create {}: {err}", output.display()))?;
+
+    write("devcontainer-feature.json", feature_json(id, pinned))?;
+    write("install.sh", install_script(pinned))?;
+    write("tools.lock", tools_lock(pinned))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        fs::set_permissions(output.join("install.sh"), fs::Permissions::from_mode(0o755))
+            .map_err(|err| miette!("Failed to make install.sh executable: {err}"))?;
+    }
+
+    Ok(())
+}
+
+pub fn devcontainer_feature(
+    args: Args,
+    from_file: &Path,
+    output: PathBuf,
+    id: String,
+    jobserver_client: LazyJobserverClient,
+) -> Result<Option<impl Future<Output = Result<()>>>> {
+    let tools = load_tools(from_file, args.verify_lockfile.as_ref())?;
+
+    Ok(Some(async move {
+        let pinned = pin_tools(args, tools, jobserver_client).await?;
+
+        block_in_place(|| write_feature(&output, &id, &pinned))?;
+
+        info!(
+            "Generated devcontainer feature {id} in {}",
+            output.display()
+        );
+        Ok(())
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_feature() {
+        let dir = tempfile::tempdir().unwrap();
+        let pinned = [
+            ("cargo-nextest".into(), Version::new(0, 9, 59)),
+            ("ripgrep".into(), Version::new(13, 0, 0)),
+        ];
+
+        write_feature(dir.path(), "rust-tools", &pinned).unwrap();
+
+        let feature: serde_json::Value = serde_json::from_slice(
+            &fs::read(dir.path().join("devcontainer-feature.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(feature["id"], "rust-tools");
+
+        let script = fs::read_to_string(dir.path().join("install.sh")).unwrap();
+        assert!(script.ends_with(
+            "cargo binstall --no-confirm --force \\\n    \
+            'cargo-nextest@=0.9.59' \\\n    'ripgrep@=13.0.0'\n"
+        ));
+
+        // The lockfile can be used as the tools file of `prefetch`.
+        let tools = load_tools(&dir.path().join("tools.lock"), None).unwrap();
+        assert_eq!(
+            tools.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["cargo-nextest@=0.9.59", "ripgrep@=13.0.0"]
+        );
+    }
+}
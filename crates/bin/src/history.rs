@@ -0,0 +1,114 @@
+//! `cargo binstall history`: query the audit log of installs and upgrades.
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use binstalk::errors::BinstallError;
+use binstalk_manifests::{
+    audit_log::{self, Action, Entry},
+    cargo_config::Config,
+};
+use compact_str::CompactString;
+use home::cargo_home;
+use miette::{miette, Result};
+
+use crate::{args::Args, install_path};
+
+fn format_entry(entry: &Entry) -> String {
+    let time = httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(entry.time));
+    let user = entry.user.as_deref().unwrap_or("unknown user");
+    let Entry {
+        name,
+        version,
+        target,
+        ..
+    } = entry;
+
+    let change = match (entry.action, &entry.previous_version) {
+        (Action::Upgrade, Some(previous_version)) => {
+            format!("upgraded {name} v{previous_version} -> v{version}")
+        }
+        (Action::Install, Some(_)) => format!("reinstalled {name} v{version}"),
+        _ => format!("installed {name} v{version}"),
+    };
+
+    let mut line = format!("{time}  {user}  {change} ({target})");
+    for digest in &entry.bin_digests {
+        line += &format!("\n    {} sha256:{}", digest.path.display(), digest.sha256);
+    }
+    line
+}
+
+pub fn history(args: Args, crate_name: Option<CompactString>) -> Result<()> {
+    let cargo_home = cargo_home().map_err(BinstallError::from)?;
+    let mut config = Config::load_from_path(cargo_home.join("config.toml"))?;
+
+    let cargo_roots = install_path::get_cargo_roots_path(args.root, cargo_home, &mut config)
+        .ok_or_else(|| miette!("No cargo roots path found or specified"))?;
+
+    let entries = audit_log::load_from_path(audit_log::path(&cargo_roots))?;
+
+    let mut found = false;
+    for entry in entries
+        .iter()
+        .filter(|entry| crate_name.as_ref().map_or(true, |name| entry.name == *name))
+    {
+        println!("{}", format_entry(entry));
+        found = true;
+    }
+
+    if !found {
+        match crate_name {
+            Some(name) => println!("No history recorded for {name}"),
+            None => println!("No history recorded"),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use binstalk_manifests::crate_info::BinDigest;
+    use semver::Version;
+
+    use super::*;
+
+    #[test]
+    fn test_format_entry() {
+        let mut entry = Entry {
+            time: 0,
+            user: Some("alice".into()),
+            action: Action::Install,
+            name: "foo".into(),
+            version: Version::new(0, 1, 0),
+            previous_version: None,
+            target: "x86_64-unknown-linux-gnu".into(),
+            bin_digests: vec![BinDigest {
+                path: PathBuf::from("/bin/foo"),
+                sha256: "abcd".into(),
+                size: 1,
+                mtime_ns: 0,
+            }],
+        };
+
+        assert_eq!(
+            format_entry(&entry),
+            "Thu, 01 Jan 1970 00:00:00 GMT  alice  installed foo v0.1.0 \
+            (x86_64-unknown-linux-gnu)\n    /bin/foo sha256:abcd"
+        );
+
+        entry.action = Action::Upgrade;
+        entry.version = Version::new(0, 2, 0);
+        entry.previous_version = Some(Version::new(0, 1, 0));
+        entry.user = None;
+        entry.bin_digests.clear();
+
+        assert_eq!(
+            format_entry(&entry),
+            "Thu, 01 Jan 1970 00:00:00 GMT  unknown user  upgraded foo v0.1.0 -> v0.2.0 \
+            (x86_64-unknown-linux-gnu)"
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Resume journal of the crates left to install when binstall is
+//! interrupted, e.g. by Ctrl-C, so that `--resume` installs them later.
+//!
+//! It is stored at `$CARGO_ROOT/binstall/resume.json`, see [`Journal`].
+//!
+//! The downloads in progress are journaled in `$CARGO_ROOT/binstall/downloads`
+//! too, so that the temporary data of a run which crashed or was killed is
+//! cleaned up by the next one, see [`open_download_journal`].
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use binstalk::{
+    helpers::{message::Message, DownloadJournal, JournalEntry},
+    ops::resolve::CrateName,
+};
+use compact_str::{CompactString, ToCompactString};
+use miette::{miette, Result};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+#[derive(Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Journal {
+    /// Crates left to install, as `crate[@version]`.
+    crates: Vec<CompactString>,
+}
+
+fn journal_path(cargo_roots: &Path) -> PathBuf {
+    cargo_roots.join("binstall/resume.json")
+}
+
+/// Load the crates left to install by the last interrupted run, if any.
+pub(crate) fn load(cargo_roots: &Path) -> Result<Vec<CrateName>> {
+    let path = journal_path(cargo_roots);
+    let content = match fs::read(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(miette!("Failed to read {}: {err}", path.display())),
+    };
+
+    let journal: Journal = serde_json::from_slice(&content)
+        .map_err(|err| miette!("Failed to parse {}: {err}", path.display()))?;
+    journal
+        .crates
+        .iter()
+        .map(|crate_name| {
+            crate_name
+                .parse()
+                .map_err(|err| miette!("Invalid crate {crate_name} in {}: {err}", path.display()))
+        })
+        .collect()
+}
+
+/// Clean up the temporary data left behind by the runs which crashed or
+/// were killed, then open the journal of the downloads of this run, with
+/// an entry for its `temp_dir` kept until the entry returned is dropped.
+///
+/// Failures are only logged since nothing depends on the journal.
+pub(crate) fn open_download_journal(
+    cargo_roots: &Path,
+    temp_dir: &Path,
+) -> Option<(DownloadJournal, JournalEntry)> {
+    let res = DownloadJournal::new(cargo_roots.join("binstall/downloads")).and_then(|journal| {
+        let recovered = journal.recover()?;
+        if !recovered.is_empty() {
+            Message::new(
+                "binstall.downloads-recovered",
+                "Cleaned up { count } downloads left behind by an interrupted run",
+            )
+            .arg("count", recovered.len().to_string())
+            .info();
+        }
+
+        let entry = journal.begin(None, None)?;
+        entry.add_temp_path(temp_dir);
+        Ok((journal, entry))
+    });
+
+    res.map_err(|err| warn!("Failed to open the download journal: {err}"))
+        .ok()
+}
+
+fn write(path: &Path, journal: &Journal) -> io::Result<()> {
+    fs::create_dir_all(path.parent().unwrap())?;
+
+    // Write to a temporary file then rename it, so that an interrupted
+    // write never leaves a corrupted journal.
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, serde_json::to_vec(journal)?)?;
+    fs::rename(tmp_path, path)
+}
+
+/// Crates of the current run which are not done yet, by name.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Pending(Arc<Mutex<BTreeMap<CompactString, CompactString>>>);
+
+impl Pending {
+    pub(crate) fn add(&self, crate_name: &CrateName) {
+        self.0
+            .lock()
+            .unwrap()
+            .insert(crate_name.name.clone(), crate_name.to_compact_string());
+    }
+
+    /// The crate `name` is installed, or failed to be.
+    pub(crate) fn done(&self, name: &str) {
+        self.0.lock().unwrap().remove(name);
+    }
+}
+
+/// Write the journal of the crates still pending when dropped before
+/// [`ResumeGuard::finish`], i.e. when the install is cancelled.
+#[derive(Debug)]
+pub(crate) struct ResumeGuard {
+    /// `None` if installed crates are not tracked, or once finished.
+    path: Option<PathBuf>,
+    pending: Pending,
+}
+
+impl ResumeGuard {
+    pub(crate) fn new(cargo_roots: Option<&Path>) -> Self {
+        Self {
+            path: cargo_roots.map(journal_path),
+            pending: Pending::default(),
+        }
+    }
+
+    pub(crate) fn pending(&self) -> Pending {
+        self.pending.clone()
+    }
+
+    /// The run completed, successfully or not, so there is nothing to
+    /// resume: remove the journal of the previous run, if any.
+    pub(crate) fn finish(mut self) {
+        if let Some(path) = self.path.take() {
+            match fs::remove_file(&path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                    warn!("Failed to remove the resume journal: {err}")
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+impl Drop for ResumeGuard {
+    fn drop(&mut self) {
+        let Some(path) = self.path.take() else {
+            return;
+        };
+        let crates: Vec<_> = self.pending.0.lock().unwrap().values().cloned().collect();
+        if crates.is_empty() {
+            return;
+        }
+
+        let len = crates.len();
+        match write(&path, &Journal { crates }) {
+            Ok(()) => warn!("{len} crates were not installed, use --resume to install them"),
+            Err(err) => warn!("Failed to write the resume journal: {err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resume_guard() {
+        let cargo_roots = tempfile::tempdir().unwrap();
+        let crate_names = ["ripgrep@14", "cargo-watch", "bat@^0.24"]
+            .map(|crate_name| crate_name.parse::<CrateName>().unwrap());
+
+        let guard = ResumeGuard::new(Some(cargo_roots.path()));
+        let pending = guard.pending();
+        for crate_name in &crate_names {
+            pending.add(crate_name);
+        }
+        pending.done("cargo-watch");
+        // Cancelled
+        drop(guard);
+
+        let loaded = load(cargo_roots.path()).unwrap();
+        assert_eq!(
+            loaded.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["bat@^0.24", "ripgrep@=14"]
+        );
+
+        ResumeGuard::new(Some(cargo_roots.path())).finish();
+        assert!(load(cargo_roots.path()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,201 @@
+use std::io::{self, Write};
+
+use binstalk::{
+    fetchers::Fetcher,
+    ops::{
+        event::{InstallEvent, ProgressSink},
+        resolve::Resolution,
+    },
+};
+use serde_json::{json, Value};
+use tracing::warn;
+
+/// Print [`InstallEvent`]s as newline-delimited JSON on stdout.
+pub(crate) struct JsonLinesSink;
+
+fn fetcher_to_json(crate_name: &str, fetcher: &dyn Fetcher) -> Value {
+    json!({
+        "crate": crate_name,
+        "source": fetcher.source_name(),
+        "url": fetcher.download_url().map(|url| url.as_str()),
+        "target": fetcher.target(),
+        "third-party": fetcher.is_third_party(),
+    })
+}
+
+fn event_to_json(event: InstallEvent<'_>) -> Option<Value> {
+    let (event, mut value) = match event {
+        InstallEvent::Environment { environment } => (
+            "environment",
+            serde_json::to_value(environment).unwrap_or_default(),
+        ),
+        InstallEvent::Resolving { crate_name } => (
+            "resolving",
+            json!({
+                "crate": crate_name.name,
+                "version-req": crate_name.version_req.as_ref().map(|req| req.to_string()),
+            }),
+        ),
+        InstallEvent::FetcherFound {
+            crate_name,
+            fetcher,
+        } => ("fetcher-found", fetcher_to_json(crate_name, fetcher)),
+        InstallEvent::Downloading {
+            crate_name,
+            received,
+            total,
+        } => (
+            "downloading",
+            json!({
+                "crate": crate_name,
+                "received": received,
+                "total": total,
+            }),
+        ),
+        InstallEvent::Extracting {
+            crate_name,
+            path,
+            written,
+            size,
+        } => (
+            "extracting",
+            json!({
+                "crate": crate_name,
+                "path": path,
+                "written": written,
+                "size": size,
+            }),
+        ),
+        InstallEvent::Downloaded {
+            crate_name,
+            fetcher,
+        } => ("downloaded", fetcher_to_json(crate_name, fetcher)),
+        InstallEvent::Verified {
+            crate_name,
+            fetcher,
+        } => ("verified", fetcher_to_json(crate_name, fetcher)),
+        InstallEvent::FetchFailed {
+            crate_name,
+            fetcher,
+            reason,
+        } => {
+            let mut value = fetcher_to_json(crate_name, fetcher);
+            value["reason"] = reason.into();
+            ("fetch-failed", value)
+        }
+        InstallEvent::Resolved {
+            crate_name,
+            resolution,
+        } => (
+            "resolved",
+            match resolution {
+                Resolution::Fetch(fetch) => json!({
+                    "crate": crate_name,
+                    "status": "fetch",
+                    "version": fetch.new_version.to_string(),
+                    "source": fetch.fetcher.source_name(),
+                    "degraded": fetch.fetcher.is_degraded(),
+                    "rejected": fetch
+                        .failed_fetches
+                        .iter()
+                        .map(|failed| {
+                            let mut value = fetcher_to_json(crate_name, failed.fetcher.as_ref());
+                            value["reason"] = failed.reason.as_str().into();
+                            value
+                        })
+                        .collect::<Vec<_>>(),
+                }),
+                Resolution::InstallFromSource(source) => json!({
+                    "crate": crate_name,
+                    "status": "source",
+                    "version": source.version,
+                }),
+                Resolution::AlreadyUpToDate => json!({
+                    "crate": crate_name,
+                    "status": "up-to-date",
+                }),
+            },
+        ),
+        InstallEvent::Changelog {
+            crate_name,
+            version,
+            notes,
+        } => (
+            "changelog",
+            json!({
+                "crate": crate_name,
+                "version": version.to_string(),
+                "notes": notes,
+            }),
+        ),
+        InstallEvent::Installed { crate_info } => (
+            "installed",
+            json!({
+                "crate": crate_info.name,
+                "version": crate_info.current_version.to_string(),
+                "target": crate_info.target,
+                "bins": crate_info.bins,
+            }),
+        ),
+        InstallEvent::InstalledFromSource {
+            crate_name,
+            version,
+        } => (
+            "installed-from-source",
+            json!({
+                "crate": crate_name,
+                "version": version,
+            }),
+        ),
+        _ => return None,
+    };
+
+    value["event"] = event.into();
+    Some(value)
+}
+
+impl ProgressSink for JsonLinesSink {
+    fn on_event(&self, event: InstallEvent<'_>) {
+        if let Some(value) = event_to_json(event) {
+            let mut line = value.to_string();
+            line.push('\n');
+
+            if let Err(err) = io::stdout().lock().write_all(line.as_bytes()) {
+                warn!("Failed to write event to stdout: {err}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_event_to_json() {
+        let crate_name = "cargo-binstall@1".parse().unwrap();
+
+        assert_eq!(
+            event_to_json(InstallEvent::Resolving {
+                crate_name: &crate_name
+            }),
+            Some(json!({
+                "event": "resolving",
+                "crate": "cargo-binstall",
+                "version-req": "=1",
+            }))
+        );
+
+        assert_eq!(
+            event_to_json(InstallEvent::InstalledFromSource {
+                crate_name: "cargo-binstall",
+                version: "1.0.0",
+            }),
+            Some(json!({
+                "event": "installed-from-source",
+                "crate": "cargo-binstall",
+                "version": "1.0.0",
+            }))
+        );
+    }
+}
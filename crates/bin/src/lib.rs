@@ -1,14 +1,41 @@
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 
+mod apply;
+mod archive_artifacts;
 mod args;
 mod bin_util;
+mod cache;
+mod changelog;
+mod complete;
+mod diff;
 mod entry;
+mod environment;
+mod export_nix;
+mod export_oci;
+mod generate;
 mod gh_token;
 mod git_credentials;
+mod history;
 mod install_path;
+mod journal;
+mod json_lines;
+mod lint;
+mod lockfile;
 mod logging;
 mod main_impl;
+mod messages;
+mod metrics;
+mod notify;
+mod policy;
+mod prefetch;
+mod probe;
+mod progress_bars;
+mod publishers;
+mod remote;
+mod serve;
 mod signal;
+mod status_file;
 mod ui;
+mod verify;
 
 pub use main_impl::do_main;
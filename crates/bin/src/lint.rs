@@ -0,0 +1,191 @@
+//! `cargo binstall lint-metadata` and `cargo binstall render-template`:
+//! help maintainers to get `[package.metadata.binstall]` right.
+
+use std::{iter, path::PathBuf};
+
+use binstalk::{
+    manifests::cargo_toml_binstall::{PkgMeta, PkgOverride},
+    ops::lint::{
+        expand_templates, lint_metadata as lint, load_package_templates, Expansion, LintReport,
+        PackageTemplates, TemplateInput,
+    },
+    TARGET,
+};
+use miette::{miette, Result};
+
+use crate::args::RenderTemplateArgs;
+
+pub fn lint_metadata(manifest_path: Option<PathBuf>, targets: Vec<String>) -> Result<()> {
+    let mut manifest_path = manifest_path.unwrap_or_else(|| PathBuf::from("Cargo.toml"));
+    if manifest_path.is_dir() {
+        manifest_path.push("Cargo.toml");
+    }
+
+    let report = lint(&manifest_path, &targets)?;
+
+    print!("{}", format_report(&report));
+
+    match report.lints.len() {
+        0 => Ok(()),
+        n => Err(miette!(
+            "Found {n} problems in the binstall metadata of {}",
+            manifest_path.display()
+        )),
+    }
+}
+
+pub fn render_template(args: RenderTemplateArgs) -> Result<()> {
+    let manifest_path = args
+        .manifest_path
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("Cargo.toml"));
+
+    let package = if args.manifest_path.is_some() || manifest_path.exists() {
+        Some(load_package_templates(&manifest_path)?)
+    } else {
+        None
+    };
+    let PackageTemplates {
+        name,
+        version,
+        repo,
+        bins,
+        meta,
+    } = package.unwrap_or_else(|| PackageTemplates {
+        name: String::new(),
+        version: String::new(),
+        repo: None,
+        bins: Vec::new(),
+        meta: PkgMeta::default(),
+    });
+
+    let name = args.name.unwrap_or(name);
+    if name.is_empty() {
+        return Err(miette!("--name is required if there is no Cargo.toml"));
+    }
+    let version = args.version.unwrap_or(version);
+    if version.is_empty() {
+        return Err(miette!("--version is required if there is no Cargo.toml"));
+    }
+    let repo = args.repo.or(repo);
+    let bins = if args.bins.is_empty() {
+        bins
+    } else {
+        args.bins
+    };
+    let bins: Vec<&str> = if bins.is_empty() {
+        vec![&name]
+    } else {
+        bins.iter().map(String::as_str).collect()
+    };
+
+    let targets = if args.targets.is_empty() {
+        vec![TARGET.to_string()]
+    } else {
+        args.targets
+    };
+
+    let cli_overrides = PkgOverride {
+        pkg_url: args.pkg_url,
+        pkg_fmt: args.pkg_fmt,
+        bin_dir: args.bin_dir,
+        flavor: args.flavor,
+        ..Default::default()
+    };
+
+    let mut expansions = Vec::with_capacity(targets.len());
+    for target in &targets {
+        let mut meta =
+            meta.merge_overrides(iter::once(&cli_overrides).chain(meta.overrides.get(target)));
+        if let Some(tag_prefix) = &args.tag_prefix {
+            meta.tag_prefix = Some(tag_prefix.clone());
+        }
+        if let Some(asset_name) = &args.asset_name {
+            meta.asset_name = Some(asset_name.clone());
+        }
+
+        if meta.pkg_url.is_none() && meta.bin_dir.is_none() {
+            return Err(miette!(
+                "Neither pkg-url nor bin-dir is specified for target {target}"
+            ));
+        }
+
+        expansions.push(expand_templates(
+            &TemplateInput {
+                name: &name,
+                version: &version,
+                repo: repo.as_deref(),
+                bins: bins.clone(),
+                meta: &meta,
+            },
+            target,
+        )?);
+    }
+
+    print!("{}", format_expansions(&expansions));
+
+    Ok(())
+}
+
+fn format_report(report: &LintReport) -> String {
+    let mut output = String::new();
+
+    for lint in &report.lints {
+        let key = if lint.key.is_empty() {
+            "package.metadata.binstall".to_string()
+        } else {
+            format!("package.metadata.binstall.{}", lint.key)
+        };
+        output.push_str(&format!("{key}: {}\n", lint.message));
+    }
+
+    output.push_str(&format_expansions(&report.expansions));
+
+    output
+}
+
+fn format_expansions(expansions: &[Expansion]) -> String {
+    let mut output = String::new();
+
+    for expansion in expansions {
+        output.push_str(&format!("{}:\n", expansion.target));
+        for pkg_url in &expansion.pkg_urls {
+            output.push_str(&format!("  pkg-url: {pkg_url}\n"));
+        }
+        for bin_path in &expansion.bin_paths {
+            output.push_str(&format!("  bin: {bin_path}\n"));
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use binstalk::ops::lint::Lint;
+
+    use super::*;
+
+    #[test]
+    fn test_format_report() {
+        let report = LintReport {
+            lints: vec![Lint {
+                key: "pkg-urll".to_string(),
+                message: "Unknown key".to_string(),
+            }],
+            expansions: vec![Expansion {
+                target: "x86_64-unknown-linux-gnu".to_string(),
+                pkg_urls: vec!["https://example.com/foo.tgz".to_string()],
+                bin_paths: vec!["foo".to_string()],
+            }],
+        };
+
+        assert_eq!(
+            format_report(&report),
+            "package.metadata.binstall.pkg-urll: Unknown key\n\
+            x86_64-unknown-linux-gnu:\n  \
+            pkg-url: https://example.com/foo.tgz\n  \
+            bin: foo\n"
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! `cargo binstall lockfile`: sign tools lockfiles (see `prefetch` and
+//! `generate`) with an Ed25519 team key, so that `--verify-lockfile` can
+//! refuse to use a tampered one.
+//!
+//! The signature of `tools.lock` is stored in `tools.lock.sig`, hex encoded.
+
+use std::{
+    fs,
+    io::Write as _,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use binstalk::helpers::hex;
+use miette::{miette, Result};
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519},
+};
+
+/// Public key of a team, hex encoded on the command line.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct PublicKey([u8; 32]);
+
+impl FromStr for PublicKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        decode_hex(s)
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(PublicKey)
+            .ok_or_else(|| "expected an Ed25519 public key of 64 hex digits".to_string())
+    }
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    let s = s.trim();
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn signature_path(lockfile: &Path) -> PathBuf {
+    let mut path = lockfile.as_os_str().to_owned();
+    path.push(".sig");
+    PathBuf::from(path)
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).map_err(|err| miette!("Failed to read {}: {err}", path.display()))
+}
+
+fn write(path: &Path, content: &[u8]) -> Result<()> {
+    fs::write(path, content).map_err(|err| miette!("Failed to write {}: {err}", path.display()))
+}
+
+/// Return true if `signature`, hex encoded, is the signature of `content`
+/// by `key`.
+pub(crate) fn verify_signature(content: &[u8], signature: &[u8], key: &PublicKey) -> bool {
+    decode_hex(&String::from_utf8_lossy(signature))
+        .map(|signature| {
+            UnparsedPublicKey::new(&ED25519, &key.0)
+                .verify(content, &signature)
+                .is_ok()
+        })
+        .unwrap_or(false)
+}
+
+/// Check that `content`, read from `lockfile`, is signed by `key`.
+pub(crate) fn verify(lockfile: &Path, content: &[u8], key: &PublicKey) -> Result<()> {
+    let signature = read(&signature_path(lockfile))?;
+
+    if verify_signature(content, &signature, key) {
+        Ok(())
+    } else {
+        Err(miette!(
+            "{} is not signed by the key {}, refusing to use it",
+            lockfile.display(),
+            hex(key.0)
+        ))
+    }
+}
+
+fn load_key_pair(key: &Path) -> Result<Ed25519KeyPair> {
+    Ed25519KeyPair::from_pkcs8(&read(key)?)
+        .map_err(|err| miette!("Invalid key {}: {err}", key.display()))
+}
+
+/// Generate a key pair into `output`, in PKCS#8 format, and print its
+/// public key.
+pub fn keygen(output: &Path) -> Result<()> {
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+        .map_err(|err| miette!("Failed to generate a key: {err}"))?;
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(output)
+        .and_then(|mut file| file.write_all(pkcs8.as_ref()))
+        .map_err(|err| miette!("Failed to create {}: {err}", output.display()))?;
+
+    let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref())
+        .map_err(|err| miette!("Invalid key generated: {err}"))?;
+    println!("{}", hex(key_pair.public_key().as_ref()));
+
+    Ok(())
+}
+
+/// Sign `lockfile` with the key pair in `key`.
+pub fn sign(lockfile: &Path, key: &Path) -> Result<()> {
+    let key_pair = load_key_pair(key)?;
+    let signature = key_pair.sign(&read(lockfile)?);
+
+    let signature_path = signature_path(lockfile);
+    write(
+        &signature_path,
+        format!("{}\n", hex(signature.as_ref())).as_bytes(),
+    )?;
+    println!(
+        "Signed {} into {}",
+        lockfile.display(),
+        signature_path.display()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = dir.path().join("team.key");
+        let lockfile = dir.path().join("tools.lock");
+
+        keygen(&key).unwrap();
+        let public_key = PublicKey(
+            load_key_pair(&key)
+                .unwrap()
+                .public_key()
+                .as_ref()
+                .try_into()
+                .unwrap(),
+        );
+        assert_eq!(
+            hex(public_key.0).parse::<PublicKey>(),
+            Ok(public_key.clone())
+        );
+
+        let content = b"[tools]\nripgrep = \"=13.0.0\"\n";
+        fs::write(&lockfile, content).unwrap();
+        sign(&lockfile, &key).unwrap();
+
+        verify(&lockfile, content, &public_key).unwrap();
+        verify(&lockfile, b"[tools]\nripgrep = \"=14.0.0\"\n", &public_key).unwrap_err();
+
+        let other_key = PublicKey([0; 32]);
+        verify(&lockfile, content, &other_key).unwrap_err();
+
+        // The key is never overwritten.
+        keygen(&key).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_public_key() {
+        assert!("ab".parse::<PublicKey>().is_err());
+        assert!("zz".repeat(32).parse::<PublicKey>().is_err());
+        assert_eq!("01".repeat(32).parse(), Ok(PublicKey([1; 32])));
+    }
+}
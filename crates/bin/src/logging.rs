@@ -6,7 +6,10 @@ use std::{
 
 use log::{LevelFilter, Log, STATIC_MAX_LEVEL};
 use once_cell::sync::Lazy;
-use supports_color::{on as supports_color_on_stream, Stream::Stdout};
+use supports_color::{
+    on as supports_color_on_stream,
+    Stream::{Stderr, Stdout},
+};
 use tracing::{
     callsite::Callsite,
     dispatcher, field,
@@ -17,7 +20,7 @@ use tracing_core::{identify_callsite, metadata::Kind, subscriber::Subscriber};
 use tracing_log::AsTrace;
 use tracing_subscriber::{
     filter::targets::Targets,
-    fmt::{fmt, MakeWriter},
+    fmt::{fmt, format::debug_fn, MakeWriter},
     layer::SubscriberExt,
 };
 
@@ -137,47 +140,54 @@ impl Log for Logger {
     fn flush(&self) {}
 }
 
-struct ErrorFreeWriter;
+/// Writes to stdout, or stderr if `to_stderr` is true.
+struct ErrorFreeWriter {
+    to_stderr: bool,
+}
 
 fn report_err(err: io::Error) {
-    writeln!(io::stderr(), "Failed to write to stdout: {err}").ok();
+    writeln!(io::stderr(), "Failed to write log: {err}").ok();
 }
 
-impl io::Write for &ErrorFreeWriter {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        io::stdout().write(buf).or_else(|err| {
+impl ErrorFreeWriter {
+    fn with_output<T>(
+        &self,
+        f: impl FnOnce(&mut dyn io::Write) -> io::Result<T>,
+        on_err: T,
+    ) -> io::Result<T> {
+        let res = if self.to_stderr {
+            f(&mut io::stderr())
+        } else {
+            f(&mut io::stdout())
+        };
+
+        res.or_else(|err| {
             report_err(err);
             // Behave as if writing to /dev/null so that logging system
             // would keep working.
-            Ok(buf.len())
+            Ok(on_err)
         })
     }
+}
+
+impl io::Write for &ErrorFreeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.with_output(|output| output.write(buf), buf.len())
+    }
 
     fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
-        io::stdout().write_all(buf).or_else(|err| {
-            report_err(err);
-            // Behave as if writing to /dev/null so that logging system
-            // would keep working.
-            Ok(())
-        })
+        self.with_output(|output| output.write_all(buf), ())
     }
 
     fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
-        io::stdout().write_vectored(bufs).or_else(|err| {
-            report_err(err);
-            // Behave as if writing to /dev/null so that logging system
-            // would keep working.
-            Ok(bufs.iter().map(|io_slice| io_slice.len()).sum())
-        })
+        self.with_output(
+            |output| output.write_vectored(bufs),
+            bufs.iter().map(|io_slice| io_slice.len()).sum(),
+        )
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        io::stdout().flush().or_else(|err| {
-            report_err(err);
-            // Behave as if writing to /dev/null so that logging system
-            // would keep working.
-            Ok(())
-        })
+        self.with_output(|output| output.flush(), ())
     }
 }
 
@@ -189,7 +199,8 @@ impl<'a> MakeWriter<'a> for ErrorFreeWriter {
     }
 }
 
-pub fn logging(log_level: LevelFilter, json_output: bool) {
+/// * `to_stderr` - print logs to stderr instead of stdout.
+pub fn logging(log_level: LevelFilter, json_output: bool, to_stderr: bool) {
     // Calculate log_level
     let log_level = min(log_level, STATIC_MAX_LEVEL);
 
@@ -210,7 +221,9 @@ pub fn logging(log_level: LevelFilter, json_output: bool) {
 
     // Build fmt subscriber
     let log_level = log_level.as_trace();
-    let subscriber_builder = fmt().with_max_level(log_level).with_writer(ErrorFreeWriter);
+    let subscriber_builder = fmt()
+        .with_max_level(log_level)
+        .with_writer(ErrorFreeWriter { to_stderr });
 
     let subscriber: Box<dyn Subscriber + Send + Sync> = if json_output {
         Box::new(subscriber_builder.json().finish())
@@ -218,6 +231,14 @@ pub fn logging(log_level: LevelFilter, json_output: bool) {
         // Disable time, target, file, line_num, thread name/ids to make the
         // output more readable
         let subscriber_builder = subscriber_builder
+            // The identifiers of the messages are only for json output,
+            // they are printed in place of the text with
+            // `--message-format id`.
+            .fmt_fields(debug_fn(|writer, field, value| match field.name() {
+                "message" => write!(writer, "{value:?}"),
+                "message_id" => Ok(()),
+                name => write!(writer, " {name}={value:?}"),
+            }))
             .without_time()
             .with_target(false)
             .with_file(false)
@@ -225,13 +246,12 @@ pub fn logging(log_level: LevelFilter, json_output: bool) {
             .with_thread_names(false)
             .with_thread_ids(false);
 
-        // subscriber_builder defaults to write to io::stdout(),
-        // so tests whether it supports color.
-        let stdout_supports_color = supports_color_on_stream(Stdout)
+        // Tests whether the output supports color.
+        let supports_color = supports_color_on_stream(if to_stderr { Stderr } else { Stdout })
             .map(|color_level| color_level.has_basic)
             .unwrap_or_default();
 
-        Box::new(subscriber_builder.with_ansi(stdout_supports_color).finish())
+        Box::new(subscriber_builder.with_ansi(supports_color).finish())
     };
 
     // Builder layer for filtering
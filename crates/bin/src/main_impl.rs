@@ -1,21 +1,30 @@
 use std::{process::Termination, time::Instant};
 
-use binstalk::{helpers::jobserver_client::LazyJobserverClient, TARGET};
+use binstalk::{
+    helpers::{
+        jobserver_client::LazyJobserverClient,
+        message::{set_message_format, MessageFormat},
+    },
+    TARGET,
+};
 use log::LevelFilter;
 use tracing::debug;
 
 use crate::{
-    args,
+    apply, archive_artifacts, args,
     bin_util::{run_tokio_main, MainExit},
-    entry,
+    cache, complete, diff, entry, export_nix, export_oci, generate, history, lint, lockfile,
     logging::logging,
+    messages, metrics,
+    notify::notify,
+    prefetch, probe, remote, serve, verify,
 };
 
 pub fn do_main() -> impl Termination {
     // This must be the very first thing to happen
     let jobserver_client = LazyJobserverClient::new();
 
-    let args = args::parse();
+    let mut args = args::parse();
 
     if args.version {
         let cargo_binstall_version = env!("CARGO_PKG_VERSION");
@@ -47,18 +56,126 @@ rustc-llvm-version: {rustc_llvm_version}"#
         }
         MainExit::Success(None)
     } else {
+        // The candidates printed by `complete` must not be mixed with logs.
+        if matches!(args.command, Some(args::Command::Complete { .. })) {
+            args.log_level = Some(LevelFilter::Off);
+        }
+
         logging(
             args.log_level.unwrap_or(LevelFilter::Info),
             args.json_output,
+            args.json_lines,
         );
 
+        match args.message_format {
+            args::MessageFormat::Human => {
+                if let Ok(cargo_home) = home::cargo_home() {
+                    messages::load_catalog(&cargo_home);
+                }
+            }
+            args::MessageFormat::Id => set_message_format(MessageFormat::Id),
+        }
+
         let start = Instant::now();
+        // The time spent is not reproducible.
+        let deterministic = args.deterministic;
+
+        let result = match args.command.take() {
+            Some(args::Command::Serve { socket }) => {
+                run_tokio_main(|| serve::serve(args, socket, jobserver_client))
+            }
+            Some(args::Command::Probe {
+                crate_name,
+                targets,
+            }) => run_tokio_main(|| probe::probe(args, crate_name, targets, jobserver_client)),
+            Some(args::Command::CheckRelease {
+                crate_name,
+                targets,
+            }) => {
+                run_tokio_main(|| probe::check_release(args, crate_name, targets, jobserver_client))
+            }
+            Some(args::Command::LintMetadata {
+                manifest_path,
+                targets,
+            }) => lint::lint_metadata(manifest_path, targets),
+            Some(args::Command::RenderTemplate(render_args)) => lint::render_template(render_args),
+            Some(args::Command::Verify { crate_names, quick }) => {
+                verify::verify(args, crate_names, quick)
+            }
+            Some(args::Command::History { crate_name }) => history::history(args, crate_name),
+            Some(args::Command::Metrics { reset }) => metrics::export(args, reset),
+            Some(args::Command::Complete {
+                register,
+                index,
+                words,
+            }) => complete::complete(args, register, index, words),
+            Some(args::Command::Diff {
+                crate_name,
+                old,
+                new,
+            }) => run_tokio_main(|| diff::diff(args, crate_name, old, new, jobserver_client)),
+            Some(args::Command::Prefetch { from_file, targets }) => {
+                run_tokio_main(|| prefetch::prefetch(args, &from_file, targets, jobserver_client))
+            }
+            Some(args::Command::ExportOci {
+                crate_names,
+                output,
+            }) => run_tokio_main(|| {
+                export_oci::export_oci(args, crate_names, output, jobserver_client)
+            }),
+            Some(args::Command::ArchiveArtifacts { crate_names, dest }) => run_tokio_main(|| {
+                archive_artifacts::archive_artifacts(args, crate_names, dest, jobserver_client)
+            }),
+            Some(args::Command::Generate(args::GenerateCommand::DevcontainerFeature {
+                from_file,
+                output,
+                id,
+            })) => run_tokio_main(|| {
+                generate::devcontainer_feature(args, &from_file, output, id, jobserver_client)
+            }),
+            Some(args::Command::Apply { plan }) => {
+                run_tokio_main(|| apply::apply(args, &plan, jobserver_client))
+            }
+            Some(args::Command::Lockfile(args::LockfileCommand::Keygen { output })) => {
+                lockfile::keygen(&output)
+            }
+            Some(args::Command::Lockfile(args::LockfileCommand::Sign { lockfile, key })) => {
+                lockfile::sign(&lockfile, &key)
+            }
+            Some(args::Command::Cache(args::CacheCommand::Clean)) => cache::clean(args),
+            Some(args::Command::ExportNix {
+                crate_names,
+                output,
+            }) => run_tokio_main(|| {
+                export_nix::export_nix(args, crate_names, output, jobserver_client)
+            }),
+            None => {
+                let should_notify = args.notify;
+
+                let result = if args.remote.is_empty() {
+                    run_tokio_main(|| entry::install_crates(args, jobserver_client))
+                } else {
+                    run_tokio_main(|| remote::install_remote(args, jobserver_client))
+                };
+
+                if should_notify {
+                    let done = start.elapsed();
+                    match &result {
+                        Ok(()) => notify("cargo-binstall", &format!("Done in {done:?}")),
+                        Err(err) => notify(
+                            "cargo-binstall failed",
+                            &format!("Failed after {done:?}: {err}"),
+                        ),
+                    }
+                }
 
-        let result = run_tokio_main(|| entry::install_crates(args, jobserver_client));
+                result
+            }
+        };
 
         let done = start.elapsed();
         debug!("run time: {done:?}");
 
-        MainExit::new(result, done)
+        MainExit::new(result, (!deterministic).then_some(done))
     }
 }
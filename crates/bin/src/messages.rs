@@ -0,0 +1,61 @@
+//! Translations of the messages printed, see
+//! [`binstalk::helpers::message`].
+//!
+//! The translations for a language, e.g. `fr`, are loaded from
+//! `$CARGO_HOME/binstall/messages/fr.toml`, which maps the identifiers of
+//! the messages to templates of their text:
+//!
+//! ```toml
+//! "binstall.already-installed" = "{ name } v{ version } est déjà installé"
+//! ```
+
+use std::{collections::HashMap, env, fs, path::Path};
+
+use binstalk::helpers::message::set_catalog;
+use tracing::warn;
+
+/// Languages of the locale, most specific first, e.g. `fr_FR` then `fr`
+/// for `fr_FR.UTF-8`.
+fn languages() -> Vec<String> {
+    let Some(locale) = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .find_map(|key| env::var(key).ok().filter(|locale| !locale.is_empty()))
+    else {
+        return Vec::new();
+    };
+
+    let locale = locale
+        .split(['.', '@'])
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    if locale == "C" || locale == "POSIX" || locale.is_empty() {
+        return Vec::new();
+    }
+
+    let mut languages = vec![locale.clone()];
+    if let Some((language, _territory)) = locale.split_once('_') {
+        languages.push(language.to_string());
+    }
+    languages
+}
+
+/// Load the translations for the language of the locale, if any.
+pub(crate) fn load_catalog(cargo_home: &Path) {
+    let dir = cargo_home.join("binstall/messages");
+
+    for language in languages() {
+        let path = dir.join(format!("{language}.toml"));
+        let Ok(content) = fs::read(&path) else {
+            continue;
+        };
+
+        match toml_edit::de::from_slice::<HashMap<String, String>>(&content) {
+            Ok(catalog) => {
+                set_catalog(catalog);
+            }
+            Err(err) => warn!("Failed to parse {}: {err}", path.display()),
+        }
+        return;
+    }
+}
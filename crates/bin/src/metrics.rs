@@ -0,0 +1,190 @@
+//! Opt-in usage metrics, aggregated locally in
+//! `$CARGO_ROOT/binstall/metrics.json` and never sent anywhere.
+//!
+//! Crate names are only recorded if explicitly allowed, see [`Metrics`].
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use binstalk::{errors::BinstallError, helpers::remote::ProbeCacheStats};
+use binstalk_manifests::cargo_config::Config;
+use compact_str::CompactString;
+use home::cargo_home;
+use miette::{miette, Diagnostic, Result};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{args::Args, install_path};
+
+#[derive(Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct Metrics {
+    /// Number of runs recorded.
+    runs: u64,
+    /// Number of crates resolved by each strategy.
+    strategies: BTreeMap<CompactString, u64>,
+    /// Number of urls looked up in the probe cache.
+    probe_lookups: u64,
+    /// Number of lookups answered from the probe cache.
+    probe_cache_hits: u64,
+    /// Number of crates failed, by error code.
+    errors: BTreeMap<CompactString, u64>,
+    /// Number of times each crate is resolved, only recorded with
+    /// `--metrics-crate-names`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    crates: BTreeMap<CompactString, u64>,
+}
+
+/// Metrics of one run.
+#[derive(Debug, Default)]
+pub(crate) struct Run {
+    pub(crate) strategies: Vec<&'static str>,
+    pub(crate) probe_cache: ProbeCacheStats,
+    pub(crate) errors: Vec<CompactString>,
+    pub(crate) crates: Vec<CompactString>,
+}
+
+impl Run {
+    pub(crate) fn record_error(&mut self, err: &BinstallError) {
+        // Some errors, e.g. the registry ones, forward to the diagnostic of
+        // their source which has no code.
+        let class = err
+            .code()
+            .map(|code| code.to_string().into())
+            .unwrap_or_else(|| format!("exit-{}", err.exit_number()).into());
+        self.errors.push(class);
+    }
+}
+
+impl Metrics {
+    fn record(&mut self, run: Run) {
+        self.runs += 1;
+
+        for strategy in run.strategies {
+            *self.strategies.entry(strategy.into()).or_default() += 1;
+        }
+
+        self.probe_lookups += run.probe_cache.lookups;
+        self.probe_cache_hits += run
+            .probe_cache
+            .lookups
+            .saturating_sub(run.probe_cache.probes);
+
+        for class in run.errors {
+            *self.errors.entry(class).or_default() += 1;
+        }
+        for name in run.crates {
+            *self.crates.entry(name).or_default() += 1;
+        }
+    }
+}
+
+fn metrics_path(cargo_roots: &Path) -> PathBuf {
+    cargo_roots.join("binstall/metrics.json")
+}
+
+fn load(path: &Path) -> Metrics {
+    // Start over if the file is missing or corrupted.
+    fs::read(path)
+        .ok()
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save(path: &Path, metrics: &Metrics) -> io::Result<()> {
+    fs::create_dir_all(path.parent().unwrap())?;
+
+    // Write to a temporary file then rename it, so that readers never
+    // see a partially written file.
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, serde_json::to_vec_pretty(metrics)?)?;
+    fs::rename(tmp_path, path)
+}
+
+/// Add `run` to the metrics, failures are logged and ignored.
+pub(crate) fn record_run(cargo_roots: &Path, run: Run) {
+    let path = metrics_path(cargo_roots);
+
+    let mut metrics = load(&path);
+    metrics.record(run);
+
+    if let Err(err) = save(&path, &metrics) {
+        warn!("Failed to update the metrics: {err}");
+    }
+}
+
+pub fn export(args: Args, reset: bool) -> Result<()> {
+    let cargo_home = cargo_home().map_err(BinstallError::from)?;
+    let mut config = Config::load_from_path(cargo_home.join("config.toml"))?;
+
+    let cargo_roots = install_path::get_cargo_roots_path(args.root, cargo_home, &mut config)
+        .ok_or_else(|| miette!("No cargo roots path found or specified"))?;
+
+    let path = metrics_path(&cargo_roots);
+
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&load(&path)).map_err(|err| miette!("{err}"))?
+    );
+
+    if reset {
+        save(&path, &Metrics::default())
+            .map_err(|err| miette!("Failed to reset the metrics: {err}"))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_metrics_record() {
+        let mut metrics = Metrics::default();
+
+        metrics.record(Run {
+            strategies: vec!["GhCrateMeta", "QuickInstall", "GhCrateMeta"],
+            probe_cache: ProbeCacheStats {
+                lookups: 10,
+                probes: 7,
+            },
+            errors: vec!["binstall::timeout".into()],
+            crates: Vec::new(),
+        });
+        metrics.record(Run {
+            strategies: vec!["Compile"],
+            crates: vec!["cargo-binstall".into()],
+            ..Default::default()
+        });
+
+        assert_eq!(
+            metrics,
+            Metrics {
+                runs: 2,
+                strategies: BTreeMap::from([
+                    ("Compile".into(), 1),
+                    ("GhCrateMeta".into(), 2),
+                    ("QuickInstall".into(), 1),
+                ]),
+                probe_lookups: 10,
+                probe_cache_hits: 3,
+                errors: BTreeMap::from([("binstall::timeout".into(), 1)]),
+                crates: BTreeMap::from([("cargo-binstall".into(), 1)]),
+            }
+        );
+    }
+
+    #[test]
+    fn test_record_run() {
+        let cargo_roots = tempfile::tempdir().unwrap();
+
+        record_run(cargo_roots.path(), Run::default());
+        record_run(cargo_roots.path(), Run::default());
+
+        assert_eq!(load(&metrics_path(cargo_roots.path())).runs, 2);
+    }
+}
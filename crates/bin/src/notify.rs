@@ -0,0 +1,91 @@
+use std::process::Command;
+
+use tracing::{debug, warn};
+
+/// Show a desktop notification using the notifier of the platform:
+///
+///  - `osascript` on macOS
+///  - PowerShell toast notification on Windows
+///  - `notify-send` on other platforms
+///
+/// Failures are logged and ignored.
+pub(crate) fn notify(title: &str, body: &str) {
+    let mut cmd = notifier_command(title, body);
+
+    debug!("Running {cmd:?}");
+
+    match cmd.output() {
+        Ok(output) if output.status.success() => (),
+        Ok(output) => warn!(
+            "Failed to show notification, {:?} exited with {}: {}",
+            cmd.get_program(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(err) => warn!(
+            "Failed to show notification, unable to run {:?}: {err}",
+            cmd.get_program()
+        ),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn notifier_command(title: &str, body: &str) -> Command {
+    let mut cmd = Command::new("osascript");
+    // Pass title and body as arguments to avoid quoting them in the script.
+    cmd.args([
+        "-e",
+        "on run argv",
+        "-e",
+        "display notification (item 2 of argv) with title (item 1 of argv)",
+        "-e",
+        "end run",
+        title,
+        body,
+    ]);
+    cmd
+}
+
+#[cfg(windows)]
+fn notifier_command(title: &str, body: &str) -> Command {
+    const SCRIPT: &str = r#"
+[Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime] > $null
+$template = [Windows.UI.Notifications.ToastNotificationManager]::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02)
+$texts = $template.GetElementsByTagName('text')
+$texts.Item(0).AppendChild($template.CreateTextNode($env:BINSTALL_NOTIFY_TITLE)) > $null
+$texts.Item(1).AppendChild($template.CreateTextNode($env:BINSTALL_NOTIFY_BODY)) > $null
+$toast = [Windows.UI.Notifications.ToastNotification]::new($template)
+[Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier('cargo-binstall').Show($toast)
+"#;
+
+    let mut cmd = Command::new("powershell");
+    // Pass title and body as env to avoid quoting them in the script.
+    cmd.args(["-NoProfile", "-NonInteractive", "-Command", SCRIPT])
+        .env("BINSTALL_NOTIFY_TITLE", title)
+        .env("BINSTALL_NOTIFY_BODY", body);
+    cmd
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn notifier_command(title: &str, body: &str) -> Command {
+    let mut cmd = Command::new("notify-send");
+    cmd.args(["--app-name=cargo-binstall", "--", title, body]);
+    cmd
+}
+
+#[cfg(test)]
+mod test {
+    use std::ffi::OsStr;
+
+    use super::*;
+
+    #[test]
+    fn test_notifier_command() {
+        let cmd = notifier_command("title", "body; $(rm -rf /)");
+
+        assert!(cmd
+            .get_args()
+            .chain(cmd.get_envs().filter_map(|(_, v)| v))
+            .any(|arg| arg == OsStr::new("body; $(rm -rf /)")));
+    }
+}
@@ -0,0 +1,304 @@
+//! Organization-wide defaults of the `[binstall]` table of cargo config,
+//! fetched from `--policy-url` and signed like lockfiles (see `lockfile`),
+//! so that platform teams can roll out mirrors and headers to every
+//! machine.
+//!
+//! The signature of the document is fetched from the same url with `.sig`
+//! appended to its path. The last document verified is kept in the cache
+//! directory and used if the url cannot be fetched.
+//!
+//! Signature requirements cannot be rolled out this way: tools files and
+//! install plans are verified against `--verify-lockfile` before the policy
+//! is loaded, so it has no key for them.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use binstalk::helpers::remote::{Client, Url};
+use binstalk_manifests::cargo_config::Binstall;
+use compact_str::CompactString;
+use miette::{miette, Result};
+use serde::Deserialize;
+use tokio::{runtime::Handle, task::block_in_place};
+use tracing::{debug, warn};
+
+use crate::lockfile::{verify_signature, PublicKey};
+
+/// The document, in the format of cargo config, of which only `[binstall]`
+/// is used.
+#[derive(Debug, Deserialize)]
+struct Policy {
+    #[serde(default)]
+    binstall: Binstall,
+}
+
+fn signature_url(url: &Url) -> Url {
+    let mut signature_url = url.clone();
+    signature_url.set_path(&format!("{}.sig", url.path()));
+    signature_url
+}
+
+async fn fetch(client: &Client, url: &Url) -> Result<(Vec<u8>, Vec<u8>)> {
+    let get = |url: Url| async {
+        client
+            .get(url)
+            .send_cached(true)
+            .await?
+            .bytes()
+            .await
+            .map(Vec::from)
+    };
+
+    let (document, signature) = tokio::try_join!(get(url.clone()), get(signature_url(url)))
+        .map_err(|err| miette!("Failed to fetch the policy {url}: {err}"))?;
+    Ok((document, signature))
+}
+
+fn parse(url: &Url, document: &[u8], signature: &[u8], key: &PublicKey) -> Result<Binstall> {
+    if !verify_signature(document, signature, key) {
+        return Err(miette!(
+            "The policy {url} is not signed by the policy key, refusing to use it"
+        ));
+    }
+
+    toml_edit::de::from_slice::<Policy>(document)
+        .map(|policy| policy.binstall)
+        .map_err(|err| miette!("Failed to parse the policy {url}: {err}"))
+}
+
+fn cached_paths(cache_dir: &Path) -> (PathBuf, PathBuf) {
+    (
+        cache_dir.join("policy.toml"),
+        cache_dir.join("policy.toml.sig"),
+    )
+}
+
+/// Fetch the policy of `url` and check that it is signed by `key`, falling
+/// back to the last one cached in `cache_dir` if it cannot be fetched.
+pub(crate) fn load(
+    client: &Client,
+    url: &Url,
+    key: &PublicKey,
+    cache_dir: Option<&Path>,
+) -> Result<Binstall> {
+    let fetched = block_in_place(|| Handle::current().block_on(fetch(client, url)));
+
+    match fetched {
+        Ok((document, signature)) => {
+            let policy = parse(url, &document, &signature, key)?;
+
+            if let Some(cache_dir) = cache_dir {
+                let (document_path, signature_path) = cached_paths(cache_dir);
+                if let Err(err) = fs::write(document_path, &document)
+                    .and_then(|()| fs::write(signature_path, &signature))
+                {
+                    debug!("Failed to cache the policy {url}: {err}");
+                }
+            }
+
+            Ok(policy)
+        }
+        Err(err) => {
+            let cached = cache_dir.and_then(|cache_dir| {
+                let (document_path, signature_path) = cached_paths(cache_dir);
+                Some((
+                    fs::read(document_path).ok()?,
+                    fs::read(signature_path).ok()?,
+                ))
+            });
+            let Some((document, signature)) = cached else {
+                return Err(err);
+            };
+
+            warn!("{err}, using the policy cached");
+            parse(url, &document, &signature, key)
+        }
+    }
+}
+
+fn merge_maps<V>(
+    local: Option<BTreeMap<CompactString, V>>,
+    policy: Option<BTreeMap<CompactString, V>>,
+    merge_values: impl Fn(V, V) -> V,
+) -> Option<BTreeMap<CompactString, V>> {
+    match (local, policy) {
+        (Some(local), Some(mut merged)) => {
+            for (key, local_value) in local {
+                let value = match merged.remove(&key) {
+                    Some(policy_value) => merge_values(local_value, policy_value),
+                    None => local_value,
+                };
+                merged.insert(key, value);
+            }
+            Some(merged)
+        }
+        (local, policy) => local.or(policy),
+    }
+}
+
+/// Return the patterns of the hosts matching both `a` and `b`, which are a
+/// host or `*.` followed by a domain to match its subdomains.
+fn intersect_host_patterns(a: &str, b: &str) -> Option<CompactString> {
+    let (a, b) = (a.to_ascii_lowercase(), b.to_ascii_lowercase());
+    let is_subdomain = |host: &str, domain: &str| host.ends_with(&format!(".{domain}"));
+
+    match (a.strip_prefix("*."), b.strip_prefix("*.")) {
+        (None, None) => (a == b).then_some(a),
+        (None, Some(domain)) => is_subdomain(&a, domain).then_some(a),
+        (Some(domain), None) => is_subdomain(&b, domain).then_some(b),
+        (Some(a_domain), Some(b_domain)) => {
+            if a_domain == b_domain || is_subdomain(a_domain, b_domain) {
+                Some(a)
+            } else {
+                is_subdomain(b_domain, a_domain).then_some(b)
+            }
+        }
+    }
+    .map(CompactString::from)
+}
+
+/// Combine the allowed hosts of two sources, e.g. of the policy and of
+/// `--allow-hosts`, so that only the hosts allowed by both are, since
+/// neither may allow more hosts than the other.
+pub(crate) fn combine_allowed_hosts(
+    a: Option<Vec<CompactString>>,
+    b: Option<Vec<CompactString>>,
+) -> Option<Vec<CompactString>> {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            let mut combined = Vec::new();
+            for pattern in a
+                .iter()
+                .flat_map(|a| b.iter().filter_map(move |b| intersect_host_patterns(a, b)))
+            {
+                if !combined.contains(&pattern) {
+                    combined.push(pattern);
+                }
+            }
+            Some(combined)
+        }
+        (a, b) => a.or(b),
+    }
+}
+
+/// Use the settings of `policy` missing from `local`, the `[binstall]`
+/// table of the local cargo config, entry by entry for the tables.
+///
+/// The allowed hosts of the policy are a restriction, which `local` can
+/// only narrow.
+pub(crate) fn merge(local: Binstall, policy: Binstall) -> Binstall {
+    Binstall {
+        user_agent_suffix: local.user_agent_suffix.or(policy.user_agent_suffix),
+        headers: merge_maps(local.headers, policy.headers, |local, _| local),
+        host_headers: merge_maps(local.host_headers, policy.host_headers, |local, policy| {
+            merge_maps(Some(local), Some(policy), |local, _| local).unwrap_or_default()
+        }),
+        mirrors: merge_maps(local.mirrors, policy.mirrors, |local, _| local),
+        patch_interpreter: local.patch_interpreter.or(policy.patch_interpreter),
+        patch_rpath: local.patch_rpath.or(policy.patch_rpath),
+        build_env: merge_maps(local.build_env, policy.build_env, |local, policy| {
+            merge_maps(Some(local), Some(policy), |local, _| local).unwrap_or_default()
+        }),
+        allow_hosts: combine_allowed_hosts(local.allow_hosts, policy.allow_hosts),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_signature_url() {
+        let url = Url::parse("https://corp/binstall/policy.toml?ref=main").unwrap();
+        assert_eq!(
+            signature_url(&url).as_str(),
+            "https://corp/binstall/policy.toml.sig?ref=main"
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let policy: Policy = toml_edit::de::from_str(
+            r#"
+[binstall]
+user-agent-suffix = "corp"
+headers = { X-Team = "platform", X-Env = "prod" }
+host-headers = { "artifacts.corp" = { Authorization = "Bearer corp", X-Env = "prod" } }
+mirrors = { "https://github.com/" = "https://gh-mirror.corp/" }
+build-env = { cargo-update = { OPENSSL_DIR = "/opt/openssl" } }
+allow-hosts = ["github.com", "*.githubusercontent.com", "*.corp"]
+"#,
+        )
+        .unwrap();
+        let local: Policy = toml_edit::de::from_str(
+            r#"
+[binstall]
+headers = { X-Team = "tools" }
+host-headers = { "artifacts.corp" = { Authorization = "Bearer me" } }
+allow-hosts = ["github.com", "artifacts.corp", "example.com"]
+"#,
+        )
+        .unwrap();
+
+        let merged = merge(local.binstall, policy.binstall);
+
+        assert_eq!(merged.user_agent_suffix.unwrap(), "corp");
+        let headers = merged.headers.unwrap();
+        assert_eq!(headers["X-Team"], "tools");
+        assert_eq!(headers["X-Env"], "prod");
+        let host_headers = &merged.host_headers.unwrap()["artifacts.corp"];
+        assert_eq!(host_headers["Authorization"], "Bearer me");
+        assert_eq!(host_headers["X-Env"], "prod");
+        assert_eq!(
+            merged.mirrors.unwrap()["https://github.com/"],
+            "https://gh-mirror.corp/"
+        );
+        assert_eq!(
+            merged.build_env.unwrap()["cargo-update"]["OPENSSL_DIR"],
+            "/opt/openssl"
+        );
+        assert_eq!(
+            merged.allow_hosts.unwrap(),
+            ["github.com", "artifacts.corp"]
+        );
+    }
+
+    #[test]
+    fn test_combine_allowed_hosts() {
+        let hosts = |hosts: &[&str]| Some(hosts.iter().copied().map(CompactString::from).collect());
+
+        assert_eq!(
+            combine_allowed_hosts(hosts(&["github.com"]), None),
+            hosts(&["github.com"])
+        );
+        assert_eq!(
+            combine_allowed_hosts(None, hosts(&["github.com"])),
+            hosts(&["github.com"])
+        );
+        assert_eq!(
+            combine_allowed_hosts(
+                hosts(&["GitHub.com", "*.githubusercontent.com", "*.corp"]),
+                hosts(&[
+                    "github.com",
+                    "objects.githubusercontent.com",
+                    "*.artifacts.corp",
+                    "*.com"
+                ]),
+            ),
+            hosts(&[
+                "github.com",
+                "objects.githubusercontent.com",
+                "*.githubusercontent.com",
+                "*.artifacts.corp"
+            ])
+        );
+        // `*.corp` does not match `corp`.
+        assert_eq!(
+            combine_allowed_hosts(hosts(&["*.corp"]), hosts(&["corp", "evilcorp"])),
+            hosts(&[])
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! `cargo binstall prefetch`: populate the artifact cache with the prebuilt
+//! artifacts of a set of tools, e.g. when building CI images.
+
+use std::{collections::BTreeMap, fs, future::Future, path::Path};
+
+use binstalk::{
+    helpers::{jobserver_client::LazyJobserverClient, tasks::AutoAbortJoinHandle},
+    ops::resolve::{self, CrateName, Probe},
+};
+use compact_str::CompactString;
+use miette::{miette, Result, WrapErr};
+use semver::VersionReq;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::{
+    args::Args,
+    cache,
+    lockfile::{self, PublicKey},
+    probe::{compute_probe_options, format_probe},
+};
+
+/// Tools to prefetch, e.g.
+///
+/// ```toml
+/// [tools]
+/// ripgrep = "13"
+/// cargo-nextest = "*"
+/// ```
+#[derive(Debug, Deserialize)]
+struct ToolsFile {
+    tools: BTreeMap<CompactString, VersionReq>,
+}
+
+/// Load the tools of `path`, checking that it is signed by `verify_key`
+/// if any.
+pub(crate) fn load_tools(path: &Path, verify_key: Option<&PublicKey>) -> Result<Vec<CrateName>> {
+    let content =
+        fs::read(path).map_err(|err| miette!("Failed to read {}: {err}", path.display()))?;
+    if let Some(verify_key) = verify_key {
+        lockfile::verify(path, &content, verify_key)?;
+    }
+    let tools_file: ToolsFile = toml_edit::de::from_slice(&content)
+        .map_err(|err| miette!("Failed to parse {}: {err}", path.display()))?;
+
+    Ok(tools_file
+        .tools
+        .into_iter()
+        .map(|(name, version_req)| CrateName {
+            name,
+            version_req: Some(version_req),
+        })
+        .collect())
+}
+
+pub fn prefetch(
+    args: Args,
+    from_file: &Path,
+    targets: Vec<String>,
+    jobserver_client: LazyJobserverClient,
+) -> Result<Option<impl Future<Output = Result<()>>>> {
+    if cache::enabled_cache_dir(&args).is_none() {
+        return Err(miette!(
+            "prefetch requires the artifact cache, use --cache-dir or BINSTALL_CACHE_DIR \
+             without --no-cache"
+        ));
+    }
+
+    let tools = load_tools(from_file, args.verify_lockfile.as_ref())
+        .wrap_err("Failed to load the tools to prefetch")?;
+    let (opts, temp_dir) = compute_probe_options(args, targets, jobserver_client)?;
+
+    Ok(Some(async move {
+        let tasks: Vec<_> = tools
+            .into_iter()
+            .map(|crate_name| {
+                AutoAbortJoinHandle::spawn(resolve::check_release(opts.clone(), crate_name))
+            })
+            .collect();
+
+        let total = tasks.len();
+        let mut failed = 0;
+
+        for task in tasks {
+            match task.flattened_join().await {
+                Ok(probe) => {
+                    print!("{}", format_probe(&probe));
+                    if !is_prefetched(&probe) {
+                        failed += 1;
+                    }
+                }
+                Err(err) => {
+                    warn!("{err}");
+                    failed += 1;
+                }
+            }
+        }
+
+        drop(temp_dir);
+
+        if failed == 0 {
+            Ok(())
+        } else {
+            Err(miette!("{failed} of {total} tools failed to prefetch"))
+        }
+    }))
+}
+
+/// Return true if the artifacts of all targets are downloaded and verified.
+fn is_prefetched(probe: &Probe) -> bool {
+    probe
+        .targets
+        .iter()
+        .all(|target_probe| matches!(target_probe.verified, Some(Ok(n)) if n > 0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_load_tools() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tools.toml");
+        fs::write(&path, "[tools]\nripgrep = \"13\"\ncargo-nextest = \"*\"\n").unwrap();
+
+        let tools = load_tools(&path, None).unwrap();
+        assert_eq!(
+            tools.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["cargo-nextest@*", "ripgrep@^13"]
+        );
+
+        fs::write(&path, "[tools]\nripgrep = \"not a version\"\n").unwrap();
+        assert!(load_tools(&path, None).is_err());
+    }
+}
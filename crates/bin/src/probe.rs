@@ -0,0 +1,263 @@
+//! `cargo binstall probe` and `cargo binstall check-release`: check which
+//! targets have prebuilt artifacts.
+
+use std::{future::Future, sync::Arc};
+
+use binstalk::{
+    errors::BinstallError,
+    helpers::jobserver_client::LazyJobserverClient,
+    ops::{
+        resolve::{self, CrateName, Probe, STANDARD_TARGETS},
+        Options,
+    },
+};
+use binstalk_manifests::cargo_config::Config;
+use home::cargo_home;
+use miette::{miette, Result};
+use tempfile::TempDir;
+
+use crate::{
+    args::Args,
+    entry::{compute_options, compute_paths},
+};
+
+pub(crate) fn compute_probe_options(
+    mut args: Args,
+    targets: Vec<String>,
+    jobserver_client: LazyJobserverClient,
+) -> Result<(Arc<Options>, TempDir)> {
+    if !targets.is_empty() {
+        args.targets = Some(targets);
+    }
+
+    // Load .cargo/config.toml
+    let cargo_home = cargo_home().map_err(BinstallError::from)?;
+    let mut config = Config::load_from_path(cargo_home.join("config.toml"))?;
+
+    let (install_path, _cargo_roots, temp_dir) = compute_paths(
+        args.root.clone(),
+        args.install_path.take(),
+        args.no_track,
+        cargo_home,
+        &mut config,
+    )?;
+
+    let opts = compute_options(
+        args,
+        config,
+        install_path,
+        temp_dir.path().to_owned(),
+        jobserver_client,
+        None,
+    )?;
+
+    Ok((Arc::new(opts), temp_dir))
+}
+
+pub fn probe(
+    args: Args,
+    crate_name: CrateName,
+    targets: Vec<String>,
+    jobserver_client: LazyJobserverClient,
+) -> Result<Option<impl Future<Output = Result<()>>>> {
+    let (opts, temp_dir) = compute_probe_options(args, targets, jobserver_client)?;
+
+    Ok(Some(async move {
+        let probe = resolve::probe(opts, crate_name).await?;
+
+        print!("{}", format_probe(&probe));
+
+        drop(temp_dir);
+        Ok(())
+    }))
+}
+
+pub fn check_release(
+    mut args: Args,
+    crate_name: CrateName,
+    mut targets: Vec<String>,
+    jobserver_client: LazyJobserverClient,
+) -> Result<Option<impl Future<Output = Result<()>>>> {
+    if targets.is_empty() {
+        targets = args
+            .targets
+            .take()
+            .unwrap_or_else(|| STANDARD_TARGETS.iter().map(ToString::to_string).collect());
+    }
+
+    let (opts, temp_dir) = compute_probe_options(args, targets, jobserver_client)?;
+
+    Ok(Some(async move {
+        let probe = resolve::check_release(opts, crate_name).await?;
+
+        print!("{}", format_probe(&probe));
+
+        drop(temp_dir);
+
+        let failed = probe
+            .targets
+            .iter()
+            .filter(|target_probe| {
+                !target_probe.failed.is_empty()
+                    || matches!(target_probe.verified, Some(Err(_)))
+                    || matches!(target_probe.checksum, Some(Err(_)))
+            })
+            .count();
+
+        if failed == 0 {
+            Ok(())
+        } else {
+            Err(miette!(
+                "{failed} of {} targets failed the release check",
+                probe.targets.len()
+            ))
+        }
+    }))
+}
+
+pub(crate) fn format_probe(probe: &Probe) -> String {
+    let mut output = format!("{} v{}\n", probe.name, probe.version);
+
+    for target_probe in &probe.targets {
+        let found = if target_probe.is_available() {
+            target_probe
+                .found
+                .iter()
+                .map(|fetcher| match fetcher.download_url() {
+                    Some(url) => format!("{} ({url})", fetcher.source_name()),
+                    None => fetcher.source_name().to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        } else {
+            "not available".to_string()
+        };
+
+        output.push_str(&format!("{}: {found}\n", target_probe.target));
+
+        for (fetcher, err) in &target_probe.failed {
+            output.push_str(&format!("  {} failed: {err}\n", fetcher.fetcher_name()));
+        }
+
+        match &target_probe.verified {
+            Some(Ok(0)) => output.push_str("  verified, but no binary is found\n"),
+            Some(Ok(n)) => output.push_str(&format!("  verified {n} binaries\n")),
+            Some(Err(err)) => output.push_str(&format!("  verification failed: {err}\n")),
+            None => (),
+        }
+
+        match &target_probe.checksum {
+            Some(Ok(algorithm)) => output.push_str(&format!("  {algorithm} checksum matches\n")),
+            Some(Err(err)) => output.push_str(&format!("  checksum verification failed: {err}\n")),
+            None => (),
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeMap, io, num::NonZeroU16};
+
+    use binstalk::{
+        fetchers::{Data, Fetcher, GhCrateMeta, TargetData, TargetDataErased},
+        helpers::{gh_api_client::GhApiClient, remote::Client},
+        manifests::cargo_toml_binstall::PkgMeta,
+        ops::resolve::TargetProbe,
+    };
+
+    use super::*;
+
+    fn fetcher(target: &str) -> Arc<dyn Fetcher> {
+        let client = Client::new(
+            "cargo-binstall",
+            None,
+            NonZeroU16::new(10).unwrap(),
+            1.try_into().unwrap(),
+            [],
+        )
+        .unwrap();
+        let gh_api_client = GhApiClient::new(client.clone(), None);
+        let data = Arc::new(Data::new("cargo-binstall".into(), "1.0.0".into(), None));
+        let target_data: Arc<TargetDataErased> = Arc::new(TargetData {
+            target: target.to_string(),
+            meta: PkgMeta::default(),
+            target_related_info: BTreeMap::<String, String>::new(),
+        });
+        GhCrateMeta::new(client, gh_api_client, data, target_data)
+    }
+
+    #[test]
+    fn test_format_probe() {
+        let probe = Probe {
+            name: "cargo-binstall".into(),
+            version: "1.0.0".into(),
+            targets: vec![TargetProbe {
+                target: "x86_64-unknown-linux-gnu".to_string(),
+                found: Vec::new(),
+                failed: Vec::new(),
+                verified: None,
+                checksum: None,
+            }],
+        };
+
+        assert_eq!(
+            format_probe(&probe),
+            "cargo-binstall v1.0.0\nx86_64-unknown-linux-gnu: not available\n"
+        );
+    }
+
+    #[test]
+    fn test_format_check_release() {
+        let linux = fetcher("x86_64-unknown-linux-gnu");
+        let darwin = fetcher("x86_64-apple-darwin");
+        let mismatch = BinstallError::from(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "sha512 checksum mismatch",
+        ));
+        let expected = format!(
+            "cargo-binstall v1.0.0
+x86_64-unknown-linux-gnu: invalid url
+  verified 2 binaries
+  sha256 checksum matches
+x86_64-apple-darwin: not available
+  GhCrateMeta failed: {}
+x86_64-pc-windows-msvc: invalid url
+  verified 2 binaries
+  checksum verification failed: {mismatch}
+",
+            BinstallError::NoViableTargets
+        );
+
+        let probe = Probe {
+            name: "cargo-binstall".into(),
+            version: "1.0.0".into(),
+            targets: vec![
+                TargetProbe {
+                    target: "x86_64-unknown-linux-gnu".to_string(),
+                    found: vec![linux],
+                    failed: Vec::new(),
+                    verified: Some(Ok(2)),
+                    checksum: Some(Ok("sha256")),
+                },
+                TargetProbe {
+                    target: "x86_64-apple-darwin".to_string(),
+                    found: Vec::new(),
+                    failed: vec![(darwin, BinstallError::NoViableTargets)],
+                    verified: None,
+                    checksum: None,
+                },
+                TargetProbe {
+                    target: "x86_64-pc-windows-msvc".to_string(),
+                    found: vec![fetcher("x86_64-pc-windows-msvc")],
+                    failed: Vec::new(),
+                    verified: Some(Ok(2)),
+                    checksum: Some(Err(mismatch)),
+                },
+            ],
+        };
+
+        assert_eq!(format_probe(&probe), expected);
+    }
+}
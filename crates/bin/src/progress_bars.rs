@@ -0,0 +1,157 @@
+//! Progress bars of the artifacts being downloaded, one per crate, drawn on
+//! stderr.
+
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+    sync::Mutex,
+};
+
+use binstalk::ops::event::{InstallEvent, ProgressSink};
+use compact_str::CompactString;
+
+const BAR_WIDTH: usize = 30;
+
+struct Bar {
+    crate_name: CompactString,
+    received: u64,
+    total: Option<u64>,
+}
+
+#[derive(Default)]
+struct State {
+    bars: Vec<Bar>,
+    /// Number of lines drawn last time, to be redrawn.
+    drawn: usize,
+}
+
+#[derive(Default)]
+pub(crate) struct ProgressBars(Mutex<State>);
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+fn render_bar(bar: &Bar) -> String {
+    let received = format_bytes(bar.received);
+
+    match bar.total {
+        Some(total) if total > 0 => {
+            let filled =
+                ((bar.received.min(total) as f64 / total as f64) * BAR_WIDTH as f64) as usize;
+            format!(
+                "{:<24} [{}{}] {received}/{}",
+                bar.crate_name,
+                "#".repeat(filled),
+                "-".repeat(BAR_WIDTH - filled),
+                format_bytes(total),
+            )
+        }
+        _ => format!("{:<24} {received}", bar.crate_name),
+    }
+}
+
+impl State {
+    fn draw(&mut self) {
+        let mut out = String::new();
+
+        // Move back to the first line drawn last time and clear the lines
+        // drawn since then.
+        if self.drawn > 0 {
+            write!(out, "\x1b[{}A", self.drawn).unwrap();
+        }
+        out.push_str("\r\x1b[J");
+
+        for bar in &self.bars {
+            out.push_str(&render_bar(bar));
+            out.push('\n');
+        }
+        self.drawn = self.bars.len();
+
+        let mut stderr = io::stderr().lock();
+        stderr.write_all(out.as_bytes()).ok();
+        stderr.flush().ok();
+    }
+
+    fn remove(&mut self, crate_name: &str) {
+        let len = self.bars.len();
+        self.bars.retain(|bar| bar.crate_name != crate_name);
+        if self.bars.len() != len {
+            self.draw();
+        }
+    }
+}
+
+impl ProgressSink for ProgressBars {
+    fn on_event(&self, event: InstallEvent<'_>) {
+        let mut state = self.0.lock().unwrap();
+
+        match event {
+            InstallEvent::Downloading {
+                crate_name,
+                received,
+                total,
+            } => {
+                match state
+                    .bars
+                    .iter_mut()
+                    .find(|bar| bar.crate_name == crate_name)
+                {
+                    Some(bar) => {
+                        bar.received = received;
+                        bar.total = total;
+                    }
+                    None => state.bars.push(Bar {
+                        crate_name: crate_name.into(),
+                        received,
+                        total,
+                    }),
+                }
+                state.draw();
+            }
+            InstallEvent::Downloaded { crate_name, .. }
+            | InstallEvent::FetchFailed { crate_name, .. } => state.remove(crate_name),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render_bar() {
+        let mut bar = Bar {
+            crate_name: "ripgrep".into(),
+            received: 512,
+            total: None,
+        };
+        assert_eq!(render_bar(&bar), format!("{:<24} 512 B", "ripgrep"));
+
+        bar.received = 3 * 1024 * 1024 / 2;
+        bar.total = Some(3 * 1024 * 1024);
+        assert_eq!(
+            render_bar(&bar),
+            format!(
+                "{:<24} [{}{}] 1.5 MiB/3.0 MiB",
+                "ripgrep",
+                "#".repeat(15),
+                "-".repeat(15)
+            )
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! Publish metadata of the crates to install from crates.io, shown with
+//! `--show-publishers` and `--confirm on-new-publisher`.
+
+use binstalk::{
+    helpers::{remote::Client, tasks::AutoAbortJoinHandle},
+    registry::{fetch_publish_info, fetch_published_by, PublishInfo, RegistryError},
+};
+use compact_str::CompactString;
+use semver::Version;
+use tracing::{info, warn};
+
+pub(crate) struct Publisher {
+    name: CompactString,
+    version: CompactString,
+    info: PublishInfo,
+    /// Installed version and its publisher, if the crate is installed.
+    previous: Option<(Version, Option<CompactString>)>,
+}
+
+impl Publisher {
+    /// Return the publisher of the installed version if it is different
+    /// from the publisher of the version to install.
+    ///
+    /// Versions published before crates.io records the publishers are
+    /// not considered.
+    pub(crate) fn previous_publisher(&self) -> Option<&str> {
+        match (&self.previous, &self.info.published_by) {
+            (Some((_, Some(previous))), Some(published_by)) if previous != published_by => {
+                Some(previous)
+            }
+            _ => None,
+        }
+    }
+
+    fn describe(&self) -> String {
+        let Self {
+            name,
+            version,
+            info,
+            ..
+        } = self;
+
+        // Only keep the date of the RFC 3339 timestamp.
+        let published_at = info.published_at.get(..10).unwrap_or(&info.published_at);
+
+        format!(
+            "{name} v{version} is published by {} on {published_at}, downloaded {} times, \
+            owned by {}",
+            info.published_by.as_deref().unwrap_or("an unknown user"),
+            info.downloads,
+            info.owners.join(", "),
+        )
+    }
+}
+
+/// Fetch the publish metadata of `crates`, which are tuples of name,
+/// version to install and installed version.
+///
+/// Crates failing to fetch are logged and skipped.
+pub(crate) async fn fetch_publishers(
+    client: &Client,
+    crates: Vec<(CompactString, CompactString, Option<Version>)>,
+) -> Vec<Publisher> {
+    let tasks: Vec<_> = crates
+        .into_iter()
+        .map(|(name, version, current_version)| {
+            let client = client.clone();
+            AutoAbortJoinHandle::spawn(async move {
+                let info = fetch_publish_info(&client, &name, &version).await?;
+
+                let previous = match current_version {
+                    Some(current_version) => {
+                        let published_by =
+                            fetch_published_by(&client, &name, &current_version.to_string())
+                                .await?;
+                        Some((current_version, published_by))
+                    }
+                    None => None,
+                };
+
+                Ok::<_, RegistryError>(Publisher {
+                    name,
+                    version,
+                    info,
+                    previous,
+                })
+            })
+        })
+        .collect();
+
+    let mut publishers = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        match task.flattened_join().await {
+            Ok(publisher) => publishers.push(publisher),
+            Err(err) => warn!("Failed to fetch the publish metadata from crates.io: {err}"),
+        }
+    }
+    publishers
+}
+
+pub(crate) fn print_publishers(publishers: &[Publisher]) {
+    for publisher in publishers {
+        info!("{}", publisher.describe());
+
+        if let (Some(previous), Some((version, _))) =
+            (publisher.previous_publisher(), &publisher.previous)
+        {
+            warn!(
+                "The publisher of {} changed: the installed v{version} is published by {previous}",
+                publisher.name,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_publisher() {
+        let mut publisher = Publisher {
+            name: "foo".into(),
+            version: "1.1.0".into(),
+            info: PublishInfo {
+                owners: vec!["alice".into(), "github:org:team".into()],
+                published_by: Some("alice".into()),
+                published_at: "2023-06-01T12:00:00.000000+00:00".into(),
+                downloads: 42,
+            },
+            previous: None,
+        };
+
+        assert_eq!(
+            publisher.describe(),
+            "foo v1.1.0 is published by alice on 2023-06-01, downloaded 42 times, \
+            owned by alice, github:org:team"
+        );
+        assert_eq!(publisher.previous_publisher(), None);
+
+        publisher.previous = Some((Version::new(1, 0, 0), None));
+        assert_eq!(publisher.previous_publisher(), None);
+
+        publisher.previous = Some((Version::new(1, 0, 0), Some("alice".into())));
+        assert_eq!(publisher.previous_publisher(), None);
+
+        publisher.previous = Some((Version::new(1, 0, 0), Some("mallory".into())));
+        assert_eq!(publisher.previous_publisher(), Some("mallory"));
+    }
+}
@@ -0,0 +1,319 @@
+//! `cargo binstall --remote`: install crates on remote hosts over ssh.
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    path::{Component, Path},
+    process::Command,
+};
+
+use binstalk::{helpers::jobserver_client::LazyJobserverClient, manifests::crate_info::BinDigest};
+use binstalk_manifests::binstall_crates_v1::Records;
+use miette::{miette, Result};
+use tokio::task::block_in_place;
+use tracing::{info, warn};
+
+use crate::{
+    args::{Args, Strategy},
+    entry,
+};
+
+/// Install root of the remote hosts, relative to the home directory of the
+/// ssh user.
+///
+/// The binaries are uploaded to the same paths relative to it as they are
+/// installed to in the staging root, so `bin-dir` and bin-dest are honored.
+const REMOTE_ROOT: &str = ".cargo";
+
+/// Run `command` on `host` and return its stdout.
+fn ssh(host: &str, command: &str) -> Result<String> {
+    let output = Command::new("ssh")
+        .args(["-o", "BatchMode=yes", "--", host, command])
+        .output()
+        .map_err(|err| miette!("Failed to run ssh: {err}"))?;
+
+    if !output.status.success() {
+        return Err(miette!(
+            "`{command}` failed on {host}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    String::from_utf8(output.stdout).map_err(|_| miette!("Output of `{command}` is not utf-8"))
+}
+
+fn scp(host: &str, src: &Path, dst: &str) -> Result<()> {
+    let status = Command::new("scp")
+        .args(["-q", "-o", "BatchMode=yes", "--"])
+        .arg(src)
+        .arg(format!("{host}:{dst}"))
+        .status()
+        .map_err(|err| miette!("Failed to run scp: {err}"))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(miette!("Failed to upload {} to {host}", src.display()))
+    }
+}
+
+/// Return the targets to install for a host, in order of preference, from
+/// the output of `uname -sm` and the first line of `ldd --version`.
+fn targets_from_uname(uname: &str, ldd: &str) -> Option<Vec<String>> {
+    let mut uname = uname.split_whitespace();
+    let os = uname.next()?;
+    let machine = uname.next()?;
+
+    let targets: Vec<String> = match os {
+        "Linux" => {
+            let (arch, abi) = match machine {
+                "x86_64" => ("x86_64", ""),
+                "aarch64" | "arm64" => ("aarch64", ""),
+                "armv7l" => ("armv7", "eabihf"),
+                "armv6l" => ("arm", "eabihf"),
+                "i686" | "i586" => (machine, ""),
+                "ppc64le" => ("powerpc64le", ""),
+                "riscv64" => ("riscv64gc", ""),
+                "loongarch64" => ("loongarch64", ""),
+                "s390x" => ("s390x", ""),
+                _ => return None,
+            };
+
+            let musl = format!("{arch}-unknown-linux-musl{abi}");
+            // musl binaries run on glibc hosts too, but not the other way.
+            if ldd.to_ascii_lowercase().contains("musl") {
+                vec![musl]
+            } else {
+                vec![format!("{arch}-unknown-linux-gnu{abi}"), musl]
+            }
+        }
+        "Darwin" => match machine {
+            // x86_64 binaries run with Rosetta 2.
+            "arm64" => vec!["aarch64-apple-darwin".into(), "x86_64-apple-darwin".into()],
+            "x86_64" => vec!["x86_64-apple-darwin".into()],
+            _ => return None,
+        },
+        "FreeBSD" => match machine {
+            "amd64" => vec!["x86_64-unknown-freebsd".into()],
+            "arm64" => vec!["aarch64-unknown-freebsd".into()],
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    Some(targets)
+}
+
+/// Detect the targets of `host` by probing it over ssh.
+fn detect_targets(host: &str) -> Result<Vec<String>> {
+    let output = ssh(host, "uname -sm; (ldd --version 2>&1 || true) | head -n 1")?;
+    let mut lines = output.lines();
+    let uname = lines.next().unwrap_or_default();
+    let ldd = lines.next().unwrap_or_default();
+
+    targets_from_uname(uname, ldd)
+        .ok_or_else(|| miette!("Unsupported platform `{uname}` of {host}"))
+}
+
+/// Return whether `file_name` is safe to interpolate into the commands run
+/// by the remote shell and into the destination of `scp`, i.e. only has
+/// characters which are not special to them.
+fn is_safe_file_name(file_name: &str) -> bool {
+    !file_name.is_empty()
+        && file_name
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'.' | b'_' | b'-'))
+}
+
+/// Return the path on the remote hosts of `path` installed in `staging`.
+fn remote_path(staging: &Path, path: &Path) -> Result<String> {
+    let relative = path
+        .strip_prefix(staging)
+        .map_err(|_| miette!("{} is not installed in the staging root", path.display()))?;
+
+    let mut remote_path = String::from(REMOTE_ROOT);
+    for component in relative.components() {
+        let component = match component {
+            Component::Normal(component) => component.to_str(),
+            _ => None,
+        }
+        .filter(|component| is_safe_file_name(component) && !component.starts_with('.'))
+        .ok_or_else(|| {
+            miette!(
+                "Refusing to upload {}, only ASCII letters, digits, `.`, `_` and `-` are allowed in the paths of the binaries",
+                relative.display()
+            )
+        })?;
+
+        remote_path.push('/');
+        remote_path.push_str(component);
+    }
+
+    Ok(remote_path)
+}
+
+/// Upload the binary of `digest` installed in `staging` to `host`, verify it
+/// and then move it to its path under [`REMOTE_ROOT`].
+fn upload(host: &str, staging: &Path, digest: &BinDigest) -> Result<()> {
+    let dst = remote_path(staging, &digest.path)?;
+    let (dir, file_name) = dst.rsplit_once('/').unwrap();
+    let tmp = format!("{dir}/.{file_name}.binstall-tmp");
+
+    ssh(host, &format!("mkdir -p '{dir}'"))?;
+    scp(host, &digest.path, &tmp)?;
+
+    let sha256 = ssh(
+        host,
+        &format!("(sha256sum '{tmp}' 2>/dev/null || shasum -a 256 '{tmp}') | cut -d ' ' -f 1"),
+    )?;
+    if sha256.trim() != digest.sha256 {
+        ssh(host, &format!("rm -f '{tmp}'"))?;
+        return Err(miette!(
+            "{dst} uploaded to {host} has sha256 {}, expected {}",
+            sha256.trim(),
+            digest.sha256
+        ));
+    }
+
+    ssh(host, &format!("chmod 755 '{tmp}' && mv -f '{tmp}' '{dst}'"))?;
+    Ok(())
+}
+
+/// Upload all the binaries installed in `staging` to `host`.
+fn install_on_host(host: &str, staging: &Path) -> Result<()> {
+    let records = Records::load_from_path(staging.join("binstall/crates-v1.json"))?;
+
+    for data in &records {
+        let crate_info = &data.crate_info;
+        for digest in &crate_info.bin_digests {
+            upload(host, staging, digest)?;
+        }
+        info!(
+            "Installed {} v{} ({}) on {host}",
+            crate_info.name, crate_info.current_version, crate_info.target
+        );
+    }
+
+    Ok(())
+}
+
+pub fn install_remote(
+    mut args: Args,
+    jobserver_client: LazyJobserverClient,
+) -> Result<Option<impl Future<Output = Result<()>>>> {
+    let hosts = std::mem::take(&mut args.remote);
+
+    if args.targets.is_some() {
+        return Err(miette!(
+            "--targets cannot be used with --remote, the targets of the hosts are detected"
+        ));
+    }
+
+    // Crates built from source are built for the local host and are not
+    // recorded in `crates-v1.json`, so they cannot be uploaded.
+    args.strategies
+        .retain(|strategy| !matches!(strategy, Strategy::SourceArchive | Strategy::Compile));
+    if args.strategies.is_empty() {
+        return Err(miette!(
+            "--remote only installs pre-built binaries, enable strategy crate-meta-data or quick-install"
+        ));
+    }
+
+    // Hosts of the same targets share the installed binaries.
+    let mut groups: BTreeMap<Vec<String>, Vec<String>> = BTreeMap::new();
+    for host in hosts {
+        let targets = detect_targets(&host)?;
+        info!("Detected targets of {host}: {}", targets.join(", "));
+        groups.entry(targets).or_default().push(host);
+    }
+
+    Ok(Some(async move {
+        let total: usize = groups.values().map(Vec::len).sum();
+        let mut failed = 0;
+
+        for (targets, hosts) in groups {
+            let staging = tempfile::tempdir()
+                .map_err(|err| miette!("Failed to create the staging root: {err}"))?;
+
+            let mut args = args.clone();
+            args.targets = Some(targets);
+            args.root = Some(staging.path().to_owned());
+            args.install_path = None;
+            args.force = true;
+
+            let install = block_in_place(|| entry::install_crates(args, jobserver_client.clone()))?;
+            if let Some(install) = install {
+                install.await?;
+            }
+
+            for host in hosts {
+                if let Err(err) = block_in_place(|| install_on_host(&host, staging.path())) {
+                    warn!("Failed to install on {host}: {err}");
+                    failed += 1;
+                }
+            }
+        }
+
+        if failed == 0 {
+            Ok(())
+        } else {
+            Err(miette!("Failed to install on {failed} of {total} hosts"))
+        }
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_safe_file_name() {
+        assert!(is_safe_file_name("cargo-binstall"));
+        assert!(is_safe_file_name("rg_1.2.exe"));
+        assert!(!is_safe_file_name(""));
+        assert!(!is_safe_file_name("a'; rm -rf ~; '"));
+        assert!(!is_safe_file_name("$(reboot)"));
+        assert!(!is_safe_file_name("a b"));
+    }
+
+    #[test]
+    fn test_remote_path() {
+        let staging = Path::new("/tmp/staging");
+
+        assert_eq!(
+            remote_path(staging, &staging.join("bin/cargo-binstall")).unwrap(),
+            ".cargo/bin/cargo-binstall"
+        );
+        assert_eq!(
+            remote_path(staging, &staging.join("libexec/foo/foo-helper")).unwrap(),
+            ".cargo/libexec/foo/foo-helper"
+        );
+        assert!(remote_path(staging, Path::new("/usr/bin/foo")).is_err());
+        assert!(remote_path(staging, &staging.join("bin/../foo")).is_err());
+        assert!(remote_path(staging, &staging.join("bin/a b")).is_err());
+    }
+
+    #[test]
+    fn test_targets_from_uname() {
+        assert_eq!(
+            targets_from_uname("Linux x86_64", "ldd (GNU libc) 2.36").unwrap(),
+            ["x86_64-unknown-linux-gnu", "x86_64-unknown-linux-musl"]
+        );
+        assert_eq!(
+            targets_from_uname("Linux aarch64", "musl libc (aarch64)").unwrap(),
+            ["aarch64-unknown-linux-musl"]
+        );
+        assert_eq!(
+            targets_from_uname("Linux armv7l", "ldd (Debian GLIBC 2.36-9) 2.36").unwrap(),
+            [
+                "armv7-unknown-linux-gnueabihf",
+                "armv7-unknown-linux-musleabihf"
+            ]
+        );
+        assert_eq!(
+            targets_from_uname("Darwin arm64", "").unwrap(),
+            ["aarch64-apple-darwin", "x86_64-apple-darwin"]
+        );
+        assert_eq!(targets_from_uname("SunOS i86pc", ""), None);
+    }
+}
@@ -0,0 +1,442 @@
+//! `cargo binstall serve`: JSON-RPC 2.0 over a unix socket.
+//!
+//! All requests share the same [`Options`], thus the same http client,
+//! GitHub API client and registry, so connections and caches are reused
+//! across requests.
+
+use std::{future::Future, path::PathBuf};
+
+use binstalk::helpers::jobserver_client::LazyJobserverClient;
+use miette::Result;
+
+use crate::args::Args;
+
+#[cfg(unix)]
+pub fn serve(
+    args: Args,
+    socket: PathBuf,
+    jobserver_client: LazyJobserverClient,
+) -> Result<Option<impl Future<Output = Result<()>>>> {
+    unix::serve(args, socket, jobserver_client)
+}
+
+#[cfg(not(unix))]
+pub fn serve(
+    _args: Args,
+    _socket: PathBuf,
+    _jobserver_client: LazyJobserverClient,
+) -> Result<Option<impl Future<Output = Result<()>>>> {
+    Err::<Option<std::future::Ready<Result<()>>>, _>(miette::miette!(
+        "`serve` is only supported on unix"
+    ))
+}
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        collections::BTreeMap, fs, future::Future, io, os::unix::fs::FileTypeExt, path::PathBuf,
+        sync::Arc,
+    };
+
+    use binstalk::{
+        errors::BinstallError,
+        helpers::jobserver_client::LazyJobserverClient,
+        ops::{
+            self,
+            event::InstallEvent,
+            installer::{InstallContext, InstallOutcome, Installer},
+            resolve::CrateName,
+        },
+    };
+    use binstalk_manifests::{
+        cargo_config::Config,
+        crates_manifests::{load_installed_crates_read_only, ManifestsError},
+    };
+    use compact_str::CompactString;
+    use home::cargo_home;
+    use miette::{Diagnostic, Result};
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::{UnixListener, UnixStream},
+        sync::Mutex,
+        task::block_in_place,
+    };
+    use tracing::{debug, info, warn};
+
+    use crate::{
+        args::Args,
+        entry::{compute_options, compute_paths, load_system_crates},
+        environment,
+    };
+
+    // Error codes defined by JSON-RPC 2.0
+    const PARSE_ERROR: i64 = -32700;
+    const INVALID_REQUEST: i64 = -32600;
+    const METHOD_NOT_FOUND: i64 = -32601;
+    const INVALID_PARAMS: i64 = -32602;
+    /// Error returned by binstall itself.
+    const SERVER_ERROR: i64 = -32000;
+
+    #[derive(Debug)]
+    struct RpcError {
+        code: i64,
+        message: String,
+        data: Option<Value>,
+    }
+
+    impl RpcError {
+        fn new(code: i64, message: impl ToString) -> Self {
+            Self {
+                code,
+                message: message.to_string(),
+                data: None,
+            }
+        }
+
+        fn into_json(self) -> Value {
+            let mut err = json!({
+                "code": self.code,
+                "message": self.message,
+            });
+            if let Some(data) = self.data {
+                err["data"] = data;
+            }
+            err
+        }
+    }
+
+    impl<E: Diagnostic + Send + Sync + 'static> From<E> for RpcError {
+        fn from(err: E) -> Self {
+            Self {
+                code: SERVER_ERROR,
+                data: Some(json!({ "code": err.code().map(|code| code.to_string()) })),
+                message: format!("{:?}", miette::Report::new(err)),
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct Request {
+        jsonrpc: CompactString,
+        #[serde(default)]
+        id: Option<Value>,
+        method: CompactString,
+        #[serde(default)]
+        params: Value,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct ResolveParams {
+        #[serde(rename = "crate")]
+        crate_name: CompactString,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct InstallParams {
+        crates: Vec<CompactString>,
+    }
+
+    struct Server {
+        installer: Installer,
+        /// `None` if the installed crates are not tracked.
+        cargo_roots: Option<PathBuf>,
+        system_root: Option<PathBuf>,
+        /// Installations share the temporary directory of the options of
+        /// `installer`, so they are done one at a time.
+        install_lock: Mutex<()>,
+        _temp_dir: tempfile::TempDir,
+    }
+
+    /// Remove the socket file on exit.
+    struct SocketGuard(PathBuf);
+
+    impl Drop for SocketGuard {
+        fn drop(&mut self) {
+            if let Err(err) = fs::remove_file(&self.0) {
+                warn!("Failed to remove socket {}: {err}", self.0.display());
+            }
+        }
+    }
+
+    pub(super) fn serve(
+        mut args: Args,
+        socket: PathBuf,
+        jobserver_client: LazyJobserverClient,
+    ) -> Result<Option<impl Future<Output = Result<()>>>> {
+        // Load .cargo/config.toml
+        let cargo_home = cargo_home().map_err(BinstallError::from)?;
+        let mut config = Config::load_from_path(cargo_home.join("config.toml"))?;
+
+        let (install_path, cargo_roots, temp_dir) = compute_paths(
+            args.root.clone(),
+            args.install_path.take(),
+            args.no_track,
+            cargo_home,
+            &mut config,
+        )?;
+
+        let system_root = args.system_root.clone();
+
+        let opts = compute_options(
+            args,
+            config,
+            install_path,
+            temp_dir.path().to_owned(),
+            jobserver_client,
+            None,
+        )?;
+
+        let server = Arc::new(Server {
+            installer: Installer::from_options(Arc::new(opts), cargo_roots.clone()),
+            cargo_roots,
+            system_root,
+            install_lock: Mutex::new(()),
+            _temp_dir: temp_dir,
+        });
+
+        // Remove stale socket left by a previous server.
+        if fs::symlink_metadata(&socket)
+            .map(|metadata| metadata.file_type().is_socket())
+            .unwrap_or(false)
+        {
+            fs::remove_file(&socket).map_err(BinstallError::from)?;
+        }
+
+        let listener = UnixListener::bind(&socket).map_err(BinstallError::from)?;
+        let guard = SocketGuard(socket);
+
+        info!("Listening on {}", guard.0.display());
+
+        Ok(Some(async move {
+            let _guard = guard;
+
+            loop {
+                let (stream, _) = listener.accept().await.map_err(BinstallError::from)?;
+                let server = server.clone();
+
+                tokio::spawn(async move {
+                    if let Err(err) = server.handle_connection(stream).await {
+                        warn!("Connection closed with error: {err}");
+                    }
+                });
+            }
+        }))
+    }
+
+    impl Server {
+        async fn handle_connection(&self, stream: UnixStream) -> io::Result<()> {
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+
+            while let Some(line) = lines.next_line().await? {
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                if let Some(response) = self.handle_line(&line).await {
+                    let mut response = response.to_string();
+                    response.push('\n');
+                    writer.write_all(response.as_bytes()).await?;
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Return `None` if `line` is a notification.
+        async fn handle_line(&self, line: &str) -> Option<Value> {
+            let request: Request = match serde_json::from_str(line) {
+                Ok(request) => request,
+                Err(err) => {
+                    let code = if err.is_data() {
+                        INVALID_REQUEST
+                    } else {
+                        PARSE_ERROR
+                    };
+                    return Some(response(Value::Null, Err(RpcError::new(code, err))));
+                }
+            };
+
+            debug!("Received request {}", request.method);
+
+            let id = request.id?;
+            let res = if request.jsonrpc != "2.0" {
+                Err(RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\""))
+            } else {
+                self.dispatch(&request.method, request.params).await
+            };
+
+            Some(response(id, res))
+        }
+
+        async fn dispatch(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+            match method {
+                "resolve" => self.resolve(parse_params(params)?).await,
+                "install" => self.install(parse_params(params)?).await,
+                "list" => self.list(),
+                _ => Err(RpcError::new(
+                    METHOD_NOT_FOUND,
+                    format_args!("method {method} not found"),
+                )),
+            }
+        }
+
+        /// Load the installed crates with a shared lock, so that read-only
+        /// requests do not wait for each other.
+        fn installed_crates(
+            &self,
+        ) -> Result<Option<BTreeMap<CompactString, semver::Version>>, ManifestsError> {
+            self.cargo_roots
+                .as_deref()
+                .map(|cargo_roots| block_in_place(|| load_installed_crates_read_only(cargo_roots)))
+                .transpose()
+        }
+
+        fn system_crates(
+            &self,
+        ) -> Result<Option<BTreeMap<CompactString, semver::Version>>, RpcError> {
+            block_in_place(|| {
+                load_system_crates(self.system_root.as_deref(), self.cargo_roots.as_deref())
+            })
+            .map_err(|err| RpcError::new(SERVER_ERROR, format_args!("{err:?}")))
+        }
+
+        async fn resolve(&self, params: ResolveParams) -> Result<Value, RpcError> {
+            let crate_name = parse_crate_name(&params.crate_name)?;
+
+            let current_version = self
+                .installed_crates()?
+                .and_then(|mut installed_crates| installed_crates.remove(&crate_name.name))
+                .or(self
+                    .system_crates()?
+                    .and_then(|mut system_crates| system_crates.remove(&crate_name.name)));
+
+            let resolution = ops::resolve::resolve(
+                self.installer.options().clone(),
+                crate_name,
+                current_version,
+            )
+            .await?;
+
+            Ok(resolution.to_json())
+        }
+
+        async fn install(&self, params: InstallParams) -> Result<Value, RpcError> {
+            let crate_names = params
+                .crates
+                .iter()
+                .map(|crate_name| parse_crate_name(crate_name))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let _lock = self.install_lock.lock().await;
+
+            let opts = self.installer.options();
+            let environment = environment::capture(&opts.desired_targets).await;
+            opts.emit(InstallEvent::Environment {
+                environment: &environment,
+            });
+
+            let context = InstallContext {
+                system_crates: self.system_crates()?.unwrap_or_default(),
+                environment: Some(environment),
+            };
+
+            let mut installed = Vec::new();
+            let mut compiled = Vec::new();
+            let mut up_to_date = Vec::new();
+            let mut failed = Vec::new();
+
+            for (name, outcome) in self.installer.install_with(crate_names, context).await? {
+                match outcome {
+                    Ok(InstallOutcome::Installed(crate_info)) => installed.push(crate_info),
+                    Ok(InstallOutcome::Compiled { version }) => {
+                        compiled.push(json!({ "name": name, "version": version }))
+                    }
+                    Ok(InstallOutcome::AlreadyUpToDate) => up_to_date.push(name),
+                    Ok(_) => (),
+                    Err(err) => failed.push(json!({
+                        "name": name,
+                        "error": RpcError::from(err).into_json(),
+                    })),
+                }
+            }
+
+            Ok(json!({
+                "installed": installed,
+                "compiled": compiled,
+                "up-to-date": up_to_date,
+                "failed": failed,
+            }))
+        }
+
+        fn list(&self) -> Result<Value, RpcError> {
+            let installed_crates = self
+                .installed_crates()?
+                .ok_or_else(|| RpcError::new(SERVER_ERROR, "installed crates are not tracked"))?;
+
+            Ok(installed_crates
+                .into_iter()
+                .map(|(name, version)| json!({ "name": name, "version": version.to_string() }))
+                .collect())
+        }
+    }
+
+    fn response(id: Value, res: Result<Value, RpcError>) -> Value {
+        match res {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(err) => json!({ "jsonrpc": "2.0", "id": id, "error": err.into_json() }),
+        }
+    }
+
+    fn parse_params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+        serde_json::from_value(params).map_err(|err| RpcError::new(INVALID_PARAMS, err))
+    }
+
+    fn parse_crate_name(crate_name: &str) -> Result<CrateName, RpcError> {
+        crate_name.parse().map_err(|err| {
+            RpcError::new(
+                INVALID_PARAMS,
+                format_args!("invalid crate name {crate_name:?}: {err}"),
+            )
+        })
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[test]
+        fn test_response() {
+            assert_eq!(
+                response(json!(1), Ok(json!([]))),
+                json!({ "jsonrpc": "2.0", "id": 1, "result": [] })
+            );
+            assert_eq!(
+                response(
+                    json!("a"),
+                    Err(RpcError::new(METHOD_NOT_FOUND, "method x not found"))
+                ),
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": "a",
+                    "error": { "code": METHOD_NOT_FOUND, "message": "method x not found" },
+                })
+            );
+        }
+
+        #[test]
+        fn test_parse_params() {
+            let params: InstallParams =
+                parse_params(json!({ "crates": ["cargo-binstall@1"] })).unwrap();
+            assert_eq!(params.crates, ["cargo-binstall@1"]);
+
+            let err = parse_params::<ResolveParams>(json!({})).unwrap_err();
+            assert_eq!(err.code, INVALID_PARAMS);
+
+            let err = parse_crate_name("a@!").unwrap_err();
+            assert_eq!(err.code, INVALID_PARAMS);
+        }
+    }
+}
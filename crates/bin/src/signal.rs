@@ -6,14 +6,17 @@ use tokio::signal;
 /// This function will poll the handle while listening for ctrl_c,
 /// `SIGINT`, `SIGHUP`, `SIGTERM` and `SIGQUIT`.
 ///
-/// When signal is received, [`BinstallError::UserAbort`] will be returned.
+/// When signal is received, the task of the handle is cancelled and
+/// waited for, so that it cleans up its temporary files and writes its
+/// resume journal (see `journal`) before [`BinstallError::UserAbort`] is
+/// returned.
 ///
 /// It would also ignore `SIGUSER1` and `SIGUSER2` on unix.
 ///
 /// This function uses [`tokio::signal`] and once exit, does not reset the default
 /// signal handler, so be careful when using it.
 pub async fn cancel_on_user_sig_term<T>(
-    handle: AutoAbortJoinHandle<T>,
+    mut handle: AutoAbortJoinHandle<T>,
 ) -> Result<T, BinstallError> {
     ignore_signals()?;
 
@@ -21,10 +24,16 @@ pub async fn cancel_on_user_sig_term<T>(
         biased;
 
         res = wait_on_cancellation_signal() => {
-            res.map_err(BinstallError::Io)
-                .and(Err(BinstallError::UserAbort))
+            res.map_err(BinstallError::Io)?;
+
+            // The task is dropped, cancelling its downloads, once it reaches
+            // its next await point.
+            handle.abort();
+            let _ = (&mut handle).await;
+
+            Err(BinstallError::UserAbort)
         }
-        res = handle => res,
+        res = &mut handle => res,
     }
 }
 
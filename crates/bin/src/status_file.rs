@@ -0,0 +1,127 @@
+//! Status file for shell prompts (e.g. starship) to show pending updates
+//! of the installed crates without running binstall.
+//!
+//! It is stored at `$CARGO_ROOT/binstall/status.json`, see [`Status`].
+
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use compact_str::CompactString;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+#[derive(Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct Status {
+    /// Unix timestamp of the last time installed crates are checked
+    /// for updates.
+    pub(crate) last_check: u64,
+    /// Number of installed crates with an update available.
+    pub(crate) outdated: usize,
+    /// Names of the installed crates with an update available.
+    pub(crate) outdated_crates: BTreeSet<CompactString>,
+}
+
+impl Status {
+    /// Record that the installed crates `checked` have been checked for
+    /// updates, of which `outdated` have updates available.
+    fn update<'a>(
+        &mut self,
+        now: u64,
+        checked: impl IntoIterator<Item = &'a str>,
+        outdated: impl IntoIterator<Item = &'a str>,
+    ) {
+        for name in checked {
+            self.outdated_crates.remove(name);
+        }
+        self.outdated_crates
+            .extend(outdated.into_iter().map(From::from));
+
+        self.last_check = now;
+        self.outdated = self.outdated_crates.len();
+    }
+}
+
+fn status_file_path(cargo_roots: &Path) -> PathBuf {
+    cargo_roots.join("binstall/status.json")
+}
+
+fn do_update_status_file<'a>(
+    cargo_roots: &Path,
+    checked: impl IntoIterator<Item = &'a str>,
+    outdated: impl IntoIterator<Item = &'a str>,
+) -> io::Result<()> {
+    let path = status_file_path(cargo_roots);
+
+    // Start over if the file is missing or corrupted.
+    let mut status: Status = fs::read(&path)
+        .ok()
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or_default();
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+
+    status.update(now, checked, outdated);
+
+    fs::create_dir_all(path.parent().unwrap())?;
+
+    // Write to a temporary file then rename it, so that readers never
+    // see a partially written file.
+    let tmp_path = path.with_extension("json.tmp");
+    fs::write(&tmp_path, serde_json::to_vec(&status)?)?;
+    fs::rename(tmp_path, path)
+}
+
+/// Update the status file, failures are logged and ignored.
+pub(crate) fn update_status_file<'a>(
+    cargo_roots: &Path,
+    checked: impl IntoIterator<Item = &'a str>,
+    outdated: impl IntoIterator<Item = &'a str>,
+) {
+    if let Err(err) = do_update_status_file(cargo_roots, checked, outdated) {
+        warn!("Failed to update the status file: {err}");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_status_update() {
+        let mut status = Status::default();
+
+        status.update(1, ["a", "b", "c"], ["a", "b"]);
+        assert_eq!(status.last_check, 1);
+        assert_eq!(status.outdated, 2);
+
+        status.update(2, ["a"], []);
+        assert_eq!(
+            status,
+            Status {
+                last_check: 2,
+                outdated: 1,
+                outdated_crates: BTreeSet::from(["b".into()]),
+            }
+        );
+    }
+
+    #[test]
+    fn test_update_status_file() {
+        let cargo_roots = tempfile::tempdir().unwrap();
+
+        update_status_file(cargo_roots.path(), ["a"], ["a"]);
+
+        let status: Status =
+            serde_json::from_slice(&fs::read(status_file_path(cargo_roots.path())).unwrap())
+                .unwrap();
+        assert_eq!(status.outdated, 1);
+    }
+}
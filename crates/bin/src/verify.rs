@@ -0,0 +1,114 @@
+//! `cargo binstall verify`: check the installed binaries against the
+//! digests recorded when they were installed.
+
+use binstalk::{
+    errors::BinstallError,
+    ops::verify::{verify_bin, BinStatus, BinVerification},
+};
+use binstalk_manifests::{binstall_crates_v1::Records, cargo_config::Config};
+use compact_str::CompactString;
+use home::cargo_home;
+use miette::{miette, Result};
+
+use crate::{args::Args, install_path};
+
+/// Return the problem found with the binary, if any.
+fn format_problem(crate_name: &str, verification: &BinVerification) -> Option<String> {
+    let path = verification.path.display();
+    let problem = match &verification.status {
+        BinStatus::Unchanged => return None,
+        BinStatus::Modified => "is modified".to_string(),
+        BinStatus::Missing => "is missing".to_string(),
+        BinStatus::Unreadable(err) => format!("cannot be read: {err}"),
+    };
+    Some(format!("{crate_name}: {path} {problem}"))
+}
+
+pub fn verify(args: Args, crate_names: Vec<CompactString>, quick: bool) -> Result<()> {
+    let cargo_home = cargo_home().map_err(BinstallError::from)?;
+    let mut config = Config::load_from_path(cargo_home.join("config.toml"))?;
+
+    let cargo_roots = install_path::get_cargo_roots_path(args.root, cargo_home, &mut config)
+        .ok_or_else(|| miette!("No cargo roots path found or specified"))?;
+
+    let records = Records::load_from_path(cargo_roots.join("binstall/crates-v1.json"))?;
+
+    if let Some(name) = crate_names.iter().find(|name| !records.contains(name)) {
+        return Err(miette!("{name} is not installed by binstall"));
+    }
+
+    let (mut verified, mut rehashed, mut failed) = (0, 0, 0);
+
+    for data in &records {
+        let crate_info = &data.crate_info;
+        if !crate_names.is_empty() && !crate_names.contains(&crate_info.name) {
+            continue;
+        }
+
+        if crate_info.bin_digests.is_empty() {
+            println!(
+                "{}: unrecorded, reinstall it to record the digests of its binaries",
+                crate_info.name
+            );
+            continue;
+        }
+
+        for digest in &crate_info.bin_digests {
+            let verification = verify_bin(digest, quick);
+
+            verified += 1;
+            rehashed += usize::from(verification.rehashed);
+
+            if let Some(problem) = format_problem(&crate_info.name, &verification) {
+                println!("{problem}");
+                failed += 1;
+            }
+        }
+    }
+
+    println!("Verified {verified} binaries, rehashed {rehashed} of them");
+
+    match failed {
+        0 => Ok(()),
+        n => Err(BinstallError::VerificationFailed(
+            format!("{n} of {verified} binaries failed verification").into(),
+        )
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{io, path::PathBuf};
+
+    use super::*;
+
+    #[test]
+    fn test_format_problem() {
+        let verification = |status| BinVerification {
+            path: PathBuf::from("/bin/foo"),
+            status,
+            rehashed: true,
+        };
+
+        assert_eq!(
+            format_problem("foo", &verification(BinStatus::Unchanged)),
+            None
+        );
+        assert_eq!(
+            format_problem("foo", &verification(BinStatus::Modified)).unwrap(),
+            "foo: /bin/foo is modified"
+        );
+        assert_eq!(
+            format_problem(
+                "foo",
+                &verification(BinStatus::Unreadable(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "denied"
+                )))
+            )
+            .unwrap(),
+            "foo: /bin/foo cannot be read: denied"
+        );
+    }
+}
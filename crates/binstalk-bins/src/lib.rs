@@ -1,13 +1,13 @@
 use std::{
     borrow::Cow,
-    fmt, io,
+    fmt, io, iter,
     path::{self, Component, Path, PathBuf},
 };
 
 use atomic_file_install::{
     atomic_install, atomic_install_noclobber, atomic_symlink_file, atomic_symlink_file_noclobber,
 };
-use binstalk_types::cargo_toml_binstall::{PkgFmt, PkgMeta};
+use binstalk_types::cargo_toml_binstall::{BinLauncher, PkgFmt, PkgMeta};
 use compact_str::{format_compact, CompactString};
 use leon::Template;
 use miette::Diagnostic;
@@ -28,6 +28,13 @@ pub enum Error {
     #[error("bin-dir configuration provided generates empty source path")]
     EmptySourceFilePath,
 
+    /// bin-dest configuration provided generates destination path outside
+    /// of the install root.
+    #[error(
+        "bin-dest configuration provided generates destination path outside of the install root: {}", .0.display()
+    )]
+    InvalidDestFilePath(Box<Path>),
+
     /// Bin file is not found.
     #[error("bin file {} not found", .0.display())]
     BinFileNotFound(Box<Path>),
@@ -35,6 +42,10 @@ pub enum Error {
     #[error(transparent)]
     Io(#[from] io::Error),
 
+    #[error("Failed to parse template: {0}")]
+    #[diagnostic(transparent)]
+    TemplateParse(#[from] leon::ParseError),
+
     #[error("Failed to render template: {0}")]
     #[diagnostic(transparent)]
     TemplateRender(#[from] leon::RenderError),
@@ -54,11 +65,20 @@ fn is_valid_path(path: &Path) -> bool {
 
 /// Must be called after the archive is downloaded and extracted.
 /// This function might uses blocking I/O.
+///
+/// The directories named after the `asset-name` of the package, which
+/// the release assets are named after, are tried before the ones named
+/// after the crate.
 pub fn infer_bin_dir_template(
     data: &Data,
     has_dir: &mut dyn FnMut(&Path) -> bool,
 ) -> Cow<'static, str> {
-    let name = data.name;
+    let names = data
+        .meta
+        .asset_name
+        .as_deref()
+        .into_iter()
+        .chain(iter::once(data.name));
     let target = data.target;
     let version = data.version;
 
@@ -79,9 +99,12 @@ pub fn infer_bin_dir_template(
 
     let default_bin_dir_template = Cow::Borrowed("{ bin }{ binary-ext }");
 
-    gen_possible_dirs
-        .into_iter()
-        .map(|gen_possible_dir| gen_possible_dir(name, target, version))
+    names
+        .flat_map(|name| {
+            gen_possible_dirs
+                .into_iter()
+                .map(move |gen_possible_dir| gen_possible_dir(name, target, version))
+        })
         .find(|dirname| has_dir(Path::new(&dirname)))
         .map(|mut dir| {
             dir.reserve_exact(1 + default_bin_dir_template.len());
@@ -99,6 +122,59 @@ pub struct BinFile {
     pub archive_source_path: PathBuf,
     pub dest: PathBuf,
     pub link: Option<PathBuf>,
+    /// Shim running the binary with its launcher, if it is not a native
+    /// executable.
+    pub shim: Option<Shim>,
+}
+
+/// Shim generated for binaries which are not native executables, named
+/// after the binary and running it with its [`BinLauncher`].
+pub struct Shim {
+    /// Path of the shim generated, next to the source of the binary.
+    pub source: PathBuf,
+    pub dest: PathBuf,
+    content: String,
+}
+
+impl Shim {
+    fn new(
+        launcher: BinLauncher,
+        bin_source: &Path,
+        bin_dest: &Path,
+        dest: PathBuf,
+        is_windows: bool,
+    ) -> Self {
+        let mut source = bin_source.as_os_str().to_owned();
+        source.push(".shim");
+
+        let command = launcher.command(is_windows).join(" ");
+        let content = if is_windows {
+            // `%` would be expanded in batch files.
+            let bin = bin_dest.display().to_string().replace('%', "%%");
+            format!("@{command} \"{bin}\" %*\r\n")
+        } else {
+            let bin = bin_dest.display().to_string().replace('\'', r"'\''");
+            format!("#!/bin/sh\nexec {command} '{bin}' \"$@\"\n")
+        };
+
+        Self {
+            source: source.into(),
+            dest,
+            content,
+        }
+    }
+
+    fn write(&self) -> Result<(), Error> {
+        std::fs::write(&self.source, &self.content)?;
+
+        #[cfg(unix)]
+        std::fs::set_permissions(
+            &self.source,
+            std::os::unix::fs::PermissionsExt::from_mode(0o755),
+        )?;
+
+        Ok(())
+    }
 }
 
 impl BinFile {
@@ -109,10 +185,12 @@ impl BinFile {
         tt: &Template<'_>,
         no_symlinks: bool,
     ) -> Result<Self, Error> {
-        let binary_ext = if data.target.contains("windows") {
-            ".exe"
-        } else {
-            ""
+        let is_windows = data.target.contains("windows");
+        let launcher = data.meta.bin_launchers.get(base_name).copied();
+        let binary_ext = match launcher {
+            Some(launcher) => launcher.extension(),
+            None if is_windows => ".exe",
+            None => "",
         };
 
         let ctx = Context {
@@ -122,6 +200,7 @@ impl BinFile {
             version: data.version,
             bin: base_name,
             binary_ext,
+            flavor: data.meta.flavor.as_deref(),
 
             target_related_info: data.target_related_info,
         };
@@ -149,33 +228,66 @@ impl BinFile {
             (data.bin_path.join(&path_normalized), path_normalized)
         };
 
-        // Destination at install dir + base-name{.extension}
-        let mut dest = data.install_path.join(ctx.bin);
-        if !binary_ext.is_empty() {
-            let binary_ext = binary_ext.strip_prefix('.').unwrap();
+        let dest = if let Some(bin_dest) = data.meta.bin_dest.get(base_name) {
+            // Destination at install root + the generated path
+            let path = Template::parse(bin_dest)?.render(&ctx)?;
+            let path_normalized = Path::new(&path)
+                .try_normalize()
+                .filter(|path| path.file_name().is_some())
+                .ok_or_else(|| Error::InvalidDestFilePath(Path::new(&path).into()))?;
 
-            // PathBuf::set_extension returns false if Path::file_name
-            // is None, but we know that the file name must be Some,
-            // thus we assert! the return value here.
-            assert!(dest.set_extension(binary_ext));
-        }
+            let install_root = data.install_path.parent().unwrap_or(data.install_path);
+            install_root.join(path_normalized)
+        } else {
+            // Destination at install dir + base-name{.extension}
+            let mut dest = data.install_path.join(ctx.bin);
+            if !binary_ext.is_empty() {
+                let binary_ext = binary_ext.strip_prefix('.').unwrap();
+
+                // PathBuf::set_extension returns false if Path::file_name
+                // is None, but we know that the file name must be Some,
+                // thus we assert! the return value here.
+                assert!(dest.set_extension(binary_ext));
+            }
+            dest
+        };
 
         let (dest, link) = if no_symlinks {
             (dest, None)
         } else {
-            // Destination path is the install dir + base-name-version{.extension}
+            // Destination path is the dir of dest + base-name-version{.extension}
             let dest_file_path_with_ver = format!("{}-v{}{}", ctx.bin, ctx.version, ctx.binary_ext);
-            let dest_with_ver = data.install_path.join(dest_file_path_with_ver);
+            let dest_with_ver = dest.with_file_name(dest_file_path_with_ver);
 
             (dest_with_ver, Some(dest))
         };
 
+        let Some(launcher) = launcher else {
+            return Ok(Self {
+                base_name: format_compact!("{base_name}{binary_ext}"),
+                source,
+                archive_source_path,
+                dest,
+                link,
+                shim: None,
+            });
+        };
+
+        // The shim takes the place of the link, or of the binary if there
+        // is none, without the extension of the launcher.
+        let shim_dest = link
+            .as_deref()
+            .unwrap_or(&dest)
+            .with_extension(if is_windows { "cmd" } else { "" });
+        let shim = Shim::new(launcher, &source, &dest, shim_dest, is_windows);
+
         Ok(Self {
-            base_name: format_compact!("{base_name}{binary_ext}"),
+            base_name: shim.dest.file_name().unwrap().to_string_lossy().into(),
             source,
             archive_source_path,
             dest,
-            link,
+            link: None,
+            shim: Some(shim),
         })
     }
 
@@ -187,12 +299,20 @@ impl BinFile {
         }
     }
 
+    /// Preview the link, or the shim in its place.
     pub fn preview_link(&self) -> impl fmt::Display + '_ {
-        OptionalLazyFormat(self.link.as_ref().map(|link| LazyFormat {
+        let link = self.link.as_ref().map(|link| LazyFormat {
             base_name: &self.base_name,
             source: link.display(),
             dest: self.link_dest().display(),
-        }))
+        });
+        let shim = self.shim.as_ref().map(|shim| LazyFormat {
+            base_name: &self.base_name,
+            source: shim.dest.display(),
+            dest: self.dest.display(),
+        });
+
+        OptionalLazyFormat(link.or(shim))
     }
 
     /// Return `Ok` if the source exists, otherwise `Err`.
@@ -212,12 +332,21 @@ impl BinFile {
             return Err(Error::BinFileNotFound((&*self.source).into()));
         }
 
+        // The dir of a destination from bin-dest might not exist yet.
+        if let Some(parent) = self.dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
         #[cfg(unix)]
         std::fs::set_permissions(
             &self.source,
             std::os::unix::fs::PermissionsExt::from_mode(0o755),
         )?;
 
+        if let Some(shim) = &self.shim {
+            shim.write()?;
+        }
+
         Ok(())
     }
 
@@ -232,6 +361,11 @@ impl BinFile {
 
         atomic_install(&self.source, &self.dest)?;
 
+        if let Some(shim) = &self.shim {
+            debug!("Atomically install shim to '{}'", shim.dest.display());
+            atomic_install(&shim.source, &shim.dest)?;
+        }
+
         Ok(())
     }
 
@@ -246,6 +380,14 @@ impl BinFile {
 
         atomic_install_noclobber(&self.source, &self.dest)?;
 
+        if let Some(shim) = &self.shim {
+            debug!(
+                "Installing shim to '{}' only if dst not exists",
+                shim.dest.display()
+            );
+            atomic_install_noclobber(&shim.source, &shim.dest)?;
+        }
+
         Ok(())
     }
 
@@ -314,6 +456,9 @@ struct Context<'c> {
     /// Filename extension on the binary, i.e. .exe on Windows, nothing otherwise
     binary_ext: &'c str,
 
+    /// Build flavor, only set if `flavor` is specified.
+    flavor: Option<&'c str>,
+
     target_related_info: &'c dyn leon::Values,
 }
 
@@ -328,6 +473,7 @@ impl leon::Values for Context<'_> {
             "binary-ext" => Some(Cow::Borrowed(self.binary_ext)),
             // Soft-deprecated alias for binary-ext
             "format" => Some(Cow::Borrowed(self.binary_ext)),
+            "flavor" => self.flavor.map(Cow::Borrowed),
 
             key => self.target_related_info.get_value(key),
         }
@@ -357,3 +503,149 @@ impl fmt::Display for OptionalLazyFormat<'_> {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    #[test]
+    fn test_bin_dest() {
+        let meta = PkgMeta {
+            bin_dest: BTreeMap::from([(
+                "mydaemon".to_string(),
+                "libexec/{ name }/{ bin }{ binary-ext }".to_string(),
+            )]),
+            ..Default::default()
+        };
+        let mut data = Data {
+            name: "mytool",
+            target: "x86_64-unknown-linux-gnu",
+            version: "1.0.0",
+            repo: None,
+            meta,
+            bin_path: Path::new("/tmp/mytool"),
+            install_path: Path::new("/home/user/.cargo/bin"),
+            target_related_info: &BTreeMap::<String, String>::new(),
+        };
+        let tt = Template::parse("{ bin }").unwrap();
+
+        let bin_file = BinFile::new(&data, "mytool", &tt, true).unwrap();
+        assert_eq!(bin_file.dest, Path::new("/home/user/.cargo/bin/mytool"));
+
+        let bin_file = BinFile::new(&data, "mydaemon", &tt, false).unwrap();
+        assert_eq!(
+            bin_file.dest,
+            Path::new("/home/user/.cargo/libexec/mytool/mydaemon-v1.0.0")
+        );
+        assert_eq!(
+            bin_file.link.as_deref(),
+            Some(Path::new("/home/user/.cargo/libexec/mytool/mydaemon"))
+        );
+
+        data.meta
+            .bin_dest
+            .insert("mydaemon".to_string(), "../{ bin }".to_string());
+        assert!(matches!(
+            BinFile::new(&data, "mydaemon", &tt, true),
+            Err(Error::InvalidDestFilePath(_))
+        ));
+    }
+
+    #[test]
+    fn test_infer_bin_dir_template() {
+        let mut data = Data {
+            name: "mytool-cli",
+            target: "x86_64-unknown-linux-gnu",
+            version: "1.0.0",
+            repo: None,
+            meta: PkgMeta::default(),
+            bin_path: Path::new("/tmp/mytool"),
+            install_path: Path::new("/home/user/.cargo/bin"),
+            target_related_info: &BTreeMap::<String, String>::new(),
+        };
+        let infer = |data: &Data, dirs: &[&str]| {
+            infer_bin_dir_template(data, &mut |dir| dirs.iter().any(|d| Path::new(d) == dir))
+        };
+
+        assert_eq!(
+            infer(&data, &["mytool-cli-x86_64-unknown-linux-gnu"]),
+            "mytool-cli-x86_64-unknown-linux-gnu/{ bin }{ binary-ext }"
+        );
+        assert_eq!(
+            infer(&data, &["mytool-x86_64-unknown-linux-gnu"]),
+            "{ bin }{ binary-ext }"
+        );
+
+        data.meta.asset_name = Some("mytool".to_string());
+        assert_eq!(
+            infer(&data, &["mytool-x86_64-unknown-linux-gnu"]),
+            "mytool-x86_64-unknown-linux-gnu/{ bin }{ binary-ext }"
+        );
+        assert_eq!(
+            infer(&data, &["mytool-cli-1.0.0", "mytool-v1.0.0"]),
+            "mytool-v1.0.0/{ bin }{ binary-ext }"
+        );
+        assert_eq!(
+            infer(&data, &["mytool-cli-1.0.0"]),
+            "mytool-cli-1.0.0/{ bin }{ binary-ext }"
+        );
+    }
+
+    #[test]
+    fn test_bin_launchers() {
+        let meta = PkgMeta {
+            bin_launchers: BTreeMap::from([("mytool-helper".to_string(), BinLauncher::Jar)]),
+            ..Default::default()
+        };
+        let mut data = Data {
+            name: "mytool",
+            target: "x86_64-unknown-linux-gnu",
+            version: "1.0.0",
+            repo: None,
+            meta,
+            bin_path: Path::new("/tmp/mytool"),
+            install_path: Path::new("/home/user/.cargo/bin"),
+            target_related_info: &BTreeMap::<String, String>::new(),
+        };
+        let tt = Template::parse("{ bin }{ binary-ext }").unwrap();
+
+        let bin_file = BinFile::new(&data, "mytool", &tt, false).unwrap();
+        assert_eq!(bin_file.base_name, "mytool");
+        assert!(bin_file.shim.is_none());
+
+        let bin_file = BinFile::new(&data, "mytool-helper", &tt, false).unwrap();
+        assert_eq!(bin_file.base_name, "mytool-helper");
+        assert_eq!(bin_file.source, Path::new("/tmp/mytool/mytool-helper.jar"));
+        assert_eq!(
+            bin_file.dest,
+            Path::new("/home/user/.cargo/bin/mytool-helper-v1.0.0.jar")
+        );
+        assert_eq!(bin_file.link, None);
+        let shim = bin_file.shim.unwrap();
+        assert_eq!(shim.source, Path::new("/tmp/mytool/mytool-helper.jar.shim"));
+        assert_eq!(shim.dest, Path::new("/home/user/.cargo/bin/mytool-helper"));
+        assert_eq!(
+            shim.content,
+            "#!/bin/sh\nexec java -jar '/home/user/.cargo/bin/mytool-helper-v1.0.0.jar' \"$@\"\n"
+        );
+
+        data.target = "x86_64-pc-windows-msvc";
+        let bin_file = BinFile::new(&data, "mytool-helper", &tt, true).unwrap();
+        assert_eq!(bin_file.base_name, "mytool-helper.cmd");
+        assert_eq!(
+            bin_file.dest,
+            Path::new("/home/user/.cargo/bin/mytool-helper.jar")
+        );
+        let shim = bin_file.shim.unwrap();
+        assert_eq!(
+            shim.dest,
+            Path::new("/home/user/.cargo/bin/mytool-helper.cmd")
+        );
+        assert_eq!(
+            shim.content,
+            "@java -jar \"/home/user/.cargo/bin/mytool-helper.jar\" %*\r\n"
+        );
+    }
+}
@@ -0,0 +1,615 @@
+//! Cache of the downloaded artifacts, which can be shared by multiple users
+//! and processes on the same machine, see [`Client::with_artifact_cache`].
+
+use std::{
+    fs,
+    future::Future,
+    io, mem,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use fs_lock::FileLock;
+use futures_util::{future, stream, Stream, StreamExt};
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    task::spawn_blocking,
+};
+use tracing::{debug, warn};
+
+use super::{hex, verifier_pool::OffloadedVerifier, DownloadError, Sha256Verifier};
+use crate::remote::Url;
+
+/// The artifacts are stored by the sha256 digest of their content under
+/// `blobs`, so that the same artifact downloaded from different urls, e.g.
+/// mirrors, is only stored once. `urls` maps the sha256 digest of each url
+/// to the digest of its artifact.
+#[derive(Clone, Debug)]
+pub(crate) struct ArtifactCache {
+    dir: PathBuf,
+    #[cfg(unix)]
+    uid: u32,
+}
+
+fn sha256_file(mut file: &fs::File) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hex(hasher.finalize()))
+}
+
+impl ArtifactCache {
+    pub(crate) fn new(dir: PathBuf) -> io::Result<Self> {
+        for dir in [dir.join("urls"), dir.join("blobs"), dir.clone()] {
+            if !dir.is_dir() {
+                fs::create_dir_all(&dir)?;
+                set_shared_dir_permissions(&dir)?;
+            }
+        }
+
+        // The owner of the files created by this process.
+        #[cfg(unix)]
+        let uid = {
+            use std::os::unix::fs::MetadataExt;
+
+            tempfile::tempfile_in(&dir)?.metadata()?.uid()
+        };
+
+        Ok(Self {
+            dir,
+            #[cfg(unix)]
+            uid,
+        })
+    }
+
+    /// Return true if the entry of `metadata` is written by this user.
+    ///
+    /// The members of the group of the cache can replace the entries of
+    /// each other, so theirs are never used.
+    fn is_trusted(&self, metadata: &fs::Metadata) -> bool {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            metadata.uid() == self.uid
+        }
+
+        #[cfg(not(unix))]
+        {
+            let _ = metadata;
+            true
+        }
+    }
+
+    fn url_path(&self, url: &Url) -> PathBuf {
+        self.dir
+            .join("urls")
+            .join(hex(Sha256::digest(url.as_str())))
+    }
+
+    fn blob_path(&self, digest: &str) -> PathBuf {
+        self.dir.join("blobs").join(digest)
+    }
+
+    /// Return the sha256 digest recorded for the artifact of `url`, if it
+    /// has been cached.
+    pub(crate) fn digest(&self, url: &Url) -> Option<String> {
+        let digest = fs::read_to_string(self.url_path(url)).ok()?;
+        Some(digest.trim().to_string())
+    }
+
+    /// Open the cached artifact of `url` if any, after checking that its
+    /// content matches the digest recorded.
+    ///
+    /// Entries which do not match are removed, e.g. if they are truncated
+    /// or tampered with. Entries written by other users are ignored.
+    async fn open_cached(&self, url: &Url) -> io::Result<Option<tokio::fs::File>> {
+        let url_path = self.url_path(url);
+        let Some(mut file) = open_if_exists(&url_path).await? else {
+            return Ok(None);
+        };
+        if !self.is_trusted(&file.metadata().await?) {
+            debug!("Ignoring the cached digest of {url} written by another user");
+            return Ok(None);
+        }
+
+        let mut digest = String::new();
+        file.read_to_string(&mut digest).await?;
+        let digest = digest.trim();
+
+        if digest.len() != 64 || !digest.bytes().all(|b| b.is_ascii_hexdigit()) {
+            warn!("Cached digest of {url} is corrupted, removing it");
+            let _ = tokio::fs::remove_file(&url_path).await;
+            return Ok(None);
+        }
+
+        let blob_path = self.blob_path(digest);
+        let Some(blob) = open_if_exists(&blob_path).await? else {
+            debug!("Cached artifact of {url} is missing");
+            let _ = tokio::fs::remove_file(&url_path).await;
+            return Ok(None);
+        };
+        if !self.is_trusted(&blob.metadata().await?) {
+            debug!("Ignoring the cached artifact of {url} written by another user");
+            return Ok(None);
+        }
+
+        // Hash the file opened, so that it cannot be replaced once checked.
+        let blob = blob.into_std().await;
+        let (blob, actual) = spawn_blocking(move || {
+            let actual = sha256_file(&blob)?;
+            io::Seek::rewind(&mut &blob)?;
+            io::Result::Ok((blob, actual))
+        })
+        .await??;
+
+        if actual == digest {
+            debug!("Using cached artifact {} for {url}", blob_path.display());
+            return Ok(Some(tokio::fs::File::from_std(blob)));
+        }
+
+        warn!("Cached artifact of {url} has sha256 {actual}, expected {digest}, removing it");
+        let _ = tokio::fs::remove_file(&blob_path).await;
+        let _ = tokio::fs::remove_file(&url_path).await;
+
+        Ok(None)
+    }
+
+    /// Open the cached artifact of `url` if any, or else return a
+    /// [`CacheWriter`] to cache it while it is downloaded.
+    ///
+    /// Processes downloading the same artifact concurrently wait for each
+    /// other instead of downloading it twice, since the writer holds a lock
+    /// on `url` until it is dropped.
+    pub(crate) async fn open(&self, url: &Url) -> io::Result<CacheEntry> {
+        // Entries are moved into the cache once fully written, so they can
+        // be read without locking.
+        if let Some(file) = self.open_cached(url).await? {
+            return Ok(CacheEntry::Cached(file));
+        }
+
+        let lock_path = self.url_path(url).with_extension("lock");
+        let lock =
+            spawn_blocking(move || FileLock::new_exclusive(open_lock_file(&lock_path)?)).await??;
+
+        // Another process might have downloaded it while waiting for the lock.
+        if let Some(file) = self.open_cached(url).await? {
+            return Ok(CacheEntry::Cached(file));
+        }
+
+        debug!("Downloading {url} into the artifact cache");
+
+        let tmp = tempfile::NamedTempFile::new_in(&self.dir)?;
+        let file = tokio::fs::File::from_std(tmp.reopen()?);
+        let hasher = Arc::new(Mutex::new(Sha256Verifier::default()));
+
+        Ok(CacheEntry::Missing(CacheWriter {
+            cache: self.clone(),
+            url_path: self.url_path(url),
+            tmp,
+            file,
+            offloaded_hasher: OffloadedVerifier::new(hasher.clone()),
+            hasher,
+            _lock: lock,
+        }))
+    }
+}
+
+/// An artifact looked up in the [`ArtifactCache`].
+pub(crate) enum CacheEntry {
+    Cached(tokio::fs::File),
+    /// The artifact is not cached yet.
+    Missing(CacheWriter),
+}
+
+/// Writes an artifact to a temporary file while it is downloaded, which is
+/// moved into the cache once all of it is written.
+///
+/// Nothing is cached if it is dropped before that, e.g. if the download
+/// fails or is cancelled.
+pub(crate) struct CacheWriter {
+    cache: ArtifactCache,
+    url_path: PathBuf,
+    tmp: tempfile::NamedTempFile,
+    file: tokio::fs::File,
+    hasher: Arc<Mutex<Sha256Verifier>>,
+    offloaded_hasher: OffloadedVerifier,
+    _lock: FileLock,
+}
+
+impl CacheWriter {
+    async fn write(&mut self, bytes: Bytes) -> io::Result<()> {
+        self.file.write_all(&bytes).await?;
+        self.offloaded_hasher.update(bytes).await;
+        Ok(())
+    }
+
+    async fn finish(self) -> io::Result<()> {
+        let Self {
+            cache,
+            url_path,
+            tmp,
+            file,
+            hasher,
+            offloaded_hasher,
+            _lock,
+        } = self;
+
+        file.sync_all().await?;
+        drop(file);
+        offloaded_hasher.finish().await;
+
+        let digest = mem::take(&mut *hasher.lock().unwrap()).finalize_hex();
+        let blob_path = cache.blob_path(&digest);
+
+        // The same artifact might be cached already from another url, the
+        // ones of other users are replaced.
+        let cached = fs::metadata(&blob_path).map_or(false, |metadata| cache.is_trusted(&metadata));
+        if !cached {
+            set_shared_file_permissions(tmp.path())?;
+            tmp.persist(&blob_path).map_err(io::Error::from)?;
+        }
+
+        let url_tmp = tempfile::NamedTempFile::new_in(&cache.dir)?;
+        fs::write(url_tmp.path(), &digest)?;
+        set_shared_file_permissions(url_tmp.path())?;
+        url_tmp.persist(url_path).map_err(io::Error::from)?;
+
+        Ok(())
+    }
+
+    /// Write the data of `stream` of `url` to the cache as it is consumed,
+    /// so that downloading it, e.g. in chunks, and extracting it still
+    /// overlap.
+    ///
+    /// The artifact is cached once `stream` is fully consumed without
+    /// errors. Failing to write to the cache does not fail `stream`.
+    pub(crate) fn cache_stream<S>(
+        self,
+        url: Url,
+        stream: S,
+    ) -> impl Stream<Item = Result<Bytes, DownloadError>> + Send + Sync + Unpin
+    where
+        S: Stream<Item = Result<Bytes, DownloadError>> + Send + Sync + Unpin,
+    {
+        let writer = Arc::new(tokio::sync::Mutex::new(Some(self)));
+        let finish = writer.clone();
+
+        stream
+            .then(move |res| {
+                let writer = writer.clone();
+                let url = url.clone();
+                Box::pin(async move {
+                    let mut guard = writer.lock().await;
+                    match (&res, guard.as_mut()) {
+                        (Ok(bytes), Some(cache_writer)) => {
+                            if let Err(err) = cache_writer.write(bytes.clone()).await {
+                                warn!("Failed to write {url} to the artifact cache: {err}");
+                                *guard = None;
+                            }
+                        }
+                        (Err(_), _) => *guard = None,
+                        (Ok(_), None) => (),
+                    }
+                    res
+                }) as Pin<Box<dyn Future<Output = _> + Send + Sync>>
+            })
+            .chain(
+                stream::once(Box::pin(async move {
+                    let cache_writer = finish.lock().await.take();
+                    if let Some(cache_writer) = cache_writer {
+                        if let Err(err) = cache_writer.finish().await {
+                            warn!("Failed to move the artifact downloaded into the cache: {err}");
+                        }
+                    }
+                })
+                    as Pin<Box<dyn Future<Output = ()> + Send + Sync>>)
+                .filter_map(|()| future::ready(None)),
+            )
+    }
+}
+
+/// Remove all the artifacts cached in `dir`, returning the number of bytes
+/// freed.
+///
+/// Entries of other users which cannot be removed are skipped.
+pub fn clean_artifact_cache(dir: &Path) -> io::Result<u64> {
+    fn remove_all(path: &Path) -> u64 {
+        let metadata = match fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(_) => return 0,
+        };
+
+        if metadata.is_dir() {
+            let freed = fs::read_dir(path)
+                .map(|entries| {
+                    entries
+                        .filter_map(Result::ok)
+                        .map(|entry| remove_all(&entry.path()))
+                        .sum()
+                })
+                .unwrap_or(0);
+            let _ = fs::remove_dir(path);
+            freed
+        } else {
+            match fs::remove_file(path) {
+                Ok(()) => metadata.len(),
+                Err(err) => {
+                    debug!("Failed to remove {}: {err}", path.display());
+                    0
+                }
+            }
+        }
+    }
+
+    let mut freed = 0;
+    for entry in fs::read_dir(dir)? {
+        freed += remove_all(&entry?.path());
+    }
+    Ok(freed)
+}
+
+async fn open_if_exists(path: &Path) -> io::Result<Option<tokio::fs::File>> {
+    match tokio::fs::File::open(path).await {
+        Ok(file) => Ok(Some(file)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Open the lock file, read-only if it is created by another user who
+/// does not let others write to it, which is enough for locking.
+fn open_lock_file(path: &Path) -> io::Result<fs::File> {
+    match fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+    {
+        Ok(file) => {
+            // Let the other users of the cache lock it too.
+            let _ = set_shared_file_permissions(path);
+            Ok(file)
+        }
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => fs::File::open(path),
+        Err(err) => Err(err),
+    }
+}
+
+/// Let the members of the group of the cache directory add entries to it,
+/// with new entries inheriting its group.
+#[cfg(unix)]
+fn set_shared_dir_permissions(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(0o2775))
+}
+
+#[cfg(not(unix))]
+fn set_shared_dir_permissions(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Let the members of the group of the cache directory read the file, but
+/// not modify it.
+#[cfg(unix)]
+fn set_shared_file_permissions(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(0o644))
+}
+
+#[cfg(not(unix))]
+fn set_shared_file_permissions(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::AsyncReadExt;
+
+    use super::*;
+
+    /// Cache `content` as the artifact of `url`, returning its digest.
+    fn cache_artifact(cache: &ArtifactCache, url: &Url, content: &[u8]) -> String {
+        let digest = hex(Sha256::digest(content));
+        fs::write(cache.blob_path(&digest), content).unwrap();
+        fs::write(cache.url_path(url), &digest).unwrap();
+        digest
+    }
+
+    async fn read(file: tokio::fs::File) -> Vec<u8> {
+        let mut content = Vec::new();
+        let mut file = file;
+        file.read_to_end(&mut content).await.unwrap();
+        content
+    }
+
+    async fn open(cache: &ArtifactCache, url: &Url) -> Option<tokio::fs::File> {
+        match cache.open(url).await.unwrap() {
+            CacheEntry::Cached(file) => Some(file),
+            CacheEntry::Missing(_) => None,
+        }
+    }
+
+    async fn cache_stream(
+        cache: &ArtifactCache,
+        url: &Url,
+        chunks: &[&'static [u8]],
+    ) -> Vec<Result<Bytes, DownloadError>> {
+        let CacheEntry::Missing(cache_writer) = cache.open(url).await.unwrap() else {
+            panic!("{url} is cached already");
+        };
+        let stream = stream::iter(chunks.iter().map(|chunk| Ok(Bytes::from_static(chunk))));
+        cache_writer
+            .cache_stream(url.clone(), stream)
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_hit() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ArtifactCache::new(dir.path().join("cache")).unwrap();
+
+        let url = Url::parse("https://example.invalid/a.tgz").unwrap();
+        let other_url = Url::parse("https://example.invalid/b.tgz").unwrap();
+        assert_ne!(cache.url_path(&url), cache.url_path(&other_url));
+
+        // The same artifact from two urls is stored once.
+        let digest = cache_artifact(&cache, &url, b"artifact");
+        assert_eq!(cache_artifact(&cache, &other_url, b"artifact"), digest);
+        assert_eq!(cache.digest(&url).unwrap(), digest);
+
+        for url in [url, other_url] {
+            let file = open(&cache, &url).await.unwrap();
+            assert_eq!(read(file).await, b"artifact");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_miss() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ArtifactCache::new(dir.path().join("cache")).unwrap();
+        let url = Url::parse("https://example.invalid/a.tgz").unwrap();
+
+        assert!(cache.digest(&url).is_none());
+        assert!(cache.open_cached(&url).await.unwrap().is_none());
+
+        // The artifact is downloaded, which is cancelled, leaving nothing
+        // behind but the lock file.
+        assert!(open(&cache, &url).await.is_none());
+        assert!(cache.digest(&url).is_none());
+        assert_eq!(
+            fs::read_dir(dir.path().join("cache/blobs"))
+                .unwrap()
+                .count(),
+            0
+        );
+        // `urls` and `blobs`, without any temporary file.
+        assert_eq!(fs::read_dir(dir.path().join("cache")).unwrap().count(), 2);
+        for entry in fs::read_dir(dir.path().join("cache/urls")).unwrap() {
+            let path = entry.unwrap().path();
+            assert_eq!(path.extension().unwrap(), "lock", "{}", path.display());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ArtifactCache::new(dir.path().join("cache")).unwrap();
+        let url = Url::parse("https://example.invalid/a.tgz").unwrap();
+
+        // Tampered with, truncated and missing artifacts.
+        for content in [Some(&b"tampered"[..]), Some(b"artif"), None] {
+            let digest = cache_artifact(&cache, &url, b"artifact");
+            match content {
+                Some(content) => fs::write(cache.blob_path(&digest), content).unwrap(),
+                None => fs::remove_file(cache.blob_path(&digest)).unwrap(),
+            }
+
+            assert!(cache.open_cached(&url).await.unwrap().is_none());
+            assert!(!cache.url_path(&url).exists());
+            assert!(!cache.blob_path(&digest).exists());
+
+            // It is downloaded again instead.
+            assert!(open(&cache, &url).await.is_none());
+        }
+
+        // Corrupted digest, which must not be used as a path.
+        fs::write(dir.path().join("artifact"), b"artifact").unwrap();
+        fs::write(cache.url_path(&url), "../../artifact").unwrap();
+        assert!(cache.open_cached(&url).await.unwrap().is_none());
+        assert!(!cache.url_path(&url).exists());
+        assert!(dir.path().join("artifact").exists());
+    }
+
+    #[tokio::test]
+    async fn test_cache_stream() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ArtifactCache::new(dir.path().join("cache")).unwrap();
+        let url = Url::parse("https://example.invalid/a.tgz").unwrap();
+
+        // The stream is forwarded as is while it is cached.
+        let chunks = cache_stream(&cache, &url, &[b"arti", b"fact"]).await;
+        assert_eq!(
+            chunks.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+            [&b"arti"[..], b"fact"]
+        );
+
+        assert_eq!(
+            cache.digest(&url).unwrap(),
+            hex(Sha256::digest(b"artifact"))
+        );
+        let file = open(&cache, &url).await.unwrap();
+        assert_eq!(read(file).await, b"artifact");
+    }
+
+    #[tokio::test]
+    async fn test_cache_stream_partially_consumed() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ArtifactCache::new(dir.path().join("cache")).unwrap();
+        let url = Url::parse("https://example.invalid/a.tgz").unwrap();
+
+        let CacheEntry::Missing(cache_writer) = cache.open(&url).await.unwrap() else {
+            panic!("{url} is cached already");
+        };
+        let stream = stream::iter([
+            Ok(Bytes::from_static(b"arti")),
+            Ok(Bytes::from_static(b"fact")),
+        ]);
+        let mut stream = cache_writer.cache_stream(url.clone(), stream);
+        stream.next().await.unwrap().unwrap();
+        drop(stream);
+
+        assert!(cache.digest(&url).is_none());
+        assert!(open(&cache, &url).await.is_none());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_entries_of_other_users() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ArtifactCache::new(dir.path().join("cache")).unwrap();
+        let url = Url::parse("https://example.invalid/a.tgz").unwrap();
+
+        cache_stream(&cache, &url, &[b"artifact"]).await;
+        let digest = cache.digest(&url).unwrap();
+        // The other members of the group can only read them.
+        for path in [cache.url_path(&url), cache.blob_path(&digest)] {
+            let mode = fs::metadata(path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o644);
+        }
+        assert!(open(&cache, &url).await.is_some());
+
+        // The entries of other users are ignored, but not removed.
+        let other = ArtifactCache {
+            uid: cache.uid.wrapping_add(1),
+            ..cache.clone()
+        };
+        assert!(other.open_cached(&url).await.unwrap().is_none());
+        assert!(cache.url_path(&url).exists());
+        assert!(cache.blob_path(&digest).exists());
+    }
+
+    #[test]
+    fn test_clean_artifact_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ArtifactCache::new(dir.path().join("cache")).unwrap();
+        let url = Url::parse("https://example.invalid/a.tgz").unwrap();
+        let digest = cache_artifact(&cache, &url, b"artifact");
+
+        // The artifact and the digest recorded for `url`.
+        assert_eq!(
+            clean_artifact_cache(&dir.path().join("cache")).unwrap(),
+            8 + 64
+        );
+        assert!(!cache.blob_path(&digest).exists());
+        assert!(!cache.url_path(&url).exists());
+    }
+}
@@ -1,21 +1,31 @@
 use std::{
     borrow::Cow,
+    cell::RefCell,
     fs,
     future::Future,
-    io::{self, Write},
+    io::{self, BufRead, Read, Write},
     path::{Component, Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
 };
 
 use async_zip::base::read::stream::ZipFileReader;
 use bytes::{Bytes, BytesMut};
 use futures_util::Stream;
+use sha2::{Digest, Sha256};
 use tokio::sync::mpsc;
 use tokio_util::io::StreamReader;
 use tracing::debug;
 
+#[cfg(unix)]
+use super::zip_extraction::{extract_symlinks, read_symlink_names};
 use super::{
-    extracter::*, zip_extraction::extract_zip_entry, DownloadError, ExtractedFiles, TarBasedFmt,
-    ZipError,
+    deb, dmg,
+    extract_progress::{EntryProgress, ExtractProgress},
+    extracter::*,
+    msi, rpm, seven_zip,
+    zip_extraction::{extract_zip_entry, strip_split_signature},
+    DownloadError, ExtractFilter, ExtractedFiles, TarBasedFmt, ZipError,
 };
 use crate::utils::{extract_with_blocking_task, StreamReadable};
 
@@ -25,58 +35,154 @@ where
 {
     debug!("Writing to `{}`", path.display());
 
-    extract_with_blocking_decoder(stream, path, |mut rx, path| {
+    let sha256 = extract_with_blocking_decoder(stream, path, |mut rx, path| {
         let mut file = fs::File::create(path)?;
+        let mut hasher = Sha256::new();
 
         while let Some(bytes) = rx.blocking_recv() {
+            hasher.update(&bytes);
             file.write_all(&bytes)?;
         }
 
-        file.flush()
+        file.flush()?;
+
+        Ok(hasher.finalize().into())
     })
     .await?;
 
     let mut extracted_files = ExtractedFiles::new();
 
-    extracted_files.add_file(Path::new(path.file_name().unwrap()));
+    extracted_files.add_file(Path::new(path.file_name().unwrap()), Some(sha256));
 
     Ok(extracted_files)
 }
 
-pub async fn extract_zip<S>(stream: S, path: &Path) -> Result<ExtractedFiles, DownloadError>
+pub async fn extract_zip<S>(
+    stream: S,
+    path: &Path,
+    progress: Option<Arc<dyn ExtractProgress>>,
+    filter: Option<ExtractFilter>,
+) -> Result<ExtractedFiles, DownloadError>
 where
     S: Stream<Item = Result<Bytes, DownloadError>> + Unpin + Send + Sync,
 {
     debug!("Decompressing from zip archive to `{}`", path.display());
 
-    let reader = StreamReader::new(stream);
-    let mut zip = ZipFileReader::with_tokio(reader);
+    let mut reader = strip_split_signature(StreamReader::new(stream)).await?;
+    // Borrow `reader` to read the central directory once all the entries
+    // are extracted.
+    let mut zip = ZipFileReader::with_tokio(&mut reader);
     let mut buf = BytesMut::with_capacity(4 * 4096);
     let mut extracted_files = ExtractedFiles::new();
 
     while let Some(mut zip_reader) = zip.next_with_entry().await.map_err(ZipError::from_inner)? {
-        extract_zip_entry(
+        let extracted = extract_zip_entry(
             zip_reader.reader_mut(),
             path,
             &mut buf,
             &mut extracted_files,
+            progress.clone(),
+            filter.as_ref(),
         )
         .await?;
 
-        // extract_zip_entry would read the zip_reader until read the file until
-        // eof unless extract_zip itself is cancelled or an error is raised.
-        //
-        // So calling done here should not raise any error.
-        zip = zip_reader.done().await.map_err(ZipError::from_inner)?;
+        zip = if extracted {
+            // extract_zip_entry would read the zip_reader until read the file until
+            // eof unless extract_zip itself is cancelled or an error is raised.
+            //
+            // So calling done here should not raise any error.
+            zip_reader.done().await
+        } else {
+            zip_reader.skip().await
+        }
+        .map_err(ZipError::from_inner)?;
+    }
+
+    #[cfg(unix)]
+    {
+        let symlink_names = read_symlink_names(&mut reader).await;
+        extract_symlinks(path, symlink_names, &mut extracted_files).await?;
     }
 
     Ok(extracted_files)
 }
 
+pub async fn extract_seven_zip<S>(
+    stream: S,
+    path: &Path,
+    progress: Option<Arc<dyn ExtractProgress>>,
+    filter: Option<ExtractFilter>,
+) -> Result<ExtractedFiles, DownloadError>
+where
+    S: Stream<Item = Result<Bytes, DownloadError>> + Send + Sync + Unpin,
+{
+    debug!("Decompressing from 7z archive to `{}`", path.display());
+
+    extract_with_blocking_decoder(stream, path, move |mut rx, path| {
+        // The header of 7z archives is at their end.
+        let mut archive = tempfile::tempfile()?;
+        while let Some(bytes) = rx.blocking_recv() {
+            archive.write_all(&bytes)?;
+        }
+
+        seven_zip::extract_seven_zip(&mut archive, path, progress, filter)
+    })
+    .await
+}
+
+pub async fn extract_dmg<S>(
+    stream: S,
+    path: &Path,
+    progress: Option<Arc<dyn ExtractProgress>>,
+    filter: Option<ExtractFilter>,
+) -> Result<ExtractedFiles, DownloadError>
+where
+    S: Stream<Item = Result<Bytes, DownloadError>> + Send + Sync + Unpin,
+{
+    debug!("Extracting from dmg image to `{}`", path.display());
+
+    extract_with_blocking_decoder(stream, path, move |mut rx, path| {
+        // hdiutil mounts images from files only.
+        let mut image = tempfile::Builder::new().suffix(".dmg").tempfile()?;
+        while let Some(bytes) = rx.blocking_recv() {
+            image.write_all(&bytes)?;
+        }
+        image.flush()?;
+
+        dmg::extract_dmg(image.path(), path, progress, filter)
+    })
+    .await
+}
+
+pub async fn extract_msi<S>(
+    stream: S,
+    path: &Path,
+    progress: Option<Arc<dyn ExtractProgress>>,
+    filter: Option<ExtractFilter>,
+) -> Result<ExtractedFiles, DownloadError>
+where
+    S: Stream<Item = Result<Bytes, DownloadError>> + Send + Sync + Unpin,
+{
+    debug!("Extracting from msi package to `{}`", path.display());
+
+    extract_with_blocking_decoder(stream, path, move |mut rx, path| {
+        // The streams of compound files are scattered over their sectors.
+        let mut msi = tempfile::tempfile()?;
+        while let Some(bytes) = rx.blocking_recv() {
+            msi.write_all(&bytes)?;
+        }
+
+        msi::extract_msi(&mut msi, path, progress, filter)
+    })
+    .await
+}
+
 pub async fn extract_tar_based_stream<S>(
     stream: S,
     dst: &Path,
     fmt: TarBasedFmt,
+    progress: Option<Arc<dyn ExtractProgress>>,
+    filter: Option<ExtractFilter>,
 ) -> Result<ExtractedFiles, DownloadError>
 where
     S: Stream<Item = Result<Bytes, DownloadError>> + Send + Sync + Unpin,
@@ -84,76 +190,241 @@ where
     debug!("Extracting from {fmt} archive to {}", dst.display());
 
     extract_with_blocking_decoder(stream, dst, move |rx, dst| {
-        // Adapted from https://docs.rs/tar/latest/src/tar/archive.rs.html#189-219
-
-        if dst.symlink_metadata().is_err() {
-            fs::create_dir_all(dst)?;
-        }
-
-        // Canonicalizing the dst directory will prepend the path with '\\?\'
-        // on windows which will allow windows APIs to treat the path as an
-        // extended-length path with a 32,767 character limit. Otherwise all
-        // unpacked paths over 260 characters will fail on creation with a
-        // NotFound exception.
-        let dst = &dst
-            .canonicalize()
-            .map(Cow::Owned)
-            .unwrap_or(Cow::Borrowed(dst));
-
-        let mut tar = create_tar_decoder(StreamReadable::new(rx), fmt)?;
-        let mut entries = tar.entries()?;
-
-        let mut extracted_files = ExtractedFiles::new();
-
-        // Delay any directory entries until the end (they will be created if needed by
-        // descendants), to ensure that directory permissions do not interfer with descendant
-        // extraction.
-        let mut directories = Vec::new();
-
-        while let Some(mut entry) = entries.next().transpose()? {
-            match entry.header().entry_type() {
-                tar::EntryType::Regular => {
-                    // unpack_in returns false if the path contains ".."
-                    // and is skipped.
-                    if entry.unpack_in(dst)? {
-                        let path = entry.path()?;
-
-                        // create normalized_path in the same way
-                        // tar::Entry::unpack_in would normalize the path.
-                        let mut normalized_path = PathBuf::new();
-
-                        for part in path.components() {
-                            match part {
-                                Component::Prefix(..) | Component::RootDir | Component::CurDir => {
-                                    continue
-                                }
-
-                                // unpack_in would return false if this happens.
-                                Component::ParentDir => unreachable!(),
-
-                                Component::Normal(part) => normalized_path.push(part),
-                            }
-                        }
-
-                        extracted_files.add_file(&normalized_path);
+        unpack_tarball(StreamReadable::new(rx), fmt, dst, progress, filter, None)
+    })
+    .await
+}
+
+pub async fn extract_deb<S>(
+    stream: S,
+    dst: &Path,
+    progress: Option<Arc<dyn ExtractProgress>>,
+    filter: Option<ExtractFilter>,
+) -> Result<ExtractedFiles, DownloadError>
+where
+    S: Stream<Item = Result<Bytes, DownloadError>> + Send + Sync + Unpin,
+{
+    debug!("Extracting from deb package to {}", dst.display());
+
+    extract_with_blocking_decoder(stream, dst, move |rx, dst| {
+        let (fmt, data) = deb::data_tarball(StreamReadable::new(rx))?;
+        debug!("Extracting from {fmt} data tarball of the deb package");
+
+        unpack_tarball(data, fmt, dst, progress, filter, Some(deb::map_data_path))
+    })
+    .await
+}
+
+pub async fn extract_rpm<S>(
+    stream: S,
+    dst: &Path,
+    progress: Option<Arc<dyn ExtractProgress>>,
+    filter: Option<ExtractFilter>,
+) -> Result<ExtractedFiles, DownloadError>
+where
+    S: Stream<Item = Result<Bytes, DownloadError>> + Send + Sync + Unpin,
+{
+    debug!("Extracting from rpm package to {}", dst.display());
+
+    extract_with_blocking_decoder(stream, dst, move |rx, dst| {
+        let (fmt, payload) = rpm::payload(StreamReadable::new(rx))?;
+        debug!("Extracting from cpio payload of the rpm package, compressed like {fmt}");
+
+        // The payload is compressed like tarballs, so their decoders
+        // decompress it.
+        rpm::extract_cpio(create_tar_decoder(payload, fmt)?, dst, progress, filter)
+    })
+    .await
+}
+
+/// Unpack the tarball `dat` of format `fmt` into `dst`.
+///
+/// If `map_path` is `Some`, only the regular files whose normalized path
+/// it maps are extracted, to the path returned, and directories are only
+/// created as needed.
+fn unpack_tarball(
+    dat: impl BufRead + 'static,
+    fmt: TarBasedFmt,
+    dst: &Path,
+    progress: Option<Arc<dyn ExtractProgress>>,
+    filter: Option<ExtractFilter>,
+    map_path: Option<fn(&Path) -> Option<&Path>>,
+) -> io::Result<ExtractedFiles> {
+    // Adapted from https://docs.rs/tar/latest/src/tar/archive.rs.html#189-219
+
+    if dst.symlink_metadata().is_err() {
+        fs::create_dir_all(dst)?;
+    }
+
+    // Canonicalizing the dst directory will prepend the path with '\\?\'
+    // on windows which will allow windows APIs to treat the path as an
+    // extended-length path with a 32,767 character limit. Otherwise all
+    // unpacked paths over 260 characters will fail on creation with a
+    // NotFound exception.
+    let dst = &dst
+        .canonicalize()
+        .map(Cow::Owned)
+        .unwrap_or(Cow::Borrowed(dst));
+
+    // The entry being extracted, to hash its content and report its
+    // progress.
+    let current_entry = Rc::new(RefCell::new(None));
+
+    let mut tar = tar::Archive::new(EntryReader {
+        inner: create_tar_decoder(dat, fmt)?,
+        entry: current_entry.clone(),
+    });
+    let mut entries = tar.entries()?;
+
+    let mut extracted_files = ExtractedFiles::new();
+
+    // Delay any directory entries until the end (they will be created if needed by
+    // descendants), to ensure that directory permissions do not interfer with descendant
+    // extraction.
+    let mut directories = Vec::new();
+
+    while let Some(mut entry) = entries.next().transpose()? {
+        match entry.header().entry_type() {
+            tar::EntryType::Regular => {
+                // unpack_in skips the entry if the path contains "..".
+                let Some(mut normalized_path) = normalize_tar_path(&entry.path()?) else {
+                    continue;
+                };
+                if let Some(map_path) = map_path {
+                    match map_path(&normalized_path) {
+                        Some(path) => normalized_path = path.to_owned(),
+                        None => continue,
                     }
                 }
-                tar::EntryType::Directory => {
-                    directories.push(entry);
+                if let Some(filter) = &filter {
+                    if !filter(&normalized_path) {
+                        continue;
+                    }
+                }
+
+                let size = entry.size();
+                let entry_progress = match &progress {
+                    Some(progress) => Some(EntryProgress::new(
+                        progress.clone(),
+                        entry.path()?.into_owned(),
+                        size,
+                    )),
+                    None => None,
+                };
+                *current_entry.borrow_mut() = Some(CurrentEntry::new(entry_progress));
+
+                let unpacked = if map_path.is_some() {
+                    unpack_to(&mut entry, &dst.join(&normalized_path)).map(|()| true)
+                } else {
+                    entry.unpack_in(dst)
+                };
+
+                // Report the entry as done.
+                let sha256 = current_entry
+                    .borrow_mut()
+                    .take()
+                    .and_then(|current_entry| current_entry.finish(size));
+
+                if unpacked? {
+                    extracted_files.add_file(&normalized_path, sha256);
+                }
+            }
+            tar::EntryType::Directory if map_path.is_none() => {
+                if let Some(filter) = &filter {
+                    match normalize_tar_path(&entry.path()?) {
+                        Some(path) if filter(&path) => (),
+                        _ => continue,
+                    }
                 }
-                _ => (),
+                directories.push(entry);
             }
+            _ => (),
         }
+    }
 
-        for mut dir in directories {
-            if dir.unpack_in(dst)? {
-                extracted_files.add_dir(&dir.path()?);
-            }
+    for mut dir in directories {
+        if dir.unpack_in(dst)? {
+            extracted_files.add_dir(&dir.path()?);
         }
+    }
 
-        Ok(extracted_files)
-    })
-    .await
+    Ok(extracted_files)
+}
+
+/// Unpack the regular file `entry` to `path`, creating its parent
+/// directories if needed.
+fn unpack_to<R: Read>(entry: &mut tar::Entry<'_, R>, path: &Path) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    entry.unpack(path).map(drop)
+}
+
+/// Normalize `path` in the same way [`tar::Entry::unpack_in`] does, or
+/// return `None` if it contains "..", in which case the entry is skipped.
+pub(super) fn normalize_tar_path(path: &Path) -> Option<PathBuf> {
+    let mut normalized_path = PathBuf::new();
+
+    for part in path.components() {
+        match part {
+            Component::Prefix(..) | Component::RootDir | Component::CurDir => continue,
+            Component::ParentDir => return None,
+            Component::Normal(part) => normalized_path.push(part),
+        }
+    }
+
+    Some(normalized_path)
+}
+
+/// The regular entry being unpacked from a tarball.
+///
+/// The bytes read from the archive while unpacking it are its content, so
+/// they are hashed and reported as they pass through [`EntryReader`],
+/// without being copied nor read again once written.
+struct CurrentEntry {
+    progress: Option<EntryProgress>,
+    hasher: Sha256,
+    read: u64,
+}
+
+impl CurrentEntry {
+    fn new(progress: Option<EntryProgress>) -> Self {
+        Self {
+            progress,
+            hasher: Sha256::new(),
+            read: 0,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+        self.read += data.len() as u64;
+        if let Some(progress) = &mut self.progress {
+            progress.advance(data.len() as u64);
+        }
+    }
+
+    /// Return the digest of the entry, unless fewer or more than its `size`
+    /// bytes were read, e.g. if unpacking it failed or was skipped.
+    fn finish(self, size: u64) -> Option<[u8; 32]> {
+        (self.read == size).then(|| self.hasher.finalize().into())
+    }
+}
+
+/// Reader feeding the bytes read into the [`CurrentEntry`], if any.
+struct EntryReader<R> {
+    inner: R,
+    entry: Rc<RefCell<Option<CurrentEntry>>>,
+}
+
+impl<R: Read> Read for EntryReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(entry) = self.entry.borrow_mut().as_mut() {
+            entry.update(&buf[..n]);
+        }
+        Ok(n)
+    }
 }
 
 fn extract_with_blocking_decoder<S, F, T>(
@@ -176,3 +447,159 @@ where
         f(rx, &path)
     })
 }
+
+#[cfg(test)]
+mod test {
+    use futures_util::stream;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_extract_tar_digests() {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, content) in [("bin/a", &b"aaa"[..]), ("b", &[7; 1000][..])] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append_data(&mut header, path, content).unwrap();
+        }
+        let tarball = Bytes::from(builder.into_inner().unwrap());
+
+        // Split the tarball so that entries span multiple chunks.
+        let chunks: Vec<_> = tarball
+            .chunks(100)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect();
+
+        let dir = tempfile::tempdir().unwrap();
+        let extracted_files = extract_tar_based_stream(
+            stream::iter(chunks),
+            dir.path(),
+            TarBasedFmt::Tar,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+        for path in ["bin/a", "b"] {
+            let content = fs::read(dir.path().join(path)).unwrap();
+            let sha256: [u8; 32] = Sha256::digest(content).into();
+            assert_eq!(extracted_files.sha256(Path::new(path)), Some(&sha256));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_extract_with_filter() {
+        let filter: ExtractFilter = Arc::new(|path: &Path| !path.starts_with("doc"));
+        let entries = [("bin/a", &b"aaa"[..]), ("doc/README", &b"read me"[..])];
+
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, content) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, content).unwrap();
+        }
+        let tarball = Bytes::from(builder.into_inner().unwrap());
+
+        let mut zip = async_zip::base::write::ZipFileWriter::new(Vec::new());
+        for (path, content) in entries {
+            let entry =
+                async_zip::ZipEntryBuilder::new(path.into(), async_zip::Compression::Stored);
+            zip.write_entry_whole(entry, content).await.unwrap();
+        }
+        let zip = Bytes::from(zip.close().await.unwrap());
+
+        let tar_dir = tempfile::tempdir().unwrap();
+        let zip_dir = tempfile::tempdir().unwrap();
+        let extracted = [
+            extract_tar_based_stream(
+                stream::iter([Ok(tarball)]),
+                tar_dir.path(),
+                TarBasedFmt::Tar,
+                None,
+                Some(filter.clone()),
+            )
+            .await
+            .unwrap(),
+            extract_zip(stream::iter([Ok(zip)]), zip_dir.path(), None, Some(filter))
+                .await
+                .unwrap(),
+        ];
+
+        for (dir, extracted_files) in [tar_dir.path(), zip_dir.path()].into_iter().zip(extracted) {
+            assert_eq!(fs::read(dir.join("bin/a")).unwrap(), b"aaa");
+            assert!(extracted_files.has_file(Path::new("bin/a")));
+
+            assert!(!dir.join("doc").exists());
+            assert!(extracted_files.get_entry(Path::new("doc")).is_none());
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_extract_zip_symlinks() {
+        let mut zip = async_zip::base::write::ZipFileWriter::new(Vec::new());
+        for (path, content, mode) in [
+            ("bin/foo", &b"foo"[..], 0o100755),
+            ("bin/link", &b"foo"[..], 0o120777),
+            ("bin/escape", &b"../../foo"[..], 0o120777),
+        ] {
+            let entry =
+                async_zip::ZipEntryBuilder::new(path.into(), async_zip::Compression::Stored)
+                    .attribute_compatibility(async_zip::AttributeCompatibility::Unix)
+                    .unix_permissions(mode);
+            zip.write_entry_whole(entry, content).await.unwrap();
+        }
+        let zip = Bytes::from(zip.close().await.unwrap());
+
+        let dir = tempfile::tempdir().unwrap();
+        let extracted_files = extract_zip(stream::iter([Ok(zip)]), dir.path(), None, None)
+            .await
+            .unwrap();
+
+        let link = dir.path().join("bin/link");
+        assert_eq!(fs::read_link(&link).unwrap(), Path::new("foo"));
+        assert_eq!(fs::read(&link).unwrap(), b"foo");
+        assert_eq!(
+            extracted_files.get_symlink(Path::new("bin/link")),
+            Some(Path::new("foo"))
+        );
+        assert!(!extracted_files.has_file(Path::new("bin/link")));
+        assert!(extracted_files.has_file(Path::new("bin/foo")));
+
+        // Symlinks pointing outside of the extraction root are kept as
+        // regular files.
+        let escape = dir.path().join("bin/escape");
+        assert!(escape.symlink_metadata().unwrap().is_file());
+        assert!(extracted_files.has_file(Path::new("bin/escape")));
+    }
+
+    #[tokio::test]
+    async fn test_extract_split_zip() {
+        let mut zip = async_zip::base::write::ZipFileWriter::new(Vec::new());
+        for (path, content) in [("bin/foo", &b"foo"[..]), ("README", b"readme")] {
+            let entry =
+                async_zip::ZipEntryBuilder::new(path.into(), async_zip::Compression::Deflate);
+            zip.write_entry_whole(entry, content).await.unwrap();
+        }
+        // The parts concatenated, starting with the split signature.
+        let zip = [&b"PK\x07\x08"[..], &zip.close().await.unwrap()].concat();
+
+        let dir = tempfile::tempdir().unwrap();
+        let chunks = zip
+            .chunks(3)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect::<Vec<_>>();
+        let extracted_files = extract_zip(stream::iter(chunks), dir.path(), None, None)
+            .await
+            .unwrap();
+
+        assert!(extracted_files.has_file(Path::new("bin/foo")));
+        assert_eq!(fs::read(dir.path().join("bin/foo")).unwrap(), b"foo");
+        assert_eq!(fs::read(dir.path().join("README")).unwrap(), b"readme");
+    }
+}
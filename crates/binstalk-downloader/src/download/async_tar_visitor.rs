@@ -1,14 +1,23 @@
-use std::{borrow::Cow, fmt::Debug, io, path::Path, pin::Pin};
+use std::{
+    borrow::Cow,
+    fmt::Debug,
+    io,
+    path::Path,
+    pin::Pin,
+    task::{Context, Poll},
+};
 
 use async_compression::tokio::bufread;
 use bytes::Bytes;
 use futures_util::{Stream, StreamExt};
-use tokio::io::{copy, sink, AsyncRead};
+use tokio::io::{copy, sink, AsyncRead, ReadBuf};
 use tokio_tar::{Archive, Entry, EntryType};
 use tokio_util::io::StreamReader;
 use tracing::debug;
 
 use super::{
+    lz4::decode_lz4_stream,
+    spooled::DEFAULT_VISIT_MEMORY_LIMIT,
     DownloadError,
     TarBasedFmt::{self, *},
 };
@@ -26,6 +35,12 @@ pub trait TarEntry: AsyncRead + Send + Sync + Unpin + Debug {
     fn size(&self) -> io::Result<u64>;
 
     fn entry_type(&self) -> TarEntryType;
+
+    /// Maximum number of bytes of the entry a visitor should keep in
+    /// memory, see [`spool`](super::spool).
+    fn memory_limit(&self) -> usize {
+        DEFAULT_VISIT_MEMORY_LIMIT
+    }
 }
 
 impl<T: TarEntry + ?Sized> TarEntry for &mut T {
@@ -40,6 +55,46 @@ impl<T: TarEntry + ?Sized> TarEntry for &mut T {
     fn entry_type(&self) -> TarEntryType {
         T::entry_type(self)
     }
+
+    fn memory_limit(&self) -> usize {
+        T::memory_limit(self)
+    }
+}
+
+/// Entry passed to the visitor, along with the memory limit of the
+/// [`Download`](super::Download).
+#[derive(Debug)]
+struct VisitedEntry<'a> {
+    entry: &'a mut dyn TarEntry,
+    memory_limit: usize,
+}
+
+impl AsyncRead for VisitedEntry<'_> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.entry).poll_read(cx, buf)
+    }
+}
+
+impl TarEntry for VisitedEntry<'_> {
+    fn path(&self) -> io::Result<Cow<'_, Path>> {
+        self.entry.path()
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        self.entry.size()
+    }
+
+    fn entry_type(&self) -> TarEntryType {
+        self.entry.entry_type()
+    }
+
+    fn memory_limit(&self) -> usize {
+        self.memory_limit
+    }
 }
 
 impl<R: AsyncRead + Unpin + Send + Sync> TarEntry for Entry<R> {
@@ -92,6 +147,7 @@ pub(crate) async fn extract_tar_based_stream_and_visit<S>(
     stream: S,
     fmt: TarBasedFmt,
     visitor: &mut dyn TarEntriesVisitor,
+    memory_limit: usize,
 ) -> Result<(), DownloadError>
 where
     S: Stream<Item = Result<Bytes, DownloadError>> + Send + Sync,
@@ -105,6 +161,8 @@ where
         Tgz => Box::pin(bufread::GzipDecoder::new(reader)),
         Txz => Box::pin(bufread::XzDecoder::new(reader)),
         Tzstd => Box::pin(bufread::ZstdDecoder::new(reader)),
+        Tlz4 => Box::pin(StreamReader::new(decode_lz4_stream(reader.into_inner()))),
+        Tbr => Box::pin(bufread::BrotliDecoder::new(reader)),
     };
 
     let mut tar = Archive::new(decoder);
@@ -114,7 +172,12 @@ where
 
     while let Some(res) = entries.next().await {
         let mut entry = res?;
-        visitor.visit(&mut entry).await?;
+        visitor
+            .visit(&mut VisitedEntry {
+                entry: &mut entry,
+                memory_limit,
+            })
+            .await?;
 
         // Consume all remaining data so that next iteration would work fine
         // instead of reading the data of prevoius entry.
@@ -0,0 +1,178 @@
+//! [`DataVerifier`]s computing the checksum of the data downloaded, to
+//! compare it against the one published for the artifact.
+
+use std::fmt::Write as _;
+
+use bytes::Bytes;
+use compact_str::CompactString;
+use sha2::{Digest, Sha256, Sha512};
+use thiserror::Error as ThisError;
+
+use super::DataVerifier;
+
+/// The checksum of the data does not match the one expected.
+#[derive(Debug, ThisError)]
+#[error("{algorithm} checksum mismatch: expected {expected}, got {actual}")]
+pub struct ChecksumMismatch {
+    /// Name of the algorithm, e.g. `sha256`.
+    pub algorithm: &'static str,
+    pub expected: CompactString,
+    pub actual: CompactString,
+}
+
+/// Encode `digest` as lowercase hex.
+pub fn hex(digest: impl AsRef<[u8]>) -> String {
+    let digest = digest.as_ref();
+    let mut hex = String::with_capacity(digest.len() * 2);
+    for byte in digest {
+        write!(hex, "{byte:02x}").unwrap();
+    }
+    hex
+}
+
+macro_rules! impl_verifier {
+    ($verifier:ident, $algorithm:literal) => {
+        impl $verifier {
+            pub fn new() -> Self {
+                Self::default()
+            }
+
+            /// Return the hex encoded digest of the data.
+            pub fn finalize_hex(self) -> String {
+                hex(self.finalize())
+            }
+
+            /// Check that the digest of the data is the hex encoded
+            /// `expected`, case insensitively.
+            pub fn verify_hex(self, expected: &str) -> Result<(), ChecksumMismatch> {
+                let expected = expected.trim();
+                let actual = self.finalize_hex();
+
+                if actual.eq_ignore_ascii_case(expected) {
+                    Ok(())
+                } else {
+                    Err(ChecksumMismatch {
+                        algorithm: $algorithm,
+                        expected: expected.into(),
+                        actual: actual.into(),
+                    })
+                }
+            }
+        }
+
+        impl DataVerifier for $verifier {
+            fn update(&mut self, data: &Bytes) {
+                self.0.update(data);
+            }
+        }
+    };
+}
+
+/// Computes the sha256 digest of the data.
+#[derive(Clone, Default)]
+pub struct Sha256Verifier(Sha256);
+
+impl Sha256Verifier {
+    pub fn finalize(self) -> [u8; 32] {
+        self.0.finalize().into()
+    }
+}
+
+impl_verifier!(Sha256Verifier, "sha256");
+
+/// Computes the sha512 digest of the data.
+#[derive(Clone, Default)]
+pub struct Sha512Verifier(Sha512);
+
+impl Sha512Verifier {
+    pub fn finalize(self) -> [u8; 64] {
+        self.0.finalize().into()
+    }
+}
+
+impl_verifier!(Sha512Verifier, "sha512");
+
+/// Computes the BLAKE3 hash of the data.
+#[derive(Clone, Default)]
+pub struct Blake3Verifier(blake3::Hasher);
+
+impl Blake3Verifier {
+    pub fn finalize(self) -> [u8; 32] {
+        self.0.finalize().into()
+    }
+}
+
+impl_verifier!(Blake3Verifier, "blake3");
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn update(verifier: &mut dyn DataVerifier, data: &'static [u8], chunk_len: usize) {
+        for chunk in data.chunks(chunk_len) {
+            verifier.update(&Bytes::from_static(chunk));
+        }
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(hex([]), "");
+        assert_eq!(hex([0x00, 0x0f, 0xab, 0xff]), "000fabff");
+    }
+
+    #[test]
+    fn test_known_digests() {
+        let mut verifier = Sha256Verifier::new();
+        update(&mut verifier, b"abc", 1);
+        verifier
+            .verify_hex("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+            .unwrap();
+
+        let mut verifier = Sha512Verifier::new();
+        update(&mut verifier, b"abc", 2);
+        assert_eq!(
+            verifier.finalize_hex(),
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+             2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+        );
+
+        assert_eq!(
+            Blake3Verifier::new().finalize_hex(),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+
+        let mut verifier = Blake3Verifier::new();
+        update(&mut verifier, b"abc", 3);
+        verifier
+            .verify_hex("6437B3AC38465133FFB63B75273A8DB548C558465D79DB03FD359C6CD5BD9D85")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_blake3_chunks() {
+        // Spans several chunks of 1024 bytes, so that parent nodes are
+        // hashed.
+        let data: &'static [u8] = Vec::from_iter((0..5000).map(|i| (i % 251) as u8)).leak();
+
+        let mut whole = Blake3Verifier::new();
+        update(&mut whole, data, data.len());
+        let whole = whole.finalize();
+
+        for chunk_len in [1, 63, 64, 1023, 1024, 1025] {
+            let mut verifier = Blake3Verifier::new();
+            update(&mut verifier, data, chunk_len);
+            assert_eq!(verifier.finalize(), whole, "chunk_len = {chunk_len}");
+        }
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let mut verifier = Sha256Verifier::new();
+        update(&mut verifier, b"abc", 3);
+
+        let err = verifier.verify_hex(&"0".repeat(64)).unwrap_err();
+        assert_eq!(err.algorithm, "sha256");
+        assert_eq!(err.expected, "0".repeat(64));
+        assert!(err.actual.starts_with("ba7816bf"));
+    }
+}
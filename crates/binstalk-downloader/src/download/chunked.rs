@@ -0,0 +1,318 @@
+//! Download large artifacts as several ranged chunks fetched concurrently,
+//! see [`Client::with_download_chunks`].
+
+use std::{
+    future::Future,
+    io,
+    num::NonZeroU8,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use futures_util::{ready, stream, Stream, StreamExt, TryStreamExt};
+use tokio::{
+    io::{AsyncSeekExt, AsyncWriteExt},
+    task::JoinHandle,
+};
+use tokio_util::io::ReaderStream;
+use tracing::debug;
+
+use super::DownloadError;
+use crate::remote::{
+    header::{ACCEPT_RANGES, CONTENT_LENGTH, ETAG, IF_RANGE, LAST_MODIFIED, RANGE},
+    Client, Method, StatusCode, Url,
+};
+
+/// Artifacts are not split into chunks smaller than this, since the extra
+/// requests would not pay off.
+const MIN_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Split `len` bytes into at most `chunks` inclusive ranges of at least
+/// [`MIN_CHUNK_SIZE`] bytes.
+///
+/// `len` must be at least [`MIN_CHUNK_SIZE`].
+fn chunk_ranges(len: u64, chunks: NonZeroU8) -> Vec<(u64, u64)> {
+    debug_assert!(len >= MIN_CHUNK_SIZE);
+
+    let count = (len / MIN_CHUNK_SIZE).clamp(1, chunks.get().into());
+    let chunk_size = len / count;
+
+    (0..count)
+        .map(|i| {
+            let start = i * chunk_size;
+            let end = if i + 1 == count {
+                len - 1
+            } else {
+                start + chunk_size - 1
+            };
+            (start, end)
+        })
+        .collect()
+}
+
+/// Return the length of `url` and the value of the `If-Range` header to
+/// send along the range requests, so that they fail if `url` changes in
+/// the meantime, if its server supports range requests.
+async fn ranged_len(client: &Client, url: &Url) -> Result<Option<(u64, String)>, DownloadError> {
+    let response = client.request(Method::HEAD, url.clone()).send(true).await?;
+    let headers = response.headers();
+
+    if headers.get(ACCEPT_RANGES).map(|value| value.as_bytes()) != Some(b"bytes") {
+        return Ok(None);
+    }
+
+    let Some(len) = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse().ok())
+    else {
+        return Ok(None);
+    };
+
+    // `If-Range` only accepts strong ETags, or else a date.
+    let if_range = headers
+        .get(ETAG)
+        .filter(|etag| !etag.as_bytes().starts_with(b"W/"))
+        .or_else(|| headers.get(LAST_MODIFIED))
+        .and_then(|value| value.to_str().ok());
+
+    Ok(if_range.map(|if_range| (len, if_range.to_owned())))
+}
+
+async fn get_range(
+    client: &Client,
+    url: Url,
+    if_range: &str,
+    range: (u64, u64),
+) -> Result<CheckLen<impl Stream<Item = Result<Bytes, DownloadError>>>, DownloadError> {
+    let (start, end) = range;
+    let response = client
+        .get(url.clone())
+        .header(RANGE.as_str(), &format!("bytes={start}-{end}"))
+        .header(IF_RANGE.as_str(), if_range)
+        .send(true)
+        .await?;
+
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{url} changed or ignored the range request of bytes {start}-{end}"),
+        )
+        .into());
+    }
+
+    Ok(CheckLen {
+        stream: response
+            .bytes_stream()
+            .map(|res| res.map_err(DownloadError::from)),
+        url,
+        range,
+        len: 0,
+        done: false,
+    })
+}
+
+/// Stream of a range, which fails at its end if it did not receive the
+/// length of the range.
+struct CheckLen<S> {
+    stream: S,
+    url: Url,
+    range: (u64, u64),
+    len: u64,
+    done: bool,
+}
+
+impl<S> Stream for CheckLen<S>
+where
+    S: Stream<Item = Result<Bytes, DownloadError>> + Unpin,
+{
+    type Item = Result<Bytes, DownloadError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+
+        let res = match ready!(self.stream.poll_next_unpin(cx)) {
+            Some(Ok(bytes)) => {
+                self.len += bytes.len() as u64;
+                Some(Ok(bytes))
+            }
+            Some(Err(err)) => {
+                self.done = true;
+                Some(Err(err))
+            }
+            None => {
+                self.done = true;
+                let (start, end) = self.range;
+                (self.len != end - start + 1).then(|| {
+                    Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!(
+                            "Received {} bytes for bytes {start}-{end} of {}",
+                            self.len, self.url
+                        ),
+                    )
+                    .into())
+                })
+            }
+        };
+
+        Poll::Ready(res)
+    }
+}
+
+/// Download the range of `url` into a temporary file.
+async fn download_chunk(
+    client: Client,
+    url: Url,
+    if_range: String,
+    range: (u64, u64),
+) -> Result<tokio::fs::File, DownloadError> {
+    let mut stream = get_range(&client, url, &if_range, range).await?;
+    let mut file = tokio::fs::File::from_std(tempfile::tempfile()?);
+
+    while let Some(bytes) = stream.next().await {
+        file.write_all(&bytes?).await?;
+    }
+
+    file.rewind().await?;
+    Ok(file)
+}
+
+/// Abort the task once dropped, e.g. if the download is cancelled.
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl<T> Future for AbortOnDrop<T> {
+    type Output = Result<T, io::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0).poll(cx).map_err(io::Error::from)
+    }
+}
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Stream the content of `url` downloaded in `chunks` concurrent ranged
+/// requests along with its length, or return `None` if it is too small or
+/// its server does not support range requests, or does not return a
+/// validator to check that the ranges are of the same content.
+///
+/// The first chunk is streamed as it is received so that the extraction can
+/// start right away, while the following ones are downloaded into temporary
+/// files and streamed in order once the previous ones are consumed.
+pub(super) async fn get_chunked_stream(
+    client: &Client,
+    url: &Url,
+    chunks: NonZeroU8,
+) -> Result<
+    Option<(
+        u64,
+        impl Stream<Item = Result<Bytes, DownloadError>> + Send + Sync + Unpin,
+    )>,
+    DownloadError,
+> {
+    let (len, if_range) = match ranged_len(client, url).await {
+        Ok(Some(res)) => res,
+        Ok(None) => return Ok(None),
+        Err(err) => {
+            debug!("Failed to check whether {url} supports range requests: {err}");
+            return Ok(None);
+        }
+    };
+
+    if len < 2 * MIN_CHUNK_SIZE {
+        return Ok(None);
+    }
+
+    let ranges = chunk_ranges(len, chunks);
+    if ranges.len() < 2 {
+        return Ok(None);
+    }
+
+    debug!("Downloading {url} in {} chunks", ranges.len());
+
+    let handles: Vec<_> = ranges[1..]
+        .iter()
+        .map(|range| {
+            AbortOnDrop(tokio::spawn(download_chunk(
+                client.clone(),
+                url.clone(),
+                if_range.clone(),
+                *range,
+            )))
+        })
+        .collect();
+
+    let first = get_range(client, url.clone(), &if_range, ranges[0]).await?;
+
+    let rest = stream::iter(handles)
+        .then(|handle| handle)
+        .map(|res| res?)
+        .map_ok(|file| ReaderStream::new(file).map_err(DownloadError::from))
+        .try_flatten();
+
+    Ok(Some((len, first.chain(rest))))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_chunk_ranges() {
+        let chunks = NonZeroU8::new(4).unwrap();
+
+        assert_eq!(
+            chunk_ranges(MIN_CHUNK_SIZE, chunks),
+            [(0, MIN_CHUNK_SIZE - 1)]
+        );
+
+        let len = 3 * MIN_CHUNK_SIZE + 1;
+        let ranges = chunk_ranges(len, chunks);
+        assert_eq!(ranges.len(), 3);
+        assert_eq!(ranges[0].0, 0);
+        assert_eq!(ranges[2].1, len - 1);
+        for window in ranges.windows(2) {
+            assert_eq!(window[0].1 + 1, window[1].0);
+        }
+
+        assert_eq!(chunk_ranges(100 * MIN_CHUNK_SIZE, chunks).len(), 4);
+    }
+
+    async fn check_len(chunks: &[&'static [u8]], range: (u64, u64)) -> Vec<Result<Bytes, String>> {
+        let stream = CheckLen {
+            stream: stream::iter(chunks.iter().map(|chunk| Ok(Bytes::from_static(chunk)))),
+            url: Url::parse("https://example.com/foo.tgz").unwrap(),
+            range,
+            len: 0,
+            done: false,
+        };
+        stream.map_err(|err| err.to_string()).collect().await
+    }
+
+    #[tokio::test]
+    async fn test_check_len() {
+        assert_eq!(
+            check_len(&[b"abc", b"de"], (10, 14)).await,
+            [
+                Ok(Bytes::from_static(b"abc")),
+                Ok(Bytes::from_static(b"de"))
+            ]
+        );
+
+        let res = check_len(&[b"abc"], (10, 14)).await;
+        assert_eq!(res.len(), 2);
+        assert!(res[1]
+            .as_ref()
+            .unwrap_err()
+            .contains("Received 3 bytes for bytes 10-14"));
+
+        let res = check_len(&[b"abc", b"def"], (10, 14)).await;
+        assert!(res[2].is_err());
+    }
+}
@@ -0,0 +1,230 @@
+//! Extraction of Debian packages.
+//!
+//! A `.deb` is an ar archive containing `debian-binary`, the
+//! `control.tar.*` tarball of the metadata of the package and the
+//! `data.tar.*` tarball of its files. Only the latter is extracted, and
+//! only its `usr/bin` directory, whose files are mapped to the root of the
+//! destination so that the default `bin-dir` finds them.
+
+use std::{
+    io::{self, BufRead, Read, Take},
+    path::Path,
+};
+
+use binstalk_types::cargo_toml_binstall::{PkgFmt, PkgFmtDecomposed};
+
+use super::TarBasedFmt;
+
+/// Global header of ar archives.
+const AR_MAGIC: &[u8] = b"!<arch>\n";
+
+/// Directory of the data tarball which is extracted.
+const BIN_DIR: &str = "usr/bin";
+
+/// Length of the header of each member of ar archives.
+const MEMBER_HEADER_LEN: usize = 60;
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Header of a member of an ar archive.
+struct MemberHeader {
+    name: String,
+    size: u64,
+}
+
+impl MemberHeader {
+    /// Read the header of the next member, return `None` at the end of
+    /// the archive.
+    fn read(ar: &mut impl Read) -> io::Result<Option<Self>> {
+        let mut header = [0; MEMBER_HEADER_LEN];
+
+        // Detect the end of the archive, which can only be before a header.
+        let mut read = 0;
+        while read < header.len() {
+            match ar.read(&mut header[read..]) {
+                Ok(0) if read == 0 => return Ok(None),
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => read += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err),
+            }
+        }
+
+        if &header[58..] != b"`\n" {
+            return Err(invalid("invalid ar member header"));
+        }
+
+        let field = |range: std::ops::Range<usize>| {
+            std::str::from_utf8(&header[range])
+                .map(str::trim_end)
+                .map_err(|_| invalid("invalid ar member header"))
+        };
+
+        // GNU ar terminates names with `/`.
+        let name = field(0..16)?;
+        let name = name.strip_suffix('/').unwrap_or(name).to_owned();
+        let size = field(48..58)?
+            .parse()
+            .map_err(|_| invalid(format!("invalid size of ar member {name}")))?;
+
+        Ok(Some(Self { name, size }))
+    }
+}
+
+/// Skip the members of the ar archive `ar` up to the data tarball, and
+/// return its format along with a reader of it.
+pub(super) fn data_tarball<R: BufRead>(mut ar: R) -> io::Result<(TarBasedFmt, Take<R>)> {
+    let mut magic = [0; AR_MAGIC.len()];
+    ar.read_exact(&mut magic)?;
+    if magic != AR_MAGIC {
+        return Err(invalid("not a deb package"));
+    }
+
+    while let Some(MemberHeader { name, size }) = MemberHeader::read(&mut ar)? {
+        if name.starts_with("data.tar") {
+            return match PkgFmt::guess_pkg_format(&name).map(PkgFmt::decompose) {
+                Some(PkgFmtDecomposed::Tar(fmt)) => Ok((fmt, ar.take(size))),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("unsupported data tarball {name} in deb package"),
+                )),
+            };
+        }
+
+        // Members are aligned to 2 bytes.
+        let padded_size = size + (size & 1);
+        let skipped = io::copy(&mut (&mut ar).take(padded_size), &mut io::sink())?;
+        if skipped != padded_size {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+    }
+
+    Err(invalid("missing data tarball in deb package"))
+}
+
+/// Map the path of an entry of the data tarball to the path to extract it
+/// to, or return `None` if it is not in [`BIN_DIR`].
+pub(super) fn map_data_path(path: &Path) -> Option<&Path> {
+    path.strip_prefix(BIN_DIR)
+        .ok()
+        .filter(|path| path.file_name().is_some())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::download::extract_file;
+
+    fn ar(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut ar = AR_MAGIC.to_vec();
+        for (name, data) in members {
+            ar.extend_from_slice(
+                format!(
+                    "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+                    format!("{name}/"),
+                    0,
+                    0,
+                    0,
+                    100644,
+                    data.len()
+                )
+                .as_bytes(),
+            );
+            ar.extend_from_slice(data);
+            if data.len() % 2 == 1 {
+                ar.push(b'\n');
+            }
+        }
+        ar
+    }
+
+    #[test]
+    fn test_data_tarball() {
+        let deb = ar(&[
+            ("debian-binary", b"2.0\n"),
+            ("control.tar.gz", b"odd"),
+            ("data.tar.xz", b"data"),
+        ]);
+        let (fmt, mut data) = data_tarball(&deb[..]).unwrap();
+        assert_eq!(fmt, TarBasedFmt::Txz);
+        let mut buf = Vec::new();
+        data.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"data");
+
+        let deb = ar(&[("debian-binary", b"2.0\n"), ("data.tar.lzma", b"")]);
+        assert_eq!(
+            data_tarball(&deb[..]).unwrap_err().kind(),
+            io::ErrorKind::Unsupported
+        );
+
+        let deb = ar(&[("debian-binary", b"2.0\n")]);
+        assert_eq!(
+            data_tarball(&deb[..]).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        assert!(data_tarball(&b"PK\x03\x04"[..]).is_err());
+    }
+
+    #[test]
+    fn test_map_data_path() {
+        assert_eq!(
+            map_data_path(Path::new("usr/bin/foo")),
+            Some(Path::new("foo"))
+        );
+        assert_eq!(map_data_path(Path::new("usr/bin")), None);
+        assert_eq!(map_data_path(Path::new("usr/share/doc/foo")), None);
+    }
+
+    #[tokio::test]
+    async fn test_extract_deb() {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_size(0);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "./usr/bin/", io::empty())
+            .unwrap();
+        for (path, content) in [
+            ("./usr/bin/cargo-foo", &b"foo"[..]),
+            ("./usr/share/doc/cargo-foo/copyright", b"MIT"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append_data(&mut header, path, content).unwrap();
+        }
+        let data = builder.into_inner().unwrap().finish().unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("cargo-foo.deb");
+        std::fs::write(
+            &src,
+            ar(&[
+                ("debian-binary", b"2.0\n"),
+                ("control.tar.gz", b"control"),
+                ("data.tar.gz", &data),
+            ]),
+        )
+        .unwrap();
+
+        let dst = dir.path().join("extracted");
+        let extracted_files = extract_file(&src, PkgFmt::Deb, &dst).await.unwrap();
+
+        assert_eq!(
+            extracted_files.files().collect::<Vec<_>>(),
+            [Path::new("cargo-foo")]
+        );
+        assert!(extracted_files.sha256(Path::new("cargo-foo")).is_some());
+        assert_eq!(std::fs::read(dst.join("cargo-foo")).unwrap(), b"foo");
+        assert!(!dst.join("usr").exists());
+    }
+}
@@ -0,0 +1,229 @@
+//! Extraction of macOS disk images.
+//!
+//! Parsing their compressed blocks and the HFS+ or APFS filesystem they
+//! contain is left to `hdiutil`, so they can only be extracted on macOS:
+//! the image is spooled to a file, mounted read-only, and the regular files
+//! and directories of its filesystem are copied to the destination.
+//! Symlinks, e.g. the link to `/Applications` shipped next to apps, are
+//! skipped.
+
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+    process::{Command, Stdio},
+    sync::Arc,
+};
+
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+
+use super::{
+    extract_progress::{EntryProgress, ExtractProgress},
+    ExtractFilter, ExtractedFiles,
+};
+
+/// Run `hdiutil` with `args`.
+fn hdiutil(args: &[&std::ffi::OsStr]) -> io::Result<()> {
+    debug!("Running hdiutil {args:?}");
+
+    let mut child = Command::new("hdiutil")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| {
+            if err.kind() == io::ErrorKind::NotFound {
+                io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "hdiutil not found, dmg images can only be extracted on macOS",
+                )
+            } else {
+                err
+            }
+        })?;
+
+    // Accept the license agreement of the image, if any.
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(b"Y\n").ok();
+    }
+
+    let output = child.wait_with_output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!(
+                "hdiutil {args:?} failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ))
+    }
+}
+
+/// Image mounted by `hdiutil`, detached on drop.
+struct Mounted<'a>(&'a Path);
+
+impl<'a> Mounted<'a> {
+    fn attach(image: &Path, mount_point: &'a Path) -> io::Result<Self> {
+        hdiutil(&[
+            "attach".as_ref(),
+            "-nobrowse".as_ref(),
+            "-noautoopen".as_ref(),
+            "-readonly".as_ref(),
+            "-mountpoint".as_ref(),
+            mount_point.as_os_str(),
+            image.as_os_str(),
+        ])?;
+
+        Ok(Self(mount_point))
+    }
+}
+
+impl Drop for Mounted<'_> {
+    fn drop(&mut self) {
+        if let Err(err) = hdiutil(&["detach".as_ref(), "-force".as_ref(), self.0.as_os_str()]) {
+            warn!(
+                "Failed to detach the dmg image at {}: {err}",
+                self.0.display()
+            );
+        }
+    }
+}
+
+/// Copy the regular file `src` to `dst`, and return its sha256 digest.
+fn copy_file(src: &Path, dst: &Path, mut progress: Option<EntryProgress>) -> io::Result<[u8; 32]> {
+    let mut src = fs::File::open(src)?;
+    let mut dst = fs::File::create(dst)?;
+    let mut hasher = Sha256::new();
+
+    let mut buf = [0; 8192];
+    loop {
+        let n = match src.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        dst.write_all(&buf[..n])?;
+        hasher.update(&buf[..n]);
+        if let Some(progress) = &mut progress {
+            progress.advance(n as u64);
+        }
+    }
+
+    dst.set_permissions(src.metadata()?.permissions())?;
+
+    Ok(hasher.finalize().into())
+}
+
+/// Copy the directory `root/path` to `dst/path`, recursively.
+fn copy_dir(
+    root: &Path,
+    path: &Path,
+    dst: &Path,
+    progress: &Option<Arc<dyn ExtractProgress>>,
+    filter: Option<&ExtractFilter>,
+    extracted_files: &mut ExtractedFiles,
+) -> io::Result<()> {
+    let mut entries = fs::read_dir(root.join(path))?.collect::<io::Result<Vec<_>>>()?;
+    // Extract the entries in a deterministic order.
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let entry_path = path.join(entry.file_name());
+        let included = filter.map_or(true, |filter| filter(&entry_path));
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            if included {
+                fs::create_dir_all(dst.join(&entry_path))?;
+                extracted_files.add_dir(&entry_path);
+            }
+            copy_dir(root, &entry_path, dst, progress, filter, extracted_files)?;
+        } else if file_type.is_file() && included {
+            let outpath = dst.join(&entry_path);
+            if let Some(parent) = outpath.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            let entry_progress = progress.clone().map(|progress| {
+                EntryProgress::new(
+                    progress,
+                    entry_path.clone(),
+                    entry.metadata().map_or(0, |metadata| metadata.len()),
+                )
+            });
+            let sha256 = copy_file(&entry.path(), &outpath, entry_progress)?;
+            extracted_files.add_file(&entry_path, Some(sha256));
+        }
+    }
+
+    Ok(())
+}
+
+/// Extract the dmg image spooled to `image` into `dst`.
+pub(super) fn extract_dmg(
+    image: &Path,
+    dst: &Path,
+    progress: Option<Arc<dyn ExtractProgress>>,
+    filter: Option<ExtractFilter>,
+) -> io::Result<ExtractedFiles> {
+    let mount_point = tempfile::tempdir()?;
+    let mounted = Mounted::attach(image, mount_point.path())?;
+
+    fs::create_dir_all(dst)?;
+
+    let mut extracted_files = ExtractedFiles::new();
+    copy_dir(
+        mounted.0,
+        Path::new(""),
+        dst,
+        &progress,
+        filter.as_ref(),
+        &mut extracted_files,
+    )?;
+
+    Ok(extracted_files)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_copy_dir() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        fs::create_dir_all(root.join("Foo.app/Contents/MacOS")).unwrap();
+        fs::write(root.join("Foo.app/Contents/MacOS/foo"), b"foo").unwrap();
+        fs::write(root.join("README"), b"readme").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("/Applications", root.join("Applications")).unwrap();
+
+        let dst = tempfile::tempdir().unwrap();
+        let dst = dst.path();
+        let filter: ExtractFilter = Arc::new(|path: &Path| path != Path::new("README"));
+
+        let mut extracted_files = ExtractedFiles::new();
+        copy_dir(
+            root,
+            Path::new(""),
+            dst,
+            &None,
+            Some(&filter),
+            &mut extracted_files,
+        )
+        .unwrap();
+
+        let foo = Path::new("Foo.app/Contents/MacOS/foo");
+        assert_eq!(extracted_files.files().collect::<Vec<_>>(), [foo]);
+        assert!(extracted_files.sha256(foo).is_some());
+        assert_eq!(fs::read(dst.join(foo)).unwrap(), b"foo");
+        assert!(!dst.join("README").exists());
+        assert!(dst.join("Applications").symlink_metadata().is_err());
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Minimum interval between two reports of the same entry.
+pub(super) const REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Receives the progress of extracting the entries of an archive, so that
+/// extracting a huge entry does not appear frozen.
+///
+/// Only entries taking a while to extract are reported, periodically while
+/// they are being extracted and once more once they are done.
+pub trait ExtractProgress: Send + Sync {
+    /// `written` out of `size` bytes of the entry at `path` (relative to the
+    /// destination) have been extracted.
+    fn on_entry_progress(&self, path: &Path, written: u64, size: u64);
+}
+
+impl<T> ExtractProgress for T
+where
+    T: Fn(&Path, u64, u64) + Send + Sync,
+{
+    fn on_entry_progress(&self, path: &Path, written: u64, size: u64) {
+        (*self)(path, written, size)
+    }
+}
+
+/// Tracks the progress of extracting one entry.
+pub(super) struct EntryProgress {
+    progress: Arc<dyn ExtractProgress>,
+    path: PathBuf,
+    size: u64,
+    written: u64,
+    last_report: Instant,
+    reported: bool,
+}
+
+impl EntryProgress {
+    pub(super) fn new(progress: Arc<dyn ExtractProgress>, path: PathBuf, size: u64) -> Self {
+        Self {
+            progress,
+            path,
+            size,
+            written: 0,
+            last_report: Instant::now(),
+            reported: false,
+        }
+    }
+
+    pub(super) fn advance(&mut self, n: u64) {
+        self.written += n;
+
+        if self.last_report.elapsed() >= REPORT_INTERVAL {
+            self.report();
+        }
+    }
+
+    fn report(&mut self) {
+        self.progress
+            .on_entry_progress(&self.path, self.written, self.size);
+        self.last_report = Instant::now();
+        self.reported = true;
+    }
+}
+
+impl Drop for EntryProgress {
+    fn drop(&mut self) {
+        if self.reported {
+            self.report();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Mutex, thread::sleep};
+
+    use super::*;
+
+    #[test]
+    fn test_entry_progress() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let progress: Arc<dyn ExtractProgress> = {
+            let reports = reports.clone();
+            Arc::new(move |_path: &Path, written, size| {
+                reports.lock().unwrap().push((written, size))
+            })
+        };
+
+        // Entries extracted quickly are not reported.
+        let mut entry = EntryProgress::new(progress.clone(), "a".into(), 2);
+        entry.advance(2);
+        drop(entry);
+        assert!(reports.lock().unwrap().is_empty());
+
+        let mut entry = EntryProgress::new(progress, "b".into(), 4);
+        entry.advance(1);
+        sleep(REPORT_INTERVAL);
+        entry.advance(1);
+        entry.advance(2);
+        drop(entry);
+        assert_eq!(*reports.lock().unwrap(), [(2, 4), (4, 4)]);
+    }
+}
@@ -9,6 +9,8 @@ use std::{
 pub enum ExtractedFilesEntry {
     Dir(Box<HashSet<Box<OsStr>>>),
     File,
+    /// Target of the symlink, relative to its parent directory.
+    Symlink(Box<Path>),
 }
 
 impl ExtractedFilesEntry {
@@ -22,19 +24,39 @@ impl ExtractedFilesEntry {
 }
 
 #[derive(Debug)]
-pub struct ExtractedFiles(pub(super) HashMap<Box<Path>, ExtractedFilesEntry>);
+pub struct ExtractedFiles(
+    pub(super) HashMap<Box<Path>, ExtractedFilesEntry>,
+    /// Sha256 digests of the files, computed while extracting them.
+    HashMap<Box<Path>, [u8; 32]>,
+);
 
 impl ExtractedFiles {
     pub(super) fn new() -> Self {
-        Self(Default::default())
+        Self(Default::default(), Default::default())
     }
 
     /// * `path` - must be canonical and must not be empty
+    /// * `sha256` - digest of the content of the file, if it could be
+    ///   computed while extracting it
     ///
     /// NOTE that if the entry for the `path` is previously set to a dir,
     /// it would be replaced with a file.
-    pub(super) fn add_file(&mut self, path: &Path) {
+    pub(super) fn add_file(&mut self, path: &Path, sha256: Option<[u8; 32]>) {
         self.0.insert(path.into(), ExtractedFilesEntry::File);
+        match sha256 {
+            Some(sha256) => self.1.insert(path.into(), sha256),
+            None => self.1.remove(path),
+        };
+        self.add_dir_if_has_parent(path);
+    }
+
+    /// * `path` - must be canonical and must not be empty
+    /// * `target` - must be relative and stay inside the extraction root
+    ///   once resolved against the parent of `path`
+    pub(super) fn add_symlink(&mut self, path: &Path, target: &Path) {
+        self.0
+            .insert(path.into(), ExtractedFilesEntry::Symlink(target.into()));
+        self.1.remove(path);
         self.add_dir_if_has_parent(path);
     }
 
@@ -94,7 +116,18 @@ impl ExtractedFiles {
     pub fn get_dir(&self, path: &Path) -> Option<&HashSet<Box<OsStr>>> {
         match self.get_entry(path)? {
             ExtractedFilesEntry::Dir(file_names) => Some(file_names),
-            ExtractedFilesEntry::File => None,
+            ExtractedFilesEntry::File | ExtractedFilesEntry::Symlink(_) => None,
+        }
+    }
+
+    /// Return the target of the symlink at `path`, relative to its parent
+    /// directory.
+    ///
+    /// * `path` - same as [`ExtractedFiles::has_file`]
+    pub fn get_symlink(&self, path: &Path) -> Option<&Path> {
+        match self.get_entry(path)? {
+            ExtractedFilesEntry::Symlink(target) => Some(target),
+            ExtractedFilesEntry::Dir(_) | ExtractedFilesEntry::File => None,
         }
     }
 
@@ -105,4 +138,22 @@ impl ExtractedFiles {
     pub fn has_file(&self, path: &Path) -> bool {
         matches!(self.get_entry(path), Some(ExtractedFilesEntry::File))
     }
+
+    /// Return the sha256 digest of the file at `path`, computed while
+    /// extracting it so that it does not need to be read again.
+    ///
+    /// * `path` - same as [`ExtractedFiles::has_file`]
+    ///
+    /// Returns `None` for files whose content is not read sequentially when
+    /// extracted, e.g. sparse files.
+    pub fn sha256(&self, path: &Path) -> Option<&[u8; 32]> {
+        self.1.get(path)
+    }
+
+    /// Return the paths of all the files extracted, in no particular order.
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.0.iter().filter_map(|(path, entry)| {
+            matches!(entry, ExtractedFilesEntry::File).then_some(&**path)
+        })
+    }
 }
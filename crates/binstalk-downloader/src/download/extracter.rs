@@ -1,17 +1,18 @@
 use std::io::{self, BufRead, Read};
 
+use brotli::Decompressor as BrotliDecoder;
 use bzip2::bufread::BzDecoder;
 use flate2::bufread::GzDecoder;
-use tar::Archive;
 use xz2::bufread::XzDecoder;
 use zstd::stream::Decoder as ZstdDecoder;
 
-use super::TarBasedFmt;
+use super::{lz4::Lz4Decoder, TarBasedFmt};
 
+/// Create the decoder of the tarball `dat`, yielding the tar stream.
 pub fn create_tar_decoder(
     dat: impl BufRead + 'static,
     fmt: TarBasedFmt,
-) -> io::Result<Archive<Box<dyn Read>>> {
+) -> io::Result<Box<dyn Read>> {
     use TarBasedFmt::*;
 
     let r: Box<dyn Read> = match fmt {
@@ -25,7 +26,9 @@ pub fn create_tar_decoder(
             // should not return any error.
             Box::new(ZstdDecoder::with_buffer(dat)?)
         }
+        Tlz4 => Box::new(Lz4Decoder::new(dat)),
+        Tbr => Box::new(BrotliDecoder::new(dat, 4096)),
     };
 
-    Ok(Archive::new(r))
+    Ok(r)
 }
@@ -0,0 +1,306 @@
+//! Journal of the downloads in progress, so that the temporary data left
+//! behind by a run which crashed or was killed, e.g. by the OOM killer, is
+//! cleaned up by the next one, see [`Client::with_download_journal`].
+//!
+//! [`Client::with_download_journal`]: crate::remote::Client::with_download_journal
+
+use std::{
+    fs,
+    io::{self, Read, Seek, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use fs_lock::FileLock;
+use tempfile::NamedTempFile;
+use tracing::{debug, warn};
+
+use crate::remote::Url;
+
+/// Rewrite the entry of a download once this many more bytes are
+/// downloaded, instead of on every chunk.
+const BYTES_INTERVAL: u64 = 8 * 1024 * 1024;
+
+/// Each download in progress has an entry in the journal directory, which
+/// is locked by the process downloading it and removed once it is done.
+#[derive(Clone, Debug)]
+pub struct DownloadJournal {
+    dir: Arc<Path>,
+}
+
+/// What is known about a download in progress.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct JournalRecord {
+    pub url: Option<String>,
+    /// Hex encoded sha256 digest the artifact is expected to have, if known
+    /// beforehand, e.g. from the artifact cache.
+    pub expected_sha256: Option<String>,
+    /// Number of bytes downloaded so far, updated every 8MiB.
+    pub bytes: u64,
+    /// Files and directories to remove if the download is not completed.
+    pub temp_paths: Vec<PathBuf>,
+}
+
+impl JournalRecord {
+    fn serialize(&self) -> String {
+        let mut s = String::new();
+        if let Some(url) = &self.url {
+            s += &format!("url {url}\n");
+        }
+        if let Some(expected_sha256) = &self.expected_sha256 {
+            s += &format!("sha256 {expected_sha256}\n");
+        }
+        s += &format!("bytes {}\n", self.bytes);
+        for temp_path in &self.temp_paths {
+            // `add_temp_path` only accepts paths without newlines.
+            s += &format!("temp {}\n", temp_path.to_str().unwrap());
+        }
+        s
+    }
+
+    /// Lines which cannot be parsed are ignored.
+    fn parse(s: &str) -> Self {
+        let mut record = Self::default();
+        for line in s.lines() {
+            let Some((key, value)) = line.split_once(' ') else {
+                continue;
+            };
+            match key {
+                "url" => record.url = Some(value.to_owned()),
+                "sha256" => record.expected_sha256 = Some(value.to_owned()),
+                "bytes" => record.bytes = value.parse().unwrap_or_default(),
+                "temp" => record.temp_paths.push(value.into()),
+                _ => (),
+            }
+        }
+        record
+    }
+}
+
+impl DownloadJournal {
+    /// Store the journal in `dir`, which is created if it does not exist.
+    pub fn new(dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir: dir.into() })
+    }
+
+    /// Record a download of `url` in progress, until the entry returned is
+    /// dropped.
+    pub fn begin(
+        &self,
+        url: Option<&Url>,
+        expected_sha256: Option<String>,
+    ) -> io::Result<JournalEntry> {
+        let tmp = tempfile::Builder::new()
+            .suffix(".journal")
+            .tempfile_in(&self.dir)?;
+        let lock = FileLock::new_exclusive(tmp.reopen()?)?;
+
+        let entry = JournalEntry(Mutex::new(EntryInner {
+            lock,
+            _tmp: tmp,
+            record: JournalRecord {
+                url: url.map(ToString::to_string),
+                expected_sha256,
+                ..Default::default()
+            },
+            written_bytes: 0,
+        }));
+        entry.0.lock().unwrap().write()?;
+
+        Ok(entry)
+    }
+
+    /// Remove the temporary data of the downloads left in progress by runs
+    /// which are no longer running and return their records.
+    ///
+    /// The entries of the runs still in progress are locked, and skipped.
+    pub fn recover(&self) -> io::Result<Vec<JournalRecord>> {
+        let mut records = Vec::new();
+
+        for dir_entry in fs::read_dir(&self.dir)? {
+            let path = dir_entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("journal") {
+                continue;
+            }
+
+            let file = match fs::OpenOptions::new().read(true).write(true).open(&path) {
+                Ok(file) => file,
+                // Removed by the run which just completed it.
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+            let Some(mut lock) = FileLock::try_new_exclusive(file)? else {
+                continue;
+            };
+            let mut content = Vec::new();
+            lock.read_to_end(&mut content)?;
+            drop(lock);
+            if content.is_empty() {
+                // Just created by another run, which is about to lock it.
+                continue;
+            }
+
+            let record = JournalRecord::parse(&String::from_utf8_lossy(&content));
+            debug!(
+                "Cleaning up the download of {} interrupted after {} bytes",
+                record.url.as_deref().unwrap_or("unknown url"),
+                record.bytes
+            );
+            record
+                .temp_paths
+                .iter()
+                .for_each(|path| remove_temp_path(path));
+            fs::remove_file(&path)?;
+
+            records.push(record);
+        }
+
+        Ok(records)
+    }
+}
+
+fn remove_temp_path(path: &Path) {
+    let res = match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(err) => Err(err),
+    };
+    match res {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => {
+            warn!("Failed to remove {}: {err}", path.display())
+        }
+        _ => (),
+    }
+}
+
+/// The entry of a download in progress, removed from the journal when
+/// dropped.
+///
+/// Failing to update it does not fail the download, it is only logged.
+#[derive(Debug)]
+pub struct JournalEntry(Mutex<EntryInner>);
+
+#[derive(Debug)]
+struct EntryInner {
+    /// Declared before `_tmp` so that it is unlocked before the file is
+    /// removed.
+    lock: FileLock,
+    _tmp: NamedTempFile,
+    record: JournalRecord,
+    /// Value of `record.bytes` last written.
+    written_bytes: u64,
+}
+
+impl EntryInner {
+    fn write(&mut self) -> io::Result<()> {
+        self.lock.set_len(0)?;
+        self.lock.rewind()?;
+        self.lock.write_all(self.record.serialize().as_bytes())?;
+        self.written_bytes = self.record.bytes;
+        Ok(())
+    }
+
+    fn write_or_warn(&mut self) {
+        if let Err(err) = self.write() {
+            warn!("Failed to update the download journal: {err}");
+        }
+    }
+}
+
+impl JournalEntry {
+    /// Remove `path` if the download is not completed, e.g. a staging
+    /// directory or a partially written file.
+    ///
+    /// Paths which are not valid utf-8 or contain newlines are not recorded.
+    pub fn add_temp_path(&self, path: &Path) {
+        match path.to_str() {
+            Some(s) if !s.contains('\n') => (),
+            _ => {
+                debug!("Not recording {} in the download journal", path.display());
+                return;
+            }
+        }
+
+        let mut inner = self.0.lock().unwrap();
+        inner.record.temp_paths.push(path.to_owned());
+        inner.write_or_warn();
+    }
+
+    /// `bytes` more bytes are downloaded.
+    pub(super) fn advance(&self, bytes: u64) {
+        let mut inner = self.0.lock().unwrap();
+        inner.record.bytes += bytes;
+        if inner.record.bytes - inner.written_bytes >= BYTES_INTERVAL {
+            inner.write_or_warn();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let record = JournalRecord {
+            url: Some("https://example.com/foo.tgz".to_owned()),
+            expected_sha256: Some("0".repeat(64)),
+            bytes: 42,
+            temp_paths: vec!["/tmp/foo bar".into(), "/tmp/.foo.staging-a1b2".into()],
+        };
+        assert_eq!(JournalRecord::parse(&record.serialize()), record);
+        assert_eq!(
+            JournalRecord::parse("bytes x\nunknown\ntemp /tmp/foo\n"),
+            JournalRecord {
+                temp_paths: vec!["/tmp/foo".into()],
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_recover() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = DownloadJournal::new(dir.path().join("journal")).unwrap();
+        let url = Url::parse("https://example.com/foo.tgz").unwrap();
+
+        let orphaned_dir = dir.path().join("staging");
+        fs::create_dir_all(orphaned_dir.join("bin")).unwrap();
+        let orphaned_file = dir.path().join("partial");
+        fs::write(&orphaned_file, b"partial").unwrap();
+
+        let entry = journal.begin(Some(&url), None).unwrap();
+        entry.add_temp_path(&orphaned_dir);
+        entry.add_temp_path(&orphaned_file);
+        entry.advance(BYTES_INTERVAL);
+
+        // The entry of a download in progress is left alone.
+        assert_eq!(journal.recover().unwrap(), []);
+        assert!(orphaned_dir.exists());
+
+        // Simulate a crash: the entry is neither removed nor unlocked.
+        let EntryInner {
+            lock, _tmp: tmp, ..
+        } = entry.0.into_inner().unwrap();
+        drop(lock);
+        tmp.keep().unwrap();
+
+        assert_eq!(
+            journal.recover().unwrap(),
+            [JournalRecord {
+                url: Some(url.to_string()),
+                expected_sha256: None,
+                bytes: BYTES_INTERVAL,
+                temp_paths: vec![orphaned_dir.clone(), orphaned_file.clone()],
+            }]
+        );
+        assert!(!orphaned_dir.exists());
+        assert!(!orphaned_file.exists());
+        assert_eq!(fs::read_dir(dir.path().join("journal")).unwrap().count(), 0);
+
+        // Completed downloads are removed from the journal.
+        drop(journal.begin(Some(&url), None).unwrap());
+        assert_eq!(fs::read_dir(dir.path().join("journal")).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,193 @@
+//! Decoding of the LZ4 frame format, for `.tar.lz4`, with the frame decoder
+//! of `lz4_flex`.
+//!
+//! The decoder is blocking, so [`decode_lz4_stream`] runs it on a blocking
+//! thread for the async visitor. Concatenated frames are supported, but
+//! skippable frames are not.
+
+use std::{
+    future,
+    io::{self, BufRead, Read},
+};
+
+use bytes::Bytes;
+use futures_util::{stream, Stream, StreamExt};
+use lz4_flex::frame::FrameDecoder;
+use tokio::{sync::mpsc, task};
+
+use super::DownloadError;
+use crate::utils::StreamReadable;
+
+/// Decoder of the LZ4 frames of `R`.
+pub(super) struct Lz4Decoder<R: Read>(FrameDecoder<R>);
+
+impl<R: BufRead> Lz4Decoder<R> {
+    pub(super) fn new(inner: R) -> Self {
+        Self(FrameDecoder::new(inner))
+    }
+}
+
+impl<R: BufRead> Read for Lz4Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.0.read(buf)? {
+                // `FrameDecoder` returns 0 at the end of each frame, which
+                // may be followed by another one.
+                0 if !buf.is_empty() && !self.0.get_mut().fill_buf()?.is_empty() => continue,
+                n => break Ok(n),
+            }
+        }
+    }
+}
+
+/// Decode the LZ4 frames of `stream`.
+pub(super) fn decode_lz4_stream<S>(stream: S) -> impl Stream<Item = Result<Bytes, DownloadError>>
+where
+    S: Stream<Item = Result<Bytes, DownloadError>> + Send + Sync,
+{
+    let (in_tx, in_rx) = mpsc::channel(5);
+    let (out_tx, out_rx) = mpsc::channel(5);
+
+    // Stops once the input is decoded, on error, or once the receiver of
+    // `out_tx` is dropped, i.e. once the stream returned is dropped.
+    task::spawn_blocking(move || {
+        let mut decoder = Lz4Decoder::new(StreamReadable::new(in_rx));
+        loop {
+            let mut buf = vec![0; 64 * 1024];
+            let res = match decoder.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    buf.truncate(n);
+                    Ok(Bytes::from(buf))
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => Err(err),
+            };
+            let is_err = res.is_err();
+            if out_tx.blocking_send(res).is_err() || is_err {
+                break;
+            }
+        }
+    });
+
+    let state = Some((Box::pin(stream), Some(in_tx), out_rx));
+
+    stream::unfold(state, |state| async move {
+        let (mut stream, mut in_tx, mut out_rx) = state?;
+
+        loop {
+            let mut output = None;
+            let mut input_done = false;
+
+            // Only read the input once it can be sent to the decoder, so
+            // that the decoded data keeps being received meanwhile.
+            let reserve = async {
+                match &in_tx {
+                    Some(in_tx) => in_tx.reserve().await.ok(),
+                    None => future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                biased;
+
+                res = out_rx.recv() => output = Some(res),
+                permit = reserve => match permit {
+                    Some(permit) => match stream.next().await {
+                        Some(Ok(bytes)) if bytes.is_empty() => (),
+                        Some(Ok(bytes)) => permit.send(bytes),
+                        Some(Err(err)) => break Some((Err(err), None)),
+                        None => input_done = true,
+                    },
+                    // The decoder failed, its error is received next.
+                    None => input_done = true,
+                },
+            }
+
+            match output {
+                Some(Some(Ok(bytes))) => break Some((Ok(bytes), Some((stream, in_tx, out_rx)))),
+                Some(Some(Err(err))) => break Some((Err(err.into()), None)),
+                Some(None) => break None,
+                None => (),
+            }
+
+            if input_done {
+                // Lets the decoder reach the end of its input.
+                in_tx = None;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use lz4_flex::frame::{BlockMode, FrameEncoder, FrameInfo};
+
+    use super::*;
+
+    /// Two frames with linked blocks and checksums.
+    fn frames() -> (Vec<u8>, Vec<u8>) {
+        let content: Vec<u8> = (0..200_000u32)
+            .map(|i| (i % 251 * (i / 1000)) as u8)
+            .collect();
+
+        let mut data = Vec::new();
+        for _ in 0..2 {
+            let mut frame_info = FrameInfo::new();
+            frame_info.block_mode = BlockMode::Linked;
+            frame_info.block_checksums = true;
+            frame_info.content_checksum = true;
+
+            let mut encoder = FrameEncoder::with_frame_info(frame_info, Vec::new());
+            io::Write::write_all(&mut encoder, &content).unwrap();
+            data.extend(encoder.finish().unwrap());
+        }
+
+        (data, [&content[..], &content].concat())
+    }
+
+    #[test]
+    fn test_lz4_decoder() {
+        let (data, content) = frames();
+
+        let mut decoded = Vec::new();
+        Lz4Decoder::new(&data[..])
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert!(decoded == content);
+
+        // Truncated
+        Lz4Decoder::new(&data[..data.len() - 1])
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+    }
+
+    async fn decode(data: &[u8]) -> Result<Vec<u8>, DownloadError> {
+        let chunks: Vec<Result<Bytes, DownloadError>> = data
+            .chunks(1000)
+            .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+            .collect();
+
+        let mut decoded = Vec::new();
+        let mut stream = Box::pin(decode_lz4_stream(stream::iter(chunks)));
+        while let Some(bytes) = stream.next().await {
+            decoded.extend(bytes?);
+        }
+        Ok(decoded)
+    }
+
+    #[tokio::test]
+    async fn test_decode_lz4_stream() {
+        let (data, content) = frames();
+        assert!(decode(&data).await.unwrap() == content);
+
+        // Truncated
+        decode(&data[..data.len() - 1]).await.unwrap_err();
+
+        // Corrupted
+        let mut corrupted = data.clone();
+        let len = corrupted.len();
+        corrupted[len / 2] ^= 1;
+        decode(&corrupted).await.unwrap_err();
+    }
+}
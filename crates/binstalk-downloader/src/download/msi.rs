@@ -0,0 +1,414 @@
+//! Extraction of Windows Installer packages.
+//!
+//! An `.msi` is a compound file storing the tables of the installer
+//! database along with the cabinets of the files to install. The files of
+//! the cabinets embedded in the package are extracted without running the
+//! installer, to the directories the `Directory`, `Component` and `File`
+//! tables install them to, relative to the destination: e.g. a file
+//! installed to `[ProgramFiles64Folder]foo\bin` is extracted to `foo/bin`.
+//! Cabinets shipped next to the package are not supported.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufReader, Read, Seek, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use cab::Cabinet;
+use msi::{Package, Select, Value};
+use sha2::{Digest, Sha256};
+
+use super::{
+    extract_progress::{EntryProgress, ExtractProgress},
+    ExtractFilter, ExtractedFiles,
+};
+
+/// Maximum depth of the directories of the `Directory` table.
+const MAX_DIRECTORY_DEPTH: usize = 64;
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn unsupported(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, msg.into())
+}
+
+/// Read the `columns` of the rows of the table `name`.
+fn select<R: Read + Seek>(
+    package: &mut Package<R>,
+    name: &str,
+    columns: &[&str],
+) -> io::Result<Vec<Vec<Value>>> {
+    if !package.has_table(name) {
+        return Err(invalid(format!("missing table {name} in msi package")));
+    }
+
+    Ok(package
+        .select_rows(Select::table(name).columns(columns))?
+        .map(|row| (0..row.len()).map(|i| row[i].clone()).collect())
+        .collect())
+}
+
+/// Return the long name of `name`, which may be `short|long`.
+fn long_name(name: &str) -> &str {
+    name.split_once('|').map_or(name, |(_, long)| long)
+}
+
+/// Append the name of a directory or file of the package to `path`.
+fn push_name(path: &mut PathBuf, name: &str) -> io::Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\', ':']) {
+        return Err(invalid(format!("invalid file name {name} in msi package")));
+    }
+    path.push(name);
+    Ok(())
+}
+
+/// Directories of the `Directory` table, as their parents and names.
+type Directories<'a> = HashMap<&'a str, (Option<&'a str>, Option<&'a str>)>;
+
+fn directory_path(directories: &Directories<'_>, key: &str, depth: usize) -> io::Result<PathBuf> {
+    let (parent, name) = directories
+        .get(key)
+        .ok_or_else(|| invalid(format!("missing directory {key} in msi package")))?;
+
+    match parent {
+        // The root directory, i.e. `TARGETDIR`, is the destination.
+        None => Ok(PathBuf::new()),
+        Some(parent) if *parent == key => Ok(PathBuf::new()),
+        Some(_) if depth >= MAX_DIRECTORY_DEPTH => {
+            Err(invalid("too deep directories in msi package"))
+        }
+        Some(parent) => {
+            let mut path = directory_path(directories, parent, depth + 1)?;
+            if let Some(name) = name {
+                push_name(&mut path, name)?;
+            }
+            Ok(path)
+        }
+    }
+}
+
+/// Return the paths of the files of the package, relative to the root
+/// directory, by their keys in the `File` table.
+fn file_paths<R: Read + Seek>(package: &mut Package<R>) -> io::Result<HashMap<String, PathBuf>> {
+    let directory = select(
+        package,
+        "Directory",
+        &["Directory", "Directory_Parent", "DefaultDir"],
+    )?;
+    let mut directories = Directories::new();
+    for row in &directory {
+        let key = row[0].as_str().unwrap_or_default();
+        // The name is either `target` or `target:source`, `.` for the
+        // directory of the parent.
+        let name = row[2]
+            .as_str()
+            .map(|name| long_name(name.split(':').next().unwrap_or(name)))
+            .filter(|name| *name != ".");
+        directories.insert(key, (row[1].as_str(), name));
+    }
+
+    let component = select(package, "Component", &["Component", "Directory_"])?;
+    let components: HashMap<_, _> = component
+        .iter()
+        .map(|row| (row[0].as_str(), row[1].as_str()))
+        .collect();
+
+    let file = select(package, "File", &["File", "Component_", "FileName"])?;
+    let mut paths = HashMap::new();
+    for row in &file {
+        let (Some(key), Some(file_name)) = (row[0].as_str(), row[2].as_str()) else {
+            return Err(invalid("invalid File table in msi package"));
+        };
+        let directory = components
+            .get(&row[1].as_str())
+            .copied()
+            .flatten()
+            .ok_or_else(|| invalid(format!("missing component of file {key} in msi package")))?;
+
+        let mut path = directory_path(&directories, directory, 0)?;
+        push_name(&mut path, long_name(file_name))?;
+        paths.insert(key.to_owned(), path);
+    }
+
+    Ok(paths)
+}
+
+/// Copy the `size` bytes of `file` to `dst`, and return its sha256 digest.
+fn copy_file(
+    file: &mut impl Read,
+    size: u64,
+    dst: &Path,
+    mut progress: Option<EntryProgress>,
+) -> io::Result<[u8; 32]> {
+    let mut src = file.take(size);
+    let mut dst = fs::File::create(dst)?;
+    let mut hasher = Sha256::new();
+
+    let mut written = 0;
+    let mut buf = [0; 8192];
+    loop {
+        let n = match src.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        dst.write_all(&buf[..n])?;
+        hasher.update(&buf[..n]);
+        written += n as u64;
+        if let Some(progress) = &mut progress {
+            progress.advance(n as u64);
+        }
+    }
+
+    if written != size {
+        return Err(invalid("truncated file in cabinet of msi package"));
+    }
+
+    Ok(hasher.finalize().into())
+}
+
+/// Extract the files of `cabinet` whose paths are in `paths`.
+fn extract_cabinet<R: Read + Seek>(
+    cabinet: &mut Cabinet<R>,
+    paths: &HashMap<String, PathBuf>,
+    dst: &Path,
+    progress: &Option<Arc<dyn ExtractProgress>>,
+    filter: Option<&ExtractFilter>,
+    extracted_files: &mut ExtractedFiles,
+) -> io::Result<()> {
+    let mut files = Vec::new();
+    for folder in cabinet.folder_entries() {
+        for file in folder.file_entries() {
+            // Files of other features, e.g. of merge modules, are not in
+            // the `File` table.
+            let Some(path) = paths.get(file.name()) else {
+                continue;
+            };
+            if filter.map_or(true, |filter| filter(path)) {
+                files.push((file.name().to_owned(), file.uncompressed_size(), path));
+            }
+        }
+    }
+
+    for (name, size, path) in files {
+        let outpath = dst.join(path);
+        if let Some(parent) = outpath.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let size = u64::from(size);
+        let entry_progress = progress
+            .clone()
+            .map(|progress| EntryProgress::new(progress, path.to_owned(), size));
+        let sha256 = copy_file(
+            &mut cabinet.read_file(&name)?,
+            size,
+            &outpath,
+            entry_progress,
+        )?;
+        extracted_files.add_file(path, Some(sha256));
+    }
+
+    Ok(())
+}
+
+/// Extract the msi package `msi` into `dst`.
+pub(super) fn extract_msi<R: Read + Seek>(
+    msi: R,
+    dst: &Path,
+    progress: Option<Arc<dyn ExtractProgress>>,
+    filter: Option<ExtractFilter>,
+) -> io::Result<ExtractedFiles> {
+    let mut package = Package::open(msi)?;
+    let paths = file_paths(&mut package)?;
+
+    let mut cabinets = Vec::new();
+    for row in select(&mut package, "Media", &["Cabinet"])? {
+        let Some(name) = row[0].as_str() else {
+            continue;
+        };
+        // Embedded cabinets are referred to by `#` and their stream.
+        match name.strip_prefix('#') {
+            Some(stream) => cabinets.push(stream.to_owned()),
+            None => {
+                return Err(unsupported(format!(
+                    "external cabinet {name} of msi package is not supported"
+                )))
+            }
+        }
+    }
+    if cabinets.is_empty() {
+        return Err(unsupported("msi package without embedded cabinets"));
+    }
+
+    fs::create_dir_all(dst)?;
+
+    let mut extracted_files = ExtractedFiles::new();
+    for name in cabinets {
+        if !package.has_stream(&name) {
+            return Err(invalid(format!("missing cabinet {name} in msi package")));
+        }
+        let mut cabinet = Cabinet::new(BufReader::new(package.read_stream(&name)?))?;
+        extract_cabinet(
+            &mut cabinet,
+            &paths,
+            dst,
+            &progress,
+            filter.as_ref(),
+            &mut extracted_files,
+        )?;
+    }
+
+    Ok(extracted_files)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use binstalk_types::cargo_toml_binstall::PkgFmt;
+    use cab::{CabinetBuilder, CompressionType};
+    use msi::{Column, Insert, PackageType};
+
+    use super::*;
+    use crate::download::extract_file;
+
+    #[test]
+    fn test_long_name() {
+        assert_eq!(long_name("CARGO-~1.EXE|cargo-foo.exe"), "cargo-foo.exe");
+        assert_eq!(long_name("README.md"), "README.md");
+    }
+
+    fn s(s: &str) -> Value {
+        Value::Str(s.to_owned())
+    }
+
+    fn cabinet(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = CabinetBuilder::new();
+        let folder = builder.add_folder(CompressionType::MsZip);
+        for (name, _) in files {
+            folder.add_file(*name);
+        }
+
+        let mut writer = builder.build(Cursor::new(Vec::new())).unwrap();
+        while let Some(mut file) = writer.next_file().unwrap() {
+            let (_, data) = files
+                .iter()
+                .find(|(name, _)| *name == file.file_name())
+                .unwrap();
+            file.write_all(data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    fn msi(cab: Vec<u8>) -> Vec<u8> {
+        let mut package = Package::create(PackageType::Installer, Cursor::new(Vec::new())).unwrap();
+
+        let mut table = |name: &str, columns: Vec<Column>, rows: Vec<Vec<Value>>| {
+            package.create_table(name, columns).unwrap();
+            package.insert_rows(Insert::into(name).rows(rows)).unwrap();
+        };
+        table(
+            "Directory",
+            vec![
+                Column::build("Directory").primary_key().id_string(72),
+                Column::build("Directory_Parent").nullable().id_string(72),
+                Column::build("DefaultDir").text_string(255),
+            ],
+            vec![
+                vec![s("TARGETDIR"), Value::Null, s("SourceDir")],
+                vec![s("ProgramFiles64Folder"), s("TARGETDIR"), s(".")],
+                vec![
+                    s("APPLICATIONFOLDER"),
+                    s("ProgramFiles64Folder"),
+                    s("CARGO-~1|cargo-foo"),
+                ],
+                vec![s("Bin"), s("APPLICATIONFOLDER"), s("bin:.")],
+            ],
+        );
+        table(
+            "Component",
+            vec![
+                Column::build("Component").primary_key().id_string(72),
+                Column::build("Directory_").id_string(72),
+            ],
+            vec![
+                vec![s("main"), s("Bin")],
+                vec![s("docs"), s("APPLICATIONFOLDER")],
+            ],
+        );
+        table(
+            "File",
+            vec![
+                Column::build("File").primary_key().id_string(72),
+                Column::build("Component_").id_string(72),
+                Column::build("FileName").text_string(255),
+                Column::build("FileSize").int32(),
+                Column::build("Sequence").int16(),
+            ],
+            vec![
+                vec![
+                    s("exe0"),
+                    s("main"),
+                    s("CARGO-~1.EXE|cargo-foo.exe"),
+                    Value::Int(3),
+                    Value::Int(1),
+                ],
+                vec![
+                    s("readme"),
+                    s("docs"),
+                    s("README.md"),
+                    Value::Int(6),
+                    Value::Int(2),
+                ],
+            ],
+        );
+        table(
+            "Media",
+            vec![
+                Column::build("DiskId").primary_key().int16(),
+                Column::build("LastSequence").int16(),
+                Column::build("Cabinet").nullable().text_string(255),
+            ],
+            vec![vec![Value::Int(1), Value::Int(2), s("#cargo.cab")]],
+        );
+
+        package
+            .write_stream("cargo.cab")
+            .unwrap()
+            .write_all(&cab)
+            .unwrap();
+        package.into_inner().unwrap().into_inner()
+    }
+
+    #[tokio::test]
+    async fn test_extract_msi() {
+        let cab = cabinet(&[
+            ("exe0", b"foo"),
+            ("unknown", b"unknown"),
+            ("readme", b"readme"),
+        ]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("cargo-foo.msi");
+        fs::write(&src, msi(cab)).unwrap();
+
+        let dst = dir.path().join("extracted");
+        let extracted_files = extract_file(&src, PkgFmt::Msi, &dst).await.unwrap();
+
+        let exe = Path::new("cargo-foo/bin/cargo-foo.exe");
+        let readme = Path::new("cargo-foo/README.md");
+        let mut files: Vec<_> = extracted_files.files().collect();
+        files.sort();
+        assert_eq!(files, [readme, exe]);
+        assert!(extracted_files.sha256(exe).is_some());
+        assert_eq!(fs::read(dst.join(exe)).unwrap(), b"foo");
+        assert_eq!(fs::read(dst.join(readme)).unwrap(), b"readme");
+        assert!(!dst.join("unknown").exists());
+    }
+}
@@ -0,0 +1,59 @@
+use bytes::Bytes;
+
+use super::DataVerifier;
+
+/// Feeds the data downloaded to several [`DataVerifier`]s, in the order
+/// they are added, e.g. a checksum and a signature verifier, since only
+/// one can be attached to a [`Download`](super::Download).
+///
+/// The verifiers are borrowed, so that they can be finalized once the
+/// download is done and the `MultiVerifier` dropped.
+#[derive(Default)]
+pub struct MultiVerifier<'a>(Vec<&'a mut dyn DataVerifier>);
+
+impl<'a> MultiVerifier<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, data_verifier: &'a mut dyn DataVerifier) -> Self {
+        self.push(data_verifier);
+        self
+    }
+
+    pub fn push(&mut self, data_verifier: &'a mut dyn DataVerifier) {
+        self.0.push(data_verifier);
+    }
+}
+
+impl DataVerifier for MultiVerifier<'_> {
+    fn update(&mut self, data: &Bytes) {
+        for data_verifier in &mut self.0 {
+            data_verifier.update(data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::download::Sha256Verifier;
+
+    #[test]
+    fn test_multi_verifier() {
+        let mut sha256 = Sha256Verifier::new();
+        let mut len = 0;
+        let mut count_len = |data: &Bytes| len += data.len();
+
+        let mut multi_verifier = MultiVerifier::new().with(&mut sha256).with(&mut count_len);
+        for data in ["a", "bc"] {
+            multi_verifier.update(&Bytes::from_static(data.as_bytes()));
+        }
+        drop(multi_verifier);
+
+        assert_eq!(len, 3);
+        sha256
+            .verify_hex("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+            .unwrap();
+    }
+}
@@ -0,0 +1,94 @@
+//! Extraction of packages wrapping another archive, e.g. a zip containing
+//! a tarball, see [`Download::with_nested_depth`](super::Download::with_nested_depth).
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use binstalk_types::cargo_toml_binstall::PkgFmt;
+use tracing::debug;
+
+use super::{
+    extract_file_with, DownloadError, ExtractFilter, ExtractProgress, ExtractedFiles,
+    ExtractedFilesEntry, JournalEntry,
+};
+
+/// Default of [`Client::with_nested_depth`](crate::remote::Client::with_nested_depth).
+pub(crate) const DEFAULT_NESTED_DEPTH: u8 = 1;
+
+/// Return the path and format of the only file of `extracted_files`, if
+/// it is an archive.
+fn single_archive(extracted_files: &ExtractedFiles) -> Option<(&Path, PkgFmt)> {
+    let mut files = extracted_files.files();
+    let file = files.next()?;
+    if files.next().is_some() {
+        return None;
+    }
+
+    let fmt = PkgFmt::guess_pkg_format(file.file_name()?.to_str()?)?;
+    (fmt != PkgFmt::Bin).then_some((file, fmt))
+}
+
+/// While `extracted_files`, extracted to `dst`, consist of a single archive
+/// and less than `max_depth` levels have been extracted, replace it with
+/// its content.
+pub(super) async fn extract_nested(
+    mut extracted_files: ExtractedFiles,
+    dst: &Path,
+    max_depth: u8,
+    progress: Option<Arc<dyn ExtractProgress>>,
+    filter: Option<ExtractFilter>,
+    journal_entry: Option<&JournalEntry>,
+) -> Result<ExtractedFiles, DownloadError> {
+    for depth in 1..=max_depth {
+        let Some((archive, fmt)) = single_archive(&extracted_files) else {
+            break;
+        };
+        debug!(
+            "Extracting nested {fmt} archive '{}' (depth {depth})",
+            archive.display()
+        );
+
+        // Move the archive out of `dst` so that it is not left behind
+        // among its own content.
+        let parent = match dst.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let tempdir = tempfile::Builder::new()
+            .prefix(".nested-")
+            .tempdir_in(parent)?;
+        if let Some(journal_entry) = journal_entry {
+            journal_entry.add_temp_path(tempdir.path());
+        }
+        let src = tempdir.path().join(archive.file_name().unwrap());
+        tokio::fs::rename(dst.join(archive), &src).await?;
+        remove_dirs(&extracted_files, dst).await;
+
+        extracted_files =
+            extract_file_with(&src, fmt, dst, progress.clone(), filter.clone()).await?;
+    }
+
+    Ok(extracted_files)
+}
+
+/// Remove the directories of `extracted_files` from `dst`, deepest first,
+/// keeping those which are not empty, e.g. since they existed beforehand.
+async fn remove_dirs(extracted_files: &ExtractedFiles, dst: &Path) {
+    let mut dirs: Vec<PathBuf> = extracted_files
+        .0
+        .iter()
+        .filter(|(path, entry)| {
+            matches!(entry, ExtractedFilesEntry::Dir(_)) && &***path != Path::new(".")
+        })
+        .map(|(path, _)| dst.join(path))
+        .collect();
+    dirs.sort_unstable_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+
+    for dir in dirs {
+        if let Err(err) = tokio::fs::remove_dir(&dir).await {
+            debug!("Keeping '{}': {err}", dir.display());
+        }
+    }
+}
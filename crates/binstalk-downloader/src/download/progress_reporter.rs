@@ -0,0 +1,102 @@
+use std::{path::Path, sync::Arc, time::Instant};
+
+use super::extract_progress::{ExtractProgress, REPORT_INTERVAL};
+
+/// Receives the progress of a [`Download`](super::Download): the number of
+/// bytes downloaded out of the total size, and the progress of extracting
+/// the entries of the package.
+pub trait ProgressReporter: Send + Sync {
+    /// The download starts, `total` being the number of bytes to download
+    /// if known.
+    fn on_download_start(&self, total: Option<u64>);
+
+    /// `received` bytes have been downloaded so far.
+    ///
+    /// It is called periodically while downloading and once more once the
+    /// download is done.
+    fn on_download_progress(&self, received: u64);
+
+    /// See [`ExtractProgress::on_entry_progress`].
+    fn on_entry_progress(&self, path: &Path, written: u64, size: u64) {
+        let _ = (path, written, size);
+    }
+}
+
+/// Forwards the progress of extracting the entries to a
+/// [`ProgressReporter`].
+pub(super) struct ReporterExtractProgress(pub(super) Arc<dyn ProgressReporter>);
+
+impl ExtractProgress for ReporterExtractProgress {
+    fn on_entry_progress(&self, path: &Path, written: u64, size: u64) {
+        self.0.on_entry_progress(path, written, size)
+    }
+}
+
+/// Tracks the number of bytes downloaded.
+pub(super) struct DownloadProgress {
+    reporter: Arc<dyn ProgressReporter>,
+    received: u64,
+    last_report: Instant,
+}
+
+impl DownloadProgress {
+    pub(super) fn new(reporter: Arc<dyn ProgressReporter>, total: Option<u64>) -> Self {
+        reporter.on_download_start(total);
+
+        Self {
+            reporter,
+            received: 0,
+            last_report: Instant::now(),
+        }
+    }
+
+    pub(super) fn advance(&mut self, n: u64) {
+        self.received += n;
+
+        if self.last_report.elapsed() >= REPORT_INTERVAL {
+            self.reporter.on_download_progress(self.received);
+            self.last_report = Instant::now();
+        }
+    }
+}
+
+impl Drop for DownloadProgress {
+    fn drop(&mut self) {
+        self.reporter.on_download_progress(self.received);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Reports(Mutex<Vec<(Option<u64>, u64)>>);
+
+    impl ProgressReporter for Reports {
+        fn on_download_start(&self, total: Option<u64>) {
+            self.0.lock().unwrap().push((total, 0));
+        }
+
+        fn on_download_progress(&self, received: u64) {
+            let mut reports = self.0.lock().unwrap();
+            let total = reports.last().unwrap().0;
+            reports.push((total, received));
+        }
+    }
+
+    #[test]
+    fn test_download_progress() {
+        let reports = Arc::new(Reports::default());
+
+        let mut progress = DownloadProgress::new(reports.clone(), Some(3));
+        progress.advance(1);
+        progress.advance(2);
+        drop(progress);
+
+        // Progress is reported at most once per interval, and once done.
+        assert_eq!(*reports.0.lock().unwrap(), [(Some(3), 0), (Some(3), 3)]);
+    }
+}
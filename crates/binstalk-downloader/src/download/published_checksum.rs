@@ -0,0 +1,172 @@
+//! Checksums published along the artifacts, e.g. to verify the artifacts
+//! downloaded from the mirrors of [`Client::with_mirrors`].
+
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use compact_str::CompactString;
+use futures_util::{future, stream, Stream, StreamExt};
+use tracing::debug;
+
+use super::{ChecksumMismatch, DataVerifier, DownloadError, Sha256Verifier, Sha512Verifier};
+use crate::remote::{Client, Error as RemoteError, StatusCode, Url};
+
+/// Checksum published at the url of an artifact with a `.sha256` or
+/// `.sha512` suffix, as output by `sha256sum` and `sha512sum`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PublishedChecksum {
+    /// Name of the algorithm, `sha256` or `sha512`.
+    pub algorithm: &'static str,
+    /// Hex encoded digest.
+    pub digest: CompactString,
+}
+
+impl PublishedChecksum {
+    /// Parse the checksum file of `algorithm`, in which the file name may
+    /// follow the digest.
+    pub fn parse(algorithm: &'static str, checksum: &[u8]) -> Self {
+        let checksum = String::from_utf8_lossy(checksum);
+        Self {
+            algorithm,
+            digest: checksum
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .into(),
+        }
+    }
+
+    /// Fetch the checksum published for `url` by its host, bypassing the
+    /// mirrors of [`Client::with_mirrors`], or return `None` if there is
+    /// none.
+    ///
+    /// `.sha256` is looked for first, then `.sha512`.
+    pub async fn fetch(client: &Client, url: &Url) -> Result<Option<Self>, RemoteError> {
+        for algorithm in ["sha256", "sha512"] {
+            let mut checksum_url = url.clone();
+            checksum_url.set_path(&format!("{}.{algorithm}", url.path()));
+
+            let response = client
+                .get(checksum_url.clone())
+                .without_mirrors()
+                .send(false)
+                .await?;
+            if response.status() == StatusCode::NOT_FOUND {
+                continue;
+            }
+            debug!("Verifying the artifact against {checksum_url}");
+            let checksum = response.error_for_status()?.bytes().await?;
+
+            return Ok(Some(Self::parse(algorithm, &checksum)));
+        }
+
+        Ok(None)
+    }
+
+    fn verifier(&self) -> Verifier {
+        if self.algorithm == "sha256" {
+            Verifier::Sha256(Sha256Verifier::new())
+        } else {
+            Verifier::Sha512(Sha512Verifier::new())
+        }
+    }
+
+    /// Check that `data` matches the checksum.
+    pub fn verify(&self, data: &Bytes) -> Result<(), ChecksumMismatch> {
+        let mut verifier = self.verifier();
+        verifier.update(data);
+        verifier.verify_hex(&self.digest)
+    }
+
+    /// Check that the data of `stream` matches the checksum as it is
+    /// consumed, failing at its end if it does not.
+    pub(super) fn verify_stream<S>(
+        self,
+        stream: S,
+    ) -> impl Stream<Item = Result<Bytes, DownloadError>> + Send + Sync + Unpin
+    where
+        S: Stream<Item = Result<Bytes, DownloadError>> + Send + Sync + Unpin,
+    {
+        let verifier = Arc::new(Mutex::new(Some(self.verifier())));
+        let finish = verifier.clone();
+
+        stream
+            .map(move |res| {
+                if let (Ok(bytes), Some(verifier)) = (&res, &mut *verifier.lock().unwrap()) {
+                    verifier.update(bytes);
+                }
+                res
+            })
+            .chain(
+                stream::once(future::lazy(move |_| {
+                    let verifier = finish.lock().unwrap().take()?;
+                    let res = verifier.verify_hex(&self.digest);
+                    res.err().map(|err| Err(DownloadError::from(err)))
+                }))
+                .filter_map(future::ready),
+            )
+    }
+}
+
+enum Verifier {
+    Sha256(Sha256Verifier),
+    Sha512(Sha512Verifier),
+}
+
+impl Verifier {
+    fn update(&mut self, data: &Bytes) {
+        match self {
+            Verifier::Sha256(verifier) => verifier.update(data),
+            Verifier::Sha512(verifier) => verifier.update(data),
+        }
+    }
+
+    fn verify_hex(self, expected: &str) -> Result<(), ChecksumMismatch> {
+        match self {
+            Verifier::Sha256(verifier) => verifier.verify_hex(expected),
+            Verifier::Sha512(verifier) => verifier.verify_hex(expected),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    async fn verify_stream(
+        checksum: PublishedChecksum,
+        chunks: &[&'static [u8]],
+    ) -> Vec<Result<Bytes, String>> {
+        let stream = stream::iter(chunks.iter().map(|chunk| Ok(Bytes::from_static(chunk))));
+        checksum
+            .verify_stream(stream)
+            .map(|res| res.map_err(|err| err.to_string()))
+            .collect()
+            .await
+    }
+
+    #[test]
+    fn test_parse() {
+        let checksum =
+            PublishedChecksum::parse("sha256", format!("{SHA256}  foo.tgz\n").as_bytes());
+        assert_eq!(checksum.digest, SHA256);
+        checksum.verify(&Bytes::from_static(b"abc")).unwrap();
+        checksum.verify(&Bytes::from_static(b"abd")).unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn test_verify_stream() {
+        let checksum = PublishedChecksum::parse("sha256", SHA256.as_bytes());
+
+        assert_eq!(
+            verify_stream(checksum.clone(), &[b"a", b"bc"]).await,
+            [Ok(Bytes::from_static(b"a")), Ok(Bytes::from_static(b"bc"))]
+        );
+
+        let res = verify_stream(checksum, &[b"a", b"bd"]).await;
+        assert_eq!(res.len(), 3);
+        assert!(res[2].as_ref().unwrap_err().contains("checksum mismatch"));
+    }
+}
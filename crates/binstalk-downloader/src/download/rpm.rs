@@ -0,0 +1,382 @@
+//! Extraction of RPM packages.
+//!
+//! An `.rpm` is made of a lead, a signature header and a header, followed
+//! by the payload, a compressed cpio archive of the files of the package.
+//! Like for deb packages, only the files in `usr/bin` are extracted, to the
+//! root of the destination.
+
+use std::{
+    fs,
+    io::{self, BufRead, Read, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use sha2::{Digest, Sha256};
+
+use super::{
+    async_extracter::normalize_tar_path,
+    deb::map_data_path,
+    extract_progress::{EntryProgress, ExtractProgress},
+    ExtractFilter, ExtractedFiles, TarBasedFmt,
+};
+
+const LEAD_MAGIC: &[u8] = b"\xED\xAB\xEE\xDB";
+const LEAD_LEN: usize = 96;
+
+const HEADER_MAGIC: &[u8] = b"\x8E\xAD\xE8\x01";
+/// Limits of the number of entries and of the size of the data store of
+/// headers, as enforced by rpm.
+const HEADER_TAGS_MAX: u32 = 0xFFFF;
+const HEADER_DATA_MAX: u32 = 256 * 1024 * 1024;
+
+const RPMTAG_PAYLOADFORMAT: u32 = 1124;
+const RPMTAG_PAYLOADCOMPRESSOR: u32 = 1125;
+const RPM_STRING_TYPE: u32 = 6;
+
+const CPIO_HEADER_LEN: usize = 110;
+const CPIO_TRAILER: &[u8] = b"TRAILER!!!";
+/// Maximum length of the names in cpio archives, as `PATH_MAX`.
+const CPIO_NAME_MAX: usize = 4096;
+const S_IFMT: u32 = 0o170000;
+const S_IFREG: u32 = 0o100000;
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn unsupported(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, msg.into())
+}
+
+fn skip(reader: &mut impl Read, n: u64) -> io::Result<()> {
+    if io::copy(&mut reader.take(n), &mut io::sink())? == n {
+        Ok(())
+    } else {
+        Err(io::ErrorKind::UnexpectedEof.into())
+    }
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes(bytes.try_into().unwrap())
+}
+
+/// Header structure of RPM packages, used by both the signature and the
+/// header.
+struct Header {
+    /// Entries of the index, as `(tag, type, offset)`.
+    index: Vec<(u32, u32, u32)>,
+    store: Vec<u8>,
+}
+
+impl Header {
+    fn read(rpm: &mut impl Read) -> io::Result<Self> {
+        let mut intro = [0; 16];
+        rpm.read_exact(&mut intro)?;
+        if &intro[..4] != HEADER_MAGIC {
+            return Err(invalid("invalid header in rpm package"));
+        }
+
+        let index_len = be_u32(&intro[8..12]);
+        let store_len = be_u32(&intro[12..16]);
+        if index_len > HEADER_TAGS_MAX || store_len > HEADER_DATA_MAX {
+            return Err(invalid("header of rpm package is too large"));
+        }
+
+        let mut index = vec![0; index_len as usize * 16];
+        rpm.read_exact(&mut index)?;
+        let mut store = vec![0; store_len as usize];
+        rpm.read_exact(&mut store)?;
+
+        Ok(Self {
+            index: index
+                .chunks_exact(16)
+                .map(|entry| {
+                    (
+                        be_u32(&entry[..4]),
+                        be_u32(&entry[4..8]),
+                        be_u32(&entry[8..12]),
+                    )
+                })
+                .collect(),
+            store,
+        })
+    }
+
+    /// Length of the header, including its intro.
+    fn len(&self) -> usize {
+        16 + self.index.len() * 16 + self.store.len()
+    }
+
+    /// Return the value of the string `tag`, if any.
+    fn string(&self, tag: u32) -> io::Result<Option<&str>> {
+        let Some((_, ty, offset)) = self.index.iter().find(|(t, ..)| *t == tag) else {
+            return Ok(None);
+        };
+        if *ty != RPM_STRING_TYPE {
+            return Err(invalid(format!("tag {tag} of rpm package is not a string")));
+        }
+
+        let value = self
+            .store
+            .get(*offset as usize..)
+            .and_then(|value| value.split(|b| *b == 0).next())
+            .and_then(|value| std::str::from_utf8(value).ok())
+            .ok_or_else(|| invalid(format!("invalid tag {tag} in rpm package")))?;
+
+        Ok(Some(value))
+    }
+}
+
+/// Skip the headers of the RPM package `rpm`, and return the compression
+/// of its payload, as the format of the tarball having the same one, along
+/// with a reader of it.
+pub(super) fn payload<R: BufRead>(mut rpm: R) -> io::Result<(TarBasedFmt, R)> {
+    let mut lead = [0; LEAD_LEN];
+    rpm.read_exact(&mut lead)?;
+    if &lead[..4] != LEAD_MAGIC {
+        return Err(invalid("not a rpm package"));
+    }
+
+    // The signature is padded to 8 bytes.
+    let signature = Header::read(&mut rpm)?;
+    skip(&mut rpm, ((8 - signature.len() % 8) % 8) as u64)?;
+
+    let header = Header::read(&mut rpm)?;
+
+    match header.string(RPMTAG_PAYLOADFORMAT)? {
+        None | Some("cpio") => (),
+        Some(format) => return Err(unsupported(format!("unsupported rpm payload {format}"))),
+    }
+
+    let fmt = match header.string(RPMTAG_PAYLOADCOMPRESSOR)? {
+        // Payloads are compressed with gzip unless specified otherwise.
+        None | Some("gzip") => TarBasedFmt::Tgz,
+        Some("bzip2") => TarBasedFmt::Tbz2,
+        Some("xz") => TarBasedFmt::Txz,
+        Some("zstd") => TarBasedFmt::Tzstd,
+        Some(compressor) => {
+            return Err(unsupported(format!(
+                "unsupported rpm payload compression {compressor}"
+            )))
+        }
+    };
+
+    Ok((fmt, rpm))
+}
+
+/// Parse the hexadecimal field `index` of the cpio header `header`.
+fn cpio_field(header: &[u8; CPIO_HEADER_LEN], index: usize) -> io::Result<u32> {
+    let field = &header[6 + index * 8..][..8];
+    std::str::from_utf8(field)
+        .ok()
+        .and_then(|field| u32::from_str_radix(field, 16).ok())
+        .ok_or_else(|| invalid("invalid cpio header in rpm payload"))
+}
+
+/// Extract the files in `usr/bin` of the cpio archive `cpio` into `dst`.
+pub(super) fn extract_cpio(
+    mut cpio: impl Read,
+    dst: &Path,
+    progress: Option<Arc<dyn ExtractProgress>>,
+    filter: Option<ExtractFilter>,
+) -> io::Result<ExtractedFiles> {
+    fs::create_dir_all(dst)?;
+
+    let mut extracted_files = ExtractedFiles::new();
+    let mut buf = [0; 8192];
+
+    loop {
+        let mut header = [0; CPIO_HEADER_LEN];
+        cpio.read_exact(&mut header)?;
+        match &header[..6] {
+            b"070701" | b"070702" => (),
+            // Used by rpm for files larger than 4GiB.
+            b"07070X" => return Err(unsupported("unsupported cpio format in rpm payload")),
+            _ => return Err(invalid("invalid cpio header in rpm payload")),
+        }
+
+        let mode = cpio_field(&header, 1)?;
+        let size = u64::from(cpio_field(&header, 6)?);
+        let name_len = cpio_field(&header, 11)? as usize;
+        if name_len > CPIO_NAME_MAX {
+            return Err(invalid("name too long in rpm payload"));
+        }
+
+        // The name is NUL terminated, and padded along with the header to
+        // 4 bytes.
+        let mut name = vec![0; name_len];
+        cpio.read_exact(&mut name)?;
+        skip(
+            &mut cpio,
+            ((4 - (CPIO_HEADER_LEN + name_len) % 4) % 4) as u64,
+        )?;
+        let name = name.strip_suffix(b"\0").unwrap_or(&name);
+        if name == CPIO_TRAILER {
+            break;
+        }
+
+        let path = std::str::from_utf8(name)
+            .ok()
+            .filter(|_| mode & S_IFMT == S_IFREG)
+            .and_then(|name| normalize_tar_path(Path::new(name)))
+            .and_then(|path| map_data_path(&path).map(Path::to_owned))
+            .filter(|path| filter.as_ref().map_or(true, |filter| filter(path)));
+
+        if let Some(path) = path {
+            let outpath = dst.join(&path);
+            if let Some(parent) = outpath.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut file = fs::File::create(&outpath)?;
+            let mut hasher = Sha256::new();
+            let mut entry_progress = progress
+                .clone()
+                .map(|progress| EntryProgress::new(progress, path.clone(), size));
+
+            let mut data = (&mut cpio).take(size);
+            let mut left = size;
+            while left > 0 {
+                let n = data.read(&mut buf)?;
+                if n == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                file.write_all(&buf[..n])?;
+                hasher.update(&buf[..n]);
+                if let Some(entry_progress) = &mut entry_progress {
+                    entry_progress.advance(n as u64);
+                }
+                left -= n as u64;
+            }
+
+            #[cfg(unix)]
+            file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(
+                (mode & 0o7777) | 0o400,
+            ))?;
+
+            extracted_files.add_file(&path, Some(hasher.finalize().into()));
+        } else {
+            skip(&mut cpio, size)?;
+        }
+
+        // The data is padded to 4 bytes.
+        skip(&mut cpio, (4 - size % 4) % 4)?;
+    }
+
+    Ok(extracted_files)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::download::extract_file;
+    use binstalk_types::cargo_toml_binstall::PkgFmt;
+
+    fn header(entries: &[(u32, &str)]) -> Vec<u8> {
+        let mut index = Vec::new();
+        let mut store = Vec::new();
+        for (tag, value) in entries {
+            index.extend_from_slice(&tag.to_be_bytes());
+            index.extend_from_slice(&RPM_STRING_TYPE.to_be_bytes());
+            index.extend_from_slice(&(store.len() as u32).to_be_bytes());
+            index.extend_from_slice(&1_u32.to_be_bytes());
+            store.extend_from_slice(value.as_bytes());
+            store.push(0);
+        }
+
+        [
+            HEADER_MAGIC,
+            &[0; 4],
+            &(entries.len() as u32).to_be_bytes(),
+            &(store.len() as u32).to_be_bytes(),
+            &index,
+            &store,
+        ]
+        .concat()
+    }
+
+    fn cpio(entries: &[(&str, u32, &[u8])]) -> Vec<u8> {
+        let mut cpio = Vec::new();
+        let trailer = ("TRAILER!!!", 0, &b""[..]);
+        for (name, mode, data) in entries.iter().chain([&trailer]) {
+            cpio.extend_from_slice(b"070701");
+            for field in [0, *mode, 0, 0, 1, 0, data.len() as u32, 0, 0, 0, 0] {
+                cpio.extend_from_slice(format!("{field:08X}").as_bytes());
+            }
+            cpio.extend_from_slice(format!("{:08X}{:08X}", name.len() + 1, 0).as_bytes());
+            cpio.extend_from_slice(name.as_bytes());
+            cpio.push(0);
+            cpio.resize((cpio.len() + 3) / 4 * 4, 0);
+            cpio.extend_from_slice(data);
+            cpio.resize((cpio.len() + 3) / 4 * 4, 0);
+        }
+        cpio
+    }
+
+    fn rpm(compressor: &str, payload: &[u8]) -> Vec<u8> {
+        let mut lead = [0; LEAD_LEN];
+        lead[..4].copy_from_slice(LEAD_MAGIC);
+
+        // A signature, which needs padding.
+        let mut signature = header(&[(1000, "sig")]);
+        signature.resize((signature.len() + 7) / 8 * 8, 0);
+
+        [
+            &lead[..],
+            &signature,
+            &header(&[
+                (RPMTAG_PAYLOADFORMAT, "cpio"),
+                (RPMTAG_PAYLOADCOMPRESSOR, compressor),
+            ]),
+            payload,
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_payload() {
+        let package = rpm("zstd", b"payload");
+        let (fmt, mut payload) = payload(&package[..]).unwrap();
+        assert_eq!(fmt, TarBasedFmt::Tzstd);
+        let mut buf = Vec::new();
+        payload.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"payload");
+
+        let package = rpm("lzma", b"");
+        assert_eq!(
+            super::payload(&package[..]).unwrap_err().kind(),
+            io::ErrorKind::Unsupported
+        );
+
+        assert!(super::payload(&[0; LEAD_LEN + 16][..]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_extract_rpm() {
+        let cpio = cpio(&[
+            ("./usr/bin", 0o040755, b""),
+            ("./usr/bin/cargo-foo", 0o100755, b"foo"),
+            ("./usr/share/doc/cargo-foo/README", 0o100644, b"readme"),
+        ]);
+        let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+        encoder.write_all(&cpio).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("cargo-foo.rpm");
+        fs::write(&src, rpm("xz", &encoder.finish().unwrap())).unwrap();
+
+        let dst = dir.path().join("extracted");
+        let extracted_files = extract_file(&src, PkgFmt::Rpm, &dst).await.unwrap();
+
+        assert_eq!(
+            extracted_files.files().collect::<Vec<_>>(),
+            [Path::new("cargo-foo")]
+        );
+        assert_eq!(
+            extracted_files.sha256(Path::new("cargo-foo")),
+            Some(&Sha256::digest(b"foo").into())
+        );
+        assert_eq!(fs::read(dst.join("cargo-foo")).unwrap(), b"foo");
+        assert!(!dst.join("usr").exists());
+    }
+}
@@ -0,0 +1,1201 @@
+//! Extraction of 7z archives.
+//!
+//! Their header is at their end, so they are spooled to a file before being
+//! extracted. The Copy, LZMA, LZMA2, Deflate and BZip2 methods are
+//! supported, optionally combined with the BCJ x86 filter, which covers the
+//! archives created by 7-Zip with its default settings. Encrypted archives
+//! are not supported.
+
+use std::{
+    fs,
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use sha2::{Digest, Sha256};
+
+use super::{
+    extract_progress::{EntryProgress, ExtractProgress},
+    ExtractFilter, ExtractedFiles,
+};
+
+mod bcj;
+use bcj::BcjX86Writer;
+
+mod lzma;
+use lzma::{decode_lzma, decode_lzma2};
+
+const SIGNATURE: &[u8] = b"7z\xBC\xAF\x27\x1C";
+
+/// Identifiers of the properties of the header.
+mod id {
+    pub(super) const END: u64 = 0x00;
+    pub(super) const HEADER: u64 = 0x01;
+    pub(super) const ARCHIVE_PROPERTIES: u64 = 0x02;
+    pub(super) const ADDITIONAL_STREAMS_INFO: u64 = 0x03;
+    pub(super) const MAIN_STREAMS_INFO: u64 = 0x04;
+    pub(super) const FILES_INFO: u64 = 0x05;
+    pub(super) const PACK_INFO: u64 = 0x06;
+    pub(super) const UNPACK_INFO: u64 = 0x07;
+    pub(super) const SUBSTREAMS_INFO: u64 = 0x08;
+    pub(super) const SIZE: u64 = 0x09;
+    pub(super) const CRC: u64 = 0x0A;
+    pub(super) const FOLDER: u64 = 0x0B;
+    pub(super) const CODERS_UNPACK_SIZE: u64 = 0x0C;
+    pub(super) const NUM_UNPACK_STREAM: u64 = 0x0D;
+    pub(super) const EMPTY_STREAM: u64 = 0x0E;
+    pub(super) const EMPTY_FILE: u64 = 0x0F;
+    pub(super) const ANTI: u64 = 0x10;
+    pub(super) const NAME: u64 = 0x11;
+    pub(super) const WIN_ATTRIBUTES: u64 = 0x15;
+    pub(super) const ENCODED_HEADER: u64 = 0x17;
+}
+
+/// Identifiers of the methods of the coders.
+mod method {
+    pub(super) const COPY: &[u8] = &[0x00];
+    pub(super) const LZMA2: &[u8] = &[0x21];
+    pub(super) const LZMA: &[u8] = &[0x03, 0x01, 0x01];
+    pub(super) const BCJ_X86: &[u8] = &[0x03, 0x03, 0x01, 0x03];
+    pub(super) const DEFLATE: &[u8] = &[0x04, 0x01, 0x08];
+    pub(super) const BZIP2: &[u8] = &[0x04, 0x02, 0x02];
+    pub(super) const AES: &[u8] = &[0x06, 0xF1, 0x07, 0x01];
+}
+
+/// Set in the attributes of directories.
+const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+/// Set in the attributes if their high 16 bits are the unix mode.
+const FILE_ATTRIBUTE_UNIX_EXTENSION: u32 = 0x8000;
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn unsupported(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, msg.into())
+}
+
+struct HeaderReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> HeaderReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn bytes(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if n > self.data.len() {
+            return Err(invalid("truncated 7z header"));
+        }
+        let (bytes, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    /// Numbers are stored in 1 to 9 bytes, the number of leading ones of
+    /// the first byte being the number of bytes following it.
+    fn number(&mut self) -> io::Result<u64> {
+        let first = self.u8()?;
+        let mut mask = 0x80;
+        let mut value = 0;
+
+        for i in 0..8 {
+            if first & mask == 0 {
+                let high = u64::from(first & (mask - 1));
+                return Ok(value | (high << (8 * i)));
+            }
+            value |= u64::from(self.u8()?) << (8 * i);
+            mask >>= 1;
+        }
+
+        Ok(value)
+    }
+
+    /// Read the number of items following, each of which takes at least
+    /// one byte, so that it cannot be arbitrary large.
+    fn count(&mut self) -> io::Result<usize> {
+        usize::try_from(self.number()?)
+            .ok()
+            .filter(|count| *count <= self.data.len())
+            .ok_or_else(|| invalid("invalid count in 7z header"))
+    }
+
+    fn expect(&mut self, id: u64) -> io::Result<()> {
+        if self.number()? == id {
+            Ok(())
+        } else {
+            Err(invalid("unexpected property in 7z header"))
+        }
+    }
+
+    fn bits(&mut self, n: usize) -> io::Result<Vec<bool>> {
+        let bytes = self.bytes(n / 8 + usize::from(n % 8 != 0))?;
+        Ok((0..n)
+            .map(|i| bytes[i / 8] & (0x80 >> (i % 8)) != 0)
+            .collect())
+    }
+
+    /// Read which of the `n` items following are defined.
+    fn defined_bits(&mut self, n: usize) -> io::Result<Vec<bool>> {
+        if self.u8()? == 0 {
+            self.bits(n)
+        } else if n <= self.data.len() {
+            Ok(vec![true; n])
+        } else {
+            Err(invalid("invalid count in 7z header"))
+        }
+    }
+
+    fn digests(&mut self, n: usize) -> io::Result<Vec<Option<u32>>> {
+        self.defined_bits(n)?
+            .into_iter()
+            .map(|defined| defined.then(|| self.u32()).transpose())
+            .collect()
+    }
+}
+
+struct Coder {
+    method: Vec<u8>,
+    num_in_streams: usize,
+    num_out_streams: usize,
+    props: Vec<u8>,
+}
+
+/// The data of a folder, i.e. the concatenation of the data of one or more
+/// files.
+struct SubStream {
+    size: u64,
+    crc: Option<u32>,
+}
+
+/// A solid block of data, decoded by a graph of coders.
+struct Folder {
+    coders: Vec<Coder>,
+    /// Pairs of an input stream and of the output stream it is bound to.
+    bind_pairs: Vec<(usize, usize)>,
+    /// Input streams read from the packed streams.
+    packed_streams: Vec<usize>,
+    /// Sizes of the output streams.
+    unpack_sizes: Vec<u64>,
+    crc: Option<u32>,
+    substreams: Vec<SubStream>,
+}
+
+impl Folder {
+    fn read(r: &mut HeaderReader<'_>) -> io::Result<Self> {
+        let num_coders = r.count()?;
+        let mut coders = Vec::with_capacity(num_coders);
+
+        for _ in 0..num_coders {
+            let flags = r.u8()?;
+            if flags & 0x80 != 0 {
+                return Err(unsupported("7z alternative methods are not supported"));
+            }
+
+            let method = r.bytes(usize::from(flags & 0x0F))?.to_vec();
+            let (num_in_streams, num_out_streams) = if flags & 0x10 != 0 {
+                (r.count()?, r.count()?)
+            } else {
+                (1, 1)
+            };
+            let props = if flags & 0x20 != 0 {
+                let len = r.count()?;
+                r.bytes(len)?.to_vec()
+            } else {
+                Vec::new()
+            };
+
+            coders.push(Coder {
+                method,
+                num_in_streams,
+                num_out_streams,
+                props,
+            });
+        }
+
+        let num_in_streams: usize = coders.iter().map(|coder| coder.num_in_streams).sum();
+        let num_out_streams: usize = coders.iter().map(|coder| coder.num_out_streams).sum();
+        if num_out_streams == 0 {
+            return Err(invalid("7z folder without output"));
+        }
+
+        let bind_pairs = (1..num_out_streams)
+            .map(|_| Ok((r.count()?, r.count()?)))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let packed_streams = match num_in_streams.checked_sub(bind_pairs.len()) {
+            Some(1) => (0..num_in_streams)
+                .filter(|i| bind_pairs.iter().all(|(in_index, _)| in_index != i))
+                .take(1)
+                .collect(),
+            Some(n) if n > 1 => (0..n).map(|_| r.count()).collect::<io::Result<_>>()?,
+            _ => return Err(invalid("invalid 7z folder")),
+        };
+
+        Ok(Self {
+            coders,
+            bind_pairs,
+            packed_streams,
+            unpack_sizes: Vec::new(),
+            crc: None,
+            substreams: Vec::new(),
+        })
+    }
+
+    /// The output stream which is not bound, i.e. the data of the folder.
+    fn main_out_stream(&self) -> io::Result<usize> {
+        (0..self.unpack_sizes.len())
+            .find(|i| self.bind_pairs.iter().all(|(_, out_index)| out_index != i))
+            .ok_or_else(|| invalid("invalid 7z folder"))
+    }
+
+    fn unpack_size(&self) -> io::Result<u64> {
+        Ok(self.unpack_sizes[self.main_out_stream()?])
+    }
+
+    /// Return the coders, from the one outputting the data of the folder to
+    /// the one reading the packed stream, if they form a chain.
+    fn chain(&self) -> io::Result<Vec<(&Coder, u64)>> {
+        let unsupported = || unsupported("unsupported combination of 7z coders");
+
+        // Each stream has the index of its coder.
+        if self
+            .coders
+            .iter()
+            .any(|coder| coder.num_in_streams != 1 || coder.num_out_streams != 1)
+            || self.packed_streams.len() != 1
+        {
+            return Err(unsupported());
+        }
+
+        let mut chain = Vec::new();
+        let mut index = self.main_out_stream()?;
+
+        loop {
+            chain.push((&self.coders[index], self.unpack_sizes[index]));
+
+            match self
+                .bind_pairs
+                .iter()
+                .find(|(in_index, _)| *in_index == index)
+            {
+                Some((_, out_index))
+                    if *out_index < self.coders.len() && chain.len() < self.coders.len() =>
+                {
+                    index = *out_index
+                }
+                Some(_) => return Err(invalid("invalid 7z folder")),
+                None if self.packed_streams[0] == index => break Ok(chain),
+                None => return Err(invalid("invalid 7z folder")),
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct StreamsInfo {
+    /// Offset of the packed streams, after the signature header.
+    pack_pos: u64,
+    pack_sizes: Vec<u64>,
+    folders: Vec<Folder>,
+}
+
+impl StreamsInfo {
+    fn read(r: &mut HeaderReader<'_>) -> io::Result<Self> {
+        let mut info = Self::default();
+        let mut id = r.number()?;
+
+        if id == id::PACK_INFO {
+            info.pack_pos = r.number()?;
+            let num_pack_streams = r.count()?;
+
+            loop {
+                match r.number()? {
+                    id::END => break,
+                    id::SIZE => {
+                        info.pack_sizes = (0..num_pack_streams)
+                            .map(|_| r.number())
+                            .collect::<io::Result<_>>()?;
+                    }
+                    id::CRC => {
+                        r.digests(num_pack_streams)?;
+                    }
+                    _ => return Err(invalid("unexpected property in 7z header")),
+                }
+            }
+            if info.pack_sizes.len() != num_pack_streams {
+                return Err(invalid("missing sizes of 7z packed streams"));
+            }
+
+            id = r.number()?;
+        }
+
+        if id == id::UNPACK_INFO {
+            r.expect(id::FOLDER)?;
+            let num_folders = r.count()?;
+            if r.u8()? != 0 {
+                return Err(unsupported("external 7z folders are not supported"));
+            }
+            info.folders = (0..num_folders)
+                .map(|_| Folder::read(r))
+                .collect::<io::Result<_>>()?;
+
+            r.expect(id::CODERS_UNPACK_SIZE)?;
+            for folder in &mut info.folders {
+                let num_out_streams = folder
+                    .coders
+                    .iter()
+                    .map(|coder| coder.num_out_streams)
+                    .sum();
+                folder.unpack_sizes = (0..num_out_streams)
+                    .map(|_| r.number())
+                    .collect::<io::Result<_>>()?;
+            }
+
+            id = r.number()?;
+            if id == id::CRC {
+                for (folder, crc) in info.folders.iter_mut().zip(r.digests(num_folders)?) {
+                    folder.crc = crc;
+                }
+                id = r.number()?;
+            }
+            if id != id::END {
+                return Err(invalid("unexpected property in 7z header"));
+            }
+
+            id = r.number()?;
+        }
+
+        if id == id::SUBSTREAMS_INFO {
+            info.read_substreams_info(r)?;
+            id = r.number()?;
+        } else {
+            for folder in &mut info.folders {
+                folder.substreams = vec![SubStream {
+                    size: folder.unpack_size()?,
+                    crc: folder.crc,
+                }];
+            }
+        }
+
+        if id != id::END {
+            return Err(invalid("unexpected property in 7z header"));
+        }
+
+        Ok(info)
+    }
+
+    fn read_substreams_info(&mut self, r: &mut HeaderReader<'_>) -> io::Result<()> {
+        let mut id = r.number()?;
+
+        let mut num_substreams = vec![1; self.folders.len()];
+        if id == id::NUM_UNPACK_STREAM {
+            for num in &mut num_substreams {
+                *num = r.count()?;
+            }
+            id = r.number()?;
+        }
+
+        for (folder, num) in self.folders.iter_mut().zip(&num_substreams) {
+            if *num == 0 {
+                continue;
+            }
+
+            let mut sizes = Vec::with_capacity(*num);
+            if id == id::SIZE {
+                for _ in 1..*num {
+                    sizes.push(r.number()?);
+                }
+            } else if *num > 1 {
+                return Err(invalid("missing sizes of 7z substreams"));
+            }
+
+            let last = sizes
+                .iter()
+                .try_fold(folder.unpack_size()?, |left, size| left.checked_sub(*size))
+                .ok_or_else(|| invalid("invalid sizes of 7z substreams"))?;
+            sizes.push(last);
+
+            // The CRC of the folder is the CRC of its only substream.
+            let crc = if *num == 1 { folder.crc } else { None };
+            folder.substreams = sizes
+                .into_iter()
+                .map(|size| SubStream { size, crc })
+                .collect();
+        }
+        if id == id::SIZE {
+            id = r.number()?;
+        }
+
+        loop {
+            match id {
+                id::END => break Ok(()),
+                id::CRC => {
+                    let mut missing_crcs: Vec<&mut Option<u32>> = self
+                        .folders
+                        .iter_mut()
+                        .filter(|folder| folder.substreams.len() != 1 || folder.crc.is_none())
+                        .flat_map(|folder| &mut folder.substreams)
+                        .map(|substream| &mut substream.crc)
+                        .collect();
+
+                    let digests = r.digests(missing_crcs.len())?;
+                    for (crc, digest) in missing_crcs.iter_mut().zip(digests) {
+                        **crc = digest;
+                    }
+                }
+                _ => {
+                    let len = r.count()?;
+                    r.bytes(len)?;
+                }
+            }
+            id = r.number()?;
+        }
+    }
+
+    /// Return the offset and size of the packed stream of the folder at
+    /// `index`, relative to the end of the signature header.
+    fn pack_range(&self, index: usize) -> io::Result<(u64, u64)> {
+        let first_pack_stream: usize = self.folders[..index]
+            .iter()
+            .map(|folder| folder.packed_streams.len())
+            .sum();
+
+        let (before, after) = self
+            .pack_sizes
+            .split_at(first_pack_stream.min(self.pack_sizes.len()));
+        let size = *after
+            .first()
+            .ok_or_else(|| invalid("missing 7z packed stream"))?;
+        let offset = before
+            .iter()
+            .try_fold(self.pack_pos, |offset, size| offset.checked_add(*size))
+            .ok_or_else(|| invalid("invalid sizes of 7z packed streams"))?;
+
+        Ok((offset, size))
+    }
+}
+
+struct FileInfo {
+    name: String,
+    has_stream: bool,
+    is_dir: bool,
+    is_anti: bool,
+    attributes: Option<u32>,
+}
+
+impl FileInfo {
+    fn read_all(r: &mut HeaderReader<'_>) -> io::Result<Vec<Self>> {
+        let num_files = r.count()?;
+
+        let mut empty_streams = vec![false; num_files];
+        let mut empty_files = Vec::new();
+        let mut antis = Vec::new();
+        let mut names = Vec::new();
+        let mut attributes = vec![None; num_files];
+
+        loop {
+            let id = r.number()?;
+            if id == id::END {
+                break;
+            }
+
+            let len = r.count()?;
+            let mut property = HeaderReader::new(r.bytes(len)?);
+
+            match id {
+                id::EMPTY_STREAM => empty_streams = property.bits(num_files)?,
+                id::EMPTY_FILE | id::ANTI => {
+                    let num_empty_streams = empty_streams.iter().filter(|empty| **empty).count();
+                    let bits = property.bits(num_empty_streams)?;
+                    if id == id::EMPTY_FILE {
+                        empty_files = bits;
+                    } else {
+                        antis = bits;
+                    }
+                }
+                id::NAME => {
+                    if property.u8()? != 0 {
+                        return Err(unsupported("external 7z names are not supported"));
+                    }
+                    let utf16: Vec<u16> = property
+                        .data
+                        .chunks_exact(2)
+                        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+                        .collect();
+                    names = utf16
+                        .split(|unit| *unit == 0)
+                        .take(num_files)
+                        .map(String::from_utf16)
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| invalid("invalid name in 7z header"))?;
+                }
+                id::WIN_ATTRIBUTES => {
+                    let defined = property.defined_bits(num_files)?;
+                    if property.u8()? != 0 {
+                        return Err(unsupported("external 7z attributes are not supported"));
+                    }
+                    for (attributes, defined) in attributes.iter_mut().zip(defined) {
+                        if defined {
+                            *attributes = Some(property.u32()?);
+                        }
+                    }
+                }
+                // Times and padding
+                _ => (),
+            }
+        }
+
+        if names.len() != num_files {
+            return Err(invalid("missing names in 7z header"));
+        }
+
+        let mut empty_stream_index = 0;
+        Ok(names
+            .into_iter()
+            .zip(empty_streams)
+            .zip(attributes)
+            .map(|((name, empty_stream), attributes)| {
+                let mut file = FileInfo {
+                    name,
+                    has_stream: !empty_stream,
+                    is_dir: attributes.map_or(false, |attributes| {
+                        attributes & FILE_ATTRIBUTE_DIRECTORY != 0
+                    }),
+                    is_anti: false,
+                    attributes,
+                };
+
+                if empty_stream {
+                    let is_empty_file = empty_files
+                        .get(empty_stream_index)
+                        .copied()
+                        .unwrap_or(false);
+                    file.is_dir |= !is_empty_file;
+                    file.is_anti = antis.get(empty_stream_index).copied().unwrap_or(false);
+                    empty_stream_index += 1;
+                }
+
+                file
+            })
+            .collect())
+    }
+
+    fn unix_mode(&self) -> Option<u32> {
+        self.attributes
+            .filter(|attributes| attributes & FILE_ATTRIBUTE_UNIX_EXTENSION != 0)
+            .map(|attributes| attributes >> 16)
+    }
+
+    fn is_symlink(&self) -> bool {
+        self.unix_mode()
+            .map_or(false, |mode| mode & 0o170000 == 0o120000)
+    }
+}
+
+#[derive(Default)]
+struct Header {
+    streams: StreamsInfo,
+    files: Vec<FileInfo>,
+}
+
+impl Header {
+    fn read(r: &mut HeaderReader<'_>) -> io::Result<Self> {
+        let mut header = Self::default();
+        let mut id = r.number()?;
+
+        if id == id::ARCHIVE_PROPERTIES {
+            while r.number()? != id::END {
+                let len = r.count()?;
+                r.bytes(len)?;
+            }
+            id = r.number()?;
+        }
+        if id == id::ADDITIONAL_STREAMS_INFO {
+            return Err(unsupported("7z additional streams are not supported"));
+        }
+        if id == id::MAIN_STREAMS_INFO {
+            header.streams = StreamsInfo::read(r)?;
+            id = r.number()?;
+        }
+        if id == id::FILES_INFO {
+            header.files = FileInfo::read_all(r)?;
+            id = r.number()?;
+        }
+
+        if id != id::END {
+            return Err(invalid("unexpected property in 7z header"));
+        }
+
+        Ok(header)
+    }
+}
+
+fn read_header(archive: &mut fs::File) -> io::Result<Header> {
+    let mut start_header = [0; 32];
+    archive.seek(SeekFrom::Start(0))?;
+    archive.read_exact(&mut start_header)?;
+
+    if !start_header.starts_with(SIGNATURE) {
+        return Err(invalid("not a 7z archive"));
+    }
+    if start_header[6] != 0 {
+        return Err(unsupported("unsupported 7z version"));
+    }
+    if crc32fast::hash(&start_header[12..]).to_le_bytes() != start_header[8..12] {
+        return Err(invalid("corrupted 7z start header"));
+    }
+
+    let next_header_offset = u64::from_le_bytes(start_header[12..20].try_into().unwrap());
+    let next_header_size = u64::from_le_bytes(start_header[20..28].try_into().unwrap());
+    let next_header_crc = u32::from_le_bytes(start_header[28..32].try_into().unwrap());
+
+    // Empty archive
+    if next_header_size == 0 {
+        return Ok(Header::default());
+    }
+
+    let archive_len = archive.metadata()?.len();
+    if next_header_offset
+        .checked_add(next_header_size)
+        .and_then(|end| end.checked_add(32))
+        .map_or(true, |end| end > archive_len)
+    {
+        return Err(invalid("truncated 7z archive"));
+    }
+
+    let mut data = vec![0; next_header_size as usize];
+    archive.seek(SeekFrom::Start(32 + next_header_offset))?;
+    archive.read_exact(&mut data)?;
+
+    if crc32fast::hash(&data) != next_header_crc {
+        return Err(invalid("corrupted 7z header"));
+    }
+
+    loop {
+        let mut r = HeaderReader::new(&data);
+
+        match r.number()? {
+            id::HEADER => break Header::read(&mut r),
+            id::ENCODED_HEADER => {
+                // The header is itself packed, as the data of a folder.
+                let streams = StreamsInfo::read(&mut r)?;
+                let folder = streams
+                    .folders
+                    .first()
+                    .ok_or_else(|| invalid("invalid 7z encoded header"))?;
+
+                let mut decoded = Vec::new();
+                decode_folder(archive, &streams, 0, &mut decoded)?;
+
+                if folder
+                    .crc
+                    .map_or(false, |crc| crc != crc32fast::hash(&decoded))
+                {
+                    return Err(invalid("corrupted 7z header"));
+                }
+
+                data = decoded;
+            }
+            _ => break Err(invalid("invalid 7z header")),
+        }
+    }
+}
+
+/// Decode the data of the coder into `out`.
+fn decode(
+    coder: &Coder,
+    input: impl Read,
+    mut out: impl Write,
+    unpack_size: u64,
+) -> io::Result<()> {
+    match &coder.method[..] {
+        method::COPY => {
+            io::copy(&mut input.take(unpack_size), &mut out)?;
+        }
+        method::LZMA => decode_lzma(&coder.props, input, out, unpack_size)?,
+        method::LZMA2 => decode_lzma2(&coder.props, input, out, unpack_size)?,
+        method::DEFLATE => {
+            io::copy(&mut flate2::read::DeflateDecoder::new(input), &mut out)?;
+        }
+        method::BZIP2 => {
+            io::copy(&mut bzip2::read::BzDecoder::new(input), &mut out)?;
+        }
+        method::AES => return Err(unsupported("encrypted 7z archives are not supported")),
+        method => {
+            return Err(unsupported(format!(
+                "unsupported 7z compression method {method:02X?}"
+            )))
+        }
+    }
+
+    Ok(())
+}
+
+/// Decode the data of the folder at `index` into `out`.
+fn decode_folder(
+    archive: &mut fs::File,
+    streams: &StreamsInfo,
+    index: usize,
+    mut out: impl Write,
+) -> io::Result<()> {
+    let folder = &streams.folders[index];
+    let (offset, size) = streams.pack_range(index)?;
+
+    archive.seek(SeekFrom::Start(
+        offset
+            .checked_add(32)
+            .ok_or_else(|| invalid("invalid 7z packed stream"))?,
+    ))?;
+    let input = BufReader::new(archive.take(size));
+
+    match folder.chain()?[..] {
+        [(coder, unpack_size)] => decode(coder, input, out, unpack_size),
+        [(filter, _), (coder, unpack_size)] if filter.method == method::BCJ_X86 => {
+            let mut writer = BcjX86Writer::new(&mut out);
+            decode(coder, input, &mut writer, unpack_size)?;
+            writer.finish()?;
+            Ok(())
+        }
+        _ => Err(unsupported("unsupported combination of 7z coders")),
+    }
+}
+
+/// Normalize `name`, or return `None` if it is not a relative path
+/// within the destination.
+fn normalize_name(name: &str) -> Option<PathBuf> {
+    if name.contains('\0') {
+        return None;
+    }
+
+    // Names always use '/' on unix, and may use '\\' when created on
+    // windows.
+    let name = name.replace('\\', "/");
+    let mut normalized_path = PathBuf::new();
+
+    for part in Path::new(&name).components() {
+        match part {
+            Component::CurDir => continue,
+            Component::Normal(part) => normalized_path.push(part),
+            Component::Prefix(..) | Component::RootDir | Component::ParentDir => return None,
+        }
+    }
+
+    Some(normalized_path)
+}
+
+#[cfg(unix)]
+fn set_unix_mode(file: &fs::File, mode: Option<u32>) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if let Some(mode) = mode {
+        // The file needs to be at least readable for the current user.
+        file.set_permissions(fs::Permissions::from_mode((mode & 0o7777) | 0o400))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_unix_mode(_file: &fs::File, _mode: Option<u32>) -> io::Result<()> {
+    Ok(())
+}
+
+/// A file to extract the data of a substream to.
+struct Target {
+    path: PathBuf,
+    unix_mode: Option<u32>,
+}
+
+struct Output {
+    path: PathBuf,
+    unix_mode: Option<u32>,
+    file: fs::File,
+    hasher: Sha256,
+    progress: Option<EntryProgress>,
+}
+
+struct CurrentSubStream {
+    left: u64,
+    expected_crc: Option<u32>,
+    crc: crc32fast::Hasher,
+    output: Option<Output>,
+}
+
+/// Splits the data of a folder into its substreams, writing those with a
+/// [`Target`] to their file.
+struct FolderWriter<'a, I> {
+    substreams: I,
+    current: Option<CurrentSubStream>,
+    dst: &'a Path,
+    progress: Option<Arc<dyn ExtractProgress>>,
+    extracted_files: &'a mut ExtractedFiles,
+}
+
+impl<'a, I> FolderWriter<'a, I>
+where
+    I: Iterator<Item = (&'a SubStream, Option<Target>)>,
+{
+    /// Start the next non-empty substream, finishing the empty ones.
+    fn start_next(&mut self) -> io::Result<Option<&mut CurrentSubStream>> {
+        while self.current.is_none() {
+            let Some((substream, target)) = self.substreams.next() else {
+                return Ok(None);
+            };
+
+            let output = match target {
+                Some(Target { path, unix_mode }) => {
+                    let outpath = self.dst.join(&path);
+                    if let Some(parent) = outpath.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+
+                    Some(Output {
+                        file: fs::File::create(&outpath)?,
+                        hasher: Sha256::new(),
+                        progress: self.progress.clone().map(|progress| {
+                            EntryProgress::new(progress, path.clone(), substream.size)
+                        }),
+                        path,
+                        unix_mode,
+                    })
+                }
+                None => None,
+            };
+
+            self.current = Some(CurrentSubStream {
+                left: substream.size,
+                expected_crc: substream.crc,
+                crc: crc32fast::Hasher::new(),
+                output,
+            });
+
+            if substream.size == 0 {
+                self.finish_current()?;
+            }
+        }
+
+        Ok(self.current.as_mut())
+    }
+
+    fn finish_current(&mut self) -> io::Result<()> {
+        let Some(current) = self.current.take() else {
+            return Ok(());
+        };
+
+        if current
+            .expected_crc
+            .map_or(false, |crc| crc != current.crc.finalize())
+        {
+            return Err(invalid(match &current.output {
+                Some(output) => format!("CRC mismatch of {}", output.path.display()),
+                None => "CRC mismatch in 7z archive".to_string(),
+            }));
+        }
+
+        if let Some(mut output) = current.output {
+            output.file.flush()?;
+            set_unix_mode(&output.file, output.unix_mode)?;
+            self.extracted_files
+                .add_file(&output.path, Some(output.hasher.finalize().into()));
+        }
+
+        Ok(())
+    }
+
+    /// Finish the substreams, returning an error unless all of their data
+    /// has been written.
+    fn finish(mut self) -> io::Result<()> {
+        if self.current.is_some() || self.start_next()?.is_some() {
+            return Err(invalid("truncated data in 7z archive"));
+        }
+        Ok(())
+    }
+}
+
+impl<'a, I> Write for FolderWriter<'a, I>
+where
+    I: Iterator<Item = (&'a SubStream, Option<Target>)>,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let current = self
+            .start_next()?
+            .ok_or_else(|| invalid("more data than expected in 7z archive"))?;
+
+        let n = buf
+            .len()
+            .min(usize::try_from(current.left).unwrap_or(usize::MAX));
+        let data = &buf[..n];
+
+        current.crc.update(data);
+        if let Some(output) = &mut current.output {
+            output.file.write_all(data)?;
+            output.hasher.update(data);
+            if let Some(progress) = &mut output.progress {
+                progress.advance(n as u64);
+            }
+        }
+
+        current.left -= n as u64;
+        if current.left == 0 {
+            self.finish_current()?;
+        }
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Extract the 7z archive spooled to `archive` into `dst`.
+pub(super) fn extract_seven_zip(
+    archive: &mut fs::File,
+    dst: &Path,
+    progress: Option<Arc<dyn ExtractProgress>>,
+    filter: Option<ExtractFilter>,
+) -> io::Result<ExtractedFiles> {
+    let Header { streams, files } = read_header(archive)?;
+
+    fs::create_dir_all(dst)?;
+
+    let mut extracted_files = ExtractedFiles::new();
+
+    // The substreams of each folder, with the file to extract it to unless
+    // it is skipped.
+    let mut folders: Vec<Vec<(&SubStream, Option<Target>)>> =
+        streams.folders.iter().map(|_| Vec::new()).collect();
+    let mut substreams = streams
+        .folders
+        .iter()
+        .enumerate()
+        .flat_map(|(index, folder)| folder.substreams.iter().map(move |s| (index, s)));
+
+    for file in &files {
+        let path = normalize_name(&file.name)
+            .ok_or_else(|| invalid(format!("Invalid file path: {}", file.name)))?;
+        // Entries for the destination itself, i.e. `.`, are skipped.
+        let extract = !path.as_os_str().is_empty()
+            && !file.is_anti
+            && !file.is_symlink()
+            && filter.as_ref().map_or(true, |filter| filter(&path));
+
+        if file.has_stream {
+            let (index, substream) = substreams
+                .next()
+                .ok_or_else(|| invalid("missing data in 7z archive"))?;
+            let target = extract.then(|| Target {
+                path,
+                unix_mode: file.unix_mode(),
+            });
+            folders[index].push((substream, target));
+        } else if !extract {
+            continue;
+        } else if file.is_dir {
+            fs::create_dir_all(dst.join(&path))?;
+            extracted_files.add_dir(&path);
+        } else {
+            let outpath = dst.join(&path);
+            if let Some(parent) = outpath.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let file_created = fs::File::create(&outpath)?;
+            set_unix_mode(&file_created, file.unix_mode())?;
+            extracted_files.add_file(&path, Some(Sha256::digest(b"").into()));
+        }
+    }
+    if substreams.next().is_some() {
+        return Err(invalid("data without file in 7z archive"));
+    }
+
+    for (index, substreams) in folders.into_iter().enumerate() {
+        // Solid folders are decoded as a whole, unless all of their files
+        // are skipped.
+        if substreams.iter().all(|(_, target)| target.is_none()) {
+            continue;
+        }
+
+        let mut writer = FolderWriter {
+            substreams: substreams.into_iter(),
+            current: None,
+            dst,
+            progress: progress.clone(),
+            extracted_files: &mut extracted_files,
+        };
+        decode_folder(archive, &streams, index, &mut writer)?;
+        writer.finish()?;
+    }
+
+    Ok(extracted_files)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn number(n: u64) -> Vec<u8> {
+        if n < 0x80 {
+            vec![n as u8]
+        } else {
+            [&[0xFF], &n.to_le_bytes()[..]].concat()
+        }
+    }
+
+    /// A 7z archive storing `bin/cargo-foo`, `README.md`, the empty file
+    /// `bin/empty` and the directory `docs`.
+    fn archive() -> Vec<u8> {
+        let files: [(&str, &[u8]); 2] = [("bin/cargo-foo", b"foo"), ("README.md", b"readme")];
+        let data = [files[0].1, files[1].1].concat();
+
+        let names: Vec<u8> = ["bin/cargo-foo", "README.md", "bin/empty", "docs"]
+            .iter()
+            .flat_map(|name| name.encode_utf16().chain([0]))
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let attributes = [
+            FILE_ATTRIBUTE_UNIX_EXTENSION | (0o100755 << 16),
+            0x20,
+            0x20,
+            FILE_ATTRIBUTE_DIRECTORY,
+        ];
+
+        let header = [
+            &[id::HEADER as u8, id::MAIN_STREAMS_INFO as u8][..],
+            // A single packed stream, at the start.
+            &[id::PACK_INFO as u8, 0, 1, id::SIZE as u8],
+            &number(data.len() as u64),
+            &[id::END as u8],
+            // A single folder, using the Copy method.
+            &[id::UNPACK_INFO as u8, id::FOLDER as u8, 1, 0, 1, 0x01, 0x00],
+            &[id::CODERS_UNPACK_SIZE as u8],
+            &number(data.len() as u64),
+            &[id::END as u8],
+            // Containing the data of the two files.
+            &[id::SUBSTREAMS_INFO as u8, id::NUM_UNPACK_STREAM as u8, 2],
+            &[id::SIZE as u8],
+            &number(files[0].1.len() as u64),
+            &[id::CRC as u8, 1],
+            &crc32fast::hash(files[0].1).to_le_bytes(),
+            &crc32fast::hash(files[1].1).to_le_bytes(),
+            &[id::END as u8, id::END as u8],
+            &[id::FILES_INFO as u8, 4],
+            &[id::EMPTY_STREAM as u8, 1, 0b0011_0000],
+            &[id::EMPTY_FILE as u8, 1, 0b1000_0000],
+            &[id::NAME as u8],
+            &number(names.len() as u64 + 1),
+            &[0],
+            &names,
+            &[id::WIN_ATTRIBUTES as u8, 18, 1, 0],
+            &attributes.map(u32::to_le_bytes).concat(),
+            &[id::END as u8, id::END as u8],
+        ]
+        .concat();
+
+        let next_header = [
+            (data.len() as u64).to_le_bytes(),
+            (header.len() as u64).to_le_bytes(),
+        ]
+        .concat();
+        let next_header = [&next_header[..], &crc32fast::hash(&header).to_le_bytes()].concat();
+
+        [
+            SIGNATURE,
+            &[0, 4],
+            &crc32fast::hash(&next_header).to_le_bytes(),
+            &next_header,
+            &data,
+            &header,
+        ]
+        .concat()
+    }
+
+    fn extract(
+        archive: &[u8],
+        filter: Option<ExtractFilter>,
+    ) -> (tempfile::TempDir, io::Result<ExtractedFiles>) {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(archive).unwrap();
+
+        let dst = tempfile::tempdir().unwrap();
+        let extracted_files = extract_seven_zip(&mut file, dst.path(), None, filter);
+        (dst, extracted_files)
+    }
+
+    #[test]
+    fn test_extract_seven_zip() {
+        let (dst, extracted_files) = extract(&archive(), None);
+        let extracted_files = extracted_files.unwrap();
+        let dst = dst.path();
+
+        assert_eq!(fs::read(dst.join("bin/cargo-foo")).unwrap(), b"foo");
+        assert_eq!(fs::read(dst.join("README.md")).unwrap(), b"readme");
+        assert_eq!(fs::read(dst.join("bin/empty")).unwrap(), b"");
+        assert!(dst.join("docs").is_dir());
+
+        assert_eq!(
+            extracted_files.sha256(Path::new("bin/cargo-foo")),
+            Some(&Sha256::digest(b"foo").into())
+        );
+        assert!(extracted_files.has_file(Path::new("bin/empty")));
+        assert!(extracted_files.get_dir(Path::new("docs")).is_some());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = fs::metadata(dst.join("bin/cargo-foo"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o755);
+        }
+    }
+
+    #[test]
+    fn test_extract_seven_zip_filter() {
+        let filter: ExtractFilter = Arc::new(|path: &Path| path.starts_with("bin"));
+        let (dst, extracted_files) = extract(&archive(), Some(filter));
+        let extracted_files = extracted_files.unwrap();
+
+        assert!(extracted_files.has_file(Path::new("bin/cargo-foo")));
+        assert!(!extracted_files.has_file(Path::new("README.md")));
+        assert!(!dst.path().join("README.md").exists());
+        assert!(!dst.path().join("docs").exists());
+    }
+
+    #[test]
+    fn test_extract_seven_zip_crc_mismatch() {
+        let mut archive = archive();
+        // The data of `README.md`
+        archive[32 + 3] ^= 1;
+
+        let err = extract(&archive, None).1.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("README.md"), "{err}");
+    }
+
+    #[test]
+    fn test_normalize_name() {
+        assert_eq!(
+            normalize_name("./bin\\cargo-foo"),
+            Some(PathBuf::from("bin/cargo-foo"))
+        );
+        assert_eq!(normalize_name("."), Some(PathBuf::new()));
+        assert_eq!(normalize_name("../cargo-foo"), None);
+        assert_eq!(normalize_name("/bin/cargo-foo"), None);
+    }
+}
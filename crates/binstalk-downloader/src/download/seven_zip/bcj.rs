@@ -0,0 +1,190 @@
+//! The BCJ x86 filter, which 7-Zip applies to executables before
+//! compressing them, following `simple/x86.c` of xz.
+
+use std::io::{self, Write};
+
+fn test_ms_byte(byte: u8) -> bool {
+    byte == 0x00 || byte == 0xFF
+}
+
+/// Reverts the filter on the data written, before writing it to `inner`.
+pub(super) struct BcjX86Writer<W> {
+    inner: W,
+    /// Data written but not filtered yet, since the instruction at its end
+    /// may be incomplete.
+    buf: Vec<u8>,
+    /// Position of the start of `buf` in the data.
+    now_pos: u32,
+    prev_mask: u32,
+    prev_pos: u32,
+}
+
+impl<W: Write> BcjX86Writer<W> {
+    pub(super) fn new(inner: W) -> Self {
+        Self {
+            inner,
+            buf: Vec::new(),
+            now_pos: 0,
+            prev_mask: 0,
+            prev_pos: 0u32.wrapping_sub(5),
+        }
+    }
+
+    /// Filter `buf` and return the number of bytes filtered.
+    fn filter(&mut self) -> usize {
+        const MASK_TO_ALLOWED_STATUS: [bool; 8] =
+            [true, true, true, false, true, false, false, false];
+        const MASK_TO_BIT_NUMBER: [u32; 8] = [0, 1, 2, 2, 3, 3, 3, 3];
+
+        let buf = &mut self.buf;
+        let now_pos = self.now_pos;
+        let mut prev_mask = self.prev_mask;
+        let mut prev_pos = self.prev_pos;
+
+        if buf.len() < 5 {
+            return 0;
+        }
+
+        if now_pos.wrapping_sub(prev_pos) > 5 {
+            prev_pos = now_pos.wrapping_sub(5);
+        }
+
+        let limit = buf.len() - 5;
+        let mut pos = 0;
+
+        while pos <= limit {
+            let byte = buf[pos];
+            if byte != 0xE8 && byte != 0xE9 {
+                pos += 1;
+                continue;
+            }
+
+            let offset = now_pos.wrapping_add(pos as u32).wrapping_sub(prev_pos);
+            prev_pos = now_pos.wrapping_add(pos as u32);
+
+            if offset > 5 {
+                prev_mask = 0;
+            } else {
+                for _ in 0..offset {
+                    prev_mask &= 0x77;
+                    prev_mask <<= 1;
+                }
+            }
+
+            let byte = buf[pos + 4];
+
+            if test_ms_byte(byte)
+                && MASK_TO_ALLOWED_STATUS[((prev_mask >> 1) & 0x7) as usize]
+                && (prev_mask >> 1) < 0x10
+            {
+                let mut src = u32::from_le_bytes(buf[pos + 1..pos + 5].try_into().unwrap());
+
+                let dest = loop {
+                    let dest = src.wrapping_sub(now_pos.wrapping_add(pos as u32 + 5));
+
+                    if prev_mask == 0 {
+                        break dest;
+                    }
+
+                    let i = MASK_TO_BIT_NUMBER[(prev_mask >> 1) as usize];
+                    if !test_ms_byte((dest >> (24 - i * 8)) as u8) {
+                        break dest;
+                    }
+
+                    src = dest ^ ((1 << (32 - i * 8)) - 1);
+                };
+
+                buf[pos + 4] = !(((dest >> 24) & 1) as u8).wrapping_sub(1);
+                buf[pos + 1..pos + 4].copy_from_slice(&dest.to_le_bytes()[..3]);
+                pos += 5;
+                prev_mask = 0;
+            } else {
+                pos += 1;
+                prev_mask |= 1;
+                if test_ms_byte(byte) {
+                    prev_mask |= 0x10;
+                }
+            }
+        }
+
+        self.prev_mask = prev_mask;
+        self.prev_pos = prev_pos;
+        self.now_pos = now_pos.wrapping_add(pos as u32);
+
+        pos
+    }
+
+    /// Write the data left, which is too short to be filtered.
+    pub(super) fn finish(mut self) -> io::Result<W> {
+        self.inner.write_all(&self.buf)?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for BcjX86Writer<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+
+        let filtered = self.filter();
+        self.inner.write_all(&self.buf[..filtered])?;
+        self.buf.drain(..filtered);
+
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use xz2::stream::{Check, Filters, LzmaOptions, Stream};
+
+    use super::{super::lzma::decode_lzma2, *};
+
+    #[test]
+    fn test_bcj_x86() {
+        // Data with many call and jump instructions.
+        let mut rng = fastrand::Rng::with_seed(3);
+        let data: Vec<u8> = (0..100_000)
+            .map(|_| match rng.u8(..8) {
+                0 => 0xE8,
+                1 => 0xE9,
+                2 => 0x00,
+                3 => 0xFF,
+                _ => rng.u8(..),
+            })
+            .collect();
+
+        let mut options = LzmaOptions::new_preset(0).unwrap();
+        options.dict_size(1 << 20);
+        let mut filters = Filters::new();
+        filters.x86().lzma2(&options);
+
+        let mut encoded = Vec::new();
+        xz2::read::XzEncoder::new_stream(
+            &data[..],
+            Stream::new_stream_encoder(&filters, Check::None).unwrap(),
+        )
+        .read_to_end(&mut encoded)
+        .unwrap();
+        // Skip the headers of the .xz stream and of its block.
+        let encoded = &encoded[12 + (usize::from(encoded[12]) + 1) * 4..];
+
+        // Only undo the compression, leaving the data filtered.
+        let mut filtered = Vec::new();
+        decode_lzma2(&[16], encoded, &mut filtered, data.len() as u64).unwrap();
+        assert!(filtered != data);
+
+        for chunk_size in [1, 7, 4096, filtered.len()] {
+            let mut writer = BcjX86Writer::new(Vec::new());
+            for chunk in filtered.chunks(chunk_size) {
+                writer.write_all(chunk).unwrap();
+            }
+            assert!(writer.finish().unwrap() == data, "chunk size {chunk_size}");
+        }
+    }
+}
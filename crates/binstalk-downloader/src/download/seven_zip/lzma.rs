@@ -0,0 +1,329 @@
+//! LZMA and LZMA2 decoders for the coders of 7z archives, using the raw
+//! decoder of liblzma, since the 7z archives store the data of these
+//! coders without the headers of the .lzma and .xz formats.
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    mem, ptr,
+};
+
+use lzma_sys::{
+    lzma_code, lzma_end, lzma_filter, lzma_options_lzma, lzma_raw_decoder, lzma_stream,
+    LZMA_BUF_ERROR, LZMA_DATA_ERROR, LZMA_FILTER_LZMA1, LZMA_FILTER_LZMA2, LZMA_MEMLIMIT_ERROR,
+    LZMA_MEM_ERROR, LZMA_OK, LZMA_OPTIONS_ERROR, LZMA_RUN, LZMA_STREAM_END, LZMA_VLI_UNKNOWN,
+};
+
+/// Largest dictionary accepted, which is allocated before decoding any
+/// data, 4 times the one of the ultra preset of 7-Zip.
+const MAX_DICT_SIZE: u32 = 256 * 1024 * 1024;
+
+/// Smallest dictionary supported by liblzma.
+const MIN_DICT_SIZE: u32 = 4096;
+
+fn corrupted(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("corrupted {what} data"))
+}
+
+/// Return the size of the dictionary to allocate, or an error if
+/// `dict_size` is too large.
+///
+/// The dictionary never needs to be larger than the data, since a match
+/// cannot reach before its start.
+fn dict_size(dict_size: u32, unpack_size: u64) -> io::Result<u32> {
+    let dict_size = u64::from(dict_size)
+        .min(unpack_size)
+        .max(MIN_DICT_SIZE.into());
+
+    if dict_size > MAX_DICT_SIZE.into() {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("7z dictionary of {dict_size} bytes is too large"),
+        ))
+    } else {
+        Ok(dict_size as u32)
+    }
+}
+
+/// A `lzma_stream`, ended when dropped.
+struct Stream(lzma_stream);
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        // Safety: the stream was initialized by `lzma_raw_decoder`, or is
+        // zeroed, which `lzma_end` accepts.
+        unsafe { lzma_end(&mut self.0) }
+    }
+}
+
+/// Decode `input` with the raw decoder of liblzma, `options` being the
+/// options of its LZMA1 or LZMA2 filter, and write the first `unpack_size`
+/// bytes of the output to `out`.
+fn decode_raw(
+    filter_id: u64,
+    mut options: lzma_options_lzma,
+    input: impl Read,
+    mut out: impl Write,
+    unpack_size: u64,
+) -> io::Result<()> {
+    let what = if filter_id == LZMA_FILTER_LZMA1 {
+        "LZMA"
+    } else {
+        "LZMA2"
+    };
+
+    let filters = [
+        lzma_filter {
+            id: filter_id,
+            options: &mut options as *mut lzma_options_lzma as *mut _,
+        },
+        lzma_filter {
+            id: LZMA_VLI_UNKNOWN,
+            options: ptr::null_mut(),
+        },
+    ];
+
+    // Safety: a zeroed `lzma_stream` is `LZMA_STREAM_INIT`, and `filters`
+    // is terminated by `LZMA_VLI_UNKNOWN`. liblzma copies the options.
+    let mut stream = Stream(unsafe { mem::zeroed() });
+    match unsafe { lzma_raw_decoder(&mut stream.0, filters.as_ptr()) } {
+        LZMA_OK => (),
+        LZMA_MEM_ERROR => return Err(io::ErrorKind::OutOfMemory.into()),
+        _ => return Err(corrupted(&format!("{what} properties"))),
+    }
+
+    let mut input = BufReader::new(input);
+    let mut buf = vec![0; 64 * 1024];
+    let mut remaining = unpack_size;
+
+    while remaining > 0 {
+        let in_buf = input.fill_buf()?;
+        let in_len = in_buf.len();
+        let out_len = buf.len().min(remaining.try_into().unwrap_or(usize::MAX));
+
+        stream.0.next_in = in_buf.as_ptr();
+        stream.0.avail_in = in_len;
+        stream.0.next_out = buf.as_mut_ptr();
+        stream.0.avail_out = out_len;
+
+        // Safety: `next_in` and `next_out` point to `avail_in` and
+        // `avail_out` valid bytes, which are not used after the call.
+        let ret = unsafe { lzma_code(&mut stream.0, LZMA_RUN) };
+
+        let consumed = in_len - stream.0.avail_in;
+        let produced = out_len - stream.0.avail_out;
+        stream.0.next_in = ptr::null();
+        stream.0.next_out = ptr::null_mut();
+
+        input.consume(consumed);
+        out.write_all(&buf[..produced])?;
+        remaining -= produced as u64;
+
+        match ret {
+            LZMA_OK if in_len == 0 && produced == 0 => {
+                return Err(io::ErrorKind::UnexpectedEof.into())
+            }
+            LZMA_OK => (),
+            LZMA_STREAM_END => break,
+            LZMA_BUF_ERROR => return Err(io::ErrorKind::UnexpectedEof.into()),
+            LZMA_MEM_ERROR | LZMA_MEMLIMIT_ERROR => return Err(io::ErrorKind::OutOfMemory.into()),
+            LZMA_DATA_ERROR | LZMA_OPTIONS_ERROR => return Err(corrupted(what)),
+            ret => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("failed to decode {what} data: liblzma error {ret}"),
+                ))
+            }
+        }
+    }
+
+    if remaining > 0 {
+        return Err(corrupted(what));
+    }
+
+    Ok(())
+}
+
+/// Return the default options, with the given dictionary size.
+fn options(dict_size: u32) -> lzma_options_lzma {
+    // Safety: all the fields are integers or nullable pointers.
+    let mut options: lzma_options_lzma = unsafe { mem::zeroed() };
+    options.dict_size = dict_size;
+    options
+}
+
+/// Decode the output of a LZMA coder of `unpack_size` bytes, `props`
+/// being the properties of the coder.
+pub(super) fn decode_lzma(
+    props: &[u8],
+    input: impl Read,
+    out: impl Write,
+    unpack_size: u64,
+) -> io::Result<()> {
+    let [props, dict_size_bytes @ ..] = props else {
+        return Err(corrupted("LZMA properties"));
+    };
+    let dict_size_bytes: [u8; 4] = dict_size_bytes
+        .try_into()
+        .map_err(|_| corrupted("LZMA properties"))?;
+
+    let props = u32::from(*props);
+    if props >= 9 * 5 * 5 {
+        return Err(corrupted("LZMA properties"));
+    }
+
+    let mut options = options(dict_size(u32::from_le_bytes(dict_size_bytes), unpack_size)?);
+    options.lc = props % 9;
+    options.lp = props / 9 % 5;
+    options.pb = props / 45;
+
+    decode_raw(LZMA_FILTER_LZMA1, options, input, out, unpack_size)
+}
+
+/// Decode the output of a LZMA2 coder of `unpack_size` bytes, `props`
+/// being the properties of the coder.
+pub(super) fn decode_lzma2(
+    props: &[u8],
+    input: impl Read,
+    out: impl Write,
+    unpack_size: u64,
+) -> io::Result<()> {
+    let dict_size_prop = match props {
+        [bits @ 0..=39] => (2 | (u32::from(*bits) & 1)) << (bits / 2 + 11),
+        [40] => u32::MAX,
+        _ => return Err(corrupted("LZMA2 properties")),
+    };
+
+    // lc, lp and pb are stored in the data of LZMA2.
+    let options = options(dict_size(dict_size_prop, unpack_size)?);
+
+    decode_raw(LZMA_FILTER_LZMA2, options, input, out, unpack_size)
+}
+
+#[cfg(test)]
+mod test {
+    use xz2::stream::{Check, Filters, LzmaOptions, Stream};
+
+    use super::*;
+
+    /// Compressible data, with matches of all lengths and distances.
+    fn data() -> Vec<u8> {
+        let mut rng = fastrand::Rng::with_seed(7);
+        let mut data = Vec::new();
+        while data.len() < 300_000 {
+            if data.len() > 16 && rng.bool() {
+                let start = rng.usize(..data.len() - 8);
+                let len = rng.usize(2..300).min(data.len() - start);
+                data.extend_from_within(start..start + len);
+            } else {
+                data.extend((0..rng.usize(1..20)).map(|_| rng.alphanumeric() as u8));
+            }
+        }
+        data
+    }
+
+    fn encode(stream: Stream, data: &[u8]) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        xz2::read::XzEncoder::new_stream(data, stream)
+            .read_to_end(&mut encoded)
+            .unwrap();
+        encoded
+    }
+
+    #[test]
+    fn test_decode_lzma() {
+        let data = data();
+        for preset in [0, 6] {
+            let options = LzmaOptions::new_preset(preset).unwrap();
+            // The .lzma format: the properties, the size, which is unknown
+            // so the data ends with the end marker, then the data.
+            let encoded = encode(Stream::new_lzma_encoder(&options).unwrap(), &data);
+
+            let mut decoded = Vec::new();
+            decode_lzma(
+                &encoded[..5],
+                &encoded[13..],
+                &mut decoded,
+                data.len() as u64,
+            )
+            .unwrap();
+            assert!(decoded == data, "preset {preset}");
+
+            decode_lzma(
+                &encoded[..5],
+                &encoded[13..],
+                io::sink(),
+                data.len() as u64 + 1,
+            )
+            .unwrap_err();
+
+            // Truncated data.
+            decode_lzma(
+                &encoded[..5],
+                &encoded[13..encoded.len() / 2],
+                io::sink(),
+                data.len() as u64,
+            )
+            .unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_decode_lzma2() {
+        let data = data();
+        for preset in [0, 6] {
+            let mut options = LzmaOptions::new_preset(preset).unwrap();
+            options.dict_size(1 << 20);
+            let mut filters = Filters::new();
+            filters.lzma2(&options);
+            let encoded = encode(
+                Stream::new_stream_encoder(&filters, Check::None).unwrap(),
+                &data,
+            );
+            // Skip the headers of the .xz stream and of its block.
+            let encoded = &encoded[12 + (usize::from(encoded[12]) + 1) * 4..];
+
+            // The dictionary size of 1 MiB.
+            let props = [16];
+
+            let mut decoded = Vec::new();
+            decode_lzma2(&props, encoded, &mut decoded, data.len() as u64).unwrap();
+            assert!(decoded == data, "preset {preset}");
+        }
+    }
+
+    #[test]
+    fn test_dict_size() {
+        assert_eq!(dict_size(1 << 26, 1000).unwrap(), MIN_DICT_SIZE);
+        assert_eq!(dict_size(1 << 20, 1 << 30).unwrap(), 1 << 20);
+        assert_eq!(dict_size(u32::MAX, 1 << 26).unwrap(), 1 << 26);
+        // The header of the archive claims 4 GiB of data and dictionary.
+        dict_size(u32::MAX, u64::MAX).unwrap_err();
+        decode_lzma2(&[40], io::empty(), io::sink(), u64::MAX).unwrap_err();
+    }
+
+    #[test]
+    fn test_decode_lzma_corrupted() {
+        // Corrupted data may contain matches reaching before the start of
+        // the data, which must be rejected instead of read out of bounds.
+        let mut options = LzmaOptions::new_preset(0).unwrap();
+        options.dict_size(1 << 20);
+        let data = b"abcdefgh".repeat(1000);
+        let encoded = encode(Stream::new_lzma_encoder(&options).unwrap(), &data);
+
+        // Flip the bits of the compressed data, after the first literal.
+        for i in 14..encoded.len().min(64) {
+            let mut corrupted = encoded.clone();
+            corrupted[i] ^= 0xFF;
+            let mut decoded = Vec::new();
+            // Either an error or garbage, but never a panic or a read out
+            // of bounds.
+            let _ = decode_lzma(
+                &corrupted[..5],
+                &corrupted[13..],
+                &mut decoded,
+                data.len() as u64,
+            );
+            assert!(decoded.len() <= data.len());
+        }
+    }
+}
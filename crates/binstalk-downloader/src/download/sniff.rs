@@ -0,0 +1,83 @@
+use binstalk_types::cargo_toml_binstall::PkgFmt;
+
+/// Number of bytes needed by [`sniff_pkg_fmt`], which is enough to
+/// contain the magic of the ustar header.
+pub(super) const SNIFF_LEN: usize = 262;
+
+/// Detect the format of the package from the first bytes of it.
+///
+/// Compressed streams are assumed to be tarballs since they cannot be
+/// decompressed at this point. Return `None` if the format is unknown.
+pub(super) fn sniff_pkg_fmt(prefix: &[u8]) -> Option<PkgFmt> {
+    const MAGICS: &[(&[u8], PkgFmt)] = &[
+        (b"\x1f\x8b", PkgFmt::Tgz),
+        (b"\xfd7zXZ\x00", PkgFmt::Txz),
+        (b"\x28\xb5\x2f\xfd", PkgFmt::Tzstd),
+        (b"BZh", PkgFmt::Tbz2),
+        (b"\x04\x22\x4d\x18", PkgFmt::Tlz4),
+        // Brotli streams have no magic, so `PkgFmt::Tbr` cannot be sniffed.
+        (b"PK\x03\x04", PkgFmt::Zip),
+        (b"PK\x05\x06", PkgFmt::Zip),
+        // Split zip archive
+        (b"PK\x07\x08", PkgFmt::Zip),
+        (b"7z\xbc\xaf\x27\x1c", PkgFmt::SevenZip),
+        (b"!<arch>\ndebian-binary", PkgFmt::Deb),
+        (b"\xed\xab\xee\xdb", PkgFmt::Rpm),
+        // The magic of dmg images is in their trailer, so `PkgFmt::Dmg`
+        // cannot be sniffed.
+        // Compound file, i.e. msi package
+        (b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1", PkgFmt::Msi),
+        // ELF
+        (b"\x7fELF", PkgFmt::Bin),
+        // PE
+        (b"MZ", PkgFmt::Bin),
+        // Mach-O, 32 and 64 bits, both endianness, and universal binaries
+        (b"\xfe\xed\xfa\xce", PkgFmt::Bin),
+        (b"\xfe\xed\xfa\xcf", PkgFmt::Bin),
+        (b"\xce\xfa\xed\xfe", PkgFmt::Bin),
+        (b"\xcf\xfa\xed\xfe", PkgFmt::Bin),
+        (b"\xca\xfe\xba\xbe", PkgFmt::Bin),
+    ];
+
+    MAGICS
+        .iter()
+        .find(|(magic, _)| prefix.starts_with(magic))
+        .map(|(_, fmt)| *fmt)
+        .or_else(|| (prefix.get(257..262) == Some(b"ustar")).then_some(PkgFmt::Tar))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sniff_pkg_fmt() {
+        assert_eq!(sniff_pkg_fmt(b"\x1f\x8b\x08\x00"), Some(PkgFmt::Tgz));
+        assert_eq!(sniff_pkg_fmt(b"PK\x03\x04\x14\x00"), Some(PkgFmt::Zip));
+        assert_eq!(sniff_pkg_fmt(b"PK\x07\x08PK\x03\x04"), Some(PkgFmt::Zip));
+        assert_eq!(sniff_pkg_fmt(b"\x7fELF\x02\x01"), Some(PkgFmt::Bin));
+        assert_eq!(
+            sniff_pkg_fmt(b"7z\xbc\xaf\x27\x1c\x00\x04"),
+            Some(PkgFmt::SevenZip)
+        );
+        assert_eq!(
+            sniff_pkg_fmt(b"!<arch>\ndebian-binary/  0"),
+            Some(PkgFmt::Deb)
+        );
+        assert_eq!(
+            sniff_pkg_fmt(b"\xed\xab\xee\xdb\x03\x00"),
+            Some(PkgFmt::Rpm)
+        );
+        assert_eq!(
+            sniff_pkg_fmt(b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1\x00"),
+            Some(PkgFmt::Msi)
+        );
+
+        let mut tar = vec![0; SNIFF_LEN];
+        tar[257..].copy_from_slice(b"ustar");
+        assert_eq!(sniff_pkg_fmt(&tar), Some(PkgFmt::Tar));
+
+        assert_eq!(sniff_pkg_fmt(b"#!/bin/sh\n"), None);
+        assert_eq!(sniff_pkg_fmt(b""), None);
+    }
+}
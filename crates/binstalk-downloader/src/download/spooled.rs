@@ -0,0 +1,125 @@
+//! Buffers for the content of the entries read by [`TarEntriesVisitor`]s,
+//! kept in memory up to a limit and spilled to a temporary file past it, so
+//! that visiting pathological archives cannot exhaust the memory.
+//!
+//! [`TarEntriesVisitor`]: super::TarEntriesVisitor
+
+use std::io;
+
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use super::TarEntry;
+
+/// Default of [`Client::with_visit_memory_limit`](crate::remote::Client::with_visit_memory_limit).
+pub(crate) const DEFAULT_VISIT_MEMORY_LIMIT: usize = 1024 * 1024;
+
+#[derive(Debug)]
+enum Storage {
+    Memory(Vec<u8>),
+    File(tokio::fs::File),
+}
+
+/// Content kept in memory up to `memory_limit` bytes, then moved to an
+/// anonymous temporary file, removed once dropped.
+#[derive(Debug)]
+pub struct SpooledBuffer {
+    memory_limit: usize,
+    storage: Storage,
+    len: u64,
+}
+
+impl SpooledBuffer {
+    pub fn new(memory_limit: usize) -> Self {
+        Self {
+            memory_limit,
+            storage: Storage::Memory(Vec::new()),
+            len: 0,
+        }
+    }
+
+    pub async fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        if let Storage::Memory(buffer) = &mut self.storage {
+            if buffer.len() + data.len() <= self.memory_limit {
+                buffer.extend_from_slice(data);
+                self.len += data.len() as u64;
+                return Ok(());
+            }
+
+            let mut file = tokio::fs::File::from_std(tempfile::tempfile()?);
+            file.write_all(buffer).await?;
+            self.storage = Storage::File(file);
+        }
+
+        if let Storage::File(file) = &mut self.storage {
+            file.write_all(data).await?;
+        }
+        self.len += data.len() as u64;
+
+        Ok(())
+    }
+
+    /// Number of bytes written.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Return true if the content exceeded the memory limit and is stored
+    /// in a temporary file.
+    pub fn is_spilled(&self) -> bool {
+        matches!(self.storage, Storage::File(_))
+    }
+
+    /// Return the content, read back from the temporary file if spilled.
+    pub async fn into_vec(self) -> io::Result<Vec<u8>> {
+        match self.storage {
+            Storage::Memory(buffer) => Ok(buffer),
+            Storage::File(mut file) => {
+                file.rewind().await?;
+                let mut buffer = Vec::with_capacity(self.len.try_into().unwrap_or(usize::MAX));
+                file.read_to_end(&mut buffer).await?;
+                Ok(buffer)
+            }
+        }
+    }
+}
+
+/// Read the rest of `entry` into a [`SpooledBuffer`] limited to
+/// [`TarEntry::memory_limit`].
+///
+/// Unlike reading it into a `Vec`, the memory used does not depend on the
+/// size of the entry, which is not trustworthy.
+pub async fn spool(entry: &mut dyn TarEntry) -> io::Result<SpooledBuffer> {
+    let mut buffer = SpooledBuffer::new(entry.memory_limit());
+    let mut chunk = vec![0; 8 * 1024];
+
+    loop {
+        let n = entry.read(&mut chunk).await?;
+        if n == 0 {
+            break Ok(buffer);
+        }
+        buffer.write_all(&chunk[..n]).await?;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_spooled_buffer() {
+        let mut buffer = SpooledBuffer::new(4);
+        buffer.write_all(b"abc").await.unwrap();
+        assert!(!buffer.is_spilled());
+
+        buffer.write_all(b"de").await.unwrap();
+        assert!(buffer.is_spilled());
+        buffer.write_all(b"f").await.unwrap();
+
+        assert_eq!(buffer.len(), 6);
+        assert_eq!(buffer.into_vec().await.unwrap(), b"abcdef");
+    }
+}
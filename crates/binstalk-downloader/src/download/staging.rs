@@ -0,0 +1,80 @@
+//! Extraction into a staging directory which is only renamed into place
+//! once complete, see [`Download::with_staged_extraction`](super::Download::with_staged_extraction).
+
+use std::{
+    fs::{self, File},
+    io,
+    path::Path,
+};
+
+use tempfile::TempDir;
+
+use super::{ExtractedFiles, ExtractedFilesEntry};
+
+/// Create the staging directory of `dst`, next to it so that it is on the
+/// same filesystem and can be renamed into place.
+pub(super) fn staging_dir(dst: &Path) -> io::Result<TempDir> {
+    let parent = match dst.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let name = dst
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+
+    fs::create_dir_all(parent)?;
+    tempfile::Builder::new()
+        .prefix(&format!(".{name}.staging-"))
+        .tempdir_in(parent)
+}
+
+/// Sync the files and directories of `extracted_files`, extracted into
+/// `staging`, to disk, then rename `staging` to `dst`.
+///
+/// `dst` must not exist or be an empty directory. `staging` is removed if
+/// it cannot be renamed.
+pub(super) fn commit(
+    staging: TempDir,
+    dst: &Path,
+    extracted_files: &ExtractedFiles,
+) -> io::Result<()> {
+    for (path, entry) in &extracted_files.0 {
+        let path = staging.path().join(path);
+        match entry {
+            ExtractedFilesEntry::File => File::open(path)?.sync_all()?,
+            // Directories cannot be opened to be synced on Windows, where
+            // their entries are synced with the files.
+            #[cfg(unix)]
+            ExtractedFilesEntry::Dir(_) => File::open(path)?.sync_all()?,
+            #[cfg(not(unix))]
+            ExtractedFilesEntry::Dir(_) => (),
+            // Synced along with their parent directory.
+            ExtractedFilesEntry::Symlink(_) => (),
+        }
+    }
+
+    match fs::remove_dir(dst) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => {
+            return Err(io::Error::new(
+                err.kind(),
+                format!(
+                    "{} must not exist or be an empty directory: {err}",
+                    dst.display()
+                ),
+            ))
+        }
+        _ => (),
+    }
+
+    fs::rename(staging.path(), dst)?;
+    // It is renamed, so there is nothing left to remove.
+    let _ = staging.into_path();
+
+    #[cfg(unix)]
+    if let Some(parent) = dst.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        File::open(parent)?.sync_all()?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,92 @@
+//! Writing the data downloaded, as is, to a file while it is extracted or
+//! visited, see [`Download::with_tee`](super::Download::with_tee).
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use bytes::Bytes;
+use tempfile::NamedTempFile;
+use tokio::{fs, io::AsyncWriteExt, sync::Mutex};
+
+struct TeeFile {
+    /// Removed unless persisted to the destination.
+    tmp: NamedTempFile,
+    file: fs::File,
+}
+
+/// Writes the data to a temporary file next to `path`, which is renamed to
+/// `path` once all the data is written, so that `path` never contains a
+/// partial download.
+pub(super) struct Tee {
+    path: PathBuf,
+    /// Created on the first write.
+    file: Mutex<Option<TeeFile>>,
+}
+
+impl Tee {
+    pub(super) fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            file: Mutex::new(None),
+        }
+    }
+
+    pub(super) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn create(path: &Path) -> io::Result<TeeFile> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        std::fs::create_dir_all(dir)?;
+
+        let tmp = NamedTempFile::new_in(dir)?;
+        let file = fs::File::from_std(tmp.reopen()?);
+
+        Ok(TeeFile { tmp, file })
+    }
+
+    /// Return the path of the temporary file the data is written to,
+    /// creating it if nothing is written yet.
+    pub(super) async fn temp_path(&self) -> io::Result<PathBuf> {
+        let mut file = self.file.lock().await;
+        let tee_file = match &mut *file {
+            Some(tee_file) => tee_file,
+            None => file.insert(Self::create(&self.path)?),
+        };
+        Ok(tee_file.tmp.path().to_owned())
+    }
+
+    pub(super) async fn write(&self, bytes: &Bytes) -> io::Result<()> {
+        let mut file = self.file.lock().await;
+        let tee_file = match &mut *file {
+            Some(tee_file) => tee_file,
+            None => file.insert(Self::create(&self.path)?),
+        };
+
+        tee_file.file.write_all(bytes).await
+    }
+
+    /// Move the file written to its destination, once all the data is
+    /// written.
+    pub(super) async fn finish(&self) -> io::Result<()> {
+        let tee_file = match self.file.lock().await.take() {
+            Some(tee_file) => tee_file,
+            // Nothing is downloaded.
+            None => Self::create(&self.path)?,
+        };
+
+        let TeeFile { tmp, mut file } = tee_file;
+        file.flush().await?;
+        file.sync_all().await?;
+        drop(file);
+
+        tmp.persist(&self.path).map_err(io::Error::from)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+//! Pool of threads feeding the data downloaded to the data verifiers, so
+//! that digesting large artifacts does not compete with downloading and
+//! extracting on the async runtime, see
+//! [`Download::with_offloaded_data_verifier`](super::Download::with_offloaded_data_verifier).
+
+use std::{
+    collections::VecDeque,
+    num::NonZeroUsize,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+use bytes::Bytes;
+use tokio::sync::{oneshot, OwnedSemaphorePermit, Semaphore};
+use tracing::warn;
+
+use super::DataVerifier;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// At most this many threads are used, since verifiers are fed one chunk
+/// at a time and there are rarely more artifacts downloaded concurrently.
+const MAX_THREADS: usize = 8;
+
+/// Number of chunks queued for a verifier before the download waits for
+/// it to catch up.
+const MAX_QUEUED_CHUNKS: usize = 64;
+
+fn start_pool() -> mpsc::Sender<Job> {
+    let (sender, receiver) = mpsc::channel::<Job>();
+    let receiver = Arc::new(Mutex::new(receiver));
+
+    let threads = thread::available_parallelism()
+        .map(NonZeroUsize::get)
+        .unwrap_or(1)
+        .min(MAX_THREADS);
+
+    for i in 0..threads {
+        let receiver = receiver.clone();
+        let res = thread::Builder::new()
+            .name(format!("binstall-verifier-{i}"))
+            .spawn(move || loop {
+                let job = receiver.lock().unwrap().recv();
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => break,
+                }
+            });
+
+        if let Err(err) = res {
+            warn!("Failed to spawn a data verifier thread: {err}");
+        }
+    }
+
+    sender
+}
+
+/// Run `job` on the pool, starting it if needed.
+fn spawn(job: Job) {
+    static POOL: Mutex<Option<mpsc::Sender<Job>>> = Mutex::new(None);
+
+    let res = POOL
+        .lock()
+        .unwrap()
+        .get_or_insert_with(start_pool)
+        .send(job);
+
+    // No thread could be spawned, run it here instead.
+    if let Err(mpsc::SendError(job)) = res {
+        job()
+    }
+}
+
+enum Item {
+    Data(Bytes, OwnedSemaphorePermit),
+    Flush(oneshot::Sender<()>),
+}
+
+#[derive(Default)]
+struct Queue {
+    items: VecDeque<Item>,
+    /// Whether a job draining `items` is spawned.
+    scheduled: bool,
+}
+
+struct State {
+    data_verifier: Arc<Mutex<dyn DataVerifier>>,
+    queue: Mutex<Queue>,
+}
+
+impl State {
+    fn drain(&self) {
+        loop {
+            let item = {
+                let mut queue = self.queue.lock().unwrap();
+                match queue.items.pop_front() {
+                    Some(item) => item,
+                    None => {
+                        queue.scheduled = false;
+                        return;
+                    }
+                }
+            };
+
+            match item {
+                Item::Data(bytes, _permit) => self.data_verifier.lock().unwrap().update(&bytes),
+                Item::Flush(sender) => {
+                    let _ = sender.send(());
+                }
+            }
+        }
+    }
+}
+
+/// Feeds the data to a [`DataVerifier`] on the pool, in order.
+///
+/// Verifiers are fed by one job at a time, so that the chunks are never
+/// reordered, while different verifiers run concurrently.
+pub(super) struct OffloadedVerifier {
+    state: Arc<State>,
+    permits: Arc<Semaphore>,
+}
+
+impl OffloadedVerifier {
+    pub(super) fn new(data_verifier: Arc<Mutex<dyn DataVerifier>>) -> Self {
+        Self {
+            state: Arc::new(State {
+                data_verifier,
+                queue: Mutex::default(),
+            }),
+            permits: Arc::new(Semaphore::new(MAX_QUEUED_CHUNKS)),
+        }
+    }
+
+    fn push(&self, item: Item) {
+        let mut queue = self.state.queue.lock().unwrap();
+        queue.items.push_back(item);
+
+        if !queue.scheduled {
+            queue.scheduled = true;
+            let state = self.state.clone();
+            spawn(Box::new(move || state.drain()));
+        }
+    }
+
+    /// Queue `bytes` to be fed to the verifier, waiting if too many chunks
+    /// are queued already.
+    pub(super) async fn update(&self, bytes: Bytes) {
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the semaphore is never closed");
+        self.push(Item::Data(bytes, permit));
+    }
+
+    /// Wait for all the chunks queued to be fed to the verifier.
+    pub(super) async fn finish(&self) {
+        let (sender, receiver) = oneshot::channel();
+        self.push(Item::Flush(sender));
+        // The sender is only dropped without sending if the verifier panics.
+        let _ = receiver.await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_offloaded_verifier() {
+        let data = Arc::new(Mutex::new(Vec::new()));
+        let verifier = OffloadedVerifier::new(Arc::new(Mutex::new({
+            let data = data.clone();
+            move |bytes: &Bytes| data.lock().unwrap().extend_from_slice(bytes)
+        })));
+
+        let chunks: Vec<u8> = (0..=255).collect();
+        for chunk in chunks.chunks(3) {
+            verifier.update(Bytes::copy_from_slice(chunk)).await;
+        }
+        verifier.finish().await;
+
+        assert_eq!(*data.lock().unwrap(), chunks);
+    }
+}
@@ -1,24 +1,28 @@
 use std::{
     borrow::Cow,
-    io::Write,
+    io::{self, Cursor, Write},
     path::{Component, Path, PathBuf},
+    sync::Arc,
 };
 
-use async_zip::{
-    base::{read::WithEntry, read::ZipEntryReader},
-    ZipString,
-};
+use async_zip::base::{read::WithEntry, read::ZipEntryReader};
 use bytes::{Bytes, BytesMut};
 use futures_util::future::try_join;
 use futures_util::io::Take;
+use sha2::{Digest, Sha256};
 use thiserror::Error as ThisError;
 use tokio::{
     io::{AsyncRead, AsyncReadExt},
     sync::mpsc,
 };
 use tokio_util::compat::{Compat, FuturesAsyncReadCompatExt};
+#[cfg(unix)]
+use tracing::warn;
 
-use super::{DownloadError, ExtractedFiles};
+use super::{
+    extract_progress::{EntryProgress, ExtractProgress},
+    DownloadError, ExtractFilter, ExtractedFiles,
+};
 use crate::utils::asyncify;
 
 #[derive(Debug, ThisError)]
@@ -28,6 +32,9 @@ enum ZipErrorInner {
 
     #[error("Invalid file path: {0}")]
     InvalidFilePath(Box<str>),
+
+    #[error("Symlink {0} points outside of the extraction root: {1}")]
+    InvalidSymlinkTarget(Box<str>, Box<str>),
 }
 
 #[derive(Debug, ThisError)]
@@ -40,18 +47,47 @@ impl ZipError {
     }
 }
 
+/// Signatures at the start of split zip archives, i.e. of their first part,
+/// before the first local file header.
+const SPLIT_SIGNATURES: &[&[u8]] = &[b"PK\x07\x08", b"PK00"];
+
+/// Return a reader of `reader` without the signature of split zip archives
+/// at its start, if any, so that the concatenation of the parts of a split
+/// zip archive can be read as a regular one.
+pub(super) async fn strip_split_signature<R>(mut reader: R) -> io::Result<impl AsyncRead + Unpin>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut prefix = Vec::with_capacity(4);
+    (&mut reader).take(4).read_to_end(&mut prefix).await?;
+    if SPLIT_SIGNATURES.contains(&&prefix[..]) {
+        prefix.clear();
+    }
+    Ok(Cursor::new(prefix).chain(reader))
+}
+
+/// Extract the entry, unless `filter` rejects it, in which case it is left
+/// unread and `false` is returned.
 pub(super) async fn extract_zip_entry<R>(
     zip_reader: &mut ZipEntryReader<'_, Take<Compat<R>>, WithEntry<'_>>,
     path: &Path,
     buf: &mut BytesMut,
     extracted_files: &mut ExtractedFiles,
-) -> Result<(), DownloadError>
+    progress: Option<Arc<dyn ExtractProgress>>,
+    filter: Option<&ExtractFilter>,
+) -> Result<bool, DownloadError>
 where
     R: AsyncRead + Unpin + Send + Sync,
 {
     // Sanitize filename
     let raw_filename = zip_reader.entry().filename();
-    let (filename, is_dir) = check_filename_and_normalize(raw_filename)?;
+    let (filename, is_dir) = check_filename_and_normalize(raw_filename.as_bytes())?;
+
+    if let Some(filter) = filter {
+        if !filter(&filename) {
+            return Ok(false);
+        }
+    }
 
     // Calculates the outpath
     let outpath = path.join(&filename);
@@ -90,21 +126,32 @@ where
         })
         .await?;
     } else {
-        extracted_files.add_file(&filename);
-
         // Use channel size = 5 to minimize the waiting time in the extraction task
         let (tx, mut rx) = mpsc::channel::<Bytes>(5);
 
         // This entry is a file.
 
+        let mut entry_progress = progress.map(|progress| {
+            EntryProgress::new(
+                progress,
+                filename.clone(),
+                zip_reader.entry().uncompressed_size(),
+            )
+        });
+
         let write_task = asyncify(move || {
             if let Some(p) = outpath.parent() {
                 std::fs::create_dir_all(p)?;
             }
             let mut outfile = std::fs::File::create(&outpath)?;
+            let mut hasher = Sha256::new();
 
             while let Some(bytes) = rx.blocking_recv() {
+                hasher.update(&bytes);
                 outfile.write_all(&bytes)?;
+                if let Some(entry_progress) = &mut entry_progress {
+                    entry_progress.advance(bytes.len() as u64);
+                }
             }
 
             outfile.flush()?;
@@ -113,7 +160,7 @@ where
                 outfile.set_permissions(perms)?;
             }
 
-            Ok(())
+            Ok(hasher.finalize().into())
         });
 
         let read_task = async move {
@@ -128,7 +175,7 @@ where
             Ok(())
         };
 
-        try_join(
+        let (sha256, ()) = try_join(
             async move { write_task.await.map_err(From::from) },
             async move {
                 read_task
@@ -138,6 +185,131 @@ where
             },
         )
         .await?;
+
+        extracted_files.add_file(&filename, Some(sha256));
+    }
+
+    Ok(true)
+}
+
+/// Signature of the records of the central directory.
+#[cfg(unix)]
+const CDH_SIGNATURE: u32 = 0x02014b50;
+
+/// Longest symlink target accepted, `PATH_MAX` on linux.
+#[cfg(unix)]
+const MAX_SYMLINK_TARGET_LEN: u64 = 4096;
+
+/// Return the names of the symlinks listed in the central directory of a
+/// zip, read from `reader` right after the signature of its first record.
+///
+/// The unix mode of the entries, and thus whether they are symlinks, is
+/// only stored there and not in their local headers read while extracting
+/// them. Reading stops at the first malformed record.
+#[cfg(unix)]
+pub(super) async fn read_symlink_names<R>(reader: &mut R) -> Vec<Vec<u8>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut names = Vec::new();
+
+    loop {
+        // The fixed size part of the record, after its signature.
+        let mut header = [0; 42];
+        if reader.read_exact(&mut header).await.is_err() {
+            break;
+        }
+        let u16_at = |offset: usize| u16::from_le_bytes([header[offset], header[offset + 1]]);
+        // The upper byte of "version made by".
+        let host = header[1];
+        let name_len = u16_at(24);
+        let skipped_len = u64::from(u16_at(26)) + u64::from(u16_at(28));
+        let mode = u16_at(36);
+
+        let mut name = vec![0; name_len.into()];
+        if reader.read_exact(&mut name).await.is_err() {
+            break;
+        }
+        // Symlinks are stored with their target as content, and with
+        // `S_IFLNK` as the type of their unix mode.
+        if host == 3 && mode & 0o170000 == 0o120000 {
+            names.push(name);
+        }
+
+        let mut skipped = (&mut *reader).take(skipped_len);
+        match tokio::io::copy(&mut skipped, &mut tokio::io::sink()).await {
+            Ok(len) if len == skipped_len => (),
+            _ => break,
+        }
+
+        match reader.read_u32_le().await {
+            Ok(CDH_SIGNATURE) => (),
+            _ => break,
+        }
+    }
+
+    names
+}
+
+/// Replace the entries named `symlink_names`, extracted to `path` as regular
+/// files containing their target, with symlinks.
+///
+/// The symlinks whose target is outside of `path` are left as regular
+/// files.
+#[cfg(unix)]
+pub(super) async fn extract_symlinks(
+    path: &Path,
+    symlink_names: Vec<Vec<u8>>,
+    extracted_files: &mut ExtractedFiles,
+) -> Result<(), DownloadError> {
+    let symlinks: Vec<PathBuf> = symlink_names
+        .iter()
+        .filter_map(|name| {
+            let (filename, is_dir) = check_filename_and_normalize(name).ok()?;
+            // Skip the entries rejected by the filter.
+            (!is_dir && extracted_files.has_file(&filename)).then_some(filename)
+        })
+        .collect();
+    if symlinks.is_empty() {
+        return Ok(());
+    }
+
+    let path = path.to_owned();
+    let extracted = asyncify(move || {
+        let mut extracted = Vec::new();
+
+        for filename in &symlinks {
+            let outpath = path.join(filename);
+            if std::fs::metadata(&outpath)?.len() > MAX_SYMLINK_TARGET_LEN {
+                warn!(
+                    "Extracting symlink {} as a regular file: its target is too long",
+                    filename.display()
+                );
+                continue;
+            }
+
+            let target = std::fs::read(&outpath)?;
+            match check_symlink_target(filename, &target, |path| {
+                symlinks.iter().any(|symlink| symlink == path)
+            }) {
+                Ok(target) => {
+                    std::fs::remove_file(&outpath)?;
+                    std::os::unix::fs::symlink(&target, &outpath)?;
+                    extracted.push((filename.clone(), target));
+                }
+                Err(err) => warn!(
+                    "Extracting symlink {} as a regular file: {err}",
+                    filename.display()
+                ),
+            }
+        }
+
+        Ok(extracted)
+    })
+    .await?;
+
+    for (filename, target) in extracted {
+        extracted_files.add_symlink(&filename, &target);
     }
 
     Ok(())
@@ -194,11 +366,8 @@ where
 /// to path-based exploits.
 ///
 /// This function is adapted from `zip::ZipFile::enclosed_name`.
-fn check_filename_and_normalize(filename: &ZipString) -> Result<(PathBuf, bool), DownloadError> {
-    let filename = filename
-        .as_str()
-        .map(Cow::Borrowed)
-        .unwrap_or_else(|_| String::from_utf8_lossy(filename.as_bytes()));
+fn check_filename_and_normalize(filename: &[u8]) -> Result<(PathBuf, bool), DownloadError> {
+    let filename = String::from_utf8_lossy(filename);
 
     let bail = |filename: Cow<'_, str>| {
         Err(ZipError(ZipErrorInner::InvalidFilePath(
@@ -231,3 +400,104 @@ fn check_filename_and_normalize(filename: &ZipString) -> Result<(PathBuf, bool),
 
     Ok((path, filename.ends_with('/')))
 }
+
+/// Ensure the `target` of the symlink at `path`, a path normalized by
+/// [`check_filename_and_normalize`], stays inside the extraction root once
+/// resolved against the parent of `path`.
+///
+/// The resolution is lexical, so `target` must not go through any other
+/// symlink of the archive, as determined by `is_symlink`, since its own
+/// target is not resolved.
+#[cfg_attr(not(unix), allow(dead_code))]
+fn check_symlink_target(
+    path: &Path,
+    target: &[u8],
+    is_symlink: impl Fn(&Path) -> bool,
+) -> Result<PathBuf, DownloadError> {
+    let bail = || {
+        Err(ZipError(ZipErrorInner::InvalidSymlinkTarget(
+            path.display().to_string().into(),
+            String::from_utf8_lossy(target).into(),
+        )))
+    };
+
+    let target = match std::str::from_utf8(target) {
+        Ok(target) if !target.is_empty() && !target.contains('\0') => Path::new(target),
+        _ => return bail()?,
+    };
+
+    let mut resolved = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut components = target.components().peekable();
+    while let Some(component) = components.next() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => return bail()?,
+            Component::CurDir => (),
+            Component::ParentDir => {
+                if !resolved.pop() {
+                    return bail()?;
+                }
+            }
+            Component::Normal(c) => {
+                resolved.push(c);
+                // Only the last component may be a symlink.
+                if components.peek().is_some() && is_symlink(&resolved) {
+                    return bail()?;
+                }
+            }
+        }
+    }
+
+    Ok(target.to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_strip_split_signature() {
+        for (data, stripped) in [
+            (&b"PK\x07\x08PK\x03\x04"[..], &b"PK\x03\x04"[..]),
+            (b"PK00PK\x03\x04", b"PK\x03\x04"),
+            (b"PK\x03\x04", b"PK\x03\x04"),
+            (b"PK", b"PK"),
+        ] {
+            let mut buf = Vec::new();
+            strip_split_signature(data)
+                .await
+                .unwrap()
+                .read_to_end(&mut buf)
+                .await
+                .unwrap();
+            assert_eq!(buf, stripped);
+        }
+    }
+
+    #[test]
+    fn test_check_symlink_target() {
+        let is_symlink = |path: &Path| path == Path::new("lib");
+
+        for (path, target) in [
+            ("bin/foo", "../libexec/foo"),
+            ("bin/foo", "foo-1.0"),
+            ("bin/foo", "./../bin/.."),
+            ("bin/foo", "../lib"),
+        ] {
+            assert_eq!(
+                check_symlink_target(Path::new(path), target.as_bytes(), is_symlink).unwrap(),
+                Path::new(target)
+            );
+        }
+
+        for (path, target) in [
+            ("bin/foo", "../../foo"),
+            ("foo", ".."),
+            ("bin/foo", "/usr/bin/foo"),
+            ("bin/foo", ""),
+            ("bin/foo", "foo\0"),
+            ("bin/foo", "../lib/.."),
+        ] {
+            check_symlink_target(Path::new(path), target.as_bytes(), is_symlink).unwrap_err();
+        }
+    }
+}
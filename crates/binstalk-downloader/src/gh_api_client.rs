@@ -18,7 +18,7 @@ use tracing::{debug, warn};
 use crate::remote;
 
 mod request;
-pub use request::{GhApiContextError, GhApiError, GhGraphQLErrors};
+pub use request::{GhApiContextError, GhApiError, GhGraphQLErrors, GhReleaseNotes};
 
 /// default retry duration if x-ratelimit-reset is not found in response header
 const DEFAULT_RETRY_DURATION: Duration = Duration::from_secs(10 * 60);
@@ -268,6 +268,29 @@ impl GhApiClient {
     }
 }
 
+impl GhApiClient {
+    /// Return the notes of the latest releases of `owner/repo`, up to 100,
+    /// newest first.
+    pub async fn list_release_notes(
+        &self,
+        owner: &str,
+        repo: &str,
+    ) -> Result<Vec<GhReleaseNotes>, GhApiError> {
+        if self.0.is_auth_token_valid.load(Relaxed) {
+            if let Some(auth_token) = self.0.auth_token.as_deref() {
+                match request::fetch_release_notes(&self.0.client, owner, repo, Some(auth_token))
+                    .await
+                {
+                    Ok(notes) => return Ok(notes),
+                    Err(err) => debug!("Failed to list releases with auth token: {err}"),
+                }
+            }
+        }
+
+        request::fetch_release_notes(&self.0.client, owner, repo, None).await
+    }
+}
+
 #[derive(Eq, PartialEq, Copy, Clone, Debug)]
 pub enum HasReleaseArtifact {
     Yes,
@@ -152,7 +152,7 @@ async fn fetch_release_artifacts_restful_api(
         request_builder = request_builder.bearer_auth(&auth_token);
     }
 
-    let response = request_builder.send(false).await?;
+    let response = request_builder.send_cached(false).await?;
 
     if let Some(ret) = check_for_status(response.status(), response.headers()) {
         Ok(ret)
@@ -161,6 +161,42 @@ async fn fetch_release_artifacts_restful_api(
     }
 }
 
+/// Notes of a release, only include fields we do care about.
+#[derive(Debug, Deserialize)]
+pub struct GhReleaseNotes {
+    pub tag_name: CompactString,
+    #[serde(default)]
+    pub name: Option<CompactString>,
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default)]
+    pub draft: bool,
+    #[serde(default)]
+    pub prerelease: bool,
+}
+
+pub(super) async fn fetch_release_notes(
+    client: &remote::Client,
+    owner: &str,
+    repo: &str,
+    auth_token: Option<&str>,
+) -> Result<Vec<GhReleaseNotes>, GhApiError> {
+    let mut request_builder = client
+        .get(Url::parse(&format!(
+            "https://api.github.com/repos/{owner}/{repo}/releases?per_page=100",
+            owner = percent_encode_http_url_path(owner),
+            repo = percent_encode_http_url_path(repo),
+        ))?)
+        .header("Accept", "application/vnd.github+json")
+        .header("X-GitHub-Api-Version", "2022-11-28");
+
+    if let Some(auth_token) = auth_token {
+        request_builder = request_builder.bearer_auth(&auth_token);
+    }
+
+    Ok(request_builder.send_cached(true).await?.json().await?)
+}
+
 #[derive(Deserialize)]
 enum GraphQLResponse {
     #[serde(rename = "data")]
@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use reqwest::redirect::Policy;
+use url::Url;
+
+use super::{host_headers::HostPattern, Error};
+
+/// Maximum number of redirects followed, as reqwest does by default.
+const MAX_REDIRECTS: usize = 10;
+
+/// The only hosts the client connects to, see
+/// [`ConnectionOptions::allowed_hosts`](super::ConnectionOptions::allowed_hosts).
+#[derive(Debug)]
+pub(super) struct AllowedHosts(Vec<HostPattern>);
+
+impl AllowedHosts {
+    pub(super) fn new<S: AsRef<str>>(patterns: impl IntoIterator<Item = S>) -> Self {
+        Self(
+            patterns
+                .into_iter()
+                .map(|pattern| HostPattern::new(pattern.as_ref()))
+                .collect(),
+        )
+    }
+
+    pub(super) fn is_allowed(&self, url: &Url) -> bool {
+        url.host_str().map_or(false, |host| {
+            self.0.iter().any(|pattern| pattern.matches(host))
+        })
+    }
+
+    /// Follow the redirects to the hosts allowed only.
+    pub(super) fn redirect_policy(self: Arc<Self>) -> Policy {
+        Policy::custom(move |attempt| {
+            if !self.is_allowed(attempt.url()) {
+                let url = Box::new(attempt.url().clone());
+                attempt.error(Error::HostNotAllowed(url))
+            } else if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("too many redirects")
+            } else {
+                attempt.follow()
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::{NonZeroU16, NonZeroU64};
+
+    use super::{super::Client, super::ConnectionOptions, *};
+
+    #[test]
+    fn test_allowed_hosts() {
+        let allowed_hosts = AllowedHosts::new(["github.com", "*.githubusercontent.com"]);
+        let is_allowed = |url| allowed_hosts.is_allowed(&Url::parse(url).unwrap());
+
+        assert!(is_allowed("https://github.com/cargo-bins/cargo-binstall"));
+        assert!(is_allowed("https://GitHub.com/cargo-bins/cargo-binstall"));
+        assert!(is_allowed("https://objects.githubusercontent.com/a"));
+        assert!(!is_allowed("https://api.github.com/repos"));
+        assert!(!is_allowed("https://githubusercontent.com/a"));
+        assert!(!is_allowed("https://github.com.evil.com/a"));
+    }
+
+    #[tokio::test]
+    async fn test_client_refuses_hosts() {
+        let client = Client::new_with_connection_options(
+            "test",
+            None,
+            NonZeroU16::new(10).unwrap(),
+            NonZeroU64::new(1).unwrap(),
+            [],
+            ConnectionOptions {
+                allowed_hosts: Some(vec!["github.com".to_string()]),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let err = client
+            .get(Url::parse("https://example.com/a.tgz").unwrap())
+            .send(false)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::HostNotAllowed(url) if url.host_str() == Some("example.com")));
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering::Relaxed},
+};
+
+use thiserror::Error as ThisError;
+
+/// Limits on the requests sent and the bytes received by a [`Client`](super::Client).
+#[derive(Debug)]
+pub(super) struct Budget {
+    max_requests: Option<u64>,
+    max_bytes: Option<u64>,
+    requests: AtomicU64,
+    bytes: AtomicU64,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BudgetKind {
+    Requests,
+    Bytes,
+}
+
+impl fmt::Display for BudgetKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetKind::Requests => f.write_str("requests"),
+            BudgetKind::Bytes => f.write_str("bytes downloaded"),
+        }
+    }
+}
+
+#[derive(Debug, ThisError)]
+#[error("the budget of {limit} {kind} for this run is exceeded")]
+pub struct BudgetExceeded {
+    pub kind: BudgetKind,
+    pub limit: u64,
+}
+
+impl Budget {
+    pub(super) fn new(max_requests: Option<u64>, max_bytes: Option<u64>) -> Self {
+        Self {
+            max_requests,
+            max_bytes,
+            requests: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Account for a request about to be sent.
+    pub(super) fn add_request(&self) -> Result<(), BudgetExceeded> {
+        let requests = self.requests.fetch_add(1, Relaxed) + 1;
+        match self.max_requests {
+            Some(limit) if requests > limit => Err(BudgetExceeded {
+                kind: BudgetKind::Requests,
+                limit,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Account for `n` bytes received.
+    pub(super) fn add_bytes(&self, n: u64) -> Result<(), BudgetExceeded> {
+        let bytes = self.bytes.fetch_add(n, Relaxed) + n;
+        match self.max_bytes {
+            Some(limit) if bytes > limit => Err(BudgetExceeded {
+                kind: BudgetKind::Bytes,
+                limit,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Return the limit exceeded, if any.
+    pub(super) fn exceeded(&self) -> Option<BudgetExceeded> {
+        match (self.max_requests, self.max_bytes) {
+            (Some(limit), _) if self.requests.load(Relaxed) > limit => Some(BudgetExceeded {
+                kind: BudgetKind::Requests,
+                limit,
+            }),
+            (_, Some(limit)) if self.bytes.load(Relaxed) > limit => Some(BudgetExceeded {
+                kind: BudgetKind::Bytes,
+                limit,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_budget() {
+        let budget = Budget::new(Some(1), Some(10));
+
+        budget.add_request().unwrap();
+        budget.add_bytes(10).unwrap();
+        assert!(budget.exceeded().is_none());
+
+        assert_eq!(budget.add_bytes(1).unwrap_err().kind, BudgetKind::Bytes);
+        assert_eq!(budget.add_request().unwrap_err().kind, BudgetKind::Requests);
+        assert_eq!(budget.exceeded().unwrap().kind, BudgetKind::Requests);
+    }
+}
@@ -4,7 +4,13 @@ use reqwest::tls;
 use super::Error;
 
 #[derive(Clone, Debug)]
-pub struct Certificate(#[cfg(feature = "__tls")] pub(super) tls::Certificate);
+pub struct Certificate(
+    #[cfg(feature = "__tls")] pub(super) tls::Certificate,
+    /// DER encoded certificates, to build the TLS config of pinned
+    /// certificates, see [`ConnectionOptions::pins`](super::ConnectionOptions::pins).
+    #[cfg(feature = "rustls")]
+    pub(super) Vec<rustls::Certificate>,
+);
 
 #[cfg_attr(not(feature = "__tls"), allow(unused_variables))]
 impl Certificate {
@@ -15,7 +21,13 @@ impl Certificate {
 
         #[cfg(feature = "__tls")]
         tls::Certificate::from_der(der.as_ref())
-            .map(Self)
+            .map(|certificate| {
+                Self(
+                    certificate,
+                    #[cfg(feature = "rustls")]
+                    vec![rustls::Certificate(der.as_ref().to_vec())],
+                )
+            })
             .map_err(Error::from)
     }
 
@@ -26,7 +38,50 @@ impl Certificate {
 
         #[cfg(feature = "__tls")]
         tls::Certificate::from_pem(pem.as_ref())
-            .map(Self)
+            .map(|certificate| {
+                Self(
+                    certificate,
+                    #[cfg(feature = "rustls")]
+                    rustls_pemfile::certs(&mut pem.as_ref())
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(rustls::Certificate)
+                        .collect(),
+                )
+            })
             .map_err(Error::from)
     }
+
+    /// Create a Certificate for each of the PEM encoded certificates of
+    /// `pem`, e.g. a CA bundle.
+    pub fn from_pem_bundle(pem: impl AsRef<[u8]>) -> Result<Vec<Self>, Error> {
+        const END: &[u8] = b"-----END CERTIFICATE-----";
+
+        let mut pem = pem.as_ref();
+        let mut certificates = Vec::new();
+
+        while let Some(end) = pem.windows(END.len()).position(|window| window == END) {
+            let (certificate, rest) = pem.split_at(end + END.len());
+            certificates.push(Self::from_pem(certificate)?);
+            pem = rest;
+        }
+
+        Ok(certificates)
+    }
+}
+
+#[cfg(all(test, feature = "__tls"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_from_pem_bundle() {
+        let bundle = format!(
+            "# Test CA\n{cert}\n# Test CA again\n{cert}\n",
+            cert = super::super::identity::test::CERT
+        );
+
+        assert_eq!(Certificate::from_pem_bundle(bundle).unwrap().len(), 2);
+        assert!(Certificate::from_pem_bundle("").unwrap().is_empty());
+    }
 }
@@ -0,0 +1,117 @@
+use std::{
+    fmt::Write,
+    io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering::Relaxed},
+    time::Duration,
+};
+
+use reqwest::{
+    header::{HeaderMap, AUTHORIZATION, COOKIE, PROXY_AUTHORIZATION, SET_COOKIE},
+    Request,
+};
+use tokio::fs;
+use tracing::warn;
+
+/// Records the requests sent and their responses into a directory, one
+/// file per request, so that users can attach them to bug reports.
+#[derive(Debug)]
+pub(super) struct DebugHttp {
+    dir: PathBuf,
+    record_bodies: bool,
+    counter: AtomicUsize,
+}
+
+fn write_headers(record: &mut String, headers: &HeaderMap) {
+    for (name, value) in headers {
+        let value = if value.is_sensitive()
+            || [AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE, SET_COOKIE].contains(name)
+        {
+            "<redacted>"
+        } else {
+            value.to_str().unwrap_or("<non-utf8>")
+        };
+        writeln!(record, "{name}: {value}").unwrap();
+    }
+}
+
+impl DebugHttp {
+    pub(super) fn new(dir: PathBuf, record_bodies: bool) -> io::Result<Self> {
+        std::fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            dir,
+            record_bodies,
+            counter: AtomicUsize::new(0),
+        })
+    }
+
+    /// Record `request` and its result, return the path to record the
+    /// response body to if bodies are recorded.
+    pub(super) async fn record(
+        &self,
+        request: &Request,
+        result: &Result<reqwest::Response, reqwest::Error>,
+        elapsed: Duration,
+    ) -> Option<PathBuf> {
+        let id = self.counter.fetch_add(1, Relaxed);
+        let host = request.url().host_str().unwrap_or("unknown");
+        let path = self
+            .dir
+            .join(format!("{id:04}-{}-{host}.txt", request.method()));
+
+        let mut record = format!("{} {}\n", request.method(), request.url());
+        write_headers(&mut record, request.headers());
+        record.push('\n');
+
+        match result {
+            Ok(response) => {
+                writeln!(record, "{:?} {}", response.version(), response.status()).unwrap();
+                if response.url() != request.url() {
+                    writeln!(record, "final-url: {}", response.url()).unwrap();
+                }
+                writeln!(record, "elapsed: {elapsed:?}").unwrap();
+                write_headers(&mut record, response.headers());
+            }
+            Err(err) => {
+                writeln!(record, "error: {err}").unwrap();
+                writeln!(record, "elapsed: {elapsed:?}").unwrap();
+            }
+        }
+
+        write_record(&path, record.as_bytes()).await;
+
+        (self.record_bodies && result.is_ok()).then(|| path.with_extension("body"))
+    }
+}
+
+pub(super) async fn write_record(path: &Path, content: &[u8]) {
+    if let Err(err) = fs::write(path, content).await {
+        warn!("Failed to record http trace to {}: {err}", path.display());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use reqwest::header::{HeaderValue, USER_AGENT};
+
+    use super::*;
+
+    #[test]
+    fn test_write_headers_redacts_credentials() {
+        let mut headers = HeaderMap::new();
+        headers.insert(USER_AGENT, HeaderValue::from_static("cargo-binstall"));
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+        let mut api_key = HeaderValue::from_static("secret");
+        api_key.set_sensitive(true);
+        headers.insert("x-api-key", api_key);
+
+        let mut record = String::new();
+        write_headers(&mut record, &headers);
+
+        assert_eq!(
+            record,
+            "user-agent: cargo-binstall\nauthorization: <redacted>\nx-api-key: <redacted>\n"
+        );
+    }
+}
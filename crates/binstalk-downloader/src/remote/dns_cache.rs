@@ -0,0 +1,88 @@
+//! Resolver caching the addresses of the hosts for a fixed duration, see
+//! [`ConnectionOptions::dns_cache_ttl`](super::ConnectionOptions::dns_cache_ttl).
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures_util::future;
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use tracing::debug;
+
+type Entries = HashMap<Box<str>, (Instant, Arc<[SocketAddr]>)>;
+
+#[derive(Debug)]
+pub(super) struct DnsCache {
+    ttl: Duration,
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl DnsCache {
+    pub(super) fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Default::default(),
+        }
+    }
+
+    /// Return the addresses of `host` resolved less than `ttl` before `now`.
+    fn get(&self, host: &str, now: Instant) -> Option<Arc<[SocketAddr]>> {
+        let entries = self.entries.lock().unwrap();
+        let (resolved_at, addrs) = entries.get(host)?;
+        (now.saturating_duration_since(*resolved_at) < self.ttl).then(|| addrs.clone())
+    }
+}
+
+fn to_addrs(addrs: Arc<[SocketAddr]>) -> Addrs {
+    Box::new((0..addrs.len()).map(move |i| addrs[i]))
+}
+
+impl Resolve for DnsCache {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host: Box<str> = name.as_str().into();
+
+        if let Some(addrs) = self.get(&host, Instant::now()) {
+            return Box::pin(future::ready(Ok(to_addrs(addrs))));
+        }
+
+        let entries = self.entries.clone();
+        Box::pin(async move {
+            // The port is replaced by the one of the url once resolved.
+            let addrs: Arc<[SocketAddr]> = tokio::net::lookup_host((&*host, 0)).await?.collect();
+            debug!("Resolved {host} to {addrs:?}");
+
+            entries
+                .lock()
+                .unwrap()
+                .insert(host, (Instant::now(), addrs.clone()));
+
+            Ok(to_addrs(addrs))
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dns_cache_expiry() {
+        let cache = DnsCache::new(Duration::from_secs(60));
+        let now = Instant::now();
+        let addrs: Arc<[SocketAddr]> = Arc::new(["140.82.121.3:0".parse().unwrap()]);
+
+        cache
+            .entries
+            .lock()
+            .unwrap()
+            .insert("github.com".into(), (now, addrs.clone()));
+
+        assert_eq!(cache.get("github.com", now), Some(addrs));
+        assert_eq!(cache.get("api.github.com", now), None);
+        assert_eq!(cache.get("github.com", now + Duration::from_secs(60)), None);
+    }
+}
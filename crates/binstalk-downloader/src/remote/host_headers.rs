@@ -0,0 +1,111 @@
+use std::cmp::Reverse;
+
+use reqwest::header::HeaderMap;
+use url::Url;
+
+/// Headers added to the requests to the hosts matching a pattern, e.g. the
+/// token of a private artifact server.
+#[derive(Debug, Default)]
+pub(super) struct HostHeaders(
+    /// Sorted by specificity in descending order: exact hosts first, then
+    /// wildcards by the length of their suffix.
+    Vec<(HostPattern, HeaderMap)>,
+);
+
+#[derive(Debug)]
+pub(super) enum HostPattern {
+    /// `artifacts.corp`, matching only that host.
+    Exact(String),
+    /// `*.corp`, matching any subdomain of `corp` but not `corp` itself.
+    Subdomains(String),
+}
+
+impl HostPattern {
+    pub(super) fn new(pattern: &str) -> Self {
+        let pattern = pattern.to_ascii_lowercase();
+        match pattern.strip_prefix("*.") {
+            Some(suffix) => HostPattern::Subdomains(format!(".{suffix}")),
+            None => HostPattern::Exact(pattern),
+        }
+    }
+
+    pub(super) fn matches(&self, host: &str) -> bool {
+        match self {
+            HostPattern::Exact(pattern) => host == pattern,
+            HostPattern::Subdomains(suffix) => host.ends_with(suffix.as_str()),
+        }
+    }
+
+    fn specificity(&self) -> Reverse<(bool, usize)> {
+        match self {
+            HostPattern::Exact(pattern) => Reverse((true, pattern.len())),
+            HostPattern::Subdomains(suffix) => Reverse((false, suffix.len())),
+        }
+    }
+}
+
+impl HostHeaders {
+    pub(super) fn new(rules: impl IntoIterator<Item = (String, HeaderMap)>) -> Self {
+        let mut rules: Vec<_> = rules
+            .into_iter()
+            .map(|(pattern, headers)| (HostPattern::new(&pattern), headers))
+            .collect();
+        rules.sort_by_key(|(pattern, _)| pattern.specificity());
+        Self(rules)
+    }
+
+    /// Add the headers of the rules matching the host of `url` to
+    /// `headers`, the ones already present are kept, as are the ones of
+    /// the more specific rules.
+    pub(super) fn apply(&self, url: &Url, headers: &mut HeaderMap) {
+        let Some(host) = url.host_str() else { return };
+
+        for (_, rule_headers) in self.0.iter().filter(|(pattern, _)| pattern.matches(host)) {
+            for (name, value) in rule_headers {
+                if !headers.contains_key(name) {
+                    headers.insert(name, value.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use reqwest::header::{HeaderValue, AUTHORIZATION};
+
+    fn authorization(token: &'static str) -> HeaderMap {
+        HeaderMap::from_iter([(AUTHORIZATION, HeaderValue::from_static(token))])
+    }
+
+    fn apply(host_headers: &HostHeaders, url: &str) -> Option<HeaderValue> {
+        let mut headers = HeaderMap::new();
+        host_headers.apply(&Url::parse(url).unwrap(), &mut headers);
+        headers.remove(AUTHORIZATION)
+    }
+
+    #[test]
+    fn test_host_headers() {
+        let host_headers = HostHeaders::new([
+            ("*.corp".to_string(), authorization("Bearer corp")),
+            (
+                "Artifacts.corp".to_string(),
+                authorization("Bearer artifacts"),
+            ),
+        ]);
+
+        assert_eq!(
+            apply(&host_headers, "https://artifacts.corp/a.tgz").unwrap(),
+            "Bearer artifacts"
+        );
+        assert_eq!(
+            apply(&host_headers, "https://cdn.eu.corp/a.tgz").unwrap(),
+            "Bearer corp"
+        );
+        assert_eq!(apply(&host_headers, "https://corp/a.tgz"), None);
+        assert_eq!(apply(&host_headers, "https://notcorp/a.tgz"), None);
+        assert_eq!(apply(&host_headers, "https://github.com/a.tgz"), None);
+    }
+}
@@ -0,0 +1,169 @@
+//! Cache of the responses of metadata endpoints revalidated with conditional
+//! requests, see [`Client::with_http_cache`](super::Client::with_http_cache).
+
+use std::{fs, io, path::PathBuf};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use sha2::{Digest, Sha256};
+use tokio::task::spawn_blocking;
+use tracing::debug;
+
+use super::{header::HeaderValue, Url};
+use crate::download::hex;
+
+/// A cached response along with the validators to revalidate it with.
+#[derive(Debug, PartialEq, Eq)]
+pub(super) struct Entry {
+    pub(super) etag: Option<HeaderValue>,
+    pub(super) last_modified: Option<HeaderValue>,
+    pub(super) body: Bytes,
+}
+
+const ETAG: &str = "ETag: ";
+const LAST_MODIFIED: &str = "Last-Modified: ";
+
+impl Entry {
+    /// Encode the validators as header lines followed by an empty line and
+    /// the body, header values cannot contain newlines.
+    fn encode(&self) -> Bytes {
+        let mut buf = BytesMut::with_capacity(self.body.len() + 128);
+
+        for (name, value) in [(ETAG, &self.etag), (LAST_MODIFIED, &self.last_modified)] {
+            if let Some(value) = value {
+                buf.put_slice(name.as_bytes());
+                buf.put_slice(value.as_bytes());
+                buf.put_u8(b'\n');
+            }
+        }
+        buf.put_u8(b'\n');
+        buf.put_slice(&self.body);
+
+        buf.freeze()
+    }
+
+    fn decode(mut bytes: Bytes) -> Option<Self> {
+        let mut entry = Self {
+            etag: None,
+            last_modified: None,
+            body: Bytes::new(),
+        };
+
+        loop {
+            let end = bytes.iter().position(|byte| *byte == b'\n')?;
+            let line = bytes.split_to(end + 1);
+            let line = &line[..end];
+
+            if line.is_empty() {
+                break;
+            } else if let Some(value) = line.strip_prefix(ETAG.as_bytes()) {
+                entry.etag = Some(HeaderValue::from_bytes(value).ok()?);
+            } else if let Some(value) = line.strip_prefix(LAST_MODIFIED.as_bytes()) {
+                entry.last_modified = Some(HeaderValue::from_bytes(value).ok()?);
+            } else {
+                return None;
+            }
+        }
+
+        entry.body = bytes;
+        Some(entry)
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct HttpCache {
+    dir: PathBuf,
+}
+
+impl HttpCache {
+    pub(super) fn new(dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, url: &Url) -> PathBuf {
+        self.dir.join(hex(Sha256::digest(url.as_str())))
+    }
+
+    /// Return the cached response of `url`, if any.
+    pub(super) async fn load(&self, url: &Url) -> Option<Entry> {
+        match tokio::fs::read(self.path(url)).await {
+            Ok(bytes) => {
+                let entry = Entry::decode(bytes.into());
+                if entry.is_none() {
+                    debug!("Ignoring the malformed cached response of {url}");
+                }
+                entry
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => {
+                debug!("Failed to read the cached response of {url}: {err}");
+                None
+            }
+        }
+    }
+
+    /// Cache the response of `url`, replacing the previous one atomically.
+    ///
+    /// Failing to do so only means the next request will not be
+    /// conditional, so errors are only logged.
+    pub(super) async fn store(&self, url: &Url, entry: Entry) {
+        let dir = self.dir.clone();
+        let path = self.path(url);
+
+        let res = spawn_blocking(move || {
+            let tmp = tempfile::NamedTempFile::new_in(dir)?;
+            fs::write(tmp.path(), entry.encode())?;
+            tmp.persist(path).map_err(io::Error::from)?;
+            Ok::<_, io::Error>(())
+        })
+        .await
+        .map_err(io::Error::from)
+        .and_then(|res| res);
+
+        if let Err(err) = res {
+            debug!("Failed to cache the response of {url}: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_entry_encoding() {
+        let entry = Entry {
+            etag: Some(HeaderValue::from_static("W/\"abc\"")),
+            last_modified: Some(HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT")),
+            body: Bytes::from_static(b"{\n\"assets\": []\n}\n"),
+        };
+        assert_eq!(Entry::decode(entry.encode()), Some(entry));
+
+        let entry = Entry {
+            etag: None,
+            last_modified: Some(HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT")),
+            body: Bytes::new(),
+        };
+        assert_eq!(Entry::decode(entry.encode()), Some(entry));
+
+        assert_eq!(Entry::decode(Bytes::from_static(b"ETag: \"abc\"")), None);
+        assert_eq!(Entry::decode(Bytes::from_static(b"Age: 1\n\n")), None);
+    }
+
+    #[tokio::test]
+    async fn test_http_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = HttpCache::new(dir.path().join("http")).unwrap();
+        let url = Url::parse("https://api.github.com/repos/o/r/releases/tags/v1").unwrap();
+
+        assert_eq!(cache.load(&url).await, None);
+
+        let entry = || Entry {
+            etag: Some(HeaderValue::from_static("\"abc\"")),
+            last_modified: None,
+            body: Bytes::from_static(b"body"),
+        };
+        cache.store(&url, entry()).await;
+        assert_eq!(cache.load(&url).await, Some(entry()));
+    }
+}
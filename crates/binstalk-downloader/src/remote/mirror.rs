@@ -0,0 +1,133 @@
+use std::cmp::Reverse;
+
+use tracing::debug;
+use url::Url;
+
+/// Rules rewriting the url of requests by replacing their prefix, e.g. to
+/// download from a corporate mirror of GitHub releases.
+#[derive(Debug, Default)]
+pub(super) struct Mirrors(
+    /// Sorted by the length of the prefix in descending order, so that the
+    /// most specific rule is used, while keeping the order of the rules of
+    /// the same prefix.
+    Vec<(String, String)>,
+);
+
+impl Mirrors {
+    pub(super) fn new(rules: impl IntoIterator<Item = (String, String)>) -> Self {
+        let mut rules: Vec<_> = rules.into_iter().collect();
+        rules.sort_by_key(|(from, _)| Reverse(from.len()));
+        Self(rules)
+    }
+
+    /// Return the rewritten url if any rule matches `url`.
+    pub(super) fn rewrite(&self, url: &Url) -> Option<Url> {
+        let (from, to) = self
+            .0
+            .iter()
+            .find(|(from, _)| url.as_str().starts_with(from))?;
+
+        rewrite(url, from, to)
+    }
+
+    /// Return the urls rewritten by the rules of the same prefix as the
+    /// rule used by [`Mirrors::rewrite`], except for that one.
+    pub(super) fn fallbacks(&self, url: &Url) -> Vec<Url> {
+        let mut rules = self
+            .0
+            .iter()
+            .skip_while(|(from, _)| !url.as_str().starts_with(from));
+
+        let Some((prefix, _)) = rules.next() else {
+            return Vec::new();
+        };
+
+        rules
+            .take_while(|(from, _)| from == prefix)
+            .filter_map(|(from, to)| rewrite(url, from, to))
+            .collect()
+    }
+}
+
+fn rewrite(url: &Url, from: &str, to: &str) -> Option<Url> {
+    let rewritten = format!("{to}{}", &url.as_str()[from.len()..]);
+    match Url::parse(&rewritten) {
+        Ok(rewritten) => {
+            debug!("Rewriting '{url}' to mirror '{rewritten}'");
+            Some(rewritten)
+        }
+        Err(err) => {
+            debug!("Ignoring invalid mirror url '{rewritten}' for '{url}': {err}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rewrite() {
+        let mirrors = Mirrors::new([
+            (
+                "https://github.com/".to_string(),
+                "https://gh-mirror.corp/".to_string(),
+            ),
+            (
+                "https://github.com/rust-lang/".to_string(),
+                "https://rust-mirror.corp/".to_string(),
+            ),
+        ]);
+
+        let rewrite = |url: &str| {
+            mirrors
+                .rewrite(&Url::parse(url).unwrap())
+                .map(|url| url.to_string())
+        };
+
+        assert_eq!(
+            rewrite("https://github.com/foo/bar/releases/download/v1/bar.tgz").as_deref(),
+            Some("https://gh-mirror.corp/foo/bar/releases/download/v1/bar.tgz")
+        );
+        assert_eq!(
+            rewrite("https://github.com/rust-lang/cargo").as_deref(),
+            Some("https://rust-mirror.corp/cargo")
+        );
+        assert_eq!(rewrite("https://api.github.com/repos/foo/bar"), None);
+    }
+
+    #[test]
+    fn test_fallbacks() {
+        let mirrors = Mirrors::new([
+            (
+                "https://github.com/".to_string(),
+                "https://gh-mirror-1.corp/".to_string(),
+            ),
+            (
+                "https://github.com/rust-lang/".to_string(),
+                "https://rust-mirror.corp/".to_string(),
+            ),
+            (
+                "https://github.com/".to_string(),
+                "https://gh-mirror-2.corp/".to_string(),
+            ),
+        ]);
+
+        let url = Url::parse("https://github.com/foo/bar/releases/download/v1/bar.tgz").unwrap();
+        assert_eq!(
+            mirrors.rewrite(&url).unwrap().as_str(),
+            "https://gh-mirror-1.corp/foo/bar/releases/download/v1/bar.tgz"
+        );
+        assert_eq!(
+            mirrors.fallbacks(&url),
+            [
+                Url::parse("https://gh-mirror-2.corp/foo/bar/releases/download/v1/bar.tgz")
+                    .unwrap()
+            ]
+        );
+
+        let url = Url::parse("https://github.com/rust-lang/cargo").unwrap();
+        assert_eq!(mirrors.fallbacks(&url), []);
+    }
+}
@@ -0,0 +1,335 @@
+//! Pinning of the public keys of the certificates of hosts, see
+//! [`ConnectionOptions::pins`](super::ConnectionOptions::pins).
+
+use std::{fmt, str::FromStr};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+/// Pin of the public key of a certificate of `host`, i.e. the sha256 digest
+/// of its DER encoded SubjectPublicKeyInfo.
+///
+/// It is parsed from `HOST=sha256//BASE64`, the format of the pins of
+/// `curl --pinnedpubkey`, e.g. as printed by
+/// `openssl x509 -pubkey -noout | openssl pkey -pubin -outform der | openssl dgst -sha256 -binary | base64`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CertificatePin {
+    pub host: String,
+    pub spki_sha256: [u8; 32],
+}
+
+impl FromStr for CertificatePin {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (host, pin) = s
+            .split_once('=')
+            .ok_or_else(|| "expected `HOST=sha256//BASE64`".to_string())?;
+        let digest = pin
+            .strip_prefix("sha256//")
+            .ok_or_else(|| format!("expected a pin starting with `sha256//`, found `{pin}`"))?;
+
+        let spki_sha256 = STANDARD
+            .decode(digest)
+            .ok()
+            .and_then(|digest| digest.try_into().ok())
+            .ok_or_else(|| format!("expected a base64 encoded sha256 digest, found `{digest}`"))?;
+
+        Ok(Self {
+            host: host.to_ascii_lowercase(),
+            spki_sha256,
+        })
+    }
+}
+
+impl fmt::Display for CertificatePin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}=sha256//{}",
+            self.host,
+            STANDARD.encode(self.spki_sha256)
+        )
+    }
+}
+
+/// Split the first DER element of `der` into its tag, the element itself
+/// and the rest.
+#[cfg_attr(not(feature = "rustls"), allow(dead_code))]
+fn der_element(der: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = der.split_first()?;
+    let (&len, rest) = rest.split_first()?;
+
+    let (content_len, rest) = if len < 0x80 {
+        (len as usize, rest)
+    } else {
+        let n = (len & 0x7f) as usize;
+        if n == 0 || n > 4 || rest.len() < n {
+            return None;
+        }
+        let len = rest[..n]
+            .iter()
+            .fold(0_usize, |len, byte| (len << 8) | *byte as usize);
+        (len, &rest[n..])
+    };
+
+    let header_len = der.len() - rest.len();
+    let end = header_len.checked_add(content_len)?;
+    (end <= der.len()).then(|| (tag, &der[..end], &der[end..]))
+}
+
+/// Return the content of the DER element `element`.
+#[cfg_attr(not(feature = "rustls"), allow(dead_code))]
+fn der_content(element: &[u8]) -> Option<&[u8]> {
+    let (_, element, _) = der_element(element)?;
+    let len = element[1];
+    let header_len = if len < 0x80 {
+        2
+    } else {
+        2 + (len & 0x7f) as usize
+    };
+    element.get(header_len..)
+}
+
+/// Return the DER encoded SubjectPublicKeyInfo of the DER encoded X.509
+/// certificate `cert`.
+#[cfg_attr(not(feature = "rustls"), allow(dead_code))]
+fn spki(cert: &[u8]) -> Option<&[u8]> {
+    const SEQUENCE: u8 = 0x30;
+    const VERSION: u8 = 0xa0;
+
+    let tbs_certificate = der_content(der_content(cert)?)?;
+
+    let mut rest = tbs_certificate;
+    let mut element = || {
+        let (tag, element, next) = der_element(rest)?;
+        rest = next;
+        Some((tag, element))
+    };
+
+    let (mut tag, mut field) = element()?;
+    if tag == VERSION {
+        (tag, field) = element()?;
+    }
+    // Skip serialNumber, signature, issuer, validity and subject.
+    for _ in 0..5 {
+        (tag, field) = element()?;
+    }
+
+    (tag == SEQUENCE).then_some(field)
+}
+
+/// Return true if the public key of the DER encoded X.509 certificate
+/// `end_entity` matches one of `pins`.
+///
+/// Only the certificate of the host is checked, like `curl --pinnedpubkey`:
+/// the intermediates are sent by the server unverified, so any certificate
+/// could be appended to them.
+#[cfg_attr(not(feature = "rustls"), allow(dead_code))]
+fn is_pinned(end_entity: &[u8], pins: &[[u8; 32]]) -> bool {
+    use sha2::{Digest, Sha256};
+
+    spki(end_entity).map_or(false, |spki| pins.contains(&Sha256::digest(spki).into()))
+}
+
+#[cfg(feature = "rustls")]
+mod verifier {
+    use std::{collections::HashMap, sync::Arc, time::SystemTime};
+
+    use super::{is_pinned, CertificatePin};
+    use crate::remote::{Certificate, Error, Identity, TLSVersion};
+    use rustls::{
+        client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+        Certificate as RustlsCertificate, ClientConfig, OwnedTrustAnchor, RootCertStore,
+        ServerName,
+    };
+
+    /// Verify the certificates like the default verifier, then check that
+    /// the certificate of the hosts pinned matches one of their pins.
+    struct PinningVerifier {
+        inner: WebPkiVerifier,
+        pins: HashMap<String, Vec<[u8; 32]>>,
+    }
+
+    impl ServerCertVerifier for PinningVerifier {
+        fn verify_server_cert(
+            &self,
+            end_entity: &RustlsCertificate,
+            intermediates: &[RustlsCertificate],
+            server_name: &ServerName,
+            scts: &mut dyn Iterator<Item = &[u8]>,
+            ocsp_response: &[u8],
+            now: SystemTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            let verified = self.inner.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                scts,
+                ocsp_response,
+                now,
+            )?;
+
+            let host = match server_name {
+                ServerName::DnsName(name) => name.as_ref().to_ascii_lowercase(),
+                ServerName::IpAddress(ip) => ip.to_string(),
+                _ => return Ok(verified),
+            };
+            let Some(pins) = self.pins.get(&host) else {
+                return Ok(verified);
+            };
+
+            if is_pinned(&end_entity.0, pins) {
+                Ok(verified)
+            } else {
+                Err(rustls::Error::General(format!(
+                    "the certificate of {host} does not match its pinned public keys"
+                )))
+            }
+        }
+    }
+
+    /// Build the TLS config of the client, the same as the one reqwest
+    /// builds except for the certificate verifier.
+    pub(in crate::remote) fn tls_config(
+        min_tls: TLSVersion,
+        certificates: &[Certificate],
+        identity: Option<&Identity>,
+        pins: Vec<CertificatePin>,
+    ) -> Result<ClientConfig, Error> {
+        let mut roots = RootCertStore::empty();
+        roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|trust_anchor| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                trust_anchor.subject,
+                trust_anchor.spki,
+                trust_anchor.name_constraints,
+            )
+        }));
+        for certificate in certificates.iter().flat_map(|certificate| &certificate.1) {
+            roots.add(certificate).map_err(|err| {
+                Error::UnsupportedPinning(format!("invalid root certificate: {err}"))
+            })?;
+        }
+
+        let mut pins_by_host: HashMap<String, Vec<[u8; 32]>> = HashMap::new();
+        for pin in pins {
+            pins_by_host
+                .entry(pin.host)
+                .or_default()
+                .push(pin.spki_sha256);
+        }
+
+        let versions: &[_] = if min_tls >= TLSVersion::TLS_1_3 {
+            &[&rustls::version::TLS13]
+        } else {
+            rustls::ALL_VERSIONS
+        };
+
+        let builder = ClientConfig::builder()
+            .with_safe_default_cipher_suites()
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(versions)
+            .map_err(|err| Error::UnsupportedPinning(err.to_string()))?
+            .with_custom_certificate_verifier(Arc::new(PinningVerifier {
+                inner: WebPkiVerifier::new(roots, None),
+                pins: pins_by_host,
+            }));
+
+        let mut config = match identity {
+            Some(Identity {
+                rustls: Some((certs, key)),
+                ..
+            }) => builder
+                .with_client_auth_cert(certs.clone(), key.clone())
+                .map_err(|err| Error::UnsupportedPinning(err.to_string()))?,
+            Some(Identity { rustls: None, .. }) => {
+                return Err(Error::UnsupportedPinning(
+                    "client certificates of PKCS#12 archives cannot be used with pins".into(),
+                ))
+            }
+            None => builder.with_no_client_auth(),
+        };
+        // reqwest is built without http2.
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+        Ok(config)
+    }
+}
+
+#[cfg(feature = "rustls")]
+pub(super) use verifier::tls_config;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_pin() {
+        let pin: CertificatePin = "GitHub.com=sha256//47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="
+            .parse()
+            .unwrap();
+        assert_eq!(pin.host, "github.com");
+        assert_eq!(pin.to_string().parse(), Ok(pin));
+
+        assert!("github.com".parse::<CertificatePin>().is_err());
+        assert!("github.com=sha1//AAAA".parse::<CertificatePin>().is_err());
+        assert!("github.com=sha256//AAAA".parse::<CertificatePin>().is_err());
+    }
+
+    #[test]
+    fn test_der_element() {
+        assert_eq!(
+            der_element(&[0x02, 0x01, 0x05, 0xff]),
+            Some((0x02, &[0x02, 0x01, 0x05][..], &[0xff][..]))
+        );
+        assert_eq!(der_element(&[0x02, 0x02, 0x05]), None);
+        assert_eq!(der_element(&[0x02, 0x81, 0x01, 0x05]).unwrap().1.len(), 4);
+    }
+
+    #[cfg(feature = "__tls")]
+    fn test_cert() -> Vec<u8> {
+        let cert = super::super::identity::test::CERT;
+        STANDARD
+            .decode(
+                cert.lines()
+                    .filter(|line| !line.starts_with("-----"))
+                    .collect::<String>(),
+            )
+            .unwrap()
+    }
+
+    #[cfg(feature = "__tls")]
+    #[test]
+    fn test_spki() {
+        use sha2::{Digest, Sha256};
+
+        let der = test_cert();
+
+        // The pin printed by openssl, see `CertificatePin`.
+        assert_eq!(
+            STANDARD.encode(Sha256::digest(spki(&der).unwrap())),
+            "CeKyaT5OfN2awlQSomWYJ9ilS0yzgAYD1aMhbMGN2dU="
+        );
+    }
+
+    #[cfg(feature = "__tls")]
+    #[test]
+    fn test_is_pinned() {
+        use sha2::{Digest, Sha256};
+
+        let pinned = test_cert();
+        let pins = [Sha256::digest(spki(&pinned).unwrap()).into()];
+        assert!(is_pinned(&pinned, &pins));
+
+        // An unrelated certificate, with another public key, followed by
+        // the pinned certificate as an intermediate: only the former is
+        // the certificate of the host.
+        let mut unrelated = pinned.clone();
+        let key_end = spki(&pinned).unwrap().as_ptr() as usize - pinned.as_ptr() as usize
+            + spki(&pinned).unwrap().len();
+        unrelated[key_end - 1] ^= 0xff;
+        assert_ne!(spki(&unrelated), spki(&pinned));
+
+        let chain = [unrelated, pinned];
+        assert!(!is_pinned(&chain[0], &pins));
+    }
+}
@@ -0,0 +1,120 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering::Relaxed},
+        Arc, Mutex,
+    },
+};
+
+use tokio::sync::{OnceCell, Semaphore};
+use url::Url;
+
+use super::Error;
+
+/// Maximum number of probes in flight at once, across all crates.
+const MAX_CONCURRENT_PROBES: usize = 32;
+
+/// Caches the result of probing urls for the whole run, so that crates
+/// sharing a repository and templates do not probe the same urls again,
+/// and concurrent probes of the same url are only sent once.
+///
+/// Errors are not cached.
+#[derive(Debug)]
+pub(super) struct ProbeCache {
+    results: Mutex<HashMap<Url, Arc<OnceCell<bool>>>>,
+    semaphore: Semaphore,
+    lookups: AtomicU64,
+    probes: AtomicU64,
+}
+
+/// Number of urls looked up in the probe cache and the number of them
+/// actually probed, the rest are cache hits.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ProbeCacheStats {
+    pub lookups: u64,
+    pub probes: u64,
+}
+
+impl Default for ProbeCache {
+    fn default() -> Self {
+        Self {
+            results: Mutex::default(),
+            semaphore: Semaphore::new(MAX_CONCURRENT_PROBES),
+            lookups: AtomicU64::new(0),
+            probes: AtomicU64::new(0),
+        }
+    }
+}
+
+impl ProbeCache {
+    pub(super) async fn get_or_probe<F>(&self, url: Url, probe: F) -> Result<bool, Error>
+    where
+        F: Future<Output = Result<bool, Error>>,
+    {
+        let cell = self.results.lock().unwrap().entry(url).or_default().clone();
+
+        self.lookups.fetch_add(1, Relaxed);
+
+        cell.get_or_try_init(|| async {
+            let _permit = self
+                .semaphore
+                .acquire()
+                .await
+                .expect("semaphore is never closed");
+            self.probes.fetch_add(1, Relaxed);
+            probe.await
+        })
+        .await
+        .copied()
+    }
+
+    pub(super) fn stats(&self) -> ProbeCacheStats {
+        ProbeCacheStats {
+            lookups: self.lookups.load(Relaxed),
+            probes: self.probes.load(Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_probe_cache() {
+        let cache = ProbeCache::default();
+        let probes = AtomicUsize::new(0);
+        let url = Url::parse("https://example.com/a.tgz").unwrap();
+
+        let probe = |exists| {
+            let probes = &probes;
+            async move {
+                probes.fetch_add(1, Relaxed);
+                Ok(exists)
+            }
+        };
+
+        let (x, y) = tokio::join!(
+            cache.get_or_probe(url.clone(), probe(false)),
+            cache.get_or_probe(url.clone(), probe(true)),
+        );
+        assert!(!x.unwrap());
+        assert!(!y.unwrap());
+        assert_eq!(probes.load(Relaxed), 1);
+
+        let other = Url::parse("https://example.com/b.tgz").unwrap();
+        assert!(cache.get_or_probe(other, probe(true)).await.unwrap());
+        assert_eq!(probes.load(Relaxed), 2);
+
+        assert_eq!(
+            cache.stats(),
+            ProbeCacheStats {
+                lookups: 3,
+                probes: 2
+            }
+        );
+    }
+}
@@ -1,10 +1,20 @@
-use std::fmt;
+use std::{fmt, path::PathBuf, sync::Arc, time::Duration};
 
-use bytes::Bytes;
-use futures_util::{Stream, StreamExt};
+use bytes::{Bytes, BytesMut};
+use futures_util::{future::Either, Stream, StreamExt};
 use reqwest::Method;
+use tracing::debug;
 
-use super::{header, Client, Error, HttpError, StatusCode, Url};
+use super::{
+    budget::Budget,
+    debug_http::write_record,
+    header::{
+        self, HeaderMap, CONTENT_ENCODING, CONTENT_LENGTH, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+        LAST_MODIFIED, TRANSFER_ENCODING,
+    },
+    http_cache::Entry,
+    Client, Error, HttpError, ReadTimeout, StatusCode, Url,
+};
 
 pub use reqwest::Body;
 
@@ -15,64 +25,241 @@ pub use serde_json::Error as JsonError;
 pub struct RequestBuilder {
     pub(super) client: Client,
     pub(super) inner: reqwest::RequestBuilder,
+    /// Whether the url is rewritten by [`Client::with_mirrors`].
+    pub(super) mirrored: bool,
 }
 
 impl RequestBuilder {
     pub fn bearer_auth(self, token: &dyn fmt::Display) -> Self {
         Self {
-            client: self.client,
             inner: self.inner.bearer_auth(token),
+            ..self
         }
     }
 
     pub fn header(self, key: &str, value: &str) -> Self {
         Self {
-            client: self.client,
             inner: self.inner.header(key, value),
+            ..self
         }
     }
 
     pub fn body(self, body: impl Into<Body>) -> Self {
         Self {
-            client: self.client,
             inner: self.inner.body(body.into()),
+            ..self
+        }
+    }
+
+    /// Send the request to the url as is, instead of rewriting it with
+    /// [`Client::with_mirrors`], e.g. to fetch the checksum of an artifact
+    /// from its original host.
+    pub fn without_mirrors(self) -> Self {
+        Self {
+            mirrored: false,
+            ..self
         }
     }
 
     pub async fn send(self, error_for_status: bool) -> Result<Response, Error> {
         let request = self.inner.build()?;
+        send_request(&self.client, request, error_for_status, self.mirrored).await
+    }
+
+    /// Send the request, reusing the response cached by
+    /// [`Client::with_http_cache`] if the server responds that it is not
+    /// modified, and caching the successful responses which have an `ETag`
+    /// or `Last-Modified` header.
+    ///
+    /// The whole body is read before returning, so it is only meant for
+    /// metadata fetched on every run.
+    pub async fn send_cached(self, error_for_status: bool) -> Result<Response, Error> {
+        let Some(http_cache) = &self.client.0.http_cache else {
+            return self.send(error_for_status).await;
+        };
+
+        let mut request = self.inner.build()?;
         let method = request.method().clone();
-        Ok(Response {
-            inner: self.client.send_request(request, error_for_status).await?,
-            method,
-        })
+        let url = request.url().clone();
+
+        let cached = http_cache.load(&url).await;
+        if let Some(cached) = &cached {
+            let headers = request.headers_mut();
+            if let Some(etag) = &cached.etag {
+                headers.insert(IF_NONE_MATCH, etag.clone());
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                headers.insert(IF_MODIFIED_SINCE, last_modified.clone());
+            }
+        }
+
+        let response = send_request(&self.client, request, false, self.mirrored).await?;
+        let status = response.status();
+
+        if status == StatusCode::NOT_MODIFIED {
+            if let Some(cached) = cached {
+                debug!("{url} is not modified, using the cached response");
+                let headers = response.headers().clone();
+                return Ok(Response::buffered(
+                    method,
+                    url,
+                    StatusCode::OK,
+                    headers,
+                    cached.body,
+                ));
+            }
+        }
+
+        let headers = response.headers();
+        let etag = headers.get(ETAG).cloned();
+        let last_modified = headers.get(LAST_MODIFIED).cloned();
+
+        if !status.is_success() || (etag.is_none() && last_modified.is_none()) {
+            return if error_for_status {
+                response.error_for_status()
+            } else {
+                Ok(response)
+            };
+        }
+
+        let headers = headers.clone();
+        let body = response.bytes().await?;
+
+        http_cache
+            .store(
+                &url,
+                Entry {
+                    etag,
+                    last_modified,
+                    body: body.clone(),
+                },
+            )
+            .await;
+
+        Ok(Response::buffered(method, url, status, headers, body))
     }
 }
 
+async fn send_request(
+    client: &Client,
+    request: reqwest::Request,
+    error_for_status: bool,
+    mirrored: bool,
+) -> Result<Response, Error> {
+    let method = request.method().clone();
+    let (inner, body_path) = client
+        .send_request_recorded(request, error_for_status, mirrored)
+        .await?;
+    Ok(Response {
+        url: inner.url().clone(),
+        inner,
+        method,
+        body_path,
+        budget: client.0.budget.clone(),
+        read_timeout: client.1.read,
+    })
+}
+
 #[derive(Debug)]
 pub struct Response {
     inner: reqwest::Response,
     method: Method,
+    url: Url,
+    /// Path to record the body to, see [`Client::with_debug_http`].
+    body_path: Option<PathBuf>,
+    /// See [`Client::with_budget`].
+    budget: Option<Arc<Budget>>,
+    /// See [`Timeouts::read`](super::Timeouts::read).
+    read_timeout: Option<Duration>,
 }
 
 impl Response {
-    pub async fn bytes(self) -> Result<Bytes, Error> {
-        self.inner.bytes().await.map_err(Error::from)
+    /// Create a response whose body is already read and decoded, see
+    /// [`RequestBuilder::send_cached`].
+    ///
+    /// The body is not counted in the budget nor recorded again, since it
+    /// either was already or comes from the cache.
+    fn buffered(
+        method: Method,
+        url: Url,
+        status: StatusCode,
+        mut headers: HeaderMap,
+        body: Bytes,
+    ) -> Self {
+        for name in [CONTENT_ENCODING, CONTENT_LENGTH, TRANSFER_ENCODING] {
+            headers.remove(name);
+        }
+
+        let mut inner = http::Response::new(body);
+        *inner.status_mut() = status;
+        *inner.headers_mut() = headers;
+
+        Self {
+            inner: inner.into(),
+            method,
+            url,
+            body_path: None,
+            budget: None,
+            read_timeout: None,
+        }
+    }
+
+    pub async fn bytes(mut self) -> Result<Bytes, Error> {
+        let bytes = match self.read_timeout {
+            Some(duration) => {
+                let mut bytes = BytesMut::new();
+                loop {
+                    match tokio::time::timeout(duration, self.inner.chunk()).await {
+                        Ok(chunk) => match chunk? {
+                            Some(chunk) => bytes.extend_from_slice(&chunk),
+                            None => break bytes.freeze(),
+                        },
+                        Err(_) => {
+                            return Err(Error::Timeout {
+                                url: Box::new(self.url),
+                                duration,
+                            })
+                        }
+                    }
+                }
+            }
+            None => self.inner.bytes().await?,
+        };
+        if let Some(budget) = &self.budget {
+            budget.add_bytes(bytes.len() as u64)?;
+        }
+        if let Some(body_path) = &self.body_path {
+            write_record(body_path, &bytes).await;
+        }
+        Ok(bytes)
     }
 
     pub fn bytes_stream(self) -> impl Stream<Item = Result<Bytes, Error>> {
-        let url = Box::new(self.inner.url().clone());
+        let url = Box::new(self.url);
         let method = self.method;
+        let budget = self.budget;
+        let read_timeout = self
+            .read_timeout
+            .map(|duration| (Url::clone(&*url), duration));
 
-        self.inner.bytes_stream().map(move |res| {
-            res.map_err(|err| {
+        let stream = self.inner.bytes_stream().map(move |res| {
+            let bytes = res.map_err(|err| {
                 Error::Http(Box::new(HttpError {
                     method: method.clone(),
                     url: Url::clone(&*url),
                     err,
                 }))
-            })
-        })
+            })?;
+            if let Some(budget) = &budget {
+                budget.add_bytes(bytes.len() as u64)?;
+            }
+            Ok(bytes)
+        });
+
+        match read_timeout {
+            Some((url, duration)) => Either::Left(ReadTimeout::new(stream, url, duration)),
+            None => Either::Right(stream),
+        }
     }
 
     pub fn status(&self) -> StatusCode {
@@ -80,7 +267,7 @@ impl Response {
     }
 
     pub fn url(&self) -> &Url {
-        self.inner.url()
+        &self.url
     }
 
     pub fn method(&self) -> &Method {
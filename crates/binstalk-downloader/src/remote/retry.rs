@@ -0,0 +1,145 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use super::{StatusCode, MAX_RETRY_DURATION};
+
+/// A failure which is retried, see [`RetryPolicy::retryable`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Retryable {
+    /// The server cannot be connected to.
+    Connect,
+    /// The request timed out.
+    Timeout,
+    /// The server responded with this status code.
+    Status(StatusCode),
+}
+
+impl fmt::Display for Retryable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Retryable::Connect => f.write_str("connect"),
+            Retryable::Timeout => f.write_str("timeout"),
+            Retryable::Status(status) => write!(f, "{}", status.as_u16()),
+        }
+    }
+}
+
+impl FromStr for Retryable {
+    type Err = String;
+
+    /// Parse `connect`, `timeout` or a status code, e.g. `502`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "connect" => Ok(Retryable::Connect),
+            "timeout" => Ok(Retryable::Timeout),
+            s => StatusCode::from_bytes(s.as_bytes())
+                .map(Retryable::Status)
+                .map_err(|_| {
+                    format!("expected `connect`, `timeout` or a status code, found `{s}`")
+                }),
+        }
+    }
+}
+
+/// How failed requests are retried, see [`Client::with_retry_policy`].
+///
+/// The delay before each retry grows exponentially from `backoff_base`,
+/// unless the server specifies one with a `Retry-After` header, and up to
+/// 2 minutes. Requests to the same host are delayed too in the meantime.
+///
+/// [`Client::with_retry_policy`]: super::Client::with_retry_policy
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt.
+    pub retries: u8,
+    /// Delay before the first retry, doubled for each following one.
+    pub backoff_base: Duration,
+    /// Fraction of the delay, between 0 and 1, added at random so that
+    /// clients failing at the same time do not retry in lockstep.
+    pub jitter: f64,
+    /// Failures which are retried, the others are returned right away.
+    pub retryable: Vec<Retryable>,
+}
+
+impl Default for RetryPolicy {
+    /// Retry twice, from 200ms, on connection errors, timeouts, rate limits
+    /// and the transient errors of gateways and CDNs.
+    fn default() -> Self {
+        Self {
+            retries: 2,
+            backoff_base: Duration::from_millis(200),
+            jitter: 0.5,
+            retryable: vec![
+                Retryable::Connect,
+                Retryable::Timeout,
+                Retryable::Status(StatusCode::REQUEST_TIMEOUT),
+                Retryable::Status(StatusCode::TOO_MANY_REQUESTS),
+                Retryable::Status(StatusCode::BAD_GATEWAY),
+                Retryable::Status(StatusCode::SERVICE_UNAVAILABLE),
+                Retryable::Status(StatusCode::GATEWAY_TIMEOUT),
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub(super) fn is_retryable_error(&self, err: &reqwest::Error) -> bool {
+        (err.is_connect() && self.retryable.contains(&Retryable::Connect))
+            || (err.is_timeout() && self.retryable.contains(&Retryable::Timeout))
+    }
+
+    pub(super) fn is_retryable_status(&self, status: StatusCode) -> bool {
+        self.retryable.contains(&Retryable::Status(status))
+    }
+
+    /// Return the delay before the `retry`-th retry, starting from 1,
+    /// without the jitter.
+    fn base_delay(&self, retry: u16) -> Duration {
+        let factor = 1_u32 << retry.saturating_sub(1).min(16);
+        self.backoff_base
+            .saturating_mul(factor)
+            .min(MAX_RETRY_DURATION)
+    }
+
+    /// Return the delay before the `retry`-th retry, starting from 1.
+    pub(super) fn backoff(&self, retry: u16) -> Duration {
+        let delay = self.base_delay(retry);
+        delay + delay.mul_f64(self.jitter.clamp(0.0, 1.0) * fastrand::f64())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            backoff_base: Duration::from_millis(100),
+            jitter: 0.5,
+            ..Default::default()
+        };
+
+        assert_eq!(policy.base_delay(1), Duration::from_millis(100));
+        assert_eq!(policy.base_delay(2), Duration::from_millis(200));
+        assert_eq!(policy.base_delay(4), Duration::from_millis(800));
+        assert_eq!(policy.base_delay(u16::MAX), MAX_RETRY_DURATION);
+
+        for retry in 1..5 {
+            let delay = policy.backoff(retry);
+            let base = policy.base_delay(retry);
+            assert!(base <= delay && delay <= base.mul_f64(1.5), "{delay:?}");
+        }
+    }
+
+    #[test]
+    fn test_parse_retryable() {
+        for retryable in RetryPolicy::default().retryable {
+            assert_eq!(retryable.to_string().parse(), Ok(retryable));
+        }
+        assert_eq!(
+            "500".parse(),
+            Ok(Retryable::Status(StatusCode::INTERNAL_SERVER_ERROR))
+        );
+        assert!("reset".parse::<Retryable>().is_err());
+    }
+}
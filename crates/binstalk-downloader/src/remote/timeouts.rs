@@ -0,0 +1,131 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use tokio::time::{sleep, Instant, Sleep};
+
+use super::{Error, Url};
+
+/// Timeouts of the requests sent by a [`Client`](super::Client), see
+/// [`Client::with_timeouts`](super::Client::with_timeouts).
+///
+/// There is no timeout by default.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Timeouts {
+    /// Maximum duration to wait for the response to a request, i.e. to
+    /// connect and receive its headers, retries included.
+    pub connect: Option<Duration>,
+    /// Maximum duration to wait for the next bytes of the body of a
+    /// response.
+    pub read: Option<Duration>,
+}
+
+/// Stream of the body of a response, ending with [`Error::Timeout`] if no
+/// bytes are received for [`Timeouts::read`].
+pub(super) struct ReadTimeout<S> {
+    stream: S,
+    url: Url,
+    duration: Duration,
+    sleep: Pin<Box<Sleep>>,
+    timed_out: bool,
+}
+
+impl<S> ReadTimeout<S> {
+    pub(super) fn new(stream: S, url: Url, duration: Duration) -> Self {
+        Self {
+            stream,
+            url,
+            duration,
+            sleep: Box::pin(sleep(duration)),
+            timed_out: false,
+        }
+    }
+}
+
+impl<S> Stream for ReadTimeout<S>
+where
+    S: Stream<Item = Result<Bytes, Error>> + Unpin,
+{
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.timed_out {
+            return Poll::Ready(None);
+        }
+
+        match this.stream.poll_next_unpin(cx) {
+            Poll::Ready(item) => {
+                let deadline = Instant::now() + this.duration;
+                this.sleep.as_mut().reset(deadline);
+                Poll::Ready(item)
+            }
+            Poll::Pending => {
+                ready!(this.sleep.as_mut().poll(cx));
+                this.timed_out = true;
+                Poll::Ready(Some(Err(Error::Timeout {
+                    url: Box::new(this.url.clone()),
+                    duration: this.duration,
+                })))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures_util::stream;
+
+    #[tokio::test]
+    async fn test_read_timeout() {
+        let url = Url::parse("https://example.com/artifact.tgz").unwrap();
+        let stream = stream::iter([Ok(Bytes::from_static(b"artifact"))]).chain(stream::pending());
+
+        let items: Vec<_> = ReadTimeout::new(stream, url, Duration::from_millis(10))
+            .collect()
+            .await;
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].as_ref().unwrap(), "artifact");
+        assert!(matches!(items[1], Err(Error::Timeout { .. })));
+    }
+
+    #[cfg(feature = "__tls")]
+    #[tokio::test]
+    async fn test_connect_timeout() {
+        use std::num::NonZeroU16;
+
+        use tokio::net::TcpListener;
+
+        use crate::remote::Client;
+
+        // Accept connections but never complete the TLS handshake.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("https://{}/", listener.local_addr().unwrap())).unwrap();
+
+        let client = Client::new(
+            "binstall-test",
+            None,
+            NonZeroU16::new(1).unwrap(),
+            1.try_into().unwrap(),
+            [],
+        )
+        .unwrap()
+        .with_timeouts(Timeouts {
+            connect: Some(Duration::from_millis(100)),
+            read: None,
+        });
+
+        let err = client.get(url).send(false).await.unwrap_err();
+        assert!(matches!(err, Error::Timeout { .. }));
+        assert!(err.is_unavailable());
+        drop(listener);
+    }
+}
@@ -5,7 +5,7 @@ use std::sync::{
 
 use binstalk_downloader::gh_api_client::{GhReleaseArtifact, HasReleaseArtifact};
 pub(super) use binstalk_downloader::{
-    download::{Download, ExtractedFiles},
+    download::{extract_file, Download, DownloadError, ExtractedFiles, ProgressReporter},
     gh_api_client::GhApiClient,
     remote::{Client, Url},
 };
@@ -18,42 +18,54 @@ use crate::FetchError;
 
 /// This function returns a future where its size should be at most size of
 /// 2-4 pointers.
+///
+/// `degraded` is set if `url` is a GitHub release artifact but the GitHub API
+/// cannot be used to check for it, see [`crate::Fetcher::is_degraded`].
 pub(super) async fn does_url_exist(
     client: Client,
     gh_api_client: GhApiClient,
     url: &Url,
+    degraded: &AtomicBool,
 ) -> Result<bool, FetchError> {
     static GH_API_CLIENT_FAILED: AtomicBool = AtomicBool::new(false);
     static WARN_RATE_LIMIT_ONCE: Once = Once::new();
     static WARN_UNAUTHORIZED_ONCE: Once = Once::new();
+    static WARN_UNREACHABLE_ONCE: Once = Once::new();
 
     debug!("Checking for package at: '{url}'");
 
-    if !GH_API_CLIENT_FAILED.load(Relaxed) {
-        if let Some(artifact) = GhReleaseArtifact::try_extract_from_url(url) {
+    if let Some(artifact) = GhReleaseArtifact::try_extract_from_url(url) {
+        if !GH_API_CLIENT_FAILED.load(Relaxed) {
             debug!("Using GitHub API to check for existence of artifact, which will also cache the API response");
 
             // The future returned has the same size as a pointer
-            match gh_api_client.has_release_artifact(artifact).await? {
-                HasReleaseArtifact::Yes => return Ok(true),
-                HasReleaseArtifact::No | HasReleaseArtifact::NoSuchRelease => return Ok(false),
+            match Box::pin(gh_api_client.has_release_artifact(artifact)).await {
+                Ok(HasReleaseArtifact::Yes) => return Ok(true),
+                Ok(HasReleaseArtifact::No | HasReleaseArtifact::NoSuchRelease) => return Ok(false),
 
-                HasReleaseArtifact::RateLimit { retry_after } => {
+                Ok(HasReleaseArtifact::RateLimit { retry_after }) => {
                     WARN_RATE_LIMIT_ONCE.call_once(|| {
                         warn!("Your GitHub API token (if any) has reached its rate limit and cannot be used again until {retry_after:?}, so we will fallback to HEAD/GET on the url.");
                         warn!("If you did not supply a github token, consider doing so: GitHub limits unauthorized users to 60 requests per hour per origin IP address.");
                     });
                 }
-                HasReleaseArtifact::Unauthorized => {
+                Ok(HasReleaseArtifact::Unauthorized) => {
                     WARN_UNAUTHORIZED_ONCE.call_once(|| {
                         warn!("GitHub API somehow requires a token for the API access, so we will fallback to HEAD/GET on the url.");
                         warn!("Please consider supplying a token to cargo-binstall to speedup resolution.");
                     });
                 }
+                Err(err) => {
+                    WARN_UNREACHABLE_ONCE.call_once(|| {
+                        warn!("GitHub API is unreachable ({err}), so we will fallback to HEAD/GET on the url.");
+                    });
+                }
             }
 
             GH_API_CLIENT_FAILED.store(true, Relaxed);
         }
+
+        degraded.store(true, Relaxed);
     }
 
     Ok(Box::pin(client.remote_gettable(url.clone())).await?)
@@ -1,19 +1,44 @@
-use std::{future::Future, pin::Pin};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering::Relaxed},
+};
 use tokio::sync::mpsc;
 
 /// Given multiple futures with output = `Result<Option<T>, E>`,
 /// returns the the first one that returns either `Err(_)` or
 /// `Ok(Some(_))`.
 pub struct FuturesResolver<T, E> {
-    rx: mpsc::Receiver<Result<T, E>>,
-    tx: mpsc::Sender<Result<T, E>>,
+    rx: mpsc::UnboundedReceiver<(usize, Option<Result<T, E>>)>,
+    tx: mpsc::UnboundedSender<(usize, Option<Result<T, E>>)>,
+    /// Number of futures inserted.
+    count: AtomicUsize,
+    ordered: bool,
 }
 
 impl<T, E> Default for FuturesResolver<T, E> {
     fn default() -> Self {
-        // We only need the first one, so the channel is of size 1.
-        let (tx, rx) = mpsc::channel(1);
-        Self { tx, rx }
+        let (tx, rx) = mpsc::unbounded_channel();
+        Self {
+            tx,
+            rx,
+            count: AtomicUsize::new(0),
+            ordered: false,
+        }
+    }
+}
+
+impl<T, E> FuturesResolver<T, E> {
+    /// Create a resolver returning the first future inserted that returns
+    /// either `Err(_)` or `Ok(Some(_))`, instead of the first one to
+    /// complete, so that the resolution does not depend on timing.
+    ///
+    /// The futures still run concurrently.
+    pub fn ordered() -> Self {
+        Self {
+            ordered: true,
+            ..Self::default()
+        }
     }
 }
 
@@ -25,17 +50,19 @@ impl<T: Send + 'static, E: Send + 'static> FuturesResolver<T, E> {
         Fut: Future<Output = Result<Option<T>, E>> + Send + 'static,
     {
         let tx = self.tx.clone();
+        let index = self.count.fetch_add(1, Relaxed);
 
         tokio::spawn(async move {
             tokio::pin!(fut);
 
-            Self::spawn_inner(fut, tx).await;
+            Self::spawn_inner(fut, tx, index).await;
         });
     }
 
     async fn spawn_inner(
         fut: Pin<&mut (dyn Future<Output = Result<Option<T>, E>> + Send)>,
-        tx: mpsc::Sender<Result<T, E>>,
+        tx: mpsc::UnboundedSender<(usize, Option<Result<T, E>>)>,
+        index: usize,
     ) {
         let res = tokio::select! {
             biased;
@@ -44,16 +71,9 @@ impl<T: Send + 'static, E: Send + 'static> FuturesResolver<T, E> {
             res = fut => res,
         };
 
-        if let Some(res) = res.transpose() {
-            // try_send can only fail due to being full or being closed.
-            //
-            // In both cases, this could means some other future has
-            // completed first.
-            //
-            // For closed, it could additionally means that the task
-            // is cancelled.
-            tx.try_send(res).ok();
-        }
+        // send can only fail due to being closed, which means the
+        // resolution is done or the task is cancelled.
+        tx.send((index, res.transpose())).ok();
     }
 
     /// Insert multiple futures into this resolver, they will start running
@@ -70,7 +90,66 @@ impl<T: Send + 'static, E: Send + 'static> FuturesResolver<T, E> {
     pub fn resolve(self) -> impl Future<Output = Result<Option<T>, E>> {
         let mut rx = self.rx;
         drop(self.tx);
+        let count = self.count.into_inner();
+        let ordered = self.ordered;
+
+        async move {
+            // Outputs of the futures completed, by index, only used if
+            // `ordered`.
+            let mut outputs: Vec<Option<Option<Result<T, E>>>> = (0..count).map(|_| None).collect();
+            let mut next = 0;
+
+            while let Some((index, output)) = rx.recv().await {
+                if !ordered {
+                    if let Some(res) = output {
+                        return res.map(Some);
+                    }
+                    continue;
+                }
+
+                outputs[index] = Some(output);
+                while let Some(Some(output)) = outputs.get_mut(next).map(Option::take) {
+                    if let Some(res) = output {
+                        return res.map(Some);
+                    }
+                    next += 1;
+                }
+            }
+
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::time::sleep;
+
+    use super::*;
+
+    fn push_all(resolver: &FuturesResolver<u32, ()>) {
+        for (value, delay) in [(None, 1), (Some(1), 30), (Some(2), 0)] {
+            resolver.push(async move {
+                sleep(Duration::from_millis(delay)).await;
+                Ok(value)
+            });
+        }
+    }
+
+    #[tokio::test]
+    async fn test_futures_resolver() {
+        let resolver = FuturesResolver::default();
+        push_all(&resolver);
+        assert_eq!(resolver.resolve().await, Ok(Some(2)));
+
+        let resolver = FuturesResolver::ordered();
+        push_all(&resolver);
+        assert_eq!(resolver.resolve().await, Ok(Some(1)));
 
-        async move { rx.recv().await.transpose() }
+        let resolver = FuturesResolver::<u32, ()>::ordered();
+        resolver.push(async { Ok(None) });
+        assert_eq!(resolver.resolve().await, Ok(None));
     }
 }
@@ -1,9 +1,21 @@
-use std::{borrow::Cow, fmt, iter, marker::PhantomData, path::Path, sync::Arc};
+use std::{
+    borrow::Cow,
+    fmt, io, iter,
+    marker::PhantomData,
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering::Relaxed},
+        Arc,
+    },
+};
 
+use binstalk_types::cargo_toml_binstall::SplitStyle;
 use compact_str::{CompactString, ToCompactString};
 use either::Either;
 use leon::Template;
 use once_cell::sync::OnceCell;
+use percent_encoding::percent_decode_str;
 use strum::IntoEnumIterator;
 use tracing::{debug, warn};
 use url::Url;
@@ -14,6 +26,54 @@ use crate::{
 };
 
 pub(crate) mod hosting;
+use hosting::RepositoryHost;
+
+/// Return the urls of the parts of the package at `url` split into
+/// `parts`, in order.
+fn part_urls(url: &Url, parts: NonZeroU32, style: Option<SplitStyle>) -> Vec<Url> {
+    let path = url.path();
+
+    (1..=parts.get())
+        .map(|part| {
+            let path = match style.unwrap_or(SplitStyle::Parts) {
+                SplitStyle::Parts => format!("{path}.part{part}"),
+                // The last part of a split zip is the `.zip` itself.
+                SplitStyle::Zip if part == parts.get() => return url.clone(),
+                SplitStyle::Zip => {
+                    let file_name_start = path.rfind('/').map_or(0, |i| i + 1);
+                    let stem = match path[file_name_start..].rfind('.') {
+                        Some(i) => &path[..file_name_start + i],
+                        None => path,
+                    };
+                    format!("{stem}.z{part:02}")
+                }
+            };
+
+            let mut url = url.clone();
+            url.set_path(&path);
+            url
+        })
+        .collect()
+}
+
+/// Find the archive named after `name`, with any of `extensions`, among
+/// `extracted_files`.
+fn find_nested_archive<'a>(
+    extracted_files: &'a ExtractedFiles,
+    name: &str,
+    extensions: &[&str],
+) -> Option<&'a Path> {
+    extracted_files
+        .files()
+        .filter(|path| {
+            let Some(file_name) = path.file_name().and_then(|file_name| file_name.to_str()) else {
+                return false;
+            };
+            file_name.contains(name) && extensions.iter().any(|ext| file_name.ends_with(ext))
+        })
+        // Pick the same archive if there are several.
+        .min()
+}
 
 pub struct GhCrateMeta {
     client: Client,
@@ -21,6 +81,7 @@ pub struct GhCrateMeta {
     data: Arc<Data>,
     target_data: Arc<TargetDataErased>,
     resolution: OnceCell<(Url, PkgFmt)>,
+    degraded: Arc<AtomicBool>,
 }
 
 impl GhCrateMeta {
@@ -31,6 +92,7 @@ impl GhCrateMeta {
         pkg_url: &Template<'_>,
         repo: Option<&str>,
         subcrate: Option<&str>,
+        tag: Option<&str>,
     ) {
         let render_url = |ext| {
             let ctx = Context::from_data_with_repo(
@@ -40,7 +102,9 @@ impl GhCrateMeta {
                 ext,
                 repo,
                 subcrate,
-            );
+            )
+            .with_release_naming(self.target_data.meta.asset_name.as_deref(), tag)
+            .with_flavor(self.target_data.meta.flavor.as_deref());
             match ctx.render_url_with_compiled_tt(pkg_url) {
                 Ok(url) => Some(url),
                 Err(err) => {
@@ -50,6 +114,21 @@ impl GhCrateMeta {
             }
         };
 
+        let is_release_of_crate = |url: &Url| match self.target_data.meta.tag_prefix.as_deref() {
+            Some(tag_prefix) => {
+                let matched = is_release_url(pkg_url, url, tag_prefix, &self.data.version);
+                if !matched {
+                    warn!(
+                        "Skipping {url}: it does not belong to release {tag_prefix}{version} of crate {name}",
+                        version = self.data.version,
+                        name = self.data.name,
+                    );
+                }
+                matched
+            }
+            None => true,
+        };
+
         let is_windows = self.target_data.target.contains("windows");
 
         let urls = if pkg_url.has_any_of_keys(&["format", "archive-format", "archive-suffix"]) {
@@ -58,19 +137,29 @@ impl GhCrateMeta {
                 pkg_fmt
                     .extensions(is_windows)
                     .iter()
-                    .filter_map(|ext| render_url(Some(ext))),
+                    .filter_map(|ext| render_url(Some(ext)))
+                    .filter(is_release_of_crate),
             )
         } else {
-            Either::Right(render_url(None).into_iter())
+            Either::Right(render_url(None).into_iter().filter(is_release_of_crate))
         };
 
         // go check all potential URLs at once
         futures_resolver.extend(urls.map(move |url| {
             let client = self.client.clone();
             let gh_api_client = self.gh_api_client.clone();
+            let degraded = self.degraded.clone();
+            let split_parts = self.target_data.meta.split_parts;
+            let split_style = self.target_data.meta.split_style;
 
             async move {
-                Ok(does_url_exist(client, gh_api_client, &url)
+                // The package exists if its first part does.
+                let probe_url = match split_parts {
+                    Some(parts) => Cow::Owned(part_urls(&url, parts, split_style).swap_remove(0)),
+                    None => Cow::Borrowed(&url),
+                };
+
+                Ok(does_url_exist(client, gh_api_client, &probe_url, &degraded)
                     .await?
                     .then_some((url, pkg_fmt)))
             }
@@ -92,6 +181,7 @@ impl super::Fetcher for GhCrateMeta {
             data,
             target_data,
             resolution: OnceCell::new(),
+            degraded: Arc::default(),
         })
     }
 
@@ -104,6 +194,17 @@ impl super::Fetcher for GhCrateMeta {
 
             let mut pkg_fmt = self.target_data.meta.pkg_fmt;
 
+            // Tag of the release for this crate, with '/' escaped so that it
+            // can be used as a single path segment.
+            let tag = self
+                .target_data
+                .meta
+                .tag_prefix
+                .as_deref()
+                .map(|tag_prefix| {
+                    format!("{}{}", tag_prefix.replace('/', "%2F"), self.data.version)
+                });
+
             let pkg_urls = if let Some(pkg_url) = self.target_data.meta.pkg_url.as_deref() {
                 let template = Template::parse(pkg_url)?;
 
@@ -152,17 +253,9 @@ archive-format or archive-suffix which is required for automatically deducing pk
                 ..
             }) = info
             {
-                if let Some(pkg_urls) = repository_host.get_default_pkg_url_template() {
-                    let has_subcrate = subcrate.is_some();
-
-                    Either::Right(
-                        pkg_urls
-                            .map(Template::cast)
-                            // If subcrate is Some, then all templates will be included.
-                            // Otherwise, only templates without key "subcrate" will be
-                            // included.
-                            .filter(move |template| has_subcrate || !template.has_key("subcrate")),
-                    )
+                if let Some(pkg_urls) = default_pkg_urls(*repository_host, subcrate, tag.as_deref())
+                {
+                    Either::Right(pkg_urls.map(Template::cast))
                 } else {
                     warn!(
                         concat!(
@@ -199,7 +292,11 @@ archive-format or archive-suffix which is required for automatically deducing pk
                 Either::Right(PkgFmt::iter())
             };
 
-            let resolver = FuturesResolver::default();
+            let resolver = if self.data.deterministic {
+                FuturesResolver::ordered()
+            } else {
+                FuturesResolver::default()
+            };
 
             // Iterate over pkg_urls first to avoid String::clone.
             for pkg_url in pkg_urls {
@@ -208,7 +305,14 @@ archive-format or archive-suffix which is required for automatically deducing pk
                 //             basically cartesian product.
                 //             |
                 for pkg_fmt in pkg_fmts.clone() {
-                    this.launch_baseline_find_tasks(&resolver, pkg_fmt, &pkg_url, repo, subcrate);
+                    this.launch_baseline_find_tasks(
+                        &resolver,
+                        pkg_fmt,
+                        &pkg_url,
+                        repo,
+                        subcrate,
+                        tag.as_deref(),
+                    );
                 }
             }
 
@@ -222,15 +326,70 @@ archive-format or archive-suffix which is required for automatically deducing pk
         })
     }
 
-    async fn fetch_and_extract(&self, dst: &Path) -> Result<ExtractedFiles, FetchError> {
+    async fn fetch_and_extract(
+        &self,
+        dst: &Path,
+        artifact_path: Option<&Path>,
+        progress_reporter: Option<Arc<dyn ProgressReporter>>,
+    ) -> Result<ExtractedFiles, FetchError> {
         let (url, pkg_fmt) = self.resolution.get().unwrap(); // find() is called first
         debug!(
             "Downloading package from: '{url}' dst:{} fmt:{pkg_fmt:?}",
             dst.display()
         );
-        Ok(Download::new(self.client.clone(), url.clone())
-            .and_extract(*pkg_fmt, dst)
-            .await?)
+        let mut download = match self.target_data.meta.split_parts {
+            Some(parts) => {
+                let mut part_urls =
+                    part_urls(url, parts, self.target_data.meta.split_style).into_iter();
+                Download::new(self.client.clone(), part_urls.next().unwrap()).with_parts(part_urls)
+            }
+            None => Download::new(self.client.clone(), url.clone()),
+        };
+        if let Some(artifact_path) = artifact_path {
+            download = download.with_tee(artifact_path);
+        }
+        if let Some(progress_reporter) = progress_reporter {
+            download = download.with_progress_reporter(progress_reporter);
+        }
+
+        let Some(nested_fmt) = self.target_data.meta.nested_fmt else {
+            return Ok(download.and_extract(*pkg_fmt, dst).await?);
+        };
+
+        let mut outer_dir = dst.as_os_str().to_owned();
+        outer_dir.push("-outer");
+        let outer_dir = PathBuf::from(outer_dir);
+
+        // The nested archive is looked up by name below instead.
+        let extracted_files = download
+            .with_nested_depth(0)
+            .and_extract(*pkg_fmt, &outer_dir)
+            .await?;
+
+        let name = self
+            .target_data
+            .meta
+            .asset_name
+            .as_deref()
+            .unwrap_or(&self.data.name);
+        let is_windows = self.target_data.target.contains("windows");
+
+        let nested = find_nested_archive(&extracted_files, name, nested_fmt.extensions(is_windows))
+            .ok_or_else(|| {
+                DownloadError::from(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no {nested_fmt} archive named after {name} in {url}"),
+                ))
+            })?;
+        debug!("Extracting nested archive '{}'", nested.display());
+
+        let res = extract_file(&outer_dir.join(nested), nested_fmt, dst).await;
+
+        if let Err(err) = tokio::fs::remove_dir_all(&outer_dir).await {
+            debug!("Failed to remove '{}': {err}", outer_dir.display());
+        }
+
+        Ok(res?)
     }
 
     fn pkg_fmt(&self) -> PkgFmt {
@@ -258,6 +417,14 @@ archive-format or archive-suffix which is required for automatically deducing pk
             .unwrap_or_else(|| "invalid url".into())
     }
 
+    fn is_degraded(&self) -> bool {
+        self.degraded.load(Relaxed)
+    }
+
+    fn download_url(&self) -> Option<&Url> {
+        self.resolution.get().map(|(url, _pkg_fmt)| url)
+    }
+
     fn fetcher_name(&self) -> &'static str {
         "GhCrateMeta"
     }
@@ -294,6 +461,15 @@ struct Context<'c> {
     /// Workspace of the crate inside the repository.
     subcrate: Option<&'c str>,
 
+    /// Name of the release assets, defaults to `name`.
+    asset_name: &'c str,
+
+    /// Release tag of the crate, only set if `tag-prefix` is specified.
+    tag: Option<&'c str>,
+
+    /// Build flavor, only set if `flavor` is specified.
+    flavor: Option<&'c str>,
+
     target_related_info: &'c dyn leon::Values,
 }
 
@@ -315,6 +491,12 @@ impl fmt::Debug for Context<'_> {
 
             subcrate: Option<&'c str>,
 
+            asset_name: &'c str,
+
+            tag: Option<&'c str>,
+
+            flavor: Option<&'c str>,
+
             target_related_info: PhantomData<&'c dyn leon::Values>,
         }
 
@@ -333,6 +515,12 @@ impl fmt::Debug for Context<'_> {
 
                 subcrate: self.subcrate,
 
+                asset_name: self.asset_name,
+
+                tag: self.tag,
+
+                flavor: self.flavor,
+
                 target_related_info: PhantomData,
             },
             f,
@@ -359,6 +547,12 @@ impl leon::Values for Context<'_> {
 
             "subcrate" => self.subcrate.map(Cow::Borrowed),
 
+            "asset-name" => Some(Cow::Borrowed(self.asset_name)),
+
+            "tag" => self.tag.map(Cow::Borrowed),
+
+            "flavor" => self.flavor.map(Cow::Borrowed),
+
             key => self.target_related_info.get_value(key),
         }
     }
@@ -399,10 +593,29 @@ impl<'c> Context<'c> {
             },
             subcrate,
 
+            asset_name: &data.name,
+            tag: None,
+            flavor: None,
+
             target_related_info,
         }
     }
 
+    /// Set the asset name and release tag declared by the crate.
+    fn with_release_naming(mut self, asset_name: Option<&'c str>, tag: Option<&'c str>) -> Self {
+        if let Some(asset_name) = asset_name {
+            self.asset_name = asset_name;
+        }
+        self.tag = tag;
+        self
+    }
+
+    /// Set the build flavor selected.
+    fn with_flavor(mut self, flavor: Option<&'c str>) -> Self {
+        self.flavor = flavor;
+        self
+    }
+
     /// * `tt` - must have added a template named "pkg_url".
     fn render_url_with_compiled_tt(&self, tt: &Template<'_>) -> Result<Url, FetchError> {
         debug!("Render {tt:#?} using context: {self:?}");
@@ -419,9 +632,69 @@ impl<'c> Context<'c> {
     }
 }
 
+/// Return the default pkg-url templates of `repository_host` for a crate in
+/// `subcrate` whose releases are tagged `tag`.
+fn default_pkg_urls(
+    repository_host: RepositoryHost,
+    subcrate: Option<&str>,
+    tag: Option<&str>,
+) -> Option<impl Iterator<Item = Template<'static>>> {
+    let pkg_urls = repository_host.get_default_pkg_url_template()?;
+
+    let has_subcrate = subcrate.is_some();
+    // Hosts such as Bitbucket do not organise their downloads by release.
+    let has_tag = tag.is_some() && pkg_urls.clone().any(|template| template.has_key("tag"));
+
+    Some(pkg_urls.filter(move |template| {
+        if has_tag {
+            // The crate declares its own tag prefix, so only
+            // releases tagged with it are considered.
+            template.has_key("tag")
+        } else {
+            // If subcrate is Some, then all templates without
+            // key "tag" will be included.
+            // Otherwise, only templates without key "subcrate"
+            // will be included.
+            !template.has_key("tag") && (has_subcrate || !template.has_key("subcrate"))
+        }
+    }))
+}
+
+/// Return true if `url`, rendered from `pkg_url`, points into the release
+/// `{tag_prefix}{version}` of this crate.
+///
+/// Only the default release paths of the forges, which render `{ tag }`, are
+/// known to contain the release tag, so the urls of other templates, e.g. a
+/// custom pkg-url or the downloads of Bitbucket, are always accepted.
+fn is_release_url(pkg_url: &Template<'_>, url: &Url, tag_prefix: &str, version: &str) -> bool {
+    !pkg_url.has_key("tag") || is_url_of_release(url, tag_prefix, version)
+}
+
+/// Return true if one of the path segments of `url` is the release tag
+/// `{tag_prefix}{version}`, i.e. `url` points into the release of this crate.
+fn is_url_of_release(url: &Url, tag_prefix: &str, version: &str) -> bool {
+    let Some(segments) = url.path_segments() else {
+        return false;
+    };
+
+    segments
+        .map(|segment| percent_decode_str(segment).decode_utf8_lossy())
+        .any(|segment| {
+            segment
+                .strip_prefix(tag_prefix)
+                .map(|rest| rest == version)
+                .unwrap_or(false)
+        })
+}
+
 #[cfg(test)]
 mod test {
-    use super::{super::Data, Context};
+    use std::num::NonZeroU32;
+
+    use super::{
+        super::Data, default_pkg_urls, is_release_url, is_url_of_release, part_urls, Context,
+        RepositoryHost, SplitStyle,
+    };
     use compact_str::ToCompactString;
     use url::Url;
 
@@ -540,4 +813,146 @@ mod test {
             "https://github.com/watchexec/cargo-watch/releases/download/v9.0.0/cargo-watch-v9.0.0-aarch64-pc-windows-msvc.exe"
         );
     }
+
+    #[test]
+    fn release_naming() {
+        let data = Data::new(
+            "foo-cli".to_compact_string(),
+            "0.3.0".to_compact_string(),
+            Some("https://github.com/example/monorepo".to_string()),
+        );
+        let target_info = leon::vals(|_| None);
+
+        let ctx = Context::from_data_with_repo(
+            &data,
+            "x86_64-unknown-linux-musl",
+            &target_info,
+            Some(".tgz"),
+            data.repo.as_deref(),
+            None,
+        )
+        .with_release_naming(Some("foo"), Some("foo-cli%2Fv0.3.0"));
+
+        assert_eq!(
+            ctx.render_url("{ repo }/releases/download/{ tag }/{ asset-name }-{ target }{ archive-suffix }")
+                .unwrap(),
+            Url::parse("https://github.com/example/monorepo/releases/download/foo-cli%2Fv0.3.0/foo-x86_64-unknown-linux-musl.tgz")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn flavor() {
+        let data = Data::new(
+            "foo".to_compact_string(),
+            "0.3.0".to_compact_string(),
+            Some("https://github.com/example/foo".to_string()),
+        );
+        let target_info = leon::vals(|_| None);
+        let ctx = Context::from_data_with_repo(
+            &data,
+            "x86_64-unknown-linux-musl",
+            &target_info,
+            Some(".tgz"),
+            data.repo.as_deref(),
+            None,
+        );
+        let template = "{ repo }/releases/download/v{ version }/{ name }-{ flavor }-{ target }{ archive-suffix }";
+
+        assert!(ctx.render_url(template).is_err());
+        assert_eq!(
+            ctx.with_flavor(Some("minimal")).render_url(template).unwrap(),
+            Url::parse("https://github.com/example/foo/releases/download/v0.3.0/foo-minimal-x86_64-unknown-linux-musl.tgz")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn url_of_release() {
+        let url = Url::parse(
+            "https://github.com/example/monorepo/releases/download/foo-cli%2Fv0.3.0/foo.tgz",
+        )
+        .unwrap();
+        assert!(is_url_of_release(&url, "foo-cli/v", "0.3.0"));
+        assert!(!is_url_of_release(&url, "bar-cli/v", "0.3.0"));
+        assert!(!is_url_of_release(&url, "foo-cli/v", "0.3.1"));
+
+        let url =
+            Url::parse("https://github.com/example/monorepo/releases/download/bar-v0.3.0/bar.tgz")
+                .unwrap();
+        assert!(is_url_of_release(&url, "bar-v", "0.3.0"));
+        assert!(!is_url_of_release(&url, "foo-v", "0.3.0"));
+    }
+
+    #[test]
+    fn release_url_of_default_pkg_urls() {
+        let tag = Some("foo-v0.3.0");
+
+        let templates: Vec<_> = default_pkg_urls(RepositoryHost::GitHub, None, tag)
+            .unwrap()
+            .collect();
+        assert!(!templates.is_empty());
+        assert!(templates.iter().all(|template| template.has_key("tag")));
+        let url =
+            Url::parse("https://github.com/example/monorepo/releases/download/bar-v0.3.0/foo.tgz")
+                .unwrap();
+        assert!(!is_release_url(&templates[0], &url, "foo-v", "0.3.0"));
+
+        // Bitbucket downloads are not organised by release.
+        let templates: Vec<_> = default_pkg_urls(RepositoryHost::BitBucket, None, tag)
+            .unwrap()
+            .collect();
+        assert!(!templates.is_empty());
+        let url = Url::parse(
+            "https://bitbucket.org/example/monorepo/downloads/foo-x86_64-unknown-linux-gnu-v0.3.0.tgz",
+        )
+        .unwrap();
+        assert!(templates
+            .iter()
+            .all(|template| is_release_url(template, &url, "foo-v", "0.3.0")));
+    }
+
+    #[test]
+    fn release_url_of_custom_pkg_url() {
+        let template =
+            leon::Template::parse("https://example.com/{ name }-{ version }.tgz").unwrap();
+        let url = Url::parse("https://example.com/foo-0.3.0.tgz").unwrap();
+        assert!(is_release_url(&template, &url, "foo-v", "0.3.0"));
+    }
+
+    #[test]
+    fn test_part_urls() {
+        let parts = NonZeroU32::new(3).unwrap();
+        let part_urls = |url, style| {
+            part_urls(&Url::parse(url).unwrap(), parts, style)
+                .into_iter()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            part_urls("https://example.com/foo-v0.1.0.tar.gz?raw=true", None),
+            [
+                "https://example.com/foo-v0.1.0.tar.gz.part1?raw=true",
+                "https://example.com/foo-v0.1.0.tar.gz.part2?raw=true",
+                "https://example.com/foo-v0.1.0.tar.gz.part3?raw=true",
+            ]
+        );
+        assert_eq!(
+            part_urls("https://example.com/v0.1.0/foo.zip", Some(SplitStyle::Zip)),
+            [
+                "https://example.com/v0.1.0/foo.z01",
+                "https://example.com/v0.1.0/foo.z02",
+                "https://example.com/v0.1.0/foo.zip",
+            ]
+        );
+        assert_eq!(
+            part_urls("https://example.com/v0.1/foo", Some(SplitStyle::Zip)),
+            [
+                "https://example.com/v0.1/foo.z01",
+                "https://example.com/v0.1/foo.z02",
+                "https://example.com/v0.1/foo",
+            ]
+        );
+    }
 }
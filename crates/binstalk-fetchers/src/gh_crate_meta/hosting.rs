@@ -15,19 +15,19 @@ pub enum RepositoryHost {
 /// Make sure to update possible_dirs in `bins::infer_bin_dir_template`
 /// if you modified FULL_FILENAMES or NOVERSION_FILENAMES.
 pub const FULL_FILENAMES: &[Template<'_>] = &[
-    template!("/{ name }-{ target }-v{ version }{ archive-suffix }"),
-    template!("/{ name }-{ target }-{ version }{ archive-suffix }"),
-    template!("/{ name }-{ version }-{ target }{ archive-suffix }"),
-    template!("/{ name }-v{ version }-{ target }{ archive-suffix }"),
-    template!("/{ name }_{ target }_v{ version }{ archive-suffix }"),
-    template!("/{ name }_{ target }_{ version }{ archive-suffix }"),
-    template!("/{ name }_{ version }_{ target }{ archive-suffix }"),
-    template!("/{ name }_v{ version }_{ target }{ archive-suffix }"),
+    template!("/{ asset-name }-{ target }-v{ version }{ archive-suffix }"),
+    template!("/{ asset-name }-{ target }-{ version }{ archive-suffix }"),
+    template!("/{ asset-name }-{ version }-{ target }{ archive-suffix }"),
+    template!("/{ asset-name }-v{ version }-{ target }{ archive-suffix }"),
+    template!("/{ asset-name }_{ target }_v{ version }{ archive-suffix }"),
+    template!("/{ asset-name }_{ target }_{ version }{ archive-suffix }"),
+    template!("/{ asset-name }_{ version }_{ target }{ archive-suffix }"),
+    template!("/{ asset-name }_v{ version }_{ target }{ archive-suffix }"),
 ];
 
 pub const NOVERSION_FILENAMES: &[Template<'_>] = &[
-    template!("/{ name }-{ target }{ archive-suffix }"),
-    template!("/{ name }_{ target }{ archive-suffix }"),
+    template!("/{ asset-name }-{ target }{ archive-suffix }"),
+    template!("/{ asset-name }_{ target }{ archive-suffix }"),
 ];
 
 const GITHUB_RELEASE_PATHS: &[Template<'_>] = &[
@@ -36,6 +36,7 @@ const GITHUB_RELEASE_PATHS: &[Template<'_>] = &[
     // %2F is escaped form of '/'
     template!("{ repo }/releases/download/{ subcrate }%2F{ version }"),
     template!("{ repo }/releases/download/{ subcrate }%2Fv{ version }"),
+    template!("{ repo }/releases/download/{ tag }"),
 ];
 
 const GITLAB_RELEASE_PATHS: &[Template<'_>] = &[
@@ -44,6 +45,7 @@ const GITLAB_RELEASE_PATHS: &[Template<'_>] = &[
     // %2F is escaped form of '/'
     template!("{ repo }/-/releases/{ subcrate }%2F{ version }/downloads/binaries"),
     template!("{ repo }/-/releases/{ subcrate }%2Fv{ version }/downloads/binaries"),
+    template!("{ repo }/-/releases/{ tag }/downloads/binaries"),
 ];
 
 const BITBUCKET_RELEASE_PATHS: &[Template<'_>] = &[template!("{ repo }/downloads")];
@@ -54,6 +56,7 @@ const SOURCEFORGE_RELEASE_PATHS: &[Template<'_>] = &[
     // %2F is escaped form of '/'
     template!("{ repo }/files/binaries/{ subcrate }%2F{  version }"),
     template!("{ repo }/files/binaries/{ subcrate }%2Fv{ version }"),
+    template!("{ repo }/files/binaries/{ tag }"),
 ];
 
 impl RepositoryHost {
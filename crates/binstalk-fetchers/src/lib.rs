@@ -17,6 +17,9 @@ mod quickinstall;
 #[cfg(feature = "quickinstall")]
 pub use quickinstall::*;
 
+mod source_archive;
+pub use source_archive::*;
+
 mod common;
 use common::*;
 
@@ -85,7 +88,18 @@ pub trait Fetcher: Send + Sync {
         Self: Sized;
 
     /// Fetch a package and extract
-    async fn fetch_and_extract(&self, dst: &Path) -> Result<ExtractedFiles, FetchError>;
+    ///
+    /// The progress of downloading the package and of extracting huge
+    /// entries is reported to `progress_reporter`.
+    ///
+    /// The package downloaded is also written as is to `artifact_path` if
+    /// specified, see [`Download::with_tee`].
+    async fn fetch_and_extract(
+        &self,
+        dst: &Path,
+        artifact_path: Option<&Path>,
+        progress_reporter: Option<Arc<dyn ProgressReporter>>,
+    ) -> Result<ExtractedFiles, FetchError>;
 
     /// Find the package, if it is available for download
     ///
@@ -110,6 +124,17 @@ pub trait Fetcher: Send + Sync {
     /// A short human-readable name or descriptor for the package source
     fn source_name(&self) -> CompactString;
 
+    /// Return true if [`Fetcher::find`] could not use the GitHub API and
+    /// fell back to probing the urls of GitHub release artifacts directly.
+    fn is_degraded(&self) -> bool {
+        false
+    }
+
+    /// Return the url of the artifact found by [`Fetcher::find`].
+    fn download_url(&self) -> Option<&Url> {
+        None
+    }
+
     /// A short human-readable name, must contains only characters
     /// and numbers and it also must be unique.
     ///
@@ -140,6 +165,7 @@ pub struct Data {
     version: CompactString,
     repo: Option<String>,
     repo_info: OnceCell<Option<RepoInfo>>,
+    deterministic: bool,
 }
 
 impl Data {
@@ -149,9 +175,18 @@ impl Data {
             version,
             repo,
             repo_info: OnceCell::new(),
+            deterministic: false,
         }
     }
 
+    /// Pick the first artifact found in the order the candidates are
+    /// tried, instead of the first one to respond, so that the artifact
+    /// found does not depend on timing.
+    pub fn with_deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
     #[instrument(level = "debug")]
     async fn get_repo_info(&self, client: &Client) -> Result<&Option<RepoInfo>, FetchError> {
         self.repo_info
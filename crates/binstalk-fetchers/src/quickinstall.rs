@@ -1,4 +1,10 @@
-use std::{path::Path, sync::Arc};
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering::Relaxed},
+        Arc,
+    },
+};
 
 use binstalk_downloader::remote::Method;
 use binstalk_types::cargo_toml_binstall::{PkgFmt, PkgMeta};
@@ -26,7 +32,7 @@ async fn get_quickinstall_supported_targets(
         .get_or_try_init(|| async {
             let bytes = client
                 .get(Url::parse(QUICKINSTALL_SUPPORTED_TARGETS_URL)?)
-                .send(true)
+                .send_cached(true)
                 .await?
                 .bytes()
                 .await?;
@@ -47,6 +53,7 @@ pub struct QuickInstall {
     client: Client,
     gh_api_client: GhApiClient,
     is_supported_v: OnceCell<bool>,
+    degraded: AtomicBool,
 
     package: String,
     package_url: Url,
@@ -87,6 +94,7 @@ impl super::Fetcher for QuickInstall {
             client,
             gh_api_client,
             is_supported_v: OnceCell::new(),
+            degraded: AtomicBool::new(false),
 
             package_url: Url::parse(&format!(
                 "{BASE_URL}/{crate_name}-{version}/{package}.tar.gz",
@@ -110,6 +118,7 @@ impl super::Fetcher for QuickInstall {
                 self.client.clone(),
                 self.gh_api_client.clone(),
                 &self.package_url,
+                &self.degraded,
             )
             .await
         })
@@ -137,12 +146,22 @@ by rust officially."#,
         }
     }
 
-    async fn fetch_and_extract(&self, dst: &Path) -> Result<ExtractedFiles, FetchError> {
+    async fn fetch_and_extract(
+        &self,
+        dst: &Path,
+        artifact_path: Option<&Path>,
+        progress_reporter: Option<Arc<dyn ProgressReporter>>,
+    ) -> Result<ExtractedFiles, FetchError> {
         let url = &self.package_url;
         debug!("Downloading package from: '{url}'");
-        Ok(Download::new(self.client.clone(), url.clone())
-            .and_extract(self.pkg_fmt(), dst)
-            .await?)
+        let mut download = Download::new(self.client.clone(), url.clone());
+        if let Some(artifact_path) = artifact_path {
+            download = download.with_tee(artifact_path);
+        }
+        if let Some(progress_reporter) = progress_reporter {
+            download = download.with_progress_reporter(progress_reporter);
+        }
+        Ok(download.and_extract(self.pkg_fmt(), dst).await?)
     }
 
     fn pkg_fmt(&self) -> PkgFmt {
@@ -160,6 +179,14 @@ by rust officially."#,
         CompactString::from("QuickInstall")
     }
 
+    fn is_degraded(&self) -> bool {
+        self.degraded.load(Relaxed)
+    }
+
+    fn download_url(&self) -> Option<&Url> {
+        Some(&self.package_url)
+    }
+
     fn fetcher_name(&self) -> &'static str {
         "QuickInstall"
     }
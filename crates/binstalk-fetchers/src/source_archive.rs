@@ -0,0 +1,99 @@
+use crate::{common::*, Data, FetchError, RepoInfo};
+
+use crate::gh_crate_meta::hosting::RepositoryHost;
+
+/// Find the source archive of the release tag of the crate in its repository.
+///
+/// If `tag_prefix` is `None`, then tags `v{version}` and `{version}` are
+/// tried in order.
+///
+/// Return `None` if the repository is not hosted on GitHub or GitLab, or if
+/// none of the source archives exist.
+pub async fn find_tag_source_archive(
+    client: &Client,
+    data: &Data,
+    tag_prefix: Option<&str>,
+) -> Result<Option<Url>, FetchError> {
+    let Some(RepoInfo {
+        repo,
+        repository_host,
+        ..
+    }) = data.get_repo_info(client).await?
+    else {
+        return Ok(None);
+    };
+
+    let version = &data.version;
+    let tags = if let Some(tag_prefix) = tag_prefix {
+        vec![format!("{tag_prefix}{version}")]
+    } else {
+        vec![format!("v{version}"), version.to_string()]
+    };
+
+    for tag in tags {
+        let Some(url) = source_archive_url(repo, *repository_host, &tag)? else {
+            return Ok(None);
+        };
+
+        debug!("Checking for source archive at: '{url}'");
+
+        if client.remote_gettable(url.clone()).await? {
+            return Ok(Some(url));
+        }
+    }
+
+    Ok(None)
+}
+
+fn source_archive_url(
+    repo: &Url,
+    repository_host: RepositoryHost,
+    tag: &str,
+) -> Result<Option<Url>, FetchError> {
+    let repo = repo.as_str().trim_end_matches('/');
+
+    let url = match repository_host {
+        RepositoryHost::GitHub => format!("{repo}/archive/refs/tags/{tag}.tar.gz"),
+        RepositoryHost::GitLab => {
+            let project = repo.rsplit('/').next().unwrap_or(repo);
+            let tag = tag.replace('/', "%2F");
+            format!("{repo}/-/archive/{tag}/{project}-{tag}.tar.gz")
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some(Url::parse(&url)?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_source_archive_url() {
+        let repo = Url::parse("https://github.com/cargo-bins/cargo-binstall").unwrap();
+        assert_eq!(
+            source_archive_url(&repo, RepositoryHost::GitHub, "v1.2.3")
+                .unwrap()
+                .unwrap()
+                .as_str(),
+            "https://github.com/cargo-bins/cargo-binstall/archive/refs/tags/v1.2.3.tar.gz"
+        );
+
+        let repo = Url::parse("https://gitlab.com/example/monorepo").unwrap();
+        assert_eq!(
+            source_archive_url(&repo, RepositoryHost::GitLab, "foo/v1.2.3")
+                .unwrap()
+                .unwrap()
+                .as_str(),
+            "https://gitlab.com/example/monorepo/-/archive/foo%2Fv1.2.3/monorepo-foo%2Fv1.2.3.tar.gz"
+        );
+
+        let repo = Url::parse("https://bitbucket.org/example/repo").unwrap();
+        assert!(
+            source_archive_url(&repo, RepositoryHost::BitBucket, "v1.2.3")
+                .unwrap()
+                .is_none()
+        );
+    }
+}
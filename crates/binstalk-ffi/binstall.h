@@ -0,0 +1,48 @@
+#ifndef BINSTALL_H
+#define BINSTALL_H
+
+/*
+ * C ABI of binstalk, see crates/binstalk-ffi/src/lib.rs for details.
+ *
+ * Strings returned are JSON documents of the form
+ * {"ok": <value>} or {"error": {"code": <code>, "message": <message>}}
+ * and must be freed with binstall_string_free.
+ *
+ * Panics are reported as errors with the code "binstall::ffi::panic".
+ */
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct BinstallHandle BinstallHandle;
+
+/*
+ * Create a handle from a JSON config, e.g.
+ * {"install-path": "/home/user/.cargo/bin", "strategies": ["crate-meta-data", "compile"]}
+ *
+ * Return NULL on failure, in which case *error is set if error is not NULL.
+ */
+BinstallHandle *binstall_init(const char *config, char **error);
+
+/*
+ * Install crates given as a JSON array of "name[@version-req]".
+ *
+ * The value returned is an array of {"name": <name>, "ok": <outcome>} or
+ * {"name": <name>, "error": <error>}, one per crate, where the "status" of
+ * the outcome is "installed", "compiled", "up-to-date" or "dry-run".
+ */
+char *binstall_install(const BinstallHandle *handle, const char *crate_names);
+
+/* Resolve "name[@version-req]" without installing it. */
+char *binstall_query(const BinstallHandle *handle, const char *crate_name);
+
+void binstall_string_free(char *s);
+
+void binstall_free(BinstallHandle *handle);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,425 @@
+//! Minimal C ABI for embedding binstalk.
+//!
+//! The lifecycle is:
+//!  - [`binstall_init`] creates a handle from a JSON config,
+//!  - [`binstall_install`] and [`binstall_query`] operate on the handle,
+//!  - [`binstall_free`] destroys it.
+//!
+//! All strings passed in must be nul-terminated UTF-8.
+//!
+//! Strings returned are nul-terminated JSON documents of the form
+//! `{"ok": <value>}` or `{"error": {"code": <code>, "message": <message>}}`
+//! and must be released with [`binstall_string_free`].
+//!
+//! Panics are caught at the boundary instead of unwinding into the caller:
+//! they are reported as errors with the code `binstall::ffi::panic`.
+//!
+//! See `binstall.h` for the C declarations.
+
+use std::{
+    any::Any,
+    ffi::{c_char, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    ptr,
+};
+
+use binstalk::{
+    errors::BinstallError,
+    ops::{
+        installer::{InstallOutcome, Installer, Strategy},
+        resolve::{self, CrateName},
+    },
+};
+use compact_str::CompactString;
+use miette::Diagnostic;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::runtime::Runtime;
+
+/// Opaque handle returned by [`binstall_init`].
+pub struct BinstallHandle {
+    runtime: Runtime,
+    installer: Installer,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum ConfigStrategy {
+    CrateMetaData,
+    QuickInstall,
+    SourceArchive,
+    Compile,
+}
+
+impl From<ConfigStrategy> for Strategy {
+    fn from(strategy: ConfigStrategy) -> Self {
+        match strategy {
+            ConfigStrategy::CrateMetaData => Strategy::CrateMetaData,
+            ConfigStrategy::QuickInstall => Strategy::QuickInstall,
+            ConfigStrategy::SourceArchive => Strategy::SourceArchive,
+            ConfigStrategy::Compile => Strategy::Compile,
+        }
+    }
+}
+
+/// Config accepted by [`binstall_init`].
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Config {
+    install_path: PathBuf,
+    #[serde(default)]
+    cargo_root: Option<PathBuf>,
+    #[serde(default)]
+    github_token: Option<CompactString>,
+    #[serde(default)]
+    strategies: Option<Vec<ConfigStrategy>>,
+    #[serde(default)]
+    targets: Option<Vec<String>>,
+    #[serde(default)]
+    registry: Option<String>,
+    #[serde(default)]
+    no_symlinks: bool,
+    #[serde(default)]
+    dry_run: bool,
+    #[serde(default)]
+    force: bool,
+    #[serde(default)]
+    locked: bool,
+    #[serde(default)]
+    no_track: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorReport {
+    code: Option<String>,
+    message: String,
+}
+
+impl ErrorReport {
+    fn new(code: &str, message: impl ToString) -> Self {
+        Self {
+            code: Some(code.to_owned()),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<BinstallError> for ErrorReport {
+    fn from(err: BinstallError) -> Self {
+        Self {
+            code: err.code().map(|code| code.to_string()),
+            message: format!("{:?}", miette::Report::new(err)),
+        }
+    }
+}
+
+fn into_c_string(value: Value) -> *mut c_char {
+    // serde_json escapes control characters, so the output never contains nul.
+    CString::new(value.to_string())
+        .expect("JSON output must not contain nul")
+        .into_raw()
+}
+
+fn to_json(res: Result<Value, ErrorReport>) -> *mut c_char {
+    into_c_string(match res {
+        Ok(value) => json!({ "ok": value }),
+        Err(err) => json!({ "error": err }),
+    })
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Call `f`, turning a panic into an error, since unwinding into the
+/// caller of an `extern "C"` function aborts the process.
+fn catch_panic<T>(f: impl FnOnce() -> Result<T, ErrorReport>) -> Result<T, ErrorReport> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        Err(ErrorReport::new(
+            "binstall::ffi::panic",
+            format_args!("binstall panicked: {}", panic_message(&*payload)),
+        ))
+    })
+}
+
+/// # Safety
+///
+/// `s` must be null or a valid nul-terminated string.
+unsafe fn read_str<'a>(s: *const c_char, what: &str) -> Result<&'a str, ErrorReport> {
+    if s.is_null() {
+        return Err(ErrorReport::new(
+            "binstall::ffi::null",
+            format_args!("{what} is null"),
+        ));
+    }
+
+    CStr::from_ptr(s).to_str().map_err(|err| {
+        ErrorReport::new(
+            "binstall::ffi::utf8",
+            format_args!("{what} is not valid utf-8: {err}"),
+        )
+    })
+}
+
+fn parse_crate_name(s: &str) -> Result<CrateName, ErrorReport> {
+    s.parse().map_err(|err| {
+        ErrorReport::new(
+            "binstall::ffi::crate_name",
+            format_args!("invalid crate name {s:?}: {err}"),
+        )
+    })
+}
+
+fn init(config: &str) -> Result<BinstallHandle, ErrorReport> {
+    let config: Config = serde_json::from_str(config)
+        .map_err(|err| ErrorReport::new("binstall::ffi::config", err))?;
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .map_err(|err| ErrorReport::from(BinstallError::from(err)))?;
+
+    let mut builder = Installer::builder(config.install_path)
+        .no_symlinks(config.no_symlinks)
+        .dry_run(config.dry_run)
+        .force(config.force)
+        .locked(config.locked)
+        .no_track(config.no_track);
+
+    if let Some(cargo_root) = config.cargo_root {
+        builder = builder.cargo_root(cargo_root);
+    }
+    if let Some(github_token) = config.github_token {
+        builder = builder.github_token(github_token);
+    }
+    if let Some(strategies) = config.strategies {
+        builder = builder.strategies(strategies.into_iter().map(Strategy::from));
+    }
+    if let Some(targets) = config.targets {
+        builder = builder.targets(targets);
+    }
+    if let Some(registry) = config.registry {
+        builder = builder.registry(
+            registry
+                .parse()
+                .map_err(|err| ErrorReport::new("binstall::ffi::registry", err))?,
+        );
+    }
+
+    let installer = {
+        let _guard = runtime.enter();
+        builder.build()?
+    };
+
+    Ok(BinstallHandle { runtime, installer })
+}
+
+fn install(handle: &BinstallHandle, crate_names: &str) -> Result<Value, ErrorReport> {
+    let crate_names: Vec<String> = serde_json::from_str(crate_names)
+        .map_err(|err| ErrorReport::new("binstall::ffi::crate_names", err))?;
+    let crate_names = crate_names
+        .iter()
+        .map(|s| parse_crate_name(s))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let outcomes = handle
+        .runtime
+        .block_on(handle.installer.install(crate_names))?;
+
+    Ok(outcomes
+        .into_iter()
+        .map(|(name, outcome)| match outcome {
+            Ok(outcome) => json!({ "name": name, "ok": outcome_to_json(outcome) }),
+            Err(err) => json!({ "name": name, "error": ErrorReport::from(err) }),
+        })
+        .collect())
+}
+
+fn outcome_to_json(outcome: InstallOutcome) -> Value {
+    match outcome {
+        InstallOutcome::Installed(crate_info) => json!({
+            "status": "installed",
+            "crate-info": crate_info,
+        }),
+        InstallOutcome::Compiled { version } => json!({
+            "status": "compiled",
+            "version": version,
+        }),
+        InstallOutcome::AlreadyUpToDate => json!({ "status": "up-to-date" }),
+        InstallOutcome::DryRun => json!({ "status": "dry-run" }),
+        _ => json!({ "status": "unknown" }),
+    }
+}
+
+fn query(handle: &BinstallHandle, crate_name: &str) -> Result<Value, ErrorReport> {
+    let crate_name = parse_crate_name(crate_name)?;
+    let opts = handle.installer.options().clone();
+
+    let resolution = handle
+        .runtime
+        .block_on(resolve::resolve(opts, crate_name, None))?;
+
+    Ok(resolution.to_json())
+}
+
+/// Create a handle from a JSON config.
+///
+/// Return null on failure, in which case `*error` is set to an error
+/// document if `error` is not null.
+///
+/// # Safety
+///
+/// `config` must be null or a valid nul-terminated string, `error` must
+/// be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn binstall_init(
+    config: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut BinstallHandle {
+    match catch_panic(|| read_str(config, "config").and_then(init)) {
+        Ok(handle) => Box::into_raw(Box::new(handle)),
+        Err(err) => {
+            if !error.is_null() {
+                *error = to_json(Err(err));
+            }
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Install crates given as a JSON array of `name[@version-req]`.
+///
+/// Return an array of `{"name": <name>, "ok": <outcome>}` or
+/// `{"name": <name>, "error": <error>}`, one per crate, since a crate
+/// failing to install does not stop the others. The crates installed are
+/// recorded in `cargo-root`, unless `no-track` is set.
+///
+/// # Safety
+///
+/// `handle` must be returned by [`binstall_init`] and not freed,
+/// `crate_names` must be null or a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn binstall_install(
+    handle: *const BinstallHandle,
+    crate_names: *const c_char,
+) -> *mut c_char {
+    let handle = &*handle;
+    to_json(catch_panic(|| {
+        read_str(crate_names, "crate_names").and_then(|s| install(handle, s))
+    }))
+}
+
+/// Resolve `name[@version-req]` without installing it.
+///
+/// # Safety
+///
+/// `handle` must be returned by [`binstall_init`] and not freed,
+/// `crate_name` must be null or a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn binstall_query(
+    handle: *const BinstallHandle,
+    crate_name: *const c_char,
+) -> *mut c_char {
+    let handle = &*handle;
+    to_json(catch_panic(|| {
+        read_str(crate_name, "crate_name").and_then(|s| query(handle, s))
+    }))
+}
+
+/// Free a string returned by this library.
+///
+/// # Safety
+///
+/// `s` must be null or returned by this library and not freed.
+#[no_mangle]
+pub unsafe extern "C" fn binstall_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Free a handle returned by [`binstall_init`].
+///
+/// A panic while shutting down the runtime is ignored, since there is
+/// nothing the caller could do about it.
+///
+/// # Safety
+///
+/// `handle` must be null or returned by [`binstall_init`] and not freed.
+#[no_mangle]
+pub unsafe extern "C" fn binstall_free(handle: *mut BinstallHandle) {
+    if !handle.is_null() {
+        let handle = Box::from_raw(handle);
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(handle)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn c_string(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    unsafe fn take_string(s: *mut c_char) -> Value {
+        let value = serde_json::from_str(CStr::from_ptr(s).to_str().unwrap()).unwrap();
+        binstall_string_free(s);
+        value
+    }
+
+    #[test]
+    fn test_init_error() {
+        let mut error = ptr::null_mut();
+        let handle = unsafe { binstall_init(c_string("{}").as_ptr(), &mut error) };
+        assert!(handle.is_null());
+
+        let error = unsafe { take_string(error) };
+        assert_eq!(error["error"]["code"], "binstall::ffi::config");
+    }
+
+    #[test]
+    fn test_install_invalid_crate_name() {
+        let install_path = tempfile::tempdir().unwrap();
+        let config = CString::new(
+            json!({
+                "install-path": install_path.path(),
+                "targets": ["x86_64-unknown-linux-gnu"],
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        let mut error = ptr::null_mut();
+        let handle = unsafe { binstall_init(config.as_ptr(), &mut error) };
+        assert!(!handle.is_null());
+        assert!(error.is_null());
+
+        let res = unsafe { take_string(binstall_install(handle, c_string(r#"["a@!"]"#).as_ptr())) };
+        assert_eq!(res["error"]["code"], "binstall::ffi::crate_name");
+
+        let res = unsafe { take_string(binstall_install(handle, c_string("[]").as_ptr())) };
+        assert_eq!(res["ok"], json!([]));
+
+        unsafe { binstall_free(handle) };
+    }
+
+    #[test]
+    fn test_catch_panic() {
+        let err = catch_panic::<()>(|| panic!("oops")).unwrap_err();
+        assert_eq!(err.code.as_deref(), Some("binstall::ffi::panic"));
+        assert_eq!(err.message, "binstall panicked: oops");
+
+        let err = catch_panic::<()>(|| panic!("{} oops", 2)).unwrap_err();
+        assert_eq!(err.message, "binstall panicked: 2 oops");
+
+        assert_eq!(catch_panic(|| Ok(1)).unwrap(), 1);
+    }
+}
@@ -0,0 +1,195 @@
+//! Binstall's `history.jsonl` audit log.
+//!
+//! Every install and upgrade done by Binstall is appended to this log as one
+//! JSON object per line, so that it can answer "when did this tool change"
+//! on machines shared by multiple users.
+//!
+//! The log is never rewritten, entries are only appended to it.
+
+use std::{
+    env, fs,
+    io::{self, BufRead, Seek, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use compact_str::CompactString;
+use fs_lock::FileLock;
+use miette::Diagnostic;
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    crate_info::{BinDigest, CrateInfo},
+    helpers::create_if_not_exist,
+};
+
+#[derive(Debug, Diagnostic, Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("I/O Error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Failed to parse json on line {line}: {err}")]
+    SerdeJsonParse {
+        line: usize,
+        #[source]
+        err: serde_json::Error,
+    },
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    Install,
+    Upgrade,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Unix timestamp of the operation.
+    pub time: u64,
+    /// User who did the operation, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<CompactString>,
+    pub action: Action,
+    pub name: CompactString,
+    pub version: Version,
+    /// Version installed before the operation, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_version: Option<Version>,
+    pub target: CompactString,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bin_digests: Vec<BinDigest>,
+}
+
+impl Entry {
+    /// Create an entry for installing `crate_info` now, replacing
+    /// `previous_version` if any.
+    pub fn new(crate_info: &CrateInfo, previous_version: Option<&Version>) -> Self {
+        let action = match previous_version {
+            Some(previous_version) if *previous_version != crate_info.current_version => {
+                Action::Upgrade
+            }
+            _ => Action::Install,
+        };
+
+        Self {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default(),
+            user: current_user(),
+            action,
+            name: crate_info.name.clone(),
+            version: crate_info.current_version.clone(),
+            previous_version: previous_version.cloned(),
+            target: crate_info.target.clone(),
+            bin_digests: crate_info.bin_digests.clone(),
+        }
+    }
+}
+
+fn current_user() -> Option<CompactString> {
+    ["USER", "USERNAME"]
+        .into_iter()
+        .find_map(|key| env::var(key).ok())
+        .filter(|user| !user.is_empty())
+        .map(CompactString::from)
+}
+
+pub fn path(cargo_roots: &Path) -> PathBuf {
+    cargo_roots.join("binstall/history.jsonl")
+}
+
+pub fn append_to_path(
+    path: impl AsRef<Path>,
+    entries: impl IntoIterator<Item = Entry>,
+) -> Result<(), Error> {
+    let mut file = FileLock::new_exclusive(create_if_not_exist(path.as_ref())?)?;
+    // Move the cursor to EOF
+    file.seek(io::SeekFrom::End(0))?;
+
+    let mut buffer = Vec::new();
+    for entry in entries {
+        serde_json::to_writer(&mut buffer, &entry).map_err(io::Error::from)?;
+        buffer.push(b'\n');
+    }
+
+    // Write all entries at once, so that a partially written line can only
+    // happen if the write itself fails.
+    file.write_all(&buffer)?;
+    file.flush()?;
+
+    Ok(())
+}
+
+/// Load all entries of the log, oldest first.
+///
+/// Return an empty vec if the log does not exist.
+pub fn load_from_path(path: impl AsRef<Path>) -> Result<Vec<Entry>, Error> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    io::BufReader::new(FileLock::new_shared(file)?)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|(i, line)| {
+            serde_json::from_str(&line?).map_err(|err| Error::SerdeJsonParse { line: i + 1, err })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crate_info::CrateSource;
+
+    #[test]
+    fn test_append_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = path(dir.path());
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        assert_eq!(load_from_path(&path).unwrap(), []);
+
+        let crate_info = |version| CrateInfo {
+            name: "a".into(),
+            version_req: "*".into(),
+            current_version: version,
+            source: CrateSource::cratesio_registry(),
+            target: "x86_64-unknown-linux-gnu".into(),
+            bins: vec!["a".into()],
+            bin_digests: Vec::new(),
+            artifact_url: None,
+            environment: None,
+        };
+
+        let install = Entry::new(&crate_info(Version::new(0, 1, 0)), None);
+        append_to_path(&path, [install.clone()]).unwrap();
+
+        let upgrade = Entry::new(
+            &crate_info(Version::new(0, 2, 0)),
+            Some(&Version::new(0, 1, 0)),
+        );
+        let reinstall = Entry::new(
+            &crate_info(Version::new(0, 2, 0)),
+            Some(&Version::new(0, 2, 0)),
+        );
+        append_to_path(&path, [upgrade.clone(), reinstall.clone()]).unwrap();
+
+        assert_eq!(install.action, Action::Install);
+        assert_eq!(upgrade.action, Action::Upgrade);
+        assert_eq!(reinstall.action, Action::Install);
+
+        assert_eq!(
+            load_from_path(&path).unwrap(),
+            [install, upgrade, reinstall]
+        );
+    }
+}
@@ -270,6 +270,9 @@ mod test {
                 source: CrateSource::cratesio_registry(),
                 target: target.clone(),
                 bins: vec!["1".into(), "2".into()],
+                bin_digests: Vec::new(),
+                artifact_url: None,
+                environment: None,
             },
             CrateInfo {
                 name: "b".into(),
@@ -278,6 +281,9 @@ mod test {
                 source: CrateSource::cratesio_registry(),
                 target: target.clone(),
                 bins: vec!["1".into(), "2".into()],
+                bin_digests: Vec::new(),
+                artifact_url: None,
+                environment: None,
             },
             CrateInfo {
                 name: "a".into(),
@@ -286,6 +292,9 @@ mod test {
                 source: CrateSource::cratesio_registry(),
                 target: target.clone(),
                 bins: vec!["1".into()],
+                bin_digests: Vec::new(),
+                artifact_url: None,
+                environment: None,
             },
         ];
 
@@ -316,6 +325,9 @@ mod test {
             source: CrateSource::cratesio_registry(),
             target,
             bins: vec!["1".into(), "2".into()],
+            bin_digests: Vec::new(),
+            artifact_url: None,
+            environment: None,
         };
         append_to_path(path, [new_metadata.clone()]).unwrap();
         metadata_set.insert(new_metadata);
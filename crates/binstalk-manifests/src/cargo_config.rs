@@ -60,6 +60,36 @@ pub struct DefaultRegistry {
     pub default: Option<CompactString>,
 }
 
+/// Configurations specific to binstall, under `[binstall]`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Binstall {
+    /// Appended to the User-Agent of all requests, e.g. to attribute the
+    /// traffic to a team in the logs of proxies.
+    pub user_agent_suffix: Option<CompactString>,
+    /// Headers added to all requests.
+    pub headers: Option<BTreeMap<CompactString, CompactString>>,
+    /// Headers added to the requests to the hosts matching the key, either
+    /// a host or `*.` followed by a domain to match its subdomains, e.g.
+    /// the token of a private artifact server.
+    pub host_headers: Option<BTreeMap<CompactString, BTreeMap<CompactString, CompactString>>>,
+    /// Rewrite urls starting with the key by replacing it with the value,
+    /// e.g. to download artifacts from a corporate mirror.
+    pub mirrors: Option<BTreeMap<CompactString, CompactString>>,
+    /// Replace the interpreter of the dynamically linked ELF binaries
+    /// installed.
+    pub patch_interpreter: Option<PathBuf>,
+    /// Replace the rpath of the dynamically linked ELF binaries installed.
+    pub patch_rpath: Option<CompactString>,
+    /// Environment variables set when falling back to building the crate
+    /// of the key from source, e.g. `OPENSSL_DIR` for crates linking to
+    /// native libraries.
+    pub build_env: Option<BTreeMap<CompactString, BTreeMap<CompactString, CompactString>>>,
+    /// The only hosts connected to, either a host or `*.` followed by a
+    /// domain to match its subdomains, see `--allow-hosts`.
+    pub allow_hosts: Option<Vec<CompactString>>,
+}
+
 #[derive(Debug, Default, Deserialize)]
 pub struct Config {
     pub install: Option<Install>,
@@ -67,6 +97,7 @@ pub struct Config {
     pub env: Option<BTreeMap<CompactString, Env>>,
     pub registries: Option<BTreeMap<CompactString, Registry>>,
     pub registry: Option<DefaultRegistry>,
+    pub binstall: Option<Binstall>,
 }
 
 fn join_if_relative(path: Option<&mut PathBuf>, dir: &Path) {
@@ -198,6 +229,16 @@ cainfo = "cert.pem"         # path to Certificate Authority (CA) bundle
 
 [install]
 root = "/some/path"         # `cargo install` destination directory
+
+[binstall]
+user-agent-suffix = "team/platform"
+headers = { X-Team = "platform" }
+host-headers = { "artifacts.corp" = { Authorization = "Bearer token" } }
+mirrors = { "https://github.com/" = "https://gh-mirror.corp/" }
+patch-interpreter = "/opt/glibc/lib/ld-linux-x86-64.so.2"
+
+[binstall.build-env.cargo-update]
+OPENSSL_DIR = "/opt/openssl"
     "#;
 
     #[test]
@@ -214,6 +255,27 @@ root = "/some/path"         # `cargo install` destination directory
         assert_eq!(http.timeout.unwrap(), 30);
         assert_eq!(http.cainfo.unwrap(), Path::new("root").join("cert.pem"));
 
+        let binstall = config.binstall.unwrap();
+        assert_eq!(binstall.user_agent_suffix.unwrap(), "team/platform");
+        assert_eq!(binstall.headers.unwrap()["X-Team"], "platform");
+        assert_eq!(
+            binstall.host_headers.unwrap()["artifacts.corp"]["Authorization"],
+            "Bearer token"
+        );
+        assert_eq!(
+            binstall.mirrors.unwrap()["https://github.com/"],
+            "https://gh-mirror.corp/"
+        );
+        assert_eq!(
+            binstall.patch_interpreter.unwrap(),
+            Path::new("/opt/glibc/lib/ld-linux-x86-64.so.2")
+        );
+        assert_eq!(binstall.patch_rpath, None);
+        assert_eq!(
+            binstall.build_env.unwrap()["cargo-update"]["OPENSSL_DIR"],
+            "/opt/openssl"
+        );
+
         let env = config.env.unwrap();
         assert_eq!(env.len(), 3);
         assert_eq!(
@@ -235,6 +235,9 @@ mod tests {
                 source: CrateSource::cratesio_registry(),
                 target: TARGET.into(),
                 bins: vec!["cargo-binstall".into()],
+                bin_digests: Vec::new(),
+                artifact_url: None,
+                environment: None,
             }],
         )
         .unwrap();
@@ -261,6 +264,9 @@ mod tests {
                 source: CrateSource::cratesio_registry(),
                 target: TARGET.into(),
                 bins: vec!["cargo-binstall".into()],
+                bin_digests: Vec::new(),
+                artifact_url: None,
+                environment: None,
             }],
         )
         .unwrap();
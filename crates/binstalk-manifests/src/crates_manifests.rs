@@ -2,7 +2,7 @@ use std::{
     collections::BTreeMap,
     fs,
     io::{self, Seek},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
 use fs_lock::FileLock;
@@ -10,6 +10,7 @@ use miette::Diagnostic;
 use thiserror::Error as ThisError;
 
 use crate::{
+    audit_log::{self, Entry as AuditLogEntry, Error as AuditLogError},
     binstall_crates_v1::{Error as BinstallCratesV1Error, Records as BinstallCratesV1Records},
     cargo_crates_v1::{CratesToml, CratesTomlParseError},
     crate_info::CrateInfo,
@@ -27,6 +28,10 @@ pub enum ManifestsError {
     #[diagnostic(transparent)]
     CargoManifestV1(#[from] CratesTomlParseError),
 
+    #[error("failed to update the audit log: {0}")]
+    #[diagnostic(transparent)]
+    AuditLog(#[from] AuditLogError),
+
     #[error("I/O error: {0}")]
     Io(#[from] io::Error),
 }
@@ -34,6 +39,7 @@ pub enum ManifestsError {
 pub struct Manifests {
     binstall: BinstallCratesV1Records,
     cargo_crates_v1: FileLock,
+    audit_log_path: PathBuf,
 }
 
 impl Manifests {
@@ -57,6 +63,7 @@ impl Manifests {
         Ok(Self {
             binstall,
             cargo_crates_v1,
+            audit_log_path: audit_log::path(cargo_roots),
         })
     }
 
@@ -78,7 +85,20 @@ impl Manifests {
             .map_err(ManifestsError::from)
     }
 
+    /// Return the record of the crate `name`, if it is installed by
+    /// binstall.
+    pub fn installed_crate_info(&self, name: &str) -> Option<&CrateInfo> {
+        self.binstall.get(name)
+    }
+
+    /// Record `metadata_vec` as installed and append them to the audit log.
     pub fn update(mut self, metadata_vec: Vec<CrateInfo>) -> Result<(), ManifestsError> {
+        let installed_crates = self.load_installed_crates()?;
+        let audit_log_entries: Vec<_> = metadata_vec
+            .iter()
+            .map(|metadata| AuditLogEntry::new(metadata, installed_crates.get(&metadata.name)))
+            .collect();
+
         self.rewind_cargo_crates_v1()?;
 
         CratesToml::append_to_file(&mut self.cargo_crates_v1, &metadata_vec)?;
@@ -88,6 +108,66 @@ impl Manifests {
         }
         self.binstall.overwrite()?;
 
+        audit_log::append_to_path(&self.audit_log_path, audit_log_entries)?;
+
         Ok(())
     }
 }
+
+/// Load crates installed in `cargo_roots` without modifying it, so that
+/// it works on read-only roots shared by multiple users.
+///
+/// Return an empty map if `cargo_roots` does not have any crate installed.
+pub fn load_installed_crates_read_only(
+    cargo_roots: &Path,
+) -> Result<BTreeMap<CompactString, Version>, ManifestsError> {
+    let file = match fs::File::open(cargo_roots.join(".crates.toml")) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    CratesToml::load_from_reader(FileLock::new_shared(file)?)
+        .and_then(CratesToml::collect_into_crates_versions)
+        .map_err(ManifestsError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crate_info::CrateSource;
+
+    use detect_targets::TARGET;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_load_installed_crates_read_only() {
+        let tempdir = TempDir::new().unwrap();
+
+        assert!(load_installed_crates_read_only(tempdir.path())
+            .unwrap()
+            .is_empty());
+
+        CratesToml::append_to_path(
+            tempdir.path().join(".crates.toml"),
+            &[CrateInfo {
+                name: "cargo-binstall".into(),
+                version_req: "*".into(),
+                current_version: Version::new(0, 11, 1),
+                source: CrateSource::cratesio_registry(),
+                target: TARGET.into(),
+                bins: vec!["cargo-binstall".into()],
+                bin_digests: Vec::new(),
+                artifact_url: None,
+                environment: None,
+            }],
+        )
+        .unwrap();
+
+        let crates = load_installed_crates_read_only(tempdir.path()).unwrap();
+        assert_eq!(
+            crates.get("cargo-binstall").unwrap(),
+            &Version::new(0, 11, 1)
+        );
+    }
+}
@@ -1,9 +1,12 @@
-use std::borrow::Cow;
+use std::{
+    borrow::Cow,
+    mem,
+    sync::{Arc, Mutex},
+};
 
 use base16::{decode as decode_base16, encode_lower as encode_base16};
 use binstalk_downloader::{
-    bytes::Bytes,
-    download::{DataVerifier, Download},
+    download::{Download, Sha256Verifier},
     remote::{Client, Url},
 };
 use binstalk_types::cargo_toml_binstall::{Meta, TarBasedFmt};
@@ -13,7 +16,6 @@ use leon::{Template, Values};
 use semver::{Version, VersionReq};
 use serde::Deserialize;
 use serde_json::Error as JsonError;
-use sha2::{Digest, Sha256};
 use tracing::{debug, instrument};
 
 use crate::{visitor::ManifestVisitor, RegistryError};
@@ -23,20 +25,6 @@ pub(super) struct RegistryConfig {
     pub(super) dl: CompactString,
 }
 
-struct Sha256Digest(Sha256);
-
-impl Default for Sha256Digest {
-    fn default() -> Self {
-        Sha256Digest(Sha256::new())
-    }
-}
-
-impl DataVerifier for Sha256Digest {
-    fn update(&mut self, data: &Bytes) {
-        self.0.update(data);
-    }
-}
-
 #[instrument]
 pub(super) async fn parse_manifest(
     client: Client,
@@ -49,13 +37,17 @@ pub(super) async fn parse_manifest(
     let mut manifest_visitor = ManifestVisitor::new(format!("{crate_name}-{version}").into());
 
     let checksum = decode_base16(cksum.as_bytes()).map_err(RegistryError::from)?;
-    let mut sha256_digest = Sha256Digest::default();
+    let sha256_digest = Arc::new(Mutex::new(Sha256Verifier::default()));
 
-    Download::new_with_data_verifier(client, crate_url, &mut sha256_digest)
+    // The whole .crate is downloaded and visited even though only its
+    // manifest is needed: the checksum of the index covers all of it, and
+    // a later `Cargo.toml` entry replaces an earlier one.
+    Download::new(client, crate_url)
+        .with_offloaded_data_verifier(sha256_digest.clone())
         .and_visit_tar(TarBasedFmt::Tgz, &mut manifest_visitor)
         .await?;
 
-    let digest_checksum = sha256_digest.0.finalize();
+    let digest_checksum = mem::take(&mut *sha256_digest.lock().unwrap()).finalize();
 
     if digest_checksum.as_slice() != checksum.as_slice() {
         Err(RegistryError::UnmatchedChecksum {
@@ -63,7 +55,7 @@ pub(super) async fn parse_manifest(
             actual: encode_base16(digest_checksum.as_slice()).into(),
         })
     } else {
-        manifest_visitor.load_manifest()
+        manifest_visitor.load_manifest().await
     }
 }
 
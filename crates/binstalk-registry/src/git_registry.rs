@@ -1,13 +1,20 @@
-use std::{io, path::PathBuf, sync::Arc};
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use binstalk_downloader::remote::Client;
 use binstalk_types::cargo_toml_binstall::Meta;
 use cargo_toml_workspace::cargo_toml::Manifest;
 use compact_str::{CompactString, ToCompactString};
+use fs_lock::FileLock;
 use once_cell::sync::OnceCell;
 use semver::VersionReq;
 use serde_json::{from_slice as json_from_slice, Deserializer as JsonDeserializer};
-use simple_git::{GitCancellationToken, GitUrl, Repository};
+use sha2::{Digest, Sha256};
+use simple_git::{GitCancellationToken, GitOptions, GitUrl, Repository};
 use tempfile::TempDir;
 use tokio::task::spawn_blocking;
 use tracing::instrument;
@@ -20,20 +27,46 @@ use crate::{
 
 #[derive(Debug)]
 struct GitIndex {
-    _tempdir: TempDir,
+    _tempdir: Option<TempDir>,
+    /// Lock of the clone in the cache directory, held while it is used.
+    _lock: Option<FileLock>,
     repo: Repository,
     dl_template: CompactString,
 }
 
 impl GitIndex {
-    fn new(url: GitUrl, cancellation_token: GitCancellationToken) -> Result<Self, RegistryError> {
-        let tempdir = TempDir::new()?;
-
-        let repo = Repository::shallow_clone_bare(
-            url.clone(),
-            tempdir.as_ref(),
-            Some(cancellation_token),
-        )?;
+    fn new(
+        url: GitUrl,
+        options: &GitOptions,
+        cache_dir: Option<&Path>,
+        cancellation_token: GitCancellationToken,
+    ) -> Result<Self, RegistryError> {
+        let (tempdir, lock, repo) = if let Some(cache_dir) = cache_dir {
+            // Clones are stored by the digest of their url.
+            let name = base16::encode_lower(&Sha256::digest(url.to_string()))[..16].to_owned();
+            let path = cache_dir.join(&name);
+            fs::create_dir_all(&path)?;
+
+            let lock = FileLock::new_exclusive(File::create(cache_dir.join(name + ".lock"))?)?;
+            let repo = Repository::update_or_clone_bare(
+                url.clone(),
+                &path,
+                options,
+                Some(cancellation_token),
+            )?;
+
+            (None, Some(lock), repo)
+        } else {
+            let tempdir = TempDir::new()?;
+            let repo = Repository::shallow_clone_bare(
+                url.clone(),
+                tempdir.as_ref(),
+                options,
+                Some(cancellation_token),
+            )?;
+
+            (Some(tempdir), None, repo)
+        };
 
         let config: RegistryConfig = {
             let config = repo
@@ -50,6 +83,7 @@ impl GitIndex {
 
         Ok(Self {
             _tempdir: tempdir,
+            _lock: lock,
             repo,
             dl_template: config.dl,
         })
@@ -59,6 +93,8 @@ impl GitIndex {
 #[derive(Debug)]
 struct GitRegistryInner {
     url: GitUrl,
+    options: GitOptions,
+    cache_dir: Option<PathBuf>,
     git_index: OnceCell<GitIndex>,
 }
 
@@ -67,12 +103,24 @@ pub struct GitRegistry(Arc<GitRegistryInner>);
 
 impl GitRegistry {
     pub fn new(url: GitUrl) -> Self {
+        Self::with_options(url, GitOptions::default(), None)
+    }
+
+    /// Clone the index with `options`, and keep the clone in `cache_dir`
+    /// to only fetch its new commits in later runs.
+    pub fn with_options(url: GitUrl, options: GitOptions, cache_dir: Option<PathBuf>) -> Self {
         Self(Arc::new(GitRegistryInner {
             url,
+            options,
+            cache_dir,
             git_index: Default::default(),
         }))
     }
 
+    pub fn url(&self) -> &GitUrl {
+        &self.0.url
+    }
+
     /// WARNING: This is a blocking operation.
     fn find_crate_matched_ver(
         repo: &Repository,
@@ -115,13 +163,15 @@ impl GitRegistry {
 
         let (matched_version, dl_url) = spawn_blocking(move || {
             let GitIndex {
-                _tempdir: _,
-                repo,
-                dl_template,
-            } = this
-                .0
-                .git_index
-                .get_or_try_init(|| GitIndex::new(this.0.url.clone(), cancellation_token))?;
+                repo, dl_template, ..
+            } = this.0.git_index.get_or_try_init(|| {
+                GitIndex::new(
+                    this.0.url.clone(),
+                    &this.0.options,
+                    this.0.cache_dir.as_deref(),
+                    cancellation_token,
+                )
+            })?;
 
             let matched_version =
                 Self::find_crate_matched_ver(repo, &crate_name, &crate_prefix, &version_req)?;
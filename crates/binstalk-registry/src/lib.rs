@@ -1,6 +1,6 @@
 #![cfg_attr(docsrs, feature(doc_auto_cfg))]
 
-use std::{io, str::FromStr, sync::Arc};
+use std::{fmt, io, str::FromStr, sync::Arc};
 
 use base16::DecodeError as Base16DecodeError;
 use binstalk_downloader::{
@@ -19,7 +19,7 @@ use tokio::task;
 use url::{ParseError as UrlParseError, Url};
 
 #[cfg(feature = "git")]
-pub use simple_git::{GitError, GitUrl, GitUrlParseError};
+pub use simple_git::{GitError, GitOptions, GitUrl, GitUrlParseError};
 
 mod vfs;
 
@@ -41,6 +41,9 @@ pub use crates_io_registry::fetch_crate_cratesio_api;
 mod sparse_registry;
 pub use sparse_registry::SparseRegistry;
 
+mod publish_info;
+pub use publish_info::{fetch_publish_info, fetch_published_by, PublishInfo};
+
 #[derive(Debug, ThisError, Diagnostic)]
 #[diagnostic(severity(error), code(binstall::cargo_registry))]
 #[non_exhaustive]
@@ -150,6 +153,38 @@ impl Registry {
         )))
     }
 
+    /// Return `true` if this is the sparse or git index of crates.io.
+    pub fn is_crates_io(&self) -> bool {
+        match self {
+            Self::Sparse(sparse_registry) => {
+                sparse_registry.url().as_str() == "https://index.crates.io/"
+            }
+            #[cfg(feature = "git")]
+            Self::Git(git_registry) => {
+                git_registry.url().to_string().trim_end_matches('/')
+                    == "https://github.com/rust-lang/crates.io-index"
+            }
+        }
+    }
+
+    /// Clone git registries with `options`, and keep their clones in
+    /// `cache_dir` to only fetch their new commits in later runs.
+    #[cfg(feature = "git")]
+    pub fn with_git_options(
+        self,
+        options: GitOptions,
+        cache_dir: Option<std::path::PathBuf>,
+    ) -> Self {
+        match self {
+            Self::Git(git_registry) => Self::Git(GitRegistry::with_options(
+                git_registry.url().clone(),
+                options,
+                cache_dir,
+            )),
+            registry => registry,
+        }
+    }
+
     fn from_str_inner(s: &str) -> Result<Self, InvalidRegistryErrorInner> {
         if let Some(s) = s.strip_prefix("sparse+") {
             let url = Url::parse(s)?;
@@ -197,6 +232,18 @@ impl Registry {
     }
 }
 
+/// Format the registry as accepted by `--index` of `cargo-install`
+/// and [`Registry::from_str`].
+impl fmt::Display for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sparse(sparse_registry) => write!(f, "sparse+{}", sparse_registry.url()),
+            #[cfg(feature = "git")]
+            Self::Git(git_registry) => fmt::Display::fmt(git_registry.url(), f),
+        }
+    }
+}
+
 impl FromStr for Registry {
     type Err = InvalidRegistryError;
 
@@ -229,6 +276,25 @@ mod test {
         .unwrap()
     }
 
+    #[test]
+    fn test_registry_display() {
+        let registry = Registry::default();
+        assert!(registry.is_crates_io());
+        assert_eq!(registry.to_string(), "sparse+https://index.crates.io/");
+
+        let registry: Registry = "sparse+https://example.com/index/".parse().unwrap();
+        assert!(!registry.is_crates_io());
+        assert_eq!(registry.to_string(), "sparse+https://example.com/index/");
+
+        #[cfg(feature = "git")]
+        {
+            let registry: Registry = "https://github.com/rust-lang/crates.io-index"
+                .parse()
+                .unwrap();
+            assert!(registry.is_crates_io());
+        }
+    }
+
     #[tokio::test]
     async fn test_crates_io_sparse_registry() {
         let client = create_client().await;
@@ -0,0 +1,145 @@
+//! Publish metadata of crates from the crates.io api, shown to users to
+//! help them decide whether to trust a crate.
+
+use binstalk_downloader::remote::{Client, Error as RemoteError, Url};
+use compact_str::CompactString;
+use serde::Deserialize;
+
+use crate::RegistryError;
+
+/// Publish metadata of a version of a crate on crates.io.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PublishInfo {
+    /// Logins of the owners of the crate, users and teams.
+    pub owners: Vec<CompactString>,
+    /// Login of the user who published the version, unknown for versions
+    /// published before crates.io recorded it.
+    pub published_by: Option<CompactString>,
+    /// When the version is published, in RFC 3339.
+    pub published_at: CompactString,
+    /// Number of downloads of the version.
+    pub downloads: u64,
+}
+
+#[derive(Deserialize)]
+struct VersionResponse {
+    version: VersionInfo,
+}
+
+#[derive(Deserialize)]
+struct VersionInfo {
+    created_at: CompactString,
+    downloads: u64,
+    published_by: Option<User>,
+}
+
+#[derive(Deserialize)]
+struct User {
+    login: CompactString,
+}
+
+#[derive(Deserialize)]
+struct OwnersResponse {
+    users: Vec<User>,
+}
+
+fn api_url(name: &str, segment: &str) -> Result<Url, RegistryError> {
+    let mut url = Url::parse("https://crates.io/api/v1/crates")?;
+    url.path_segments_mut().unwrap().push(name).push(segment);
+    Ok(url)
+}
+
+async fn fetch_version(
+    client: &Client,
+    name: &str,
+    version: &str,
+) -> Result<VersionInfo, RegistryError> {
+    let response: VersionResponse = client
+        .get(api_url(name, version)?)
+        .send(true)
+        .await
+        .map_err(|err| match err {
+            RemoteError::Http(err) if err.is_status() => RegistryError::NotFound(name.into()),
+            err => err.into(),
+        })?
+        .json()
+        .await?;
+
+    Ok(response.version)
+}
+
+/// Fetch the publish metadata of `version` of crate `name` from crates.io.
+pub async fn fetch_publish_info(
+    client: &Client,
+    name: &str,
+    version: &str,
+) -> Result<PublishInfo, RegistryError> {
+    let version = fetch_version(client, name, version).await?;
+
+    let owners: OwnersResponse = client
+        .get(api_url(name, "owners")?)
+        .send(true)
+        .await?
+        .json()
+        .await?;
+
+    Ok(PublishInfo {
+        owners: owners.users.into_iter().map(|user| user.login).collect(),
+        published_by: version.published_by.map(|user| user.login),
+        published_at: version.created_at,
+        downloads: version.downloads,
+    })
+}
+
+/// Fetch the login of the user who published `version` of crate `name` on
+/// crates.io, if known.
+pub async fn fetch_published_by(
+    client: &Client,
+    name: &str,
+    version: &str,
+) -> Result<Option<CompactString>, RegistryError> {
+    Ok(fetch_version(client, name, version)
+        .await?
+        .published_by
+        .map(|user| user.login))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_responses() {
+        let version: VersionResponse = serde_json::from_str(
+            r#"{"version": {
+                "num": "1.0.0",
+                "created_at": "2023-06-01T12:00:00.000000+00:00",
+                "downloads": 42,
+                "published_by": {"id": 1, "login": "alice", "name": "Alice"}
+            }}"#,
+        )
+        .unwrap();
+        assert_eq!(version.version.downloads, 42);
+        assert_eq!(version.version.published_by.unwrap().login, "alice");
+
+        let version: VersionResponse = serde_json::from_str(
+            r#"{"version": {"created_at": "2015-01-01T00:00:00+00:00", "downloads": 1, "published_by": null}}"#,
+        )
+        .unwrap();
+        assert!(version.version.published_by.is_none());
+
+        let owners: OwnersResponse = serde_json::from_str(
+            r#"{"users": [{"login": "alice", "kind": "user"}, {"login": "github:org:team", "kind": "team"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(owners.users.len(), 2);
+    }
+
+    #[test]
+    fn test_api_url() {
+        assert_eq!(
+            api_url("cargo-binstall", "owners").unwrap().as_str(),
+            "https://crates.io/api/v1/crates/cargo-binstall/owners"
+        );
+    }
+}
@@ -28,6 +28,10 @@ impl SparseRegistry {
         }
     }
 
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
     async fn get_dl_template(&self, client: &Client) -> Result<&str, RegistryError> {
         self.dl_template
             .get_or_try_init(|| {
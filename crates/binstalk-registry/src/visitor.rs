@@ -1,17 +1,20 @@
 use std::path::{Path, PathBuf};
 
-use binstalk_downloader::download::{DownloadError, TarEntriesVisitor, TarEntry};
+use binstalk_downloader::download::{
+    spool, DownloadError, SpooledBuffer, TarEntriesVisitor, TarEntry,
+};
 use binstalk_types::cargo_toml_binstall::Meta;
 use cargo_toml_workspace::cargo_toml::{Manifest, Value};
 use normalize_path::NormalizePath;
-use tokio::io::AsyncReadExt;
 use tracing::debug;
 
 use crate::{vfs::Vfs, RegistryError};
 
 #[derive(Debug)]
 pub(super) struct ManifestVisitor {
-    cargo_toml_content: Vec<u8>,
+    /// Spilled to a temporary file past the memory limit of the download,
+    /// so that a huge `Cargo.toml` cannot exhaust the memory.
+    cargo_toml_content: Option<SpooledBuffer>,
     /// manifest_dir_path is treated as the current dir.
     manifest_dir_path: PathBuf,
 
@@ -21,8 +24,7 @@ pub(super) struct ManifestVisitor {
 impl ManifestVisitor {
     pub(super) fn new(manifest_dir_path: PathBuf) -> Self {
         Self {
-            // Cargo.toml is quite large usually.
-            cargo_toml_content: Vec::with_capacity(2000),
+            cargo_toml_content: None,
             manifest_dir_path,
             vfs: Vfs::default(),
         }
@@ -52,12 +54,17 @@ impl TarEntriesVisitor for ManifestVisitor {
 
         if path == Path::new("Cargo.toml") {
             // Since it is possible for the same Cargo.toml to appear
-            // multiple times using `tar --keep-old-files`, here we
-            // clear the buffer first before reading into it.
-            self.cargo_toml_content.clear();
-            self.cargo_toml_content
-                .reserve_exact(entry.size()?.try_into().unwrap_or(usize::MAX));
-            entry.read_to_end(&mut self.cargo_toml_content).await?;
+            // multiple times using `tar --keep-old-files`, the last one
+            // replaces the previous ones.
+            let content = spool(entry).await?;
+            if content.is_spilled() {
+                debug!(
+                    "Cargo.toml exceeds the memory limit of {} bytes, spilled {} bytes to a temporary file",
+                    entry.memory_limit(),
+                    content.len()
+                );
+            }
+            self.cargo_toml_content = Some(content);
         }
 
         Ok(())
@@ -66,11 +73,19 @@ impl TarEntriesVisitor for ManifestVisitor {
 
 impl ManifestVisitor {
     /// Load binstall metadata using the extracted information stored in memory.
-    pub(super) fn load_manifest(self) -> Result<Manifest<Meta>, RegistryError> {
+    ///
+    /// The manifest is only read back into memory here, once the checksum
+    /// of the crate is verified.
+    pub(super) async fn load_manifest(self) -> Result<Manifest<Meta>, RegistryError> {
         debug!("Loading manifest directly from extracted file");
 
+        let cargo_toml_content = match self.cargo_toml_content {
+            Some(content) => content.into_vec().await?,
+            None => Vec::new(),
+        };
+
         // Load and parse manifest
-        let mut manifest = Manifest::from_slice_with_metadata(&self.cargo_toml_content)?;
+        let mut manifest = Manifest::from_slice_with_metadata(&cargo_toml_content)?;
 
         // Checks vfs for binary output names
         manifest.complete_from_abstract_filesystem::<Value, _>(&self.vfs, None)?;
@@ -2,7 +2,7 @@
 //!
 //! This manifest defines how a particular binary crate may be installed by Binstall.
 
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, num::NonZeroU32};
 
 use serde::{Deserialize, Serialize};
 
@@ -34,9 +34,55 @@ pub struct PkgMeta {
     /// Path template for binary files in packages
     pub bin_dir: Option<String>,
 
+    /// Destination path templates of specific binaries, by binary name,
+    /// relative to the parent of the install dir, e.g.
+    /// `libexec/{ name }/{ bin }{ binary-ext }` for a helper daemon.
+    ///
+    /// The other binaries are installed in the install dir.
+    pub bin_dest: BTreeMap<String, String>,
+
+    /// Launchers of the binaries which are not native executables, e.g.
+    /// Python zipapps or JARs, by binary name.
+    ///
+    /// They are installed with their own extension, along with a shim
+    /// named after the binary running them with the launcher.
+    pub bin_launchers: BTreeMap<String, BinLauncher>,
+
+    /// Build flavor to install, e.g. `minimal` or `full`, for crates
+    /// publishing several builds per target.
+    ///
+    /// It is the `{ flavor }` of the templates.
+    pub flavor: Option<String>,
+
     /// Public key for package verification (base64 encoded)
     pub pub_key: Option<String>,
 
+    /// Prefix of the release tags belonging to this crate, for repositories
+    /// publishing several crates (e.g. `my-crate-v` for `my-crate-v1.2.3`)
+    pub tag_prefix: Option<String>,
+
+    /// Name used for the release assets of this crate, if it differs from
+    /// the crate name
+    pub asset_name: Option<String>,
+
+    /// Format of the archive inside the package, for packages wrapping the
+    /// actual archive (e.g. a zip containing a tarball).
+    ///
+    /// The inner archive named after the crate (or `asset_name`) is
+    /// extracted in place of the package.
+    pub nested_fmt: Option<PkgFmt>,
+
+    /// Number of parts the package is split into, for packages split to
+    /// stay under the size limit of the host.
+    ///
+    /// The parts are at `pkg-url` suffixed with `.part1`, `.part2` and so
+    /// on, or named as set by `split_style`, and are joined by
+    /// concatenating them.
+    pub split_parts: Option<NonZeroU32>,
+
+    /// Naming of the parts of a split package, `parts` by default.
+    pub split_style: Option<SplitStyle>,
+
     /// Target specific overrides
     pub overrides: BTreeMap<String, PkgOverride>,
 }
@@ -53,6 +99,15 @@ impl PkgMeta {
         if let Some(o) = &pkg_override.bin_dir {
             self.bin_dir = Some(o.clone());
         }
+        if let Some(o) = &pkg_override.flavor {
+            self.flavor = Some(o.clone());
+        }
+        self.bin_dest.extend(
+            pkg_override
+                .bin_dest
+                .iter()
+                .map(|(bin, dest)| (bin.clone(), dest.clone())),
+        );
     }
 
     /// Merge configuration overrides into object
@@ -76,11 +131,34 @@ impl PkgMeta {
                 .or(self.pkg_fmt),
 
             bin_dir: pkg_overrides
+                .clone()
                 .into_iter()
                 .find_map(|pkg_override| pkg_override.bin_dir.clone())
                 .or_else(|| self.bin_dir.clone()),
 
+            bin_dest: pkg_overrides
+                .clone()
+                .into_iter()
+                .flat_map(|pkg_override| &pkg_override.bin_dest)
+                .chain(&self.bin_dest)
+                .fold(BTreeMap::new(), |mut bin_dest, (bin, dest)| {
+                    // The first one in preference wins
+                    bin_dest.entry(bin.clone()).or_insert_with(|| dest.clone());
+                    bin_dest
+                }),
+
+            flavor: pkg_overrides
+                .into_iter()
+                .find_map(|pkg_override| pkg_override.flavor.clone())
+                .or_else(|| self.flavor.clone()),
+
+            bin_launchers: self.bin_launchers.clone(),
             pub_key: self.pub_key.clone(),
+            tag_prefix: self.tag_prefix.clone(),
+            asset_name: self.asset_name.clone(),
+            nested_fmt: self.nested_fmt,
+            split_parts: self.split_parts,
+            split_style: self.split_style,
             overrides: Default::default(),
         }
     }
@@ -100,6 +178,57 @@ pub struct PkgOverride {
 
     /// Path template override for binary files in packages
     pub bin_dir: Option<String>,
+
+    /// Destination path template overrides of specific binaries, by binary
+    /// name
+    pub bin_dest: BTreeMap<String, String>,
+
+    /// Build flavor override
+    pub flavor: Option<String>,
+}
+
+/// Launcher of a binary which is not a native executable.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BinLauncher {
+    /// Python zipapp, run with `python3`
+    Zipapp,
+    /// Java archive, run with `java -jar`
+    Jar,
+}
+
+impl BinLauncher {
+    /// File extension of the binary (with prefix `.`).
+    pub fn extension(self) -> &'static str {
+        match self {
+            BinLauncher::Zipapp => ".pyz",
+            BinLauncher::Jar => ".jar",
+        }
+    }
+
+    /// Command running the binary, given as its last argument.
+    ///
+    /// * `is_windows` - if true, return the command for Windows, where
+    ///   Python is installed as `python`.
+    pub fn command(self, is_windows: bool) -> &'static [&'static str] {
+        match self {
+            BinLauncher::Zipapp if is_windows => &["python"],
+            BinLauncher::Zipapp => &["python3"],
+            BinLauncher::Jar => &["java", "-jar"],
+        }
+    }
+}
+
+/// Naming of the parts of a split package.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SplitStyle {
+    /// `pkg-url` suffixed with `.part1`, `.part2` and so on
+    Parts,
+    /// Split zip archive, as created by `zip -s`: the extension of
+    /// `pkg-url` replaced with `.z01`, `.z02` and so on, with the last
+    /// part at `pkg-url` itself
+    Zip,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
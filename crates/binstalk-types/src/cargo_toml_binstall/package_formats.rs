@@ -18,8 +18,28 @@ pub enum PkgFmt {
     Txz,
     /// Download format is TAR + Zstd
     Tzstd,
+    /// Download format is TAR + LZ4
+    Tlz4,
+    /// Download format is TAR + Brotli
+    Tbr,
     /// Download format is Zip
     Zip,
+    /// Download format is 7z
+    #[serde(rename = "7z")]
+    #[strum(serialize = "7z")]
+    SevenZip,
+    /// Download format is Debian package, only the files in `usr/bin` are
+    /// extracted
+    Deb,
+    /// Download format is RPM package, only the files in `usr/bin` are
+    /// extracted
+    Rpm,
+    /// Download format is macOS disk image, which can only be extracted on
+    /// macOS
+    Dmg,
+    /// Download format is Windows Installer package, the files of its
+    /// embedded cabinets are extracted without running it
+    Msi,
     /// Download format is raw / binary
     Bin,
 }
@@ -39,8 +59,15 @@ impl PkgFmt {
             PkgFmt::Tgz => PkgFmtDecomposed::Tar(TarBasedFmt::Tgz),
             PkgFmt::Txz => PkgFmtDecomposed::Tar(TarBasedFmt::Txz),
             PkgFmt::Tzstd => PkgFmtDecomposed::Tar(TarBasedFmt::Tzstd),
+            PkgFmt::Tlz4 => PkgFmtDecomposed::Tar(TarBasedFmt::Tlz4),
+            PkgFmt::Tbr => PkgFmtDecomposed::Tar(TarBasedFmt::Tbr),
             PkgFmt::Bin => PkgFmtDecomposed::Bin,
             PkgFmt::Zip => PkgFmtDecomposed::Zip,
+            PkgFmt::SevenZip => PkgFmtDecomposed::SevenZip,
+            PkgFmt::Deb => PkgFmtDecomposed::Deb,
+            PkgFmt::Rpm => PkgFmtDecomposed::Rpm,
+            PkgFmt::Dmg => PkgFmtDecomposed::Dmg,
+            PkgFmt::Msi => PkgFmtDecomposed::Msi,
         }
     }
 
@@ -56,6 +83,8 @@ impl PkgFmt {
             PkgFmt::Tgz => &[".tgz", ".tar.gz"],
             PkgFmt::Txz => &[".txz", ".tar.xz"],
             PkgFmt::Tzstd => &[".tzstd", ".tzst", ".tar.zst"],
+            PkgFmt::Tlz4 => &[".tlz4", ".tar.lz4"],
+            PkgFmt::Tbr => &[".tbr", ".tar.br"],
             PkgFmt::Bin => {
                 if is_windows {
                     &[".bin", "", ".exe"]
@@ -64,6 +93,11 @@ impl PkgFmt {
                 }
             }
             PkgFmt::Zip => &[".zip"],
+            PkgFmt::SevenZip => &[".7z"],
+            PkgFmt::Deb => &[".deb"],
+            PkgFmt::Rpm => &[".rpm"],
+            PkgFmt::Dmg => &[".dmg"],
+            PkgFmt::Msi => &[".msi"],
         }
     }
 
@@ -86,8 +120,19 @@ impl PkgFmt {
             "tzstd" | "tzst" => Some(PkgFmt::Tzstd),
             "zst" if it.next() == Some("tar") => Some(PkgFmt::Tzstd),
 
+            "tlz4" => Some(PkgFmt::Tlz4),
+            "lz4" if it.next() == Some("tar") => Some(PkgFmt::Tlz4),
+
+            "tbr" => Some(PkgFmt::Tbr),
+            "br" if it.next() == Some("tar") => Some(PkgFmt::Tbr),
+
             "exe" | "bin" => Some(PkgFmt::Bin),
             "zip" => Some(PkgFmt::Zip),
+            "7z" => Some(PkgFmt::SevenZip),
+            "deb" => Some(PkgFmt::Deb),
+            "rpm" => Some(PkgFmt::Rpm),
+            "dmg" => Some(PkgFmt::Dmg),
+            "msi" => Some(PkgFmt::Msi),
 
             _ => None,
         };
@@ -105,6 +150,11 @@ pub enum PkgFmtDecomposed {
     Tar(TarBasedFmt),
     Bin,
     Zip,
+    SevenZip,
+    Deb,
+    Rpm,
+    Dmg,
+    Msi,
 }
 
 #[derive(Debug, Display, Copy, Clone, Eq, PartialEq)]
@@ -119,6 +169,10 @@ pub enum TarBasedFmt {
     Txz,
     /// Download format is TAR + Zstd
     Tzstd,
+    /// Download format is TAR + LZ4
+    Tlz4,
+    /// Download format is TAR + Brotli
+    Tbr,
 }
 
 impl From<TarBasedFmt> for PkgFmt {
@@ -129,6 +183,8 @@ impl From<TarBasedFmt> for PkgFmt {
             TarBasedFmt::Tgz => PkgFmt::Tgz,
             TarBasedFmt::Txz => PkgFmt::Txz,
             TarBasedFmt::Tzstd => PkgFmt::Tzstd,
+            TarBasedFmt::Tlz4 => PkgFmt::Tlz4,
+            TarBasedFmt::Tbr => PkgFmt::Tbr,
         }
     }
 }
@@ -1,6 +1,6 @@
 //! Common structure for crate information for post-install manifests.
 
-use std::{borrow, cmp, hash};
+use std::{borrow, cmp, collections::BTreeMap, hash, path::PathBuf};
 
 use compact_str::CompactString;
 use maybe_owned::MaybeOwned;
@@ -24,6 +24,52 @@ pub struct CrateInfo {
     pub source: CrateSource,
     pub target: CompactString,
     pub bins: Vec<CompactString>,
+    /// Digests of the installed binaries, to verify them later.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bin_digests: Vec<BinDigest>,
+    /// Url of the prebuilt artifact the binaries are extracted from, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifact_url: Option<CompactString>,
+    /// Environment the crate was installed in, to reproduce the install.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<Environment>,
+}
+
+/// Digest of an installed binary, along with its metadata at the time, so
+/// that it only needs to be rehashed if the metadata changed.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct BinDigest {
+    pub path: PathBuf,
+    /// Hex encoded sha256 digest.
+    pub sha256: CompactString,
+    pub size: u64,
+    /// Modification time, in nanoseconds since the unix epoch.
+    pub mtime_ns: u64,
+}
+
+/// Snapshot of the environment binstall runs in, enough to reproduce an
+/// install from a report alone.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Environment {
+    pub binstall_version: CompactString,
+    /// Target binstall is built for.
+    pub binstall_target: CompactString,
+    /// Targets to install for, in the order of preference.
+    pub targets: Vec<DesiredTarget>,
+    /// Version of the libc, on linux.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub libc: Option<CompactString>,
+    /// Proxy environment variables, with credentials redacted.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub proxies: BTreeMap<CompactString, CompactString>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DesiredTarget {
+    pub target: CompactString,
+    /// Why the target is desired, e.g. detected from the host.
+    pub reason: CompactString,
 }
 
 impl borrow::Borrow<str> for CrateInfo {
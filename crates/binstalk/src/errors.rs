@@ -2,12 +2,16 @@ use std::{
     io,
     path::PathBuf,
     process::{ExitCode, ExitStatus, Termination},
+    time::Duration,
 };
 
 use binstalk_downloader::{
-    download::DownloadError, gh_api_client::GhApiError, remote::Error as RemoteError,
+    download::DownloadError,
+    gh_api_client::GhApiError,
+    remote::{BudgetExceeded, Error as RemoteError},
 };
 use binstalk_fetchers::FetchError;
+use binstalk_manifests::crates_manifests::ManifestsError;
 use compact_str::CompactString;
 use miette::{Diagnostic, Report};
 use target_lexicon::ParseError as TargetTripleParseError;
@@ -20,6 +24,7 @@ use crate::{
     helpers::{
         cargo_toml::Error as CargoTomlError, cargo_toml_workspace::Error as LoadManifestFromWSError,
     },
+    ops::patch_elf::PatchElfError,
     registry::{InvalidRegistryError, RegistryError},
 };
 
@@ -99,7 +104,7 @@ pub enum BinstallError {
     /// Failed to fetch pre-built binaries.
     ///
     /// - Code: `binstall::fetch`
-    /// - Exit: 68
+    /// - Exit: 68, or 100, 107 or 108 as for [`BinstallError::Download`]
     #[error(transparent)]
     #[diagnostic(severity(error), code(binstall::fetch))]
     #[source_code(transparent)]
@@ -109,7 +114,9 @@ pub enum BinstallError {
     /// Failed to download or failed to decode the body.
     ///
     /// - Code: `binstall::download`
-    /// - Exit: 68
+    /// - Exit: 107 if the checksum does not match or no checksum is
+    ///   published to verify a mirror against, 108 on network failures, 100
+    ///   if the host is not allowed, 68 otherwise
     #[error(transparent)]
     #[diagnostic(severity(error), code(binstall::download))]
     Download(#[from] DownloadError),
@@ -148,7 +155,7 @@ pub enum BinstallError {
     /// This could either be a "not found" or a server/transport error.
     ///
     /// - Code: `binstall::cargo_registry`
-    /// - Exit: 76
+    /// - Exit: 76, or 100, 107 or 108 as for [`BinstallError::Download`]
     #[error(transparent)]
     #[diagnostic(transparent)]
     RegistryError(#[from] Box<RegistryError>),
@@ -272,7 +279,8 @@ pub enum BinstallError {
     #[diagnostic(severity(error), code(binstall::SourceFilePath))]
     DuplicateSourceFilePath { path: PathBuf },
 
-    /// Fallback to `cargo-install` is disabled.
+    /// No pre-built artifact is found and fallback to `cargo-install` is
+    /// disabled.
     ///
     /// - Code: `binstall::no_fallback_to_cargo_install`
     /// - Exit: 94
@@ -291,7 +299,7 @@ pub enum BinstallError {
     /// Request to GitHub API failed
     ///
     /// - Code: `binstall::gh_api_failure`
-    /// - Exit: 96
+    /// - Exit: 96, or 100 or 108 as for [`BinstallError::Download`]
     #[error("Request to GitHub API failed: {0}")]
     #[diagnostic(severity(error), code(binstall::gh_api_failure))]
     GhApiErr(#[source] Box<GhApiError>),
@@ -321,6 +329,118 @@ pub enum BinstallError {
     #[diagnostic(severity(error), code(binstall::load_manifest_from_workspace))]
     LoadManifestFromWSError(#[from] Box<LoadManifestFromWSError>),
 
+    /// The version resolved is rejected by the version resolution hook.
+    ///
+    /// - Code: `binstall::version::rejected`
+    /// - Exit: 100
+    #[error("version {version} is rejected: {reason}")]
+    #[diagnostic(severity(error), code(binstall::version::rejected))]
+    VersionRejected {
+        version: CompactString,
+        reason: CompactString,
+    },
+
+    /// Resolving the crate, or building it from source, takes longer than
+    /// the timeout specified.
+    ///
+    /// - Code: `binstall::timeout`
+    /// - Exit: 101
+    #[error("timed out after {0:?}")]
+    #[diagnostic(severity(error), code(binstall::timeout))]
+    Timeout(Duration),
+
+    /// Some of the crates failed to install while others were installed.
+    ///
+    /// - Code: `binstall::partial_success`
+    /// - Exit: 102
+    #[error("{failed} out of {total} crates failed to install")]
+    #[diagnostic(severity(error), code(binstall::partial_success))]
+    PartialSuccess { failed: usize, total: usize },
+
+    /// The request or download byte budget of this run is exceeded.
+    ///
+    /// - Code: `binstall::budget_exceeded`
+    /// - Exit: 103
+    #[error(transparent)]
+    #[diagnostic(
+        severity(error),
+        code(binstall::budget_exceeded),
+        help("Raise --max-requests or --max-download-bytes if this is expected.")
+    )]
+    BudgetExceeded(#[from] BudgetExceeded),
+
+    /// Failed to rewrite the interpreter or the rpath of a binary.
+    ///
+    /// - Code: `binstall::patch_elf`
+    /// - Exit: 104
+    #[error("failed to patch {}: {err}", path.display())]
+    #[diagnostic(
+        severity(error),
+        code(binstall::patch_elf),
+        help("Install patchelf to rewrite binaries whose new interpreter or rpath is longer.")
+    )]
+    PatchElf {
+        path: PathBuf,
+        #[source]
+        err: PatchElfError,
+    },
+
+    /// The crate resolved differs from the one planned, see
+    /// [`InstallPlan`](crate::ops::plan::InstallPlan).
+    ///
+    /// - Code: `binstall::plan_mismatch`
+    /// - Exit: 105
+    #[error("resolution differs from the install plan: {0}")]
+    #[diagnostic(
+        severity(error),
+        code(binstall::plan_mismatch),
+        help("Plan the install again and review the new plan.")
+    )]
+    PlanMismatch(CompactString),
+
+    /// A binary to overwrite is modified since it was installed, e.g.
+    /// patched locally.
+    ///
+    /// - Code: `binstall::modified_binary`
+    /// - Exit: 106
+    #[error("{} is modified since it was installed", .0.display())]
+    #[diagnostic(
+        severity(error),
+        code(binstall::modified_binary),
+        help("Pass --overwrite-modified to overwrite it anyway.")
+    )]
+    ModifiedBinary(PathBuf),
+
+    /// Some installed binaries failed verification, e.g. they are modified
+    /// or missing.
+    ///
+    /// - Code: `binstall::verification_failed`
+    /// - Exit: 107
+    #[error("{0}")]
+    #[diagnostic(severity(error), code(binstall::verification_failed))]
+    VerificationFailed(CompactString),
+
+    /// The version of a crate is not pinned while `--deterministic` is
+    /// specified, so it would be resolved to the latest version matched.
+    ///
+    /// - Code: `binstall::unpinned_version`
+    /// - Exit: 109
+    #[error("version requirement '{0}' is not pinned to a single version")]
+    #[diagnostic(
+        severity(error),
+        code(binstall::unpinned_version),
+        help("Pin the version with crate@version, or apply an install plan.")
+    )]
+    UnpinnedVersion(CompactString),
+
+    /// Failed to load or update the records of the crates installed.
+    ///
+    /// - Code: `binstall::manifests`
+    /// - Exit: 110
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Manifests(Box<ManifestsError>),
+
     /// A wrapped error providing the context of which crate the error is about.
     #[error(transparent)]
     #[diagnostic(transparent)]
@@ -328,19 +448,35 @@ pub enum BinstallError {
 }
 
 impl BinstallError {
-    fn exit_number(&self) -> u8 {
+    /// The exit number of the recommended exit code, see
+    /// [`BinstallError::exit_code`].
+    ///
+    /// The failures scripts most often need to tell apart have stable
+    /// numbers:
+    /// - 94: no pre-built artifact is found
+    /// - 100: denied by policy, e.g. the version is rejected or the host is
+    ///   not allowed
+    /// - 102: some crates failed to install while the others are installed
+    /// - 107: verification failed, e.g. checksum mismatch
+    /// - 108: network failure
+    pub fn exit_number(&self) -> u8 {
         use BinstallError::*;
         let code: u8 = match self {
             TaskJoinError(_) => 17,
             UserAbort => 32,
             UrlParse(_) => 65,
             TemplateParseError(..) => 67,
-            FetchError(..) => 68,
-            Download(_) => 68,
+            FetchError(err) => match &**err {
+                binstalk_fetchers::FetchError::Download(err) => download_exit_number(err),
+                binstalk_fetchers::FetchError::GhApi(err) => gh_api_exit_number(err),
+                _ => None,
+            }
+            .unwrap_or(68),
+            Download(err) => download_exit_number(err).unwrap_or(68),
             SubProcess { .. } => 70,
             Io(_) => 74,
             UnknownRegistryName(_) => 75,
-            RegistryError { .. } => 76,
+            RegistryError(err) => registry_exit_number(err).unwrap_or(76),
             CargoManifestPath => 77,
             CargoManifest { .. } => 78,
             RegistryParseError(..) => 79,
@@ -353,11 +489,21 @@ impl BinstallError {
             DuplicateSourceFilePath { .. } => 90,
             NoFallbackToCargoInstall => 94,
             InvalidPkgFmt(..) => 95,
-            GhApiErr(..) => 96,
+            GhApiErr(err) => gh_api_exit_number(err).unwrap_or(96),
             TargetTripleParseError(..) => 97,
             #[cfg(feature = "git")]
             GitError(_) => 98,
             LoadManifestFromWSError(_) => 99,
+            VersionRejected { .. } => 100,
+            Timeout(_) => 101,
+            PartialSuccess { .. } => 102,
+            BudgetExceeded(_) => 103,
+            PatchElf { .. } => 104,
+            PlanMismatch(_) => 105,
+            ModifiedBinary(_) => 106,
+            VerificationFailed(_) => 107,
+            UnpinnedVersion(_) => 109,
+            Manifests(_) => 110,
             CrateContext(context) => context.err.exit_number(),
         };
 
@@ -387,6 +533,45 @@ impl BinstallError {
     }
 }
 
+/// Exit number of a download failure that is a verification, network or
+/// policy failure.
+fn download_exit_number(err: &DownloadError) -> Option<u8> {
+    match err {
+        DownloadError::ChecksumMismatch(_) | DownloadError::UnverifiedMirror { .. } => Some(107),
+        DownloadError::Remote(err) => remote_exit_number(err),
+        _ => None,
+    }
+}
+
+/// Exit number of a request failure that is a network or policy failure.
+fn remote_exit_number(err: &RemoteError) -> Option<u8> {
+    match err {
+        RemoteError::Reqwest(_) | RemoteError::Http(_) | RemoteError::Timeout { .. } => Some(108),
+        RemoteError::HostNotAllowed(_) => Some(100),
+        _ => None,
+    }
+}
+
+fn registry_exit_number(err: &RegistryError) -> Option<u8> {
+    match err {
+        RegistryError::UnmatchedChecksum { .. } => Some(107),
+        RegistryError::Remote(err) => remote_exit_number(err),
+        RegistryError::Download(err) => download_exit_number(err),
+        _ => None,
+    }
+}
+
+fn gh_api_exit_number(err: &GhApiError) -> Option<u8> {
+    match err {
+        GhApiError::Remote(err) => remote_exit_number(err),
+        // The context only wraps the error it is about.
+        GhApiError::Context(context) => std::error::Error::source(&**context)?
+            .downcast_ref::<GhApiError>()
+            .and_then(gh_api_exit_number),
+        _ => None,
+    }
+}
+
 impl Termination for BinstallError {
     fn report(self) -> ExitCode {
         let code = self.exit_code();
@@ -430,7 +615,10 @@ impl From<BinstallError> for io::Error {
 
 impl From<RemoteError> for BinstallError {
     fn from(e: RemoteError) -> Self {
-        DownloadError::from(e).into()
+        match e {
+            RemoteError::BudgetExceeded(e) => e.into(),
+            e => DownloadError::from(e).into(),
+        }
     }
 }
 
@@ -476,8 +664,136 @@ impl From<LoadManifestFromWSError> for BinstallError {
     }
 }
 
+impl From<ManifestsError> for BinstallError {
+    fn from(e: ManifestsError) -> Self {
+        BinstallError::Manifests(Box::new(e))
+    }
+}
+
 impl From<FetchError> for BinstallError {
     fn from(e: FetchError) -> Self {
         BinstallError::FetchError(Box::new(e))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use binstalk_downloader::download::ChecksumMismatch;
+
+    use super::*;
+
+    fn url() -> Box<url::Url> {
+        Box::new("https://example.com".parse().unwrap())
+    }
+
+    fn mismatch() -> DownloadError {
+        DownloadError::from(ChecksumMismatch {
+            algorithm: "sha256",
+            expected: "00".into(),
+            actual: "ff".into(),
+        })
+    }
+
+    fn timeout() -> RemoteError {
+        RemoteError::Timeout {
+            url: url(),
+            duration: Duration::from_secs(1),
+        }
+    }
+
+    #[test]
+    fn test_exit_number_not_found() {
+        assert_eq!(
+            BinstallError::NoFallbackToCargoInstall
+                .crate_context("ripgrep")
+                .exit_number(),
+            94
+        );
+    }
+
+    #[test]
+    fn test_exit_number_policy_denied() {
+        let rejected = BinstallError::VersionRejected {
+            version: "1.0.0".into(),
+            reason: "yanked".into(),
+        };
+        assert_eq!(rejected.exit_number(), 100);
+
+        let not_allowed = || RemoteError::HostNotAllowed(url());
+        assert_eq!(BinstallError::from(not_allowed()).exit_number(), 100);
+        assert_eq!(
+            BinstallError::from(FetchError::from(not_allowed())).exit_number(),
+            100
+        );
+        assert_eq!(
+            BinstallError::from(RegistryError::from(not_allowed())).exit_number(),
+            100
+        );
+    }
+
+    #[test]
+    fn test_exit_number_partial_success() {
+        let err = BinstallError::PartialSuccess {
+            failed: 1,
+            total: 2,
+        };
+        assert_eq!(err.exit_number(), 102);
+    }
+
+    #[test]
+    fn test_exit_number_verification_failed() {
+        assert_eq!(BinstallError::from(mismatch()).exit_number(), 107);
+        assert_eq!(
+            BinstallError::from(FetchError::from(mismatch())).exit_number(),
+            107
+        );
+        assert_eq!(
+            BinstallError::from(DownloadError::UnverifiedMirror {
+                url: Box::new("https://github.com/foo.tgz".parse().unwrap()),
+                mirror: Box::new("https://gh-mirror.corp/foo.tgz".parse().unwrap()),
+            })
+            .exit_number(),
+            107
+        );
+        assert_eq!(
+            BinstallError::from(RegistryError::UnmatchedChecksum {
+                expected: "00".into(),
+                actual: "ff".into(),
+            })
+            .exit_number(),
+            107
+        );
+    }
+
+    #[test]
+    fn test_exit_number_network() {
+        assert_eq!(BinstallError::from(timeout()).exit_number(), 108);
+        assert_eq!(
+            BinstallError::from(FetchError::from(timeout())).exit_number(),
+            108
+        );
+        assert_eq!(
+            BinstallError::from(RegistryError::from(timeout())).exit_number(),
+            108
+        );
+        assert_eq!(
+            BinstallError::from(GhApiError::from(timeout()).context("fetching release"))
+                .exit_number(),
+            108
+        );
+        assert_eq!(
+            BinstallError::from(FetchError::from(GhApiError::from(timeout()))).exit_number(),
+            108
+        );
+
+        // Other registry and GitHub API failures keep their own numbers.
+        assert_eq!(
+            BinstallError::from(RegistryError::NotFound("foo".into())).exit_number(),
+            76
+        );
+        assert_eq!(
+            BinstallError::from(GhApiError::from(url::ParseError::EmptyHost)).exit_number(),
+            96
+        );
+    }
+}
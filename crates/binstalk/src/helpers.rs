@@ -1,9 +1,11 @@
 pub mod jobserver_client;
+pub mod message;
 pub mod remote;
 pub(crate) mod target_triple;
 pub mod tasks;
 
 pub(crate) use binstalk_downloader::download;
+pub use binstalk_downloader::download::{clean_artifact_cache, hex, DownloadJournal, JournalEntry};
 pub use binstalk_downloader::gh_api_client;
 
 pub(crate) use cargo_toml_workspace::{self, cargo_toml};
@@ -5,6 +5,9 @@ use tokio::sync::OnceCell;
 
 use crate::errors::BinstallError;
 
+/// Clones share the jobserver inherited from the environment, or the one
+/// created by [`LazyJobserverClient::get`] if it is already created.
+#[derive(Clone)]
 pub struct LazyJobserverClient(OnceCell<Client>);
 
 impl LazyJobserverClient {
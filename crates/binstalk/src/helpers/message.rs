@@ -0,0 +1,143 @@
+//! User facing messages, rendered either as human text, possibly
+//! translated, or as stable identifiers followed by their arguments, see
+//! [`MessageFormat`].
+//!
+//! Wrapper tools should match the identifiers, e.g.
+//! `binstall.already-installed`, which never change, rather than the human
+//! text, whose wording does.
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use leon::Template;
+use once_cell::sync::OnceCell;
+use tracing::{error, info, warn};
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum MessageFormat {
+    /// Human text, translated by the catalog set by [`set_catalog`], if any.
+    #[default]
+    Human,
+    /// The identifier of the message followed by its arguments, e.g.
+    /// `binstall.already-installed name="ripgrep" version="14.0.0"`.
+    Id,
+}
+
+static ID_FORMAT: AtomicBool = AtomicBool::new(false);
+static CATALOG: OnceCell<HashMap<String, String>> = OnceCell::new();
+
+pub fn set_message_format(format: MessageFormat) {
+    ID_FORMAT.store(format == MessageFormat::Id, Ordering::Relaxed);
+}
+
+/// Set the translations of the human text of the messages, by identifier.
+///
+/// The translations are templates using the arguments of the messages,
+/// e.g. `{ name } v{ version } est déjà installé`. Messages without a valid
+/// translation are rendered in English.
+///
+/// Return `false` if a catalog is already set.
+pub fn set_catalog(catalog: HashMap<String, String>) -> bool {
+    CATALOG.set(catalog).is_ok()
+}
+
+/// A user facing message, see [the module](self).
+#[derive(Clone, Debug)]
+pub struct Message<'a> {
+    id: &'static str,
+    /// English text, as a template of the arguments.
+    template: &'static str,
+    args: Vec<(&'static str, Cow<'a, str>)>,
+}
+
+impl<'a> Message<'a> {
+    pub fn new(id: &'static str, template: &'static str) -> Self {
+        Self {
+            id,
+            template,
+            args: Vec::new(),
+        }
+    }
+
+    pub fn arg(mut self, key: &'static str, value: impl Into<Cow<'a, str>>) -> Self {
+        self.args.push((key, value.into()));
+        self
+    }
+
+    pub fn id(&self) -> &'static str {
+        self.id
+    }
+
+    fn render_human(&self, template: &str) -> Option<String> {
+        Template::parse(template).ok()?.render(&self.args).ok()
+    }
+
+    pub fn info(&self) {
+        info!(message_id = self.id, "{self}");
+    }
+
+    pub fn warn(&self) {
+        warn!(message_id = self.id, "{self}");
+    }
+
+    pub fn error(&self) {
+        error!(message_id = self.id, "{self}");
+    }
+}
+
+impl fmt::Display for Message<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if ID_FORMAT.load(Ordering::Relaxed) {
+            f.write_str(self.id)?;
+            for (key, value) in &self.args {
+                write!(f, " {key}={value:?}")?;
+            }
+            return Ok(());
+        }
+
+        let translated = CATALOG
+            .get()
+            .and_then(|catalog| catalog.get(self.id))
+            .and_then(|template| self.render_human(template));
+
+        match translated.or_else(|| self.render_human(self.template)) {
+            Some(text) => f.write_str(&text),
+            // The English templates are valid, unless a bug is introduced.
+            None => f.write_str(self.template),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_message() {
+        let message = Message::new(
+            "binstall.already-installed",
+            "{ name } v{ version } is already installed",
+        )
+        .arg("name", "ripgrep")
+        .arg("version", "14.0.0".to_string());
+
+        assert_eq!(message.to_string(), "ripgrep v14.0.0 is already installed");
+
+        set_catalog(HashMap::from([(
+            "binstall.already-installed".to_string(),
+            "{ name } v{ version } est déjà installé".to_string(),
+        )]));
+        assert_eq!(message.to_string(), "ripgrep v14.0.0 est déjà installé");
+
+        set_message_format(MessageFormat::Id);
+        assert_eq!(
+            message.to_string(),
+            r#"binstall.already-installed name="ripgrep" version="14.0.0""#
+        );
+        set_message_format(MessageFormat::Human);
+    }
+}
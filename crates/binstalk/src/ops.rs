@@ -1,7 +1,8 @@
 //! Concrete Binstall operations.
 
-use std::{path::PathBuf, sync::Arc};
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
 
+use compact_str::CompactString;
 use semver::VersionReq;
 
 use crate::{
@@ -10,11 +11,25 @@ use crate::{
         self, gh_api_client::GhApiClient, jobserver_client::LazyJobserverClient, remote::Client,
     },
     manifests::cargo_toml_binstall::PkgOverride,
+    ops::{
+        build_cache::BuildCache,
+        event::{InstallEvent, ProgressSink},
+        patch_elf::PatchElf,
+        resolve::VersionResolutionHook,
+    },
     registry::Registry,
     DesiredTargets,
 };
 
+pub mod build_cache;
+pub mod changelog;
+pub mod event;
+pub mod installer;
+pub mod lint;
+pub mod patch_elf;
+pub mod plan;
 pub mod resolve;
+pub mod verify;
 
 pub type Resolver = fn(Client, GhApiClient, Arc<Data>, Arc<TargetDataErased>) -> Arc<dyn Fetcher>;
 
@@ -32,14 +47,34 @@ pub struct Options {
     pub quiet: bool,
     pub locked: bool,
     pub no_track: bool,
+    /// Refuse to look up the latest version of crates from the registry,
+    /// and pick the first artifact found in the order the candidates are
+    /// tried, so that the resolution does not depend on timing.
+    pub deterministic: bool,
 
     pub version_req: Option<VersionReq>,
     pub cargo_toml_fetch_override: Option<CargoTomlFetchOverride>,
+    /// Options of the clone of `--git`.
+    #[cfg(feature = "git")]
+    pub git_options: helpers::git::GitOptions,
+    /// Check out all the files of `--git`, instead of only the ones needed
+    /// to load the manifests.
+    #[cfg(feature = "git")]
+    pub git_full_checkout: bool,
     pub cli_overrides: PkgOverride,
 
     pub desired_targets: DesiredTargets,
     pub resolvers: Vec<Resolver>,
+    /// Build from the source archive of the release tag if no prebuilt
+    /// binary is found, before falling back to `cargo install`.
+    pub source_archive_fallback: bool,
     pub cargo_install_fallback: bool,
+    /// Environment variables set when building the crate of the key from
+    /// source.
+    pub build_env: BTreeMap<CompactString, BTreeMap<CompactString, CompactString>>,
+    /// Reuse the binaries built from source by previous runs, and cache the
+    /// ones built.
+    pub build_cache: Option<BuildCache>,
 
     pub temp_dir: PathBuf,
     pub install_path: PathBuf,
@@ -49,4 +84,24 @@ pub struct Options {
     pub gh_api_client: GhApiClient,
     pub jobserver_client: LazyJobserverClient,
     pub registry: Registry,
+    pub version_resolution_hook: Option<Arc<dyn VersionResolutionHook>>,
+    pub progress_sink: Option<Arc<dyn ProgressSink>>,
+    /// Move the artifacts failing verification into this directory for
+    /// investigation, instead of deleting them.
+    pub quarantine_dir: Option<PathBuf>,
+    /// Rewrite the interpreter and the rpath of the binaries before they
+    /// are installed.
+    pub patch_elf: Option<PatchElf>,
+    /// Keep the artifacts downloaded in `temp_dir`, see
+    /// [`ResolutionFetch::artifact`](resolve::ResolutionFetch::artifact).
+    pub keep_artifacts: bool,
+}
+
+impl Options {
+    /// Emit `event` to the progress sink, if any.
+    pub fn emit(&self, event: InstallEvent<'_>) {
+        if let Some(progress_sink) = &self.progress_sink {
+            progress_sink.on_event(event);
+        }
+    }
 }
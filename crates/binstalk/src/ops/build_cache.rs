@@ -0,0 +1,368 @@
+//! Cache of the binaries built from source when no prebuilt binary is
+//! found, so that a crate is only compiled once per machine, or once per
+//! team with a remote build cache, see [`BuildCache`].
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use compact_str::CompactString;
+use sha2::{Digest, Sha256};
+use tempfile::TempDir;
+use tokio::task::spawn_blocking;
+use tracing::{debug, warn};
+use url::Url;
+
+use crate::{
+    errors::BinstallError,
+    helpers::{
+        hex,
+        remote::{Client, Method},
+    },
+    ops::verify::sha256_file,
+};
+
+/// Cache of the binaries built from source, by [`BuildKey`].
+///
+/// Each entry is a directory with the binaries built under `bin/`, and
+/// `bins` listing the sha256 digest and the name of each binary, one per
+/// line. Entries have the same layout in the remote cache, if any, where
+/// they are downloaded from if they are not cached locally, and uploaded to
+/// with `PUT` once built.
+#[derive(Clone, Debug)]
+pub struct BuildCache {
+    dir: PathBuf,
+    remote: Option<Url>,
+}
+
+/// Key of a build, the sha256 digest of what changes the binaries built by
+/// `cargo install`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct BuildKey(CompactString);
+
+impl BuildKey {
+    /// * `source` - the registry or the url of the source archive the crate
+    ///   is built from.
+    /// * `env` - the environment variables set for the build.
+    ///
+    /// Crates are always built with their default features.
+    pub(crate) fn new(
+        name: &str,
+        version: &str,
+        target: &str,
+        source: &str,
+        locked: bool,
+        env: Option<&BTreeMap<CompactString, CompactString>>,
+    ) -> Self {
+        let locked = if locked { "locked" } else { "unlocked" };
+
+        let mut hasher = Sha256::new();
+        for field in [name, version, target, source, locked, "default-features"] {
+            hasher.update(field);
+            hasher.update([0]);
+        }
+        for (var, value) in env.into_iter().flatten() {
+            hasher.update(var);
+            hasher.update("=");
+            hasher.update(value);
+            hasher.update([0]);
+        }
+
+        Self(hex(hasher.finalize()).into())
+    }
+}
+
+impl fmt::Display for BuildKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Parse `bins` of an entry into the digest and the name of each binary.
+fn parse_manifest(manifest: &str) -> Option<Vec<(CompactString, CompactString)>> {
+    manifest
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (sha256, name) = line.split_once("  ")?;
+            let valid_name =
+                !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\']);
+            valid_name.then(|| (sha256.into(), name.into()))
+        })
+        .collect()
+}
+
+/// Return the binaries of the entry `entry_dir`, `None` if it does not
+/// exist or does not match its manifest, in which case it is removed.
+fn read_entry(entry_dir: &Path) -> io::Result<Option<Vec<PathBuf>>> {
+    let manifest = match fs::read_to_string(entry_dir.join("bins")) {
+        Ok(manifest) => manifest,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let bins = parse_manifest(&manifest).and_then(|manifest| {
+        manifest
+            .into_iter()
+            .map(|(sha256, name)| {
+                let path = entry_dir.join("bin").join(&*name);
+                (sha256_file(&path).ok()? == sha256).then_some(path)
+            })
+            .collect::<Option<Vec<_>>>()
+    });
+
+    if bins.is_none() {
+        warn!(
+            "Build cached in {} does not match its manifest, removing it",
+            entry_dir.display()
+        );
+        fs::remove_dir_all(entry_dir)?;
+    }
+
+    Ok(bins)
+}
+
+/// Store `bins`, the name and the content of each binary, into the entry
+/// `key` of `dir` and return their paths.
+///
+/// The entry is written to a temporary directory then renamed, so that it
+/// is never read partially written.
+fn write_entry(
+    dir: &Path,
+    key: &BuildKey,
+    bins: &[(CompactString, Vec<u8>)],
+) -> io::Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)?;
+
+    let tmp = TempDir::new_in(dir)?;
+    fs::create_dir(tmp.path().join("bin"))?;
+
+    let mut manifest = String::new();
+    for (name, content) in bins {
+        let path = tmp.path().join("bin").join(&**name);
+        fs::write(&path, content)?;
+        #[cfg(unix)]
+        fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o755))?;
+
+        manifest.push_str(&format!("{}  {name}\n", hex(Sha256::digest(content))));
+    }
+    fs::write(tmp.path().join("bins"), manifest)?;
+
+    let entry_dir = dir.join(&*key.0);
+    // Another process might have stored the same build meanwhile.
+    if let Err(err) = fs::rename(tmp.path(), &entry_dir) {
+        if !entry_dir.join("bins").exists() {
+            return Err(err);
+        }
+    }
+
+    read_entry(&entry_dir)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("failed to store the build {key}"),
+        )
+    })
+}
+
+impl BuildCache {
+    /// * `dir` - directory to cache the builds in, e.g. `builds` in the
+    ///   artifact cache.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            remote: None,
+        }
+    }
+
+    /// Download the builds which are not cached locally from `url`, and
+    /// upload the builds to it, e.g. a directory of a self-hosted artifact
+    /// server shared by a team.
+    pub fn with_remote(mut self, mut url: Url) -> Self {
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+        self.remote = Some(url);
+        self
+    }
+
+    /// Return the binaries built for `key`, downloading them from the
+    /// remote cache if they are not cached locally.
+    pub(crate) async fn get(
+        &self,
+        client: &Client,
+        key: &BuildKey,
+    ) -> Result<Option<Vec<PathBuf>>, BinstallError> {
+        let entry_dir = self.dir.join(&*key.0);
+        if let Some(bins) = spawn_blocking(move || read_entry(&entry_dir)).await?? {
+            return Ok(Some(bins));
+        }
+
+        let Some(remote) = &self.remote else {
+            return Ok(None);
+        };
+        let entry_url = remote.join(&format!("{key}/"))?;
+
+        let response = client.get(entry_url.join("bins")?).send(false).await?;
+        if !response.status().is_success() {
+            debug!(
+                "Build {key} not found in the remote build cache: {}",
+                response.status()
+            );
+            return Ok(None);
+        }
+        let manifest = response.bytes().await?;
+        let Some(manifest) = parse_manifest(&String::from_utf8_lossy(&manifest)) else {
+            warn!("Invalid manifest of the build {key} in the remote build cache, ignoring it");
+            return Ok(None);
+        };
+
+        let mut bins = Vec::with_capacity(manifest.len());
+        for (sha256, name) in manifest {
+            let url = entry_url.join(&format!("bin/{name}"))?;
+            let content = Vec::from(client.get(url).send(true).await?.bytes().await?);
+
+            if hex(Sha256::digest(&content)) != sha256 {
+                warn!("Binary {name} of the build {key} in the remote build cache does not match its manifest, ignoring it");
+                return Ok(None);
+            }
+            bins.push((name, content));
+        }
+
+        let (dir, key) = (self.dir.clone(), key.clone());
+        Ok(Some(
+            spawn_blocking(move || write_entry(&dir, &key, &bins)).await??,
+        ))
+    }
+
+    /// Store the binaries of `bin_dir`, built for `key`, and upload them
+    /// to the remote cache, then return their paths.
+    pub(crate) async fn put(
+        &self,
+        client: &Client,
+        key: &BuildKey,
+        bin_dir: PathBuf,
+    ) -> Result<Vec<PathBuf>, BinstallError> {
+        let bins = spawn_blocking(move || {
+            fs::read_dir(bin_dir)?
+                .map(|entry| {
+                    let entry = entry?;
+                    let name = entry.file_name().to_string_lossy().into();
+                    Ok((name, fs::read(entry.path())?))
+                })
+                .collect::<io::Result<Vec<_>>>()
+        })
+        .await??;
+
+        if let Some(remote) = &self.remote {
+            if let Err(err) = upload(client, remote, key, &bins).await {
+                warn!("Failed to upload the build {key} to the remote build cache: {err}");
+            }
+        }
+
+        let (dir, key) = (self.dir.clone(), key.clone());
+        Ok(spawn_blocking(move || write_entry(&dir, &key, &bins)).await??)
+    }
+}
+
+/// Upload the binaries first, so that the entry is only found once it is
+/// complete.
+async fn upload(
+    client: &Client,
+    remote: &Url,
+    key: &BuildKey,
+    bins: &[(CompactString, Vec<u8>)],
+) -> Result<(), BinstallError> {
+    let entry_url = remote.join(&format!("{key}/"))?;
+
+    let mut manifest = String::new();
+    for (name, content) in bins {
+        client
+            .request(Method::PUT, entry_url.join(&format!("bin/{name}"))?)
+            .body(content.clone())
+            .send(true)
+            .await?;
+        manifest.push_str(&format!("{}  {name}\n", hex(Sha256::digest(content))));
+    }
+
+    client
+        .request(Method::PUT, entry_url.join("bins")?)
+        .body(manifest)
+        .send(true)
+        .await?;
+
+    debug!("Uploaded the build {key} to the remote build cache");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_build_key() {
+        let key = |target, env: Option<&BTreeMap<_, _>>| {
+            BuildKey::new(
+                "ripgrep",
+                "14.0.0",
+                target,
+                "sparse+https://index.crates.io/",
+                false,
+                env,
+            )
+        };
+        let env = BTreeMap::from([("OPENSSL_DIR".into(), "/opt/openssl".into())]);
+
+        assert_eq!(
+            key("x86_64-unknown-linux-gnu", None),
+            key("x86_64-unknown-linux-gnu", None)
+        );
+        assert_ne!(
+            key("x86_64-unknown-linux-gnu", None),
+            key("x86_64-unknown-linux-musl", None)
+        );
+        assert_ne!(
+            key("x86_64-unknown-linux-gnu", None),
+            key("x86_64-unknown-linux-gnu", Some(&env))
+        );
+    }
+
+    #[test]
+    fn test_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = BuildKey::new(
+            "ripgrep",
+            "14.0.0",
+            "x86_64-unknown-linux-gnu",
+            "",
+            false,
+            None,
+        );
+        let entry_dir = dir.path().join(&*key.0);
+
+        assert_eq!(read_entry(&entry_dir).unwrap(), None);
+
+        let bins = write_entry(dir.path(), &key, &[("rg".into(), b"rg".to_vec())]).unwrap();
+        assert_eq!(bins, [entry_dir.join("bin").join("rg")]);
+        assert_eq!(read_entry(&entry_dir).unwrap(), Some(bins.clone()));
+
+        // Storing the same build again keeps the entry.
+        write_entry(dir.path(), &key, &[("rg".into(), b"rg".to_vec())]).unwrap();
+
+        fs::write(&bins[0], "tampered").unwrap();
+        assert_eq!(read_entry(&entry_dir).unwrap(), None);
+        assert!(!entry_dir.exists());
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        assert_eq!(
+            parse_manifest("abc  rg\n").unwrap(),
+            [("abc".into(), "rg".into())]
+        );
+        assert_eq!(parse_manifest("abc  ../rg\n"), None);
+        assert_eq!(parse_manifest("abc rg\n"), None);
+    }
+}
@@ -0,0 +1,220 @@
+//! Release notes of the versions between the installed version of a crate
+//! and the version it is upgraded to.
+
+use semver::Version;
+use tracing::debug;
+use url::Url;
+
+use crate::{
+    errors::BinstallError,
+    helpers::{
+        gh_api_client::GhApiClient,
+        remote::{Client, StatusCode},
+    },
+};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ChangelogEntry {
+    pub version: Version,
+    pub notes: String,
+}
+
+/// Fetch the release notes of versions of `crate_name` newer than `current`
+/// and up to `new`, newest first.
+///
+/// The notes are taken from the GitHub releases of `repo`, or from the
+/// sections of its `CHANGELOG.md` if none of the releases match.
+///
+/// Return an empty vec if `repo` is not hosted on GitHub.
+pub async fn fetch_changelog(
+    client: &Client,
+    gh_api_client: &GhApiClient,
+    crate_name: &str,
+    repo: &str,
+    current: &Version,
+    new: &Version,
+) -> Result<Vec<ChangelogEntry>, BinstallError> {
+    let Some((owner, repo)) = github_repo(repo) else {
+        debug!("Repository {repo} of {crate_name} is not hosted on GitHub, no changelog");
+        return Ok(Vec::new());
+    };
+
+    let in_range = |version: &Version| current < version && version <= new;
+
+    let mut entries: Vec<_> = gh_api_client
+        .list_release_notes(&owner, &repo)
+        .await?
+        .into_iter()
+        .filter(|release| !release.draft)
+        .filter_map(|release| {
+            let version = version_from_tag(crate_name, &release.tag_name)?;
+            in_range(&version).then(|| ChangelogEntry {
+                version,
+                notes: release.body.unwrap_or_default().trim().to_string(),
+            })
+        })
+        .collect();
+
+    if entries.is_empty() {
+        debug!("No GitHub release of {crate_name} matches, falling back to CHANGELOG.md");
+
+        let response = client
+            .get(Url::parse(&format!(
+                "https://raw.githubusercontent.com/{owner}/{repo}/HEAD/CHANGELOG.md"
+            ))?)
+            .send(false)
+            .await?;
+
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+
+        let content = response.error_for_status()?.bytes().await?;
+        entries = changelog_sections(&String::from_utf8_lossy(&content), &in_range);
+    }
+
+    entries.sort_by(|x, y| y.version.cmp(&x.version));
+    entries.dedup_by(|x, y| x.version == y.version);
+
+    Ok(entries)
+}
+
+/// Return the owner and name of `repo` if it is hosted on GitHub.
+fn github_repo(repo: &str) -> Option<(String, String)> {
+    let url = Url::parse(repo).ok()?;
+    if url.domain()? != "github.com" {
+        return None;
+    }
+
+    let mut segments = url.path_segments()?;
+    let owner = segments.next().filter(|s| !s.is_empty())?;
+    let repo = segments.next().filter(|s| !s.is_empty())?;
+
+    Some((owner.to_string(), repo.trim_end_matches(".git").to_string()))
+}
+
+/// Find the version in `tag`, e.g. `v1.2.3`, `1.2.3`, `{crate_name}-v1.2.3`
+/// or `{crate_name}/1.2.3`.
+///
+/// Return `None` if there is anything else than the crate name before the
+/// version, since the tag is probably for another crate of the repository.
+fn version_from_tag(crate_name: &str, tag: &str) -> Option<Version> {
+    tag.char_indices()
+        .filter(|(_, c)| c.is_ascii_digit())
+        .find_map(|(i, _)| {
+            let prefix = tag[..i]
+                .trim_end_matches(['v', 'V'])
+                .trim_end_matches(['-', '_', '/', '@', ' ']);
+
+            if prefix.is_empty() || prefix.eq_ignore_ascii_case(crate_name) {
+                Version::parse(&tag[i..]).ok()
+            } else {
+                None
+            }
+        })
+}
+
+/// Split `content` into its markdown sections whose heading contains a
+/// version accepted by `in_range`.
+fn changelog_sections(content: &str, in_range: &dyn Fn(&Version) -> bool) -> Vec<ChangelogEntry> {
+    let mut entries = Vec::new();
+    let mut current: Option<(Version, Vec<&str>)> = None;
+
+    let mut finish = |current: Option<(Version, Vec<&str>)>| {
+        if let Some((version, lines)) = current {
+            entries.push(ChangelogEntry {
+                version,
+                notes: lines.join("\n").trim().to_string(),
+            });
+        }
+    };
+
+    for line in content.lines() {
+        if line.starts_with("## ") || line.starts_with("# ") {
+            finish(current.take());
+
+            current = version_in_heading(line)
+                .filter(in_range)
+                .map(|version| (version, Vec::new()));
+        } else if let Some((_, lines)) = &mut current {
+            lines.push(line);
+        }
+    }
+    finish(current);
+
+    entries
+}
+
+/// Find the first word of `heading` that is a version, e.g.
+/// `## [1.2.3] - 2023-01-01` or `## v1.2.3`.
+fn version_in_heading(heading: &str) -> Option<Version> {
+    heading
+        .split(|c: char| c.is_whitespace() || "[]()#".contains(c))
+        .find_map(|word| Version::parse(word.trim_start_matches(['v', 'V'])).ok())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_github_repo() {
+        assert_eq!(
+            github_repo("https://github.com/cargo-bins/cargo-binstall.git"),
+            Some(("cargo-bins".into(), "cargo-binstall".into()))
+        );
+        assert_eq!(github_repo("https://gitlab.com/a/b"), None);
+        assert_eq!(github_repo("https://github.com/a"), None);
+    }
+
+    #[test]
+    fn test_version_from_tag() {
+        let v = |s| Some(Version::parse(s).unwrap());
+
+        assert_eq!(version_from_tag("foo", "v1.2.3"), v("1.2.3"));
+        assert_eq!(version_from_tag("foo", "1.2.3-rc.1"), v("1.2.3-rc.1"));
+        assert_eq!(version_from_tag("foo", "foo-v1.2.3"), v("1.2.3"));
+        assert_eq!(version_from_tag("foo", "foo/1.2.3"), v("1.2.3"));
+        assert_eq!(version_from_tag("foo", "bar-v1.2.3"), None);
+        assert_eq!(version_from_tag("foo", "nightly"), None);
+    }
+
+    #[test]
+    fn test_changelog_sections() {
+        let content = "\
+# Changelog
+
+## [Unreleased]
+
+- wip
+
+## [1.3.0] - 2023-02-01
+
+- Add bar
+
+## v1.2.0
+
+- Add foo
+
+## 1.1.0
+
+- Initial
+";
+        let current = Version::new(1, 1, 0);
+        let new = Version::new(1, 3, 0);
+
+        assert_eq!(
+            changelog_sections(content, &|v| &current < v && v <= &new),
+            [
+                ChangelogEntry {
+                    version: Version::new(1, 3, 0),
+                    notes: "- Add bar".into(),
+                },
+                ChangelogEntry {
+                    version: Version::new(1, 2, 0),
+                    notes: "- Add foo".into(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,135 @@
+//! Events emitted while resolving and installing crates.
+
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use compact_str::CompactString;
+use semver::Version;
+
+use crate::{
+    fetchers::Fetcher,
+    helpers::download::ProgressReporter,
+    manifests::crate_info::{CrateInfo, Environment},
+    ops::resolve::{CrateName, Resolution},
+};
+
+/// Event emitted to the [`ProgressSink`] of [`Options`](super::Options).
+#[non_exhaustive]
+pub enum InstallEvent<'a> {
+    /// Snapshot of the environment, emitted once before installing.
+    Environment { environment: &'a Environment },
+    /// Resolution of the crate has started.
+    Resolving { crate_name: &'a CrateName },
+    /// The fetcher found an artifact and starts downloading it.
+    FetcherFound {
+        crate_name: &'a str,
+        fetcher: &'a dyn Fetcher,
+    },
+    /// `received` out of `total` bytes, if known, of the artifact have been
+    /// downloaded.
+    ///
+    /// It is emitted once the download starts, periodically while
+    /// downloading and once more once it is done.
+    Downloading {
+        crate_name: &'a str,
+        received: u64,
+        total: Option<u64>,
+    },
+    /// An entry of the artifact is taking a while to extract, `written` out
+    /// of `size` bytes of it have been extracted.
+    ///
+    /// It is emitted periodically while the entry is extracted and once
+    /// more once it is done.
+    Extracting {
+        crate_name: &'a str,
+        path: &'a Path,
+        written: u64,
+        size: u64,
+    },
+    /// The artifact of the fetcher has been downloaded and extracted.
+    Downloaded {
+        crate_name: &'a str,
+        fetcher: &'a dyn Fetcher,
+    },
+    /// The binaries extracted from the artifact of the fetcher have been
+    /// verified.
+    Verified {
+        crate_name: &'a str,
+        fetcher: &'a dyn Fetcher,
+    },
+    /// The artifact of the fetcher failed to download, extract or verify,
+    /// the next fetcher is going to be tried.
+    FetchFailed {
+        crate_name: &'a str,
+        fetcher: &'a dyn Fetcher,
+        reason: &'a str,
+    },
+    /// The crate has been resolved.
+    Resolved {
+        crate_name: &'a str,
+        resolution: &'a Resolution,
+    },
+    /// Release notes of a version between the installed version of the
+    /// crate and the version it is upgraded to.
+    Changelog {
+        crate_name: &'a str,
+        version: &'a Version,
+        notes: &'a str,
+    },
+    /// Pre-built binaries of the crate have been installed.
+    Installed { crate_info: &'a CrateInfo },
+    /// The crate has been built and installed by `cargo-install`.
+    InstalledFromSource {
+        crate_name: &'a str,
+        version: &'a str,
+    },
+}
+
+/// Receives the [`InstallEvent`]s.
+pub trait ProgressSink: Send + Sync {
+    fn on_event(&self, event: InstallEvent<'_>);
+}
+
+/// Emits the progress of downloading and extracting the artifact of a crate
+/// as [`InstallEvent`]s.
+pub(crate) struct EventProgressReporter {
+    progress_sink: Arc<dyn ProgressSink>,
+    crate_name: CompactString,
+    total: Mutex<Option<u64>>,
+}
+
+impl EventProgressReporter {
+    pub(crate) fn new(progress_sink: Arc<dyn ProgressSink>, crate_name: CompactString) -> Self {
+        Self {
+            progress_sink,
+            crate_name,
+            total: Mutex::new(None),
+        }
+    }
+}
+
+impl ProgressReporter for EventProgressReporter {
+    fn on_download_start(&self, total: Option<u64>) {
+        *self.total.lock().unwrap() = total;
+        self.on_download_progress(0);
+    }
+
+    fn on_download_progress(&self, received: u64) {
+        self.progress_sink.on_event(InstallEvent::Downloading {
+            crate_name: &self.crate_name,
+            received,
+            total: *self.total.lock().unwrap(),
+        })
+    }
+
+    fn on_entry_progress(&self, path: &Path, written: u64, size: u64) {
+        self.progress_sink.on_event(InstallEvent::Extracting {
+            crate_name: &self.crate_name,
+            path,
+            written,
+            size,
+        })
+    }
+}
@@ -0,0 +1,569 @@
+//! High-level API to resolve, fetch, verify and install crates
+//! programmatically, without going through the `cargo-binstall` CLI.
+
+use std::{
+    collections::BTreeMap,
+    num::{NonZeroU16, NonZeroU64},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use binstalk_manifests::crates_manifests::{Manifests, ManifestsError};
+use compact_str::CompactString;
+use semver::Version;
+use tempfile::TempDir;
+use tokio::task::spawn_blocking;
+use tracing::debug;
+
+#[doc(inline)]
+pub use crate::ops::event::{InstallEvent, ProgressSink};
+
+use crate::{
+    errors::BinstallError,
+    fetchers::{Fetcher, GhCrateMeta, QuickInstall},
+    get_desired_targets,
+    helpers::{
+        gh_api_client::GhApiClient, jobserver_client::LazyJobserverClient, remote::Client,
+        tasks::AutoAbortJoinHandle,
+    },
+    manifests::{
+        cargo_toml_binstall::PkgOverride,
+        crate_info::{CrateInfo, Environment},
+    },
+    ops::{
+        build_cache::BuildCache,
+        patch_elf::PatchElf,
+        resolve::{self, CrateName, Resolution, VersionResolutionHook},
+        CargoTomlFetchOverride, Options, Resolver,
+    },
+    registry::Registry,
+};
+
+/// Strategy for installing a crate, tried in the order specified.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Strategy {
+    /// Download official pre-built artifacts using the information
+    /// provided in `Cargo.toml`.
+    CrateMetaData,
+    /// Query third-party QuickInstall for the crate.
+    QuickInstall,
+    /// Build the crate from the source archive of its release tag.
+    SourceArchive,
+    /// Build the crate from source using `cargo-install`.
+    Compile,
+}
+
+/// Builder for [`Installer`].
+pub struct InstallerBuilder {
+    install_path: PathBuf,
+    cargo_root: Option<PathBuf>,
+    manifest_path: Option<PathBuf>,
+    client: Option<Client>,
+    github_token: Option<CompactString>,
+    strategies: Vec<Strategy>,
+    targets: Option<Vec<String>>,
+    registry: Registry,
+    overrides: PkgOverride,
+    no_symlinks: bool,
+    dry_run: bool,
+    force: bool,
+    locked: bool,
+    no_track: bool,
+    deterministic: bool,
+    version_resolution_hook: Option<Arc<dyn VersionResolutionHook>>,
+    progress_sink: Option<Arc<dyn ProgressSink>>,
+    quarantine_dir: Option<PathBuf>,
+    patch_elf: Option<PatchElf>,
+    build_env: BTreeMap<CompactString, BTreeMap<CompactString, CompactString>>,
+    build_cache: Option<BuildCache>,
+}
+
+impl InstallerBuilder {
+    /// * `install_path` - directory where the binaries are installed to.
+    pub fn new(install_path: impl Into<PathBuf>) -> Self {
+        Self {
+            install_path: install_path.into(),
+            cargo_root: None,
+            manifest_path: None,
+            client: None,
+            github_token: None,
+            strategies: vec![
+                Strategy::CrateMetaData,
+                Strategy::QuickInstall,
+                Strategy::Compile,
+            ],
+            targets: None,
+            registry: Registry::default(),
+            overrides: PkgOverride::default(),
+            no_symlinks: false,
+            dry_run: false,
+            force: false,
+            locked: false,
+            no_track: false,
+            deterministic: false,
+            version_resolution_hook: None,
+            progress_sink: None,
+            quarantine_dir: None,
+            patch_elf: None,
+            build_env: BTreeMap::new(),
+            build_cache: None,
+        }
+    }
+
+    /// Root passed to `cargo-install` when building from source, in which
+    /// the crates installed are recorded unless `no_track` is set.
+    pub fn cargo_root(mut self, cargo_root: impl Into<PathBuf>) -> Self {
+        self.cargo_root = Some(cargo_root.into());
+        self
+    }
+
+    /// Load the manifests of the crates from the crate or workspace at
+    /// `manifest_path`, instead of the registry.
+    pub fn manifest_path(mut self, manifest_path: impl Into<PathBuf>) -> Self {
+        self.manifest_path = Some(manifest_path.into());
+        self
+    }
+
+    /// Use this client for all requests, by default a client with
+    /// default rate limit and no extra root certificates is created.
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    pub fn github_token(mut self, github_token: impl Into<CompactString>) -> Self {
+        self.github_token = Some(github_token.into());
+        self
+    }
+
+    /// Strategies tried in order, `Compile` and `SourceArchive` are
+    /// always tried after the others.
+    ///
+    /// Default to `CrateMetaData`, `QuickInstall` and `Compile`.
+    pub fn strategies(mut self, strategies: impl IntoIterator<Item = Strategy>) -> Self {
+        self.strategies = strategies.into_iter().collect();
+        self
+    }
+
+    /// Targets to look for, in order of preference, detected from the
+    /// host by default.
+    pub fn targets(mut self, targets: Vec<String>) -> Self {
+        self.targets = Some(targets);
+        self
+    }
+
+    pub fn registry(mut self, registry: Registry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Overrides of `pkg-url`, `pkg-fmt` and `bin-dir` for all crates.
+    pub fn overrides(mut self, overrides: PkgOverride) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    pub fn no_symlinks(mut self, no_symlinks: bool) -> Self {
+        self.no_symlinks = no_symlinks;
+        self
+    }
+
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    pub fn locked(mut self, locked: bool) -> Self {
+        self.locked = locked;
+        self
+    }
+
+    pub fn no_track(mut self, no_track: bool) -> Self {
+        self.no_track = no_track;
+        self
+    }
+
+    /// Require the versions of the crates to be pinned and pick artifacts
+    /// regardless of timing, so that the resolution is reproducible.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    pub fn version_resolution_hook(mut self, hook: Arc<dyn VersionResolutionHook>) -> Self {
+        self.version_resolution_hook = Some(hook);
+        self
+    }
+
+    pub fn progress_sink(mut self, progress_sink: Arc<dyn ProgressSink>) -> Self {
+        self.progress_sink = Some(progress_sink);
+        self
+    }
+
+    /// Move the artifacts failing verification into `quarantine_dir`,
+    /// instead of deleting them.
+    pub fn quarantine_dir(mut self, quarantine_dir: impl Into<PathBuf>) -> Self {
+        self.quarantine_dir = Some(quarantine_dir.into());
+        self
+    }
+
+    /// Rewrite the interpreter and the rpath of the binaries before they
+    /// are installed, see [`PatchElf`].
+    pub fn patch_elf(mut self, patch_elf: PatchElf) -> Self {
+        self.patch_elf = Some(patch_elf);
+        self
+    }
+
+    /// Set the environment variables `vars` when building `crate_name`
+    /// from source, e.g. `OPENSSL_DIR` for crates linking to OpenSSL.
+    pub fn build_env(
+        mut self,
+        crate_name: impl Into<CompactString>,
+        vars: impl IntoIterator<Item = (CompactString, CompactString)>,
+    ) -> Self {
+        self.build_env
+            .entry(crate_name.into())
+            .or_default()
+            .extend(vars);
+        self
+    }
+
+    /// Reuse the binaries built from source in `build_cache`, and cache the
+    /// ones built.
+    pub fn build_cache(mut self, build_cache: BuildCache) -> Self {
+        self.build_cache = Some(build_cache);
+        self
+    }
+
+    /// Create the [`Installer`], this also creates a temporary directory
+    /// inside `install_path` and starts detecting targets if they are not
+    /// specified.
+    ///
+    /// This must be called inside a tokio runtime.
+    pub fn build(self) -> Result<Installer, BinstallError> {
+        let client = match self.client {
+            Some(client) => client,
+            None => Client::new(
+                concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")),
+                None,
+                NonZeroU16::new(10).unwrap(),
+                NonZeroU64::new(1).unwrap(),
+                [],
+            )?,
+        };
+        let gh_api_client = GhApiClient::new(client.clone(), self.github_token);
+
+        let mut source_archive_fallback = false;
+        let mut cargo_install_fallback = false;
+
+        let resolvers = self
+            .strategies
+            .into_iter()
+            .filter_map(|strategy| match strategy {
+                Strategy::CrateMetaData => Some(GhCrateMeta::new as Resolver),
+                Strategy::QuickInstall => Some(QuickInstall::new as Resolver),
+                Strategy::SourceArchive => {
+                    source_archive_fallback = true;
+                    None
+                }
+                Strategy::Compile => {
+                    cargo_install_fallback = true;
+                    None
+                }
+            })
+            .collect();
+
+        std::fs::create_dir_all(&self.install_path)?;
+        let temp_dir = tempfile::Builder::new()
+            .prefix("cargo-binstall")
+            .tempdir_in(&self.install_path)?;
+
+        let opts = Options {
+            no_symlinks: self.no_symlinks,
+            dry_run: self.dry_run,
+            force: self.force,
+            quiet: false,
+            locked: self.locked,
+            no_track: self.no_track,
+            deterministic: self.deterministic,
+
+            version_req: None,
+            cargo_toml_fetch_override: self.manifest_path.map(CargoTomlFetchOverride::Path),
+            #[cfg(feature = "git")]
+            git_options: Default::default(),
+            #[cfg(feature = "git")]
+            git_full_checkout: false,
+            cli_overrides: self.overrides,
+
+            desired_targets: get_desired_targets(self.targets),
+            resolvers,
+            source_archive_fallback,
+            cargo_install_fallback,
+            build_env: self.build_env,
+            build_cache: self.build_cache,
+
+            temp_dir: temp_dir.path().to_owned(),
+            install_path: self.install_path,
+            cargo_root: self.cargo_root.clone(),
+
+            client,
+            gh_api_client,
+            jobserver_client: LazyJobserverClient::new(),
+            registry: self.registry,
+            version_resolution_hook: self.version_resolution_hook,
+            progress_sink: self.progress_sink,
+            quarantine_dir: self.quarantine_dir,
+            patch_elf: self.patch_elf,
+            keep_artifacts: false,
+        };
+
+        Ok(Installer {
+            opts: Arc::new(opts),
+            cargo_roots: self.cargo_root,
+            _temp_dir: Some(temp_dir),
+        })
+    }
+}
+
+/// What became of a crate passed to [`Installer::install`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum InstallOutcome {
+    /// Installed from pre-built binaries, or from the binaries of the build
+    /// cache, and recorded unless `no_track` is set.
+    Installed(Box<CrateInfo>),
+    /// Built from source by `cargo-install`, which records it itself.
+    Compiled { version: CompactString },
+    /// The version installed is the latest matching the version
+    /// requirement.
+    AlreadyUpToDate,
+    /// Pre-built binaries are found, but not installed since `dry_run` is
+    /// set.
+    DryRun,
+}
+
+/// Context of [`Installer::install_with`].
+#[derive(Debug, Default)]
+pub struct InstallContext {
+    /// Versions of the crates installed outside of the cargo root, e.g. in
+    /// a system root, which are only updated if a newer version is found.
+    pub system_crates: BTreeMap<CompactString, Version>,
+    /// Environment recorded along the crates installed.
+    pub environment: Option<Environment>,
+}
+
+/// Resolve, fetch, verify and install crates.
+///
+/// The crates installed are recorded in `.crates.toml` and
+/// `binstall/crates-v1.json` of the cargo root, unless `no_track` is set.
+pub struct Installer {
+    opts: Arc<Options>,
+    cargo_roots: Option<PathBuf>,
+    _temp_dir: Option<TempDir>,
+}
+
+impl Installer {
+    pub fn builder(install_path: impl Into<PathBuf>) -> InstallerBuilder {
+        InstallerBuilder::new(install_path)
+    }
+
+    /// Create an installer sharing `opts`, which records the crates
+    /// installed in `cargo_roots`.
+    ///
+    /// `opts.temp_dir` must outlive the installer.
+    pub fn from_options(opts: Arc<Options>, cargo_roots: Option<PathBuf>) -> Self {
+        Self {
+            opts,
+            cargo_roots,
+            _temp_dir: None,
+        }
+    }
+
+    pub fn options(&self) -> &Arc<Options> {
+        &self.opts
+    }
+
+    /// Install the latest version matching the version requirement of
+    /// each crate, return what became of each of them, by name.
+    ///
+    /// A crate failing to resolve or install does not stop the others,
+    /// only failing to load or update the records of the crates installed
+    /// is an error.
+    pub async fn install(
+        &self,
+        crate_names: Vec<CrateName>,
+    ) -> Result<Vec<(CompactString, Result<InstallOutcome, BinstallError>)>, BinstallError> {
+        self.install_with(crate_names, InstallContext::default())
+            .await
+    }
+
+    /// Same as [`Installer::install`], in `context`.
+    pub async fn install_with(
+        &self,
+        crate_names: Vec<CrateName>,
+        context: InstallContext,
+    ) -> Result<Vec<(CompactString, Result<InstallOutcome, BinstallError>)>, BinstallError> {
+        let (manifests, mut installed_crates) = match &self.cargo_roots {
+            Some(cargo_roots) if !self.opts.no_track => {
+                let cargo_roots = cargo_roots.clone();
+                spawn_blocking(move || {
+                    let mut manifests = Manifests::open_exclusive(&cargo_roots)?;
+                    let installed_crates = manifests.load_installed_crates()?;
+                    Ok::<_, ManifestsError>((Some(manifests), installed_crates))
+                })
+                .await??
+            }
+            _ => (None, BTreeMap::new()),
+        };
+        // Crates installed in the cargo root take precedence.
+        for (name, version) in context.system_crates {
+            installed_crates.entry(name).or_insert(version);
+        }
+
+        let tasks: Vec<_> = CrateName::dedup(crate_names)
+            .map(|crate_name| {
+                let curr_version = installed_crates
+                    .remove(&crate_name.name)
+                    .filter(|_| !self.opts.force);
+                (
+                    crate_name.name.clone(),
+                    AutoAbortJoinHandle::spawn(resolve::resolve(
+                        self.opts.clone(),
+                        crate_name,
+                        curr_version,
+                    )),
+                )
+            })
+            .collect();
+
+        let mut outcomes = Vec::with_capacity(tasks.len());
+        let mut crate_infos = Vec::new();
+
+        for (name, task) in tasks {
+            let outcome = match task.flattened_join().await {
+                Ok(resolution) => self
+                    .install_resolution(resolution, &context.environment)
+                    .await
+                    .map_err(|err| err.crate_context(name.clone())),
+                Err(err) => Err(err),
+            };
+            if let Ok(InstallOutcome::Installed(crate_info)) = &outcome {
+                crate_infos.push(CrateInfo::clone(crate_info));
+            }
+            outcomes.push((name, outcome));
+        }
+
+        if let Some(manifests) = manifests {
+            spawn_blocking(move || manifests.update(crate_infos)).await??;
+        }
+
+        Ok(outcomes)
+    }
+
+    async fn install_resolution(
+        &self,
+        resolution: Resolution,
+        environment: &Option<Environment>,
+    ) -> Result<InstallOutcome, BinstallError> {
+        let mut crate_info = match resolution {
+            Resolution::Fetch(fetch) => {
+                if self.opts.dry_run {
+                    debug!("Dry-run: not installing {}", fetch.name);
+                    return Ok(InstallOutcome::DryRun);
+                }
+
+                let opts = self.opts.clone();
+                spawn_blocking(move || fetch.install(&opts)).await??
+            }
+            Resolution::InstallFromSource(source) => {
+                let version = source.version.clone();
+                match source.install(self.opts.clone()).await? {
+                    Some(crate_info) => crate_info,
+                    None => return Ok(InstallOutcome::Compiled { version }),
+                }
+            }
+            Resolution::AlreadyUpToDate => return Ok(InstallOutcome::AlreadyUpToDate),
+        };
+
+        crate_info.environment = environment.clone();
+        Ok(InstallOutcome::Installed(Box::new(crate_info)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use url::Url;
+
+    use super::*;
+
+    const TARGET: &str = "x86_64-unknown-linux-gnu";
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_install_file_artifact() {
+        let dir = tempfile::tempdir().unwrap();
+        let artifacts_url = Url::from_directory_path(dir.path()).unwrap();
+        fs::write(dir.path().join(format!("foo-{TARGET}")), b"foo").unwrap();
+
+        let crate_dir = dir.path().join("foo");
+        fs::create_dir_all(crate_dir.join("src")).unwrap();
+        fs::write(crate_dir.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(
+            crate_dir.join("Cargo.toml"),
+            format!(
+                r#"
+[package]
+name = "foo"
+version = "0.1.0"
+
+[package.metadata.binstall]
+pkg-url = "{artifacts_url}foo-{{ target }}"
+pkg-fmt = "bin"
+"#
+            ),
+        )
+        .unwrap();
+
+        let cargo_root = dir.path().join("root");
+        let installer = Installer::builder(cargo_root.join("bin"))
+            .cargo_root(&cargo_root)
+            .manifest_path(crate_dir.join("Cargo.toml"))
+            .strategies([Strategy::CrateMetaData])
+            .targets(vec![TARGET.to_string()])
+            .no_symlinks(true)
+            .build()
+            .unwrap();
+        let crate_names = || vec!["foo".parse().unwrap(), "bar".parse().unwrap()];
+
+        let outcomes = installer.install(crate_names()).await.unwrap();
+        assert_eq!(outcomes.len(), 2);
+        // bar is not in the workspace, which does not stop foo.
+        let (name, outcome) = &outcomes[0];
+        assert_eq!(*name, "bar");
+        assert!(outcome.is_err());
+        let (name, outcome) = &outcomes[1];
+        assert_eq!(*name, "foo");
+        assert!(
+            matches!(outcome, Ok(InstallOutcome::Installed(crate_info)) if crate_info.version_req == "*")
+        );
+        assert_eq!(fs::read(cargo_root.join("bin/foo")).unwrap(), b"foo");
+
+        let mut manifests = Manifests::open_exclusive(&cargo_root).unwrap();
+        assert_eq!(
+            manifests.load_installed_crates().unwrap()["foo"],
+            Version::new(0, 1, 0)
+        );
+        assert!(manifests.installed_crate_info("foo").is_some());
+        drop(manifests);
+
+        let outcomes = installer.install(crate_names()).await.unwrap();
+        assert!(matches!(outcomes[1].1, Ok(InstallOutcome::AlreadyUpToDate)));
+    }
+}
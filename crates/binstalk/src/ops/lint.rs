@@ -0,0 +1,673 @@
+//! Static checks of the `[package.metadata.binstall]` of a crate, so that
+//! mistakes are caught before it is published.
+
+use std::{borrow::Cow, iter, path::Path, str::FromStr};
+
+use leon::{Template, Values};
+
+use crate::{
+    bins,
+    errors::BinstallError,
+    fetchers::FetchError,
+    helpers::{
+        cargo_toml::{Error as CargoTomlError, Manifest, Package, Value},
+        target_triple::TargetTriple,
+    },
+    manifests::cargo_toml_binstall::{PkgFmt, PkgMeta},
+};
+
+/// Keys of `[package.metadata.binstall]`.
+const META_KEYS: &[&str] = &[
+    "pkg-url",
+    "pkg-fmt",
+    "bin-dir",
+    "pub-key",
+    "tag-prefix",
+    "asset-name",
+    "nested-fmt",
+    "split-parts",
+    "split-style",
+    "bin-dest",
+    "bin-launchers",
+    "flavor",
+    "overrides",
+];
+
+/// Keys of `[package.metadata.binstall.overrides.<target>]`.
+const OVERRIDE_KEYS: &[&str] = &["pkg-url", "pkg-fmt", "bin-dir", "bin-dest", "flavor"];
+
+/// Keys derived from the target, available in all templates.
+const TARGET_KEYS: &[&str] = &[
+    "target-family",
+    "target-arch",
+    "target-libc",
+    "target-vendor",
+];
+
+/// Keys available in `pkg-url`.
+const PKG_URL_KEYS: &[&str] = &[
+    "name",
+    "repo",
+    "target",
+    "version",
+    "archive-format",
+    "format",
+    "archive-suffix",
+    "binary-ext",
+    "subcrate",
+    "asset-name",
+    "tag",
+    "flavor",
+];
+
+/// Keys available in `bin-dir`.
+const BIN_DIR_KEYS: &[&str] = &[
+    "name",
+    "repo",
+    "target",
+    "version",
+    "bin",
+    "binary-ext",
+    "format",
+    "flavor",
+];
+
+/// A problem found in `[package.metadata.binstall]`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Lint {
+    /// Path of the offending key, relative to `package.metadata.binstall`,
+    /// e.g. `overrides.x86_64-pc-windows-msvc.pkg-url`.
+    pub key: String,
+    pub message: String,
+}
+
+impl Lint {
+    fn new(key: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Package fields and metadata used to expand the templates.
+pub struct TemplateInput<'a> {
+    pub name: &'a str,
+    pub version: &'a str,
+    pub repo: Option<&'a str>,
+    /// Names of the binaries of the crate.
+    pub bins: Vec<&'a str>,
+    /// Metadata, with the overrides of the target merged in.
+    pub meta: &'a PkgMeta,
+}
+
+/// Templates of a crate expanded for a target.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Expansion {
+    pub target: String,
+    /// Urls `pkg-url` expands to, one for each archive extension of
+    /// `pkg-fmt` if `pkg-url` contains the archive format.
+    ///
+    /// Empty if `pkg-url` is not specified.
+    pub pkg_urls: Vec<String>,
+    /// Paths of the binaries in the archive, `bin-dir` expanded for each
+    /// binary.
+    ///
+    /// Empty if `bin-dir` is not specified.
+    pub bin_paths: Vec<String>,
+}
+
+struct Context<'c> {
+    input: &'c TemplateInput<'c>,
+    target: &'c str,
+    target_related_info: &'c TargetTriple,
+    archive_suffix: Option<&'c str>,
+    tag: Option<String>,
+    bin: Option<&'c str>,
+}
+
+impl Values for Context<'_> {
+    fn get_value<'s>(&'s self, key: &str) -> Option<Cow<'s, str>> {
+        let binary_ext = if self.target.contains("windows") {
+            ".exe"
+        } else {
+            ""
+        };
+
+        match key {
+            "name" => Some(Cow::Borrowed(self.input.name)),
+            "repo" => self.input.repo.map(Cow::Borrowed),
+            "target" => Some(Cow::Borrowed(self.target)),
+            "version" => Some(Cow::Borrowed(self.input.version)),
+            "archive-format" => self
+                .archive_suffix
+                .map(|suffix| Cow::Borrowed(suffix.strip_prefix('.').unwrap_or("bin"))),
+            "archive-suffix" => self.archive_suffix.map(Cow::Borrowed),
+            "binary-ext" => Some(Cow::Borrowed(binary_ext)),
+            // `format` is an alias of `archive-format` in `pkg-url`, but of
+            // `binary-ext` in `bin-dir`.
+            "format" => match self.bin {
+                Some(_) => Some(Cow::Borrowed(binary_ext)),
+                None => self
+                    .archive_suffix
+                    .map(|suffix| Cow::Borrowed(suffix.strip_prefix('.').unwrap_or("bin"))),
+            },
+            "asset-name" => Some(Cow::Borrowed(
+                self.input
+                    .meta
+                    .asset_name
+                    .as_deref()
+                    .unwrap_or(self.input.name),
+            )),
+            "tag" => self.tag.as_deref().map(Cow::Borrowed),
+            "flavor" => self.input.meta.flavor.as_deref().map(Cow::Borrowed),
+            "bin" => self.bin.map(Cow::Borrowed),
+            key => self.target_related_info.get_value(key),
+        }
+    }
+}
+
+/// Expand `pkg-url` and `bin-dir` of `input` for `target`, as binstall
+/// would when looking for the artifact of the target.
+pub fn expand_templates(
+    input: &TemplateInput<'_>,
+    target: &str,
+) -> Result<Expansion, BinstallError> {
+    let target_related_info = TargetTriple::from_str(target)?;
+    let is_windows = target.contains("windows");
+    let tag = input
+        .meta
+        .tag_prefix
+        .as_deref()
+        .map(|tag_prefix| format!("{tag_prefix}{}", input.version));
+
+    let mut ctx = Context {
+        input,
+        target,
+        target_related_info: &target_related_info,
+        archive_suffix: None,
+        tag,
+        bin: None,
+    };
+
+    let mut pkg_urls = Vec::new();
+    if let Some(pkg_url) = input.meta.pkg_url.as_deref() {
+        let template = Template::parse(pkg_url)?;
+
+        let pkg_fmt = input
+            .meta
+            .pkg_fmt
+            .or_else(|| PkgFmt::guess_pkg_format(pkg_url))
+            .unwrap_or_default();
+
+        if template.has_any_of_keys(&["format", "archive-format", "archive-suffix"]) {
+            for ext in pkg_fmt.extensions(is_windows) {
+                ctx.archive_suffix = Some(ext);
+                pkg_urls.push(
+                    template
+                        .render(&ctx)
+                        .map_err(|err| BinstallError::from(FetchError::from(err)))?,
+                );
+            }
+            ctx.archive_suffix = None;
+        } else {
+            pkg_urls.push(
+                template
+                    .render(&ctx)
+                    .map_err(|err| BinstallError::from(FetchError::from(err)))?,
+            );
+        }
+    }
+
+    let mut bin_paths = Vec::new();
+    if let Some(bin_dir) = input.meta.bin_dir.as_deref() {
+        let template = Template::parse(bin_dir)?;
+
+        for bin in &input.bins {
+            ctx.bin = Some(bin);
+            bin_paths.push(
+                template
+                    .render(&ctx)
+                    .map_err(|err| BinstallError::from(bins::Error::from(err)))?,
+            );
+        }
+    }
+
+    Ok(Expansion {
+        target: target.to_string(),
+        pkg_urls,
+        bin_paths,
+    })
+}
+
+struct PackageFields<'a> {
+    name: &'a str,
+    version: &'a str,
+    repo: Option<&'a str>,
+    bins: Vec<&'a str>,
+}
+
+fn package_fields<'a>(
+    manifest: &'a Manifest<Value>,
+    manifest_path: &Path,
+) -> Result<(&'a Package<Value>, PackageFields<'a>), BinstallError> {
+    let package = manifest.package.as_ref().ok_or_else(|| {
+        BinstallError::CargoTomlMissingPackage(manifest_path.display().to_string().into())
+    })?;
+
+    let name = package.name.as_str();
+    let version = package.version.get().map(String::as_str).unwrap_or("0.0.0");
+    let repo = package
+        .repository
+        .as_ref()
+        .and_then(|repo| repo.get().ok())
+        .map(String::as_str);
+    let mut bins: Vec<&str> = manifest
+        .bin
+        .iter()
+        .filter_map(|bin| bin.name.as_deref())
+        .collect();
+    if bins.is_empty() {
+        bins.push(name);
+    }
+
+    Ok((
+        package,
+        PackageFields {
+            name,
+            version,
+            repo,
+            bins,
+        },
+    ))
+}
+
+/// Package fields and `[package.metadata.binstall]` of a `Cargo.toml`.
+pub struct PackageTemplates {
+    pub name: String,
+    pub version: String,
+    pub repo: Option<String>,
+    pub bins: Vec<String>,
+    pub meta: PkgMeta,
+}
+
+/// Load the package fields and metadata used by templates from the
+/// `Cargo.toml` at `manifest_path`.
+///
+/// This is a blocking function.
+pub fn load_package_templates(manifest_path: &Path) -> Result<PackageTemplates, BinstallError> {
+    let manifest = Manifest::<Value>::from_path_with_metadata(manifest_path)?;
+    let (package, fields) = package_fields(&manifest, manifest_path)?;
+
+    let meta = package
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.get("binstall"))
+        .map(|binstall| binstall.clone().try_into::<PkgMeta>())
+        .transpose()
+        .map_err(CargoTomlError::from)?
+        .unwrap_or_default();
+
+    Ok(PackageTemplates {
+        name: fields.name.to_string(),
+        version: fields.version.to_string(),
+        repo: fields.repo.map(ToString::to_string),
+        bins: fields.bins.into_iter().map(ToString::to_string).collect(),
+        meta,
+    })
+}
+
+/// Result of [`lint_metadata`].
+pub struct LintReport {
+    pub lints: Vec<Lint>,
+    /// Templates expanded for each of the targets requested, in the same
+    /// order. Targets failing to expand are reported in `lints` instead.
+    pub expansions: Vec<Expansion>,
+}
+
+/// Check `[package.metadata.binstall]` of the `Cargo.toml` at
+/// `manifest_path` for unknown keys, unavailable template keys and
+/// conflicting overrides, then expand the templates for `targets`.
+///
+/// This is a blocking function.
+pub fn lint_metadata(
+    manifest_path: &Path,
+    targets: &[String],
+) -> Result<LintReport, BinstallError> {
+    let manifest = Manifest::<Value>::from_path_with_metadata(manifest_path)?;
+    let (package, fields) = package_fields(&manifest, manifest_path)?;
+    let PackageFields {
+        name,
+        version,
+        repo,
+        bins,
+    } = fields;
+
+    let Some(binstall) = package
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.get("binstall"))
+    else {
+        return Ok(LintReport {
+            lints: Vec::new(),
+            expansions: Vec::new(),
+        });
+    };
+
+    let mut lints = lint_keys(binstall);
+
+    let meta: PkgMeta = match binstall.clone().try_into() {
+        Ok(meta) => meta,
+        Err(err) => {
+            lints.push(Lint::new("", err.to_string()));
+            return Ok(LintReport {
+                lints,
+                expansions: Vec::new(),
+            });
+        }
+    };
+
+    lints.extend(lint_meta(&meta, repo.is_some()));
+
+    let mut expansions = Vec::with_capacity(targets.len());
+    for target in targets {
+        let meta = meta.merge_overrides(meta.overrides.get(target));
+        let input = TemplateInput {
+            name,
+            version,
+            repo,
+            bins: bins.clone(),
+            meta: &meta,
+        };
+
+        match expand_templates(&input, target) {
+            Ok(expansion) => expansions.push(expansion),
+            Err(err) => lints.push(Lint::new(
+                format!("overrides.{target}"),
+                format!("Failed to expand templates for target {target}: {err}"),
+            )),
+        }
+    }
+
+    Ok(LintReport { lints, expansions })
+}
+
+/// Check for keys binstall does not know about, typically typos.
+fn lint_keys(binstall: &Value) -> Vec<Lint> {
+    let mut lints = Vec::new();
+
+    let Some(table) = binstall.as_table() else {
+        lints.push(Lint::new("", "Expected a table"));
+        return lints;
+    };
+
+    for key in table.keys() {
+        if !META_KEYS.contains(&key.as_str()) {
+            lints.push(Lint::new(key.as_str(), "Unknown key"));
+        }
+    }
+
+    if let Some(overrides) = table.get("overrides").and_then(Value::as_table) {
+        for (target, pkg_override) in overrides {
+            for key in pkg_override.as_table().into_iter().flat_map(|t| t.keys()) {
+                if !OVERRIDE_KEYS.contains(&key.as_str()) {
+                    lints.push(Lint::new(
+                        format!("overrides.{target}.{key}"),
+                        "Unknown key, only pkg-url, pkg-fmt, bin-dir, bin-dest and flavor can be overridden",
+                    ));
+                }
+            }
+        }
+    }
+
+    lints
+}
+
+/// Check the templates and the overrides.
+fn lint_meta(meta: &PkgMeta, has_repo: bool) -> Vec<Lint> {
+    let mut lints = Vec::new();
+
+    let mut lint_template = |key: String, template: &str, keys: &[&str]| {
+        let template = match Template::parse(template) {
+            Ok(template) => template,
+            Err(err) => {
+                lints.push(Lint::new(key, format!("Invalid template: {err}")));
+                return;
+            }
+        };
+
+        for template_key in template.keys() {
+            let message = if !keys.contains(template_key) && !TARGET_KEYS.contains(template_key) {
+                format!("Unknown template key `{template_key}`")
+            } else if *template_key == "repo" && !has_repo {
+                "Template key `repo` is used but the package does not specify `repository`"
+                    .to_string()
+            } else if *template_key == "tag" && meta.tag_prefix.is_none() {
+                "Template key `tag` is used but `tag-prefix` is not specified".to_string()
+            } else if *template_key == "flavor"
+                && meta.flavor.is_none()
+                && meta.overrides.values().all(|o| o.flavor.is_none())
+            {
+                "Template key `flavor` is used but `flavor` is not specified".to_string()
+            } else if *template_key == "subcrate" && !has_repo {
+                "Template key `subcrate` is used but the package does not specify `repository`"
+                    .to_string()
+            } else {
+                continue;
+            };
+
+            lints.push(Lint::new(key.clone(), message));
+        }
+    };
+
+    let overrides = meta
+        .overrides
+        .iter()
+        .map(|(target, pkg_override)| (format!("overrides.{target}."), pkg_override));
+
+    if let Some(pkg_url) = &meta.pkg_url {
+        lint_template("pkg-url".to_string(), pkg_url, PKG_URL_KEYS);
+    }
+    if let Some(bin_dir) = &meta.bin_dir {
+        lint_template("bin-dir".to_string(), bin_dir, BIN_DIR_KEYS);
+    }
+    for (bin, bin_dest) in &meta.bin_dest {
+        lint_template(format!("bin-dest.{bin}"), bin_dest, BIN_DIR_KEYS);
+    }
+    for (prefix, pkg_override) in overrides.clone() {
+        if let Some(pkg_url) = &pkg_override.pkg_url {
+            lint_template(format!("{prefix}pkg-url"), pkg_url, PKG_URL_KEYS);
+        }
+        if let Some(bin_dir) = &pkg_override.bin_dir {
+            lint_template(format!("{prefix}bin-dir"), bin_dir, BIN_DIR_KEYS);
+        }
+        for (bin, bin_dest) in &pkg_override.bin_dest {
+            lint_template(format!("{prefix}bin-dest.{bin}"), bin_dest, BIN_DIR_KEYS);
+        }
+    }
+
+    if meta.pkg_url.is_none() && !has_repo {
+        lints.push(Lint::new(
+            "pkg-url",
+            "Neither pkg-url nor the repository of the package is specified, \
+            binstall cannot find the artifacts",
+        ));
+    }
+
+    for (target, pkg_override) in &meta.overrides {
+        let prefix = format!("overrides.{target}.");
+
+        if TargetTriple::from_str(target).is_err() {
+            lints.push(Lint::new(
+                format!("overrides.{target}"),
+                "Not a valid target triple, this override is never used",
+            ));
+        }
+
+        for (key, overridden, base) in [
+            (
+                "pkg-url",
+                pkg_override.pkg_url.as_deref(),
+                meta.pkg_url.as_deref(),
+            ),
+            (
+                "bin-dir",
+                pkg_override.bin_dir.as_deref(),
+                meta.bin_dir.as_deref(),
+            ),
+            (
+                "flavor",
+                pkg_override.flavor.as_deref(),
+                meta.flavor.as_deref(),
+            ),
+        ] {
+            if overridden.is_some() && overridden == base {
+                lints.push(Lint::new(
+                    format!("{prefix}{key}"),
+                    "Same as the value it overrides",
+                ));
+            }
+        }
+        if pkg_override.pkg_fmt.is_some() && pkg_override.pkg_fmt == meta.pkg_fmt {
+            lints.push(Lint::new(
+                format!("{prefix}pkg-fmt"),
+                "Same as the value it overrides",
+            ));
+        }
+    }
+
+    // The pkg-fmt must match the extension hardcoded in pkg-url, for the
+    // base metadata and each target with overrides.
+    for (prefix, merged) in iter::once((String::new(), meta.clone())).chain(
+        overrides
+            .map(|(prefix, pkg_override)| (prefix, meta.merge_overrides(iter::once(pkg_override)))),
+    ) {
+        let (Some(pkg_url), Some(pkg_fmt)) = (&merged.pkg_url, merged.pkg_fmt) else {
+            continue;
+        };
+
+        if let Some(guessed) = PkgFmt::guess_pkg_format(pkg_url) {
+            if guessed != pkg_fmt {
+                lints.push(Lint::new(
+                    format!("{prefix}pkg-fmt"),
+                    format!("pkg-fmt is {pkg_fmt} but pkg-url ends with a {guessed} extension"),
+                ));
+            }
+        }
+    }
+
+    lints
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn test_lint_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest_path = dir.path().join("Cargo.toml");
+        fs::create_dir(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(
+            &manifest_path,
+            r#"
+[package]
+name = "foo"
+version = "1.2.3"
+
+[package.metadata.binstall]
+pkg-url = "https://example.com/{ name }-{ tag }-{ target }.zip"
+pkg-fmt = "tgz"
+pkg-urll = "typo"
+
+[package.metadata.binstall.overrides.x86_64-pc-windows-msvc]
+pkg-fmt = "tgz"
+bin-dir = "{ bin }{ binary-ext }"
+"#,
+        )
+        .unwrap();
+
+        let report =
+            lint_metadata(&manifest_path, &["x86_64-pc-windows-msvc".to_string()]).unwrap();
+        let keys: Vec<_> = report.lints.iter().map(|lint| lint.key.as_str()).collect();
+
+        assert_eq!(
+            keys,
+            [
+                "pkg-urll",
+                "pkg-url",
+                "overrides.x86_64-pc-windows-msvc.pkg-fmt",
+                "pkg-fmt",
+                "overrides.x86_64-pc-windows-msvc.pkg-fmt",
+                "overrides.x86_64-pc-windows-msvc",
+            ],
+            "{:#?}",
+            report.lints
+        );
+        assert!(report.expansions.is_empty());
+    }
+
+    #[test]
+    fn test_expand_templates() {
+        let meta = PkgMeta {
+            pkg_url: Some(
+                "{ repo }/releases/download/v{ version }/{ name }-{ target }{ archive-suffix }"
+                    .to_string(),
+            ),
+            pkg_fmt: Some(PkgFmt::Zip),
+            bin_dir: Some("{ name }-{ target }/{ bin }{ binary-ext }".to_string()),
+            ..Default::default()
+        };
+        let input = TemplateInput {
+            name: "foo",
+            version: "1.2.3",
+            repo: Some("https://github.com/foo/foo"),
+            bins: vec!["foo"],
+            meta: &meta,
+        };
+
+        assert_eq!(
+            expand_templates(&input, "x86_64-pc-windows-msvc").unwrap(),
+            Expansion {
+                target: "x86_64-pc-windows-msvc".to_string(),
+                pkg_urls: vec![
+                    "https://github.com/foo/foo/releases/download/v1.2.3/foo-x86_64-pc-windows-msvc.zip".to_string()
+                ],
+                bin_paths: vec!["foo-x86_64-pc-windows-msvc/foo.exe".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_lint_flavor() {
+        use crate::manifests::cargo_toml_binstall::PkgOverride;
+
+        let mut meta = PkgMeta {
+            pkg_url: Some("https://example.com/{ name }-{ flavor }-{ target }.tgz".to_string()),
+            ..Default::default()
+        };
+        let keys = |meta: &PkgMeta| {
+            lint_meta(meta, true)
+                .into_iter()
+                .map(|lint| lint.key)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(keys(&meta), ["pkg-url"]);
+
+        meta.overrides.insert(
+            "x86_64-unknown-linux-musl".to_string(),
+            PkgOverride {
+                flavor: Some("static".to_string()),
+                ..Default::default()
+            },
+        );
+        assert!(keys(&meta).is_empty());
+
+        meta.flavor = Some("static".to_string());
+        assert_eq!(keys(&meta), ["overrides.x86_64-unknown-linux-musl.flavor"]);
+    }
+}
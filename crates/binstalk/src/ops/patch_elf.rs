@@ -0,0 +1,461 @@
+//! Rewrite the interpreter and the rpath of the ELF binaries installed, for
+//! hosts with libraries in non-standard locations.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+};
+
+use thiserror::Error as ThisError;
+use tracing::{debug, info};
+
+#[derive(Debug, ThisError)]
+#[non_exhaustive]
+pub enum PatchElfError {
+    #[error("malformed ELF file")]
+    Malformed,
+
+    #[error("the new {0} is longer than the existing one and patchelf is not installed")]
+    DoesNotFit(&'static str),
+
+    #[error("patchelf exited with {0}")]
+    PatchElf(ExitStatus),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+
+const DT_NULL: u64 = 0;
+const DT_STRTAB: u64 = 5;
+const DT_RPATH: u64 = 15;
+const DT_RUNPATH: u64 = 29;
+
+/// Class and byte order of an ELF file.
+#[derive(Copy, Clone)]
+struct Layout {
+    is_64: bool,
+    is_le: bool,
+}
+
+impl Layout {
+    fn read(self, data: &[u8], offset: usize, size: usize) -> Result<u64, PatchElfError> {
+        let bytes = offset
+            .checked_add(size)
+            .and_then(|end| data.get(offset..end))
+            .ok_or(PatchElfError::Malformed)?;
+
+        let mut buf = [0; 8];
+        if self.is_le {
+            buf[..size].copy_from_slice(bytes);
+            Ok(u64::from_le_bytes(buf))
+        } else {
+            buf[8 - size..].copy_from_slice(bytes);
+            Ok(u64::from_be_bytes(buf))
+        }
+    }
+
+    /// Read an address, offset or size, which is as large as the class.
+    fn read_word(self, data: &[u8], offset: usize) -> Result<u64, PatchElfError> {
+        self.read(data, offset, if self.is_64 { 8 } else { 4 })
+    }
+}
+
+/// Return the offset of the `index`-th entry of `size` bytes of a table
+/// at `base`, checking for overflows.
+fn entry_offset(base: u64, index: u64, size: u64) -> Result<usize, PatchElfError> {
+    index
+        .checked_mul(size)
+        .and_then(|offset| base.checked_add(offset))
+        .and_then(|offset| usize::try_from(offset).ok())
+        .ok_or(PatchElfError::Malformed)
+}
+
+/// Return the offset of the field at `offset` of the entry at `entry`.
+fn field_offset(entry: usize, offset: usize) -> Result<usize, PatchElfError> {
+    entry.checked_add(offset).ok_or(PatchElfError::Malformed)
+}
+
+struct Segment {
+    kind: u32,
+    offset: u64,
+    vaddr: u64,
+    filesz: u64,
+}
+
+/// Parse the program headers of `data`, or return `None` if it is not an
+/// ELF file.
+fn parse_segments(data: &[u8]) -> Result<Option<(Layout, Vec<Segment>)>, PatchElfError> {
+    if data.len() < 16 || &data[..4] != b"\x7fELF" {
+        return Ok(None);
+    }
+
+    let layout = Layout {
+        is_64: match data[4] {
+            1 => false,
+            2 => true,
+            _ => return Err(PatchElfError::Malformed),
+        },
+        is_le: match data[5] {
+            1 => true,
+            2 => false,
+            _ => return Err(PatchElfError::Malformed),
+        },
+    };
+
+    let (phoff, phentsize, phnum) = if layout.is_64 {
+        (
+            layout.read(data, 0x20, 8)?,
+            layout.read(data, 0x36, 2)?,
+            layout.read(data, 0x38, 2)?,
+        )
+    } else {
+        (
+            layout.read(data, 0x1c, 4)?,
+            layout.read(data, 0x2a, 2)?,
+            layout.read(data, 0x2c, 2)?,
+        )
+    };
+
+    let segments = (0..phnum)
+        .map(|i| {
+            let header = entry_offset(phoff, i, phentsize)?;
+            let kind = layout.read(data, header, 4)? as u32;
+
+            let (offset, vaddr, filesz) = if layout.is_64 {
+                (
+                    layout.read(data, field_offset(header, 8)?, 8)?,
+                    layout.read(data, field_offset(header, 16)?, 8)?,
+                    layout.read(data, field_offset(header, 32)?, 8)?,
+                )
+            } else {
+                (
+                    layout.read(data, field_offset(header, 4)?, 4)?,
+                    layout.read(data, field_offset(header, 8)?, 4)?,
+                    layout.read(data, field_offset(header, 16)?, 4)?,
+                )
+            };
+
+            Ok(Segment {
+                kind,
+                offset,
+                vaddr,
+                filesz,
+            })
+        })
+        .collect::<Result<_, PatchElfError>>()?;
+
+    Ok(Some((layout, segments)))
+}
+
+/// Return the file offset of the virtual address `vaddr`.
+fn vaddr_to_offset(segments: &[Segment], vaddr: u64) -> Result<usize, PatchElfError> {
+    segments
+        .iter()
+        .find(|segment| {
+            segment.kind == PT_LOAD
+                && segment.vaddr <= vaddr
+                && vaddr - segment.vaddr < segment.filesz
+        })
+        .and_then(|segment| {
+            let offset = segment.offset.checked_add(vaddr - segment.vaddr)?;
+            usize::try_from(offset).ok()
+        })
+        .ok_or(PatchElfError::Malformed)
+}
+
+/// Return the offset and the length, including the nul terminator, of the
+/// nul terminated string at `offset`.
+fn c_str_at(data: &[u8], offset: usize) -> Result<(usize, usize), PatchElfError> {
+    let len = data
+        .get(offset..)
+        .and_then(|bytes| bytes.iter().position(|byte| *byte == 0))
+        .ok_or(PatchElfError::Malformed)?;
+    Ok((offset, len + 1))
+}
+
+/// Return the offsets and lengths of the strings of the rpath and runpath
+/// of the dynamic section, if any.
+fn rpath_strings(
+    data: &[u8],
+    layout: Layout,
+    segments: &[Segment],
+) -> Result<Vec<(usize, usize)>, PatchElfError> {
+    let Some(dynamic) = segments.iter().find(|segment| segment.kind == PT_DYNAMIC) else {
+        return Ok(Vec::new());
+    };
+
+    let entry_size = if layout.is_64 { 16 } else { 8 };
+    let word_size = entry_size / 2;
+
+    let mut strtab = None;
+    let mut rpaths = Vec::new();
+    for i in 0..dynamic.filesz / entry_size {
+        let entry = entry_offset(dynamic.offset, i, entry_size)?;
+
+        let tag = layout.read_word(data, entry)?;
+        let value = layout.read_word(data, field_offset(entry, word_size as usize)?)?;
+        match tag {
+            DT_NULL => break,
+            DT_STRTAB => strtab = Some(value),
+            DT_RPATH | DT_RUNPATH => rpaths.push(value),
+            _ => (),
+        }
+    }
+
+    if rpaths.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let strtab = vaddr_to_offset(segments, strtab.ok_or(PatchElfError::Malformed)?)?;
+    rpaths
+        .into_iter()
+        .map(|rpath| {
+            let offset = usize::try_from(rpath)
+                .ok()
+                .and_then(|rpath| strtab.checked_add(rpath))
+                .ok_or(PatchElfError::Malformed)?;
+            c_str_at(data, offset)
+        })
+        .collect()
+}
+
+/// Replace the nul terminated string of `len` bytes at `offset` with
+/// `value`, padding it with nul bytes.
+fn replace_c_str(data: &mut [u8], (offset, len): (usize, usize), value: &[u8]) {
+    let dst = &mut data[offset..offset + len];
+    dst.fill(0);
+    dst[..value.len()].copy_from_slice(value);
+}
+
+/// How to rewrite the ELF binaries installed.
+#[derive(Clone, Debug, Default)]
+pub struct PatchElf {
+    /// Replace the interpreter, i.e. the dynamic loader, of dynamically
+    /// linked binaries.
+    pub interpreter: Option<PathBuf>,
+    /// Replace the `DT_RUNPATH` or `DT_RPATH` of dynamically linked
+    /// binaries.
+    pub rpath: Option<String>,
+}
+
+impl PatchElf {
+    /// Patch `data` in place, which only works if the new values are not
+    /// longer than the existing ones.
+    ///
+    /// Return false if `data` is not a dynamically linked ELF file, so there
+    /// is nothing to patch.
+    fn patch_in_place(&self, data: &mut [u8]) -> Result<bool, PatchElfError> {
+        let Some((layout, segments)) = parse_segments(data)? else {
+            return Ok(false);
+        };
+
+        let mut replacements: Vec<((usize, usize), &[u8])> = Vec::new();
+
+        let interp = segments.iter().find(|segment| segment.kind == PT_INTERP);
+        if let (Some(interpreter), Some(interp)) = (&self.interpreter, interp) {
+            let interpreter = interpreter
+                .to_str()
+                .ok_or(PatchElfError::DoesNotFit("interpreter"))?
+                .as_bytes();
+            let offset = usize::try_from(interp.offset).map_err(|_| PatchElfError::Malformed)?;
+            let len = usize::try_from(interp.filesz).map_err(|_| PatchElfError::Malformed)?;
+
+            if interpreter.len() >= len {
+                return Err(PatchElfError::DoesNotFit("interpreter"));
+            }
+            replacements.push(((offset, len), interpreter));
+        }
+
+        // Statically linked binaries have no interpreter and load no
+        // libraries, so they are left as is.
+        if let (Some(rpath), Some(_)) = (&self.rpath, interp) {
+            let strings = rpath_strings(data, layout, &segments)?;
+            if strings.is_empty() {
+                return Err(PatchElfError::DoesNotFit("rpath"));
+            }
+            for (offset, len) in strings {
+                if rpath.len() >= len {
+                    return Err(PatchElfError::DoesNotFit("rpath"));
+                }
+                replacements.push(((offset, len), rpath.as_bytes()));
+            }
+        }
+
+        if data.len()
+            < replacements
+                .iter()
+                .map(|((offset, len), _)| offset + len)
+                .max()
+                .unwrap_or(0)
+        {
+            return Err(PatchElfError::Malformed);
+        }
+
+        let patched = !replacements.is_empty();
+        for (string, value) in replacements {
+            replace_c_str(data, string, value);
+        }
+
+        Ok(patched)
+    }
+
+    /// Rewrite the binary at `path` with the external `patchelf`.
+    fn run_patchelf(&self, path: &Path) -> Result<(), PatchElfError> {
+        let mut command = Command::new("patchelf");
+        if let Some(interpreter) = &self.interpreter {
+            command.arg("--set-interpreter").arg(interpreter);
+        }
+        if let Some(rpath) = &self.rpath {
+            command.args(["--set-rpath", rpath]);
+        }
+
+        let status = command.arg(path).status()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(PatchElfError::PatchElf(status))
+        }
+    }
+
+    /// Rewrite the binary at `path`, if it is a dynamically linked ELF
+    /// binary.
+    ///
+    /// The values are replaced in place if they fit, otherwise `patchelf`
+    /// is used if installed since the binary has to be laid out again.
+    pub fn patch(&self, path: &Path) -> Result<(), PatchElfError> {
+        let mut data = fs::read(path)?;
+
+        match self.patch_in_place(&mut data) {
+            Ok(true) => {
+                debug!("Patched {} in place", path.display());
+                fs::write(path, data)?;
+                Ok(())
+            }
+            Ok(false) => Ok(()),
+            Err(PatchElfError::DoesNotFit(what)) => {
+                info!(
+                    "The new {what} of {} does not fit in place, running patchelf",
+                    path.display()
+                );
+                match self.run_patchelf(path) {
+                    Err(PatchElfError::Io(err)) if err.kind() == io::ErrorKind::NotFound => {
+                        Err(PatchElfError::DoesNotFit(what))
+                    }
+                    res => res,
+                }
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Build a little-endian 64-bit ELF with an interpreter and a dynamic
+    /// section with a runpath.
+    fn elf64(interpreter: &str, runpath: &str) -> Vec<u8> {
+        let mut data = vec![0; 0x200];
+        data[..4].copy_from_slice(b"\x7fELF");
+        data[4] = 2;
+        data[5] = 1;
+
+        let phoff: u64 = 0x40;
+        data[0x20..0x28].copy_from_slice(&phoff.to_le_bytes());
+        data[0x36..0x38].copy_from_slice(&56u16.to_le_bytes());
+        data[0x38..0x3a].copy_from_slice(&3u16.to_le_bytes());
+
+        let mut phdr = |i: usize, kind: u32, offset: u64, filesz: u64| {
+            let header = phoff as usize + i * 56;
+            data[header..header + 4].copy_from_slice(&kind.to_le_bytes());
+            data[header + 8..header + 16].copy_from_slice(&offset.to_le_bytes());
+            // Mapped at the same virtual address.
+            data[header + 16..header + 24].copy_from_slice(&offset.to_le_bytes());
+            data[header + 32..header + 40].copy_from_slice(&filesz.to_le_bytes());
+        };
+        phdr(0, PT_LOAD, 0, 0x200);
+        phdr(1, PT_INTERP, 0x100, interpreter.len() as u64 + 1);
+        phdr(2, PT_DYNAMIC, 0x140, 48);
+
+        data[0x100..0x100 + interpreter.len()].copy_from_slice(interpreter.as_bytes());
+
+        // The string table starts at 0x180, with the runpath at index 1.
+        let dynamic = [(DT_STRTAB, 0x180u64), (DT_RUNPATH, 1), (DT_NULL, 0)];
+        for (i, (tag, value)) in dynamic.into_iter().enumerate() {
+            let entry = 0x140 + i * 16;
+            data[entry..entry + 8].copy_from_slice(&tag.to_le_bytes());
+            data[entry + 8..entry + 16].copy_from_slice(&value.to_le_bytes());
+        }
+        data[0x181..0x181 + runpath.len()].copy_from_slice(runpath.as_bytes());
+
+        data
+    }
+
+    #[test]
+    fn test_patch_in_place() {
+        let mut data = elf64("/lib64/ld-linux-x86-64.so.2", "$ORIGIN/../lib/long/path");
+
+        let patch = PatchElf {
+            interpreter: Some("/opt/glibc/ld.so".into()),
+            rpath: Some("/opt/glibc/lib".into()),
+        };
+        assert!(patch.patch_in_place(&mut data).unwrap());
+
+        let (layout, segments) = parse_segments(&data).unwrap().unwrap();
+        assert_eq!(c_str_at(&data, 0x100).unwrap(), (0x100, 17));
+        assert_eq!(&data[0x100..0x110], b"/opt/glibc/ld.so");
+
+        let strings = rpath_strings(&data, layout, &segments).unwrap();
+        assert_eq!(strings, [(0x181, 15)]);
+        assert_eq!(&data[0x181..0x18f], b"/opt/glibc/lib");
+
+        let patch = PatchElf {
+            interpreter: Some("/a/much/longer/path/to/the/dynamic/loader.so".into()),
+            rpath: None,
+        };
+        assert!(matches!(
+            patch.patch_in_place(&mut data),
+            Err(PatchElfError::DoesNotFit("interpreter"))
+        ));
+    }
+
+    #[test]
+    fn test_malformed_offsets() {
+        assert_eq!(entry_offset(0x40, 2, 56).unwrap(), 0x40 + 2 * 56);
+        assert!(matches!(
+            entry_offset(u64::MAX - 8, 1, 56),
+            Err(PatchElfError::Malformed)
+        ));
+        assert!(matches!(
+            entry_offset(0, u64::MAX, 16),
+            Err(PatchElfError::Malformed)
+        ));
+        assert!(matches!(
+            field_offset(usize::MAX - 4, 8),
+            Err(PatchElfError::Malformed)
+        ));
+
+        // The program headers at the very end of the address space.
+        let mut data = elf64("/lib64/ld-linux-x86-64.so.2", "/usr/lib");
+        data[0x20..0x28].copy_from_slice(&(u64::MAX - 8).to_le_bytes());
+        assert!(matches!(
+            parse_segments(&data),
+            Err(PatchElfError::Malformed)
+        ));
+    }
+
+    #[test]
+    fn test_not_elf() {
+        let patch = PatchElf {
+            interpreter: Some("/opt/glibc/ld.so".into()),
+            rpath: None,
+        };
+        assert!(!patch.patch_in_place(&mut b"#!/bin/sh\n".to_vec()).unwrap());
+    }
+}
@@ -0,0 +1,334 @@
+//! Plans of installs, resolved ahead of time so that they can be reviewed,
+//! e.g. by a human or a policy bot, before they are applied.
+//!
+//! A plan is applied by resolving its crates again, pinned to the versions
+//! planned, and refusing to install them unless they resolve to the same
+//! artifacts and binaries, see [`PlannedInstall::check_resolution`].
+
+use std::{collections::HashMap, path::PathBuf};
+
+use compact_str::{format_compact, CompactString, ToCompactString};
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    errors::{BinstallError, VersionParseError},
+    helpers::hex,
+    ops::{
+        resolve::{CrateName, Resolution, ResolutionFetch, ResolutionSource},
+        verify::sha256_file,
+    },
+};
+
+/// Crates to install, saved as JSON.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct InstallPlan {
+    /// Version of binstall which made the plan.
+    pub binstall_version: CompactString,
+    pub crates: Vec<PlannedInstall>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "strategy", rename_all = "kebab-case")]
+pub enum PlannedInstall {
+    /// Install the prebuilt binaries of an artifact.
+    Fetch(PlannedFetch),
+    /// Build the crate from source with `cargo install`.
+    Source(PlannedSource),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PlannedFetch {
+    pub name: CompactString,
+    pub version: Version,
+    pub version_req: CompactString,
+    pub target: CompactString,
+    /// Name of the fetcher which found the artifact, e.g. `GhCrateMeta`.
+    pub fetcher: CompactString,
+    pub artifact_url: Option<Url>,
+    pub bins: Vec<PlannedBin>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PlannedBin {
+    pub name: CompactString,
+    pub dest: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link: Option<PathBuf>,
+    /// Hex encoded sha256 digest of the binary.
+    pub sha256: CompactString,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PlannedSource {
+    pub name: CompactString,
+    pub version: CompactString,
+    pub version_req: CompactString,
+    /// Source archive of the release tag to build from, instead of the
+    /// crate published on the registry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_archive: Option<Url>,
+}
+
+impl PlannedInstall {
+    /// Plan to install the binaries of `fetch`, which must still be
+    /// extracted.
+    pub fn from_fetch(fetch: &ResolutionFetch) -> Result<Self, BinstallError> {
+        let bins = fetch
+            .bin_files
+            .iter()
+            .map(|bin_file| {
+                let sha256 = match fetch.bin_sha256.get(&bin_file.source) {
+                    Some(sha256) => hex(sha256).into(),
+                    None => sha256_file(&bin_file.source)?,
+                };
+
+                Ok(PlannedBin {
+                    name: bin_file.base_name.clone(),
+                    dest: bin_file.dest.clone(),
+                    link: bin_file.link.clone(),
+                    sha256,
+                })
+            })
+            .collect::<Result<_, BinstallError>>()?;
+
+        Ok(Self::Fetch(PlannedFetch {
+            name: fetch.name.clone(),
+            version: fetch.new_version.clone(),
+            version_req: fetch.version_req.clone(),
+            target: fetch.fetcher.target().to_compact_string(),
+            fetcher: fetch.fetcher.fetcher_name().into(),
+            artifact_url: fetch.fetcher.download_url().cloned(),
+            bins,
+        }))
+    }
+
+    /// Plan to build `source`.
+    pub fn from_source(source: &ResolutionSource) -> Self {
+        Self::Source(PlannedSource {
+            name: source.name.clone(),
+            version: source.version.clone(),
+            version_req: source.version_req.clone(),
+            source_archive: source.source_archive.clone(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Fetch(fetch) => &fetch.name,
+            Self::Source(source) => &source.name,
+        }
+    }
+
+    /// Target of the binaries planned, `None` for builds from source which
+    /// are for the first target desired.
+    pub fn target(&self) -> Option<&str> {
+        match self {
+            Self::Fetch(fetch) => Some(&fetch.target),
+            Self::Source(_) => None,
+        }
+    }
+
+    fn version_req(&self) -> &CompactString {
+        match self {
+            Self::Fetch(fetch) => &fetch.version_req,
+            Self::Source(source) => &source.version_req,
+        }
+    }
+
+    /// The crate pinned to the version planned, to resolve it again.
+    pub fn crate_name(&self) -> Result<CrateName, BinstallError> {
+        let version = match self {
+            Self::Fetch(fetch) => fetch.version.to_compact_string(),
+            Self::Source(source) => source.version.clone(),
+        };
+        let version_req = VersionReq::parse(&format!("={version}")).map_err(|err| {
+            BinstallError::VersionParse(Box::new(VersionParseError { v: version, err }))
+        })?;
+
+        Ok(CrateName {
+            name: self.name().into(),
+            version_req: Some(version_req),
+        })
+    }
+
+    /// Check that `resolution`, of [`PlannedInstall::crate_name`], is the
+    /// install planned, then restore the version requirement planned so
+    /// that it is the one recorded.
+    ///
+    /// A crate already up to date is not installed, so it is accepted.
+    pub fn check_resolution(&self, resolution: &mut Resolution) -> Result<(), BinstallError> {
+        let resolved = match resolution {
+            Resolution::Fetch(fetch) => {
+                fetch.version_req = self.version_req().clone();
+                Self::from_fetch(fetch)?
+            }
+            Resolution::InstallFromSource(source) => {
+                source.version_req = self.version_req().clone();
+                Self::from_source(source)
+            }
+            Resolution::AlreadyUpToDate => return Ok(()),
+        };
+
+        match describe_mismatch(self, &resolved) {
+            None => Ok(()),
+            Some(mismatch) => Err(BinstallError::PlanMismatch(mismatch)),
+        }
+    }
+}
+
+fn describe_mismatch(planned: &PlannedInstall, resolved: &PlannedInstall) -> Option<CompactString> {
+    if planned == resolved {
+        return None;
+    }
+
+    let mismatch = match (planned, resolved) {
+        (PlannedInstall::Fetch(planned), PlannedInstall::Fetch(resolved)) => {
+            let planned_bins: HashMap<_, _> =
+                planned.bins.iter().map(|bin| (&bin.name, bin)).collect();
+
+            if planned.version != resolved.version {
+                format_compact!(
+                    "planned v{}, resolved v{}",
+                    planned.version,
+                    resolved.version
+                )
+            } else if planned.target != resolved.target {
+                format_compact!(
+                    "planned target {}, resolved {}",
+                    planned.target,
+                    resolved.target
+                )
+            } else if planned.artifact_url != resolved.artifact_url {
+                format_compact!(
+                    "planned artifact {}, resolved {} from {}",
+                    display_url(planned.artifact_url.as_ref()),
+                    display_url(resolved.artifact_url.as_ref()),
+                    resolved.fetcher,
+                )
+            } else if let Some(bin) = resolved.bins.iter().find(|bin| {
+                planned_bins
+                    .get(&bin.name)
+                    .map_or(true, |planned_bin| *planned_bin != *bin)
+            }) {
+                format_compact!("binary {} differs from the one planned", bin.name)
+            } else {
+                format_compact!("binaries differ from the ones planned")
+            }
+        }
+        (PlannedInstall::Source(planned), PlannedInstall::Source(resolved))
+            if planned.version != resolved.version =>
+        {
+            format_compact!(
+                "planned v{}, resolved v{}",
+                planned.version,
+                resolved.version
+            )
+        }
+        (PlannedInstall::Source(planned), PlannedInstall::Source(resolved)) => format_compact!(
+            "planned source archive {}, resolved {}",
+            display_url(planned.source_archive.as_ref()),
+            display_url(resolved.source_archive.as_ref()),
+        ),
+        (PlannedInstall::Fetch(_), PlannedInstall::Source(_)) => {
+            "planned prebuilt binaries, resolved a build from source".into()
+        }
+        (PlannedInstall::Source(_), PlannedInstall::Fetch(_)) => {
+            "planned a build from source, resolved prebuilt binaries".into()
+        }
+    };
+
+    Some(mismatch)
+}
+
+fn display_url(url: Option<&Url>) -> &str {
+    url.map(Url::as_str).unwrap_or("none")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn source(version: &str, source_archive: Option<&str>) -> ResolutionSource {
+        ResolutionSource {
+            name: "cargo-watch".into(),
+            version: version.into(),
+            version_req: "=8.4.0".into(),
+            repo: None,
+            source_archive: source_archive.map(|url| Url::parse(url).unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_check_resolution() {
+        let planned = PlannedInstall::Source(PlannedSource {
+            name: "cargo-watch".into(),
+            version: "8.4.0".into(),
+            version_req: "8".into(),
+            source_archive: None,
+        });
+        assert_eq!(
+            planned.crate_name().unwrap().to_string(),
+            "cargo-watch@=8.4.0"
+        );
+
+        let mut resolution = Resolution::InstallFromSource(source("8.4.0", None));
+        planned.check_resolution(&mut resolution).unwrap();
+        // The version requirement planned is recorded, not the pinned one.
+        let Resolution::InstallFromSource(resolved) = resolution else {
+            unreachable!()
+        };
+        assert_eq!(resolved.version_req, "8");
+
+        let mut resolution = Resolution::InstallFromSource(source(
+            "8.4.0",
+            Some("https://github.com/watchexec/cargo-watch/archive/v8.4.0.tar.gz"),
+        ));
+        assert!(matches!(
+            planned.check_resolution(&mut resolution),
+            Err(BinstallError::PlanMismatch(_))
+        ));
+
+        planned
+            .check_resolution(&mut Resolution::AlreadyUpToDate)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_serde() {
+        let plan = InstallPlan {
+            binstall_version: "1.4.0".into(),
+            crates: vec![
+                PlannedInstall::Fetch(PlannedFetch {
+                    name: "ripgrep".into(),
+                    version: Version::new(14, 0, 0),
+                    version_req: "*".into(),
+                    target: "x86_64-unknown-linux-musl".into(),
+                    fetcher: "GhCrateMeta".into(),
+                    artifact_url: Some(Url::parse("https://github.com/BurntSushi/ripgrep/releases/download/14.0.0/ripgrep-14.0.0-x86_64-unknown-linux-musl.tar.gz").unwrap()),
+                    bins: vec![PlannedBin {
+                        name: "rg".into(),
+                        dest: "/home/user/.cargo/bin/rg".into(),
+                        link: None,
+                        sha256: "0".repeat(64).into(),
+                    }],
+                }),
+                PlannedInstall::Source(PlannedSource {
+                    name: "cargo-watch".into(),
+                    version: "8.4.0".into(),
+                    version_req: "8".into(),
+                    source_archive: None,
+                }),
+            ],
+        };
+
+        let json = serde_json::to_string(&plan).unwrap();
+        assert!(json.contains(r#""strategy":"fetch""#));
+        assert_eq!(serde_json::from_str::<InstallPlan>(&json).unwrap(), plan);
+    }
+}
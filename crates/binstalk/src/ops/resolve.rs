@@ -1,8 +1,8 @@
 use std::{
     borrow::Cow,
-    collections::{BTreeMap, BTreeSet},
+    collections::{BTreeMap, BTreeSet, HashMap},
     iter, mem,
-    path::Path,
+    path::{Path, PathBuf},
     str::FromStr,
     sync::Arc,
 };
@@ -19,14 +19,21 @@ use tracing::{debug, error, info, instrument, warn};
 use crate::{
     bins,
     errors::{BinstallError, VersionParseError},
-    fetchers::{Data, Fetcher, TargetData},
+    fetchers::{find_tag_source_archive, Data, Fetcher, TargetData},
     helpers::{
-        self, cargo_toml::Manifest, cargo_toml_workspace::load_manifest_from_workspace,
-        download::ExtractedFiles, remote::Client, target_triple::TargetTriple,
+        self,
+        cargo_toml::Manifest,
+        cargo_toml_workspace::load_manifest_from_workspace,
+        download::{ExtractedFiles, ProgressReporter},
+        remote::Client,
+        target_triple::TargetTriple,
         tasks::AutoAbortJoinHandle,
     },
     manifests::cargo_toml_binstall::{Meta, PkgMeta, PkgOverride},
-    ops::{CargoTomlFetchOverride, Options},
+    ops::{
+        event::{EventProgressReporter, InstallEvent},
+        CargoTomlFetchOverride, Options,
+    },
 };
 
 mod crate_name;
@@ -39,7 +46,24 @@ pub use version_ext::VersionReqExt;
 
 mod resolution;
 #[doc(inline)]
-pub use resolution::{Resolution, ResolutionFetch, ResolutionSource};
+pub use resolution::{FailedFetch, Resolution, ResolutionFetch, ResolutionSource};
+
+mod quarantine;
+use quarantine::{is_verification_error, quarantine};
+
+mod probe;
+#[doc(inline)]
+pub use probe::{check_release, probe, Probe, TargetProbe, STANDARD_TARGETS};
+
+mod diff;
+#[doc(inline)]
+pub use diff::{diff_files, fetch_artifact_files, ArtifactFiles, FileChange};
+
+mod version_resolution_hook;
+#[doc(inline)]
+pub use version_resolution_hook::{
+    VersionDecision, VersionResolutionContext, VersionResolutionHook,
+};
 
 #[instrument(skip_all)]
 pub async fn resolve(
@@ -47,10 +71,28 @@ pub async fn resolve(
     crate_name: CrateName,
     curr_version: Option<Version>,
 ) -> Result<Resolution, BinstallError> {
+    opts.emit(InstallEvent::Resolving {
+        crate_name: &crate_name,
+    });
+
     let crate_name_name = crate_name.name.clone();
-    let resolution = resolve_inner(opts, crate_name, curr_version)
+    let resolution = resolve_inner(opts.clone(), crate_name, curr_version)
         .await
-        .map_err(|err| err.crate_context(crate_name_name))?;
+        .and_then(|resolution| {
+            // Failures of fetchers are not fatal, so make sure exceeding the
+            // budget does not silently fallback to another fetcher or to
+            // `cargo-install`.
+            match opts.client.budget_exceeded() {
+                Some(err) => Err(err.into()),
+                None => Ok(resolution),
+            }
+        })
+        .map_err(|err| err.crate_context(crate_name_name.clone()))?;
+
+    opts.emit(InstallEvent::Resolved {
+        crate_name: &crate_name_name,
+        resolution: &resolution,
+    });
 
     Ok(resolution)
 }
@@ -69,12 +111,12 @@ async fn resolve_inner(
         (None, None) => MaybeOwned::Owned(VersionReq::STAR),
     };
 
-    let version_req_str = version_req.to_compact_string();
+    let mut version_req_str = version_req.to_compact_string();
 
-    let Some(package_info) = PackageInfo::resolve(
+    let Some(mut package_info) = PackageInfo::resolve(
         &opts,
-        crate_name.name,
-        curr_version,
+        crate_name.name.clone(),
+        curr_version.clone(),
         &version_req,
         opts.client.clone(),
     )
@@ -83,58 +125,73 @@ async fn resolve_inner(
         return Ok(Resolution::AlreadyUpToDate);
     };
 
-    let desired_targets = opts
-        .desired_targets
-        .get()
-        .await
-        .iter()
-        .map(|target| TargetTriple::from_str(target).map(|triple| (triple, target)))
-        .collect::<Result<Vec<_>, _>>()?;
-    let resolvers = &opts.resolvers;
-
-    let mut handles: Vec<(Arc<dyn Fetcher>, _)> =
-        Vec::with_capacity(desired_targets.len() * resolvers.len());
-
-    let data = Arc::new(Data::new(
-        package_info.name.clone(),
-        package_info.version_str.clone(),
-        package_info.repo.clone(),
-    ));
+    if let Some(hook) = &opts.version_resolution_hook {
+        let decision = hook
+            .check_version(VersionResolutionContext {
+                crate_name: &package_info.name,
+                version_req: &version_req,
+                current_version: curr_version.as_ref(),
+                resolved_version: &package_info.version,
+                repo: package_info.repo.as_deref(),
+                registry: &opts.registry,
+            })
+            .await;
+
+        if let Some(version_req) =
+            decision.apply(&package_info.version_str, &mut version_req_str)?
+        {
+            debug!(
+                "Version {} of {} replaced with {version_req} by hook",
+                package_info.version, package_info.name
+            );
+
+            let Some(replaced) = PackageInfo::resolve(
+                &opts,
+                crate_name.name,
+                curr_version,
+                &version_req,
+                opts.client.clone(),
+            )
+            .await?
+            else {
+                return Ok(Resolution::AlreadyUpToDate);
+            };
+
+            package_info = replaced;
+        }
+    }
 
-    handles.extend(
-        desired_targets
-            .into_iter()
-            .map(|(triple, target)| {
-                debug!("Building metadata for target: {target}");
+    let desired_targets = opts.desired_targets.get().await;
 
-                let target_meta = package_info.meta.merge_overrides(
-                    iter::once(&opts.cli_overrides).chain(package_info.overrides.get(target)),
-                );
+    let data = Arc::new(
+        Data::new(
+            package_info.name.clone(),
+            package_info.version_str.clone(),
+            package_info.repo.clone(),
+        )
+        .with_deterministic(opts.deterministic),
+    );
 
-                debug!("Found metadata: {target_meta:?}");
+    // The fetchers of all the targets probe for their artifacts
+    // concurrently, their results are then taken in order of preference,
+    // so that a long chain of fallback targets does not slow down the
+    // resolution.
+    let handles: Vec<_> = create_fetchers(&opts, &package_info, &data, desired_targets)?
+        .map(|fetcher| (fetcher.clone(), AutoAbortJoinHandle::new(fetcher.find())))
+        .collect();
 
-                Arc::new(TargetData {
-                    target: target.clone(),
-                    meta: target_meta,
-                    target_related_info: triple,
-                })
-            })
-            .cartesian_product(resolvers)
-            .map(|(target_data, f)| {
-                let fetcher = f(
-                    opts.client.clone(),
-                    opts.gh_api_client.clone(),
-                    data.clone(),
-                    target_data,
-                );
-                (fetcher.clone(), AutoAbortJoinHandle::new(fetcher.find()))
-            }),
-    );
+    // Artifacts found but rejected, the next fetcher is tried instead.
+    let mut failed_fetches = Vec::new();
 
     for (fetcher, handle) in handles {
         fetcher.clone().report_to_upstream();
         match handle.flattened_join().await {
             Ok(true) => {
+                opts.emit(InstallEvent::FetcherFound {
+                    crate_name: &package_info.name,
+                    fetcher: fetcher.as_ref(),
+                });
+
                 // Generate temporary binary path
                 let bin_path = opts.temp_dir.join(format!(
                     "bin-{}-{}-{}",
@@ -143,23 +200,41 @@ async fn resolve_inner(
                     fetcher.fetcher_name()
                 ));
 
+                let artifact = opts.keep_artifacts.then(|| {
+                    opts.temp_dir.join(format!(
+                        "artifact-{}-{}-{}",
+                        package_info.name,
+                        fetcher.target(),
+                        fetcher.fetcher_name()
+                    ))
+                });
+
                 match download_extract_and_verify(
                     fetcher.as_ref(),
                     &bin_path,
+                    artifact.as_deref(),
                     &package_info,
-                    &opts.install_path,
-                    opts.no_symlinks,
+                    &opts,
                 )
                 .await
                 {
-                    Ok(bin_files) => {
+                    Ok((bin_files, bin_sha256)) => {
                         if !bin_files.is_empty() {
+                            opts.emit(InstallEvent::Verified {
+                                crate_name: &package_info.name,
+                                fetcher: fetcher.as_ref(),
+                            });
+
                             return Ok(Resolution::Fetch(Box::new(ResolutionFetch {
                                 fetcher,
                                 new_version: package_info.version,
                                 name: package_info.name,
                                 version_req: version_req_str,
+                                repo: package_info.repo,
                                 bin_files,
+                                bin_sha256,
+                                artifact,
+                                failed_fetches,
                             })));
                         } else {
                             warn!(
@@ -167,17 +242,67 @@ async fn resolve_inner(
                                 The fetcher does not provide any optional binary",
                                 fetcher.source_name(),
                             );
+
+                            let reason = "The fetcher does not provide any optional binary";
+
+                            opts.emit(InstallEvent::FetchFailed {
+                                crate_name: &package_info.name,
+                                fetcher: fetcher.as_ref(),
+                                reason,
+                            });
+
+                            if let Some(quarantine_dir) = &opts.quarantine_dir {
+                                quarantine(
+                                    quarantine_dir,
+                                    fetcher.as_ref(),
+                                    &package_info.name,
+                                    &package_info.version_str,
+                                    &bin_path,
+                                    reason.to_string(),
+                                )
+                                .await;
+                            }
+
+                            failed_fetches.push(FailedFetch {
+                                fetcher,
+                                reason: reason.to_string(),
+                            });
                         }
                     }
                     Err(err) => {
                         if let BinstallError::UserAbort = err {
                             return Err(err);
                         }
+
+                        let reason = err.to_string();
+
+                        opts.emit(InstallEvent::FetchFailed {
+                            crate_name: &package_info.name,
+                            fetcher: fetcher.as_ref(),
+                            reason: &reason,
+                        });
+
                         warn!(
                             "Error while downloading and extracting from fetcher {}: {}",
                             fetcher.source_name(),
                             err
                         );
+
+                        if let Some(quarantine_dir) = &opts.quarantine_dir {
+                            if is_verification_error(&err) {
+                                quarantine(
+                                    quarantine_dir,
+                                    fetcher.as_ref(),
+                                    &package_info.name,
+                                    &package_info.version_str,
+                                    &bin_path,
+                                    reason.clone(),
+                                )
+                                .await;
+                            }
+                        }
+
+                        failed_fetches.push(FailedFetch { fetcher, reason });
                     }
                 }
             }
@@ -192,30 +317,112 @@ async fn resolve_inner(
         }
     }
 
+    if opts.source_archive_fallback {
+        match find_tag_source_archive(&opts.client, &data, package_info.meta.tag_prefix.as_deref())
+            .await
+        {
+            Ok(Some(source_archive)) => {
+                return Ok(Resolution::InstallFromSource(ResolutionSource {
+                    name: package_info.name,
+                    version: package_info.version_str,
+                    version_req: version_req_str,
+                    repo: package_info.repo,
+                    source_archive: Some(source_archive),
+                }))
+            }
+            Ok(None) => debug!(
+                "No source archive found for release {} of {}",
+                package_info.version_str, package_info.name
+            ),
+            Err(err) => warn!("Error while looking for the source archive: {err}"),
+        }
+    }
+
     if opts.cargo_install_fallback {
         Ok(Resolution::InstallFromSource(ResolutionSource {
             name: package_info.name,
             version: package_info.version_str,
+            version_req: version_req_str,
+            repo: package_info.repo,
+            source_archive: None,
         }))
     } else {
         Err(BinstallError::NoFallbackToCargoInstall)
     }
 }
 
+/// Create fetchers for every pair of `targets` and `opts.resolvers`,
+/// ordered by target first.
+fn create_fetchers<'a>(
+    opts: &'a Options,
+    package_info: &'a PackageInfo,
+    data: &'a Arc<Data>,
+    targets: &'a [String],
+) -> Result<impl Iterator<Item = Arc<dyn Fetcher>> + 'a, BinstallError> {
+    let targets = targets
+        .iter()
+        .map(|target| TargetTriple::from_str(target).map(|triple| (triple, target)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(targets
+        .into_iter()
+        .map(|(triple, target)| {
+            debug!("Building metadata for target: {target}");
+
+            let target_meta = package_info.meta.merge_overrides(
+                iter::once(&opts.cli_overrides).chain(package_info.overrides.get(target)),
+            );
+
+            debug!("Found metadata: {target_meta:?}");
+
+            Arc::new(TargetData {
+                target: target.clone(),
+                meta: target_meta,
+                target_related_info: triple,
+            })
+        })
+        .cartesian_product(&opts.resolvers)
+        .map(|(target_data, f)| {
+            f(
+                opts.client.clone(),
+                opts.gh_api_client.clone(),
+                data.clone(),
+                target_data,
+            )
+        }))
+}
+
 ///  * `fetcher` - `fetcher.find()` must have returned `Ok(true)`.
+///  * `artifact_path` - where to keep the artifact downloaded, if any.
 ///
 /// Can return empty Vec if all `BinFile` is optional and does not exist
 /// in the archive downloaded.
+///
+/// Also return the sha256 digests of the binaries computed while extracting
+/// them, by source path.
 async fn download_extract_and_verify(
     fetcher: &dyn Fetcher,
     bin_path: &Path,
+    artifact_path: Option<&Path>,
     package_info: &PackageInfo,
-    install_path: &Path,
-    no_symlinks: bool,
-) -> Result<Vec<bins::BinFile>, BinstallError> {
+    opts: &Options,
+) -> Result<(Vec<bins::BinFile>, HashMap<PathBuf, [u8; 32]>), BinstallError> {
     // Download and extract it.
     // If that fails, then ignore this fetcher.
-    let extracted_files = fetcher.fetch_and_extract(bin_path).await?;
+    let progress_reporter = opts.progress_sink.clone().map(|progress_sink| {
+        Arc::new(EventProgressReporter::new(
+            progress_sink,
+            package_info.name.clone(),
+        )) as Arc<dyn ProgressReporter>
+    });
+    let extracted_files = fetcher
+        .fetch_and_extract(bin_path, artifact_path, progress_reporter)
+        .await?;
+
+    opts.emit(InstallEvent::Downloaded {
+        crate_name: &package_info.name,
+        fetcher,
+    });
 
     debug!("extracted_files = {extracted_files:#?}");
 
@@ -252,14 +459,14 @@ async fn download_extract_and_verify(
         package_info,
         meta,
         bin_path,
-        install_path,
-        no_symlinks,
+        &opts.install_path,
+        opts.no_symlinks,
         &extracted_files,
     )?;
 
     let name = &package_info.name;
 
-    package_info
+    let bin_files = package_info
         .binaries
         .iter()
         .zip(bin_files)
@@ -292,8 +499,17 @@ But since it requies features {features}, this bin is ignored."
                 }
             }
         })
-        .collect::<Result<Vec<bins::BinFile>, bins::Error>>()
-        .map_err(BinstallError::from)
+        .collect::<Result<Vec<bins::BinFile>, bins::Error>>()?;
+
+    let bin_sha256 = bin_files
+        .iter()
+        .filter_map(|bin_file| {
+            let sha256 = extracted_files.sha256(&bin_file.archive_source_path)?;
+            Some((bin_file.source.clone(), *sha256))
+        })
+        .collect();
+
+    Ok((bin_files, bin_sha256))
 }
 
 fn collect_bin_files(
@@ -388,14 +604,33 @@ impl PackageInfo {
                 use helpers::git::{GitCancellationToken, Repository as GitRepository};
 
                 let git_url = git_url.clone();
+                let git_options = opts.git_options.clone();
                 let name = name.clone();
                 let cancellation_token = GitCancellationToken::default();
                 // Cancel git operation if the future is cancelled (dropped).
                 let cancel_on_drop = cancellation_token.clone().cancel_on_drop();
 
+                let full_checkout = opts.git_full_checkout;
+
                 let ret = spawn_blocking(move || {
                     let dir = TempDir::new()?;
-                    GitRepository::shallow_clone(git_url, dir.as_ref(), Some(cancellation_token))?;
+                    if full_checkout {
+                        GitRepository::shallow_clone(
+                            git_url,
+                            dir.as_ref(),
+                            &git_options,
+                            Some(cancellation_token),
+                        )?;
+                    } else {
+                        let repo_dir = TempDir::new()?;
+                        GitRepository::shallow_clone_bare(
+                            git_url,
+                            repo_dir.as_ref(),
+                            &git_options,
+                            Some(cancellation_token),
+                        )?
+                        .checkout_head_paths(dir.as_ref(), is_manifest_file)?;
+                    }
 
                     load_manifest_from_workspace(dir.as_ref(), &name).map_err(BinstallError::from)
                 })
@@ -407,6 +642,12 @@ impl PackageInfo {
                 ret
             }
             None => {
+                if opts.deterministic && !version_req.is_pinned() {
+                    return Err(BinstallError::UnpinnedVersion(
+                        version_req.to_compact_string(),
+                    ));
+                }
+
                 Box::pin(
                     opts.registry
                         .fetch_crate_matched(client, &name, version_req),
@@ -476,6 +717,20 @@ impl PackageInfo {
     }
 }
 
+/// Return `true` if the file at `path` of a repository is needed to load
+/// the manifests of its crates: the `Cargo.toml`s, and the files of `src`
+/// and `src/bin` which the targets are discovered from.
+#[cfg(feature = "git")]
+fn is_manifest_file(path: &Path) -> bool {
+    path.file_name() == Some("Cargo.toml".as_ref())
+        || path
+            .parent()
+            .map_or(false, |parent| parent.ends_with("src"))
+        || path
+            .ancestors()
+            .any(|ancestor| ancestor.ends_with("src/bin"))
+}
+
 /// Load binstall metadata from the crate `Cargo.toml` at the provided path
 ///
 /// This is a blocking function.
@@ -498,3 +753,26 @@ pub fn load_manifest_path<P: AsRef<Path>, N: AsRef<str>>(
 
     inner(manifest_path.as_ref(), name.as_ref())
 }
+
+#[cfg(all(test, feature = "git"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_manifest_file() {
+        for path in [
+            "Cargo.toml",
+            "crates/foo/Cargo.toml",
+            "src/main.rs",
+            "crates/foo/src/lib.rs",
+            "src/bin/foo.rs",
+            "src/bin/foo/main.rs",
+        ] {
+            assert!(is_manifest_file(Path::new(path)), "{path}");
+        }
+
+        for path in ["README.md", "src/foo/mod.rs", "tests/foo.rs", "Cargo.lock"] {
+            assert!(!is_manifest_file(Path::new(path)), "{path}");
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+
+use compact_str::CompactString;
+use semver::VersionReq;
+use tokio::task::spawn_blocking;
+use tracing::{info, instrument, warn};
+
+use super::{create_fetchers, CrateName, PackageInfo};
+use crate::{
+    errors::BinstallError,
+    fetchers::{Data, Fetcher},
+    ops::Options,
+};
+
+/// Files of the prebuilt artifact of a version of a crate, see
+/// [`fetch_artifact_files`].
+pub struct ArtifactFiles {
+    pub version: CompactString,
+    pub fetcher: Arc<dyn Fetcher>,
+    /// Paths of the files in the artifact and their sizes.
+    pub files: BTreeMap<PathBuf, u64>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum FileChange {
+    Added {
+        path: PathBuf,
+        size: u64,
+    },
+    Removed {
+        path: PathBuf,
+        size: u64,
+    },
+    SizeChanged {
+        path: PathBuf,
+        old_size: u64,
+        new_size: u64,
+    },
+}
+
+/// Download and extract the prebuilt artifact of the crate for the first
+/// of `opts.desired_targets` having one, and list the files in it.
+///
+/// Return `None` if no prebuilt artifact is available.
+#[instrument(skip_all)]
+pub async fn fetch_artifact_files(
+    opts: Arc<Options>,
+    crate_name: CrateName,
+) -> Result<Option<ArtifactFiles>, BinstallError> {
+    let name = crate_name.name.clone();
+    fetch_artifact_files_inner(opts, crate_name)
+        .await
+        .map_err(|err| err.crate_context(name))
+}
+
+async fn fetch_artifact_files_inner(
+    opts: Arc<Options>,
+    crate_name: CrateName,
+) -> Result<Option<ArtifactFiles>, BinstallError> {
+    info!("Listing the artifact of package: '{}'", crate_name);
+
+    let version_req = crate_name.version_req.unwrap_or(VersionReq::STAR);
+
+    let package_info = PackageInfo::resolve(
+        &opts,
+        crate_name.name,
+        None,
+        &version_req,
+        opts.client.clone(),
+    )
+    .await?
+    .expect("PackageInfo::resolve only returns None if curr_version is Some");
+
+    let desired_targets = opts.desired_targets.get().await;
+
+    let data = Arc::new(
+        Data::new(
+            package_info.name.clone(),
+            package_info.version_str.clone(),
+            package_info.repo.clone(),
+        )
+        .with_deterministic(opts.deterministic),
+    );
+
+    for fetcher in create_fetchers(&opts, &package_info, &data, desired_targets)? {
+        match fetcher.clone().find().await {
+            Ok(Ok(true)) => (),
+            Ok(Ok(false)) => continue,
+            Ok(Err(err)) => {
+                warn!(
+                    "Error while checking fetcher {}: {err}",
+                    fetcher.source_name()
+                );
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        let dst = opts.temp_dir.join(format!(
+            "diff-{}-{}-{}-{}",
+            package_info.name,
+            package_info.version_str,
+            fetcher.target(),
+            fetcher.fetcher_name()
+        ));
+
+        let extracted_files = fetcher.fetch_and_extract(&dst, None, None).await?;
+
+        let paths: Vec<PathBuf> = extracted_files.files().map(PathBuf::from).collect();
+        let files = spawn_blocking(move || {
+            paths
+                .into_iter()
+                .map(|path| {
+                    let size = std::fs::metadata(dst.join(&path))?.len();
+                    Ok((path, size))
+                })
+                .collect::<Result<BTreeMap<_, _>, std::io::Error>>()
+        })
+        .await??;
+
+        return Ok(Some(ArtifactFiles {
+            version: package_info.version_str.clone(),
+            fetcher,
+            files,
+        }));
+    }
+
+    Ok(None)
+}
+
+/// Return the files added, removed or changed in size from `old` to `new`,
+/// ordered by path.
+pub fn diff_files(old: &BTreeMap<PathBuf, u64>, new: &BTreeMap<PathBuf, u64>) -> Vec<FileChange> {
+    let mut changes: Vec<_> = old
+        .iter()
+        .filter_map(|(path, &old_size)| match new.get(path) {
+            None => Some(FileChange::Removed {
+                path: path.clone(),
+                size: old_size,
+            }),
+            Some(&new_size) if new_size != old_size => Some(FileChange::SizeChanged {
+                path: path.clone(),
+                old_size,
+                new_size,
+            }),
+            Some(_) => None,
+        })
+        .chain(
+            new.iter()
+                .filter(|(path, _)| !old.contains_key(*path))
+                .map(|(path, &size)| FileChange::Added {
+                    path: path.clone(),
+                    size,
+                }),
+        )
+        .collect();
+
+    changes.sort_by(|x, y| x.path().cmp(y.path()));
+    changes
+}
+
+impl FileChange {
+    pub fn path(&self) -> &PathBuf {
+        match self {
+            Self::Added { path, .. }
+            | Self::Removed { path, .. }
+            | Self::SizeChanged { path, .. } => path,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_diff_files() {
+        let files = |entries: &[(&str, u64)]| {
+            entries
+                .iter()
+                .map(|(path, size)| (PathBuf::from(path), *size))
+                .collect::<BTreeMap<_, _>>()
+        };
+
+        let old = files(&[("a", 1), ("b", 2), ("c", 3)]);
+        let new = files(&[("a", 1), ("b", 20), ("d", 4)]);
+
+        assert_eq!(
+            diff_files(&old, &new),
+            [
+                FileChange::SizeChanged {
+                    path: "b".into(),
+                    old_size: 2,
+                    new_size: 20
+                },
+                FileChange::Removed {
+                    path: "c".into(),
+                    size: 3
+                },
+                FileChange::Added {
+                    path: "d".into(),
+                    size: 4
+                },
+            ]
+        );
+        assert_eq!(diff_files(&old, &old), []);
+    }
+}
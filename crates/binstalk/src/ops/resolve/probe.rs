@@ -0,0 +1,283 @@
+use std::{fs, path::Path, sync::Arc};
+
+use binstalk_downloader::{
+    bytes::Bytes,
+    download::{DownloadError, PublishedChecksum},
+};
+use compact_str::CompactString;
+use semver::VersionReq;
+use tokio::task::spawn_blocking;
+use tracing::{info, instrument, warn};
+
+use super::{create_fetchers, download_extract_and_verify, CrateName, PackageInfo};
+use crate::{
+    errors::BinstallError,
+    fetchers::{Data, Fetcher},
+    helpers::{
+        remote::{Client, Url},
+        tasks::AutoAbortJoinHandle,
+    },
+    ops::Options,
+};
+
+/// Targets commonly supported by prebuilt releases, used by
+/// [`check_release`] callers as the default target matrix.
+pub const STANDARD_TARGETS: &[&str] = &[
+    "x86_64-unknown-linux-gnu",
+    "x86_64-unknown-linux-musl",
+    "aarch64-unknown-linux-gnu",
+    "aarch64-unknown-linux-musl",
+    "armv7-unknown-linux-gnueabihf",
+    "armv7-unknown-linux-musleabihf",
+    "riscv64gc-unknown-linux-gnu",
+    "riscv64gc-unknown-linux-musl",
+    "loongarch64-unknown-linux-gnu",
+    "x86_64-apple-darwin",
+    "aarch64-apple-darwin",
+    "x86_64-pc-windows-msvc",
+    "aarch64-pc-windows-msvc",
+    "x86_64-unknown-freebsd",
+];
+
+/// Prebuilt artifacts available for a crate, see [`probe`].
+pub struct Probe {
+    pub name: CompactString,
+    pub version: CompactString,
+    /// One entry per target of `opts.desired_targets`, in the same order.
+    pub targets: Vec<TargetProbe>,
+}
+
+pub struct TargetProbe {
+    pub target: String,
+    /// Fetchers which found an artifact for the target, ordered by
+    /// preference as in `opts.resolvers`.
+    ///
+    /// Empty if no prebuilt artifact is available for the target.
+    pub found: Vec<Arc<dyn Fetcher>>,
+    /// Fetchers which failed to check for an artifact, e.g. due to an
+    /// invalid `pkg-url` template.
+    pub failed: Vec<(Arc<dyn Fetcher>, BinstallError)>,
+    /// Result of downloading, extracting and verifying the artifact of the
+    /// first fetcher in `found`, with the number of binaries found in it.
+    ///
+    /// Only set by [`check_release`].
+    pub verified: Option<Result<usize, BinstallError>>,
+    /// Result of checking the artifact against the checksum published next
+    /// to it, as a `.sha256` or `.sha512` asset, with the name of the
+    /// algorithm.
+    ///
+    /// Only set by [`check_release`], if the artifact is downloaded and a
+    /// checksum is published.
+    pub checksum: Option<Result<&'static str, BinstallError>>,
+}
+
+impl TargetProbe {
+    pub fn is_available(&self) -> bool {
+        !self.found.is_empty()
+    }
+}
+
+/// Check which of `opts.desired_targets` have prebuilt artifacts available
+/// for the crate, without downloading them.
+///
+/// Fetchers which fail to check for an artifact are recorded in
+/// [`TargetProbe::failed`] and treated as not having found it.
+#[instrument(skip_all)]
+pub async fn probe(opts: Arc<Options>, crate_name: CrateName) -> Result<Probe, BinstallError> {
+    let name = crate_name.name.clone();
+    probe_inner(opts, crate_name, false)
+        .await
+        .map_err(|err| err.crate_context(name))
+}
+
+/// Same as [`probe`], but also downloads the artifact of each target and
+/// verifies that it contains the binaries of the crate.
+///
+/// Intended as a sanity check of a release before announcing it.
+#[instrument(skip_all)]
+pub async fn check_release(
+    opts: Arc<Options>,
+    crate_name: CrateName,
+) -> Result<Probe, BinstallError> {
+    let name = crate_name.name.clone();
+    probe_inner(opts, crate_name, true)
+        .await
+        .map_err(|err| err.crate_context(name))
+}
+
+async fn probe_inner(
+    opts: Arc<Options>,
+    crate_name: CrateName,
+    verify: bool,
+) -> Result<Probe, BinstallError> {
+    info!("Probing package: '{}'", crate_name);
+
+    let version_req = match (crate_name.version_req, &opts.version_req) {
+        (Some(version), None) => version,
+        (None, Some(version)) => version.clone(),
+        (Some(_), Some(_)) => Err(BinstallError::SuperfluousVersionOption)?,
+        (None, None) => VersionReq::STAR,
+    };
+
+    let package_info = PackageInfo::resolve(
+        &opts,
+        crate_name.name,
+        None,
+        &version_req,
+        opts.client.clone(),
+    )
+    .await?
+    .expect("PackageInfo::resolve only returns None if curr_version is Some");
+
+    let desired_targets = opts.desired_targets.get().await;
+
+    let data = Arc::new(
+        Data::new(
+            package_info.name.clone(),
+            package_info.version_str.clone(),
+            package_info.repo.clone(),
+        )
+        .with_deterministic(opts.deterministic),
+    );
+
+    let handles: Vec<_> = create_fetchers(&opts, &package_info, &data, desired_targets)?
+        .map(|fetcher| (fetcher.clone(), AutoAbortJoinHandle::new(fetcher.find())))
+        .collect();
+
+    let mut targets: Vec<TargetProbe> = desired_targets
+        .iter()
+        .map(|target| TargetProbe {
+            target: target.clone(),
+            found: Vec::new(),
+            failed: Vec::new(),
+            verified: None,
+            checksum: None,
+        })
+        .collect();
+
+    for (fetcher, handle) in handles {
+        let Some(target_probe) = targets.iter_mut().find(|t| t.target == fetcher.target()) else {
+            continue;
+        };
+
+        match handle.flattened_join().await {
+            Ok(true) => target_probe.found.push(fetcher),
+            Ok(false) => (),
+            Err(err) => {
+                warn!(
+                    "Error while checking fetcher {} for target {}: {}",
+                    fetcher.source_name(),
+                    fetcher.target(),
+                    err
+                );
+                target_probe.failed.push((fetcher, err));
+            }
+        }
+    }
+
+    if verify {
+        for target_probe in &mut targets {
+            let Some(fetcher) = target_probe.found.first() else {
+                continue;
+            };
+
+            let bin_path = opts.temp_dir.join(format!(
+                "check-{}-{}-{}",
+                package_info.name,
+                fetcher.target(),
+                fetcher.fetcher_name()
+            ));
+
+            let artifact_path = bin_path.with_extension("artifact");
+
+            let verified = download_extract_and_verify(
+                fetcher.as_ref(),
+                &bin_path,
+                Some(&artifact_path),
+                &package_info,
+                &opts,
+            )
+            .await
+            .map(|(bin_files, _)| bin_files.len());
+
+            if let (Ok(_), Some(url)) = (&verified, fetcher.download_url()) {
+                target_probe.checksum = verify_checksum(&opts.client, url, &artifact_path)
+                    .await
+                    .transpose();
+            }
+            target_probe.verified = Some(verified);
+        }
+    }
+
+    Ok(Probe {
+        name: package_info.name,
+        version: package_info.version_str,
+        targets,
+    })
+}
+
+/// Check the artifact downloaded from `url` to `artifact_path` against the
+/// checksum published at `url` with a `.sha256` or `.sha512` suffix, as
+/// output by `sha256sum` and `sha512sum`.
+///
+/// Return the name of the algorithm of the checksum, or `None` if there is
+/// no checksum published.
+async fn verify_checksum(
+    client: &Client,
+    url: &Url,
+    artifact_path: &Path,
+) -> Result<Option<&'static str>, BinstallError> {
+    let Some(checksum) = PublishedChecksum::fetch(client, url).await? else {
+        return Ok(None);
+    };
+
+    let algorithm = checksum.algorithm;
+    let artifact_path = artifact_path.to_owned();
+    spawn_blocking(move || verify_checksum_file(&checksum, &artifact_path)).await??;
+
+    Ok(Some(algorithm))
+}
+
+fn verify_checksum_file(
+    checksum: &PublishedChecksum,
+    artifact_path: &Path,
+) -> Result<(), BinstallError> {
+    let data = Bytes::from(fs::read(artifact_path)?);
+    checksum
+        .verify(&data)
+        .map_err(|err| DownloadError::from(err).into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_verify_checksum_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let artifact_path = dir.path().join("foo.tgz");
+        fs::write(&artifact_path, b"abc").unwrap();
+        let verify = |algorithm, checksum: &[u8]| {
+            verify_checksum_file(
+                &PublishedChecksum::parse(algorithm, checksum),
+                &artifact_path,
+            )
+        };
+
+        let sha256 = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        verify("sha256", sha256.as_bytes()).unwrap();
+        verify(
+            "sha256",
+            format!("{}  foo.tgz\n", sha256.to_uppercase()).as_bytes(),
+        )
+        .unwrap();
+
+        let err = verify("sha256", &[b'0'; 64]).unwrap_err();
+        assert_eq!(err.exit_number(), 107);
+
+        let sha512 = "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+            2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f";
+        verify("sha512", sha512.as_bytes()).unwrap();
+        verify("sha512", sha256.as_bytes()).unwrap_err();
+    }
+}
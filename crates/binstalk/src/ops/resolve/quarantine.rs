@@ -0,0 +1,155 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use compact_str::CompactString;
+use serde::Serialize;
+use tokio::task::spawn_blocking;
+use tracing::{info, warn};
+
+use crate::{errors::BinstallError, fetchers::Fetcher};
+
+/// Sidecar written next to a quarantined artifact.
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct QuarantineInfo {
+    #[serde(rename = "crate")]
+    crate_name: CompactString,
+    version: CompactString,
+    target: String,
+    source: CompactString,
+    fetcher: &'static str,
+    url: Option<String>,
+    reason: String,
+    /// Unix timestamp of the quarantine.
+    quarantined_at: u64,
+}
+
+/// Return true if `err` means the artifact has been downloaded and extracted
+/// but failed verification, so it is worth quarantining.
+pub(super) fn is_verification_error(err: &BinstallError) -> bool {
+    matches!(
+        err,
+        BinstallError::BinFile(_) | BinstallError::DuplicateSourceFilePath { .. }
+    )
+}
+
+fn copy_dir_all(src: &Path, dst: &Path) -> io::Result<()> {
+    fs::create_dir_all(dst)?;
+
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let dst = dst.join(entry.file_name());
+
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&entry.path(), &dst)?;
+        } else {
+            fs::copy(entry.path(), dst)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn do_quarantine(
+    quarantine_dir: &Path,
+    extracted: &Path,
+    info: &QuarantineInfo,
+) -> io::Result<PathBuf> {
+    let dst = quarantine_dir.join(format!(
+        "{}-{}-{}-{}-{}",
+        info.crate_name, info.version, info.target, info.fetcher, info.quarantined_at
+    ));
+
+    fs::create_dir_all(quarantine_dir)?;
+
+    // The temporary directory may be on another filesystem, fallback to
+    // copying it.
+    if fs::rename(extracted, &dst).is_err() {
+        copy_dir_all(extracted, &dst)?;
+        fs::remove_dir_all(extracted)?;
+    }
+
+    fs::write(
+        dst.with_extension("json"),
+        serde_json::to_vec_pretty(info).map_err(io::Error::from)?,
+    )?;
+
+    Ok(dst)
+}
+
+/// Move the artifact of `fetcher` extracted to `extracted` into
+/// `quarantine_dir`, along with a `.json` sidecar describing it, instead
+/// of deleting it with the temporary directory.
+///
+/// Failures are logged and ignored.
+pub(super) async fn quarantine(
+    quarantine_dir: &Path,
+    fetcher: &dyn Fetcher,
+    crate_name: &str,
+    version: &str,
+    extracted: &Path,
+    reason: String,
+) {
+    let info = QuarantineInfo {
+        crate_name: crate_name.into(),
+        version: version.into(),
+        target: fetcher.target().to_string(),
+        source: fetcher.source_name(),
+        fetcher: fetcher.fetcher_name(),
+        url: fetcher.download_url().map(ToString::to_string),
+        reason,
+        quarantined_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default(),
+    };
+
+    let quarantine_dir = quarantine_dir.to_owned();
+    let extracted = extracted.to_owned();
+
+    match spawn_blocking(move || do_quarantine(&quarantine_dir, &extracted, &info)).await {
+        Ok(Ok(dst)) => info!(
+            "The artifact from {} failed verification and is quarantined at {}",
+            fetcher.source_name(),
+            dst.display()
+        ),
+        Ok(Err(err)) => warn!("Failed to quarantine the artifact: {err}"),
+        Err(err) => warn!("Failed to quarantine the artifact: {err}"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_do_quarantine() {
+        let dir = tempfile::tempdir().unwrap();
+        let extracted = dir.path().join("extracted");
+        fs::create_dir_all(extracted.join("sub")).unwrap();
+        fs::write(extracted.join("sub/bin"), "bin").unwrap();
+
+        let info = QuarantineInfo {
+            crate_name: "foo".into(),
+            version: "1.0.0".into(),
+            target: "x86_64-unknown-linux-gnu".to_string(),
+            source: "github.com".into(),
+            fetcher: "GhCrateMeta",
+            url: None,
+            reason: "bin not found".to_string(),
+            quarantined_at: 1,
+        };
+
+        let quarantine_dir = dir.path().join("quarantine");
+        let dst = do_quarantine(&quarantine_dir, &extracted, &info).unwrap();
+
+        assert!(!extracted.exists());
+        assert_eq!(fs::read(dst.join("sub/bin")).unwrap(), b"bin");
+        assert!(fs::read_to_string(dst.with_extension("json"))
+            .unwrap()
+            .contains("\"reason\": \"bin not found\""));
+    }
+}
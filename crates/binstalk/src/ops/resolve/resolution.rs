@@ -1,19 +1,41 @@
-use std::{borrow::Cow, env, ffi::OsStr, fmt, iter, path::Path, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    env,
+    ffi::OsStr,
+    fmt, fs, iter,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use command_group::AsyncCommandGroup;
 use compact_str::{CompactString, ToCompactString};
 use either::Either;
 use itertools::Itertools;
 use semver::Version;
-use tokio::process::Command;
-use tracing::{debug, error, info, warn};
+use serde_json::json;
+use tempfile::TempDir;
+use tokio::{process::Command, task::spawn_blocking};
+use tracing::{debug, warn};
+use url::Url;
 
 use crate::{
     bins,
-    errors::BinstallError,
+    errors::{BinstallError, VersionParseError},
     fetchers::Fetcher,
-    manifests::crate_info::{CrateInfo, CrateSource},
-    ops::Options,
+    helpers::{
+        cargo_toml_workspace::find_manifest_path_in_workspace, download::Download, message::Message,
+    },
+    manifests::{
+        cargo_toml_binstall::PkgFmt,
+        crate_info::{CrateInfo, CrateSource},
+    },
+    ops::{
+        build_cache::BuildKey,
+        event::InstallEvent,
+        verify::{bin_digest_from_sha256, compute_bin_digest},
+        Options,
+    },
 };
 
 pub struct ResolutionFetch {
@@ -21,12 +43,36 @@ pub struct ResolutionFetch {
     pub new_version: Version,
     pub name: CompactString,
     pub version_req: CompactString,
+    /// Repository of the crate, if any.
+    pub repo: Option<String>,
     pub bin_files: Vec<bins::BinFile>,
+    /// Sha256 digests of the binaries computed while extracting them, by
+    /// source path.
+    pub bin_sha256: HashMap<PathBuf, [u8; 32]>,
+    /// The artifact downloaded, as is, if
+    /// [`Options::keep_artifacts`](crate::ops::Options::keep_artifacts)
+    /// is set. It is removed along with the temporary directory.
+    pub artifact: Option<PathBuf>,
+    /// Artifacts of the preferred fetchers which were found but failed to
+    /// download, extract or verify, before `fetcher` succeeded.
+    pub failed_fetches: Vec<FailedFetch>,
+}
+
+/// An artifact which was found but failed to download, extract or verify.
+pub struct FailedFetch {
+    pub fetcher: Arc<dyn Fetcher>,
+    pub reason: String,
 }
 
 pub struct ResolutionSource {
     pub name: CompactString,
     pub version: CompactString,
+    pub version_req: CompactString,
+    /// Repository of the crate, if any.
+    pub repo: Option<String>,
+    /// Source archive of the release tag to build from, instead of
+    /// the crate published on the registry.
+    pub source_archive: Option<Url>,
 }
 
 pub enum Resolution {
@@ -47,6 +93,33 @@ impl Resolution {
             Resolution::AlreadyUpToDate => (),
         }
     }
+
+    /// Describe the resolution as JSON, for the machine-readable
+    /// interfaces, e.g. the JSON-RPC server and the C API.
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Resolution::Fetch(fetch) => json!({
+                "status": "fetch",
+                "name": fetch.name,
+                "version": fetch.new_version.to_string(),
+                "source": fetch.fetcher.source_name(),
+                "third-party": fetch.fetcher.is_third_party(),
+                "target": fetch.fetcher.target(),
+                "bins": fetch
+                    .bin_files
+                    .iter()
+                    .map(|bin| bin.base_name.as_str())
+                    .collect::<Vec<_>>(),
+            }),
+            Resolution::InstallFromSource(source) => json!({
+                "status": "source",
+                "name": source.name,
+                "version": source.version,
+                "source-archive": source.source_archive.as_ref().map(|url| url.as_str()),
+            }),
+            Resolution::AlreadyUpToDate => json!({ "status": "up-to-date" }),
+        }
+    }
 }
 
 impl ResolutionFetch {
@@ -62,7 +135,18 @@ impl ResolutionFetch {
             ),
         };
 
-        info!("Installing binaries...");
+        if let Some(patch_elf) = &opts.patch_elf {
+            for file in &self.bin_files {
+                patch_elf
+                    .patch(&file.source)
+                    .map_err(|err| BinstallError::PatchElf {
+                        path: file.source.clone(),
+                        err,
+                    })?;
+            }
+        }
+
+        Message::new("binstall.installing-binaries", "Installing binaries...").info();
         for file in &self.bin_files {
             install_bin(file)?;
         }
@@ -74,7 +158,32 @@ impl ResolutionFetch {
             }
         }
 
-        Ok(CrateInfo {
+        let bin_digests = self
+            .bin_files
+            .iter()
+            .filter_map(|file| {
+                // The digests computed while extracting are only reused if
+                // the binaries are not patched since.
+                let res = match self.bin_sha256.get(&file.source) {
+                    Some(sha256) if opts.patch_elf.is_none() => {
+                        bin_digest_from_sha256(&file.dest, sha256)
+                    }
+                    _ => compute_bin_digest(&file.dest),
+                };
+                match res {
+                    Ok(digest) => Some(digest),
+                    Err(err) => {
+                        warn!(
+                            "Failed to compute the digest of {}: {err}",
+                            file.dest.display()
+                        );
+                        None
+                    }
+                }
+            })
+            .collect();
+
+        let crate_info = CrateInfo {
             name: self.name,
             version_req: self.version_req,
             current_version: self.new_version,
@@ -85,7 +194,19 @@ impl ResolutionFetch {
                 .into_iter()
                 .map(|bin| bin.base_name)
                 .collect(),
-        })
+            bin_digests,
+            artifact_url: self
+                .fetcher
+                .download_url()
+                .map(|url| url.as_str().to_compact_string()),
+            environment: None,
+        };
+
+        opts.emit(InstallEvent::Installed {
+            crate_info: &crate_info,
+        });
+
+        Ok(crate_info)
     }
 
     pub fn print(&self, opts: &Options) {
@@ -100,32 +221,88 @@ impl ResolutionFetch {
             fetcher.source_name(),
         );
 
-        warn!(
-            "The package {name} v{new_version} ({target}) has been downloaded from {}{}",
-            if fetcher.is_third_party() {
-                "third-party source "
-            } else {
-                ""
-            },
-            fetcher.source_name()
-        );
+        for failed in &self.failed_fetches {
+            let source = match failed.fetcher.download_url() {
+                Some(url) => format!("{} ({url})", failed.fetcher.source_name()),
+                None => failed.fetcher.source_name().to_string(),
+            };
+            Message::new(
+                "binstall.artifact-rejected",
+                "The artifact of { name } v{ version } ({ target }) from { source } was rejected \
+                and another source is used instead, please report it to the upstream: { reason }",
+            )
+            .arg("name", name.as_str())
+            .arg("version", new_version.to_string())
+            .arg("target", failed.fetcher.target())
+            .arg("source", source)
+            .arg("reason", failed.reason.to_string())
+            .warn();
+        }
 
-        info!("This will install the following binaries:");
+        let message = if fetcher.is_third_party() {
+            Message::new(
+                "binstall.downloaded-from-third-party",
+                "The package { name } v{ version } ({ target }) has been downloaded from \
+                third-party source { source }",
+            )
+        } else {
+            Message::new(
+                "binstall.downloaded-from",
+                "The package { name } v{ version } ({ target }) has been downloaded from { source }",
+            )
+        };
+        message
+            .arg("name", name.as_str())
+            .arg("version", new_version.to_string())
+            .arg("target", target)
+            .arg("source", fetcher.source_name())
+            .warn();
+
+        if fetcher.is_degraded() {
+            Message::new(
+                "binstall.found-by-probing",
+                "GitHub API could not be used to find { name } v{ version } ({ target }), \
+                its artifact was found by probing the urls rendered from templates instead",
+            )
+            .arg("name", name.as_str())
+            .arg("version", new_version.to_string())
+            .arg("target", target)
+            .warn();
+        }
+
+        Message::new(
+            "binstall.will-install-binaries",
+            "This will install the following binaries:",
+        )
+        .info();
         for file in bin_files {
-            info!("  - {}", file.preview_bin());
+            Message::new("binstall.will-install-binary", "  - { binary }")
+                .arg("binary", file.preview_bin().to_string())
+                .info();
         }
 
         if !opts.no_symlinks {
-            info!("And create (or update) the following symlinks:");
+            Message::new(
+                "binstall.will-create-symlinks",
+                "And create (or update) the following symlinks:",
+            )
+            .info();
             for file in bin_files {
-                info!("  - {}", file.preview_link());
+                Message::new("binstall.will-create-symlink", "  - { symlink }")
+                    .arg("symlink", file.preview_link().to_string())
+                    .info();
             }
         }
     }
 }
 
 impl ResolutionSource {
-    pub async fn install(self, opts: Arc<Options>) -> Result<(), BinstallError> {
+    /// Build and install the crate with `cargo install`.
+    ///
+    /// If the build cache is enabled, the binaries built are instead
+    /// installed like prebuilt ones, so the crate info to record in the
+    /// manifests is returned.
+    pub async fn install(self, opts: Arc<Options>) -> Result<Option<CrateInfo>, BinstallError> {
         let desired_targets = opts.desired_targets.get().await;
         let target = desired_targets
             .first()
@@ -134,6 +311,48 @@ impl ResolutionSource {
         let name = &self.name;
         let version = &self.version;
 
+        let build_cache = opts
+            .build_cache
+            .as_ref()
+            .filter(|_| !opts.dry_run)
+            .map(|build_cache| {
+                let source = match &self.source_archive {
+                    Some(source_archive) => source_archive.to_string(),
+                    None => opts.registry.to_string(),
+                };
+                let key = BuildKey::new(
+                    name,
+                    version,
+                    target,
+                    &source,
+                    opts.locked,
+                    opts.build_env.get(name.as_str()),
+                );
+                (build_cache, key)
+            });
+
+        if let Some((build_cache, key)) = &build_cache {
+            match build_cache.get(&opts.client, key).await {
+                Ok(Some(bins)) => {
+                    Message::new(
+                        "binstall.installing-from-build-cache",
+                        "Installing { name } v{ version } from the build cache",
+                    )
+                    .arg("name", name.as_str())
+                    .arg("version", version.as_str())
+                    .info();
+
+                    let crate_info = self.install_built(opts.clone(), target, bins).await?;
+                    opts.emit(InstallEvent::Installed {
+                        crate_info: &crate_info,
+                    });
+                    return Ok(Some(crate_info));
+                }
+                Ok(None) => debug!("Build {key} of {name} v{version} is not cached"),
+                Err(err) => warn!("Failed to look up {name} v{version} in the build cache: {err}"),
+            }
+        }
+
         let cargo = env::var_os("CARGO")
             .map(Cow::Owned)
             .unwrap_or_else(|| Cow::Borrowed(OsStr::new("cargo")));
@@ -145,13 +364,45 @@ impl ResolutionSource {
 
         let mut cmd = Command::new(cargo);
 
-        cmd.arg("install")
-            .arg(name)
-            .arg("--version")
-            .arg(version)
-            .arg("--target")
-            .arg(target)
-            .kill_on_drop(true);
+        cmd.arg("install");
+
+        // Keep the extracted source alive until cargo is done.
+        let _source_dir = match &self.source_archive {
+            Some(source_archive) if !opts.dry_run => {
+                let source_dir = TempDir::new_in(&opts.temp_dir)?;
+                let crate_path =
+                    download_source_archive(&opts, source_archive, name, source_dir.path()).await?;
+
+                cmd.arg("--path").arg(crate_path);
+
+                Some(source_dir)
+            }
+            Some(source_archive) => {
+                Message::new(
+                    "binstall.dry-run-source-archive",
+                    "Dry-run: not downloading source archive { url }",
+                )
+                .arg("url", source_archive.as_str())
+                .info();
+
+                cmd.arg(name).arg("--version").arg(version);
+
+                None
+            }
+            None => {
+                cmd.arg(name).arg("--version").arg(version);
+
+                // Build the same `.crate` whose checksum has been verified
+                // against the index binstall resolved the crate from.
+                if !opts.registry.is_crates_io() {
+                    cmd.arg("--index").arg(opts.registry.to_string());
+                }
+
+                None
+            }
+        };
+
+        cmd.arg("--target").arg(target).kill_on_drop(true);
 
         if opts.quiet {
             cmd.arg("--quiet");
@@ -165,12 +416,34 @@ impl ResolutionSource {
             cmd.arg("--locked");
         }
 
-        if let Some(cargo_root) = &opts.cargo_root {
-            cmd.arg("--root").arg(cargo_root);
+        // The binaries to cache are built into a temporary root, then
+        // installed and recorded like prebuilt ones.
+        let build_root = match build_cache {
+            Some(_) => Some(TempDir::new_in(&opts.temp_dir)?),
+            None => None,
+        };
+
+        if let Some(build_root) = &build_root {
+            cmd.arg("--root").arg(build_root.path()).arg("--no-track");
+        } else {
+            if let Some(cargo_root) = &opts.cargo_root {
+                cmd.arg("--root").arg(cargo_root);
+            }
+
+            if opts.no_track {
+                cmd.arg("--no-track");
+            }
         }
 
-        if opts.no_track {
-            cmd.arg("--no-track");
+        if let Some(vars) = opts.build_env.get(name.as_str()) {
+            debug!(
+                "Setting {} for the build of {name}",
+                vars.keys().format(", ")
+            );
+            cmd.envs(
+                vars.iter()
+                    .map(|(var, value)| (var.as_str(), value.as_str())),
+            );
         }
 
         if !opts.dry_run {
@@ -184,26 +457,181 @@ impl ResolutionSource {
 
             let status = child.wait().await?;
             if status.success() {
-                info!("Cargo finished successfully");
-                Ok(())
+                Message::new("binstall.cargo-finished", "Cargo finished successfully").info();
+
+                opts.emit(InstallEvent::InstalledFromSource {
+                    crate_name: name,
+                    version,
+                });
+
+                let (Some((build_cache, key)), Some(build_root)) = (build_cache, build_root) else {
+                    return Ok(None);
+                };
+
+                let bin_dir = build_root.path().join("bin");
+                let bins = match build_cache.put(&opts.client, &key, bin_dir.clone()).await {
+                    Ok(bins) => bins,
+                    Err(err) => {
+                        warn!("Failed to cache the build of {name} v{version}: {err}");
+                        fs::read_dir(&bin_dir)?
+                            .map(|entry| entry.map(|entry| entry.path()))
+                            .collect::<Result<_, _>>()?
+                    }
+                };
+
+                self.install_built(opts.clone(), target, bins)
+                    .await
+                    .map(Some)
             } else {
-                error!("Cargo errored! {status:?}");
+                Message::new("binstall.cargo-failed", "Cargo errored! { status }")
+                    .arg("status", format!("{status:?}"))
+                    .error();
                 Err(BinstallError::SubProcess {
                     command: format_cmd(&cmd).to_string().into_boxed_str(),
                     status,
                 })
             }
         } else {
-            info!("Dry-run: running `{}`", format_cmd(&cmd));
-            Ok(())
+            Message::new("binstall.dry-run-cargo", "Dry-run: running `{ command }`")
+                .arg("command", format_cmd(&cmd).to_string())
+                .info();
+            Ok(None)
         }
     }
 
+    /// Install `bins`, the binaries built, like prebuilt ones and return
+    /// the crate info to record.
+    async fn install_built(
+        self,
+        opts: Arc<Options>,
+        target: &str,
+        bins: Vec<PathBuf>,
+    ) -> Result<CrateInfo, BinstallError> {
+        let target = target.to_compact_string();
+
+        spawn_blocking(move || {
+            type InstallFp = fn(&bins::BinFile) -> Result<(), bins::Error>;
+
+            let install_bin: InstallFp = if opts.no_track && !opts.force {
+                bins::BinFile::install_bin_noclobber
+            } else {
+                bins::BinFile::install_bin
+            };
+
+            // The binaries are moved into place, so copy them first to keep
+            // the cached ones.
+            let staging_dir = TempDir::new_in(&opts.temp_dir)?;
+
+            let mut bin_names = Vec::with_capacity(bins.len());
+            let mut bin_digests = Vec::with_capacity(bins.len());
+
+            for bin in bins {
+                let Some(file_name) = bin.file_name() else {
+                    continue;
+                };
+                let file_name = file_name.to_string_lossy();
+                let source = staging_dir.path().join(&*file_name);
+                fs::copy(&bin, &source)?;
+
+                let bin_file = bins::BinFile {
+                    base_name: file_name.strip_suffix(".exe").unwrap_or(&file_name).into(),
+                    archive_source_path: source.clone(),
+                    source,
+                    dest: opts.install_path.join(&*file_name),
+                    link: None,
+                    shim: None,
+                };
+                install_bin(&bin_file)?;
+
+                match compute_bin_digest(&bin_file.dest) {
+                    Ok(digest) => bin_digests.push(digest),
+                    Err(err) => warn!(
+                        "Failed to compute the digest of {}: {err}",
+                        bin_file.dest.display()
+                    ),
+                }
+                bin_names.push(bin_file.base_name);
+            }
+
+            let current_version = Version::parse(&self.version).map_err(|err| {
+                BinstallError::VersionParse(Box::new(VersionParseError {
+                    v: self.version.clone(),
+                    err,
+                }))
+            })?;
+
+            Ok(CrateInfo {
+                name: self.name,
+                version_req: self.version_req,
+                current_version,
+                source: CrateSource::cratesio_registry(),
+                target,
+                bins: bin_names,
+                bin_digests,
+                artifact_url: None,
+                environment: None,
+            })
+        })
+        .await?
+    }
+
     pub fn print(&self) {
-        warn!(
-            "The package {} v{} will be installed from source (with cargo)",
-            self.name, self.version
-        )
+        let message = if let Some(source_archive) = &self.source_archive {
+            Message::new(
+                "binstall.will-install-from-source-archive",
+                "The package { name } v{ version } will be installed from source archive \
+                { url } (with cargo)",
+            )
+            .arg("url", source_archive.as_str())
+        } else {
+            Message::new(
+                "binstall.will-install-from-source",
+                "The package { name } v{ version } will be installed from source (with cargo)",
+            )
+        };
+        message
+            .arg("name", self.name.as_str())
+            .arg("version", self.version.as_str())
+            .warn();
+    }
+}
+
+/// Download and extract the source archive into `dir`, then return path
+/// to the directory of the crate inside it.
+async fn download_source_archive(
+    opts: &Options,
+    source_archive: &Url,
+    name: &str,
+    dir: &Path,
+) -> Result<PathBuf, BinstallError> {
+    debug!("Downloading source archive from {source_archive}");
+
+    Download::new(opts.client.clone(), source_archive.clone())
+        .and_extract(PkgFmt::Tgz, dir)
+        .await?;
+
+    let name = name.to_owned();
+    let dir = dir.to_owned();
+
+    spawn_blocking(move || {
+        let manifest_path = find_manifest_path_in_workspace(source_archive_root(&dir)?, name)?;
+
+        Ok::<_, BinstallError>(manifest_path.parent().unwrap().to_owned())
+    })
+    .await?
+}
+
+/// Source archives usually have a single top-level directory, e.g.
+/// `{repo}-{tag}/`, which contains the workspace.
+fn source_archive_root(dir: &Path) -> Result<PathBuf, BinstallError> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|res| res.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if entries.len() == 1 && entries[0].is_dir() {
+        Ok(entries.pop().unwrap())
+    } else {
+        Ok(dir.to_owned())
     }
 }
 
@@ -1,5 +1,5 @@
 use compact_str::format_compact;
-use semver::{Prerelease, Version, VersionReq};
+use semver::{Op, Prerelease, Version, VersionReq};
 
 /// Extension trait for [`VersionReq`].
 pub trait VersionReqExt {
@@ -7,6 +7,9 @@ pub trait VersionReqExt {
     /// and the `version` is the latest one acceptable by `self`.
     fn is_latest_compatible(&self, version: &Version) -> bool;
 
+    /// Return `true` if `self` matches a single version, e.g. `=1.2.3`.
+    fn is_pinned(&self) -> bool;
+
     /// Parse from CLI option.
     ///
     /// Notably, a bare version is treated as if preceded by `=`, not by `^` as in Cargo.toml
@@ -51,6 +54,17 @@ impl VersionReqExt for VersionReq {
         true
     }
 
+    fn is_pinned(&self) -> bool {
+        match &self.comparators[..] {
+            [comparator] => {
+                comparator.op == Op::Exact
+                    && comparator.minor.is_some()
+                    && comparator.patch.is_some()
+            }
+            _ => false,
+        }
+    }
+
     fn parse_from_cli(version: &str) -> Result<Self, semver::Error> {
         if version
             .chars()
@@ -96,4 +110,15 @@ mod tests {
             .unwrap()
             .is_latest_compatible(&Version::parse("0.1.0-alpha").unwrap()));
     }
+
+    #[test]
+    fn test_is_pinned() {
+        for req in ["=0.1.0", "=0.1.0-alpha"] {
+            assert!(VersionReq::parse(req).unwrap().is_pinned(), "{req}");
+        }
+        for req in ["*", "0.1.0", "=0.1", ">=0.1.0", "=0.1.0, <1"] {
+            assert!(!VersionReq::parse(req).unwrap().is_pinned(), "{req}");
+        }
+        assert!(VersionReq::parse_from_cli("0.1.0").unwrap().is_pinned());
+    }
 }
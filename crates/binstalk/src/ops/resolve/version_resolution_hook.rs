@@ -0,0 +1,104 @@
+use compact_str::{CompactString, ToCompactString};
+use semver::{Version, VersionReq};
+
+use crate::{errors::BinstallError, registry::Registry};
+
+/// Context passed to [`VersionResolutionHook::check_version`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct VersionResolutionContext<'a> {
+    /// Name of the crate being resolved.
+    pub crate_name: &'a str,
+    /// Version requirement specified by the user, `*` if unspecified.
+    pub version_req: &'a VersionReq,
+    /// Version currently installed, if any.
+    pub current_version: Option<&'a Version>,
+    /// Version selected from the registry (or the local manifest/git repo).
+    pub resolved_version: &'a Version,
+    /// Repository of the crate, as specified in its `Cargo.toml`.
+    pub repo: Option<&'a str>,
+    /// Registry the crate is resolved from.
+    pub registry: &'a Registry,
+}
+
+/// Decision returned by [`VersionResolutionHook::check_version`].
+#[derive(Clone, Debug)]
+pub enum VersionDecision {
+    /// Install the resolved version.
+    Accept,
+    /// Refuse to install the resolved version.
+    Reject { reason: CompactString },
+    /// Resolve the crate again using this version requirement instead.
+    ///
+    /// The hook is not called again for the newly resolved version.
+    Replace(VersionReq),
+}
+
+impl VersionDecision {
+    /// Apply the decision on `version`, resolved with `version_req_str`.
+    ///
+    /// Return the version requirement to resolve the crate again with, if
+    /// replaced, in which case it replaces `version_req_str`, which is
+    /// recorded in the receipts.
+    pub(super) fn apply(
+        self,
+        version: &str,
+        version_req_str: &mut CompactString,
+    ) -> Result<Option<VersionReq>, BinstallError> {
+        match self {
+            VersionDecision::Accept => Ok(None),
+            VersionDecision::Reject { reason } => Err(BinstallError::VersionRejected {
+                version: version.into(),
+                reason,
+            }),
+            VersionDecision::Replace(version_req) => {
+                *version_req_str = version_req.to_compact_string();
+                Ok(Some(version_req))
+            }
+        }
+    }
+}
+
+/// Hook allowing embedders to intercept version resolution, e.g. to
+/// consult an internal approval database before allowing a version.
+#[async_trait::async_trait]
+pub trait VersionResolutionHook: Send + Sync {
+    /// Called once a version has been selected for the crate, before
+    /// any artifact is looked up.
+    async fn check_version(&self, ctx: VersionResolutionContext<'_>) -> VersionDecision;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_apply_version_decision() {
+        let mut version_req_str = CompactString::from("^1.2");
+
+        let replaced = VersionDecision::Accept
+            .apply("1.2.3", &mut version_req_str)
+            .unwrap();
+        assert!(replaced.is_none());
+        assert_eq!(version_req_str, "^1.2");
+
+        let err = VersionDecision::Reject {
+            reason: "not approved".into(),
+        }
+        .apply("1.2.3", &mut version_req_str)
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            BinstallError::VersionRejected { version, reason }
+                if version == "1.2.3" && reason == "not approved"
+        ));
+        assert_eq!(version_req_str, "^1.2");
+
+        let version_req = VersionReq::parse("=1.2.0").unwrap();
+        let replaced = VersionDecision::Replace(version_req.clone())
+            .apply("1.2.3", &mut version_req_str)
+            .unwrap();
+        assert_eq!(replaced, Some(version_req));
+        assert_eq!(version_req_str, "=1.2.0");
+    }
+}
@@ -0,0 +1,227 @@
+//! Verify the installed binaries against the digests recorded when they
+//! were installed.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use compact_str::CompactString;
+use sha2::{Digest, Sha256};
+
+use tracing::warn;
+
+use crate::{
+    errors::BinstallError,
+    helpers::hex,
+    manifests::crate_info::{BinDigest, CrateInfo},
+};
+
+fn mtime_ns(metadata: &fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+pub(super) fn sha256_file(path: &Path) -> io::Result<CompactString> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hex(hasher.finalize()).into())
+}
+
+/// Compute the digest of the binary installed at `path`.
+pub fn compute_bin_digest(path: &Path) -> io::Result<BinDigest> {
+    let metadata = fs::metadata(path)?;
+
+    Ok(BinDigest {
+        path: path.to_owned(),
+        sha256: sha256_file(path)?,
+        size: metadata.len(),
+        mtime_ns: mtime_ns(&metadata),
+    })
+}
+
+/// Return the digest of the binary installed at `path` whose content has
+/// the digest `sha256`, e.g. computed while extracting it, without reading
+/// it again.
+pub fn bin_digest_from_sha256(path: &Path, sha256: &[u8; 32]) -> io::Result<BinDigest> {
+    let metadata = fs::metadata(path)?;
+
+    Ok(BinDigest {
+        path: path.to_owned(),
+        sha256: hex(sha256).into(),
+        size: metadata.len(),
+        mtime_ns: mtime_ns(&metadata),
+    })
+}
+
+#[derive(Debug)]
+pub enum BinStatus {
+    /// The binary is the same as the one installed.
+    Unchanged,
+    /// The content of the binary differs from the one installed.
+    Modified,
+    Missing,
+    Unreadable(io::Error),
+}
+
+#[derive(Debug)]
+pub struct BinVerification {
+    pub path: PathBuf,
+    pub status: BinStatus,
+    /// Whether the binary was hashed again, which is skipped in quick mode
+    /// if its size and modification time are unchanged.
+    pub rehashed: bool,
+}
+
+/// Verify the binary recorded in `digest`.
+///
+/// If `quick` is true, it is only rehashed if its size or modification time
+/// changed since it was installed.
+pub fn verify_bin(digest: &BinDigest, quick: bool) -> BinVerification {
+    let path = digest.path.clone();
+
+    let metadata = match fs::metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(err) => {
+            let status = if err.kind() == io::ErrorKind::NotFound {
+                BinStatus::Missing
+            } else {
+                BinStatus::Unreadable(err)
+            };
+            return BinVerification {
+                path,
+                status,
+                rehashed: false,
+            };
+        }
+    };
+
+    if quick && metadata.len() == digest.size && mtime_ns(&metadata) == digest.mtime_ns {
+        return BinVerification {
+            path,
+            status: BinStatus::Unchanged,
+            rehashed: false,
+        };
+    }
+
+    let status = match sha256_file(&path) {
+        Ok(sha256) if sha256 == digest.sha256 => BinStatus::Unchanged,
+        Ok(_) => BinStatus::Modified,
+        Err(err) => BinStatus::Unreadable(err),
+    };
+
+    BinVerification {
+        path,
+        status,
+        rehashed: true,
+    }
+}
+
+/// Check that the binaries of `crate_info`, which are about to be
+/// overwritten, are not modified since they were installed.
+///
+/// If `overwrite_modified` is true, the binaries modified are only warned
+/// about.
+pub fn check_unmodified(
+    crate_info: &CrateInfo,
+    overwrite_modified: bool,
+) -> Result<(), BinstallError> {
+    for digest in &crate_info.bin_digests {
+        let verification = verify_bin(digest, true);
+        if !matches!(verification.status, BinStatus::Modified) {
+            continue;
+        }
+
+        if overwrite_modified {
+            warn!(
+                "{} is modified since it was installed, overwriting it",
+                verification.path.display()
+            );
+        } else {
+            return Err(BinstallError::ModifiedBinary(verification.path));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_verify_bin() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("foo");
+
+        fs::write(&path, b"foo").unwrap();
+        let mut digest = compute_bin_digest(&path).unwrap();
+        assert_eq!(
+            digest.sha256,
+            "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae"
+        );
+
+        let verification = verify_bin(&digest, true);
+        assert!(matches!(verification.status, BinStatus::Unchanged));
+        assert!(!verification.rehashed);
+
+        // Quick mode trusts the metadata, full mode always rehashes.
+        digest.sha256 = "0".repeat(64).into();
+        assert!(matches!(
+            verify_bin(&digest, true).status,
+            BinStatus::Unchanged
+        ));
+        assert!(matches!(
+            verify_bin(&digest, false).status,
+            BinStatus::Modified
+        ));
+
+        digest.mtime_ns += 1;
+        let verification = verify_bin(&digest, true);
+        assert!(matches!(verification.status, BinStatus::Modified));
+        assert!(verification.rehashed);
+
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            verify_bin(&digest, true).status,
+            BinStatus::Missing
+        ));
+    }
+    #[test]
+    fn test_check_unmodified() {
+        use crate::manifests::crate_info::CrateSource;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("foo");
+        fs::write(&path, b"foo").unwrap();
+
+        let crate_info = CrateInfo {
+            name: "foo".into(),
+            version_req: "*".into(),
+            current_version: semver::Version::new(1, 0, 0),
+            source: CrateSource::cratesio_registry(),
+            target: "x86_64-unknown-linux-gnu".into(),
+            bins: vec!["foo".into()],
+            bin_digests: vec![compute_bin_digest(&path).unwrap()],
+            artifact_url: None,
+            environment: None,
+        };
+        check_unmodified(&crate_info, false).unwrap();
+
+        fs::write(&path, b"patched").unwrap();
+        assert!(matches!(
+            check_unmodified(&crate_info, false),
+            Err(BinstallError::ModifiedBinary(modified)) if modified == path
+        ));
+        check_unmodified(&crate_info, true).unwrap();
+
+        // Binaries removed since are not in the way.
+        fs::remove_file(&path).unwrap();
+        check_unmodified(&crate_info, false).unwrap();
+    }
+}
@@ -24,11 +24,36 @@ pub fn load_manifest_from_workspace(
     crate_name: impl AsRef<str>,
 ) -> Result<Manifest<Meta>, Error> {
     fn inner(workspace_path: &Path, crate_name: &str) -> Result<Manifest<Meta>, Error> {
-        load_manifest_from_workspace_inner(workspace_path, crate_name).map_err(|inner| Error {
-            workspace_path: workspace_path.into(),
-            crate_name: crate_name.into(),
-            inner,
-        })
+        load_manifest_from_workspace_inner(workspace_path, crate_name)
+            .map(|(_manifest_path, manifest)| manifest)
+            .map_err(|inner| Error {
+                workspace_path: workspace_path.into(),
+                crate_name: crate_name.into(),
+                inner: Box::new(inner),
+            })
+    }
+
+    inner(workspace_path.as_ref(), crate_name.as_ref())
+}
+
+/// Find the path to `Cargo.toml` of the crate in the workspace at the provided path
+///
+/// WARNING: This is a blocking operation.
+///
+///  * `workspace_path` - can be a directory (path to workspace) or
+///    a file (path to `Cargo.toml`).
+pub fn find_manifest_path_in_workspace(
+    workspace_path: impl AsRef<Path>,
+    crate_name: impl AsRef<str>,
+) -> Result<PathBuf, Error> {
+    fn inner(workspace_path: &Path, crate_name: &str) -> Result<PathBuf, Error> {
+        load_manifest_from_workspace_inner(workspace_path, crate_name)
+            .map(|(manifest_path, _manifest)| manifest_path)
+            .map_err(|inner| Error {
+                workspace_path: workspace_path.into(),
+                crate_name: crate_name.into(),
+                inner: Box::new(inner),
+            })
     }
 
     inner(workspace_path.as_ref(), crate_name.as_ref())
@@ -40,7 +65,7 @@ pub struct Error {
     workspace_path: Box<Path>,
     crate_name: CompactString,
     #[source]
-    inner: ErrorInner,
+    inner: Box<ErrorInner>,
 }
 
 #[derive(Debug, ThisError)]
@@ -65,7 +90,7 @@ enum ErrorInner {
 fn load_manifest_from_workspace_inner(
     workspace_path: &Path,
     crate_name: &str,
-) -> Result<Manifest<Meta>, ErrorInner> {
+) -> Result<(PathBuf, Manifest<Meta>), ErrorInner> {
     debug!(
         "Loading manifest of crate {crate_name} from workspace: {}",
         workspace_path.display()
@@ -90,7 +115,7 @@ fn load_manifest_from_workspace_inner(
         );
 
         if name == Some(crate_name) {
-            return Ok(manifest);
+            return Ok((manifest_path, manifest));
         }
 
         if let Some(ws) = manifest.workspace {
@@ -261,5 +286,11 @@ mod test {
         assert_eq!(package.version.as_ref().unwrap(), "8.4.0");
         assert_eq!(manifest.bin.len(), 1);
         assert_eq!(manifest.bin[0].name.as_deref().unwrap(), "cargo-watch");
+
+        let manifest_path = find_manifest_path_in_workspace(&p, "cargo-watch").unwrap();
+        assert_eq!(
+            manifest_path,
+            p.join("crates/a/b/c/d/e/cargo-watch/Cargo.toml")
+        );
     }
 }
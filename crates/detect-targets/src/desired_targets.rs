@@ -1,12 +1,24 @@
-use crate::detect_targets;
+use crate::detect_targets_with_reasons;
 
 use std::sync::Arc;
 
 use tokio::sync::OnceCell;
 
+#[derive(Debug)]
+struct Detected {
+    targets: Vec<String>,
+    /// Reason of each target in `targets`.
+    reasons: Vec<&'static str>,
+}
+
+async fn detect() -> Detected {
+    let (targets, reasons) = detect_targets_with_reasons().await.into_iter().unzip();
+    Detected { targets, reasons }
+}
+
 #[derive(Debug)]
 enum DesiredTargetsInner {
-    AutoDetect(Arc<OnceCell<Vec<String>>>),
+    AutoDetect(Arc<OnceCell<Detected>>),
     Initialized(Vec<String>),
 }
 
@@ -23,7 +35,7 @@ impl DesiredTargets {
 
         let once_cell = arc.clone();
         tokio::spawn(async move {
-            once_cell.get_or_init(detect_targets).await;
+            once_cell.get_or_init(detect).await;
         });
 
         Self(DesiredTargetsInner::AutoDetect(arc))
@@ -38,7 +50,28 @@ impl DesiredTargets {
             // This will mostly just wait for the spawned task,
             // on rare occausion though, it will poll the future
             // returned by `detect_targets`.
-            AutoDetect(once_cell) => once_cell.get_or_init(detect_targets).await,
+            AutoDetect(once_cell) => &once_cell.get_or_init(detect).await.targets,
+        }
+    }
+
+    /// Return the targets along with the reason they are desired.
+    pub async fn get_with_reasons(&self) -> Vec<(&str, &'static str)> {
+        use DesiredTargetsInner::*;
+
+        match &self.0 {
+            Initialized(targets) => targets
+                .iter()
+                .map(|target| (target.as_str(), "specified by the user"))
+                .collect(),
+            AutoDetect(once_cell) => {
+                let detected = once_cell.get_or_init(detect).await;
+                detected
+                    .targets
+                    .iter()
+                    .map(String::as_str)
+                    .zip(detected.reasons.iter().copied())
+                    .collect()
+            }
         }
     }
 }
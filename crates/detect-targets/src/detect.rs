@@ -8,6 +8,10 @@ use std::{
 use cfg_if::cfg_if;
 use tokio::process::Command;
 
+mod windows_toolchain;
+#[cfg(target_os = "windows")]
+use windows_toolchain::WindowsToolchain;
+
 cfg_if! {
     if #[cfg(target_os = "linux")] {
         mod linux;
@@ -29,32 +33,61 @@ cfg_if! {
 /// If target_os is mac and it is aarch64, then aarch64 is preferred
 /// to x86_64.
 ///
+/// If target_os is windows, then the msvc targets are preferred to the gnu
+/// ones, unless `BINSTALL_WINDOWS_TOOLCHAIN` is set to `gnu`, it is run in
+/// a mingw environment like MSYS2 or the Visual C++ runtime is missing.
+///
 /// Check [this issue](https://github.com/ryankurte/cargo-binstall/issues/155)
 /// for more information.
 pub async fn detect_targets() -> Vec<String> {
-    let target = get_target_from_rustc().await.unwrap_or_else(|| {
-        guess_host_triple::guess_host_triple()
-            .unwrap_or(crate::TARGET)
-            .to_string()
-    });
+    detect_targets_with_reasons()
+        .await
+        .into_iter()
+        .map(|(target, _)| target)
+        .collect()
+}
+
+/// Same as [`detect_targets`], but also return why each target is
+/// considered supported, for reports and debugging.
+pub async fn detect_targets_with_reasons() -> Vec<(String, &'static str)> {
+    let (target, reason) = match get_target_from_rustc().await {
+        Some(target) => (target, "host target reported by `rustc -vV`"),
+        None => match guess_host_triple::guess_host_triple() {
+            Some(target) => (target.to_string(), "host target guessed from the system"),
+            None => (
+                crate::TARGET.to_string(),
+                "target binstall is built for, the host target cannot be detected",
+            ),
+        },
+    };
 
     cfg_if! {
         if #[cfg(target_os = "macos")] {
-            let mut targets = vec![target];
-            targets.extend(macos::detect_alternative_targets(&targets[0]).await);
-            targets
+            let alternatives = macos::detect_alternative_targets(&target).await;
+            [(target, reason)]
+                .into_iter()
+                .chain(alternatives.map(|target| (target, ALTERNATIVE_TARGET)))
+                .collect()
         } else if #[cfg(target_os = "windows")] {
-            let mut targets = vec![target];
-            targets.extend(windows::detect_alternative_targets(&targets[0]));
+            let toolchain = WindowsToolchain::detect(&target);
+            let alternatives = windows::detect_alternative_targets(&target);
+            let mut targets: Vec<_> = [(target, reason)]
+                .into_iter()
+                .chain(alternatives.map(|target| (target, ALTERNATIVE_TARGET)))
+                .collect();
+            toolchain.sort_targets(&mut targets);
             targets
         } else if #[cfg(target_os = "linux")] {
             // Linux is a bit special, since the result from `guess_host_triple`
             // might be wrong about whether glibc or musl is used.
-            linux::detect_targets(target).await
+            linux::detect_targets(target, reason).await
         }
     }
 }
 
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+const ALTERNATIVE_TARGET: &str = "the host is able to run binaries of this target";
+
 /// Figure out what the host target is using `rustc`.
 /// If `rustc` is absent, then it would return `None`.
 ///
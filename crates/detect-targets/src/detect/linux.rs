@@ -1,11 +1,18 @@
 use std::{
+    env,
+    fs::{self, File},
+    io::Read,
+    path::Path,
     process::{Output, Stdio},
     str,
 };
 
 use tokio::{process::Command, task};
 
-pub(super) async fn detect_targets(target: String) -> Vec<String> {
+pub(super) async fn detect_targets(
+    target: String,
+    reason: &'static str,
+) -> Vec<(String, &'static str)> {
     let (prefix, postfix) = target
         .rsplit_once('-')
         .expect("unwrap: target always has a -");
@@ -20,8 +27,6 @@ pub(super) async fn detect_targets(target: String) -> Vec<String> {
         (postfix, Libc::Unknown)
     };
 
-    let musl_fallback_target = || format!("{prefix}-{}{abi}", "musl");
-
     match libc {
         // guess_host_triple cannot detect whether the system is using glibc,
         // musl libc or other libc.
@@ -36,42 +41,336 @@ pub(super) async fn detect_targets(target: String) -> Vec<String> {
                 .expect("unwrap: target always has a - for cpu_arch")
                 .0;
 
-            let cpu_arch_suffix = cpu_arch.replace('_', "-");
-
-            let handles: Vec<_> = [
-                format!("/lib/ld-linux-{cpu_arch_suffix}.so.2"),
-                format!("/lib/{cpu_arch}-linux-gnu/ld-linux-{cpu_arch_suffix}.so.2"),
-                format!("/usr/lib/{cpu_arch}-linux-gnu/ld-linux-{cpu_arch_suffix}.so.2"),
-            ]
-            .into_iter()
-            .map(|p| AutoAbortHandle(tokio::spawn(is_gnu_ld(p))))
-            .collect();
-
-            let has_glibc = async move {
-                for mut handle in handles {
-                    if let Ok(true) = (&mut handle.0).await {
-                        return true;
-                    }
+            if cpu_arch == "aarch64" || cpu_arch.starts_with("arm") {
+                if let Some(targets) = detect_arm_targets(cpu_arch).await {
+                    return targets;
                 }
-
-                false
             }
-            .await;
+
+            let (multiarch, ld) = glibc_ld(cpu_arch);
+            let glibc = glibc_support(&multiarch, &ld).await;
 
             [
-                has_glibc.then(|| format!("{cpu_arch}-unknown-linux-gnu{abi}")),
-                Some(musl_fallback_target()),
+                glibc
+                    .gnu_reason
+                    .map(|reason| (format!("{cpu_arch}-unknown-linux-gnu{abi}"), reason)),
+                Some((format!("{prefix}-musl{abi}"), glibc.musl_reason)),
             ]
         }
-        Libc::Android | Libc::Unknown => [Some(target.clone()), Some(musl_fallback_target())],
+        Libc::Android | Libc::Unknown | Libc::Gcompat => [
+            Some((target.clone(), reason)),
+            Some((format!("{prefix}-musl{abi}"), MUSL_REASON)),
+        ],
     }
     .into_iter()
     .flatten()
     .collect()
 }
 
-async fn is_gnu_ld(cmd: String) -> bool {
-    get_ld_flavor(&cmd).await == Some(Libc::Gnu)
+/// Return the multiarch tuple and the name of the glibc dynamic loader of
+/// `cpu_arch`.
+fn glibc_ld(cpu_arch: &str) -> (String, String) {
+    match cpu_arch {
+        "riscv64gc" => (
+            "riscv64-linux-gnu".to_string(),
+            "ld-linux-riscv64-lp64d.so.1".to_string(),
+        ),
+        "loongarch64" => (
+            "loongarch64-linux-gnu".to_string(),
+            "ld-linux-loongarch-lp64d.so.1".to_string(),
+        ),
+        _ => (
+            format!("{cpu_arch}-linux-gnu"),
+            format!("ld-linux-{}.so.2", cpu_arch.replace('_', "-")),
+        ),
+    }
+}
+
+const MUSL_REASON: &str = "musl binaries are statically linked and run on any linux";
+
+const CONTAINER_MUSL_REASON: &str = "running in a container without glibc (e.g. alpine or \
+     distroless), where only statically linked binaries run";
+
+const NIXOS_MUSL_REASON: &str = "running on NixOS without nix-ld, where dynamically linked \
+     binaries cannot find the glibc dynamic loader";
+
+/// The kind of linux host, which decides whether gnu binaries run even if
+/// the glibc dynamic loader is found or not.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Host {
+    Standard,
+    /// Docker, podman, kubernetes, lxc, etc.
+    Container,
+    /// NixOS does not follow the FHS, so binaries linked dynamically against
+    /// glibc only run if [nix-ld](https://github.com/Mic92/nix-ld) is set up.
+    NixOS {
+        nix_ld: bool,
+    },
+}
+
+impl Host {
+    fn detect() -> Self {
+        if is_nixos() {
+            Self::NixOS {
+                nix_ld: env::var_os("NIX_LD").is_some(),
+            }
+        } else if in_container() {
+            Self::Container
+        } else {
+            Self::Standard
+        }
+    }
+}
+
+/// Whether binaries dynamically linked against glibc run on this host, and
+/// the reasons of the gnu and musl targets.
+struct GlibcSupport {
+    /// `None` if gnu binaries do not run.
+    gnu_reason: Option<&'static str>,
+    musl_reason: &'static str,
+}
+
+impl GlibcSupport {
+    fn new(glibc: Option<Libc>, host: Host) -> Self {
+        let gnu_reason = match (glibc, host) {
+            // The loader of nix-ld is a shim which does not report itself
+            // as glibc.
+            (_, Host::NixOS { nix_ld }) => nix_ld.then(|| "nix-ld found on NixOS"),
+            (Some(Libc::Gnu), _) => Some("glibc dynamic loader found"),
+            // gcompat only implements part of glibc, which is good enough
+            // on a full Alpine install but not in minimal containers, where
+            // it is usually installed only to run a few specific programs.
+            (Some(Libc::Gcompat), Host::Standard) => {
+                Some("glibc compatibility layer gcompat found")
+            }
+            _ => None,
+        };
+
+        let musl_reason = match (gnu_reason, host) {
+            (None, Host::Container) => CONTAINER_MUSL_REASON,
+            (None, Host::NixOS { .. }) => NIXOS_MUSL_REASON,
+            _ => MUSL_REASON,
+        };
+
+        Self {
+            gnu_reason,
+            musl_reason,
+        }
+    }
+}
+
+async fn glibc_support(multiarch: &str, ld: &str) -> GlibcSupport {
+    let glibc = find_glibc(multiarch, ld).await;
+    let host = task::spawn_blocking(Host::detect)
+        .await
+        .unwrap_or(Host::Standard);
+    GlibcSupport::new(glibc, host)
+}
+
+/// Return true if running on NixOS.
+fn is_nixos() -> bool {
+    Path::new("/etc/NIXOS").exists()
+        || fs::read_to_string("/etc/os-release")
+            .map(|os_release| os_release_id(&os_release) == Some("nixos"))
+            .unwrap_or(false)
+}
+
+/// Return the `ID` of `os_release`, the content of `/etc/os-release`.
+fn os_release_id(os_release: &str) -> Option<&str> {
+    os_release.lines().find_map(|line| {
+        let id = line.strip_prefix("ID=")?.trim();
+        Some(id.trim_matches(|c| c == '"' || c == '\''))
+    })
+}
+
+/// Return true if running in a container, e.g. docker, podman, kubernetes
+/// or lxc.
+fn in_container() -> bool {
+    Path::new("/.dockerenv").exists()
+        || Path::new("/run/.containerenv").exists()
+        // Set by systemd-nspawn, lxc and podman.
+        || env::var_os("container").is_some()
+        || fs::read_to_string("/proc/1/cgroup")
+            .map(|cgroup| is_container_cgroup(&cgroup))
+            .unwrap_or(false)
+}
+
+/// Return true if `cgroup`, the content of `/proc/1/cgroup`, is the one of
+/// a process in a container.
+fn is_container_cgroup(cgroup: &str) -> bool {
+    cgroup.lines().any(|line| {
+        let path = line.rsplit(':').next().unwrap_or_default();
+        ["docker", "kubepods", "containerd", "libpod", "lxc"]
+            .iter()
+            .any(|runtime| path.contains(runtime))
+    })
+}
+
+/// Return the flavor of the glibc dynamic loader `ld` if found, either in
+/// `/lib`, `/lib64` or in the multiarch directories of `multiarch`.
+async fn find_glibc(multiarch: &str, ld: &str) -> Option<Libc> {
+    let handles: Vec<_> = [
+        format!("/lib/{ld}"),
+        format!("/lib64/{ld}"),
+        format!("/lib/{multiarch}/{ld}"),
+        format!("/usr/lib/{multiarch}/{ld}"),
+    ]
+    .into_iter()
+    .map(|p| AutoAbortHandle(tokio::spawn(get_glibc_ld_flavor(p))))
+    .collect();
+
+    let mut found = None;
+    for mut handle in handles {
+        match (&mut handle.0).await {
+            Ok(Some(Libc::Gnu)) => return Some(Libc::Gnu),
+            Ok(Some(libc)) => found = Some(libc),
+            _ => (),
+        }
+    }
+
+    found
+}
+
+/// Userspace of arm linux, which might differ from the arch of the kernel,
+/// e.g. Raspberry Pi OS runs a 32-bit userspace on a 64-bit kernel.
+#[derive(Debug, Eq, PartialEq)]
+enum ArmUserspace {
+    Aarch64,
+    Arm { hard_float: bool },
+}
+
+const EM_ARM: u16 = 40;
+const EM_AARCH64: u16 = 183;
+const EF_ARM_ABI_FLOAT_HARD: u32 = 0x400;
+
+/// Parse the userspace from the ELF header of one of its executables.
+fn parse_elf_header(header: &[u8; 52]) -> Option<ArmUserspace> {
+    if &header[..4] != b"\x7fELF" || header[5] != 1 {
+        // Not a little-endian ELF.
+        return None;
+    }
+
+    let machine = u16::from_le_bytes([header[18], header[19]]);
+    match (header[4], machine) {
+        (2, EM_AARCH64) => Some(ArmUserspace::Aarch64),
+        (1, EM_ARM) => {
+            let flags = u32::from_le_bytes(header[36..40].try_into().unwrap());
+            Some(ArmUserspace::Arm {
+                hard_float: flags & EF_ARM_ABI_FLOAT_HARD != 0,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Parse the arm architecture version of the cpu from `/proc/cpuinfo`.
+fn parse_cpu_architecture(cpuinfo: &str) -> Option<u32> {
+    cpuinfo
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            if key.trim() != "CPU architecture" {
+                return None;
+            }
+            match value.trim() {
+                "AArch64" => Some(8),
+                value => value.parse().ok(),
+            }
+        })
+        .max()
+}
+
+/// Return the targets of the arm `userspace` on a cpu of arm architecture
+/// `cpu_version`, in the order of preference.
+fn arm_targets(
+    userspace: ArmUserspace,
+    cpu_version: u32,
+    glibc: &GlibcSupport,
+    kernel_is_64bit: bool,
+) -> Vec<(String, &'static str)> {
+    let candidates: &[(&str, &str)] = match userspace {
+        ArmUserspace::Aarch64 => &[("aarch64", "")],
+        ArmUserspace::Arm { hard_float: true } if cpu_version >= 7 => {
+            &[("armv7", "eabihf"), ("arm", "eabihf")]
+        }
+        ArmUserspace::Arm { hard_float: true } => &[("arm", "eabihf")],
+        ArmUserspace::Arm { hard_float: false } if cpu_version >= 7 => {
+            &[("armv7", "eabi"), ("arm", "eabi"), ("armv5te", "eabi")]
+        }
+        ArmUserspace::Arm { hard_float: false } if cpu_version == 6 => {
+            &[("arm", "eabi"), ("armv5te", "eabi")]
+        }
+        ArmUserspace::Arm { hard_float: false } => &[("armv5te", "eabi")],
+    };
+
+    let gnu_targets = glibc.gnu_reason.into_iter().flat_map(|reason| {
+        candidates
+            .iter()
+            .map(move |(arch, abi)| (format!("{arch}-unknown-linux-gnu{abi}"), reason))
+    });
+    let musl_targets = candidates
+        .iter()
+        .map(|(arch, abi)| (format!("{arch}-unknown-linux-musl{abi}"), glibc.musl_reason));
+    let aarch64_target = (kernel_is_64bit && userspace != ArmUserspace::Aarch64).then(|| {
+        (
+            "aarch64-unknown-linux-musl".to_string(),
+            "the 64-bit kernel runs statically linked aarch64 binaries",
+        )
+    });
+
+    gnu_targets
+        .chain(musl_targets)
+        .chain(aarch64_target)
+        .collect()
+}
+
+/// Detect the targets of arm linux from the userspace and the cpu, instead
+/// of the generic arm target guessed from the kernel.
+async fn detect_arm_targets(cpu_arch: &str) -> Option<Vec<(String, &'static str)>> {
+    let (header, cpuinfo) = task::spawn_blocking(|| {
+        let mut header = [0; 52];
+        let header = File::open("/bin/sh")
+            .and_then(|mut file| file.read_exact(&mut header))
+            .map(|_| header);
+        (header, fs::read_to_string("/proc/cpuinfo"))
+    })
+    .await
+    .ok()?;
+
+    let userspace = parse_elf_header(&header.ok()?)?;
+
+    let cpu_version = cpuinfo
+        .ok()
+        .and_then(|cpuinfo| parse_cpu_architecture(&cpuinfo))
+        .unwrap_or(match cpu_arch {
+            "aarch64" | "armv8" => 8,
+            "armv7" => 7,
+            _ => 6,
+        });
+
+    let glibc = match userspace {
+        ArmUserspace::Aarch64 => glibc_support("aarch64-linux-gnu", "ld-linux-aarch64.so.1").await,
+        ArmUserspace::Arm { hard_float: true } => {
+            glibc_support("arm-linux-gnueabihf", "ld-linux-armhf.so.3").await
+        }
+        ArmUserspace::Arm { hard_float: false } => {
+            glibc_support("arm-linux-gnueabi", "ld-linux.so.3").await
+        }
+    };
+
+    // The target reported by rustc is the one of the userspace, so use
+    // uname to check the kernel.
+    let kernel_is_64bit = guess_host_triple::guess_host_triple()
+        .map(|target| target.starts_with("aarch64"))
+        .unwrap_or(false);
+
+    Some(arm_targets(userspace, cpu_version, &glibc, kernel_is_64bit))
+}
+
+async fn get_glibc_ld_flavor(cmd: String) -> Option<Libc> {
+    get_ld_flavor(&cmd)
+        .await
+        .filter(|libc| matches!(libc, Libc::Gnu | Libc::Gcompat))
 }
 
 async fn get_ld_flavor(cmd: &str) -> Option<Libc> {
@@ -95,13 +394,13 @@ You are not meant to run this directly.
         // succeeds.
         String::from_utf8_lossy(&stdout)
             .contains("GLIBC")
-            .then_some(Libc::Gnu)
+            .then(|| Libc::Gnu)
     } else if status.code() == Some(1) {
         // On Alpine, executing both the gcompat glibc and the ldd and
         // /lib/ld-musl-{cpu_arch}.so.1 will fail with exit status 1.
         if str::from_utf8(&stdout).as_deref() == Ok(ALPINE_GCOMPAT) {
             // Alpine's gcompat package will output ALPINE_GCOMPAT to stdout
-            Some(Libc::Gnu)
+            Some(Libc::Gcompat)
         } else if String::from_utf8_lossy(&stderr).contains("musl libc") {
             // Alpine/s ldd and musl dynlib will output to stderr
             Some(Libc::Musl)
@@ -116,6 +415,8 @@ You are not meant to run this directly.
 #[derive(Eq, PartialEq)]
 enum Libc {
     Gnu,
+    /// glibc compatibility layer for musl, e.g. on Alpine.
+    Gcompat,
     Musl,
     Android,
     Unknown,
@@ -128,3 +429,141 @@ impl<T> Drop for AutoAbortHandle<T> {
         self.0.abort();
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn elf_header(class: u8, machine: u16, flags: u32) -> [u8; 52] {
+        let mut header = [0; 52];
+        header[..4].copy_from_slice(b"\x7fELF");
+        header[4] = class;
+        header[5] = 1;
+        header[18..20].copy_from_slice(&machine.to_le_bytes());
+        header[36..40].copy_from_slice(&flags.to_le_bytes());
+        header
+    }
+
+    #[test]
+    fn test_parse_elf_header() {
+        assert_eq!(
+            parse_elf_header(&elf_header(2, EM_AARCH64, 0)),
+            Some(ArmUserspace::Aarch64)
+        );
+        assert_eq!(
+            parse_elf_header(&elf_header(1, EM_ARM, 0x5000400)),
+            Some(ArmUserspace::Arm { hard_float: true })
+        );
+        assert_eq!(
+            parse_elf_header(&elf_header(1, EM_ARM, 0x5000200)),
+            Some(ArmUserspace::Arm { hard_float: false })
+        );
+        assert_eq!(parse_elf_header(&elf_header(2, 62, 0)), None);
+    }
+
+    #[test]
+    fn test_parse_cpu_architecture() {
+        let cpuinfo = "processor\t: 0\nmodel name\t: ARMv7 Processor rev 4 (v7l)\n\
+                       CPU architecture: 7\n\nprocessor\t: 1\nCPU architecture: 7\n";
+        assert_eq!(parse_cpu_architecture(cpuinfo), Some(7));
+        assert_eq!(parse_cpu_architecture("CPU architecture: AArch64"), Some(8));
+        assert_eq!(parse_cpu_architecture("processor\t: 0"), None);
+    }
+
+    #[test]
+    fn test_glibc_ld() {
+        assert_eq!(
+            glibc_ld("x86_64"),
+            (
+                "x86_64-linux-gnu".to_string(),
+                "ld-linux-x86-64.so.2".to_string()
+            )
+        );
+        assert_eq!(glibc_ld("riscv64gc").1, "ld-linux-riscv64-lp64d.so.1");
+        assert_eq!(glibc_ld("loongarch64").1, "ld-linux-loongarch-lp64d.so.1");
+    }
+
+    #[test]
+    fn test_glibc_support() {
+        let glibc = GlibcSupport::new(Some(Libc::Gcompat), Host::Standard);
+        assert!(glibc.gnu_reason.is_some());
+        assert_eq!(glibc.musl_reason, MUSL_REASON);
+
+        let glibc = GlibcSupport::new(Some(Libc::Gcompat), Host::Container);
+        assert_eq!(glibc.gnu_reason, None);
+        assert_eq!(glibc.musl_reason, CONTAINER_MUSL_REASON);
+
+        let glibc = GlibcSupport::new(Some(Libc::Gnu), Host::Container);
+        assert!(glibc.gnu_reason.is_some());
+        assert_eq!(glibc.musl_reason, MUSL_REASON);
+
+        let glibc = GlibcSupport::new(None, Host::NixOS { nix_ld: false });
+        assert_eq!(glibc.gnu_reason, None);
+        assert_eq!(glibc.musl_reason, NIXOS_MUSL_REASON);
+
+        let glibc = GlibcSupport::new(None, Host::NixOS { nix_ld: true });
+        assert!(glibc.gnu_reason.is_some());
+        assert_eq!(glibc.musl_reason, MUSL_REASON);
+    }
+
+    #[test]
+    fn test_os_release_id() {
+        assert_eq!(
+            os_release_id("NAME=NixOS\nID=nixos\nVERSION_ID=\"23.11\"\n"),
+            Some("nixos")
+        );
+        assert_eq!(
+            os_release_id("ID=\"ubuntu\"\nID_LIKE=debian\n"),
+            Some("ubuntu")
+        );
+        assert_eq!(os_release_id("NAME=Unknown\n"), None);
+    }
+
+    #[test]
+    fn test_is_container_cgroup() {
+        assert!(is_container_cgroup(
+            "12:pids:/docker/0123456789abcdef\n0::/system.slice/containerd.service\n"
+        ));
+        assert!(is_container_cgroup(
+            "0::/kubepods/besteffort/pod1234/0123456789abcdef\n"
+        ));
+        assert!(!is_container_cgroup("0::/init.scope\n"));
+    }
+
+    #[test]
+    fn test_arm_targets() {
+        let targets = |userspace, cpu_version, has_glibc: bool, kernel_is_64bit| {
+            let glibc = GlibcSupport::new(has_glibc.then(|| Libc::Gnu), Host::Standard);
+            arm_targets(userspace, cpu_version, &glibc, kernel_is_64bit)
+                .into_iter()
+                .map(|(target, _)| target)
+                .collect::<Vec<_>>()
+        };
+
+        // Raspberry Pi OS 32-bit on a Raspberry Pi 4 with a 64-bit kernel.
+        assert_eq!(
+            targets(ArmUserspace::Arm { hard_float: true }, 8, true, true),
+            [
+                "armv7-unknown-linux-gnueabihf",
+                "arm-unknown-linux-gnueabihf",
+                "armv7-unknown-linux-musleabihf",
+                "arm-unknown-linux-musleabihf",
+                "aarch64-unknown-linux-musl",
+            ]
+        );
+
+        // Raspberry Pi Zero.
+        assert_eq!(
+            targets(ArmUserspace::Arm { hard_float: true }, 6, true, false),
+            [
+                "arm-unknown-linux-gnueabihf",
+                "arm-unknown-linux-musleabihf"
+            ]
+        );
+
+        assert_eq!(
+            targets(ArmUserspace::Aarch64, 8, false, true),
+            ["aarch64-unknown-linux-musl"]
+        );
+    }
+}
@@ -0,0 +1,134 @@
+// Only windows detects windows targets to sort.
+#![cfg_attr(not(target_os = "windows"), allow(dead_code))]
+
+use std::{env, str::FromStr};
+
+/// Toolchain of the windows targets to prefer, since binaries built with
+/// different toolchains depend on different runtime libraries.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(super) enum WindowsToolchain {
+    /// `*-pc-windows-msvc`, which requires the Visual C++ runtime.
+    Msvc,
+    /// `*-pc-windows-gnu` and `*-pc-windows-gnullvm`, which are preferred in
+    /// mingw environments such as MSYS2.
+    Gnu,
+}
+
+impl FromStr for WindowsToolchain {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "msvc" => Ok(Self::Msvc),
+            "gnu" => Ok(Self::Gnu),
+            _ => Err("expected msvc or gnu"),
+        }
+    }
+}
+
+impl WindowsToolchain {
+    /// Detect the toolchain to prefer on this host, whose target is `target`:
+    ///
+    ///  - `BINSTALL_WINDOWS_TOOLCHAIN` if it is set to `msvc` or `gnu`
+    ///  - gnu if run in a mingw environment, where `MSYSTEM` is set
+    ///  - gnu if the Visual C++ runtime is not installed
+    ///  - the toolchain of `target` otherwise
+    pub(super) fn detect(target: &str) -> Self {
+        if let Some(toolchain) = env::var("BINSTALL_WINDOWS_TOOLCHAIN")
+            .ok()
+            .and_then(|s| s.parse().ok())
+        {
+            return toolchain;
+        }
+
+        if env::var_os("MSYSTEM").is_some() || !has_msvc_runtime() {
+            return Self::Gnu;
+        }
+
+        match target.rsplit_once('-') {
+            Some((_, "gnu" | "gnullvm")) => Self::Gnu,
+            _ => Self::Msvc,
+        }
+    }
+
+    fn rank(self, abi: &str) -> u8 {
+        match (self, abi) {
+            (Self::Msvc, "msvc") | (Self::Gnu, "gnu") => 0,
+            (Self::Msvc, "gnu") | (Self::Gnu, "gnullvm") => 1,
+            _ => 2,
+        }
+    }
+
+    /// Sort the windows `targets` so that the targets of this toolchain come
+    /// first among the targets of the same architecture, while keeping the
+    /// order of the architectures.
+    pub(super) fn sort_targets(self, targets: &mut [(String, &'static str)]) {
+        let mut archs: Vec<String> = Vec::new();
+        for (target, _) in targets.iter() {
+            let arch = target.split('-').next().unwrap_or_default();
+            if !archs.iter().any(|a| a == arch) {
+                archs.push(arch.to_string());
+            }
+        }
+
+        targets.sort_by_key(|(target, _)| {
+            let arch = target.split('-').next().unwrap_or_default();
+            let abi = target.rsplit('-').next().unwrap_or_default();
+            (archs.iter().position(|a| a == arch), self.rank(abi))
+        });
+    }
+}
+
+/// Return true if the Visual C++ runtime, which binaries of msvc targets
+/// link to dynamically, is installed.
+#[cfg(target_os = "windows")]
+fn has_msvc_runtime() -> bool {
+    env::var_os("SystemRoot")
+        .map(|root| {
+            std::path::Path::new(&root)
+                .join("System32")
+                .join("vcruntime140.dll")
+                .is_file()
+        })
+        .unwrap_or(true)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn has_msvc_runtime() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sort_targets() {
+        let mut targets: Vec<_> = [
+            "x86_64-pc-windows-msvc",
+            "x86_64-pc-windows-gnu",
+            "x86_64-pc-windows-gnullvm",
+            "i686-pc-windows-msvc",
+            "i686-pc-windows-gnu",
+        ]
+        .into_iter()
+        .map(|target| (target.to_string(), ""))
+        .collect();
+
+        WindowsToolchain::Gnu.sort_targets(&mut targets);
+        assert_eq!(
+            targets.iter().map(|(t, _)| t.as_str()).collect::<Vec<_>>(),
+            [
+                "x86_64-pc-windows-gnu",
+                "x86_64-pc-windows-gnullvm",
+                "x86_64-pc-windows-msvc",
+                "i686-pc-windows-gnu",
+                "i686-pc-windows-msvc",
+            ]
+        );
+
+        WindowsToolchain::Msvc.sort_targets(&mut targets);
+        assert_eq!(targets[0].0, "x86_64-pc-windows-msvc");
+        assert_eq!(targets[3].0, "i686-pc-windows-msvc");
+    }
+}
@@ -24,6 +24,18 @@ impl FileLock {
         Ok(Self(file))
     }
 
+    /// Take an exclusive lock on a [`File`], unless it is already locked,
+    /// in which case `None` is returned instead of blocking.
+    pub fn try_new_exclusive(file: File) -> Result<Option<Self>> {
+        match file.try_lock_exclusive() {
+            Ok(()) => Ok(Some(Self(file))),
+            Err(err) if err.raw_os_error() == fs4::lock_contended_error().raw_os_error() => {
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
     /// Take a shared lock on a [`File`].
     ///
     /// Note that this operation is blocking, and should not be called in async contexts.
@@ -22,6 +22,11 @@ otherwise it is equivalent to `GitCancellationToken::cancel()`"]
         self.0.store(true, Relaxed)
     }
 
+    /// Return `true` if the git operation is cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Relaxed)
+    }
+
     pub(super) fn get_atomic(&self) -> &AtomicBool {
         &self.0
     }
@@ -1,8 +1,20 @@
-use std::{fmt, mem, num::NonZeroU32, path::Path, str::FromStr, sync::atomic::AtomicBool};
+use std::{
+    fmt, fs, io, mem,
+    num::NonZeroU32,
+    path::{Component, Path},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering::Relaxed},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
-use gix::{clone, create, open, remote, Url};
+use gix::{clone, create, open, refs::transaction::PreviousValue, remote, Url};
 use thiserror::Error as ThisError;
-use tracing::debug;
+use tokio::time;
+use tracing::{debug, warn};
 
 mod progress_tracing;
 use progress_tracing::TracingProgress;
@@ -32,6 +44,49 @@ pub enum GitError {
 
     #[error("An object was missing in the crates-io index repository clone")]
     ObjectLookup(#[source] Box<gix::object::find::existing::Error>),
+
+    #[error("Failed to open repository: {0}")]
+    OpenError(#[source] Box<open::Error>),
+
+    #[error("Failed to find remote: {0}")]
+    FindRemoteError(#[source] Box<remote::find::existing::Error>),
+
+    #[error("Failed to connect to remote: {0}")]
+    ConnectError(#[source] Box<remote::connect::Error>),
+
+    #[error("Failed to prepare for update: {0}")]
+    PrepareUpdateError(#[source] Box<remote::fetch::prepare::Error>),
+
+    #[error("Failed to update: {0}")]
+    UpdateError(#[source] Box<remote::fetch::Error>),
+
+    #[error("Failed to update HEAD: {0}")]
+    EditReferenceError(#[source] Box<gix::reference::edit::Error>),
+
+    #[error("Failed to traverse the tree of head commit: {0}")]
+    TraverseTree(#[source] Box<gix::traverse::tree::breadthfirst::Error>),
+
+    #[error("Timed out after {0:?}")]
+    Timeout(Duration),
+
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl GitError {
+    /// Return `true` if the operation may succeed if it is attempted again,
+    /// i.e. it failed to communicate with the remote.
+    fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::PrepareFetchError(_)
+                | Self::FetchError(_)
+                | Self::ConnectError(_)
+                | Self::PrepareUpdateError(_)
+                | Self::UpdateError(_)
+                | Self::Timeout(_)
+        )
+    }
 }
 
 impl From<clone::Error> for GitError {
@@ -70,6 +125,179 @@ impl From<gix::object::find::existing::Error> for GitError {
     }
 }
 
+impl From<open::Error> for GitError {
+    fn from(e: open::Error) -> Self {
+        Self::OpenError(Box::new(e))
+    }
+}
+
+impl From<remote::find::existing::Error> for GitError {
+    fn from(e: remote::find::existing::Error) -> Self {
+        Self::FindRemoteError(Box::new(e))
+    }
+}
+
+impl From<remote::connect::Error> for GitError {
+    fn from(e: remote::connect::Error) -> Self {
+        Self::ConnectError(Box::new(e))
+    }
+}
+
+impl From<remote::fetch::prepare::Error> for GitError {
+    fn from(e: remote::fetch::prepare::Error) -> Self {
+        Self::PrepareUpdateError(Box::new(e))
+    }
+}
+
+impl From<remote::fetch::Error> for GitError {
+    fn from(e: remote::fetch::Error) -> Self {
+        Self::UpdateError(Box::new(e))
+    }
+}
+
+impl From<gix::reference::edit::Error> for GitError {
+    fn from(e: gix::reference::edit::Error) -> Self {
+        Self::EditReferenceError(Box::new(e))
+    }
+}
+
+impl From<gix::traverse::tree::breadthfirst::Error> for GitError {
+    fn from(e: gix::traverse::tree::breadthfirst::Error) -> Self {
+        Self::TraverseTree(Box::new(e))
+    }
+}
+
+/// Options of the git operations, e.g. to apply the retry policy of the
+/// http requests to them.
+#[derive(Clone, Debug)]
+pub struct GitOptions {
+    /// Number of commits fetched, `None` to fetch the whole history.
+    pub depth: Option<NonZeroU32>,
+    /// Abort each attempt after this duration.
+    pub timeout: Option<Duration>,
+    /// Number of retries after the first attempt.
+    pub retries: u8,
+    /// Delay before the first retry, doubled for each following one.
+    pub backoff_base: Duration,
+}
+
+impl Default for GitOptions {
+    /// Fetch the last commit only, without timeout, and retry twice from
+    /// 200ms.
+    fn default() -> Self {
+        Self {
+            depth: NonZeroU32::new(1),
+            timeout: None,
+            retries: 2,
+            backoff_base: Duration::from_millis(200),
+        }
+    }
+}
+
+impl GitOptions {
+    fn shallow(&self) -> remote::fetch::Shallow {
+        match self.depth {
+            Some(depth) => remote::fetch::Shallow::DepthAtRemote(depth),
+            None => remote::fetch::Shallow::NoChange,
+        }
+    }
+
+    /// Run `attempt` until it succeeds or fails with an error which is not
+    /// retryable, at most `self.retries + 1` times.
+    ///
+    /// `attempt` is passed the flag to interrupt it, which is set once
+    /// `cancellation_token` is cancelled or `self.timeout` elapses.
+    fn run<T>(
+        &self,
+        cancellation_token: Option<&GitCancellationToken>,
+        mut attempt: impl FnMut(&AtomicBool) -> Result<T, GitError>,
+    ) -> Result<T, GitError> {
+        let is_cancelled = || cancellation_token.map_or(false, GitCancellationToken::is_cancelled);
+
+        let mut delay = self.backoff_base;
+        let mut retries = self.retries;
+
+        loop {
+            let res = match self.timeout {
+                Some(timeout) => {
+                    let interrupt = Arc::new(AtomicBool::new(false));
+                    let deadline = Instant::now() + timeout;
+                    spawn_watchdog(
+                        Arc::clone(&interrupt),
+                        cancellation_token.cloned(),
+                        deadline,
+                    );
+
+                    let res = attempt(&interrupt);
+                    match res {
+                        Err(_) if Instant::now() >= deadline && !is_cancelled() => {
+                            Err(GitError::Timeout(timeout))
+                        }
+                        res => res,
+                    }
+                }
+                None => attempt(
+                    cancellation_token
+                        .map(GitCancellationToken::get_atomic)
+                        .unwrap_or(&AtomicBool::new(false)),
+                ),
+            };
+
+            match res {
+                Err(err) if retries > 0 && err.is_retryable() && !is_cancelled() => {
+                    warn!("{err}, retrying in {delay:?}");
+                    thread::sleep(delay);
+
+                    delay = delay.saturating_mul(2);
+                    retries -= 1;
+                }
+                res => break res,
+            }
+        }
+    }
+}
+
+/// Set `interrupt` once `cancellation_token` is cancelled or `deadline`
+/// is reached, until `interrupt` is dropped by the attempt.
+fn spawn_watchdog(
+    interrupt: Arc<AtomicBool>,
+    cancellation_token: Option<GitCancellationToken>,
+    deadline: Instant,
+) {
+    tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_millis(50));
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+
+        while Arc::strong_count(&interrupt) > 1 {
+            if Instant::now() >= deadline
+                || cancellation_token
+                    .as_ref()
+                    .map_or(false, GitCancellationToken::is_cancelled)
+            {
+                interrupt.store(true, Relaxed);
+                break;
+            }
+            interval.tick().await;
+        }
+    });
+}
+
+/// Remove the content of `path`, so that it can be cloned into again.
+fn clear_dir(path: &Path) -> io::Result<()> {
+    match fs::read_dir(path) {
+        Ok(mut entries) => entries.try_for_each(|entry| {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                fs::remove_dir_all(entry.path())
+            } else {
+                fs::remove_file(entry.path())
+            }
+        }),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct GitUrl(Url);
 
@@ -98,6 +326,7 @@ impl Repository {
         url: GitUrl,
         path: &Path,
         kind: create::Kind,
+        options: &GitOptions,
     ) -> Result<clone::PrepareFetch, GitError> {
         Ok(clone::PrepareFetch::new(
             url.0,
@@ -109,9 +338,15 @@ impl Repository {
             },
             open::Options::isolated(),
         )?
-        .with_shallow(remote::fetch::Shallow::DepthAtRemote(
-            NonZeroU32::new(1).unwrap(),
-        )))
+        // Only fetch HEAD, i.e. neither the other branches nor the tags.
+        .configure_remote(|mut remote| {
+            remote.replace_refspecs(
+                Some("+HEAD:refs/remotes/origin/HEAD"),
+                remote::Direction::Fetch,
+            )?;
+            Ok(remote.with_fetch_tags(remote::fetch::Tags::None))
+        })
+        .with_shallow(options.shallow()))
     }
 
     /// WARNING: This is a blocking operation, if you want to use it in
@@ -121,22 +356,25 @@ impl Repository {
     pub fn shallow_clone_bare(
         url: GitUrl,
         path: &Path,
+        options: &GitOptions,
         cancellation_token: Option<GitCancellationToken>,
     ) -> Result<Self, GitError> {
         debug!("Shallow cloning {url} to {}", path.display());
 
-        Ok(Self(
-            Self::prepare_fetch(url, path, create::Kind::Bare)?
-                .fetch_only(
-                    &mut TracingProgress::new("Cloning bare"),
-                    cancellation_token
-                        .as_ref()
-                        .map(GitCancellationToken::get_atomic)
-                        .unwrap_or(&AtomicBool::new(false)),
-                )?
-                .0
-                .into(),
-        ))
+        let mut retry = false;
+        options.run(cancellation_token.as_ref(), |interrupt| {
+            // Remove what the failed attempt cloned.
+            if mem::replace(&mut retry, true) {
+                clear_dir(path)?;
+            }
+
+            Ok(Self(
+                Self::prepare_fetch(url.clone(), path, create::Kind::Bare, options)?
+                    .fetch_only(&mut TracingProgress::new("Cloning bare"), interrupt)?
+                    .0
+                    .into(),
+            ))
+        })
     }
 
     /// WARNING: This is a blocking operation, if you want to use it in
@@ -146,16 +384,29 @@ impl Repository {
     pub fn shallow_clone(
         url: GitUrl,
         path: &Path,
+        options: &GitOptions,
         cancellation_token: Option<GitCancellationToken>,
     ) -> Result<Self, GitError> {
         debug!("Shallow cloning {url} to {} with worktree", path.display());
 
         let mut progress = TracingProgress::new("Cloning with worktree");
 
+        let mut retry = false;
+        let mut checkout = options.run(cancellation_token.as_ref(), |interrupt| {
+            // Remove what the failed attempt cloned.
+            if mem::replace(&mut retry, true) {
+                clear_dir(path)?;
+            }
+
+            Ok(
+                Self::prepare_fetch(url.clone(), path, create::Kind::WithWorktree, options)?
+                    .fetch_then_checkout(&mut progress, interrupt)?
+                    .0,
+            )
+        })?;
+
         Ok(Self(
-            Self::prepare_fetch(url, path, create::Kind::WithWorktree)?
-                .fetch_then_checkout(&mut progress, &AtomicBool::new(false))?
-                .0
+            checkout
                 .main_worktree(
                     &mut progress,
                     cancellation_token
@@ -168,6 +419,148 @@ impl Repository {
         ))
     }
 
+    /// Fetch the new commits of the bare clone of `url` at `path` made by
+    /// [`Repository::shallow_clone_bare`], e.g. in a previous run, or clone
+    /// it again if `path` does not contain a clone of `url` or it cannot be
+    /// updated.
+    ///
+    /// WARNING: This is a blocking operation, if you want to use it in
+    /// async context then you must wrap the call in [`tokio::task::spawn_blocking`].
+    ///
+    /// WARNING: This function must be called after tokio runtime is initialized.
+    pub fn update_or_clone_bare(
+        url: GitUrl,
+        path: &Path,
+        options: &GitOptions,
+        cancellation_token: Option<GitCancellationToken>,
+    ) -> Result<Self, GitError> {
+        match Self::update_bare(&url, path, options, cancellation_token.as_ref()) {
+            Ok(Some(repo)) => return Ok(repo),
+            Ok(None) => (),
+            Err(err) => warn!(
+                "Failed to update the clone of {url} at {}, cloning it again: {err}",
+                path.display()
+            ),
+        }
+
+        clear_dir(path)?;
+        Self::shallow_clone_bare(url, path, options, cancellation_token)
+    }
+
+    /// Return `None` if `path` does not contain a clone of `url`.
+    fn update_bare(
+        url: &GitUrl,
+        path: &Path,
+        options: &GitOptions,
+        cancellation_token: Option<&GitCancellationToken>,
+    ) -> Result<Option<Self>, GitError> {
+        let Ok(repo) = gix::open_opts(path, open::Options::isolated()) else {
+            return Ok(None);
+        };
+        let remote = match repo.find_default_remote(remote::Direction::Fetch) {
+            Some(remote) => remote?,
+            None => return Ok(None),
+        };
+        if remote.url(remote::Direction::Fetch) != Some(&url.0) {
+            return Ok(None);
+        }
+
+        debug!("Updating the clone of {url} at {}", path.display());
+
+        // Fetch HEAD of the remote, as done by the clone.
+        let head_refspec = gix::refspec::parse(
+            "HEAD:refs/remotes/origin/HEAD".into(),
+            gix::refspec::parse::Operation::Fetch,
+        )
+        .expect("valid")
+        .to_owned();
+
+        let outcome = options.run(cancellation_token, |interrupt| {
+            let mut progress = TracingProgress::new("Updating bare");
+
+            Ok(remote
+                .connect(remote::Direction::Fetch)?
+                .prepare_fetch(
+                    &mut progress,
+                    remote::ref_map::Options {
+                        extra_refspecs: vec![head_refspec.clone()],
+                        ..Default::default()
+                    },
+                )?
+                .with_shallow(options.shallow())
+                .receive(&mut progress, interrupt)?)
+        })?;
+
+        // HEAD cannot be updated by refspecs, point it to the commit of the
+        // HEAD of the remote.
+        let head = outcome
+            .ref_map
+            .remote_refs
+            .iter()
+            .find_map(|remote_ref| match remote_ref {
+                gix::protocol::handshake::Ref::Symbolic {
+                    full_ref_name,
+                    object,
+                    ..
+                }
+                | gix::protocol::handshake::Ref::Direct {
+                    full_ref_name,
+                    object,
+                } if full_ref_name == "HEAD" => Some(*object),
+                _ => None,
+            });
+        if let Some(head) = head {
+            repo.reference("HEAD", head, PreviousValue::Any, "binstall: update HEAD")?;
+        }
+
+        Ok(Some(Self(repo.into())))
+    }
+
+    /// Write the files of the tree of the head commit for which `filter`
+    /// returns `true` to `dst`, e.g. to only check out the files needed
+    /// from a bare clone.
+    ///
+    /// Symlinks and submodules are skipped.
+    ///
+    /// WARNING: This is a blocking operation, if you want to use it in
+    /// async context then you must wrap the call in [`tokio::task::spawn_blocking`].
+    pub fn checkout_head_paths(
+        &self,
+        dst: &Path,
+        filter: impl Fn(&Path) -> bool,
+    ) -> Result<(), GitError> {
+        let repo = self.0.to_thread_local();
+
+        let mut recorder = gix::traverse::tree::Recorder::default();
+        repo.head_commit()?
+            .tree()?
+            .traverse()
+            .breadthfirst(&mut recorder)?;
+
+        for entry in recorder.records {
+            if !entry.mode.is_blob() {
+                continue;
+            }
+
+            let path = gix::path::from_bstr(entry.filepath.as_ref());
+            if !path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+                || !filter(&path)
+            {
+                continue;
+            }
+
+            let dst = dst.join(path);
+            if let Some(parent) = dst.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(dst, &repo.find_object(entry.oid)?.data)?;
+        }
+
+        Ok(())
+    }
+
     #[inline(always)]
     pub fn get_head_commit_entry_data_by_path(
         &self,
@@ -192,3 +585,161 @@ impl Repository {
         inner(self, path.as_ref())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::{process::Command, sync::atomic::AtomicU8};
+
+    use super::*;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=binstall", "-c", "user.email=binstall@test"])
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {args:?} failed");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_run() {
+        let options = GitOptions {
+            timeout: Some(Duration::from_millis(100)),
+            backoff_base: Duration::from_millis(1),
+            ..Default::default()
+        };
+
+        tokio::task::spawn_blocking(move || {
+            let attempts = AtomicU8::new(0);
+            let err = options
+                .run(None, |interrupt| {
+                    attempts.fetch_add(1, Relaxed);
+                    while !interrupt.load(Relaxed) {
+                        thread::sleep(Duration::from_millis(10));
+                    }
+                    Err::<(), _>(io::Error::from(io::ErrorKind::Interrupted).into())
+                })
+                .unwrap_err();
+            assert!(matches!(err, GitError::Timeout(_)), "{err}");
+            assert_eq!(attempts.into_inner(), 3);
+
+            let attempts = AtomicU8::new(0);
+            let err = options
+                .run(None, |_| {
+                    attempts.fetch_add(1, Relaxed);
+                    Err::<(), _>(io::Error::from(io::ErrorKind::NotFound).into())
+                })
+                .unwrap_err();
+            assert!(matches!(err, GitError::Io(_)), "{err}");
+            assert_eq!(attempts.into_inner(), 1);
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_checkout_head_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let remote = dir.path().join("remote");
+        fs::create_dir_all(remote.join("crates/foo")).unwrap();
+        git(&remote, &["init", "--quiet"]);
+        fs::write(remote.join("Cargo.toml"), "[workspace]").unwrap();
+        fs::write(remote.join("crates/foo/Cargo.toml"), "[package]").unwrap();
+        fs::write(remote.join("README.md"), "foo").unwrap();
+        git(&remote, &["add", "."]);
+        git(&remote, &["commit", "--quiet", "-m", "1"]);
+        git(&remote, &["tag", "v1"]);
+
+        let url: GitUrl = format!("file://{}", remote.display()).parse().unwrap();
+        let clone = dir.path().join("clone");
+        let dst = dir.path().join("dst");
+        let worktree = dir.path().join("worktree");
+
+        tokio::task::spawn_blocking({
+            let dst = dst.clone();
+            let worktree = worktree.clone();
+            move || {
+                Repository::shallow_clone(url.clone(), &worktree, &GitOptions::default(), None)
+                    .unwrap();
+
+                let repo =
+                    Repository::shallow_clone_bare(url, &clone, &GitOptions::default(), None)
+                        .unwrap();
+                repo.checkout_head_paths(&dst, |path| path.ends_with("Cargo.toml"))
+                    .unwrap();
+
+                // Tags are not fetched.
+                assert!(repo
+                    .0
+                    .to_thread_local()
+                    .try_find_reference("refs/tags/v1")
+                    .unwrap()
+                    .is_none());
+            }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(fs::read(dst.join("Cargo.toml")).unwrap(), b"[workspace]");
+        assert_eq!(
+            fs::read(dst.join("crates/foo/Cargo.toml")).unwrap(),
+            b"[package]"
+        );
+        assert!(!dst.join("README.md").exists());
+        assert_eq!(fs::read(worktree.join("README.md")).unwrap(), b"foo");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_update_or_clone_bare() {
+        let dir = tempfile::tempdir().unwrap();
+        let remote = dir.path().join("remote");
+        fs::create_dir(&remote).unwrap();
+        git(&remote, &["init", "--quiet"]);
+        fs::write(remote.join("config.json"), "1").unwrap();
+        git(&remote, &["add", "config.json"]);
+        git(&remote, &["commit", "--quiet", "-m", "1"]);
+
+        let url: GitUrl = format!("file://{}", remote.display()).parse().unwrap();
+        let clone = dir.path().join("clone");
+        fs::create_dir(&clone).unwrap();
+
+        let update = {
+            let url = url.clone();
+            let clone = clone.clone();
+            move || {
+                Repository::update_or_clone_bare(url, &clone, &GitOptions::default(), None)
+                    .unwrap()
+                    .get_head_commit_entry_data_by_path("config.json")
+                    .unwrap()
+                    .unwrap()
+            }
+        };
+
+        assert_eq!(
+            tokio::task::spawn_blocking(update.clone()).await.unwrap(),
+            b"1"
+        );
+
+        fs::write(remote.join("config.json"), "2").unwrap();
+        git(&remote, &["commit", "--quiet", "-am", "2"]);
+        assert_eq!(
+            tokio::task::spawn_blocking(update.clone()).await.unwrap(),
+            b"2"
+        );
+
+        // The clone of another repository is replaced.
+        fs::remove_dir_all(&remote).unwrap();
+        fs::create_dir(&remote).unwrap();
+        git(&remote, &["init", "--quiet"]);
+        fs::write(remote.join("config.json"), "3").unwrap();
+        git(&remote, &["add", "config.json"]);
+        git(&remote, &["commit", "--quiet", "-m", "3"]);
+        fs::write(
+            clone.join("config"),
+            "[remote \"origin\"]\n\turl = /elsewhere\n",
+        )
+        .unwrap();
+        assert_eq!(tokio::task::spawn_blocking(update).await.unwrap(), b"3");
+    }
+}